@@ -0,0 +1,21 @@
+//! Stamps the build time into the binary; Ollama's `/api/tags`, `/api/show` and `/api/ps` report it
+//! as every model's `modified_at`.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    // Reproducible builds pin the stamp through SOURCE_DATE_EPOCH; otherwise it moves whenever the
+    // sources change and Cargo reruns this script.
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=Cargo.toml");
+    let epoch = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs())
+        });
+    println!("cargo:rustc-env=CODEX_SERVE_BUILD_EPOCH={epoch}");
+}
@@ -0,0 +1,33 @@
+//! Mounts Codex Serve under `/llm` inside a host axum app, using only the crate-root exports.
+//!
+//! ```sh
+//! cargo run --example embedded
+//! curl http://127.0.0.1:8080/llm/healthz
+//! ```
+//!
+//! Needs a `codex login` session (or `OPENAI_API_KEY`) like the CLI does.
+
+use axum::{Router, routing::get};
+use codex_serve::{AppState, InitOptions, ServeConfig, router};
+use tokio::net::TcpListener;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let state = AppState::initialize_with(InitOptions {
+        config: ServeConfig::builder().expose_reasoning_models(true).build(),
+        ..InitOptions::default()
+    })
+    .await?;
+
+    let app = Router::new()
+        .route(
+            "/",
+            get(|| async { "host app; Codex Serve lives under /llm" }),
+        )
+        .nest("/llm", router(state));
+
+    let listener = TcpListener::bind("127.0.0.1:8080").await?;
+    println!("listening on http://{}", listener.local_addr()?);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
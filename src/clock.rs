@@ -0,0 +1,143 @@
+//! UTC calendar formatting without pulling in a date-time crate, the one timestamp a chat
+//! completion is stamped with, and the context its log events share. The API translators and
+//! the server both use these, so they live outside either.
+
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use uuid::Uuid;
+
+/// The `id` and `created` of one chat completion, taken once when the request is converted and
+/// carried on its [`PromptPayload`](crate::openai::chat::PromptPayload). The JSON reply, every
+/// chunk of a stream and the logs all report these, so a client that dedupes on the pair sees one
+/// reply however it was served.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestClock {
+    id: String,
+    created: i64,
+}
+
+impl RequestClock {
+    /// A fresh `chatcmpl-<uuid>` id, created now.
+    pub fn start() -> Self {
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs() as i64)
+            .unwrap_or_default();
+        Self {
+            id: format!("chatcmpl-{}", Uuid::new_v4()),
+            created,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Unix seconds, as OpenAI's `created`.
+    pub fn created(&self) -> i64 {
+        self.created
+    }
+}
+
+/// The fields that tie one request's verbose events together.
+#[derive(Clone, Debug)]
+pub struct LogContext {
+    /// The id from the request id middleware; empty outside a request.
+    pub request_id: String,
+    /// The model as the client asked for it.
+    pub model: String,
+    started: Instant,
+}
+
+impl LogContext {
+    /// The context of request `request_id` for `model`, timed from now.
+    pub fn new(request_id: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            request_id: request_id.into(),
+            model: model.into(),
+            started: Instant::now(),
+        }
+    }
+
+    /// When the request started, for the durations its events report.
+    pub fn started(&self) -> Instant {
+        self.started
+    }
+}
+
+/// Formats a unix timestamp as a UTC `YYYY-MM-DD` date (proleptic Gregorian calendar).
+pub(crate) fn utc_date(unix_secs: u64) -> String {
+    let days = (unix_secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Formats `time` like Go's `time.RFC3339Nano` in UTC (what Ollama emits for `created_at`):
+/// nanoseconds with trailing zeros trimmed, and no fraction at all on a whole second.
+pub(crate) fn rfc3339_nanos(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let time_of_day = secs % 86_400;
+    let mut stamp = format!(
+        "{}T{:02}:{:02}:{:02}",
+        utc_date(secs),
+        time_of_day / 3_600,
+        time_of_day % 3_600 / 60,
+        time_of_day % 60
+    );
+    let nanos = since_epoch.subsec_nanos();
+    if nanos > 0 {
+        let fraction = format!("{nanos:09}");
+        stamp.push('.');
+        stamp.push_str(fraction.trim_end_matches('0'));
+    }
+    stamp.push('Z');
+    stamp
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn every_clock_has_its_own_id() {
+        let first = RequestClock::start();
+        let second = RequestClock::start();
+        assert!(first.id().starts_with("chatcmpl-"));
+        assert_ne!(first.id(), second.id());
+        assert!(second.created() >= first.created());
+        assert_eq!(first.clone(), first);
+    }
+
+    #[test]
+    fn utc_dates_follow_the_calendar() {
+        assert_eq!(utc_date(0), "1970-01-01");
+        assert_eq!(utc_date(951_782_400), "2000-02-29");
+        assert_eq!(utc_date(1_767_225_599), "2025-12-31");
+    }
+
+    #[test]
+    fn rfc3339_nanos_trims_the_fraction_like_go() {
+        let at = |secs, nanos| UNIX_EPOCH + Duration::new(secs, nanos);
+        assert_eq!(
+            rfc3339_nanos(at(1_702_390_423, 416_799_000)),
+            "2023-12-12T14:13:43.416799Z"
+        );
+        assert_eq!(rfc3339_nanos(at(1_767_225_599, 0)), "2025-12-31T23:59:59Z");
+        assert_eq!(rfc3339_nanos(at(0, 1)), "1970-01-01T00:00:00.000000001Z");
+    }
+}
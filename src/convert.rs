@@ -0,0 +1,26 @@
+//! Codex Serve's request conversion without the HTTP server: Chat Completions requests to Codex
+//! prompts (role normalization, content parts, tool mapping, schema sanitization) and back.
+//!
+//! ```no_run
+//! use codex_serve::convert::{ChatCompletionRequest, request_from_payload};
+//!
+//! let request: ChatCompletionRequest = serde_json::from_str(
+//!     r#"{"model": "gpt-5", "messages": [{"role": "user", "content": "hi"}]}"#,
+//! )
+//! .expect("valid JSON");
+//! let payload = request.to_prompt().expect("convertible request");
+//! let again = request_from_payload(&payload);
+//! println!("{}", serde_json::to_string(&again).expect("serializable"));
+//! ```
+
+pub use crate::openai::{
+    chat::{
+        ChatCompletionRequest, ChatMessage, ConversionOptions, PromptEndpoint, PromptPayload,
+        convert_function_tools,
+    },
+    convert::ConversionError,
+    render::{messages_from_items, request_from_payload},
+    sanitize_json_schema,
+    tool_names::{ToolNames, ToolRules},
+    warnings::{Warning, Warnings},
+};
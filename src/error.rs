@@ -1,14 +1,39 @@
+use std::time::Duration;
+
 use axum::{
     Json,
-    http::StatusCode,
+    http::{HeaderValue, StatusCode, header::RETRY_AFTER},
     response::{IntoResponse, Response},
 };
 use serde::Serialize;
 
+use crate::server::current_request_id;
+
 #[derive(Debug)]
 pub enum ApiError {
     Unauthorized(String),
+    TokenExpired(String),
+    /// The client sent no key, or the wrong one, on a listener bound with `auth=required`.
+    InvalidApiKey(String),
     BadRequest(String),
+    NotFound(String),
+    MethodNotAllowed(String),
+    PayloadTooLarge(String),
+    RateLimited {
+        message: String,
+        retry_after: Option<Duration>,
+        /// The client whose own limit was hit (`error.client`), rather than the upstream's.
+        client: Option<String>,
+    },
+    /// The request's idempotency key belongs to another request it cannot share a reply with.
+    Conflict {
+        message: String,
+        original_request_id: Option<String>,
+    },
+    /// Codex could not be initialized, so the server runs without an upstream.
+    ServiceUnavailable(String),
+    /// An operator cancelled the request through `POST /admin/requests/{id}/cancel`.
+    Cancelled(String),
     Internal(String),
 }
 
@@ -17,10 +42,66 @@ impl ApiError {
         Self::Unauthorized(message.into())
     }
 
+    pub fn token_expired(message: impl Into<String>) -> Self {
+        Self::TokenExpired(message.into())
+    }
+
+    pub fn invalid_api_key(message: impl Into<String>) -> Self {
+        Self::InvalidApiKey(message.into())
+    }
+
     pub fn bad_request(message: impl Into<String>) -> Self {
         Self::BadRequest(message.into())
     }
 
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::NotFound(message.into())
+    }
+
+    pub fn method_not_allowed(message: impl Into<String>) -> Self {
+        Self::MethodNotAllowed(message.into())
+    }
+
+    pub fn payload_too_large(message: impl Into<String>) -> Self {
+        Self::PayloadTooLarge(message.into())
+    }
+
+    pub fn rate_limited(message: impl Into<String>, retry_after: Option<Duration>) -> Self {
+        Self::RateLimited {
+            message: message.into(),
+            retry_after,
+            client: None,
+        }
+    }
+
+    /// A rate limit of `client`'s own, such as its `--per-client-concurrency` cap.
+    pub fn client_rate_limited(
+        client: impl Into<String>,
+        message: impl Into<String>,
+        retry_after: Duration,
+    ) -> Self {
+        Self::RateLimited {
+            message: message.into(),
+            retry_after: Some(retry_after),
+            client: Some(client.into()),
+        }
+    }
+
+    pub fn conflict(message: impl Into<String>, original_request_id: Option<String>) -> Self {
+        Self::Conflict {
+            message: message.into(),
+            original_request_id,
+        }
+    }
+
+    pub fn service_unavailable(message: impl Into<String>) -> Self {
+        Self::ServiceUnavailable(message.into())
+    }
+
+    pub fn cancelled(message: impl Into<String>) -> Self {
+        Self::Cancelled(message.into())
+    }
+
     pub fn internal(message: impl Into<String>) -> Self {
         Self::Internal(message.into())
     }
@@ -34,21 +115,234 @@ struct ErrorBody {
 #[derive(Serialize)]
 struct ErrorDetails {
     message: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
     code: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    original_request_id: Option<String>,
 }
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
-        let (status, code, message) = match self {
-            ApiError::Unauthorized(message) => (StatusCode::UNAUTHORIZED, "NOT_LOGGED_IN", message),
-            ApiError::BadRequest(message) => (StatusCode::BAD_REQUEST, "BAD_REQUEST", message),
-            ApiError::Internal(message) => {
-                (StatusCode::INTERNAL_SERVER_ERROR, "INTERNAL_ERROR", message)
+        self.into_response_with_request_id(current_request_id())
+    }
+}
+
+impl ApiError {
+    /// The human-readable message, without the OpenAI `type`/`code` envelope.
+    pub fn message(&self) -> &str {
+        match self {
+            ApiError::Unauthorized(message)
+            | ApiError::TokenExpired(message)
+            | ApiError::InvalidApiKey(message)
+            | ApiError::BadRequest(message)
+            | ApiError::NotFound(message)
+            | ApiError::MethodNotAllowed(message)
+            | ApiError::PayloadTooLarge(message)
+            | ApiError::RateLimited { message, .. }
+            | ApiError::Conflict { message, .. }
+            | ApiError::ServiceUnavailable(message)
+            | ApiError::Cancelled(message)
+            | ApiError::Internal(message) => message,
+        }
+    }
+
+    /// The HTTP status the error is reported with.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::Unauthorized(_) | ApiError::TokenExpired(_) | ApiError::InvalidApiKey(_) => {
+                StatusCode::UNAUTHORIZED
+            }
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::MethodNotAllowed(_) => StatusCode::METHOD_NOT_ALLOWED,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Conflict { .. } => StatusCode::CONFLICT,
+            ApiError::ServiceUnavailable(_) | ApiError::Cancelled(_) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Renders the error, tagging the body with `error.request_id` when one is known.
+    pub fn into_response_with_request_id(self, request_id: Option<String>) -> Response {
+        let (status, retry_after, payload) = self.render(request_id);
+        let mut response = (status, Json(payload)).into_response();
+        if let Some(seconds) = retry_after {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        response
+    }
+
+    /// The `{"error": ...}` body alone, for failures reported inside an SSE stream whose `200`
+    /// headers have already gone out.
+    pub fn into_body_json(self, request_id: Option<String>) -> serde_json::Value {
+        let (_, _, payload) = self.render(request_id);
+        serde_json::to_value(payload).unwrap_or_default()
+    }
+
+    fn render(self, request_id: Option<String>) -> (StatusCode, Option<u64>, ErrorBody) {
+        let mut retry_after_header = None;
+        let mut client = None;
+        let mut original_request_id = None;
+        let (status, kind, code, message) = match self {
+            ApiError::Unauthorized(message) => (
+                StatusCode::UNAUTHORIZED,
+                "authentication_error",
+                "NOT_LOGGED_IN",
+                message,
+            ),
+            ApiError::TokenExpired(message) => (
+                StatusCode::UNAUTHORIZED,
+                "authentication_error",
+                "TOKEN_EXPIRED",
+                message,
+            ),
+            ApiError::InvalidApiKey(message) => (
+                StatusCode::UNAUTHORIZED,
+                "authentication_error",
+                "INVALID_API_KEY",
+                message,
+            ),
+            ApiError::BadRequest(message) => (
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                "BAD_REQUEST",
+                message,
+            ),
+            ApiError::NotFound(message) => (
+                StatusCode::NOT_FOUND,
+                "invalid_request_error",
+                "NOT_FOUND",
+                message,
+            ),
+            ApiError::MethodNotAllowed(message) => (
+                StatusCode::METHOD_NOT_ALLOWED,
+                "invalid_request_error",
+                "METHOD_NOT_ALLOWED",
+                message,
+            ),
+            ApiError::PayloadTooLarge(message) => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "invalid_request_error",
+                "PAYLOAD_TOO_LARGE",
+                message,
+            ),
+            ApiError::RateLimited {
+                message,
+                retry_after,
+                client: id,
+            } => {
+                retry_after_header = retry_after.map(retry_after_seconds);
+                client = id;
+                (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    "rate_limit_error",
+                    "RATE_LIMITED",
+                    message,
+                )
+            }
+            ApiError::Conflict {
+                message,
+                original_request_id: original,
+            } => {
+                original_request_id = original;
+                (
+                    StatusCode::CONFLICT,
+                    "invalid_request_error",
+                    "IDEMPOTENCY_CONFLICT",
+                    message,
+                )
             }
+            ApiError::ServiceUnavailable(message) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "server_error",
+                "SERVICE_UNAVAILABLE",
+                message,
+            ),
+            ApiError::Cancelled(message) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "server_error",
+                "REQUEST_CANCELLED",
+                message,
+            ),
+            ApiError::Internal(message) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "server_error",
+                "INTERNAL_ERROR",
+                message,
+            ),
         };
         let payload = ErrorBody {
-            error: ErrorDetails { message, code },
+            error: ErrorDetails {
+                message,
+                kind,
+                code,
+                request_id,
+                client,
+                original_request_id,
+            },
         };
-        (status, Json(payload)).into_response()
+        (status, retry_after_header, payload)
+    }
+}
+
+/// `Retry-After` only carries whole seconds; round up so clients never retry early.
+fn retry_after_seconds(delay: Duration) -> u64 {
+    let mut seconds = delay.as_secs();
+    if delay.subsec_nanos() > 0 {
+        seconds += 1;
+    }
+    seconds.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    async fn body_json(response: Response) -> Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("error body should be readable");
+        serde_json::from_slice(&bytes).expect("error body should be JSON")
+    }
+
+    #[tokio::test]
+    async fn rate_limited_sets_retry_after_header() {
+        let response = ApiError::rate_limited(
+            "Codex usage limit reached",
+            Some(Duration::from_millis(2_500)),
+        )
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(
+            response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok()),
+            Some("3")
+        );
+        let body = body_json(response).await;
+        assert_eq!(body["error"]["type"], "rate_limit_error");
+        assert_eq!(body["error"]["message"], "Codex usage limit reached");
+    }
+
+    #[tokio::test]
+    async fn rate_limited_without_reset_omits_header() {
+        let response = ApiError::rate_limited("slow down", None).into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().get(RETRY_AFTER).is_none());
+        let body = body_json(response).await;
+        assert!(body["error"].get("client").is_none());
     }
 }
@@ -1,5 +1,17 @@
+//! Codex Serve as a library: an axum [`router`] that speaks the OpenAI and Ollama chat APIs on
+//! top of Codex. The items re-exported here are the supported surface for embedding; see
+//! `examples/embedded.rs`.
+
+pub mod clock;
+pub mod convert;
 pub mod error;
 pub mod openai;
-pub mod prompt;
+pub(crate) mod prompt;
 pub mod serve_config;
 pub mod server;
+pub mod telemetry;
+
+pub use error::ApiError;
+pub use openai::chat::{ChatCompletionRequest, PromptPayload};
+pub use serve_config::ServeConfig;
+pub use server::{AppState, ChatExecutor, InitOptions, SharedChatExecutor, TestServer, router};
@@ -1,12 +1,21 @@
+use std::{path::PathBuf, time::Duration};
+
 use anyhow::Context;
-use clap::Parser;
+use clap::{ArgAction, Parser};
 use codex_serve::{
-    serve_config::{DeveloperPromptMode, ServeConfig, configure},
-    server,
+    serve_config::{
+        ApiKey, ApiSurfaces, DEFAULT_CAPTURE_MAX_BODY_BYTES, DEFAULT_FALLBACK_CHUNK_BYTES,
+        DEFAULT_MAX_BODY_SIZE, DEFAULT_MAX_METADATA_BODY_SIZE, DEFAULT_MAX_TOOL_OUTPUT_BYTES,
+        DEFAULT_MAX_TOOLS, DEFAULT_MAX_TRACKED_TOOL_CALLS, DEFAULT_OLLAMA_VERSION,
+        DeveloperPromptMode, ListenerAuth, ListenerSpec, OllamaTagStyle, ReportModel, ServeConfig,
+        ToolCallFallback, configure, parse_interval,
+    },
+    server, telemetry,
 };
+use opentelemetry_sdk::trace::SdkTracerProvider;
 use tokio::net::TcpListener;
 use tracing::info;
-use tracing_subscriber::{EnvFilter, filter::Directive};
+use tracing_subscriber::{EnvFilter, filter::Directive, prelude::*};
 
 #[derive(Parser)]
 #[command(
@@ -16,52 +25,391 @@ use tracing_subscriber::{EnvFilter, filter::Directive};
     long_about = "Run Codex Serve to proxy OpenAI-compatible requests into the Codex CLI engine."
 )]
 struct Cli {
-    /// Address to bind the HTTP listener to
+    /// Address to bind an HTTP listener to, optionally with `,auth=required` (clients must send
+    /// `--client-api-key`) or `,auth=none` (the default); repeat for several listeners
     #[arg(long, default_value = "127.0.0.1:8000")]
-    addr: String,
+    addr: Vec<ListenerSpec>,
+
+    /// Key clients must send as `Authorization: Bearer <key>` on `auth=required` listeners;
+    /// defaults to the `CODEX_SERVE_API_KEY` environment variable
+    #[arg(long)]
+    client_api_key: Option<String>,
+
+    /// Bind the Ollama routes (`/api/*`) to this address instead of `--addr`; both listeners
+    /// share one server state
+    #[arg(long)]
+    ollama_addr: Option<String>,
+
+    /// Comma-separated API route groups to serve (`openai`, `ollama`); routes of the others
+    /// answer 404
+    #[arg(long, default_value_t = ApiSurfaces::default())]
+    api_surface: ApiSurfaces,
 
     /// Emit verbose tool and response logging
     #[arg(long)]
     verbose: bool,
 
+    /// Redact conversation text (messages, tool arguments, reasoning) in verbose logs and captures
+    #[arg(long)]
+    verbose_redact: bool,
+
     /// Include reasoning model variants in the `/api/tags` list
     #[arg(long)]
     expose_reasoning_models: bool,
 
+    /// List `profile/model` combinations for each Codex config profile in `/v1/models`
+    #[arg(long)]
+    expose_profiles: bool,
+
     /// Override the Codex `features.web_search_request` flag (true/false). [default: false]
     #[arg(long)]
     web_search_request: bool,
 
+    /// Let a request turn web search on with `web_search_options` when it is off server-wide;
+    /// turning it off per request is always allowed
+    #[arg(long)]
+    allow_per_request_web_search: bool,
+
     /// Controls how Codex Serve injects its compatibility instructions:
     /// - `none`: never add the helper prompt.
     /// - `default`: add it only when the request lacks a system prompt.
     /// - `override`: always prepend it (the original system message is appended for transparency).
     #[arg(long, default_value_t = DeveloperPromptMode::Default)]
     developer_prompt_mode: DeveloperPromptMode,
+
+    /// Maximum request body size (in bytes) accepted by `/v1/chat/completions`
+    #[arg(long, default_value_t = DEFAULT_MAX_BODY_SIZE)]
+    max_body_size: usize,
+
+    /// Maximum request body size (in bytes) accepted by metadata routes such as `/api/show`
+    #[arg(long, default_value_t = DEFAULT_MAX_METADATA_BODY_SIZE)]
+    max_metadata_body_size: usize,
+
+    /// Authenticate with an OpenAI API key instead of the `codex login` session
+    /// [env: OPENAI_API_KEY]
+    #[arg(long)]
+    openai_api_key: Option<String>,
+
+    /// Export HTTP request traces to this OTLP/HTTP endpoint
+    /// (e.g. `http://localhost:4318/v1/traces`)
+    #[arg(long)]
+    otlp_endpoint: Option<String>,
+
+    /// Append each chat request/response pair to daily JSONL files in this directory
+    #[arg(long)]
+    capture_dir: Option<PathBuf>,
+
+    /// Maximum bytes of each request and response body kept in a capture record
+    #[arg(long, default_value_t = DEFAULT_CAPTURE_MAX_BODY_BYTES)]
+    capture_max_body_bytes: usize,
+
+    /// Serve a small HTML playground for trying prompts at `GET /`
+    #[arg(long)]
+    playground: bool,
+
+    /// Enable `POST /admin/reload` and `GET /admin/state` for operators
+    #[arg(long)]
+    enable_admin: bool,
+
+    /// Maximum concurrent chat requests per client (identified by bearer token, `user` field or
+    /// IP); extra requests get a 429
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    per_client_concurrency: Option<u64>,
+
+    /// Ollama version reported by `/api/version`
+    #[arg(long, default_value = DEFAULT_OLLAMA_VERSION)]
+    ollama_version: String,
+
+    /// Maximum bytes per SSE chunk when a reply arrives as one finished message instead of deltas
+    #[arg(
+        long,
+        default_value_t = DEFAULT_FALLBACK_CHUNK_BYTES as u64,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    fallback_chunk_bytes: u64,
+
+    /// Extra input for the Ollama model digests; change it to force clients to refetch model
+    /// metadata
+    #[arg(long)]
+    ollama_digest_salt: Option<String>,
+
+    /// How `/api/tags` and `/api/ps` name reasoning variants: `hyphen` (`gpt-5.1-codex-max-high`)
+    /// or `tag` (`gpt-5.1-codex-max:high`); requests accept both
+    #[arg(long, default_value_t = OllamaTagStyle::Hyphen)]
+    ollama_tag_style: OllamaTagStyle,
+
+    /// For requests without `tools`, what to send when the model calls tools anyway: `none`
+    /// (the tool calls) or `describe` (a text description of them, finishing with `stop`)
+    #[arg(long, default_value_t = ToolCallFallback::None)]
+    tool_call_fallback: ToolCallFallback,
+
+    /// Most tools one request may declare; more are rejected with a 400
+    #[arg(
+        long,
+        default_value_t = DEFAULT_MAX_TOOLS as u64,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    max_tools: u64,
+
+    /// Rewrite tool names outside `^[a-zA-Z0-9_-]{1,64}$` (e.g. dotted names) instead of rejecting
+    /// them; the model's tool calls are mapped back to the declared names
+    #[arg(long)]
+    sanitize_tool_names: bool,
+
+    /// Most tool calls of one upstream response to track by id; later calls are passed on once,
+    /// when done, and counted in a `tool_call_overflow` field on the reply
+    #[arg(
+        long,
+        default_value_t = DEFAULT_MAX_TRACKED_TOOL_CALLS as u64,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    max_tracked_tool_calls: u64,
+
+    /// Reject `temperature`, `top_p` or `reasoning_effort` with a 400 when the requested model
+    /// does not support them, instead of dropping them with a warning
+    #[arg(long)]
+    strict_params: bool,
+
+    /// Keep running when Codex cannot be initialized (no Codex home, broken `config.toml`):
+    /// `/healthz` reports the error and chat routes answer 503 until `/admin/reload` succeeds
+    #[arg(long)]
+    allow_degraded: bool,
+
+    /// Add Codex's raw token counts (cached input and reasoning output split out) to responses as
+    /// `codex_usage`, and total them per model and client on `/healthz`
+    #[arg(long)]
+    usage_extended: bool,
+
+    /// Developer mode: reject requests that would be served with warnings (dropped parameters,
+    /// rewritten tool schemas) with a 400 listing them
+    #[arg(long)]
+    fail_on_warnings: bool,
+
+    /// Send `refusal`, `audio`, `function_call`, `logprobs`, `system_fingerprint` and the usage
+    /// breakdowns as explicit `null`s or zeros, for SDKs whose response models require them
+    #[arg(long)]
+    compat_nulls: bool,
+
+    /// Refresh the login and re-check the default model config this often (e.g. `10m`) so the
+    /// first request after an idle spell starts warm; off by default
+    #[arg(long, value_parser = parse_interval)]
+    keepalive_interval: Option<Duration>,
+
+    /// Answer `stream: true` requests with a regular JSON completion when the backend cannot
+    /// stream, instead of a 503
+    #[arg(long)]
+    stream_fallback: bool,
+
+    /// Reject requests estimated (prompt plus a completion reserve) above this many tokens with
+    /// a 400
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    max_tokens_per_request: Option<u64>,
+
+    /// Answer 429 to a client (API key, `user` or IP) once it has used this many tokens in the
+    /// last hour, until enough of them age out of the window
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    max_tokens_per_hour: Option<u64>,
+
+    /// Give the model a request's `prediction` as a draft answer to reuse where it is correct;
+    /// by default it is ignored with a warning
+    #[arg(long)]
+    use_prediction_hint: bool,
+
+    /// Least time between the `: progress` comments of streams that request them with
+    /// `stream_options.codex_progress` (e.g. `500ms`)
+    #[arg(long, default_value = "1s", value_parser = parse_interval)]
+    progress_interval: Duration,
+
+    /// Send empty and whitespace-only messages upstream as they are instead of dropping them
+    #[arg(long)]
+    keep_empty_messages: bool,
+
+    /// Load every listed model's config at startup, so `/v1/models` capabilities carry each
+    /// model's context window from the start
+    #[arg(long)]
+    preload_models: bool,
+
+    /// Queue chat requests over --per-client-concurrency instead of refusing them with a 429
+    #[arg(long, requires = "per_client_concurrency")]
+    queue_requests: bool,
+
+    /// Save request counters, token budgets and conversation stats to this JSON file every
+    /// minute and on shutdown, and restore them at startup
+    #[arg(long)]
+    state_file: Option<PathBuf>,
+
+    /// Largest SSE event to send, in bytes; bigger content, reasoning and tool-argument deltas
+    /// are split into several chunks
+    #[arg(long)]
+    max_sse_event_bytes: Option<u64>,
+
+    /// Refuse dry-run chat requests (`x-codex-serve-dry-run` or `?dry_run=true`), which return
+    /// the converted prompt and developer prompt without calling upstream
+    #[arg(long)]
+    disable_dry_run: bool,
+
+    /// Drop a model's cached config once no request has used it for this long (e.g. `10m`); the
+    /// next request for it loads it again
+    #[arg(long, default_value = "30m", value_parser = parse_interval)]
+    cache_idle_ttl: Duration,
+
+    /// Which model name replies carry in `model`: `requested` (as the client sent it) or
+    /// `resolved` (the Codex model it ran on); the other goes in a `codex_*_model` field. Unset,
+    /// replies carry the requested name alone
+    #[arg(long)]
+    report_model: Option<ReportModel>,
+
+    /// Debug mode: end each chat stream with a SHA-256 and byte length of its content deltas so
+    /// a client can check what it reassembled
+    #[arg(long)]
+    stream_integrity: bool,
+
+    /// Cut tool results longer than this many bytes to their head and tail around a marker, with
+    /// a `tool_output_truncated` warning; `0` forwards them whole
+    #[arg(long, default_value_t = DEFAULT_MAX_TOOL_OUTPUT_BYTES)]
+    max_tool_output_bytes: usize,
+
+    /// Serve `POST /v1/codex/stream`, which answers a chat request with the raw Codex response
+    /// events as typed SSE events; the event schema follows codex-core and may change
+    #[arg(long)]
+    enable_codex_stream: bool,
+
+    /// Drop `<think>...</think>` blocks from assistant history before it goes upstream, so
+    /// reasoning a client echoes back is not read as the answer; `false` sends it as written
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
+    strip_replayed_reasoning: bool,
+
+    /// Refuse to start when the Codex config rejects `--web-search-request` (an older Codex
+    /// without `features.web_search_request`), instead of starting with web search disabled and a
+    /// warning in `/healthz`
+    #[arg(long)]
+    strict_config: bool,
+
+    /// Never let reasoning leave the process: ask Codex for no reasoning summary, and drop any
+    /// reasoning from replies on every API surface, verbose logs and capture files
+    #[arg(long)]
+    hide_reasoning: bool,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    init_tracing();
-
     let cli = Cli::parse();
+    let tracer_provider = cli
+        .otlp_endpoint
+        .as_deref()
+        .map(telemetry::otlp_tracer_provider)
+        .transpose()?;
+    init_tracing(tracer_provider.as_ref());
+
+    let openai_api_key = cli
+        .openai_api_key
+        .or_else(|| std::env::var("OPENAI_API_KEY").ok())
+        .and_then(ApiKey::new);
+    if openai_api_key.is_some() {
+        info!("using OpenAI API-key auth; the `codex login` session is ignored");
+    }
+    let client_api_key = cli
+        .client_api_key
+        .or_else(|| std::env::var("CODEX_SERVE_API_KEY").ok())
+        .and_then(ApiKey::new);
+    if client_api_key.is_none()
+        && cli
+            .addr
+            .iter()
+            .any(|spec| spec.auth == ListenerAuth::Required)
+    {
+        anyhow::bail!("`auth=required` listeners need --client-api-key (or CODEX_SERVE_API_KEY)");
+    }
     configure(ServeConfig {
         verbose: cli.verbose,
         expose_reasoning_models: cli.expose_reasoning_models,
+        expose_profiles: cli.expose_profiles,
         web_search_request: Some(cli.web_search_request),
         developer_prompt_mode: cli.developer_prompt_mode,
+        max_body_size: cli.max_body_size,
+        max_metadata_body_size: cli.max_metadata_body_size,
+        openai_api_key,
+        client_api_key,
+        capture_dir: cli.capture_dir,
+        capture_max_body_bytes: cli.capture_max_body_bytes,
+        verbose_redact: cli.verbose_redact,
+        playground: cli.playground,
+        enable_admin: cli.enable_admin,
+        per_client_concurrency: cli
+            .per_client_concurrency
+            .map(|limit| usize::try_from(limit).unwrap_or(usize::MAX)),
+        ollama_version: cli.ollama_version,
+        fallback_chunk_bytes: usize::try_from(cli.fallback_chunk_bytes).unwrap_or(usize::MAX),
+        ollama_digest_salt: cli.ollama_digest_salt,
+        strict_params: cli.strict_params,
+        allow_degraded: cli.allow_degraded,
+        usage_extended: cli.usage_extended,
+        fail_on_warnings: cli.fail_on_warnings,
+        api_surfaces: cli.api_surface,
+        ollama_tag_style: cli.ollama_tag_style,
+        tool_call_fallback: cli.tool_call_fallback,
+        max_tools: usize::try_from(cli.max_tools).unwrap_or(usize::MAX),
+        sanitize_tool_names: cli.sanitize_tool_names,
+        max_tracked_tool_calls: usize::try_from(cli.max_tracked_tool_calls).unwrap_or(usize::MAX),
+        allow_per_request_web_search: cli.allow_per_request_web_search,
+        compat_nulls: cli.compat_nulls,
+        keepalive_interval: cli.keepalive_interval,
+        stream_fallback: cli.stream_fallback,
+        max_tokens_per_request: cli.max_tokens_per_request,
+        max_tokens_per_hour: cli.max_tokens_per_hour,
+        use_prediction_hint: cli.use_prediction_hint,
+        progress_interval: cli.progress_interval,
+        keep_empty_messages: cli.keep_empty_messages,
+        preload_models: cli.preload_models,
+        queue_requests: cli.queue_requests,
+        state_file: cli.state_file,
+        max_sse_event_bytes: cli
+            .max_sse_event_bytes
+            .map(|bytes| usize::try_from(bytes).unwrap_or(usize::MAX)),
+        disable_dry_run: cli.disable_dry_run,
+        cache_idle_ttl: cli.cache_idle_ttl,
+        report_model: cli.report_model,
+        stream_integrity: cli.stream_integrity,
+        max_tool_output_bytes: cli.max_tool_output_bytes,
+        enable_codex_stream: cli.enable_codex_stream,
+        strip_replayed_reasoning: cli.strip_replayed_reasoning,
+        strict_config: cli.strict_config,
+        hide_reasoning: cli.hide_reasoning,
     });
 
-    let addr = cli.addr;
-    let listener = TcpListener::bind(&addr)
-        .await
-        .with_context(|| format!("failed to bind Codex Serve listener on {addr}"))?;
+    let mut listeners = Vec::with_capacity(cli.addr.len());
+    for spec in &cli.addr {
+        let listener = TcpListener::bind(&spec.addr)
+            .await
+            .with_context(|| format!("failed to bind Codex Serve listener on {}", spec.addr))?;
+        listeners.push((listener, spec.auth));
+    }
+
+    let ollama_listener = match cli.ollama_addr {
+        Some(ollama_addr) => {
+            if !cli.api_surface.ollama {
+                anyhow::bail!("--ollama-addr needs the ollama API surface (see --api-surface)");
+            }
+            let listener = TcpListener::bind(&ollama_addr).await.with_context(|| {
+                format!("failed to bind Codex Serve Ollama listener on {ollama_addr}")
+            })?;
+            info!(addr = %ollama_addr, "Codex Serve Ollama API listening");
+            Some(listener)
+        }
+        None => None,
+    };
 
-    info!(%addr, "Codex Serve listening");
-    server::serve(listener).await
+    for spec in &cli.addr {
+        info!(addr = %spec.addr, auth = %spec.auth, surfaces = %cli.api_surface, "Codex Serve listening");
+    }
+    let result = server::serve_listeners(listeners, ollama_listener).await;
+    if let Some(Err(err)) = tracer_provider.map(|provider| provider.shutdown()) {
+        eprintln!("failed to flush OpenTelemetry spans: {err}");
+    }
+    result
 }
 
-fn init_tracing() {
+fn init_tracing(tracer_provider: Option<&SdkTracerProvider>) {
     static SET_TRACING: std::sync::Once = std::sync::Once::new();
     SET_TRACING.call_once(|| {
         let mut filter =
@@ -71,9 +419,11 @@ fn init_tracing() {
             .expect("static directive should parse");
         filter = filter.add_directive(otel_directive);
 
-        tracing_subscriber::fmt()
-            .with_env_filter(filter)
-            .without_time()
+        tracing_subscriber::registry()
+            .with(filter)
+            .with(tracing_subscriber::fmt::layer().without_time())
+            .with(tracer_provider.map(telemetry::layer))
             .init();
+        server::install_panic_logging_hook();
     });
 }
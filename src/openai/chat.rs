@@ -1,15 +1,29 @@
-use crate::error::ApiError;
+use crate::{
+    clock::{LogContext, RequestClock},
+    error::ApiError,
+    prompt::CODEX_SERVE_PROMPT_MARKER,
+};
 use codex_core::{ContentItem, JsonSchema, Prompt, ResponseItem, ResponsesApiTool, ToolSpec};
-use codex_protocol::models::FunctionCallOutputPayload;
-use serde::{Deserialize, Serialize};
+use codex_protocol::config_types::{ReasoningEffort, ReasoningSummary, Verbosity};
+use codex_protocol::models::{FunctionCallOutputContentItem, FunctionCallOutputPayload};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use serde_json::{Map, Value, json};
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::RangeInclusive,
+};
 use tracing::{info, warn};
 
-use super::sanitize_json_schema;
-use crate::serve_config::verbose_logging_enabled;
+use super::{
+    convert::ConversionError,
+    image,
+    lenient::LenientList,
+    sanitize_json_schema,
+    tool_names::{self, ToolNames, ToolRules},
+    warnings::Warnings,
+};
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
 pub struct ChatCompletionRequest {
     #[serde(default)]
     pub model: String,
@@ -17,10 +31,113 @@ pub struct ChatCompletionRequest {
     pub messages: Vec<ChatMessage>,
     #[serde(default)]
     pub stream: bool,
+    /// `null` and a bare tool object are read as their array, with a warning.
     #[serde(default)]
-    pub tools: Vec<RequestTool>,
+    pub tools: LenientList<RequestTool>,
     #[serde(default)]
     pub parallel_tool_calls: Option<bool>,
+    /// Flat effort as older OpenAI clients send it; wins over `reasoning.effort`.
+    #[serde(default)]
+    pub reasoning_effort: Option<String>,
+    #[serde(default)]
+    pub reasoning: Option<ReasoningOptions>,
+    #[serde(default)]
+    pub temperature: Option<f64>,
+    #[serde(default)]
+    pub top_p: Option<f64>,
+    /// Answer length for models that take it (gpt-5): `low`, `medium` or `high`.
+    #[serde(default)]
+    pub verbosity: Option<String>,
+    /// `{"enabled": false}` keeps the server's `web_search` tool out of this request; `true` asks
+    /// for it where `--allow-per-request-web-search` permits.
+    #[serde(default)]
+    pub web_search_options: Option<WebSearchOptions>,
+    /// Only `"none"` is acted on: it covers the injected `web_search` tool too.
+    #[serde(default)]
+    pub tool_choice: Option<Value>,
+    /// Predicted output. Codex has no predicted outputs; `--use-prediction-hint` passes it to the
+    /// model as a draft instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prediction: Option<Prediction>,
+    /// Not enforced upstream; `json_object` or `json_schema` makes `first_valid_json` the
+    /// default `codex.select`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<Value>,
+    /// Vendor extension with Codex Serve's own request options.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub codex: Option<CodexOptions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+}
+
+/// `stream_options`. Usage is always sent on the last chunk, so `include_usage` is not read.
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct StreamOptions {
+    /// Vendor extension: interleave `: progress` SSE comments with a running token estimate.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub codex_progress: bool,
+}
+
+/// The `codex` vendor extension: `{"samples": 3, "select": "majority"}` runs the request that
+/// many times and answers with the reply `select` picks.
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct CodexOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub samples: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub select: Option<String>,
+    /// `false` forwards tool results whole, past `--max-tool-output-bytes`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub truncate_tool_output: Option<bool>,
+}
+
+/// Most completions one request may fan out to with `codex.samples`.
+pub const MAX_SAMPLES: u64 = 8;
+
+/// Several completions of one request, of which one is returned (`codex.samples`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Sampling {
+    pub samples: usize,
+    pub select: Selection,
+}
+
+/// How `codex.select` picks among the samples' replies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Selection {
+    /// The reply most samples agree on, compared without case and extra whitespace.
+    #[default]
+    Majority,
+    /// The first reply whose text parses as JSON.
+    FirstValidJson,
+    /// The longest reply.
+    Longest,
+}
+
+/// OpenAI's `prediction` object: `{"type": "content", "content": ...}`, the content as text or
+/// text parts.
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct Prediction {
+    #[serde(rename = "type", default)]
+    pub kind: String,
+    #[serde(default)]
+    pub content: Value,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct WebSearchOptions {
+    /// Sending the object at all asks for web search, as in OpenAI's API.
+    #[serde(default)]
+    pub enabled: Option<bool>,
+}
+
+/// The `reasoning` object of OpenAI's newer APIs. Values are validated in `into_prompt`.
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct ReasoningOptions {
+    #[serde(default)]
+    pub effort: Option<String>,
+    #[serde(default)]
+    pub summary: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Default, Clone)]
@@ -33,8 +150,9 @@ pub struct ChatMessage {
     pub name: Option<String>,
     #[serde(default)]
     pub tool_call_id: Option<String>,
+    /// A bare tool call object is read as a one-element array, with a warning.
     #[serde(default)]
-    pub tool_calls: Option<Vec<ChatToolCall>>,
+    pub tool_calls: Option<LenientList<ChatToolCall>>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Default, Clone)]
@@ -75,30 +193,226 @@ pub struct RequestToolFunction {
     pub parameters: Option<Value>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct PromptPayload {
     pub model: String,
     pub prompt: Prompt,
     pub first_user_message: Option<String>,
     pub system_prompt: Option<String>,
+    /// Codex config profile selected for this request, if any.
+    pub profile: Option<String>,
+    /// Per-request overrides of the Codex config's reasoning settings.
+    pub reasoning_effort: Option<ReasoningEffort>,
+    pub reasoning_summary: Option<ReasoningSummary>,
+    /// Sampling controls; the executor drops or rejects them for models that do not take them.
+    pub temperature: Option<f64>,
+    pub top_p: Option<f64>,
+    /// Per-request override of the Codex config's `model_verbosity`; dropped or rejected like
+    /// the sampling controls for models that do not take it.
+    pub verbosity: Option<Verbosity>,
+    /// Per-request override of whether the `web_search` tool is offered; the executor decides
+    /// whether an enable is honored.
+    pub web_search: Option<bool>,
+    /// What was changed or dropped to serve the request.
+    pub warnings: Warnings,
+    /// Tool names rewritten by `--sanitize-tool-names`, to restore on the model's tool calls.
+    pub tool_names: ToolNames,
+    /// The text of the request's `prediction`, if it had a non-empty one.
+    pub prediction: Option<String>,
+    /// `codex.samples` above one: how many completions to run and how to pick the reply.
+    pub sampling: Option<Sampling>,
+    /// `stream_options.codex_progress`: send progress comments while streaming.
+    pub stream_progress: bool,
+    /// The reply's `id` and `created`, taken when the request was converted.
+    pub clock: RequestClock,
+}
+
+/// Everything besides the request itself that decides how it converts: the front-end it came
+/// through and the server settings that apply there. The server builds one per endpoint with
+/// [`ServeConfig::conversion_options`](crate::ServeConfig::conversion_options), so every
+/// front-end converts the same messages by the same rules unless its `endpoint` says otherwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConversionOptions {
+    /// What the front-end's contract accepts as a prompt.
+    pub endpoint: PromptEndpoint,
+    /// Reject role aliases such as `human` and list fields sent as `null` or a bare object,
+    /// instead of reading them as what they stand for (`--strict-params`).
+    pub strict_roles: bool,
+    /// Send blank messages upstream as they are instead of dropping them
+    /// (`--keep-empty-messages`).
+    pub keep_empty_messages: bool,
+    /// Limits and naming rules for the declared tools.
+    pub tools: ToolRules,
+    /// Longest tool result forwarded, in bytes; a longer one keeps its head and tail around a
+    /// marker (`--max-tool-output-bytes`). `None` forwards tool results whole.
+    pub max_tool_output_bytes: Option<usize>,
+    /// Drop `<think>...</think>` blocks from assistant history, reasoning a client echoed back
+    /// into `content` (`--strip-replayed-reasoning`).
+    pub strip_replayed_reasoning: bool,
+    /// Ask for no reasoning summary whatever the request says (`--hide-reasoning`).
+    pub hide_reasoning: bool,
+}
+
+/// Default for `--max-tool-output-bytes`.
+pub const DEFAULT_MAX_TOOL_OUTPUT_BYTES: usize = 64 * 1024;
+
+impl Default for ConversionOptions {
+    fn default() -> Self {
+        Self {
+            endpoint: PromptEndpoint::default(),
+            strict_roles: false,
+            keep_empty_messages: false,
+            tools: ToolRules::default(),
+            max_tool_output_bytes: Some(DEFAULT_MAX_TOOL_OUTPUT_BYTES),
+            strip_replayed_reasoning: true,
+            hide_reasoning: false,
+        }
+    }
+}
+
+impl ConversionOptions {
+    /// The defaults for `endpoint`, as a library caller without server settings converts.
+    pub fn for_endpoint(endpoint: PromptEndpoint) -> Self {
+        Self {
+            endpoint,
+            ..Self::default()
+        }
+    }
+}
+
+/// What a front-end's contract accepts as a prompt, for the emptiness checks of
+/// [`ChatCompletionRequest::into_prompt_for`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PromptEndpoint {
+    /// `/v1/chat/completions` and Ollama's `/api/chat`: `messages` must not be empty, but a
+    /// system-only conversation is a prompt the model answers unprompted.
+    #[default]
+    Chat,
+    /// Instruction-style endpoints (Ollama's `/api/generate`, Gemini's `generateContent`): the
+    /// `system` prompt or `systemInstruction` alone is enough.
+    Instructions,
 }
 
 impl ChatCompletionRequest {
     pub fn into_prompt(self) -> Result<PromptPayload, ApiError> {
-        if self.messages.is_empty() {
-            return Err(ApiError::bad_request("Request must include messages: []"));
+        self.into_prompt_for(ConversionOptions::default())
+    }
+
+    /// [`Self::into_prompt`] for a request the caller keeps, e.g. to convert it again.
+    pub fn to_prompt(&self) -> Result<PromptPayload, ApiError> {
+        self.clone().into_prompt()
+    }
+
+    /// [`Self::into_prompt_for`] for a request the caller keeps.
+    pub fn to_prompt_for(&self, options: ConversionOptions) -> Result<PromptPayload, ApiError> {
+        self.clone().into_prompt_for(options)
+    }
+
+    /// Whether `response_format` asks for JSON, as `json_object` or `json_schema`.
+    fn asks_for_json(&self) -> bool {
+        let kind = self
+            .response_format
+            .as_ref()
+            .and_then(|format| format.get("type"))
+            .and_then(Value::as_str);
+        matches!(kind, Some("json_object" | "json_schema"))
+    }
+
+    /// Converts the request under `options`: its endpoint's rules for what counts as a prompt and
+    /// the server's settings. A request that leaves nothing to send upstream is rejected under
+    /// either endpoint.
+    ///
+    /// Blank text (empty or whitespace-only, often a client UI's leftover) is dropped, and with
+    /// it any message left with nothing to say: an assistant message keeps only its tool calls.
+    /// A blank tool output is kept as an empty, successful output, since "no output" is an
+    /// answer. `options.keep_empty_messages` sends blank messages as they are.
+    ///
+    /// A system message carrying Codex Serve's own developer prompt, echoed back from an earlier
+    /// turn, is passed on but is not the client's system prompt. Reasoning echoed back on an
+    /// assistant message is not sent: `reasoning` and `reasoning_content` fields are never read,
+    /// and `options.strip_replayed_reasoning` drops `<think>` blocks from its text.
+    pub fn into_prompt_for(self, options: ConversionOptions) -> Result<PromptPayload, ApiError> {
+        if options.endpoint == PromptEndpoint::Chat && self.messages.is_empty() {
+            return Err(ConversionError::new("must include at least one message")
+                .field("messages")
+                .into());
         }
 
+        let (reasoning_effort, mut reasoning_summary) =
+            parse_reasoning(self.reasoning_effort.as_deref(), self.reasoning.as_ref())?;
+        if options.hide_reasoning {
+            reasoning_summary = Some(ReasoningSummary::None);
+        }
+        let temperature =
+            check_range(self.temperature, 0.0..=2.0).map_err(|err| err.field("temperature"))?;
+        let top_p = check_range(self.top_p, 0.0..=1.0).map_err(|err| err.field("top_p"))?;
+        let verbosity = self
+            .verbosity
+            .as_deref()
+            .map(|value| parse_option_value(value, "low, medium or high"))
+            .transpose()
+            .map_err(|err| err.field("verbosity"))?;
+        let prediction = self
+            .prediction
+            .as_ref()
+            .map(prediction_text)
+            .transpose()
+            .map_err(|err| err.field("prediction"))?
+            .flatten();
+        let sampling = self
+            .codex
+            .as_ref()
+            .map(|codex| parse_sampling(codex, self.stream, self.asks_for_json()))
+            .transpose()
+            .map_err(|err| err.field("codex"))?
+            .flatten();
+        let max_tool_output_bytes = options.max_tool_output_bytes.filter(|_| {
+            self.codex
+                .as_ref()
+                .and_then(|codex| codex.truncate_tool_output)
+                != Some(false)
+        });
+        let web_search = if self.tool_choice.as_ref().and_then(Value::as_str) == Some("none") {
+            Some(false)
+        } else {
+            self.web_search_options
+                .as_ref()
+                .map(|options| options.enabled.unwrap_or(true))
+        };
         let model = normalize_model(self.model);
         let mut prompt = Prompt::default();
         let mut first_user = None;
         let mut system_segments: Vec<String> = Vec::new();
-        for message in self.messages {
+        let warnings = Warnings::default();
+        for (index, mut message) in self.messages.into_iter().enumerate() {
             let original_role = message.role.clone();
-            let role = normalize_role(&message.role);
+            let role = normalize_role(&message.role, options.strict_roles, &warnings)
+                .map_err(|err| err.in_message(index))?;
+            if let Some(calls) = &message.tool_calls {
+                calls
+                    .check_shape(
+                        &format!("messages[{index}].tool_calls"),
+                        options.strict_roles,
+                        &warnings,
+                    )
+                    .map_err(|err| err.field("tool_calls").in_message(index))?;
+            }
 
             if role == "tool" {
-                if let Some(output_item) = convert_tool_output(&message) {
+                // Legacy `function` messages name the function instead of the call they answer.
+                if message.tool_call_id.is_none()
+                    && let Some(name) = message.name.as_deref()
+                {
+                    message.tool_call_id = last_call_id(&prompt.input, name);
+                }
+                if let Some(output_item) = convert_tool_output(
+                    &message,
+                    options.keep_empty_messages,
+                    max_tool_output_bytes,
+                    &warnings,
+                )
+                .map_err(|err| err.in_message(index))?
+                {
                     prompt.input.push(output_item);
                 }
                 continue;
@@ -109,9 +423,17 @@ impl ChatCompletionRequest {
                 prompt.input.extend(tool_call_items);
             }
 
-            let content = convert_content(&role, message.content)?;
+            let mut content = convert_content(&role, message.content, &warnings)
+                .map_err(|err| err.in_message(index))?;
+            if role == "assistant" && options.strip_replayed_reasoning {
+                strip_replayed_reasoning(&mut content, &warnings);
+            }
+            if !options.keep_empty_messages {
+                content.retain(|item| !is_blank_text(item));
+            }
             if original_role.trim().eq_ignore_ascii_case("system")
                 && let Some(text) = plain_text_from_content(&content)
+                && !text.contains(CODEX_SERVE_PROMPT_MARKER)
             {
                 system_segments.push(text);
             }
@@ -129,10 +451,31 @@ impl ChatCompletionRequest {
                 content,
             });
         }
+        if prompt.input.is_empty() {
+            let err = match options.endpoint {
+                PromptEndpoint::Chat => {
+                    ConversionError::new("must include at least one message with content")
+                        .field("messages")
+                }
+                PromptEndpoint::Instructions => {
+                    ConversionError::new("must include a prompt or system instructions")
+                }
+            };
+            return Err(err.into());
+        }
 
-        if let Some(specs) = convert_function_tools(&self.tools)? {
-            log_function_tools(&specs);
-            prompt.tools.extend(specs);
+        self.tools
+            .check_shape("tools", options.strict_roles, &warnings)
+            .map_err(|err| err.field("tools"))?;
+        let (specs, tool_names) = convert_function_tools(&self.tools, options.tools, &warnings)?;
+        prompt.tools.extend(specs);
+        if !tool_names.is_empty() {
+            // Replayed calls must use the names the model is given.
+            for item in &mut prompt.input {
+                if let ResponseItem::FunctionCall { name, .. } = item {
+                    *name = tool_names.sanitized(name).to_string();
+                }
+            }
         }
 
         prompt.parallel_tool_calls = self.parallel_tool_calls.unwrap_or(true);
@@ -148,10 +491,141 @@ impl ChatCompletionRequest {
             prompt,
             first_user_message: first_user,
             system_prompt,
+            profile: None,
+            reasoning_effort,
+            reasoning_summary,
+            temperature,
+            top_p,
+            verbosity,
+            web_search,
+            warnings,
+            tool_names,
+            prediction,
+            sampling,
+            stream_progress: self
+                .stream_options
+                .is_some_and(|options| options.codex_progress),
+            clock: RequestClock::start(),
         })
     }
 }
 
+/// The predicted text of a `prediction`, `None` when it is empty.
+fn prediction_text(prediction: &Prediction) -> Result<Option<String>, ConversionError> {
+    if !prediction.kind.trim().eq_ignore_ascii_case("content") {
+        return Err(ConversionError::new(format!(
+            "unsupported value `{}`; expected content",
+            prediction.kind
+        ))
+        .field("type"));
+    }
+    let text = match &prediction.content {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .map(|part| part.get("text").and_then(Value::as_str))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| ConversionError::new("parts must be text parts").field("content"))?
+            .concat(),
+        _ => {
+            return Err(
+                ConversionError::new("must be text or an array of text parts").field("content"),
+            );
+        }
+    };
+    Ok((!text.trim().is_empty()).then_some(text))
+}
+
+/// `codex.samples` and `codex.select`; a single sample is a plain request. Without a `select`, a
+/// request that `asks_for_json` keeps the first reply that parses.
+fn parse_sampling(
+    codex: &CodexOptions,
+    stream: bool,
+    asks_for_json: bool,
+) -> Result<Option<Sampling>, ConversionError> {
+    let select = codex
+        .select
+        .as_deref()
+        .map(|value| parse_option_value(value, "majority, first_valid_json or longest"))
+        .transpose()
+        .map_err(|err| err.field("select"))?
+        .unwrap_or(if asks_for_json {
+            Selection::FirstValidJson
+        } else {
+            Selection::default()
+        });
+    let samples = match codex.samples {
+        None | Some(1) => return Ok(None),
+        Some(samples @ 2..=MAX_SAMPLES) => samples,
+        Some(samples) => {
+            return Err(ConversionError::new(format!(
+                "{samples} is out of range; expected 1 to {MAX_SAMPLES}"
+            ))
+            .field("samples"));
+        }
+    };
+    if stream {
+        return Err(
+            ConversionError::new("several samples cannot be streamed; send stream: false")
+                .field("samples"),
+        );
+    }
+    Ok(Some(Sampling {
+        samples: samples as usize,
+        select,
+    }))
+}
+
+fn check_range(
+    value: Option<f64>,
+    range: RangeInclusive<f64>,
+) -> Result<Option<f64>, ConversionError> {
+    match value {
+        Some(value) if !range.contains(&value) => Err(ConversionError::new(format!(
+            "must be between {} and {}",
+            range.start(),
+            range.end()
+        ))),
+        _ => Ok(value),
+    }
+}
+
+fn parse_reasoning(
+    flat_effort: Option<&str>,
+    reasoning: Option<&ReasoningOptions>,
+) -> Result<(Option<ReasoningEffort>, Option<ReasoningSummary>), ConversionError> {
+    const EFFORTS: &str = "none, minimal, low, medium, high or xhigh";
+    const SUMMARIES: &str = "auto, concise, detailed or none";
+
+    let effort = match flat_effort {
+        Some(value) => {
+            Some(parse_option_value(value, EFFORTS).map_err(|err| err.field("reasoning_effort"))?)
+        }
+        None => reasoning
+            .and_then(|reasoning| reasoning.effort.as_deref())
+            .map(|value| parse_option_value(value, EFFORTS))
+            .transpose()
+            .map_err(|err| err.field("effort").field("reasoning"))?,
+    };
+    let summary = reasoning
+        .and_then(|reasoning| reasoning.summary.as_deref())
+        .map(|value| parse_option_value(value, SUMMARIES))
+        .transpose()
+        .map_err(|err| err.field("summary").field("reasoning"))?;
+    Ok((effort, summary))
+}
+
+/// One of the lower-case names `T` deserializes from, case-insensitively.
+fn parse_option_value<T: DeserializeOwned>(
+    value: &str,
+    expected: &str,
+) -> Result<T, ConversionError> {
+    serde_json::from_value(Value::String(value.trim().to_ascii_lowercase())).map_err(|_| {
+        ConversionError::new(format!("unsupported value `{value}`; expected {expected}"))
+    })
+}
+
 fn normalize_model(model: String) -> String {
     let trimmed = model.trim();
     if trimmed.is_empty() {
@@ -161,94 +635,153 @@ fn normalize_model(model: String) -> String {
     }
 }
 
-fn normalize_role(role: &str) -> String {
+/// The upstream role for `role`, in any casing; a missing role is `user`. Roles that other
+/// chat exports use (`human`, `ai`, `model`, `function`) are read as their OpenAI equivalent
+/// unless `strict`, and anything else is rejected rather than failing upstream.
+fn normalize_role(
+    role: &str,
+    strict: bool,
+    warnings: &Warnings,
+) -> Result<String, ConversionError> {
     let trimmed = role.trim();
     if trimmed.is_empty() {
-        return "user".to_string();
+        return Ok("user".to_string());
     }
-    match trimmed.to_ascii_lowercase().as_str() {
+    let lower = trimmed.to_ascii_lowercase();
+    let alias = match lower.as_str() {
         // The Codex backend rejects role=system. Translate it into the
         // developer stream as described in reference/blog.
-        "system" => "developer".to_string(),
-        other => other.to_string(),
+        "system" => return Ok("developer".to_string()),
+        "developer" | "user" | "assistant" | "tool" => return Ok(lower),
+        "human" => "user",
+        "ai" | "model" => "assistant",
+        "function" => "tool",
+        _ => {
+            return Err(ConversionError::new(format!(
+                "unsupported role `{trimmed}`; expected system, developer, user, assistant or \
+                 tool"
+            ))
+            .field("role"));
+        }
+    };
+    if strict {
+        return Err(ConversionError::new(format!(
+            "unsupported role `{trimmed}`; use `{alias}` (role aliases are rejected under \
+             --strict-params)"
+        ))
+        .field("role"));
     }
+    warnings.push(
+        "role_renamed",
+        format!("role `{trimmed}` was read as `{alias}`"),
+    );
+    Ok(alias.to_string())
 }
 
-fn convert_content(role: &str, value: Value) -> Result<Vec<ContentItem>, ApiError> {
+/// The id of the latest call to `name` in `input`, for results that only name their function.
+fn last_call_id(input: &[ResponseItem], name: &str) -> Option<String> {
+    input.iter().rev().find_map(|item| match item {
+        ResponseItem::FunctionCall {
+            name: called,
+            call_id,
+            ..
+        } if called == name.trim() => Some(call_id.clone()),
+        _ => None,
+    })
+}
+
+fn convert_content(
+    role: &str,
+    value: Value,
+    warnings: &Warnings,
+) -> Result<Vec<ContentItem>, ConversionError> {
     match value {
         Value::Null => Ok(Vec::new()),
         Value::String(text) => Ok(vec![content_item_for_role(role, text)]),
-        Value::Array(items) => {
-            let mut content_items = Vec::with_capacity(items.len());
-            for item in items {
-                content_items.push(convert_content_item(role, item)?);
-            }
-            Ok(content_items)
-        }
+        Value::Array(items) => items
+            .into_iter()
+            .enumerate()
+            .map(|(index, item)| {
+                convert_content_item(role, item, warnings).map_err(|err| err.at("content", index))
+            })
+            .collect(),
         Value::Object(map) => {
             if let Some(text) = map.get("text").and_then(Value::as_str) {
                 return Ok(vec![content_item_for_role(role, text.to_string())]);
             }
-            if let Some(value) = map.get("type").and_then(Value::as_str) {
-                match value {
-                    "text" | "input_text" => {
-                        let text = map
-                            .get("text")
-                            .and_then(Value::as_str)
-                            .ok_or_else(|| ApiError::bad_request("text block missing `text`"))?;
-                        Ok(vec![content_item_for_role(role, text.to_string())])
-                    }
-                    "image_url" | "input_image" => {
-                        let url = extract_image_url(&map)?;
-                        Ok(vec![ContentItem::InputImage { image_url: url }])
-                    }
-                    other => Err(ApiError::bad_request(format!(
-                        "Unsupported content type `{other}`"
-                    ))),
-                }
-            } else {
-                Err(ApiError::bad_request(
-                    "Message content object must include `type`",
-                ))
+            if !map.contains_key("type")
+                && let Some(text) = part_text(&map, warnings)
+            {
+                return Ok(vec![content_item_for_role(role, text)]);
             }
+            convert_content_item(role, Value::Object(map), warnings)
+                .map(|item| vec![item])
+                .map_err(|err| err.field("content"))
+        }
+        _ => {
+            Err(ConversionError::new("must be text or a structured content array").field("content"))
         }
-        _ => Err(ApiError::bad_request(
-            "Message content must be text or a structured content array",
-        )),
     }
 }
 
-fn convert_content_item(role: &str, value: Value) -> Result<ContentItem, ApiError> {
+fn convert_content_item(
+    role: &str,
+    value: Value,
+    warnings: &Warnings,
+) -> Result<ContentItem, ConversionError> {
     match value {
         Value::String(text) => Ok(content_item_for_role(role, text)),
         Value::Object(map) => {
-            let ctype = map
-                .get("type")
-                .and_then(Value::as_str)
-                .ok_or_else(|| ApiError::bad_request("Content item missing `type`"))?;
+            let Some(ctype) = map.get("type").and_then(Value::as_str) else {
+                // A part that is nothing but its text needs no type to be understood.
+                if map.len() == 1
+                    && let Some(text) = part_text(&map, warnings)
+                {
+                    warnings.push(
+                        "content_part_untyped",
+                        "a content part without `type` was read as text",
+                    );
+                    return Ok(content_item_for_role(role, text));
+                }
+                return Err(ConversionError::new("is required").field("type"));
+            };
             match ctype {
                 "text" | "input_text" => {
-                    let text = map
-                        .get("text")
-                        .and_then(Value::as_str)
-                        .ok_or_else(|| ApiError::bad_request("text block missing `text`"))?;
-                    Ok(content_item_for_role(role, text.to_string()))
+                    let text = part_text(&map, warnings).ok_or_else(|| {
+                        ConversionError::new("is required for text blocks").field("text")
+                    })?;
+                    Ok(content_item_for_role(role, text))
                 }
                 "image_url" | "input_image" => {
                     let url = extract_image_url(&map)?;
                     Ok(ContentItem::InputImage { image_url: url })
                 }
-                other => Err(ApiError::bad_request(format!(
-                    "Unsupported content type `{other}`"
-                ))),
+                other => Err(
+                    ConversionError::new(format!("unsupported content type `{other}`"))
+                        .field("type"),
+                ),
             }
         }
-        _ => Err(ApiError::bad_request(
-            "Content items must be strings or structured objects",
+        _ => Err(ConversionError::new(
+            "content parts must be strings or structured objects",
         )),
     }
 }
 
+/// The `text` of a content part, or the `content` string LangChain's OpenAI adapter sometimes
+/// sends in its place.
+fn part_text(map: &Map<String, Value>, warnings: &Warnings) -> Option<String> {
+    if let Some(text) = map.get("text").and_then(Value::as_str) {
+        return Some(text.to_string());
+    }
+    let text = map.get("content").and_then(Value::as_str)?;
+    warnings.push(
+        "content_text_renamed",
+        "a content part carried its text under `content` instead of `text`",
+    );
+    Some(text.to_string())
+}
+
 fn content_item_for_role(role: &str, text: impl Into<String>) -> ContentItem {
     let text = text.into();
     if role == "assistant" {
@@ -258,16 +791,70 @@ fn content_item_for_role(role: &str, text: impl Into<String>) -> ContentItem {
     }
 }
 
-fn extract_image_url(map: &Map<String, Value>) -> Result<String, ApiError> {
-    if let Some(url) = map.get("image_url").and_then(Value::as_str) {
-        return Ok(url.to_string());
+/// The part's image URL, with bare base64 wrapped into a data URL (see [`image::image_url`]).
+fn extract_image_url(map: &Map<String, Value>) -> Result<String, ConversionError> {
+    let url = map
+        .get("image_url")
+        .and_then(|value| match value {
+            Value::String(url) => Some(url.as_str()),
+            Value::Object(url_obj) => url_obj.get("url").and_then(Value::as_str),
+            _ => None,
+        })
+        .ok_or_else(|| {
+            ConversionError::new("image content requires a URL string or an object with `url`")
+                .field("image_url")
+        })?;
+    image::image_url(url).map_err(|err| err.field("image_url"))
+}
+
+/// Removes `<think>...</think>` blocks from an assistant message's text. Clients that show
+/// reasoning inline send it back with the answer, and the model would read it as part of what it
+/// said. An unclosed `<think>` is left alone, as it may be the answer's own text.
+fn strip_replayed_reasoning(content: &mut [ContentItem], warnings: &Warnings) {
+    let mut stripped = false;
+    for item in content {
+        if let ContentItem::OutputText { text } = item
+            && let Some(answer) = strip_think_blocks(text)
+        {
+            *text = answer;
+            stripped = true;
+        }
+    }
+    if stripped {
+        warnings.push(
+            "replayed_reasoning_stripped",
+            "`<think>` blocks were removed from assistant history",
+        );
     }
-    if let Some(url_obj) = map.get("image_url").and_then(Value::as_object)
-        && let Some(url) = url_obj.get("url").and_then(Value::as_str)
+}
+
+/// `text` without its closed `<think>` blocks and the whitespace after each, or `None` if it has
+/// none.
+fn strip_think_blocks(text: &str) -> Option<String> {
+    const OPEN: &str = "<think>";
+    const CLOSE: &str = "</think>";
+    let mut answer = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(OPEN)
+        && let Some(end) = rest[start..].find(CLOSE)
     {
-        return Ok(url.to_string());
+        answer.push_str(&rest[..start]);
+        rest = rest[start + end + CLOSE.len()..].trim_start();
+    }
+    if rest.len() == text.len() {
+        return None;
+    }
+    answer.push_str(rest);
+    Some(answer)
+}
+
+fn is_blank_text(item: &ContentItem) -> bool {
+    match item {
+        ContentItem::InputText { text } | ContentItem::OutputText { text } => {
+            text.trim().is_empty()
+        }
+        _ => false,
     }
-    Err(ApiError::bad_request("image content requires `image_url`"))
 }
 
 fn first_text(content: &[ContentItem]) -> Option<String> {
@@ -297,7 +884,7 @@ fn plain_text_from_content(content: &[ContentItem]) -> Option<String> {
     }
 }
 
-fn convert_assistant_tool_calls(calls: Option<&Vec<ChatToolCall>>) -> Vec<ResponseItem> {
+fn convert_assistant_tool_calls(calls: Option<&LenientList<ChatToolCall>>) -> Vec<ResponseItem> {
     let mut items = Vec::new();
     if let Some(list) = calls {
         for tc in list {
@@ -336,34 +923,153 @@ fn convert_assistant_tool_calls(calls: Option<&Vec<ChatToolCall>>) -> Vec<Respon
     items
 }
 
-fn convert_tool_output(message: &ChatMessage) -> Option<ResponseItem> {
-    let call_id = message.tool_call_id.as_deref()?;
-    let content = match &message.content {
-        Value::String(text) => text.clone(),
-        Value::Array(parts) => parts
-            .iter()
-            .filter_map(|part| part.get("text").and_then(Value::as_str))
-            .collect::<Vec<_>>()
-            .join("\n"),
-        _ => return None,
+/// Tool results are usually text, but screenshots and other images are passed through as
+/// `content_items` so the model sees them; `content` always keeps the flattened text. Unless
+/// `keep_empty` is set, a blank or missing result becomes an empty one.
+///
+/// Text longer than `max_bytes` (the flattened text, and each text part beside images) is cut
+/// in the middle, which `warnings` records: agent clients post megabytes of logs as tool
+/// results, and the upstream would only fail on them late.
+fn convert_tool_output(
+    message: &ChatMessage,
+    keep_empty: bool,
+    max_bytes: Option<usize>,
+    warnings: &Warnings,
+) -> Result<Option<ResponseItem>, ConversionError> {
+    let Some(call_id) = message.tool_call_id.as_deref() else {
+        return Ok(None);
+    };
+    let (mut content, mut content_items) = match &message.content {
+        Value::Null if !keep_empty => (String::new(), None),
+        Value::String(text) => (text.clone(), None),
+        Value::Array(parts) => {
+            let mut texts = Vec::new();
+            let mut items = Vec::new();
+            let mut has_image = false;
+            for (index, part) in parts.iter().enumerate() {
+                let kind = part.get("type").and_then(Value::as_str);
+                if let (Some("image_url" | "input_image"), Some(map)) = (kind, part.as_object()) {
+                    has_image = true;
+                    items.push(FunctionCallOutputContentItem::InputImage {
+                        image_url: extract_image_url(map)
+                            .map_err(|err| err.at("content", index))?,
+                    });
+                } else if let Some(text) = part.get("text").and_then(Value::as_str) {
+                    texts.push(text);
+                    items.push(FunctionCallOutputContentItem::InputText {
+                        text: text.to_string(),
+                    });
+                }
+            }
+            (texts.join("\n"), has_image.then_some(items))
+        }
+        _ => return Ok(None),
     };
-    Some(ResponseItem::FunctionCallOutput {
+    if !keep_empty && content_items.is_none() && content.trim().is_empty() {
+        content.clear();
+    }
+    if let Some(limit) = max_bytes {
+        let mut omitted = truncate_middle(&mut content, limit);
+        if let Some(items) = content_items.as_mut() {
+            omitted = truncate_parts(items, limit);
+        }
+        if omitted > 0 {
+            warnings.push(
+                "tool_output_truncated",
+                format!(
+                    "the result of tool call `{call_id}` was cut to {limit} bytes, omitting \
+                     {omitted} (see --max-tool-output-bytes, or send \
+                     codex.truncate_tool_output: false)"
+                ),
+            );
+        }
+    }
+    Ok(Some(ResponseItem::FunctionCallOutput {
         call_id: call_id.to_string(),
         output: FunctionCallOutputPayload {
             content,
             success: Some(true),
-            content_items: None,
+            content_items,
         },
-    })
+    }))
 }
 
-fn convert_function_tools(tools: &[RequestTool]) -> Result<Option<Vec<ToolSpec>>, ApiError> {
+/// Cuts `text` longer than `limit` bytes down to its head and tail, half the limit each, around
+/// a marker naming how many bytes were left out. Returns that count, `0` for text left whole.
+fn truncate_middle(text: &mut String, limit: usize) -> usize {
+    if text.len() <= limit {
+        return 0;
+    }
+    let mut head_end = limit / 2;
+    while !text.is_char_boundary(head_end) {
+        head_end -= 1;
+    }
+    let mut tail_start = text.len() - (limit - limit / 2);
+    while !text.is_char_boundary(tail_start) {
+        tail_start += 1;
+    }
+    let omitted = tail_start - head_end;
+    *text = format!(
+        "{}\n[... {omitted} bytes omitted by codex-serve ...]\n{}",
+        &text[..head_end],
+        &text[tail_start..]
+    );
+    omitted
+}
+
+/// Cuts the text parts of a tool result to `limit` bytes between them, each keeping a share of
+/// the budget in proportion to its length. Returns the bytes left out.
+fn truncate_parts(items: &mut [FunctionCallOutputContentItem], limit: usize) -> usize {
+    let text_len = |item: &FunctionCallOutputContentItem| match item {
+        FunctionCallOutputContentItem::InputText { text } => text.len(),
+        _ => 0,
+    };
+    let total: usize = items.iter().map(text_len).sum();
+    if total <= limit {
+        return 0;
+    }
+    items
+        .iter_mut()
+        .map(|item| match item {
+            FunctionCallOutputContentItem::InputText { text } => {
+                let share = text.len() * limit / total;
+                truncate_middle(text, share)
+            }
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Non-function tools are skipped, and so are function tools with no `function` object or no
+/// name, with a `tool_skipped` warning. A function tool with an invalid or duplicate name is
+/// rejected, and so are more tools than `rules` allow: the upstream would otherwise fail on them
+/// only after a long wait, with an opaque error. Returns the specs with the names rewritten under
+/// `rules.sanitize_names`, which `warnings` records.
+pub fn convert_function_tools(
+    tools: &[RequestTool],
+    rules: ToolRules,
+    warnings: &Warnings,
+) -> Result<(Vec<ToolSpec>, ToolNames), ConversionError> {
+    if tools.len() > rules.max_tools {
+        return Err(ConversionError::new(format!(
+            "declares {} tools; at most {} are accepted (see `--max-tools`)",
+            tools.len(),
+            rules.max_tools
+        ))
+        .field("tools"));
+    }
     let mut specs = Vec::new();
-    for tool in tools {
+    let mut declared: HashMap<String, String> = HashMap::new();
+    let mut renamed = HashMap::new();
+    for (index, tool) in tools.iter().enumerate() {
         if !tool.kind.eq_ignore_ascii_case("function") {
             continue;
         }
         let Some(function) = tool.function.as_ref() else {
+            warnings.push(
+                "tool_skipped",
+                format!("tools[{index}] has no `function` object and was not sent upstream"),
+            );
             continue;
         };
         let Some(name) = function
@@ -372,9 +1078,13 @@ fn convert_function_tools(tools: &[RequestTool]) -> Result<Option<Vec<ToolSpec>>
             .map(|s| s.trim().to_string())
             .filter(|s| !s.is_empty())
         else {
+            warnings.push(
+                "tool_skipped",
+                format!("tools[{index}] has no function name and was not sent upstream"),
+            );
             continue;
         };
-        let description = function.description.as_ref().and_then(|d| {
+        let mut description = function.description.as_ref().and_then(|d| {
             let trimmed = d.trim();
             if trimmed.is_empty() {
                 None
@@ -382,8 +1092,53 @@ fn convert_function_tools(tools: &[RequestTool]) -> Result<Option<Vec<ToolSpec>>
                 Some(trimmed.to_string())
             }
         });
-        let mut parameters_value = normalize_tool_schema(function.parameters.clone());
+        let name_error = |reason: String| {
+            ConversionError::new(reason)
+                .field("name")
+                .field("function")
+                .at("tools", index)
+        };
+        let original = name;
+        let name = if tool_names::is_valid_name(&original) {
+            original.clone()
+        } else if rules.sanitize_names {
+            let sanitized = tool_names::sanitize_name(&original);
+            warnings.push(
+                "tool_name_sanitized",
+                format!("tool `{original}` is called `{sanitized}` upstream"),
+            );
+            let note = format!("Original tool name: `{original}`.");
+            description = Some(match description {
+                Some(description) => format!("{description}\n\n{note}"),
+                None => note,
+            });
+            renamed.insert(sanitized.clone(), original.clone());
+            sanitized
+        } else {
+            return Err(name_error(format!(
+                "`{original}` is not a valid tool name; names must match \
+                 ^[a-zA-Z0-9_-]{{1,{}}}$ (see `--sanitize-tool-names`)",
+                tool_names::MAX_NAME_LEN
+            )));
+        };
+        if let Some(earlier) = declared.insert(name.clone(), original.clone()) {
+            return Err(name_error(if earlier == original {
+                format!("duplicate tool name `{original}`")
+            } else {
+                format!("tools `{earlier}` and `{original}` are both called `{name}` upstream")
+            }));
+        }
+        let normalized = normalize_tool_schema(function.parameters.clone());
+        let mut parameters_value = normalized.clone();
         sanitize_json_schema(&mut parameters_value);
+        if parameters_value != normalized {
+            warnings.push(
+                "tool_schema_sanitized",
+                format!(
+                    "tool `{name}`: parameters schema was adjusted to the subset Codex accepts"
+                ),
+            );
+        }
         let parameters: JsonSchema = match serde_json::from_value(parameters_value.clone()) {
             Ok(schema) => schema,
             Err(source) => {
@@ -393,6 +1148,13 @@ fn convert_function_tools(tools: &[RequestTool]) -> Result<Option<Vec<ToolSpec>>
                     schema = %parameters_value,
                     "invalid tool schema; falling back to empty object"
                 );
+                warnings.push(
+                    "tool_schema_replaced",
+                    format!(
+                        "tool `{name}`: parameters schema is invalid ({source}); replaced with an \
+                         empty object schema"
+                    ),
+                );
                 JsonSchema::Object {
                     properties: BTreeMap::new(),
                     required: None,
@@ -408,11 +1170,7 @@ fn convert_function_tools(tools: &[RequestTool]) -> Result<Option<Vec<ToolSpec>>
         }));
     }
 
-    if specs.is_empty() {
-        Ok(None)
-    } else {
-        Ok(Some(specs))
-    }
+    Ok((specs, ToolNames::new(renamed)))
 }
 
 fn normalize_tool_schema(parameters: Option<Value>) -> Value {
@@ -431,8 +1189,10 @@ fn normalize_tool_schema(parameters: Option<Value>) -> Value {
     }
 }
 
-fn log_function_tools(specs: &[ToolSpec]) {
-    if !verbose_logging_enabled() || specs.is_empty() {
+/// Logs the converted tool specs under `context`'s request id and model; callers check that
+/// verbose logging is on.
+pub fn log_function_tools(context: &LogContext, specs: &[ToolSpec]) {
+    if specs.is_empty() {
         return;
     }
     let payload: Vec<Value> = specs
@@ -465,16 +1225,28 @@ fn log_function_tools(specs: &[ToolSpec]) {
         })
         .collect();
     match serde_json::to_string(&payload) {
-        Ok(serialized) => {
-            info!(event = "chat.tools", payload = %serialized, "registered function tools")
-        }
-        Err(err) => info!("chat.tools serialization failed: {err}"),
+        Ok(serialized) => info!(
+            event = "chat.tools",
+            request_id = %context.request_id,
+            model = %context.model,
+            payload = %serialized,
+            "registered function tools"
+        ),
+        Err(err) => info!(
+            request_id = %context.request_id,
+            model = %context.model,
+            "chat.tools serialization failed: {err}"
+        ),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        openai::render::describe_input, prompt::inject_developer_prompt,
+        serve_config::DeveloperPromptMode,
+    };
 
     fn user_message(value: Value) -> ChatCompletionRequest {
         ChatCompletionRequest {
@@ -485,8 +1257,19 @@ mod tests {
                 ..Default::default()
             }],
             stream: false,
-            tools: Vec::new(),
+            tools: LenientList::default(),
             parallel_tool_calls: None,
+            reasoning_effort: None,
+            reasoning: None,
+            temperature: None,
+            top_p: None,
+            verbosity: None,
+            web_search_options: None,
+            tool_choice: None,
+            prediction: None,
+            response_format: None,
+            codex: None,
+            stream_options: None,
         }
     }
 
@@ -536,6 +1319,321 @@ mod tests {
         assert!(matches!(result, Err(ApiError::BadRequest(_))));
     }
 
+    fn bad_request_message(request: ChatCompletionRequest) -> String {
+        match request.into_prompt() {
+            Err(ApiError::BadRequest(message)) => message,
+            other => panic!("expected a bad request, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn conversion_errors_name_the_offending_path() {
+        let mut request = user_message(json!("hi"));
+        request.messages.push(ChatMessage {
+            role: "user".to_string(),
+            content: json!([
+                {"type": "text", "text": "look"},
+                {"type": "image_url", "image_url": {"detail": "high"}}
+            ]),
+            ..Default::default()
+        });
+        assert_eq!(
+            bad_request_message(request),
+            "messages[1].content[1].image_url: image content requires a URL string or an object \
+             with `url`"
+        );
+
+        assert_eq!(
+            bad_request_message(user_message(json!([{"type": "audio"}]))),
+            "messages[0].content[0].type: unsupported content type `audio`"
+        );
+        assert_eq!(
+            bad_request_message(tool_result(json!([{"type": "image_url"}]))),
+            "messages[0].content[0].image_url: image content requires a URL string or an object \
+             with `url`"
+        );
+    }
+
+    #[test]
+    fn function_tools_without_a_name_are_skipped_with_a_warning() {
+        let mut request = user_message(json!("hi"));
+        request.tools = vec![
+            RequestTool {
+                kind: "web_search".to_string(),
+                function: None,
+            },
+            RequestTool {
+                kind: "function".to_string(),
+                function: None,
+            },
+            RequestTool {
+                kind: "function".to_string(),
+                function: Some(RequestToolFunction::default()),
+            },
+        ]
+        .into();
+        let payload = request.into_prompt().expect("malformed tools are skipped");
+        assert!(payload.prompt.tools.is_empty());
+        let warnings = payload.warnings.snapshot();
+        assert_eq!(warnings.len(), 2);
+        assert!(
+            warnings
+                .iter()
+                .all(|warning| warning.code == "tool_skipped")
+        );
+        assert!(warnings[1].message.starts_with("tools[2] "));
+    }
+
+    fn with_tools(names: &[&str]) -> ChatCompletionRequest {
+        let mut request = user_message(json!("hi"));
+        request.tools = names
+            .iter()
+            .map(|name| RequestTool {
+                kind: "function".to_string(),
+                function: Some(RequestToolFunction {
+                    name: Some(name.to_string()),
+                    ..Default::default()
+                }),
+            })
+            .collect();
+        request
+    }
+
+    fn tool_rules_error(request: ChatCompletionRequest, tools: ToolRules) -> String {
+        let options = ConversionOptions {
+            tools,
+            ..Default::default()
+        };
+        match request.into_prompt_for(options) {
+            Err(ApiError::BadRequest(message)) => message,
+            other => panic!("expected a bad request, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn tool_definitions_are_validated() {
+        let rules = ToolRules {
+            max_tools: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            tool_rules_error(with_tools(&["a", "b", "c"]), rules),
+            "tools: declares 3 tools; at most 2 are accepted (see `--max-tools`)"
+        );
+        assert_eq!(
+            bad_request_message(with_tools(&["get_weather", "weather.get"])),
+            "tools[1].function.name: `weather.get` is not a valid tool name; names must match \
+             ^[a-zA-Z0-9_-]{1,64}$ (see `--sanitize-tool-names`)"
+        );
+        assert_eq!(
+            bad_request_message(with_tools(&["search", "search"])),
+            "tools[1].function.name: duplicate tool name `search`"
+        );
+        let sanitizing = ToolRules {
+            sanitize_names: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            tool_rules_error(with_tools(&["weather.get", "weather_get"]), sanitizing),
+            "tools[1].function.name: tools `weather.get` and `weather_get` are both called \
+             `weather_get` upstream"
+        );
+    }
+
+    #[test]
+    fn sanitized_tool_names_map_both_ways() {
+        let mut request = with_tools(&["weather.get"]);
+        request.tools[0].function.as_mut().unwrap().description = Some("Forecast.".to_string());
+        request.messages.push(ChatMessage {
+            role: "assistant".to_string(),
+            tool_calls: Some(
+                vec![ChatToolCall {
+                    id: Some("call_1".to_string()),
+                    r#type: Some("function".to_string()),
+                    function: Some(ChatToolFunction {
+                        name: Some("weather.get".to_string()),
+                        arguments: Some("{}".to_string()),
+                    }),
+                }]
+                .into(),
+            ),
+            ..Default::default()
+        });
+        let options = ConversionOptions {
+            tools: ToolRules {
+                sanitize_names: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let payload = request
+            .into_prompt_for(options)
+            .expect("invalid names are sanitized");
+
+        match &payload.prompt.tools[..] {
+            [ToolSpec::Function(tool)] => {
+                assert_eq!(tool.name, "weather_get");
+                assert_eq!(
+                    tool.description,
+                    "Forecast.\n\nOriginal tool name: `weather.get`."
+                );
+            }
+            other => panic!("expected one function tool, got {other:?}"),
+        }
+        assert!(payload.prompt.input.iter().any(|item| matches!(
+            item,
+            ResponseItem::FunctionCall { name, .. } if name == "weather_get"
+        )));
+        assert_eq!(payload.tool_names.restore("weather_get"), "weather.get");
+        let warnings = payload.warnings.snapshot();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code, "tool_name_sanitized");
+    }
+
+    fn user_texts(payload: &PromptPayload) -> Vec<ContentItem> {
+        match &payload.prompt.input[..] {
+            [ResponseItem::Message { content, .. }] => content.clone(),
+            other => panic!("expected one message, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn accepts_langchain_content_shapes() {
+        let shapes = [
+            json!({"content": "hello"}),
+            json!([{"type": "input_text", "content": "hello"}]),
+            json!([{"type": "text", "content": "hello"}]),
+            json!([{"content": "hello"}]),
+            json!([{"text": "hello"}]),
+        ];
+        for shape in shapes {
+            let payload = user_message(shape.clone())
+                .into_prompt()
+                .unwrap_or_else(|err| panic!("{shape} should convert: {err:?}"));
+            assert_eq!(
+                user_texts(&payload),
+                vec![ContentItem::InputText {
+                    text: "hello".into()
+                }],
+                "{shape}"
+            );
+            assert_eq!(payload.first_user_message.as_deref(), Some("hello"));
+            // `--fail-on-warnings` still turns these shapes away.
+            assert!(!payload.warnings.snapshot().is_empty(), "{shape}");
+            assert!(payload.warnings.reject_any().is_err(), "{shape}");
+        }
+
+        let payload = user_message(json!({"text": "hello"}))
+            .into_prompt()
+            .expect("conversion should succeed");
+        assert!(payload.warnings.snapshot().is_empty());
+    }
+
+    #[test]
+    fn untyped_parts_need_to_be_plain_text() {
+        assert_eq!(
+            bad_request_message(user_message(json!([{"content": "hi", "name": "x"}]))),
+            "messages[0].content[0].type: is required"
+        );
+        assert_eq!(
+            bad_request_message(user_message(json!({"content": ["hi"]}))),
+            "messages[0].content.type: is required"
+        );
+    }
+
+    fn system_only(content: &str) -> ChatCompletionRequest {
+        let mut request = user_message(json!(content));
+        request.messages[0].role = "system".to_string();
+        request
+    }
+
+    #[test]
+    fn system_only_chat_is_a_developer_prompt() {
+        let payload = system_only("Introduce yourself.")
+            .into_prompt()
+            .expect("a system prompt alone is a prompt");
+        assert_eq!(
+            describe_input(&payload.prompt.input),
+            ["developer: Introduce yourself."]
+        );
+        assert_eq!(payload.first_user_message, None);
+    }
+
+    #[test]
+    fn instructions_only_requests_are_prompts() {
+        let mut request = system_only("Write a haiku.");
+        request.messages.push(ChatMessage {
+            role: "user".to_string(),
+            content: Value::Null,
+            ..Default::default()
+        });
+        let payload = request
+            .into_prompt_for(ConversionOptions::for_endpoint(
+                PromptEndpoint::Instructions,
+            ))
+            .expect("instructions alone are a prompt");
+        assert_eq!(
+            describe_input(&payload.prompt.input),
+            ["developer: Write a haiku."]
+        );
+    }
+
+    #[test]
+    fn requests_with_nothing_to_send_are_rejected() {
+        assert_eq!(
+            bad_request_message(user_message(Value::Null)),
+            "messages: must include at least one message with content"
+        );
+        let empty = || {
+            let mut request = user_message(Value::Null);
+            request.messages.clear();
+            request
+        };
+        assert_eq!(
+            bad_request_message(empty()),
+            "messages: must include at least one message"
+        );
+        match empty().into_prompt_for(ConversionOptions::for_endpoint(
+            PromptEndpoint::Instructions,
+        )) {
+            Err(ApiError::BadRequest(message)) => {
+                assert_eq!(message, "must include a prompt or system instructions");
+            }
+            other => panic!("expected a bad request, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn prediction_content_is_text_or_text_parts() {
+        let request = |prediction: Value| {
+            let mut request = user_message(json!("hi"));
+            request.prediction = Some(serde_json::from_value(prediction).unwrap());
+            request
+        };
+        let with_prediction = |prediction: Value| {
+            request(prediction)
+                .into_prompt()
+                .map(|payload| payload.prediction)
+        };
+        assert_eq!(
+            with_prediction(json!({"type": "content", "content": "let x = 1;"})).unwrap(),
+            Some("let x = 1;".to_string())
+        );
+        assert_eq!(
+            with_prediction(json!({"type": "content", "content": [
+                {"type": "text", "text": "let "}, {"type": "text", "text": "x"}
+            ]}))
+            .unwrap(),
+            Some("let x".to_string())
+        );
+        assert_eq!(
+            with_prediction(json!({"type": "content", "content": " "})).unwrap(),
+            None
+        );
+        let message = bad_request_message(request(json!({"type": "audio", "content": "x"})));
+        assert!(message.contains("prediction.type"), "{message}");
+    }
+
     #[test]
     fn system_messages_become_developer() {
         let payload = ChatCompletionRequest {
@@ -546,8 +1644,19 @@ mod tests {
                 ..Default::default()
             }],
             stream: false,
-            tools: Vec::new(),
+            tools: LenientList::default(),
             parallel_tool_calls: None,
+            reasoning_effort: None,
+            reasoning: None,
+            temperature: None,
+            top_p: None,
+            verbosity: None,
+            web_search_options: None,
+            tool_choice: None,
+            prediction: None,
+            response_format: None,
+            codex: None,
+            stream_options: None,
         };
         let prompt = payload.into_prompt().expect("conversion should succeed");
         match &prompt.prompt.input[0] {
@@ -566,8 +1675,19 @@ mod tests {
                 ..Default::default()
             }],
             stream: false,
-            tools: Vec::new(),
+            tools: LenientList::default(),
             parallel_tool_calls: None,
+            reasoning_effort: None,
+            reasoning: None,
+            temperature: None,
+            top_p: None,
+            verbosity: None,
+            web_search_options: None,
+            tool_choice: None,
+            prediction: None,
+            response_format: None,
+            codex: None,
+            stream_options: None,
         };
         let prompt = payload.into_prompt().expect("conversion should succeed");
         match &prompt.prompt.input[0] {
@@ -579,6 +1699,283 @@ mod tests {
         }
     }
 
+    fn tool_result(content: Value) -> ChatCompletionRequest {
+        ChatCompletionRequest {
+            model: "".to_string(),
+            messages: vec![ChatMessage {
+                role: "tool".to_string(),
+                content,
+                tool_call_id: Some("call_1".to_string()),
+                ..Default::default()
+            }],
+            stream: false,
+            tools: LenientList::default(),
+            parallel_tool_calls: None,
+            reasoning_effort: None,
+            reasoning: None,
+            temperature: None,
+            top_p: None,
+            verbosity: None,
+            web_search_options: None,
+            tool_choice: None,
+            prediction: None,
+            response_format: None,
+            codex: None,
+            stream_options: None,
+        }
+    }
+
+    fn tool_output(request: ChatCompletionRequest) -> FunctionCallOutputPayload {
+        let prompt = request.into_prompt().expect("conversion should succeed");
+        match prompt.prompt.input.as_slice() {
+            [ResponseItem::FunctionCallOutput { call_id, output }] => {
+                assert_eq!(call_id, "call_1");
+                output.clone()
+            }
+            other => panic!("expected one tool output, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn tool_results_keep_images() {
+        let output = tool_output(tool_result(json!([
+            {"type": "text", "text": "screenshot attached"},
+            {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBORw0KGgo="}}
+        ])));
+        assert_eq!(output.content, "screenshot attached");
+        assert_eq!(
+            output.content_items,
+            Some(vec![
+                FunctionCallOutputContentItem::InputText {
+                    text: "screenshot attached".to_string()
+                },
+                FunctionCallOutputContentItem::InputImage {
+                    image_url: "data:image/png;base64,iVBORw0KGgo=".to_string()
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn text_only_tool_results_stay_flat() {
+        let output = tool_output(tool_result(json!([
+            {"type": "text", "text": "line one"},
+            {"type": "text", "text": "line two"}
+        ])));
+        assert_eq!(output.content, "line one\nline two");
+        assert_eq!(output.content_items, None);
+
+        let result = tool_result(json!([{"type": "image_url"}])).into_prompt();
+        assert!(matches!(result, Err(ApiError::BadRequest(_))));
+    }
+
+    #[test]
+    fn long_text_is_cut_in_the_middle() {
+        let mut text = format!("{}{}", "a".repeat(60), "z".repeat(60));
+        assert_eq!(truncate_middle(&mut text, 20), 100);
+        assert_eq!(
+            text,
+            "aaaaaaaaaa\n[... 100 bytes omitted by codex-serve ...]\nzzzzzzzzzz"
+        );
+
+        // A cut inside a character moves to its boundary, dropping the character rather than
+        // splitting it.
+        let mut text = "é".repeat(20);
+        assert_eq!(truncate_middle(&mut text, 11), 30);
+        assert_eq!(text, "éé\n[... 30 bytes omitted by codex-serve ...]\nééé");
+
+        let mut short = "fits".to_string();
+        assert_eq!(truncate_middle(&mut short, 4), 0);
+        assert_eq!(short, "fits");
+    }
+
+    #[test]
+    fn oversized_tool_results_are_truncated_with_a_warning() {
+        let log = format!("start\n{}\nend", "x".repeat(200_000));
+        let options = ConversionOptions {
+            max_tool_output_bytes: Some(1_000),
+            ..Default::default()
+        };
+        let payload = tool_result(json!(log))
+            .into_prompt_for(options)
+            .expect("conversion should succeed");
+        let [ResponseItem::FunctionCallOutput { output, .. }] = payload.prompt.input.as_slice()
+        else {
+            panic!("expected one tool output");
+        };
+        assert!(output.content.starts_with("start\n"));
+        assert!(output.content.ends_with("\nend"));
+        assert!(
+            output
+                .content
+                .contains("[... 199010 bytes omitted by codex-serve ...]")
+        );
+        let warnings = payload.warnings.snapshot();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code, "tool_output_truncated");
+        assert!(warnings[0].message.contains("call_1"));
+
+        // Text parts beside an image are cut too.
+        let payload = tool_result(json!([
+            {"type": "text", "text": log},
+            {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBORw0KGgo="}}
+        ]))
+        .into_prompt_for(options)
+        .expect("conversion should succeed");
+        let [ResponseItem::FunctionCallOutput { output, .. }] = payload.prompt.input.as_slice()
+        else {
+            panic!("expected one tool output");
+        };
+        match output.content_items.as_deref() {
+            Some([FunctionCallOutputContentItem::InputText { text }, _]) => {
+                assert!(text.len() < 1_100, "{}", text.len());
+            }
+            other => panic!("expected a text part and an image, got {other:?}"),
+        }
+
+        // Parts that each fit still share one budget.
+        let part = json!({"type": "text", "text": "q".repeat(900)});
+        let payload = tool_result(json!([
+            part,
+            part,
+            part,
+            {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBORw0KGgo="}}
+        ]))
+        .into_prompt_for(options)
+        .expect("conversion should succeed");
+        let [ResponseItem::FunctionCallOutput { output, .. }] = payload.prompt.input.as_slice()
+        else {
+            panic!("expected one tool output");
+        };
+        let kept: usize = output
+            .content_items
+            .iter()
+            .flatten()
+            .map(|item| match item {
+                FunctionCallOutputContentItem::InputText { text } => text.matches('q').count(),
+                _ => 0,
+            })
+            .sum();
+        assert!(kept <= 1_000, "{kept}");
+        let warnings = payload.warnings.snapshot();
+        assert!(
+            warnings[0].message.contains("omitting 1701"),
+            "{warnings:?}"
+        );
+    }
+
+    #[test]
+    fn tool_output_truncation_can_be_turned_off() {
+        let log = "x".repeat(100_000);
+        let mut request = tool_result(json!(log));
+        request.codex = Some(CodexOptions {
+            truncate_tool_output: Some(false),
+            ..Default::default()
+        });
+        assert_eq!(tool_output(request).content, log);
+
+        let options = ConversionOptions {
+            max_tool_output_bytes: None,
+            ..Default::default()
+        };
+        let payload = tool_result(json!(log))
+            .into_prompt_for(options)
+            .expect("conversion should succeed");
+        assert!(payload.warnings.snapshot().is_empty());
+
+        // Under the default limit nothing changes.
+        let payload = tool_result(json!("x".repeat(DEFAULT_MAX_TOOL_OUTPUT_BYTES)))
+            .into_prompt()
+            .expect("conversion should succeed");
+        assert!(payload.warnings.snapshot().is_empty());
+    }
+
+    #[test]
+    fn think_blocks_are_cut_out_of_the_answer() {
+        assert_eq!(
+            strip_think_blocks("<think>add them</think>\n\nIt is 4.").as_deref(),
+            Some("It is 4.")
+        );
+        assert_eq!(
+            strip_think_blocks("First. <think>a</think> Second.<think>b</think>").as_deref(),
+            Some("First. Second.")
+        );
+        assert_eq!(strip_think_blocks("It is 4."), None);
+        // An unclosed tag is not reasoning we wrapped.
+        assert_eq!(strip_think_blocks("Write <think> in the template."), None);
+    }
+
+    fn replayed_turn(strip_replayed_reasoning: bool) -> PromptPayload {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "gpt-5",
+            "messages": [
+                {"role": "user", "content": "What is 2 + 2?"},
+                {
+                    "role": "assistant",
+                    "content": "<think>Two and two make four.</think>\n\nIt is 4.",
+                    "reasoning_content": "Two and two make four.",
+                    "reasoning": {"summary": [{"type": "text", "text": "Adding"}]}
+                },
+                {"role": "user", "content": "And 3 + 3?"}
+            ]
+        }))
+        .unwrap();
+        let options = ConversionOptions {
+            strip_replayed_reasoning,
+            ..Default::default()
+        };
+        request
+            .into_prompt_for(options)
+            .expect("conversion should succeed")
+    }
+
+    #[test]
+    fn replayed_reasoning_is_not_sent_upstream() {
+        let payload = replayed_turn(true);
+        assert_eq!(
+            describe_input(&payload.prompt.input),
+            [
+                "user: What is 2 + 2?",
+                "assistant: It is 4.",
+                "user: And 3 + 3?",
+            ]
+        );
+        let warnings = payload.warnings.snapshot();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code, "replayed_reasoning_stripped");
+
+        // Turned off, the think block goes upstream as written; the reasoning fields never do.
+        let payload = replayed_turn(false);
+        assert_eq!(
+            describe_input(&payload.prompt.input)[1],
+            "assistant: <think>Two and two make four.</think>\n\nIt is 4."
+        );
+        assert!(payload.warnings.snapshot().is_empty());
+    }
+
+    #[test]
+    fn hidden_reasoning_asks_for_no_summary() {
+        let request = || -> ChatCompletionRequest {
+            serde_json::from_value(json!({
+                "model": "gpt-5",
+                "reasoning": {"summary": "detailed"},
+                "messages": [{"role": "user", "content": "hi"}]
+            }))
+            .unwrap()
+        };
+        let payload = request().into_prompt().expect("conversion should succeed");
+        assert_eq!(payload.reasoning_summary, Some(ReasoningSummary::Detailed));
+
+        let options = ConversionOptions {
+            hide_reasoning: true,
+            ..Default::default()
+        };
+        let payload = request()
+            .into_prompt_for(options)
+            .expect("conversion should succeed");
+        assert_eq!(payload.reasoning_summary, Some(ReasoningSummary::None));
+    }
+
     #[test]
     fn convert_function_tools_handles_anyof_schemas() {
         let tools = vec![RequestTool {
@@ -600,9 +1997,8 @@ mod tests {
                 })),
             }),
         }];
-        let specs = convert_function_tools(&tools)
-            .expect("conversion should succeed")
-            .expect("tool definitions should exist");
+        let (specs, _) = convert_function_tools(&tools, ToolRules::default(), &Warnings::default())
+            .expect("conversion should succeed");
         assert_eq!(specs.len(), 1);
         match &specs[0] {
             ToolSpec::Function(tool) => {
@@ -633,11 +2029,472 @@ mod tests {
                 },
             ],
             stream: false,
-            tools: Vec::new(),
+            tools: LenientList::default(),
             parallel_tool_calls: None,
+            reasoning_effort: None,
+            reasoning: None,
+            temperature: None,
+            top_p: None,
+            verbosity: None,
+            web_search_options: None,
+            tool_choice: None,
+            prediction: None,
+            response_format: None,
+            codex: None,
+            stream_options: None,
         };
 
         let payload = request.into_prompt().expect("payload");
         assert_eq!(payload.system_prompt.as_deref(), Some("stay on topic"));
     }
+
+    fn converted(messages: Value, keep_empty_messages: bool) -> Vec<String> {
+        let request: ChatCompletionRequest =
+            serde_json::from_value(json!({"model": "gpt-5", "messages": messages})).unwrap();
+        let options = ConversionOptions {
+            keep_empty_messages,
+            ..Default::default()
+        };
+        let payload = request
+            .into_prompt_for(options)
+            .expect("conversion should succeed");
+        describe_input(&payload.prompt.input)
+    }
+
+    #[test]
+    fn blank_messages_are_dropped_unless_they_carry_something() {
+        let messages = json!([
+            {"role": "system", "content": "  "},
+            {"role": "user", "content": ""},
+            {"role": "user", "content": [{"type": "text", "text": "\n"}]},
+            {"role": "user", "content": "weather?"},
+            {"role": "assistant", "content": " ", "tool_calls": [
+                {"id": "call_1", "function": {"name": "get_weather", "arguments": "{}"}}
+            ]},
+            {"role": "tool", "tool_call_id": "call_1", "content": "  "},
+            {"role": "tool", "tool_call_id": "call_2"},
+            {"role": "assistant", "content": "\t"},
+            {"role": "user", "content": [
+                {"type": "text", "text": " "},
+                {"type": "image_url", "image_url": {"url": "https://example.com/a.png"}}
+            ]}
+        ]);
+        assert_eq!(
+            converted(messages.clone(), false),
+            [
+                "user: weather?",
+                "function_call call_1 get_weather({})",
+                "function_call_output call_1: ",
+                "function_call_output call_2: ",
+                "user: ",
+            ]
+        );
+        assert_eq!(
+            converted(messages, true),
+            [
+                "developer:   ",
+                "user: ",
+                "user: \n",
+                "user: weather?",
+                "function_call call_1 get_weather({})",
+                "assistant:  ",
+                "function_call_output call_1:   ",
+                "assistant: \t",
+                "user:  ",
+            ]
+        );
+    }
+
+    #[test]
+    fn blank_tool_outputs_are_empty_successes() {
+        for content in [
+            json!(null),
+            json!(""),
+            json!(" \n"),
+            json!([{"type": "text", "text": ""}]),
+        ] {
+            let output = tool_output(tool_result(content.clone()));
+            assert_eq!(output.content, "", "{content}");
+            assert_eq!(output.success, Some(true), "{content}");
+            assert_eq!(output.content_items, None, "{content}");
+        }
+    }
+
+    #[test]
+    fn only_blank_messages_leave_no_prompt() {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "gpt-5",
+            "messages": [{"role": "user", "content": " "}, {"role": "assistant", "content": ""}]
+        }))
+        .unwrap();
+        assert_eq!(
+            bad_request_message(request),
+            "messages: must include at least one message with content"
+        );
+    }
+
+    fn with_role(role: &str, strict_roles: bool) -> Result<PromptPayload, ApiError> {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "gpt-5",
+            "messages": [
+                {"role": "user", "content": "hi"},
+                {"role": role, "content": "hello"}
+            ]
+        }))
+        .unwrap();
+        let options = ConversionOptions {
+            strict_roles,
+            ..Default::default()
+        };
+        request.into_prompt_for(options)
+    }
+
+    #[test]
+    fn roles_match_case_insensitively() {
+        for (role, expected) in [
+            ("Assistant", "assistant: hello"),
+            ("USER", "user: hello"),
+            (" Developer ", "developer: hello"),
+            ("SYSTEM", "developer: hello"),
+            ("", "user: hello"),
+        ] {
+            let payload = with_role(role, true).expect("known role");
+            assert_eq!(describe_input(&payload.prompt.input)[1], expected, "{role}");
+            assert!(payload.warnings.snapshot().is_empty(), "{role}");
+        }
+    }
+
+    #[test]
+    fn role_aliases_map_to_openai_roles() {
+        for (role, expected) in [
+            ("human", "user: hello"),
+            ("Human", "user: hello"),
+            ("ai", "assistant: hello"),
+            ("model", "assistant: hello"),
+        ] {
+            let payload = with_role(role, false).expect("alias");
+            assert_eq!(describe_input(&payload.prompt.input)[1], expected, "{role}");
+            let warnings = payload.warnings.snapshot();
+            assert_eq!(warnings[0].code, "role_renamed", "{role}");
+        }
+    }
+
+    fn with_shapes(tools: Value, tool_calls: Value) -> ChatCompletionRequest {
+        serde_json::from_value(json!({
+            "model": "gpt-5",
+            "messages": [
+                {"role": "user", "content": "weather?"},
+                {"role": "assistant", "tool_calls": tool_calls},
+                {"role": "tool", "tool_call_id": "call_1", "content": "18°C"}
+            ],
+            "tools": tools
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn null_and_single_object_lists_are_read_with_a_warning() {
+        let tool = json!({"type": "function", "function": {"name": "get_weather"}});
+        let call = json!({"id": "call_1", "function": {"name": "get_weather", "arguments": "{}"}});
+
+        let payload = with_shapes(json!([tool]), json!([call]))
+            .into_prompt()
+            .unwrap();
+        assert!(payload.warnings.snapshot().is_empty());
+
+        let payload = with_shapes(tool.clone(), call.clone())
+            .into_prompt()
+            .unwrap();
+        assert_eq!(payload.prompt.tools.len(), 1);
+        assert_eq!(
+            describe_input(&payload.prompt.input)[1],
+            "function_call call_1 get_weather({})"
+        );
+        let messages: Vec<_> = payload
+            .warnings
+            .snapshot()
+            .into_iter()
+            .map(|warning| (warning.code, warning.message))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                (
+                    "list_shape",
+                    "`messages[1].tool_calls` was a single object; read as a one-element array"
+                        .to_string()
+                ),
+                (
+                    "list_shape",
+                    "`tools` was a single object; read as a one-element array".to_string()
+                ),
+            ]
+        );
+
+        let payload = with_shapes(Value::Null, call).into_prompt().unwrap();
+        assert!(payload.prompt.tools.is_empty());
+        assert!(
+            payload
+                .warnings
+                .snapshot()
+                .iter()
+                .any(|warning| warning.message == "`tools` was null; read as an empty array")
+        );
+    }
+
+    #[test]
+    fn strict_params_rejects_non_standard_list_shapes() {
+        let tool = json!({"type": "function", "function": {"name": "get_weather"}});
+        let call = json!({"id": "call_1", "function": {"name": "get_weather", "arguments": "{}"}});
+        let strict = ConversionOptions {
+            strict_roles: true,
+            ..Default::default()
+        };
+        for (request, expected) in [
+            (
+                with_shapes(Value::Null, json!([call])),
+                "tools: must be an array, not null",
+            ),
+            (
+                with_shapes(tool.clone(), json!([call])),
+                "tools: must be an array, not a single object",
+            ),
+            (
+                with_shapes(json!([tool]), call.clone()),
+                "messages[1].tool_calls: must be an array, not a single object",
+            ),
+        ] {
+            match request.into_prompt_for(strict) {
+                Err(ApiError::BadRequest(message)) => {
+                    assert!(message.starts_with(expected), "{message}")
+                }
+                other => panic!("expected a bad request, got {other:?}"),
+            }
+        }
+
+        // `tool_calls: null` is common and stays what it always was: no calls.
+        let payload = with_shapes(json!([tool]), Value::Null).into_prompt_for(strict);
+        assert!(payload.is_ok(), "{payload:?}");
+    }
+
+    #[test]
+    fn empty_tools_objects_are_no_tools() {
+        let call = json!({"id": "call_1", "function": {"name": "get_weather", "arguments": "{}"}});
+        for options in [
+            ConversionOptions::default(),
+            ConversionOptions {
+                strict_roles: true,
+                ..Default::default()
+            },
+        ] {
+            let payload = with_shapes(json!({}), json!([call]))
+                .into_prompt_for(options)
+                .unwrap();
+            assert!(payload.prompt.tools.is_empty());
+            assert!(payload.warnings.snapshot().is_empty());
+        }
+    }
+
+    #[test]
+    fn a_json_response_format_keeps_the_first_sample_that_parses() {
+        let select = |response_format: Value, codex: Value| {
+            let request: ChatCompletionRequest = serde_json::from_value(json!({
+                "model": "gpt-5",
+                "messages": [{"role": "user", "content": "extract the city"}],
+                "response_format": response_format,
+                "codex": codex
+            }))
+            .unwrap();
+            request
+                .into_prompt()
+                .unwrap()
+                .sampling
+                .map(|sampling| sampling.select)
+        };
+        let samples = json!({"samples": 3});
+        assert_eq!(
+            select(json!({"type": "json_object"}), samples.clone()),
+            Some(Selection::FirstValidJson)
+        );
+        assert_eq!(
+            select(
+                json!({"type": "json_schema", "json_schema": {}}),
+                samples.clone()
+            ),
+            Some(Selection::FirstValidJson)
+        );
+        assert_eq!(
+            select(json!({"type": "text"}), samples),
+            Some(Selection::Majority)
+        );
+        assert_eq!(
+            select(
+                json!({"type": "json_object"}),
+                json!({"samples": 3, "select": "longest"})
+            ),
+            Some(Selection::Longest)
+        );
+    }
+
+    #[test]
+    fn lists_of_other_types_fail_to_parse() {
+        let err = serde_json::from_value::<ChatCompletionRequest>(json!({
+            "model": "gpt-5",
+            "messages": [{"role": "user", "content": "hi"}],
+            "tools": "get_weather"
+        }))
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("expected an array, found a string"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn function_messages_answer_the_latest_call_to_their_function() {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "gpt-5",
+            "messages": [
+                {"role": "user", "content": "weather?"},
+                {"role": "assistant", "tool_calls": [
+                    {"id": "call_7", "function": {"name": "get_weather", "arguments": "{}"}}
+                ]},
+                {"role": "function", "name": "get_weather", "content": "18°C"}
+            ]
+        }))
+        .unwrap();
+        let payload = request.into_prompt().expect("function role");
+        assert_eq!(
+            describe_input(&payload.prompt.input),
+            [
+                "user: weather?",
+                "function_call call_7 get_weather({})",
+                "function_call_output call_7: 18°C",
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_roles_and_strict_aliases_are_rejected() {
+        let message = |result: Result<PromptPayload, ApiError>| match result {
+            Err(ApiError::BadRequest(message)) => message,
+            other => panic!("expected a bad request, got {other:?}"),
+        };
+        assert_eq!(
+            message(with_role("narrator", false)),
+            "messages[1].role: unsupported role `narrator`; expected system, developer, user, \
+             assistant or tool"
+        );
+        assert_eq!(
+            message(with_role("Human", true)),
+            "messages[1].role: unsupported role `Human`; use `user` (role aliases are rejected \
+             under --strict-params)"
+        );
+        assert!(message(with_role("function", true)).contains("use `tool`"));
+    }
+
+    #[test]
+    fn bare_base64_image_urls_are_wrapped_or_rejected_in_place() {
+        let request = |url: &str| -> ChatCompletionRequest {
+            serde_json::from_value(json!({
+                "model": "gpt-5",
+                "messages": [
+                    {"role": "system", "content": "describe images"},
+                    {"role": "user", "content": [
+                        {"type": "text", "text": "what is this?"},
+                        {"type": "image_url", "image_url": {"url": url}}
+                    ]}
+                ]
+            }))
+            .unwrap()
+        };
+        let payload = request("iVBORw0KGgoAAAANSUhEUg==")
+            .into_prompt()
+            .expect("a base64 PNG is accepted");
+        assert!(payload.prompt.input.iter().any(|item| matches!(
+            item,
+            ResponseItem::Message { content, .. } if content.iter().any(|part| matches!(
+                part,
+                ContentItem::InputImage { image_url }
+                    if image_url == "data:image/png;base64,iVBORw0KGgoAAAANSUhEUg=="
+            ))
+        )));
+
+        match request("aGVsbG8gd29ybGQ=").into_prompt() {
+            Err(ApiError::BadRequest(message)) => assert_eq!(
+                message,
+                "messages[1].content[1].image_url: is base64 but not a PNG, JPEG, GIF or WebP \
+                 image"
+            ),
+            other => panic!("expected a bad request, got {other:?}"),
+        }
+    }
+
+    /// A developer prompt an earlier turn was sent with: under Override, one wrapping the
+    /// client's system prompt. Disabled sends none, so a replay there comes from another server.
+    fn previous_shim(mode: DeveloperPromptMode) -> String {
+        let (mode, system) = match mode {
+            DeveloperPromptMode::Override => (mode, Some("be terse")),
+            _ => (DeveloperPromptMode::Default, None),
+        };
+        let mut prompt = Prompt::default();
+        inject_developer_prompt(&mut prompt, false, system, mode);
+        match &prompt.input[..] {
+            [ResponseItem::Message { content, .. }] => match &content[..] {
+                [ContentItem::InputText { text }] => text.clone(),
+                other => panic!("unexpected content: {other:?}"),
+            },
+            other => panic!("expected one developer message, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn replayed_developer_prompts_are_not_the_system_prompt() {
+        for mode in [
+            DeveloperPromptMode::Default,
+            DeveloperPromptMode::Override,
+            DeveloperPromptMode::Disabled,
+        ] {
+            for own_system in [None, Some("be terse")] {
+                let mut messages = vec![json!({"role": "system", "content": previous_shim(mode)})];
+                messages.extend(own_system.map(|text| json!({"role": "system", "content": text})));
+                messages.push(json!({"role": "user", "content": "hi"}));
+                let request: ChatCompletionRequest =
+                    serde_json::from_value(json!({"model": "gpt-5", "messages": messages}))
+                        .unwrap();
+                let mut payload = request.into_prompt().expect("payload");
+                assert_eq!(payload.system_prompt.as_deref(), own_system, "{mode:?}");
+
+                inject_developer_prompt(
+                    &mut payload.prompt,
+                    false,
+                    payload.system_prompt.as_deref(),
+                    mode,
+                );
+                let shims: Vec<&String> = payload
+                    .prompt
+                    .input
+                    .iter()
+                    .filter_map(|item| match item {
+                        ResponseItem::Message { content, .. } => Some(content),
+                        _ => None,
+                    })
+                    .flatten()
+                    .filter_map(|item| match item {
+                        ContentItem::InputText { text } => Some(text),
+                        _ => None,
+                    })
+                    .filter(|text| text.contains(CODEX_SERVE_PROMPT_MARKER))
+                    .collect();
+                // The replayed prompt stands; no second one is added around it.
+                assert_eq!(shims.len(), 1, "{mode:?}: {shims:?}");
+                assert_eq!(
+                    shims[0].matches(CODEX_SERVE_PROMPT_MARKER).count(),
+                    1,
+                    "{mode:?}"
+                );
+            }
+        }
+    }
 }
@@ -0,0 +1,137 @@
+//! The error every front-end's request conversion reports. It records where in the client's
+//! request the bad value sits, so `/v1/chat/completions` and the Ollama routes all answer with
+//! the same shape, e.g. `messages[4].content[1].image_url: ...`.
+
+use std::fmt;
+
+use crate::error::ApiError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConversionError {
+    path: Vec<Segment>,
+    reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Field(&'static str),
+    Index(usize),
+}
+
+impl ConversionError {
+    /// An error at the value currently being converted; callers add the path on the way out.
+    pub fn new(reason: impl Into<String>) -> Self {
+        Self {
+            path: Vec::new(),
+            reason: reason.into(),
+        }
+    }
+
+    /// Places the error under the field `name` of the enclosing object.
+    pub fn field(mut self, name: &'static str) -> Self {
+        self.path.insert(0, Segment::Field(name));
+        self
+    }
+
+    /// Places the error under item `index` of the enclosing `list`, e.g. `content[1]`.
+    pub fn at(mut self, list: &'static str, index: usize) -> Self {
+        self.path
+            .splice(0..0, [Segment::Field(list), Segment::Index(index)]);
+        self
+    }
+
+    /// Shorthand for the request's message list, which every chat front-end has.
+    pub fn in_message(self, index: usize) -> Self {
+        self.at("messages", index)
+    }
+
+    pub fn message_index(&self) -> Option<usize> {
+        self.index_after("messages")
+    }
+
+    pub fn part_index(&self) -> Option<usize> {
+        self.index_after("content")
+            .or_else(|| self.index_after("images"))
+    }
+
+    /// The innermost field name, if the error points at one.
+    pub fn field_name(&self) -> Option<&'static str> {
+        match self.path.last()? {
+            Segment::Field(name) => Some(name),
+            Segment::Index(_) => None,
+        }
+    }
+
+    /// The location rendered the way clients write it: `messages[4].content[1].image_url`.
+    pub fn path(&self) -> String {
+        let mut path = String::new();
+        for segment in &self.path {
+            match segment {
+                Segment::Field(name) => {
+                    if !path.is_empty() {
+                        path.push('.');
+                    }
+                    path.push_str(name);
+                }
+                Segment::Index(index) => path.push_str(&format!("[{index}]")),
+            }
+        }
+        path
+    }
+
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    fn index_after(&self, list: &str) -> Option<usize> {
+        self.path.windows(2).find_map(|pair| match pair {
+            [Segment::Field(name), Segment::Index(index)] if *name == list => Some(*index),
+            _ => None,
+        })
+    }
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            f.write_str(&self.reason)
+        } else {
+            write!(f, "{}: {}", self.path(), self.reason)
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+impl From<ConversionError> for ApiError {
+    fn from(err: ConversionError) -> Self {
+        ApiError::bad_request(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_nested_paths() {
+        let err = ConversionError::new("image content requires `image_url`")
+            .at("content", 1)
+            .in_message(4);
+        assert_eq!(err.path(), "messages[4].content[1]");
+        assert_eq!(err.message_index(), Some(4));
+        assert_eq!(err.part_index(), Some(1));
+        assert_eq!(err.field_name(), None);
+
+        let err = ConversionError::new("is required")
+            .field("image_url")
+            .at("content", 0)
+            .in_message(2);
+        assert_eq!(
+            err.to_string(),
+            "messages[2].content[0].image_url: is required"
+        );
+        assert_eq!(err.field_name(), Some("image_url"));
+        assert_eq!(ConversionError::new("bare").to_string(), "bare");
+    }
+}
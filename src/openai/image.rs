@@ -0,0 +1,134 @@
+//! Image URLs of `image_url` content parts. Several Ollama-to-OpenAI bridges send the image as
+//! bare base64 instead of a `data:` URL; Codex would reject that long after the request was
+//! accepted, so it is wrapped here with the media type its bytes show, or refused with a `400`.
+
+use super::convert::ConversionError;
+
+/// Base64 characters decoded to sniff the image type: 12 bytes, enough for every signature.
+const SNIFF_CHARS: usize = 16;
+
+/// `url` as Codex accepts it. URLs with a scheme, `data:` ones included, are left as they are.
+pub(crate) fn image_url(url: &str) -> Result<String, ConversionError> {
+    let trimmed = url.trim();
+    if trimmed.starts_with("data:") || trimmed.contains("://") {
+        return Ok(url.to_string());
+    }
+    let data: String = trimmed
+        .chars()
+        .filter(|ch| !ch.is_ascii_whitespace())
+        .collect();
+    if !is_base64(&data) {
+        return Err(ConversionError::new(
+            "must be an http(s) URL, a data URL or base64-encoded image data",
+        ));
+    }
+    let head = decode(&data[..data.len().min(SNIFF_CHARS)]);
+    let Some(media_type) = sniff(&head) else {
+        return Err(ConversionError::new(
+            "is base64 but not a PNG, JPEG, GIF or WebP image",
+        ));
+    };
+    Ok(format!("data:{media_type};base64,{data}"))
+}
+
+fn is_base64(data: &str) -> bool {
+    let body = data.trim_end_matches('=');
+    !body.is_empty()
+        && data.len() - body.len() <= 2
+        && body.len() % 4 != 1
+        && body.bytes().all(|byte| sextet(byte).is_some())
+}
+
+fn sextet(byte: u8) -> Option<u8> {
+    match byte {
+        b'A'..=b'Z' => Some(byte - b'A'),
+        b'a'..=b'z' => Some(byte - b'a' + 26),
+        b'0'..=b'9' => Some(byte - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+/// Decodes `data`, which [`is_base64`] accepted, stopping at the padding.
+fn decode(data: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(data.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for value in data.bytes().map_while(sextet) {
+        buffer = (buffer << 6) | u32::from(value);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    bytes
+}
+
+fn sniff(head: &[u8]) -> Option<&'static str> {
+    if head.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if head.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some("image/jpeg")
+    } else if head.starts_with(b"GIF87a") || head.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if head.starts_with(b"RIFF") && head.get(8..12) == Some(&b"WEBP"[..]) {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The first 16 bytes of a PNG file, in base64.
+    const PNG: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAAB";
+
+    #[test]
+    fn bare_base64_images_become_data_urls() {
+        assert_eq!(
+            image_url(PNG).unwrap(),
+            format!("data:image/png;base64,{PNG}")
+        );
+        // Line-wrapped base64 is joined up.
+        assert_eq!(
+            image_url(&format!(" {}\n{} ", &PNG[..8], &PNG[8..])).unwrap(),
+            format!("data:image/png;base64,{PNG}")
+        );
+        assert_eq!(
+            image_url("/9j/4AAQSkZJRg==").unwrap(),
+            "data:image/jpeg;base64,/9j/4AAQSkZJRg=="
+        );
+        assert_eq!(
+            image_url("R0lGODlhAQABAAAAACw=").unwrap(),
+            "data:image/gif;base64,R0lGODlhAQABAAAAACw="
+        );
+    }
+
+    #[test]
+    fn base64_that_is_not_an_image_is_rejected() {
+        // "hello world", then strings that are not base64 at all.
+        for garbage in ["aGVsbG8gd29ybGQ=", "not an image", "iVBORw0K$$", "=", "A"] {
+            assert!(image_url(garbage).is_err(), "{garbage}");
+        }
+        assert_eq!(
+            image_url("aGVsbG8gd29ybGQ=").unwrap_err().to_string(),
+            "is base64 but not a PNG, JPEG, GIF or WebP image"
+        );
+    }
+
+    #[test]
+    fn urls_are_left_alone() {
+        for url in [
+            "https://example.com/cat.png",
+            "http://localhost:8080/a.jpg",
+            "data:image/png;base64,iVBORw0KGgo=",
+        ] {
+            assert_eq!(image_url(url).unwrap(), url);
+        }
+    }
+}
@@ -0,0 +1,236 @@
+//! List fields that some clients send in a non-standard shape: `"tools": null` for "no tools",
+//! or a single tool call object where OpenAI takes an array. Both are read as the list they
+//! stand for, with a `list_shape` warning, or rejected under `--strict-params`. An empty object
+//! (`"tools": {}`) is an empty list and passes without comment.
+
+use std::ops::{Deref, DerefMut};
+
+use serde::{Deserialize, Deserializer, Serialize, de::DeserializeOwned, de::Error as _};
+use serde_json::Value;
+
+use super::{convert::ConversionError, warnings::Warnings};
+
+/// How a [`LenientList`] was sent.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ListShape {
+    #[default]
+    Array,
+    Null,
+    Object,
+}
+
+/// A list that also accepts `null` (no items) and a bare object (one item), remembering which
+/// shape it was sent in. Serializes as a plain array.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct LenientList<T> {
+    items: Vec<T>,
+    #[serde(skip)]
+    shape: ListShape,
+}
+
+impl<T> LenientList<T> {
+    pub fn shape(&self) -> ListShape {
+        self.shape
+    }
+
+    pub fn into_vec(self) -> Vec<T> {
+        self.items
+    }
+
+    /// Warns about a non-standard shape, or rejects it when `strict`. `name` is the field as
+    /// the client wrote it.
+    pub fn check_shape(
+        &self,
+        name: &str,
+        strict: bool,
+        warnings: &Warnings,
+    ) -> Result<(), ConversionError> {
+        let (sent, read_as) = match self.shape {
+            ListShape::Array => return Ok(()),
+            ListShape::Null => ("null", "an empty array"),
+            ListShape::Object => ("a single object", "a one-element array"),
+        };
+        if strict {
+            return Err(ConversionError::new(format!(
+                "must be an array, not {sent} (non-standard shapes are rejected under \
+                 --strict-params)"
+            )));
+        }
+        warnings.push(
+            "list_shape",
+            format!("`{name}` was {sent}; read as {read_as}"),
+        );
+        Ok(())
+    }
+}
+
+impl<T> From<Vec<T>> for LenientList<T> {
+    fn from(items: Vec<T>) -> Self {
+        Self {
+            items,
+            shape: ListShape::Array,
+        }
+    }
+}
+
+impl<T> FromIterator<T> for LenientList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Vec::from_iter(iter).into()
+    }
+}
+
+impl<T> Deref for LenientList<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.items
+    }
+}
+
+impl<T> DerefMut for LenientList<T> {
+    fn deref_mut(&mut self) -> &mut Vec<T> {
+        &mut self.items
+    }
+}
+
+impl<'a, T> IntoIterator for &'a LenientList<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.iter()
+    }
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for LenientList<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (items, shape) = match Value::deserialize(deserializer)? {
+            Value::Null => (Vec::new(), ListShape::Null),
+            Value::Object(map) if map.is_empty() => (Vec::new(), ListShape::Array),
+            object @ Value::Object(_) => (vec![item(object)?], ListShape::Object),
+            Value::Array(values) => (
+                values.into_iter().map(item).collect::<Result<_, _>>()?,
+                ListShape::Array,
+            ),
+            other => {
+                return Err(D::Error::custom(format!(
+                    "expected an array, found {}",
+                    kind(&other)
+                )));
+            }
+        };
+        Ok(Self { items, shape })
+    }
+}
+
+fn item<T: DeserializeOwned, E: serde::de::Error>(value: Value) -> Result<T, E> {
+    serde_json::from_value(value).map_err(E::custom)
+}
+
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Holder {
+        #[serde(default)]
+        list: LenientList<Value>,
+    }
+
+    fn read(value: Value) -> Result<LenientList<Value>, serde_json::Error> {
+        serde_json::from_value::<Holder>(value).map(|holder| holder.list)
+    }
+
+    #[test]
+    fn arrays_null_and_single_objects_are_accepted() {
+        let array = read(json!({"list": [{"a": 1}, {"b": 2}]})).unwrap();
+        assert_eq!((array.len(), array.shape()), (2, ListShape::Array));
+
+        let missing = read(json!({})).unwrap();
+        assert_eq!((missing.len(), missing.shape()), (0, ListShape::Array));
+
+        let null = read(json!({"list": null})).unwrap();
+        assert_eq!((null.len(), null.shape()), (0, ListShape::Null));
+
+        let empty = read(json!({"list": {}})).unwrap();
+        assert_eq!((empty.len(), empty.shape()), (0, ListShape::Array));
+
+        let object = read(json!({"list": {"a": 1}})).unwrap();
+        assert_eq!(object.shape(), ListShape::Object);
+        assert_eq!(object.into_vec(), vec![json!({"a": 1})]);
+    }
+
+    #[test]
+    fn other_shapes_are_rejected() {
+        for (value, found) in [
+            (json!("tool"), "a string"),
+            (json!(3), "a number"),
+            (json!(true), "a boolean"),
+        ] {
+            let err = read(json!({ "list": value })).unwrap_err().to_string();
+            assert!(
+                err.contains(&format!("expected an array, found {found}")),
+                "{err}"
+            );
+        }
+    }
+
+    #[test]
+    fn non_standard_shapes_warn_or_are_rejected_when_strict() {
+        let warnings = Warnings::default();
+        let array = read(json!({"list": []})).unwrap();
+        array.check_shape("tools", true, &warnings).unwrap();
+        assert!(warnings.snapshot().is_empty());
+
+        let null = read(json!({"list": null})).unwrap();
+        null.check_shape("tools", false, &warnings).unwrap();
+        let object = read(json!({"list": {"a": 1}})).unwrap();
+        object.check_shape("tool_calls", false, &warnings).unwrap();
+        let messages: Vec<_> = warnings
+            .snapshot()
+            .into_iter()
+            .map(|warning| (warning.code, warning.message))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (
+                    "list_shape",
+                    "`tools` was null; read as an empty array".to_string()
+                ),
+                (
+                    "list_shape",
+                    "`tool_calls` was a single object; read as a one-element array".to_string()
+                ),
+            ]
+        );
+
+        assert_eq!(
+            null.check_shape("tools", true, &warnings)
+                .unwrap_err()
+                .to_string(),
+            "must be an array, not null (non-standard shapes are rejected under --strict-params)"
+        );
+        assert!(
+            object
+                .check_shape("tool_calls", true, &warnings)
+                .unwrap_err()
+                .to_string()
+                .contains("not a single object")
+        );
+    }
+}
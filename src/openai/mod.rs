@@ -1,4 +1,10 @@
 pub mod chat;
+pub mod convert;
+mod image;
+pub mod lenient;
+pub mod render;
 mod schema;
+pub mod tool_names;
+pub mod warnings;
 
-pub(crate) use schema::sanitize_json_schema;
+pub use schema::sanitize_json_schema;
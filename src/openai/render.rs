@@ -0,0 +1,225 @@
+//! The way back from a converted prompt to Chat Completions: OpenAI-shaped messages for Codex
+//! [`ResponseItem`]s, for debugging conversions and for replaying captured exchanges.
+
+use codex_core::{ContentItem, ResponseItem, ToolSpec, compact::content_items_to_text};
+use codex_protocol::models::{FunctionCallOutputContentItem, FunctionCallOutputPayload};
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use super::{
+    chat::{
+        ChatCompletionRequest, ChatMessage, ChatToolCall, ChatToolFunction, CodexOptions,
+        Prediction, PromptPayload, ReasoningOptions, RequestTool, RequestToolFunction,
+        StreamOptions, WebSearchOptions,
+    },
+    lenient::LenientList,
+    tool_names::ToolNames,
+};
+
+/// Renders `items` as the messages that convert back into them. Function calls join the
+/// assistant message that follows them, as `tool_calls`; `developer` messages come back as
+/// `system`. Items Chat Completions has no message for (reasoning, web searches, shell calls)
+/// are left out.
+pub fn messages_from_items(items: &[ResponseItem]) -> Vec<ChatMessage> {
+    render_messages(items, &ToolNames::default())
+}
+
+/// Rebuilds a request that converts into `payload` again: its messages, its function tools
+/// under the names the client declared, and the options it carried.
+pub fn request_from_payload(payload: &PromptPayload) -> ChatCompletionRequest {
+    let names = &payload.tool_names;
+    let tools = payload
+        .prompt
+        .tools
+        .iter()
+        .filter_map(|spec| match spec {
+            ToolSpec::Function(tool) => Some(RequestTool {
+                kind: "function".to_string(),
+                function: Some(RequestToolFunction {
+                    name: Some(names.restore(&tool.name).to_string()),
+                    description: (!tool.description.is_empty()).then(|| tool.description.clone()),
+                    strict: Some(tool.strict),
+                    parameters: serde_json::to_value(&tool.parameters).ok(),
+                }),
+            }),
+            _ => None,
+        })
+        .collect();
+    let reasoning = payload
+        .reasoning_summary
+        .is_some()
+        .then(|| ReasoningOptions {
+            effort: None,
+            summary: option_name(payload.reasoning_summary.as_ref()),
+        });
+    ChatCompletionRequest {
+        model: payload.model.clone(),
+        messages: render_messages(&payload.prompt.input, names),
+        tools,
+        parallel_tool_calls: Some(payload.prompt.parallel_tool_calls),
+        reasoning_effort: option_name(payload.reasoning_effort.as_ref()),
+        reasoning,
+        temperature: payload.temperature,
+        top_p: payload.top_p,
+        verbosity: option_name(payload.verbosity.as_ref()),
+        web_search_options: payload.web_search.map(|enabled| WebSearchOptions {
+            enabled: Some(enabled),
+        }),
+        prediction: payload.prediction.as_ref().map(|text| Prediction {
+            kind: "content".to_string(),
+            content: Value::String(text.clone()),
+        }),
+        codex: payload.sampling.map(|sampling| CodexOptions {
+            samples: Some(sampling.samples as u64),
+            select: option_name(Some(&sampling.select)),
+            ..CodexOptions::default()
+        }),
+        stream_options: payload.stream_progress.then(|| StreamOptions {
+            codex_progress: true,
+        }),
+        ..ChatCompletionRequest::default()
+    }
+}
+
+fn render_messages(items: &[ResponseItem], names: &ToolNames) -> Vec<ChatMessage> {
+    let mut messages = Vec::new();
+    let mut pending_calls: Vec<ChatToolCall> = Vec::new();
+    for item in items {
+        match item {
+            ResponseItem::FunctionCall {
+                name,
+                arguments,
+                call_id,
+                ..
+            } => {
+                pending_calls.push(ChatToolCall {
+                    id: Some(call_id.clone()),
+                    r#type: Some("function".to_string()),
+                    function: Some(ChatToolFunction {
+                        name: Some(names.restore(name).to_string()),
+                        arguments: Some(arguments.clone()),
+                    }),
+                });
+                continue;
+            }
+            ResponseItem::Message { role, content, .. } if role == "assistant" => {
+                messages.push(ChatMessage {
+                    role: role.clone(),
+                    content: render_content(content),
+                    tool_calls: take_calls(&mut pending_calls),
+                    ..ChatMessage::default()
+                });
+                continue;
+            }
+            _ => {}
+        }
+        if let Some(tool_calls) = take_calls(&mut pending_calls) {
+            messages.push(ChatMessage {
+                role: "assistant".to_string(),
+                tool_calls: Some(tool_calls),
+                ..ChatMessage::default()
+            });
+        }
+        match item {
+            ResponseItem::Message { role, content, .. } => messages.push(ChatMessage {
+                role: if role == "developer" {
+                    "system".to_string()
+                } else {
+                    role.clone()
+                },
+                content: render_content(content),
+                ..ChatMessage::default()
+            }),
+            ResponseItem::FunctionCallOutput { call_id, output } => messages.push(ChatMessage {
+                role: "tool".to_string(),
+                content: render_tool_output(output),
+                tool_call_id: Some(call_id.clone()),
+                ..ChatMessage::default()
+            }),
+            _ => {}
+        }
+    }
+    if let Some(tool_calls) = take_calls(&mut pending_calls) {
+        messages.push(ChatMessage {
+            role: "assistant".to_string(),
+            tool_calls: Some(tool_calls),
+            ..ChatMessage::default()
+        });
+    }
+    messages
+}
+
+fn take_calls(pending: &mut Vec<ChatToolCall>) -> Option<LenientList<ChatToolCall>> {
+    (!pending.is_empty()).then(|| std::mem::take(pending).into())
+}
+
+/// A single text part as a plain string, anything else as a content array.
+fn render_content(content: &[ContentItem]) -> Value {
+    if let [ContentItem::InputText { text } | ContentItem::OutputText { text }] = content {
+        return Value::String(text.clone());
+    }
+    Value::Array(
+        content
+            .iter()
+            .map(|item| match item {
+                ContentItem::InputText { text } | ContentItem::OutputText { text } => {
+                    json!({"type": "text", "text": text})
+                }
+                ContentItem::InputImage { image_url } => {
+                    json!({"type": "image_url", "image_url": {"url": image_url}})
+                }
+            })
+            .collect(),
+    )
+}
+
+fn render_tool_output(output: &FunctionCallOutputPayload) -> Value {
+    let Some(items) = &output.content_items else {
+        return Value::String(output.content.clone());
+    };
+    Value::Array(
+        items
+            .iter()
+            .map(|item| match item {
+                FunctionCallOutputContentItem::InputText { text } => {
+                    json!({"type": "text", "text": text})
+                }
+                FunctionCallOutputContentItem::InputImage { image_url } => {
+                    json!({"type": "image_url", "image_url": {"url": image_url}})
+                }
+            })
+            .collect(),
+    )
+}
+
+/// The lower-case name a Codex option enum serializes as, e.g. `high`.
+fn option_name<T: Serialize>(value: Option<T>) -> Option<String> {
+    value
+        .and_then(|value| serde_json::to_value(value).ok())
+        .and_then(|value| value.as_str().map(str::to_string))
+}
+
+/// One line per prompt item, compact enough to write expectations by hand:
+/// `user: hi`, `function_call call_1 get_weather({"city":"Paris"})`,
+/// `function_call_output call_1: sunny`.
+pub fn describe_input(items: &[ResponseItem]) -> Vec<String> {
+    items
+        .iter()
+        .map(|item| match item {
+            ResponseItem::Message { role, content, .. } => format!(
+                "{role}: {}",
+                content_items_to_text(content).unwrap_or_default()
+            ),
+            ResponseItem::FunctionCall {
+                name,
+                arguments,
+                call_id,
+                ..
+            } => format!("function_call {call_id} {name}({arguments})"),
+            ResponseItem::FunctionCallOutput { call_id, output } => {
+                format!("function_call_output {call_id}: {}", output.content)
+            }
+            other => format!("{other:?}"),
+        })
+        .collect()
+}
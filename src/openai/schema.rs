@@ -4,7 +4,7 @@ use serde_json::{Map, Value, json};
 /// - Recursively ensures every nested schema object has a `type`.
 /// - Infers sensible defaults for `object`/`array` schemas when structural hints exist.
 /// - Normalizes boolean schemas to permissive string schemas.
-pub(crate) fn sanitize_json_schema(value: &mut Value) {
+pub fn sanitize_json_schema(value: &mut Value) {
     match value {
         Value::Bool(_) => {
             *value = json!({ "type": "string" });
@@ -0,0 +1,113 @@
+//! Rules for the function tools a request declares: how many, and what their names may look
+//! like. Names follow OpenAI's `^[a-zA-Z0-9_-]{1,64}$`; with `--sanitize-tool-names` others are
+//! rewritten to fit and mapped back on the tool calls the model makes.
+
+use std::{collections::HashMap, sync::Arc};
+
+/// Longest tool name the upstream accepts.
+pub const MAX_NAME_LEN: usize = 64;
+
+pub const DEFAULT_MAX_TOOLS: usize = 128;
+
+/// The server's rules for the function tools a request declares, part of the
+/// [`super::chat::ConversionOptions`] a request is converted under.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ToolRules {
+    /// Most tools one request may declare (`--max-tools`).
+    pub max_tools: usize,
+    /// Rewrite invalid names instead of rejecting them (`--sanitize-tool-names`).
+    pub sanitize_names: bool,
+}
+
+impl Default for ToolRules {
+    fn default() -> Self {
+        Self {
+            max_tools: DEFAULT_MAX_TOOLS,
+            sanitize_names: false,
+        }
+    }
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'_' | b'-'))
+}
+
+/// `name` with every disallowed character replaced by `_`, cut to [`MAX_NAME_LEN`].
+pub fn sanitize_name(name: &str) -> String {
+    name.chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || matches!(ch, '_' | '-') {
+                ch
+            } else {
+                '_'
+            }
+        })
+        .take(MAX_NAME_LEN)
+        .collect()
+}
+
+/// Sanitized tool names mapped back to the names the client declared. Shared by every clone of
+/// its [`super::chat::PromptPayload`]; empty unless a name was rewritten.
+#[derive(Clone, Debug, Default)]
+pub struct ToolNames(Arc<HashMap<String, String>>);
+
+impl ToolNames {
+    pub fn new(sanitized_to_original: HashMap<String, String>) -> Self {
+        Self(Arc::new(sanitized_to_original))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The name the client declared for a tool the model called as `name`.
+    pub fn restore<'a>(&'a self, name: &'a str) -> &'a str {
+        self.0.get(name).map_or(name, String::as_str)
+    }
+
+    /// The name the model knows a client-declared tool by.
+    pub fn sanitized<'a>(&'a self, original: &'a str) -> &'a str {
+        self.0
+            .iter()
+            .find(|(_, declared)| declared.as_str() == original)
+            .map_or(original, |(sanitized, _)| sanitized.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_follow_the_openai_pattern() {
+        assert!(is_valid_name("get_weather-v2"));
+        assert!(is_valid_name(&"a".repeat(MAX_NAME_LEN)));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("weather.get"));
+        assert!(!is_valid_name("get weather"));
+        assert!(!is_valid_name(&"a".repeat(MAX_NAME_LEN + 1)));
+    }
+
+    #[test]
+    fn sanitized_names_are_valid() {
+        assert_eq!(sanitize_name("weather.get"), "weather_get");
+        assert_eq!(sanitize_name("météo"), "m_t_o");
+        assert!(is_valid_name(&sanitize_name(&"x.".repeat(MAX_NAME_LEN))));
+    }
+
+    #[test]
+    fn names_map_both_ways() {
+        let names = ToolNames::new(HashMap::from([(
+            "weather_get".to_string(),
+            "weather.get".to_string(),
+        )]));
+        assert_eq!(names.restore("weather_get"), "weather.get");
+        assert_eq!(names.restore("other"), "other");
+        assert_eq!(names.sanitized("weather.get"), "weather_get");
+        assert_eq!(names.sanitized("other"), "other");
+    }
+}
@@ -0,0 +1,65 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use serde::Serialize;
+
+use crate::error::ApiError;
+
+/// Something the bridge changed or dropped to serve a request, reported back to the client.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Warning {
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// The warnings of one request. Created by `into_prompt` and shared by every clone of its
+/// [`super::chat::PromptPayload`], so the executor can add to the list the handler reports.
+#[derive(Clone, Debug, Default)]
+pub struct Warnings(Arc<Mutex<Vec<Warning>>>);
+
+impl Warnings {
+    /// Records a warning; callers log it their own way.
+    pub fn push(&self, code: &'static str, message: impl Into<String>) {
+        self.list().push(Warning {
+            code,
+            message: message.into(),
+        });
+    }
+
+    /// Adds the warnings of `other` that are not on the list yet, so a warning every sample of a
+    /// `codex.samples` request reports is listed once.
+    pub fn merge(&self, other: &Warnings) {
+        let added = other.snapshot();
+        let mut list = self.list();
+        for warning in added {
+            if !list.contains(&warning) {
+                list.push(warning);
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<Warning> {
+        self.list().clone()
+    }
+
+    /// Fails with a 400 listing the warnings, if there are any (`--fail-on-warnings`).
+    pub fn reject_any(&self) -> Result<(), ApiError> {
+        let list = self.list();
+        if list.is_empty() {
+            return Ok(());
+        }
+        let reasons: Vec<String> = list
+            .iter()
+            .map(|warning| format!("{}: {}", warning.code, warning.message))
+            .collect();
+        Err(ApiError::bad_request(format!(
+            "request would be served with warnings (--fail-on-warnings): {}",
+            reasons.join("; ")
+        )))
+    }
+
+    fn list(&self) -> MutexGuard<'_, Vec<Warning>> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
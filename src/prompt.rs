@@ -4,6 +4,10 @@ use crate::serve_config::DeveloperPromptMode;
 
 pub const CODEX_SERVE_PROMPT_MARKER: &str = "Codex Serve compatibility mode";
 
+/// Opens the developer message that carries a request's `prediction` (`--use-prediction-hint`).
+pub const PREDICTION_HINT_INTRO: &str = "A likely draft of the answer follows; reuse it verbatim \
+     where it is correct and change only what needs changing.";
+
 /// Ensures the prompt includes the Codex web search tool when allowed.
 pub fn ensure_web_search_tool(prompt: &mut Prompt, allow_web_search: bool) -> bool {
     let mut has_web_search = prompt
@@ -48,7 +52,8 @@ pub fn inject_developer_prompt(
         DeveloperPromptMode::Disabled | DeveloperPromptMode::Default => None,
     };
 
-    let text = build_developer_prompt_text(has_web_search, original_system);
+    let client_tools = client_tool_names(prompt);
+    let text = build_developer_prompt_text(has_web_search, &client_tools, original_system);
 
     prompt.input.insert(
         0,
@@ -60,15 +65,50 @@ pub fn inject_developer_prompt(
     );
 }
 
-fn build_developer_prompt_text(has_web_search: bool, original_system: Option<&str>) -> String {
+/// Appends the client's predicted output as a developer message after the conversation, the
+/// nearest Codex gets to OpenAI's predicted outputs.
+pub fn inject_prediction_hint(prompt: &mut Prompt, prediction: &str) {
+    prompt.input.push(ResponseItem::Message {
+        id: None,
+        role: "developer".to_string(),
+        content: vec![ContentItem::InputText {
+            text: format!("{PREDICTION_HINT_INTRO}\n\n{prediction}"),
+        }],
+    });
+}
+
+/// Names of the function tools the client registered, which it runs on its side.
+fn client_tool_names(prompt: &Prompt) -> Vec<&str> {
+    prompt
+        .tools
+        .iter()
+        .filter_map(|tool| match tool {
+            ToolSpec::Function(tool) => Some(tool.name.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn build_developer_prompt_text(
+    has_web_search: bool,
+    client_tools: &[&str],
+    original_system: Option<&str>,
+) -> String {
     let mut lines = vec![
         "This compatibility shim cannot run shells, edit files, or inspect your workspace.",
         "Never claim you executed commands or edits—describe what the user should run instead and wait for their results.",
     ];
 
+    let client_tools_line = format!(
+        "The client provides these callable tools: {} — request them via tool calls and wait for their results.",
+        client_tools.join(", ")
+    );
+    if !client_tools.is_empty() {
+        lines.push(&client_tools_line);
+    }
     if has_web_search {
         lines.push("You may invoke the `web_search` tool when you truly need new information.");
-    } else {
+    } else if client_tools.is_empty() {
         lines.push("No tools are available for this conversation.");
     }
 
@@ -106,6 +146,7 @@ fn has_existing_codex_serve_message(prompt: &Prompt) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use codex_core::{JsonSchema, ResponsesApiTool};
 
     #[test]
     fn ensure_web_search_tool_inserts_when_allowed() {
@@ -165,6 +206,79 @@ mod tests {
         }
     }
 
+    #[test]
+    fn prediction_hint_follows_the_conversation() {
+        let mut prompt = Prompt::default();
+        inject_developer_prompt(&mut prompt, false, None, DeveloperPromptMode::Default);
+        inject_prediction_hint(&mut prompt, "fn main() {}");
+        let Some(ResponseItem::Message { role, content, .. }) = prompt.input.last() else {
+            panic!("expected the hint last");
+        };
+        assert_eq!(role, "developer");
+        assert!(matches!(
+            &content[..],
+            [ContentItem::InputText { text }]
+                if text.starts_with(PREDICTION_HINT_INTRO) && text.ends_with("\n\nfn main() {}")
+        ));
+    }
+
+    fn function_tool(name: &str) -> ToolSpec {
+        ToolSpec::Function(ResponsesApiTool {
+            name: name.to_string(),
+            description: String::new(),
+            strict: false,
+            parameters: JsonSchema::Object {
+                properties: Default::default(),
+                required: None,
+                additional_properties: None,
+            },
+        })
+    }
+
+    fn injected_text(tools: Vec<ToolSpec>) -> String {
+        let mut prompt = Prompt {
+            tools,
+            ..Default::default()
+        };
+        let has_web_search = ensure_web_search_tool(&mut prompt, false);
+        inject_developer_prompt(
+            &mut prompt,
+            has_web_search,
+            None,
+            DeveloperPromptMode::Default,
+        );
+        match &prompt.input[0] {
+            ResponseItem::Message { content, .. } => match &content[0] {
+                ContentItem::InputText { text } => text.clone(),
+                other => panic!("unexpected content: {other:?}"),
+            },
+            other => panic!("expected developer message, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn tool_line_without_any_tools() {
+        let text = injected_text(Vec::new());
+        assert!(text.contains("No tools are available"));
+        assert!(!text.contains("callable tools"));
+    }
+
+    #[test]
+    fn tool_line_lists_client_tools() {
+        let text = injected_text(vec![function_tool("read_file"), function_tool("run_tests")]);
+        assert!(text.contains("The client provides these callable tools: read_file, run_tests"));
+        assert!(!text.contains("No tools are available"));
+        assert!(!text.contains("web_search"));
+    }
+
+    #[test]
+    fn tool_line_lists_client_tools_and_web_search() {
+        let text = injected_text(vec![function_tool("read_file"), ToolSpec::WebSearch {}]);
+        assert!(text.contains("callable tools: read_file —"));
+        assert!(text.contains("`web_search` tool"));
+        assert!(!text.contains("No tools are available"));
+    }
+
     #[test]
     fn disabled_mode_never_injects() {
         let mut prompt = Prompt::default();
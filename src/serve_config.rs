@@ -1,24 +1,508 @@
-use std::{fmt, str::FromStr, sync::OnceLock};
+use std::{fmt, path::PathBuf, str::FromStr, sync::RwLock, time::Duration};
 
-#[derive(Clone, Copy, Debug)]
+use serde::{Serialize, Serializer};
+
+pub use crate::openai::chat::DEFAULT_MAX_TOOL_OUTPUT_BYTES;
+pub use crate::openai::tool_names::DEFAULT_MAX_TOOLS;
+use crate::openai::{
+    chat::{ConversionOptions, PromptEndpoint},
+    tool_names::ToolRules,
+};
+pub use crate::server::DEFAULT_MAX_TRACKED_TOOL_CALLS;
+
+#[derive(Clone, Debug, Serialize)]
 pub struct ServeConfig {
     pub verbose: bool,
     pub expose_reasoning_models: bool,
+    /// List `profile/model` combinations in `/v1/models`.
+    pub expose_profiles: bool,
     pub web_search_request: Option<bool>,
     pub developer_prompt_mode: DeveloperPromptMode,
+    /// Maximum accepted request body for chat routes, in bytes.
+    pub max_body_size: usize,
+    /// Maximum accepted request body for metadata routes (`/api/show`, ...), in bytes.
+    pub max_metadata_body_size: usize,
+    /// Authenticate upstream calls with this OpenAI API key instead of the `codex login` session.
+    pub openai_api_key: Option<ApiKey>,
+    /// Bearer token clients must send on listeners bound with `auth=required`.
+    pub client_api_key: Option<ApiKey>,
+    /// Append every chat exchange to daily JSONL files in this directory.
+    pub capture_dir: Option<PathBuf>,
+    /// Per-body byte cap for captured requests and responses.
+    pub capture_max_body_bytes: usize,
+    /// Replace conversation text with length markers in verbose logs and captures.
+    pub verbose_redact: bool,
+    /// Serve the built-in HTML playground at `GET /`.
+    pub playground: bool,
+    /// Mount the `/admin/*` operational routes.
+    pub enable_admin: bool,
+    /// Cap on concurrent chat requests from any single client; unlimited when unset.
+    pub per_client_concurrency: Option<usize>,
+    /// Ollama release reported by `/api/version`; clients gate features on it.
+    pub ollama_version: String,
+    /// Largest SSE content chunk, in bytes, when a finished message is replayed without deltas.
+    pub fallback_chunk_bytes: usize,
+    /// Mixed into every Ollama model digest; change it to make clients drop cached model metadata.
+    pub ollama_digest_salt: Option<String>,
+    /// Answer `400` for parameters the model family does not take instead of dropping them.
+    pub strict_params: bool,
+    /// Start even when Codex cannot be initialized, reporting the failure on `/healthz`.
+    pub allow_degraded: bool,
+    /// Add Codex's raw token counts to responses as `codex_usage` and total them per model and
+    /// client on `/healthz`.
+    pub usage_extended: bool,
+    /// Reject requests with a 400 listing their warnings instead of serving them degraded.
+    pub fail_on_warnings: bool,
+    /// The API route groups to serve; routes of the others answer 404.
+    pub api_surfaces: ApiSurfaces,
+    /// How Ollama listings spell reasoning variants: `model-high` or `model:high`.
+    pub ollama_tag_style: OllamaTagStyle,
+    /// What to send instead of tool calls when the request declared no tools.
+    pub tool_call_fallback: ToolCallFallback,
+    /// Most tools one request may declare.
+    pub max_tools: usize,
+    /// Rewrite tool names the upstream would reject instead of answering `400`.
+    pub sanitize_tool_names: bool,
+    /// Most tool calls per upstream response kept apart by id; later ones are passed on untracked.
+    pub max_tracked_tool_calls: usize,
+    /// Let `web_search_options` turn web search on for a request when it is off server-wide.
+    pub allow_per_request_web_search: bool,
+    /// Write every key of a real OpenAI response, as `null` where Codex has no value, instead of
+    /// leaving them out.
+    pub compat_nulls: bool,
+    /// How often to run an upstream keepalive while serving, to keep credentials and config warm.
+    pub keepalive_interval: Option<Duration>,
+    /// Answer streaming requests with a JSON completion when the executor cannot stream, instead
+    /// of refusing them with a 503.
+    pub stream_fallback: bool,
+    /// Refuse requests whose estimated prompt plus completion reserve exceeds this many tokens.
+    pub max_tokens_per_request: Option<u64>,
+    /// Refuse a client's requests once it has used this many tokens in the last hour.
+    pub max_tokens_per_hour: Option<u64>,
+    /// Pass a request's `prediction` to the model as a draft to reuse instead of ignoring it.
+    pub use_prediction_hint: bool,
+    /// Least time between the progress comments of a stream that asked for them with
+    /// `stream_options.codex_progress`.
+    pub progress_interval: Duration,
+    /// Send empty and whitespace-only messages upstream instead of dropping them.
+    pub keep_empty_messages: bool,
+    /// Load every listed model's config at startup instead of on first use.
+    pub preload_models: bool,
+    /// Queue chat requests over `per_client_concurrency` instead of refusing them.
+    pub queue_requests: bool,
+    /// Keep the request counters, token budgets and conversation stats in this file across
+    /// restarts.
+    pub state_file: Option<PathBuf>,
+    /// Split streamed chunks so no SSE event is larger than this many bytes.
+    pub max_sse_event_bytes: Option<usize>,
+    /// Refuse `x-codex-serve-dry-run` requests instead of returning the prompt they would send.
+    pub disable_dry_run: bool,
+    /// Drop a cached per-model config once no request has used it for this long.
+    pub cache_idle_ttl: Duration,
+    /// Which model name replies carry in `model`, with the other always in a vendor field; unset,
+    /// replies carry the requested name alone.
+    pub report_model: Option<ReportModel>,
+    /// End each stream with a SHA-256 and byte count of its content deltas.
+    pub stream_integrity: bool,
+    /// Cut tool results longer than this many bytes to their head and tail; `0` keeps them whole.
+    pub max_tool_output_bytes: usize,
+    /// Serve `POST /v1/codex/stream`, which streams the raw upstream events.
+    pub enable_codex_stream: bool,
+    /// Drop `<think>` blocks a client echoed back in assistant history.
+    pub strip_replayed_reasoning: bool,
+    /// Refuse to start when the Codex config rejects an override, instead of dropping it.
+    pub strict_config: bool,
+    /// Keep reasoning inside the process: out of replies, logs and capture files.
+    pub hide_reasoning: bool,
+}
+
+/// API key wrapper that keeps the secret out of `Debug` output.
+#[derive(Clone)]
+pub struct ApiKey(String);
+
+impl ApiKey {
+    /// Returns `None` for blank keys so an empty env var does not switch auth modes.
+    pub fn new(key: impl Into<String>) -> Option<Self> {
+        let key = key.into().trim().to_string();
+        (!key.is_empty()).then_some(Self(key))
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ApiKey(<redacted>)")
+    }
+}
+
+impl Serialize for ApiKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str("<redacted>")
+    }
 }
 
+pub const DEFAULT_MAX_BODY_SIZE: usize = 50 * 1024 * 1024;
+pub const DEFAULT_MAX_METADATA_BODY_SIZE: usize = 1024 * 1024;
+pub const DEFAULT_CAPTURE_MAX_BODY_BYTES: usize = 256 * 1024;
+pub const DEFAULT_OLLAMA_VERSION: &str = "0.13.0";
+pub const DEFAULT_FALLBACK_CHUNK_BYTES: usize = 1024;
+pub const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+pub const DEFAULT_CACHE_IDLE_TTL: Duration = Duration::from_secs(30 * 60);
+
 impl Default for ServeConfig {
     fn default() -> Self {
         Self {
             verbose: false,
             expose_reasoning_models: false,
+            expose_profiles: false,
             web_search_request: None,
             developer_prompt_mode: DeveloperPromptMode::Default,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            max_metadata_body_size: DEFAULT_MAX_METADATA_BODY_SIZE,
+            openai_api_key: None,
+            client_api_key: None,
+            capture_dir: None,
+            capture_max_body_bytes: DEFAULT_CAPTURE_MAX_BODY_BYTES,
+            verbose_redact: false,
+            playground: false,
+            enable_admin: false,
+            per_client_concurrency: None,
+            ollama_version: DEFAULT_OLLAMA_VERSION.to_string(),
+            fallback_chunk_bytes: DEFAULT_FALLBACK_CHUNK_BYTES,
+            ollama_digest_salt: None,
+            strict_params: false,
+            allow_degraded: false,
+            usage_extended: false,
+            fail_on_warnings: false,
+            api_surfaces: ApiSurfaces::default(),
+            ollama_tag_style: OllamaTagStyle::Hyphen,
+            tool_call_fallback: ToolCallFallback::None,
+            max_tools: DEFAULT_MAX_TOOLS,
+            sanitize_tool_names: false,
+            max_tracked_tool_calls: DEFAULT_MAX_TRACKED_TOOL_CALLS,
+            allow_per_request_web_search: false,
+            compat_nulls: false,
+            keepalive_interval: None,
+            stream_fallback: false,
+            max_tokens_per_request: None,
+            max_tokens_per_hour: None,
+            use_prediction_hint: false,
+            progress_interval: DEFAULT_PROGRESS_INTERVAL,
+            keep_empty_messages: false,
+            preload_models: false,
+            queue_requests: false,
+            state_file: None,
+            max_sse_event_bytes: None,
+            disable_dry_run: false,
+            cache_idle_ttl: DEFAULT_CACHE_IDLE_TTL,
+            report_model: None,
+            stream_integrity: false,
+            max_tool_output_bytes: DEFAULT_MAX_TOOL_OUTPUT_BYTES,
+            enable_codex_stream: false,
+            strip_replayed_reasoning: true,
+            strict_config: false,
+            hide_reasoning: false,
+        }
+    }
+}
+
+impl ServeConfig {
+    /// Starts from the defaults; see the fields for what each setter controls.
+    pub fn builder() -> ServeConfigBuilder {
+        ServeConfigBuilder::default()
+    }
+
+    /// The tool settings request conversion applies.
+    pub fn tool_rules(&self) -> ToolRules {
+        ToolRules {
+            max_tools: self.max_tools,
+            sanitize_names: self.sanitize_tool_names,
+        }
+    }
+
+    /// How requests arriving through `endpoint` are converted under these settings.
+    pub fn conversion_options(&self, endpoint: PromptEndpoint) -> ConversionOptions {
+        ConversionOptions {
+            endpoint,
+            strict_roles: self.strict_params,
+            keep_empty_messages: self.keep_empty_messages,
+            tools: self.tool_rules(),
+            max_tool_output_bytes: (self.max_tool_output_bytes > 0)
+                .then_some(self.max_tool_output_bytes),
+            strip_replayed_reasoning: self.strip_replayed_reasoning,
+            hide_reasoning: self.hide_reasoning,
         }
     }
 }
 
+/// Builds a [`ServeConfig`] one setting at a time, for embedders and tests that only care about a
+/// few of them.
+#[derive(Clone, Debug, Default)]
+pub struct ServeConfigBuilder {
+    config: ServeConfig,
+}
+
+impl ServeConfigBuilder {
+    pub fn verbose(mut self, enabled: bool) -> Self {
+        self.config.verbose = enabled;
+        self
+    }
+
+    pub fn expose_reasoning_models(mut self, enabled: bool) -> Self {
+        self.config.expose_reasoning_models = enabled;
+        self
+    }
+
+    pub fn expose_profiles(mut self, enabled: bool) -> Self {
+        self.config.expose_profiles = enabled;
+        self
+    }
+
+    pub fn web_search_request(mut self, enabled: bool) -> Self {
+        self.config.web_search_request = Some(enabled);
+        self
+    }
+
+    pub fn developer_prompt_mode(mut self, mode: DeveloperPromptMode) -> Self {
+        self.config.developer_prompt_mode = mode;
+        self
+    }
+
+    pub fn max_body_size(mut self, bytes: usize) -> Self {
+        self.config.max_body_size = bytes;
+        self
+    }
+
+    pub fn max_metadata_body_size(mut self, bytes: usize) -> Self {
+        self.config.max_metadata_body_size = bytes;
+        self
+    }
+
+    /// Blank keys are ignored, as on the command line.
+    pub fn openai_api_key(mut self, key: impl Into<String>) -> Self {
+        self.config.openai_api_key = ApiKey::new(key);
+        self
+    }
+
+    /// Key for listeners bound with [`ListenerAuth::Required`]; blank keys are ignored.
+    pub fn client_api_key(mut self, key: impl Into<String>) -> Self {
+        self.config.client_api_key = ApiKey::new(key);
+        self
+    }
+
+    pub fn capture_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.capture_dir = Some(dir.into());
+        self
+    }
+
+    pub fn capture_max_body_bytes(mut self, bytes: usize) -> Self {
+        self.config.capture_max_body_bytes = bytes;
+        self
+    }
+
+    pub fn verbose_redact(mut self, enabled: bool) -> Self {
+        self.config.verbose_redact = enabled;
+        self
+    }
+
+    pub fn playground(mut self, enabled: bool) -> Self {
+        self.config.playground = enabled;
+        self
+    }
+
+    pub fn enable_admin(mut self, enabled: bool) -> Self {
+        self.config.enable_admin = enabled;
+        self
+    }
+
+    pub fn per_client_concurrency(mut self, limit: usize) -> Self {
+        self.config.per_client_concurrency = Some(limit);
+        self
+    }
+
+    pub fn ollama_version(mut self, version: impl Into<String>) -> Self {
+        self.config.ollama_version = version.into();
+        self
+    }
+
+    pub fn fallback_chunk_bytes(mut self, bytes: usize) -> Self {
+        self.config.fallback_chunk_bytes = bytes;
+        self
+    }
+
+    pub fn ollama_digest_salt(mut self, salt: impl Into<String>) -> Self {
+        self.config.ollama_digest_salt = Some(salt.into());
+        self
+    }
+
+    pub fn strict_params(mut self, enabled: bool) -> Self {
+        self.config.strict_params = enabled;
+        self
+    }
+
+    pub fn allow_degraded(mut self, enabled: bool) -> Self {
+        self.config.allow_degraded = enabled;
+        self
+    }
+
+    pub fn usage_extended(mut self, enabled: bool) -> Self {
+        self.config.usage_extended = enabled;
+        self
+    }
+
+    pub fn fail_on_warnings(mut self, enabled: bool) -> Self {
+        self.config.fail_on_warnings = enabled;
+        self
+    }
+
+    pub fn api_surfaces(mut self, surfaces: ApiSurfaces) -> Self {
+        self.config.api_surfaces = surfaces;
+        self
+    }
+
+    pub fn ollama_tag_style(mut self, style: OllamaTagStyle) -> Self {
+        self.config.ollama_tag_style = style;
+        self
+    }
+
+    pub fn tool_call_fallback(mut self, fallback: ToolCallFallback) -> Self {
+        self.config.tool_call_fallback = fallback;
+        self
+    }
+
+    pub fn max_tools(mut self, limit: usize) -> Self {
+        self.config.max_tools = limit;
+        self
+    }
+
+    pub fn sanitize_tool_names(mut self, enabled: bool) -> Self {
+        self.config.sanitize_tool_names = enabled;
+        self
+    }
+
+    pub fn max_tracked_tool_calls(mut self, limit: usize) -> Self {
+        self.config.max_tracked_tool_calls = limit;
+        self
+    }
+
+    pub fn allow_per_request_web_search(mut self, enabled: bool) -> Self {
+        self.config.allow_per_request_web_search = enabled;
+        self
+    }
+
+    pub fn compat_nulls(mut self, enabled: bool) -> Self {
+        self.config.compat_nulls = enabled;
+        self
+    }
+
+    pub fn keepalive_interval(mut self, interval: Duration) -> Self {
+        self.config.keepalive_interval = Some(interval);
+        self
+    }
+
+    pub fn stream_fallback(mut self, enabled: bool) -> Self {
+        self.config.stream_fallback = enabled;
+        self
+    }
+
+    pub fn max_tokens_per_request(mut self, limit: u64) -> Self {
+        self.config.max_tokens_per_request = Some(limit);
+        self
+    }
+
+    pub fn max_tokens_per_hour(mut self, limit: u64) -> Self {
+        self.config.max_tokens_per_hour = Some(limit);
+        self
+    }
+
+    pub fn use_prediction_hint(mut self, enabled: bool) -> Self {
+        self.config.use_prediction_hint = enabled;
+        self
+    }
+
+    pub fn progress_interval(mut self, interval: Duration) -> Self {
+        self.config.progress_interval = interval;
+        self
+    }
+
+    pub fn keep_empty_messages(mut self, enabled: bool) -> Self {
+        self.config.keep_empty_messages = enabled;
+        self
+    }
+
+    pub fn preload_models(mut self, enabled: bool) -> Self {
+        self.config.preload_models = enabled;
+        self
+    }
+
+    pub fn queue_requests(mut self, enabled: bool) -> Self {
+        self.config.queue_requests = enabled;
+        self
+    }
+
+    pub fn state_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.state_file = Some(path.into());
+        self
+    }
+
+    pub fn max_sse_event_bytes(mut self, bytes: usize) -> Self {
+        self.config.max_sse_event_bytes = Some(bytes);
+        self
+    }
+
+    pub fn disable_dry_run(mut self, disabled: bool) -> Self {
+        self.config.disable_dry_run = disabled;
+        self
+    }
+
+    pub fn cache_idle_ttl(mut self, ttl: Duration) -> Self {
+        self.config.cache_idle_ttl = ttl;
+        self
+    }
+
+    pub fn report_model(mut self, report: ReportModel) -> Self {
+        self.config.report_model = Some(report);
+        self
+    }
+
+    pub fn stream_integrity(mut self, enabled: bool) -> Self {
+        self.config.stream_integrity = enabled;
+        self
+    }
+
+    pub fn max_tool_output_bytes(mut self, bytes: usize) -> Self {
+        self.config.max_tool_output_bytes = bytes;
+        self
+    }
+
+    pub fn enable_codex_stream(mut self, enabled: bool) -> Self {
+        self.config.enable_codex_stream = enabled;
+        self
+    }
+
+    pub fn strip_replayed_reasoning(mut self, enabled: bool) -> Self {
+        self.config.strip_replayed_reasoning = enabled;
+        self
+    }
+
+    pub fn strict_config(mut self, enabled: bool) -> Self {
+        self.config.strict_config = enabled;
+        self
+    }
+
+    pub fn hide_reasoning(mut self, enabled: bool) -> Self {
+        self.config.hide_reasoning = enabled;
+        self
+    }
+
+    pub fn build(self) -> ServeConfig {
+        self.config
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Default)]
 pub enum DeveloperPromptMode {
     Disabled,
@@ -43,6 +527,12 @@ impl fmt::Display for DeveloperPromptMode {
     }
 }
 
+impl Serialize for DeveloperPromptMode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
 impl FromStr for DeveloperPromptMode {
     type Err = String;
 
@@ -58,35 +548,425 @@ impl FromStr for DeveloperPromptMode {
     }
 }
 
-static GLOBAL_CONFIG: OnceLock<ServeConfig> = OnceLock::new();
+/// What a reply to a request without `tools` carries when the model called tools anyway (Codex's
+/// own web search, or a confused model). Requests that declared tools always get the calls.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Default)]
+pub enum ToolCallFallback {
+    /// Send the tool calls with `finish_reason: "tool_calls"`.
+    #[default]
+    None,
+    /// Describe the attempted calls in the message content and finish with `stop`.
+    Describe,
+}
 
-/// Sets the global configuration for the running server. This should be called once at startup.
+impl ToolCallFallback {
+    fn as_str(self) -> &'static str {
+        match self {
+            ToolCallFallback::None => "none",
+            ToolCallFallback::Describe => "describe",
+        }
+    }
+}
+
+impl fmt::Display for ToolCallFallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for ToolCallFallback {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl FromStr for ToolCallFallback {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(ToolCallFallback::None),
+            "describe" => Ok(ToolCallFallback::Describe),
+            other => Err(format!(
+                "invalid tool call fallback `{other}` (expected none/describe)"
+            )),
+        }
+    }
+}
+
+/// How `/api/tags` and `/api/ps` name reasoning variants. Requests accept both spellings.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Default)]
+pub enum OllamaTagStyle {
+    /// `gpt-5.1-codex-max-high`, as `/v1/models` lists them.
+    #[default]
+    Hyphen,
+    /// `gpt-5.1-codex-max:high`, Ollama's `model:tag` convention.
+    Tag,
+}
+
+impl OllamaTagStyle {
+    fn as_str(self) -> &'static str {
+        match self {
+            OllamaTagStyle::Hyphen => "hyphen",
+            OllamaTagStyle::Tag => "tag",
+        }
+    }
+}
+
+impl fmt::Display for OllamaTagStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for OllamaTagStyle {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl FromStr for OllamaTagStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "hyphen" => Ok(OllamaTagStyle::Hyphen),
+            "tag" => Ok(OllamaTagStyle::Tag),
+            other => Err(format!(
+                "invalid Ollama tag style `{other}` (expected hyphen/tag)"
+            )),
+        }
+    }
+}
+
+/// Which name a reply reports in `model`: the one the client sent, or the Codex model it ran on
+/// (an alias or reasoning variant resolved). The other name always goes in `codex_resolved_model`
+/// or `codex_requested_model`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ReportModel {
+    Requested,
+    Resolved,
+}
+
+impl ReportModel {
+    fn as_str(self) -> &'static str {
+        match self {
+            ReportModel::Requested => "requested",
+            ReportModel::Resolved => "resolved",
+        }
+    }
+}
+
+impl fmt::Display for ReportModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for ReportModel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl FromStr for ReportModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "requested" => Ok(ReportModel::Requested),
+            "resolved" => Ok(ReportModel::Resolved),
+            other => Err(format!(
+                "invalid report model `{other}` (expected requested/resolved)"
+            )),
+        }
+    }
+}
+
+/// Whether a listener makes clients present `--client-api-key`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Default)]
+pub enum ListenerAuth {
+    /// Requests need `Authorization: Bearer <client key>`.
+    Required,
+    /// Anyone who can reach the address is served.
+    #[default]
+    None,
+}
+
+impl ListenerAuth {
+    fn as_str(self) -> &'static str {
+        match self {
+            ListenerAuth::Required => "required",
+            ListenerAuth::None => "none",
+        }
+    }
+}
+
+impl fmt::Display for ListenerAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for ListenerAuth {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl FromStr for ListenerAuth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "required" => Ok(ListenerAuth::Required),
+            "none" => Ok(ListenerAuth::None),
+            other => Err(format!(
+                "invalid listener auth `{other}` (expected required/none)"
+            )),
+        }
+    }
+}
+
+/// An address to listen on and its auth requirement, written `127.0.0.1:8000` or
+/// `0.0.0.0:8000,auth=required` on the command line.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ListenerSpec {
+    pub addr: String,
+    pub auth: ListenerAuth,
+}
+
+impl fmt::Display for ListenerSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},auth={}", self.addr, self.auth)
+    }
+}
+
+impl FromStr for ListenerSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(',').map(str::trim);
+        let addr = parts.next().unwrap_or_default();
+        if addr.is_empty() {
+            return Err("listener address is empty".to_string());
+        }
+        let mut auth = ListenerAuth::default();
+        for option in parts.filter(|option| !option.is_empty()) {
+            match option.split_once('=') {
+                Some((key, value)) if key.trim().eq_ignore_ascii_case("auth") => {
+                    auth = value.parse()?;
+                }
+                _ => {
+                    return Err(format!(
+                        "invalid listener option `{option}` (expected auth=required or auth=none)"
+                    ));
+                }
+            }
+        }
+        Ok(Self {
+            addr: addr.to_string(),
+            auth,
+        })
+    }
+}
+
+/// One of the API route groups the server can expose.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ApiSurface {
+    /// `/v1/models` and `/v1/chat/completions`.
+    OpenAi,
+    /// The `/api/*` routes.
+    Ollama,
+}
+
+impl ApiSurface {
+    fn as_str(self) -> &'static str {
+        match self {
+            ApiSurface::OpenAi => "openai",
+            ApiSurface::Ollama => "ollama",
+        }
+    }
+
+    /// Name used in messages, e.g. "Ollama".
+    pub fn label(self) -> &'static str {
+        match self {
+            ApiSurface::OpenAi => "OpenAI",
+            ApiSurface::Ollama => "Ollama",
+        }
+    }
+}
+
+/// The set of [`ApiSurface`]s a server exposes, written `openai,ollama` on the command line.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ApiSurfaces {
+    pub openai: bool,
+    pub ollama: bool,
+}
+
+impl ApiSurfaces {
+    pub const ALL: Self = Self {
+        openai: true,
+        ollama: true,
+    };
+
+    pub fn only(surface: ApiSurface) -> Self {
+        Self {
+            openai: surface == ApiSurface::OpenAi,
+            ollama: surface == ApiSurface::Ollama,
+        }
+    }
+
+    pub fn contains(self, surface: ApiSurface) -> bool {
+        match surface {
+            ApiSurface::OpenAi => self.openai,
+            ApiSurface::Ollama => self.ollama,
+        }
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = ApiSurface> {
+        [ApiSurface::OpenAi, ApiSurface::Ollama]
+            .into_iter()
+            .filter(move |surface| self.contains(*surface))
+    }
+}
+
+impl Default for ApiSurfaces {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl fmt::Display for ApiSurfaces {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = self.iter().map(ApiSurface::as_str).collect();
+        f.write_str(&names.join(","))
+    }
+}
+
+impl Serialize for ApiSurfaces {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter().map(ApiSurface::as_str))
+    }
+}
+
+impl FromStr for ApiSurfaces {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut surfaces = Self {
+            openai: false,
+            ollama: false,
+        };
+        for name in s.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            match name.to_ascii_lowercase().as_str() {
+                "openai" => surfaces.openai = true,
+                "ollama" => surfaces.ollama = true,
+                other => {
+                    return Err(format!(
+                        "invalid API surface `{other}` (expected openai and/or ollama)"
+                    ));
+                }
+            }
+        }
+        if surfaces.iter().next().is_none() {
+            return Err("at least one API surface (openai, ollama) is required".to_string());
+        }
+        Ok(surfaces)
+    }
+}
+
+/// Parses an interval written as a whole number with a unit, e.g. `500ms`, `30s`, `10m` or `1h`;
+/// a bare number is seconds.
+pub fn parse_interval(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (count, unit) = s.split_at(split);
+    let count: u64 = count
+        .parse()
+        .map_err(|_| format!("invalid interval `{s}` (expected e.g. 30s, 10m or 1h)"))?;
+    let interval = match unit.trim() {
+        "ms" => Duration::from_millis(count),
+        "" | "s" => Duration::from_secs(count),
+        "m" => Duration::from_secs(count.saturating_mul(60)),
+        "h" => Duration::from_secs(count.saturating_mul(3_600)),
+        other => {
+            return Err(format!(
+                "invalid interval unit `{other}` in `{s}` (expected ms, s, m or h)"
+            ));
+        }
+    };
+    if interval.is_zero() {
+        return Err("the interval must be greater than zero".to_string());
+    }
+    Ok(interval)
+}
+
+static GLOBAL_CONFIG: RwLock<Option<ServeConfig>> = RwLock::new(None);
+
+/// Sets the process-wide configuration that [`crate::AppState::initialize`] reads. A later call
+/// replaces the earlier one, but states already built keep the config they were built with, so
+/// prefer passing the config through [`crate::InitOptions`].
 pub fn configure(config: ServeConfig) {
-    GLOBAL_CONFIG
-        .set(config)
-        .expect("codex serve config already initialized");
+    *GLOBAL_CONFIG
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(config);
+}
+
+/// Returns the configuration set by [`configure`] (defaults before it is called).
+pub fn effective_config() -> ServeConfig {
+    read_global(|config| config.cloned().unwrap_or_default())
+}
+
+fn read_global<T>(read: impl FnOnce(Option<&ServeConfig>) -> T) -> T {
+    read(
+        GLOBAL_CONFIG
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .as_ref(),
+    )
 }
 
 /// Returns true if verbose logging was requested.
+#[deprecated(note = "read `AppState::config().verbose` instead")]
 pub fn verbose_logging_enabled() -> bool {
-    GLOBAL_CONFIG.get().is_some_and(|cfg| cfg.verbose)
+    read_global(|config| config.is_some_and(|config| config.verbose))
 }
 
 /// Returns true if the reasoning model variants should be exposed.
+#[deprecated(note = "read `AppState::config().expose_reasoning_models` instead")]
 pub fn expose_reasoning_models() -> bool {
-    GLOBAL_CONFIG
-        .get()
-        .is_some_and(|cfg| cfg.expose_reasoning_models)
+    read_global(|config| config.is_some_and(|config| config.expose_reasoning_models))
 }
 
 /// Returns the override for forcing web search requests (if any).
+#[deprecated(note = "read `AppState::config().web_search_request` instead")]
 pub fn web_search_request_override() -> Option<bool> {
-    GLOBAL_CONFIG.get().and_then(|cfg| cfg.web_search_request)
+    read_global(|config| config.and_then(|config| config.web_search_request))
 }
 
+#[deprecated(note = "read `AppState::config().developer_prompt_mode` instead")]
 pub fn developer_prompt_mode() -> DeveloperPromptMode {
-    GLOBAL_CONFIG
-        .get()
-        .map(|cfg| cfg.developer_prompt_mode)
-        .unwrap_or_default()
+    read_global(|config| {
+        config
+            .map(|config| config.developer_prompt_mode)
+            .unwrap_or_default()
+    })
+}
+
+/// Returns the request body limits as `(chat, metadata)` byte counts.
+#[deprecated(note = "read `AppState::config()` instead")]
+pub fn body_size_limits() -> (usize, usize) {
+    read_global(|config| {
+        config.map_or(
+            (DEFAULT_MAX_BODY_SIZE, DEFAULT_MAX_METADATA_BODY_SIZE),
+            |config| (config.max_body_size, config.max_metadata_body_size),
+        )
+    })
+}
+
+/// Returns true if logged and captured payloads should have their conversation text redacted.
+#[deprecated(note = "read `AppState::config().verbose_redact` instead")]
+pub fn verbose_redact_enabled() -> bool {
+    read_global(|config| config.is_some_and(|config| config.verbose_redact))
 }
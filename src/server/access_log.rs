@@ -0,0 +1,272 @@
+use std::{
+    convert::Infallible,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::{Body, BodyDataStream, Bytes},
+    http::{Method, Request, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use codex_core::protocol::TokenUsage;
+use futures_util::Stream;
+use tracing::{error, info};
+
+use super::{
+    middleware::{RequestId, is_stream_response},
+    organization::OpenAiOrganization,
+    response::Usage,
+};
+use crate::clock::RequestClock;
+
+/// Per-request access log entry. Handlers fill in chat details through the copy stored in the
+/// request extensions; the line is written when the last clone is dropped, which for SSE is when
+/// the response body finishes rather than when the headers go out. Streams also get a
+/// "stream started" line when their headers go out.
+#[derive(Clone)]
+pub(super) struct AccessLog(Arc<AccessLogRecord>);
+
+struct AccessLogRecord {
+    method: Method,
+    path: String,
+    request_id: String,
+    started: Instant,
+    details: Mutex<AccessDetails>,
+}
+
+#[derive(Default)]
+struct AccessDetails {
+    status: Option<StatusCode>,
+    model: Option<String>,
+    stream: Option<bool>,
+    /// The reply's `id` and `created`.
+    clock: Option<RequestClock>,
+    first_byte: Option<Duration>,
+    prompt_tokens: Option<u32>,
+    completion_tokens: Option<u32>,
+    /// Only recorded with `--usage-extended`.
+    codex_usage: Option<TokenUsage>,
+    finish_reason: Option<String>,
+    outcome: Option<StreamOutcome>,
+    organization: Option<OpenAiOrganization>,
+}
+
+/// How a streamed body ended, as seen by the access log's body wrapper.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StreamOutcome {
+    Completed,
+    /// The body errored, or the handler reported an in-band error before closing it.
+    Error,
+    ClientDisconnected,
+}
+
+impl StreamOutcome {
+    fn as_str(self) -> &'static str {
+        match self {
+            StreamOutcome::Completed => "completed",
+            StreamOutcome::Error => "error",
+            StreamOutcome::ClientDisconnected => "client_disconnected",
+        }
+    }
+}
+
+impl AccessLog {
+    fn new(method: Method, path: String, request_id: String) -> Self {
+        Self(Arc::new(AccessLogRecord {
+            method,
+            path,
+            request_id,
+            started: Instant::now(),
+            details: Mutex::default(),
+        }))
+    }
+
+    pub(super) fn record_request(&self, model: &str, stream: bool) {
+        let mut details = self.details();
+        details.model = Some(model.to_string());
+        details.stream = Some(stream);
+    }
+
+    pub(super) fn record_clock(&self, clock: &RequestClock) {
+        self.details().clock = Some(clock.clone());
+    }
+
+    pub(super) fn record_outcome(&self, usage: &Usage, finish_reason: Option<&str>) {
+        let mut details = self.details();
+        details.prompt_tokens = Some(usage.prompt_tokens);
+        details.completion_tokens = Some(usage.completion_tokens);
+        details.finish_reason = finish_reason.map(str::to_string);
+    }
+
+    pub(super) fn record_codex_usage(&self, usage: &TokenUsage) {
+        self.details().codex_usage = Some(usage.clone());
+    }
+
+    pub(super) fn record_finish_reason(&self, finish_reason: &str) {
+        self.details().finish_reason = Some(finish_reason.to_string());
+    }
+
+    pub(super) fn record_organization(&self, organization: &OpenAiOrganization) {
+        self.details().organization = Some(organization.clone());
+    }
+
+    /// Notes when the first SSE event left the server; later calls are ignored.
+    pub(super) fn mark_first_byte(&self) {
+        let elapsed = self.0.started.elapsed();
+        self.details().first_byte.get_or_insert(elapsed);
+    }
+
+    fn record_status(&self, status: StatusCode) {
+        self.details().status = Some(status);
+    }
+
+    fn record_stream_end(&self, outcome: StreamOutcome) {
+        let mut details = self.details();
+        let errored = details.finish_reason.as_deref() == Some("error");
+        details.outcome = Some(match outcome {
+            StreamOutcome::Completed if errored => StreamOutcome::Error,
+            outcome => outcome,
+        });
+    }
+
+    fn details(&self) -> MutexGuard<'_, AccessDetails> {
+        self.0.details()
+    }
+}
+
+impl AccessLogRecord {
+    fn details(&self) -> MutexGuard<'_, AccessDetails> {
+        self.details
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Drop for AccessLogRecord {
+    fn drop(&mut self) {
+        let duration_ms = millis(self.started.elapsed());
+        let details = self.details();
+        let status = details.status.unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let codex_usage = details.codex_usage.as_ref();
+        let organization = details.organization.as_ref();
+        let clock = details.clock.as_ref();
+        macro_rules! access_log {
+            ($level:ident, $message:literal) => {
+                $level!(
+                    method = %self.method,
+                    path = self.path,
+                    status = status.as_u16(),
+                    request_id = %self.request_id,
+                    duration_ms,
+                    model = details.model.as_deref(),
+                    stream = details.stream,
+                    completion_id = clock.map(RequestClock::id),
+                    created = clock.map(RequestClock::created),
+                    ttfb_ms = details.first_byte.map(millis),
+                    prompt_tokens = details.prompt_tokens,
+                    completion_tokens = details.completion_tokens,
+                    codex_input_tokens = codex_usage.map(|usage| usage.input_tokens),
+                    codex_cached_input_tokens = codex_usage.map(|usage| usage.cached_input_tokens),
+                    codex_output_tokens = codex_usage.map(|usage| usage.output_tokens),
+                    codex_reasoning_output_tokens =
+                        codex_usage.map(|usage| usage.reasoning_output_tokens),
+                    codex_total_tokens = codex_usage.map(|usage| usage.total_tokens),
+                    finish_reason = details.finish_reason.as_deref(),
+                    outcome = details.outcome.map(StreamOutcome::as_str),
+                    openai_organization =
+                        organization.and_then(|org| org.organization.as_deref()),
+                    openai_project = organization.and_then(|org| org.project.as_deref()),
+                    $message
+                )
+            };
+        }
+        if status.is_success() && details.outcome != Some(StreamOutcome::Error) {
+            access_log!(info, "handled request");
+        } else {
+            access_log!(error, "request failed");
+        }
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+/// Access log middleware: hands an [`AccessLog`] to the handlers and records the final status.
+/// Streaming bodies are wrapped so the final line says how the stream ended.
+pub(super) async fn log_requests(
+    mut request: Request<Body>,
+    next: Next,
+) -> Result<Response, Infallible> {
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.clone())
+        .unwrap_or_default();
+    let log = AccessLog::new(
+        request.method().clone(),
+        request.uri().path().to_string(),
+        request_id,
+    );
+    request.extensions_mut().insert(log.clone());
+    let response = next.run(request).await;
+    log.record_status(response.status());
+    if !is_stream_response(&response) {
+        return Ok(response);
+    }
+    info!(
+        method = %log.0.method,
+        path = log.0.path,
+        status = response.status().as_u16(),
+        request_id = %log.0.request_id,
+        "stream started"
+    );
+    let (parts, body) = response.into_parts();
+    let body = ObservedBody {
+        inner: body.into_data_stream(),
+        log,
+        ended: false,
+    };
+    Ok(Response::from_parts(parts, Body::from_stream(body)))
+}
+
+/// Passes a streamed body through, noting on the access log whether it ran to the end, failed,
+/// or was dropped early because the client went away.
+struct ObservedBody {
+    inner: BodyDataStream,
+    log: AccessLog,
+    ended: bool,
+}
+
+impl Stream for ObservedBody {
+    type Item = Result<Bytes, axum::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = Pin::new(&mut self.inner).poll_next(cx);
+        let outcome = match &poll {
+            Poll::Ready(Some(Err(_))) => Some(StreamOutcome::Error),
+            Poll::Ready(None) => Some(StreamOutcome::Completed),
+            _ => None,
+        };
+        if let Some(outcome) = outcome
+            && !self.ended
+        {
+            self.ended = true;
+            self.log.record_stream_end(outcome);
+        }
+        poll
+    }
+}
+
+impl Drop for ObservedBody {
+    fn drop(&mut self) {
+        if !self.ended {
+            self.log
+                .record_stream_end(StreamOutcome::ClientDisconnected);
+        }
+    }
+}
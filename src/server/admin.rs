@@ -0,0 +1,462 @@
+//! Operator routes, mounted only with `--enable-admin`. They sit behind the same middleware stack
+//! as every other route, so any client authentication applies to them too.
+
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::HeaderMap,
+    routing::{get, post},
+};
+use serde::Serialize;
+use tracing::info;
+
+use super::{
+    codex_model_ids,
+    executor::{ModelSettings, ReloadOutcome},
+    in_flight::RequestSummary,
+    metrics::MetricsSnapshot,
+    parse_reasoning_variant, resolve_profile,
+    state::AppState,
+};
+use crate::{
+    error::ApiError,
+    serve_config::{DeveloperPromptMode, ServeConfig, ToolCallFallback},
+};
+
+pub(super) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/admin/reload", post(reload))
+        .route("/admin/gc", post(gc))
+        .route("/admin/state", get(admin_state))
+        .route("/admin/requests", get(requests))
+        .route("/admin/requests/{id}/cancel", post(cancel_request))
+        .route("/v1/models/{id}/settings", get(model_settings))
+}
+
+/// Re-reads the Codex `config.toml` without restarting the server.
+async fn reload(State(state): State<AppState>) -> Result<Json<ReloadOutcome>, ApiError> {
+    let outcome = state.reload().await?;
+    info!(
+        cleared_configs = outcome.cleared_configs,
+        web_search_enabled = outcome.web_search_enabled,
+        "reloaded Codex config"
+    );
+    Ok(Json(outcome))
+}
+
+#[derive(Debug, Serialize)]
+struct GcOutcome {
+    evicted: Vec<String>,
+    cache_keys: Vec<String>,
+}
+
+/// Drops the cached per-model configs idle past `--cache-idle-ttl` now rather than at the next
+/// sweep.
+async fn gc(State(state): State<AppState>) -> Json<GcOutcome> {
+    let evicted = state.evict_idle_configs().await;
+    info!(evicted = evicted.len(), "collected idle cached configs");
+    Json(GcOutcome {
+        evicted,
+        cache_keys: state.engine().cache_keys().await,
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct AdminState {
+    cache_keys: Vec<String>,
+    stats: MetricsSnapshot,
+    web_search_enabled: bool,
+    config: ServeConfig,
+}
+
+async fn admin_state(State(state): State<AppState>) -> Json<AdminState> {
+    Json(AdminState {
+        cache_keys: state.engine().cache_keys().await,
+        stats: state.metrics().snapshot(),
+        web_search_enabled: state.web_search_enabled(),
+        config: state.config().clone(),
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct RequestList {
+    object: &'static str,
+    data: Vec<RequestSummary>,
+}
+
+/// The chat requests running now, oldest first.
+async fn requests(State(state): State<AppState>) -> Json<RequestList> {
+    Json(RequestList {
+        object: "list",
+        data: state.in_flight().list(),
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct CancelOutcome {
+    id: String,
+    cancelled: usize,
+}
+
+/// Cancels the running request with this request id: its upstream call is dropped and the client
+/// gets the usual error, as an in-stream event and `[DONE]` when it is streaming.
+async fn cancel_request(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<CancelOutcome>, ApiError> {
+    let cancelled = state.in_flight().cancel(&id);
+    if cancelled == 0 {
+        return Err(ApiError::not_found(format!(
+            "No request {id} is in flight."
+        )));
+    }
+    info!(request_id = %id, cancelled, "cancelled in-flight request");
+    Ok(Json(CancelOutcome { id, cancelled }))
+}
+
+#[derive(Debug, Serialize)]
+struct ModelSettingsResponse {
+    id: String,
+    profile: Option<String>,
+    #[serde(flatten)]
+    settings: ModelSettings,
+    request_defaults: RequestDefaults,
+}
+
+/// The serve-level settings every request for the model starts from.
+#[derive(Debug, Serialize)]
+struct RequestDefaults {
+    developer_prompt_mode: DeveloperPromptMode,
+    web_search_request: Option<bool>,
+    allow_per_request_web_search: bool,
+    strict_params: bool,
+    fail_on_warnings: bool,
+    tool_call_fallback: ToolCallFallback,
+    max_tools: usize,
+    sanitize_tool_names: bool,
+}
+
+impl RequestDefaults {
+    fn from_config(config: &ServeConfig) -> Self {
+        Self {
+            developer_prompt_mode: config.developer_prompt_mode,
+            web_search_request: config.web_search_request,
+            allow_per_request_web_search: config.allow_per_request_web_search,
+            strict_params: config.strict_params,
+            fail_on_warnings: config.fail_on_warnings,
+            tool_call_fallback: config.tool_call_fallback,
+            max_tools: config.max_tools,
+            sanitize_tool_names: config.sanitize_tool_names,
+        }
+    }
+}
+
+/// The effective settings of `id` as a chat request would resolve it: profile prefix or
+/// `X-Codex-Profile`, reasoning suffix, then the model's config. Models `/v1/models` would not
+/// list under any reasoning setting are 404s.
+async fn model_settings(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ModelSettingsResponse>, ApiError> {
+    let (profile, model) = resolve_profile(&headers, id.trim(), state.profiles())?;
+    let base = parse_reasoning_variant(&model).map_or_else(|| model.clone(), |(base, _)| base);
+    if !codex_model_ids(false, state.auth_mode()).contains(&base) {
+        return Err(ApiError::not_found(format!("Unknown model `{model}`")));
+    }
+    let settings = state
+        .engine()
+        .model_settings(&model, profile.as_deref())
+        .await?;
+    Ok(Json(ModelSettingsResponse {
+        id,
+        profile,
+        settings,
+        request_defaults: RequestDefaults::from_config(state.config()),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicBool, Ordering},
+        },
+        time::{Duration, Instant},
+    };
+
+    use async_trait::async_trait;
+    use axum::http::StatusCode;
+    use serde_json::{Value, json};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::{
+        openai::chat::PromptPayload,
+        server::{
+            executor::{ChatExecutor, MockChatExecutor, StreamingHandle},
+            latency::CompleteLatency,
+            loaded::ModelExpiry,
+            response::ChatCompletionResponse,
+            router,
+        },
+    };
+
+    /// Stands in for a `config.toml` edit: the first reload flips web search on and drops the
+    /// one cached config, which unloading `gpt-5` or leaving it idle drops too.
+    struct ReloadableExecutor {
+        reloaded: AtomicBool,
+        unloaded: AtomicBool,
+        loaded_at: Instant,
+    }
+
+    impl Default for ReloadableExecutor {
+        fn default() -> Self {
+            Self {
+                reloaded: AtomicBool::default(),
+                unloaded: AtomicBool::default(),
+                loaded_at: Instant::now(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl ChatExecutor for ReloadableExecutor {
+        async fn complete(
+            &self,
+            payload: PromptPayload,
+        ) -> Result<ChatCompletionResponse, ApiError> {
+            MockChatExecutor::new().complete(payload).await
+        }
+
+        async fn complete_observed(
+            &self,
+            payload: PromptPayload,
+            _latency: &CompleteLatency,
+        ) -> Result<ChatCompletionResponse, ApiError> {
+            self.complete(payload).await
+        }
+
+        async fn stream(&self, payload: PromptPayload) -> Result<StreamingHandle, ApiError> {
+            MockChatExecutor::new().stream(payload).await
+        }
+
+        async fn reload(&self) -> Result<ReloadOutcome, ApiError> {
+            let was_reloaded = self.reloaded.swap(true, Ordering::SeqCst);
+            Ok(ReloadOutcome {
+                cleared_configs: usize::from(!was_reloaded),
+                web_search_enabled: Some(true),
+            })
+        }
+
+        async fn cache_keys(&self) -> Vec<String> {
+            if self.reloaded.load(Ordering::SeqCst) || self.unloaded.load(Ordering::SeqCst) {
+                Vec::new()
+            } else {
+                vec!["work/gpt-5".to_string()]
+            }
+        }
+
+        async fn set_model_expiry(&self, model: &str, expiry: ModelExpiry) -> usize {
+            let unload = model == "gpt-5" && expiry == ModelExpiry::Unload;
+            usize::from(unload && !self.unloaded.swap(true, Ordering::SeqCst))
+        }
+
+        async fn evict_idle(&self, idle: Duration) -> Vec<String> {
+            let cached = !self.reloaded.load(Ordering::SeqCst);
+            if cached
+                && self.loaded_at.elapsed() >= idle
+                && !self.unloaded.swap(true, Ordering::SeqCst)
+            {
+                vec!["work/gpt-5".to_string()]
+            } else {
+                Vec::new()
+            }
+        }
+    }
+
+    async fn spawn(state: AppState) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind test listener");
+        let addr = listener.local_addr().expect("listener address");
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, router(state)).await;
+        });
+        format!("http://{addr}")
+    }
+
+    async fn get_json(url: String) -> Value {
+        reqwest::get(url)
+            .await
+            .expect("request")
+            .json()
+            .await
+            .expect("JSON body")
+    }
+
+    async fn post_json(url: String) -> Value {
+        reqwest::Client::new()
+            .post(url)
+            .send()
+            .await
+            .expect("request")
+            .json()
+            .await
+            .expect("JSON body")
+    }
+
+    #[tokio::test]
+    async fn admin_routes_are_hidden_by_default() {
+        let base = spawn(AppState::insecure_mock(true)).await;
+        let client = reqwest::Client::new();
+        let reload = client
+            .post(format!("{base}/admin/reload"))
+            .send()
+            .await
+            .expect("request");
+        assert_eq!(reload.status(), StatusCode::NOT_FOUND);
+        let state = reqwest::get(format!("{base}/admin/state"))
+            .await
+            .expect("request");
+        assert_eq!(state.status(), StatusCode::NOT_FOUND);
+        let settings = reqwest::get(format!("{base}/v1/models/gpt-5.1-codex/settings"))
+            .await
+            .expect("request");
+        assert_eq!(settings.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn model_settings_resolve_the_reasoning_suffix() {
+        let base = spawn(AppState::insecure_mock(true).with_admin(true)).await;
+        for id in ["gpt-5.1-codex-high", "gpt-5.1-codex:high"] {
+            let settings = get_json(format!("{base}/v1/models/{id}/settings")).await;
+            assert_eq!(settings["id"], id);
+            assert_eq!(settings["model"], "gpt-5.1-codex");
+            assert_eq!(settings["reasoning_effort"], "high");
+            assert_eq!(settings["request_defaults"]["strict_params"], false);
+        }
+        let plain = get_json(format!("{base}/v1/models/gpt-5.1-codex/settings")).await;
+        assert_eq!(plain["model"], "gpt-5.1-codex");
+        assert_eq!(plain["reasoning_effort"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn model_settings_of_unknown_models_are_not_found() {
+        let base = spawn(AppState::insecure_mock(true).with_admin(true)).await;
+        for id in ["gpt-unknown", "gpt-unknown-high"] {
+            let response = reqwest::get(format!("{base}/v1/models/{id}/settings"))
+                .await
+                .expect("request");
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{id}");
+        }
+    }
+
+    #[tokio::test]
+    async fn ollama_keep_alive_zero_unloads_the_model_and_its_configs() {
+        let state = AppState::insecure_mock(true)
+            .with_executor(Arc::new(ReloadableExecutor::default()))
+            .with_admin(true);
+        let base = spawn(state).await;
+        let client = reqwest::Client::new();
+        let generate = |keep_alive: Value| {
+            client
+                .post(format!("{base}/api/generate"))
+                .json(&json!({"model": "gpt-5", "keep_alive": keep_alive}))
+                .send()
+        };
+
+        let load: Value = generate(json!("1h"))
+            .await
+            .expect("load")
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(load["done_reason"], "load");
+        let ps = get_json(format!("{base}/api/ps")).await;
+        assert_eq!(ps["models"][0]["name"], "gpt-5");
+        let state = get_json(format!("{base}/admin/state")).await;
+        assert_eq!(state["cache_keys"], json!(["work/gpt-5"]));
+
+        let unload: Value = generate(json!(0))
+            .await
+            .expect("unload")
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(unload["done_reason"], "unload");
+        assert_eq!(unload["done"], true);
+        let ps = get_json(format!("{base}/api/ps")).await;
+        assert_eq!(ps["models"], json!([]));
+        let state = get_json(format!("{base}/admin/state")).await;
+        assert_eq!(state["cache_keys"], json!([]));
+
+        let invalid = generate(json!("soon")).await.expect("request");
+        assert_eq!(invalid.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn gc_reports_what_it_evicted_and_what_is_left() {
+        let state = AppState::insecure_mock(true)
+            .with_executor(Arc::new(ReloadableExecutor::default()))
+            .with_admin(true);
+        let base = spawn(state).await;
+        let gc: Value = reqwest::Client::new()
+            .post(format!("{base}/admin/gc"))
+            .send()
+            .await
+            .expect("gc request")
+            .json()
+            .await
+            .expect("gc body");
+        assert_eq!(gc, json!({"evicted": [], "cache_keys": ["work/gpt-5"]}));
+    }
+
+    #[tokio::test]
+    async fn gc_evicts_configs_idle_past_the_ttl() {
+        let ttl = Duration::from_millis(50);
+        let state = AppState::insecure_mock(true)
+            .with_config(ServeConfig::builder().cache_idle_ttl(ttl).build())
+            .with_executor(Arc::new(ReloadableExecutor::default()))
+            .with_admin(true);
+        let base = spawn(state.clone()).await;
+        tokio::time::sleep(ttl).await;
+
+        let gc = post_json(format!("{base}/admin/gc")).await;
+        assert_eq!(gc, json!({"evicted": ["work/gpt-5"], "cache_keys": []}));
+        assert_eq!(state.metrics().snapshot().config_evictions, 1);
+        let again = post_json(format!("{base}/admin/gc")).await;
+        assert_eq!(again, json!({"evicted": [], "cache_keys": []}));
+    }
+
+    #[tokio::test]
+    async fn reload_applies_to_later_requests() {
+        let state = AppState::insecure_mock(true)
+            .with_executor(Arc::new(ReloadableExecutor::default()))
+            .with_admin(true);
+        let base = spawn(state).await;
+
+        let before = get_json(format!("{base}/admin/state")).await;
+        assert_eq!(before["cache_keys"], json!(["work/gpt-5"]));
+        assert_eq!(before["web_search_enabled"], false);
+        assert!(before["config"]["max_body_size"].is_u64());
+        let health = get_json(format!("{base}/healthz")).await;
+        assert_eq!(health["config"]["web_search_request"], false);
+
+        let reload = reqwest::Client::new()
+            .post(format!("{base}/admin/reload"))
+            .send()
+            .await
+            .expect("reload request");
+        assert_eq!(reload.status(), StatusCode::OK);
+        let outcome: Value = reload.json().await.expect("reload body");
+        assert_eq!(outcome["cleared_configs"], 1);
+
+        let after = get_json(format!("{base}/admin/state")).await;
+        assert_eq!(after["cache_keys"], json!([]));
+        assert_eq!(after["web_search_enabled"], true);
+        let health = get_json(format!("{base}/healthz")).await;
+        assert_eq!(health["config"]["web_search_request"], true);
+    }
+}
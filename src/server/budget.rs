@@ -0,0 +1,324 @@
+//! `--max-tokens-per-request` and `--max-tokens-per-hour`: token spend guardrails against runaway
+//! clients such as an agent stuck in a loop. A request's size is estimated before it goes
+//! upstream; when it finishes (a stream, when it ends), its usage is booked against its client,
+//! or the estimate when the upstream reported none. A client whose bookings of the last hour
+//! reach the hourly budget is refused until the oldest ones age out.
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::persist::WallClock;
+use crate::{error::ApiError, openai::chat::PromptPayload, serve_config::ServeConfig};
+
+/// The sliding window of `--max-tokens-per-hour`.
+const WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// Tokens a request's estimate sets aside for the completion, which Codex cannot cap.
+pub const COMPLETION_RESERVE_TOKENS: u64 = 4_096;
+
+/// Bytes of prompt JSON counted as one token by the estimate; generous for English and code.
+const BYTES_PER_TOKEN: u64 = 4;
+
+/// Clients without bookings in the window are forgotten once this many have been seen.
+const MAX_TRACKED_CLIENTS: usize = 1024;
+
+/// What a request is expected to cost before it is sent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct Estimate {
+    pub(super) prompt: u64,
+    pub(super) completion: u64,
+}
+
+impl Estimate {
+    pub(super) fn of(payload: &PromptPayload) -> Self {
+        let bytes = json_len(&payload.prompt.input)
+            + json_len(&payload.prompt.tools)
+            + payload.system_prompt.as_deref().map_or(0, str::len);
+        Self {
+            prompt: tokens_for_bytes(bytes),
+            completion: COMPLETION_RESERVE_TOKENS,
+        }
+    }
+
+    pub(super) fn total(self) -> u64 {
+        self.prompt + self.completion
+    }
+}
+
+/// The estimate's token count for `bytes` of text.
+pub(super) fn tokens_for_bytes(bytes: usize) -> u64 {
+    (bytes as u64).div_ceil(BYTES_PER_TOKEN)
+}
+
+/// Serialized length of `value`, 0 if it does not serialize.
+fn json_len(value: &impl Serialize) -> usize {
+    serde_json::to_vec(value).map_or(0, |json| json.len())
+}
+
+/// Tokens booked per client over the last hour, shared by every clone of `AppState`.
+#[derive(Debug, Default)]
+pub struct TokenBudgets {
+    clients: Mutex<HashMap<String, VecDeque<Booking>>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Booking {
+    at: Instant,
+    tokens: u64,
+}
+
+/// A booking as `--state-file` keeps it, stamped in unix milliseconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct SavedBooking {
+    pub(super) at_ms: u64,
+    pub(super) tokens: u64,
+}
+
+/// One client's hourly budget, as `/stats/budget` reports it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct ClientBudget {
+    pub spent: u64,
+    pub remaining: u64,
+    /// Seconds until the oldest booking leaves the window.
+    pub resets_in_secs: u64,
+}
+
+impl TokenBudgets {
+    /// Refuses `client` while its bookings of the last hour add up to `per_hour` or more; the
+    /// error is how long until enough of them age out.
+    fn admit(&self, client: &str, per_hour: u64, now: Instant) -> Result<(), Duration> {
+        let mut clients = self.clients();
+        let Some(bookings) = clients.get_mut(client) else {
+            return Ok(());
+        };
+        expire(bookings, now);
+        let mut spent: u64 = bookings.iter().map(|booking| booking.tokens).sum();
+        if spent < per_hour {
+            return Ok(());
+        }
+        for booking in bookings.iter() {
+            spent -= booking.tokens;
+            if spent < per_hour {
+                return Err((booking.at + WINDOW).saturating_duration_since(now));
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn book(&self, client: &str, tokens: u64, now: Instant) {
+        let mut clients = self.clients();
+        if clients.len() >= MAX_TRACKED_CLIENTS && !clients.contains_key(client) {
+            clients.retain(|_, bookings| {
+                expire(bookings, now);
+                !bookings.is_empty()
+            });
+        }
+        let bookings = clients.entry(client.to_string()).or_default();
+        expire(bookings, now);
+        bookings.push_back(Booking { at: now, tokens });
+    }
+
+    /// Every client with bookings in the window, against the `per_hour` budget.
+    pub fn snapshot(&self, per_hour: u64) -> BTreeMap<String, ClientBudget> {
+        self.snapshot_at(per_hour, Instant::now())
+    }
+
+    fn snapshot_at(&self, per_hour: u64, now: Instant) -> BTreeMap<String, ClientBudget> {
+        let mut clients = self.clients();
+        clients
+            .iter_mut()
+            .filter_map(|(client, bookings)| {
+                expire(bookings, now);
+                let oldest = bookings.front()?;
+                let spent = bookings.iter().map(|booking| booking.tokens).sum();
+                let budget = ClientBudget {
+                    spent,
+                    remaining: per_hour.saturating_sub(spent),
+                    resets_in_secs: (oldest.at + WINDOW)
+                        .saturating_duration_since(now)
+                        .as_secs(),
+                };
+                Some((client.clone(), budget))
+            })
+            .collect()
+    }
+
+    /// The bookings still in the window, per client, for `--state-file`.
+    pub(super) fn save(&self, clock: &WallClock) -> BTreeMap<String, Vec<SavedBooking>> {
+        let mut clients = self.clients();
+        clients
+            .iter_mut()
+            .filter_map(|(client, bookings)| {
+                expire(bookings, clock.now());
+                let saved: Vec<SavedBooking> = bookings
+                    .iter()
+                    .map(|booking| SavedBooking {
+                        at_ms: clock.unix_ms(booking.at),
+                        tokens: booking.tokens,
+                    })
+                    .collect();
+                (!saved.is_empty()).then(|| (client.clone(), saved))
+            })
+            .collect()
+    }
+
+    /// Books `saved` again, leaving out what has aged out of the window since it was saved.
+    pub(super) fn restore(&self, saved: BTreeMap<String, Vec<SavedBooking>>, clock: &WallClock) {
+        let mut clients = self.clients();
+        for (client, saved) in saved {
+            let mut restored: Vec<Booking> = saved
+                .iter()
+                .filter_map(|booking| {
+                    let at = clock.instant(booking.at_ms, WINDOW)?;
+                    Some(Booking {
+                        at,
+                        tokens: booking.tokens,
+                    })
+                })
+                .collect();
+            if restored.is_empty() {
+                continue;
+            }
+            let bookings = clients.entry(client).or_default();
+            restored.extend(bookings.drain(..));
+            restored.sort_by_key(|booking| booking.at);
+            bookings.extend(restored);
+        }
+    }
+
+    fn clients(&self) -> MutexGuard<'_, HashMap<String, VecDeque<Booking>>> {
+        self.clients
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn expire(bookings: &mut VecDeque<Booking>, now: Instant) {
+    while bookings
+        .front()
+        .is_some_and(|booking| now.saturating_duration_since(booking.at) >= WINDOW)
+    {
+        bookings.pop_front();
+    }
+}
+
+/// Where a finished request's usage is booked; see [`super::metrics::InFlightGuard::with_budget`].
+pub(super) struct BudgetCharge {
+    budgets: Arc<TokenBudgets>,
+    client: String,
+    estimate: u64,
+}
+
+impl BudgetCharge {
+    /// Books `total_tokens`, or the request's estimate when the upstream reported no usage.
+    pub(super) fn settle(&self, total_tokens: u64) {
+        let tokens = if total_tokens == 0 {
+            self.estimate
+        } else {
+            total_tokens
+        };
+        self.budgets.book(&self.client, tokens, Instant::now());
+    }
+}
+
+/// Checks `payload` from `client` against the configured limits: `400` over the per-request
+/// bound, `429` once the client's hourly budget is spent. Returns where to book its usage when an
+/// hourly budget is set.
+pub(super) fn admit(
+    budgets: &Arc<TokenBudgets>,
+    config: &ServeConfig,
+    payload: &PromptPayload,
+    client: &str,
+) -> Result<Option<BudgetCharge>, ApiError> {
+    if config.max_tokens_per_request.is_none() && config.max_tokens_per_hour.is_none() {
+        return Ok(None);
+    }
+    let estimate = Estimate::of(payload);
+    if let Some(limit) = config.max_tokens_per_request
+        && estimate.total() > limit
+    {
+        return Err(ApiError::bad_request(format!(
+            "This request is estimated at {} tokens ({} prompt + {} reserved for the \
+             completion), over the server's --max-tokens-per-request limit of {limit}",
+            estimate.total(),
+            estimate.prompt,
+            estimate.completion
+        )));
+    }
+    let Some(per_hour) = config.max_tokens_per_hour else {
+        return Ok(None);
+    };
+    if let Err(retry_after) = budgets.admit(client, per_hour, Instant::now()) {
+        return Err(ApiError::rate_limited(
+            format!(
+                "Client `{client}` has used its --max-tokens-per-hour budget of {per_hour} \
+                 tokens; retry in {}s",
+                retry_after.as_secs().max(1)
+            ),
+            Some(retry_after),
+        ));
+    }
+    Ok(Some(BudgetCharge {
+        budgets: Arc::clone(budgets),
+        client: client.to_string(),
+        estimate: estimate.total(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn the_hourly_window_slides() {
+        let budgets = TokenBudgets::default();
+        let start = Instant::now();
+        assert_eq!(budgets.admit("a", 1000, start), Ok(()));
+        budgets.book("a", 600, start);
+        budgets.book("a", 300, start + 10 * MINUTE);
+        assert_eq!(budgets.admit("a", 1000, start + 20 * MINUTE), Ok(()));
+        budgets.book("a", 200, start + 20 * MINUTE);
+
+        // 1100 spent: the first booking has to age out to get back under 1000.
+        assert_eq!(
+            budgets.admit("a", 1000, start + 30 * MINUTE),
+            Err(30 * MINUTE)
+        );
+        assert_eq!(budgets.admit("b", 1000, start + 30 * MINUTE), Ok(()));
+        assert_eq!(
+            budgets.snapshot_at(1000, start + 30 * MINUTE)["a"],
+            ClientBudget {
+                spent: 1100,
+                remaining: 0,
+                resets_in_secs: 30 * 60,
+            }
+        );
+
+        assert_eq!(budgets.admit("a", 1000, start + 60 * MINUTE), Ok(()));
+        assert_eq!(
+            budgets.snapshot_at(1000, start + 60 * MINUTE)["a"].spent,
+            500
+        );
+        assert!(budgets.snapshot_at(1000, start + 90 * MINUTE).is_empty());
+    }
+
+    #[test]
+    fn a_client_needs_several_bookings_to_age_out() {
+        let budgets = TokenBudgets::default();
+        let start = Instant::now();
+        for minute in 0..4 {
+            budgets.book("a", 400, start + minute * MINUTE);
+        }
+        assert_eq!(
+            budgets.admit("a", 900, start + 5 * MINUTE),
+            Err(56 * MINUTE)
+        );
+    }
+}
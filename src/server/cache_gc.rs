@@ -0,0 +1,202 @@
+//! `--cache-idle-ttl`: a background task that drops the executor's cached per-model configs once
+//! no request has used them for the TTL, so a long-running server that saw many models and
+//! profiles gives the memory back. `/admin/gc` runs the same sweep on demand. Evictions are
+//! counted in the `/healthz` stats and logged at debug level.
+
+use std::{
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+use super::{SharedChatExecutor, metrics::ServerMetrics};
+
+/// Sweeps run this often relative to the TTL, so an entry goes at most half a TTL late.
+const SWEEPS_PER_TTL: u32 = 2;
+
+/// The idle-eviction task of a state, if one was started.
+#[derive(Default)]
+pub(super) struct CacheGc {
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl CacheGc {
+    /// Sweeps `engine`'s cache for entries idle past `ttl` until [`CacheGc::stop`], replacing
+    /// any task already running.
+    pub(super) fn start(
+        &self,
+        engine: SharedChatExecutor,
+        metrics: Arc<ServerMetrics>,
+        ttl: Duration,
+    ) {
+        let task = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(ttl / SWEEPS_PER_TTL).await;
+                sweep(&engine, &metrics, ttl).await;
+            }
+        });
+        if let Some(previous) = lock(&self.task).replace(task) {
+            previous.abort();
+        }
+    }
+
+    pub(super) fn stop(&self) {
+        if let Some(task) = lock(&self.task).take() {
+            task.abort();
+        }
+    }
+}
+
+impl Drop for CacheGc {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Drops the cached configs idle for `ttl` and books the evictions, returning their keys.
+pub(super) async fn sweep(
+    engine: &SharedChatExecutor,
+    metrics: &ServerMetrics,
+    ttl: Duration,
+) -> Vec<String> {
+    let evicted = engine.evict_idle(ttl).await;
+    if !evicted.is_empty() {
+        metrics.record_config_evictions(evicted.len());
+        debug!(
+            evicted = ?evicted,
+            idle_secs = ttl.as_secs(),
+            "evicted idle cached configs"
+        );
+    }
+    evicted
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Instant,
+    };
+
+    use async_trait::async_trait;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        ServeConfig,
+        error::ApiError,
+        openai::chat::{ChatCompletionRequest, PromptPayload},
+        server::{
+            AppState,
+            executor::{ChatExecutor, MockChatExecutor, StreamingHandle},
+            latency::CompleteLatency,
+            response::ChatCompletionResponse,
+        },
+    };
+
+    /// Caches a "config" per requested model the way the real executor does, counting loads.
+    #[derive(Default)]
+    struct CachingExecutor {
+        used: Mutex<BTreeMap<String, Instant>>,
+        loads: AtomicUsize,
+    }
+
+    impl CachingExecutor {
+        fn load(&self, model: &str) {
+            let mut used = lock(&self.used);
+            if used.insert(model.to_string(), Instant::now()).is_none() {
+                self.loads.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    #[async_trait]
+    impl ChatExecutor for CachingExecutor {
+        async fn complete(
+            &self,
+            payload: PromptPayload,
+        ) -> Result<ChatCompletionResponse, ApiError> {
+            self.load(&payload.model);
+            MockChatExecutor::new().complete(payload).await
+        }
+
+        async fn complete_observed(
+            &self,
+            payload: PromptPayload,
+            _latency: &CompleteLatency,
+        ) -> Result<ChatCompletionResponse, ApiError> {
+            self.complete(payload).await
+        }
+
+        async fn stream(&self, payload: PromptPayload) -> Result<StreamingHandle, ApiError> {
+            self.load(&payload.model);
+            MockChatExecutor::new().stream(payload).await
+        }
+
+        async fn cache_keys(&self) -> Vec<String> {
+            lock(&self.used).keys().cloned().collect()
+        }
+
+        async fn evict_idle(&self, idle: Duration) -> Vec<String> {
+            let mut evicted = Vec::new();
+            lock(&self.used).retain(|model, used| {
+                let keep = used.elapsed() < idle;
+                if !keep {
+                    evicted.push(model.clone());
+                }
+                keep
+            });
+            evicted
+        }
+    }
+
+    fn payload(model: &str) -> PromptPayload {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": model,
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap();
+        request.into_prompt().unwrap()
+    }
+
+    #[tokio::test]
+    async fn idle_configs_are_evicted_and_reloaded_on_the_next_request() {
+        let executor = Arc::new(CachingExecutor::default());
+        let ttl = Duration::from_millis(100);
+        let state = AppState::insecure_mock(true)
+            .with_config(ServeConfig::builder().cache_idle_ttl(ttl).build())
+            .with_executor(executor.clone());
+
+        state.engine().complete(payload("gpt-5")).await.unwrap();
+        assert!(state.evict_idle_configs().await.is_empty());
+
+        state.start_cache_gc(ttl);
+        let cleared = tokio::time::timeout(Duration::from_secs(5), async {
+            while state.metrics().snapshot().config_evictions == 0 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await;
+        state.stop_cache_gc();
+        assert!(cleared.is_ok(), "the idle config should be evicted");
+        assert!(state.engine().cache_keys().await.is_empty());
+        assert_eq!(state.metrics().snapshot().config_evictions, 1);
+
+        state.engine().complete(payload("gpt-5")).await.unwrap();
+        assert_eq!(executor.loads.load(Ordering::SeqCst), 2);
+        assert_eq!(state.engine().cache_keys().await, vec!["gpt-5"]);
+
+        tokio::time::sleep(ttl).await;
+        assert_eq!(state.evict_idle_configs().await, vec!["gpt-5"]);
+        assert_eq!(state.metrics().snapshot().config_evictions, 2);
+    }
+}
@@ -0,0 +1,204 @@
+//! Which optional request parameters a Codex model family takes. Reasoning families (gpt-5, the
+//! o-series, the codex-tuned models) reject `temperature` and `top_p` upstream but take a
+//! reasoning effort; older chat families are the other way round. `verbosity` goes by the
+//! family's own flag. The table is derived from the model family Codex resolved for the request,
+//! so new models follow their family. What a model can do overall is advertised to clients as
+//! [`ModelCapabilities`].
+
+use std::{
+    collections::HashSet,
+    sync::{Mutex, OnceLock},
+};
+
+use codex_core::model_family::ModelFamily;
+use serde::Serialize;
+use tracing::warn;
+
+use super::executor::ModelInfo;
+use crate::{error::ApiError, openai::chat::PromptPayload};
+
+/// The `capabilities` extension of a `/v1/models` entry, for clients that decide from it whether
+/// to send images or tools. It is read from the same [`ModelInfo`] as Ollama's `/api/show`
+/// capabilities, so the two never disagree.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(super) struct ModelCapabilities {
+    pub(super) vision: bool,
+    pub(super) tools: bool,
+    pub(super) reasoning: bool,
+    pub(super) web_search: bool,
+    pub(super) context_window: Option<u64>,
+    pub(super) max_output_tokens: Option<u64>,
+}
+
+impl From<&ModelInfo> for ModelCapabilities {
+    fn from(info: &ModelInfo) -> Self {
+        Self {
+            vision: info.vision,
+            tools: info.tools,
+            reasoning: info.reasoning,
+            web_search: info.web_search,
+            context_window: info.context_window,
+            max_output_tokens: info.max_output_tokens,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct ParamSupport {
+    /// `temperature` and `top_p`.
+    pub(super) sampling: bool,
+    pub(super) reasoning_effort: bool,
+    pub(super) verbosity: bool,
+}
+
+impl ParamSupport {
+    pub(super) fn for_family(family: &ModelFamily) -> Self {
+        Self {
+            verbosity: family.support_verbosity,
+            ..Self::for_reasoning(family.supports_reasoning_summaries)
+        }
+    }
+
+    /// The table of a family that does (gpt-5) or does not (gpt-4.1) reason.
+    fn for_reasoning(reasoning: bool) -> Self {
+        Self {
+            sampling: !reasoning,
+            reasoning_effort: reasoning,
+            verbosity: reasoning,
+        }
+    }
+
+    /// Clears the parameters `family` does not take from `payload`, logging each
+    /// family/parameter pair once, or rejects the request naming the first one when `strict`.
+    pub(super) fn apply(
+        self,
+        payload: &mut PromptPayload,
+        family: &str,
+        strict: bool,
+    ) -> Result<(), ApiError> {
+        let model = payload.model.clone();
+        let unsupported = [
+            (
+                "temperature",
+                !self.sampling && payload.temperature.is_some(),
+            ),
+            ("top_p", !self.sampling && payload.top_p.is_some()),
+            (
+                "reasoning_effort",
+                !self.reasoning_effort && payload.reasoning_effort.is_some(),
+            ),
+            ("verbosity", !self.verbosity && payload.verbosity.is_some()),
+        ];
+        for (param, present) in unsupported {
+            if !present {
+                continue;
+            }
+            if strict {
+                return Err(ApiError::bad_request(format!(
+                    "`{param}` is not supported by model `{model}`"
+                )));
+            }
+            match param {
+                "temperature" => payload.temperature = None,
+                "top_p" => payload.top_p = None,
+                "verbosity" => payload.verbosity = None,
+                _ => payload.reasoning_effort = None,
+            }
+            payload.warnings.push(
+                "param_dropped",
+                format!("`{param}` is not supported by model `{model}` and was dropped"),
+            );
+            if first_drop(family, param) {
+                warn!(
+                    model = %model,
+                    family,
+                    param,
+                    "dropping a parameter this model family does not support; pass --strict-params to reject it instead"
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+fn first_drop(family: &str, param: &'static str) -> bool {
+    static WARNED: OnceLock<Mutex<HashSet<(String, &'static str)>>> = OnceLock::new();
+    WARNED
+        .get_or_init(Mutex::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .insert((family.to_string(), param))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::openai::chat::ChatCompletionRequest;
+    use axum::http::StatusCode;
+    use codex_protocol::config_types::{ReasoningEffort, Verbosity};
+
+    fn payload() -> PromptPayload {
+        let request: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "some-model",
+            "messages": [{"role": "user", "content": "hi"}],
+            "temperature": 0.2,
+            "top_p": 0.9,
+            "reasoning_effort": "high",
+            "verbosity": "low"
+        }))
+        .unwrap();
+        request.into_prompt().unwrap()
+    }
+
+    #[test]
+    fn sampling_family_keeps_sampling_and_drops_effort() {
+        let support = ParamSupport::for_reasoning(false);
+
+        let mut lenient = payload();
+        support.apply(&mut lenient, "gpt-4.1", false).unwrap();
+        assert_eq!(lenient.temperature, Some(0.2));
+        assert_eq!(lenient.top_p, Some(0.9));
+        assert_eq!(lenient.reasoning_effort, None);
+        assert_eq!(lenient.verbosity, None);
+        assert_eq!(
+            lenient.warnings.snapshot()[0].message,
+            "`reasoning_effort` is not supported by model `some-model` and was dropped"
+        );
+        assert_eq!(
+            lenient.warnings.snapshot()[1].message,
+            "`verbosity` is not supported by model `some-model` and was dropped"
+        );
+
+        let err = support.apply(&mut payload(), "gpt-4.1", true).unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            err.message(),
+            "`reasoning_effort` is not supported by model `some-model`"
+        );
+    }
+
+    #[test]
+    fn reasoning_family_drops_sampling_and_keeps_effort() {
+        let support = ParamSupport::for_reasoning(true);
+
+        let mut lenient = payload();
+        support.apply(&mut lenient, "gpt-5", false).unwrap();
+        assert_eq!(lenient.temperature, None);
+        assert_eq!(lenient.top_p, None);
+        assert_eq!(lenient.reasoning_effort, Some(ReasoningEffort::High));
+        assert_eq!(lenient.verbosity, Some(Verbosity::Low));
+
+        let err = support.apply(&mut payload(), "gpt-5", true).unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            err.message(),
+            "`temperature` is not supported by model `some-model`"
+        );
+
+        let mut effort_only = payload();
+        effort_only.temperature = None;
+        effort_only.top_p = None;
+        support.apply(&mut effort_only, "gpt-5", true).unwrap();
+        assert_eq!(effort_only.reasoning_effort, Some(ReasoningEffort::High));
+    }
+}
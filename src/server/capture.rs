@@ -0,0 +1,401 @@
+use std::{
+    io,
+    path::PathBuf,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::header::CONTENT_TYPE,
+    middleware::Next,
+    response::Response,
+};
+use codex_core::ToolSpec;
+use futures_util::StreamExt;
+use serde_json::{Value, json};
+use tokio::{fs, io::AsyncWriteExt, sync::mpsc};
+use tracing::warn;
+
+use super::{
+    middleware::RequestId, organization::OpenAiOrganization, redact::redact_json, state::AppState,
+};
+use crate::{clock::utc_date, openai::chat::PromptPayload};
+
+/// Captured exchanges waiting to be written; beyond this, new captures are dropped rather than
+/// slowing requests down.
+const CAPTURE_QUEUE_DEPTH: usize = 1024;
+
+/// Background JSONL writer for `--capture-dir`. Cloning is cheap; every clone feeds the same file.
+#[derive(Clone)]
+pub struct CaptureSink {
+    tx: mpsc::Sender<Value>,
+    max_body_bytes: usize,
+    redact: bool,
+}
+
+impl CaptureSink {
+    /// Creates `dir` and starts the writer task. Must be called inside a Tokio runtime.
+    pub fn spawn(dir: PathBuf, max_body_bytes: usize, redact: bool) -> io::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        let (tx, rx) = mpsc::channel(CAPTURE_QUEUE_DEPTH);
+        tokio::spawn(write_captures(dir, rx));
+        Ok(Self {
+            tx,
+            max_body_bytes,
+            redact,
+        })
+    }
+}
+
+/// Appends each capture to `capture-YYYY-MM-DD.jsonl` (UTC), starting a new file every day.
+async fn write_captures(dir: PathBuf, mut rx: mpsc::Receiver<Value>) {
+    let mut current: Option<(String, fs::File)> = None;
+    while let Some(record) = rx.recv().await {
+        let date = utc_date(unix_now());
+        if current
+            .as_ref()
+            .is_none_or(|(open_date, _)| *open_date != date)
+        {
+            let path = dir.join(format!("capture-{date}.jsonl"));
+            match fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .await
+            {
+                Ok(file) => current = Some((date, file)),
+                Err(err) => {
+                    warn!(path = %path.display(), "failed to open capture file: {err}");
+                    current = None;
+                    continue;
+                }
+            }
+        }
+        let Some((_, file)) = current.as_mut() else {
+            continue;
+        };
+        let mut line = record.to_string();
+        line.push('\n');
+        if let Err(err) = async {
+            file.write_all(line.as_bytes()).await?;
+            file.flush().await
+        }
+        .await
+        {
+            warn!("failed to write capture record: {err}");
+        }
+    }
+}
+
+/// One captured exchange. Clones live in the request extensions and in the body taps; the line is
+/// queued when the last one drops, i.e. after the response body (or SSE stream) has finished.
+#[derive(Clone)]
+pub(super) struct Capture(Arc<CaptureRecord>);
+
+struct CaptureRecord {
+    sink: CaptureSink,
+    request_id: String,
+    path: String,
+    timestamp: u64,
+    organization: Option<OpenAiOrganization>,
+    state: Mutex<CaptureState>,
+}
+
+#[derive(Default)]
+struct CaptureState {
+    status: Option<u16>,
+    response_is_sse: bool,
+    prompt: Option<Value>,
+    request: BodyTap,
+    response: BodyTap,
+}
+
+#[derive(Default)]
+struct BodyTap {
+    bytes: Vec<u8>,
+    truncated: bool,
+}
+
+impl BodyTap {
+    fn append(&mut self, chunk: &[u8], limit: usize) {
+        let room = limit.saturating_sub(self.bytes.len());
+        if chunk.len() > room {
+            self.truncated = true;
+        }
+        self.bytes
+            .extend_from_slice(&chunk[..chunk.len().min(room)]);
+    }
+}
+
+impl Capture {
+    /// Summarizes the normalized prompt that will be sent upstream.
+    pub(super) fn record_prompt(&self, payload: &PromptPayload) {
+        let tools: Vec<&str> = payload.prompt.tools.iter().map(tool_name).collect();
+        self.state().prompt = Some(json!({
+            "model": payload.model,
+            "profile": payload.profile,
+            "input_items": payload.prompt.input.len(),
+            "tools": tools,
+            "system_prompt": payload.system_prompt,
+            "first_user_message": payload.first_user_message,
+        }));
+    }
+
+    fn state(&self) -> MutexGuard<'_, CaptureState> {
+        self.0.state()
+    }
+
+    fn append_request(&self, chunk: &Bytes) {
+        let limit = self.0.sink.max_body_bytes;
+        self.state().request.append(chunk, limit);
+    }
+
+    fn append_response(&self, chunk: &Bytes) {
+        let limit = self.0.sink.max_body_bytes;
+        self.state().response.append(chunk, limit);
+    }
+}
+
+impl CaptureRecord {
+    fn state(&self) -> MutexGuard<'_, CaptureState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn to_json(&self) -> Value {
+        let state = self.state();
+        let response = if state.response_is_sse {
+            sse_chunks(&state.response.bytes)
+        } else {
+            body_json(&state.response.bytes)
+        };
+        let mut record = json!({
+            "timestamp": self.timestamp,
+            "request_id": self.request_id,
+            "path": self.path,
+            "status": state.status,
+            "request": body_json(&state.request.bytes),
+            "request_truncated": state.request.truncated,
+            "prompt": state.prompt,
+            "response": response,
+            "response_truncated": state.response.truncated,
+        });
+        if let Some(organization) = &self.organization {
+            record["openai_organization"] = json!(organization.organization);
+            record["openai_project"] = json!(organization.project);
+        }
+        if self.sink.redact {
+            redact_json(&mut record);
+        }
+        record
+    }
+}
+
+impl Drop for CaptureRecord {
+    fn drop(&mut self) {
+        let record = self.to_json();
+        if self.sink.tx.try_send(record).is_err() {
+            warn!(request_id = %self.request_id, "capture queue full; dropping record");
+        }
+    }
+}
+
+/// Parses a captured body as JSON, falling back to the (lossy) text for malformed payloads.
+fn body_json(bytes: &[u8]) -> Value {
+    serde_json::from_slice(bytes)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(bytes).into_owned()))
+}
+
+/// Splits a captured SSE body into its `data:` payloads.
+fn sse_chunks(bytes: &[u8]) -> Value {
+    let text = String::from_utf8_lossy(bytes);
+    let chunks = text
+        .split("\n\n")
+        .filter_map(|frame| {
+            let data: Vec<&str> = frame
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(str::trim_start)
+                .collect();
+            (!data.is_empty()).then(|| body_json(data.join("\n").as_bytes()))
+        })
+        .collect();
+    Value::Array(chunks)
+}
+
+fn tool_name(tool: &ToolSpec) -> &str {
+    match tool {
+        ToolSpec::Function(tool) => &tool.name,
+        ToolSpec::Freeform(tool) => &tool.name,
+        ToolSpec::LocalShell {} => "local_shell",
+        ToolSpec::WebSearch {} => "web_search",
+    }
+}
+
+/// Tees the chat request and response bodies into a [`Capture`] when `--capture-dir` is set.
+pub(super) async fn capture_exchange(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(sink) = state.capture() else {
+        return next.run(request).await;
+    };
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.clone())
+        .unwrap_or_default();
+    let capture = Capture(Arc::new(CaptureRecord {
+        sink: sink.clone(),
+        request_id,
+        path: request.uri().path().to_string(),
+        timestamp: unix_now(),
+        organization: request.extensions().get::<OpenAiOrganization>().cloned(),
+        state: Mutex::default(),
+    }));
+
+    let (mut parts, body) = request.into_parts();
+    parts.extensions.insert(capture.clone());
+    let tap = capture.clone();
+    let body = Body::from_stream(body.into_data_stream().inspect(move |chunk| {
+        if let Ok(bytes) = chunk {
+            tap.append_request(bytes);
+        }
+    }));
+    let response = next.run(Request::from_parts(parts, body)).await;
+
+    let is_sse = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/event-stream"));
+    {
+        let mut state = capture.state();
+        state.status = Some(response.status().as_u16());
+        state.response_is_sse = is_sse;
+    }
+
+    let (parts, body) = response.into_parts();
+    if is_sse {
+        let body = Body::from_stream(body.into_data_stream().inspect(move |chunk| {
+            if let Ok(bytes) = chunk {
+                capture.append_response(bytes);
+            }
+        }));
+        return Response::from_parts(parts, body);
+    }
+    // Non-streaming bodies are already in memory; buffer them so the framing is unchanged.
+    match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => {
+            capture.append_response(&bytes);
+            Response::from_parts(parts, Body::from(bytes))
+        }
+        Err(err) => {
+            warn!("failed to buffer response for capture: {err}");
+            Response::from_parts(parts, Body::empty())
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::router;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn sse_bodies_split_into_chunks() {
+        let chunks = sse_chunks(b"data: {\"a\":1}\n\ndata: [DONE]\n\n");
+        assert_eq!(chunks, json!([{"a": 1}, "[DONE]"]));
+    }
+
+    #[tokio::test]
+    async fn writes_one_line_per_chat_request() {
+        let dir =
+            std::env::temp_dir().join(format!("codex-serve-capture-{}", uuid::Uuid::new_v4()));
+        let sink = CaptureSink::spawn(dir.clone(), 64 * 1024, false).expect("capture sink");
+        let state = AppState::insecure_mock(true).with_capture(sink);
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind test listener");
+        let addr = listener.local_addr().expect("listener address");
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, router(state)).await;
+        });
+
+        let client = reqwest::Client::new();
+        let url = format!("http://{addr}/v1/chat/completions");
+        client
+            .post(&url)
+            .json(&json!({"model": "gpt-5", "messages": [{"role": "user", "content": "hi"}]}))
+            .send()
+            .await
+            .expect("chat request");
+        client
+            .post(&url)
+            .header("content-type", "application/json")
+            .body("{\"model\": ")
+            .send()
+            .await
+            .expect("malformed request");
+
+        let path = dir.join(format!("capture-{}.jsonl", utc_date(unix_now())));
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(2);
+        let lines = loop {
+            let contents = std::fs::read_to_string(&path).unwrap_or_default();
+            let lines: Vec<Value> = contents
+                .lines()
+                .map(|line| serde_json::from_str(line).expect("capture lines are JSON"))
+                .collect();
+            if lines.len() >= 2 {
+                break lines;
+            }
+            assert!(
+                tokio::time::Instant::now() < deadline,
+                "captures not written"
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+
+        assert_eq!(lines.len(), 2);
+        for line in &lines {
+            for key in [
+                "timestamp",
+                "request_id",
+                "request",
+                "prompt",
+                "response",
+                "status",
+            ] {
+                assert!(line.get(key).is_some(), "missing {key}: {line}");
+            }
+        }
+        let ok = lines
+            .iter()
+            .find(|line| line["status"] == 200)
+            .expect("200 capture");
+        assert_eq!(ok["request"]["model"], "gpt-5");
+        assert_eq!(ok["prompt"]["model"], "gpt-5");
+        assert_eq!(ok["response"]["object"], "chat.completion");
+        let bad = lines
+            .iter()
+            .find(|line| line["status"] == 400)
+            .expect("400 capture");
+        assert_eq!(bad["request"], "{\"model\": ");
+        assert_eq!(bad["response"]["error"]["code"], "BAD_REQUEST");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
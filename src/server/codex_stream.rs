@@ -0,0 +1,236 @@
+//! `POST /v1/codex/stream` (`--enable-codex-stream`): a Chat Completions request in, the raw
+//! Codex `ResponseEvent`s out as typed SSE events, for UIs that want what the chunk translation
+//! drops (web search progress, raw reasoning, whole output items). The request is converted like
+//! any chat request and the executor runs it unchanged; only the reply skips the translation.
+//!
+//! The event names and payloads follow codex-core's `ResponseEvent` and change when it does, so
+//! they carry no stability guarantee.
+
+use std::convert::Infallible;
+
+use axum::{
+    Extension,
+    extract::State,
+    http::HeaderMap,
+    response::{
+        IntoResponse, Response,
+        sse::{Event, Sse},
+    },
+};
+use codex_core::ResponseEvent;
+use futures_util::StreamExt;
+use serde_json::{Value, json};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{Instrument, Span, warn};
+
+use super::{
+    access_log::AccessLog,
+    conversion,
+    executor::{EventStream, classify_codex_error},
+    extract::ApiJson,
+    fairness::ClientId,
+    in_flight::TrackedRequest,
+    metrics::InFlightGuard,
+    middleware::current_request_id,
+    profiles::resolve_profile,
+    response::Usage,
+    state::AppState,
+};
+use crate::{
+    clock::LogContext,
+    error::ApiError,
+    openai::chat::{ChatCompletionRequest, PromptEndpoint, PromptPayload},
+    telemetry,
+};
+
+pub(super) async fn codex_stream(
+    State(state): State<AppState>,
+    access_log: Option<Extension<AccessLog>>,
+    client: Option<Extension<ClientId>>,
+    headers: HeaderMap,
+    ApiJson(mut request): ApiJson<ChatCompletionRequest>,
+) -> Result<Response, ApiError> {
+    state.ensure_authenticated()?;
+    let log_context = LogContext::current(&request.model);
+    let (profile, model) = resolve_profile(&headers, &request.model, state.profiles())?;
+    request.model = model;
+    // The events are always streamed, which also turns away `codex.samples`.
+    request.stream = true;
+    let mut payload = conversion::convert(
+        &state,
+        request,
+        state.config().conversion_options(PromptEndpoint::Chat),
+    )
+    .await?;
+    payload.profile = profile;
+    let warnings = payload.warnings.clone();
+    if state.config().fail_on_warnings {
+        warnings.reject_any()?;
+    }
+    let client = client.map(|Extension(client)| client);
+    let budget = state.admit_tokens(&payload, client.as_ref())?;
+    state.loaded_models().touch(&payload.model);
+    let access_log = access_log.map(|Extension(log)| log);
+    if let Some(log) = &access_log {
+        log.record_request(&payload.model, true);
+    }
+
+    let tracked = state.track_request(&payload.model, client.as_ref(), true);
+    let account = state.usage_account(&payload.model, client, access_log.clone());
+    let guard = state
+        .metrics()
+        .start_stream()
+        .with_usage_account(account)
+        .with_conversation(state.conversation_turn(&payload))
+        .with_budget(budget);
+    let upstream = telemetry::upstream_span(&payload.model, true);
+    let mut response = stream_events(state.clone(), payload, guard, tracked, access_log, upstream);
+    super::warnings::report(
+        state.config(),
+        &log_context,
+        "codex_stream.warnings",
+        &warnings,
+        &mut response,
+    );
+    Ok(response)
+}
+
+/// Forwards every upstream event from a spawned task, which stops as soon as the client
+/// disconnects. A failure, before or during the stream, is sent as an `error` event carrying the
+/// usual `{"error": ...}` body.
+fn stream_events(
+    state: AppState,
+    payload: PromptPayload,
+    guard: InFlightGuard,
+    tracked: TrackedRequest,
+    access_log: Option<AccessLog>,
+    upstream: Span,
+) -> Response {
+    let (tx, rx) = mpsc::channel::<Event>(32);
+    let request_id = current_request_id();
+
+    let task_log = access_log.clone();
+    let task = async move {
+        let forward = tracked.run(async {
+            let handle = state
+                .engine()
+                .stream(payload)
+                .await
+                .inspect_err(|err| state.note_upstream_error(err))?;
+            state.note_upstream_success();
+            forward_events(handle.stream, &tx).await
+        });
+        tokio::select! {
+            result = forward => match result {
+                Ok(usage) => {
+                    guard.record_usage(&usage);
+                    telemetry::record_usage(
+                        &Span::current(),
+                        usage.prompt_tokens,
+                        usage.completion_tokens,
+                    );
+                    if let Some(log) = &task_log {
+                        log.record_outcome(&usage, Some("stop"));
+                    }
+                }
+                Err(err) => {
+                    warn!("Codex event stream error: {err:?}");
+                    if let Some(log) = &task_log {
+                        log.record_finish_reason("error");
+                    }
+                    let body = err.into_body_json(request_id);
+                    let _ = tx.send(Event::default().event("error").data(body.to_string())).await;
+                }
+            },
+            _ = tx.closed() => {
+                if let Some(log) = &task_log {
+                    log.record_finish_reason("client_disconnected");
+                }
+            }
+        }
+    };
+    tokio::spawn(task.instrument(upstream));
+
+    let events = ReceiverStream::new(rx)
+        .inspect(move |_| {
+            if let Some(log) = &access_log {
+                log.mark_first_byte();
+            }
+        })
+        .map(Ok::<_, Infallible>);
+    Sse::new(events).into_response()
+}
+
+/// Sends each event as it arrives; returns the usage once Codex reports completion.
+async fn forward_events(
+    mut stream: EventStream,
+    tx: &mpsc::Sender<Event>,
+) -> Result<Usage, ApiError> {
+    let mut rate_limits = None;
+    while let Some(event) = stream.next().await {
+        let event = event.map_err(|err| {
+            classify_codex_error(&err, rate_limits.as_ref(), "Codex stream error")
+        })?;
+        let completed = match &event {
+            ResponseEvent::Completed { token_usage, .. } => {
+                Some(token_usage.clone().map(Usage::from).unwrap_or_default())
+            }
+            ResponseEvent::RateLimits(snapshot) => {
+                rate_limits = Some(snapshot.clone());
+                None
+            }
+            _ => None,
+        };
+        let (name, data) = event_data(&event);
+        if tx
+            .send(Event::default().event(name).data(data.to_string()))
+            .await
+            .is_err()
+        {
+            break;
+        }
+        if let Some(usage) = completed {
+            return Ok(usage);
+        }
+    }
+    Err(ApiError::internal(
+        "Codex stream ended before the response completed",
+    ))
+}
+
+/// The SSE event name and payload of `event`, every field included.
+fn event_data(event: &ResponseEvent) -> (&'static str, Value) {
+    match event {
+        ResponseEvent::Created => ("created", json!({})),
+        ResponseEvent::OutputItemAdded(item) => ("output_item.added", json!({ "item": item })),
+        ResponseEvent::OutputItemDone(item) => ("output_item.done", json!({ "item": item })),
+        ResponseEvent::OutputTextDelta(delta) => ("output_text.delta", json!({ "delta": delta })),
+        ResponseEvent::ReasoningSummaryDelta {
+            delta,
+            summary_index,
+        } => (
+            "reasoning.summary.delta",
+            json!({ "delta": delta, "summary_index": summary_index }),
+        ),
+        ResponseEvent::ReasoningSummaryPartAdded { summary_index } => (
+            "reasoning.summary.part_added",
+            json!({ "summary_index": summary_index }),
+        ),
+        ResponseEvent::ReasoningContentDelta {
+            delta,
+            content_index,
+        } => (
+            "reasoning.content.delta",
+            json!({ "delta": delta, "content_index": content_index }),
+        ),
+        ResponseEvent::RateLimits(snapshot) => ("rate_limits", json!(snapshot)),
+        ResponseEvent::Completed {
+            response_id,
+            token_usage,
+        } => (
+            "completed",
+            json!({ "response_id": response_id, "token_usage": token_usage }),
+        ),
+    }
+}
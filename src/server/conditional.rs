@@ -0,0 +1,104 @@
+//! Conditional requests for the metadata routes. The model list only changes with the server's
+//! flags and auth mode, so replies carry `Cache-Control: max-age=60` and an `ETag` hashed from the
+//! serialized body; a client that sends it back in `If-None-Match` gets an empty `304`. Hashing
+//! the body means any change to what is exposed changes the tag, with nothing to invalidate.
+
+use axum::{
+    body::Body,
+    http::{
+        HeaderMap, HeaderValue, StatusCode,
+        header::{CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+    },
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::ApiError;
+
+const CACHE_CONTROL_VALUE: HeaderValue = HeaderValue::from_static("max-age=60");
+
+/// `body` as JSON with cache headers, or a `304` when `request_headers` already hold its tag.
+pub(super) fn cached_json<T: Serialize>(request_headers: &HeaderMap, body: &T) -> Response {
+    let bytes = match serde_json::to_vec(body) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            return ApiError::internal(format!("failed to serialize response: {err}"))
+                .into_response();
+        }
+    };
+    let etag = entity_tag(&bytes);
+    let mut response = if matches_any(request_headers, &etag) {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        let mut response = Body::from(bytes).into_response();
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        response
+    };
+    let headers = response.headers_mut();
+    headers.insert(CACHE_CONTROL, CACHE_CONTROL_VALUE);
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        headers.insert(ETAG, value);
+    }
+    response
+}
+
+fn entity_tag(bytes: &[u8]) -> String {
+    let digest = format!("{:x}", Sha256::digest(bytes));
+    format!("\"{}\"", &digest[..32])
+}
+
+/// Whether any `If-None-Match` tag is `etag`, compared weakly as RFC 9110 asks for this header.
+fn matches_any(request_headers: &HeaderMap, etag: &str) -> bool {
+    request_headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn if_none_match(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(IF_NONE_MATCH, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn tags_follow_the_body() {
+        let body = json!({"data": ["gpt-5"]});
+        let first = cached_json(&HeaderMap::new(), &body);
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(first.headers()[CACHE_CONTROL], "max-age=60");
+        let etag = first.headers()[ETAG].to_str().unwrap().to_string();
+        assert_eq!(
+            cached_json(&HeaderMap::new(), &body).headers()[ETAG],
+            etag.as_str()
+        );
+        let other = cached_json(&HeaderMap::new(), &json!({"data": ["gpt-5", "o3"]}));
+        assert_ne!(other.headers()[ETAG], etag.as_str());
+
+        for header in [
+            etag.clone(),
+            format!("W/{etag}"),
+            format!("\"x\", {etag}"),
+            "*".into(),
+        ] {
+            let response = cached_json(&if_none_match(&header), &body);
+            assert_eq!(response.status(), StatusCode::NOT_MODIFIED, "{header}");
+            assert_eq!(response.headers()[ETAG], etag.as_str());
+        }
+        assert_eq!(
+            cached_json(&if_none_match("\"stale\""), &body).status(),
+            StatusCode::OK
+        );
+    }
+}
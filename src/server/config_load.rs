@@ -0,0 +1,177 @@
+//! Loading the Codex config with the overrides Codex Serve adds. A codex-core whose config schema
+//! does not know `features.web_search_request` refuses `--web-search-request`; rather than refuse
+//! to start, the server loads the config without it, turns web search off and says so in the logs
+//! and `/healthz`. `--strict-config` keeps the load failure fatal.
+
+use std::future::Future;
+
+use anyhow::Result;
+use toml::Value as TomlValue;
+use tracing::warn;
+
+/// The config key `--web-search-request` sets.
+const WEB_SEARCH_REQUEST_KEY: &str = "features.web_search_request";
+
+/// A loaded config, the overrides it was loaded with, and any override that had to be dropped.
+#[derive(Debug)]
+pub(super) struct LoadedConfig<C> {
+    pub(super) config: C,
+    /// What later per-model loads must pass too: without the dropped override, or they would
+    /// fail the same way.
+    pub(super) cli_overrides: Vec<(String, TomlValue)>,
+    /// Set when the `--web-search-request` override was ignored; web search is then off.
+    pub(super) warning: Option<String>,
+}
+
+/// Loads the config through `load` with `cli_overrides` plus the `--web-search-request` override.
+/// When only the override makes the load fail, and `strict` is off, the config is loaded again
+/// without it; any other failure is returned as it is.
+pub(super) async fn load_config<C, E, F, Fut>(
+    mut cli_overrides: Vec<(String, TomlValue)>,
+    web_search_request: Option<bool>,
+    strict: bool,
+    load: F,
+) -> Result<LoadedConfig<C>>
+where
+    F: Fn(Vec<(String, TomlValue)>) -> Fut,
+    Fut: Future<Output = Result<C, E>>,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let Some(flag) = web_search_request else {
+        let config = load(cli_overrides.clone()).await?;
+        return Ok(LoadedConfig {
+            config,
+            cli_overrides,
+            warning: None,
+        });
+    };
+    let base = cli_overrides.clone();
+    cli_overrides.push((WEB_SEARCH_REQUEST_KEY.to_string(), TomlValue::Boolean(flag)));
+    let err = match load(cli_overrides.clone()).await {
+        Ok(config) => {
+            return Ok(LoadedConfig {
+                config,
+                cli_overrides,
+                warning: None,
+            });
+        }
+        Err(err) if strict => return Err(err.into()),
+        Err(err) => err,
+    };
+    // Fails without the override too: the override was not the problem.
+    let config = load(base.clone()).await?;
+    let warning = format!(
+        "ignored the `{WEB_SEARCH_REQUEST_KEY} = {flag}` override from --web-search-request: \
+         the Codex config rejected it ({err}); web search is disabled. Upgrade Codex, or pass \
+         --strict-config to refuse to start instead"
+    );
+    warn!("{warning}");
+    Ok(LoadedConfig {
+        config,
+        cli_overrides: base,
+        warning: Some(warning),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, sync::Mutex};
+
+    use super::*;
+
+    /// Stands in for `Config::load_with_cli_overrides` on a Codex that does not know the web
+    /// search key, recording the overrides of every attempt.
+    struct OldCodex {
+        attempts: Mutex<Vec<Vec<String>>>,
+        broken: bool,
+    }
+
+    impl OldCodex {
+        fn new(broken: bool) -> Self {
+            Self {
+                attempts: Mutex::default(),
+                broken,
+            }
+        }
+
+        async fn load(&self, overrides: Vec<(String, TomlValue)>) -> io::Result<&'static str> {
+            let keys: Vec<String> = overrides.into_iter().map(|(key, _)| key).collect();
+            self.attempts.lock().unwrap().push(keys.clone());
+            if self.broken {
+                return Err(io::Error::other("config.toml: expected a table"));
+            }
+            match keys.iter().find(|key| key.starts_with("features.")) {
+                Some(key) => Err(io::Error::other(format!("unknown field `{key}`"))),
+                None => Ok("config"),
+            }
+        }
+
+        fn attempts(&self) -> Vec<Vec<String>> {
+            self.attempts.lock().unwrap().clone()
+        }
+    }
+
+    fn model_override() -> Vec<(String, TomlValue)> {
+        vec![("model".to_string(), TomlValue::String("gpt-5".to_string()))]
+    }
+
+    #[tokio::test]
+    async fn a_rejected_web_search_override_is_dropped_with_a_warning() {
+        let codex = OldCodex::new(false);
+        let loaded = load_config(model_override(), Some(true), false, |overrides| {
+            codex.load(overrides)
+        })
+        .await
+        .expect("startup goes on without the override");
+
+        assert_eq!(loaded.config, "config");
+        assert_eq!(loaded.cli_overrides, model_override());
+        assert_eq!(
+            loaded.warning.as_deref(),
+            Some(
+                "ignored the `features.web_search_request = true` override from \
+                 --web-search-request: the Codex config rejected it (unknown field \
+                 `features.web_search_request`); web search is disabled. Upgrade Codex, or pass \
+                 --strict-config to refuse to start instead"
+            )
+        );
+        assert_eq!(
+            codex.attempts(),
+            [
+                vec!["model".to_string(), WEB_SEARCH_REQUEST_KEY.to_string()],
+                vec!["model".to_string()],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn strict_config_keeps_the_failure() {
+        let codex = OldCodex::new(false);
+        let err = load_config(model_override(), Some(false), true, |overrides| {
+            codex.load(overrides)
+        })
+        .await
+        .expect_err("--strict-config refuses to start");
+        assert!(err.to_string().contains(WEB_SEARCH_REQUEST_KEY));
+        assert_eq!(codex.attempts().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn other_failures_are_not_blamed_on_the_override() {
+        let codex = OldCodex::new(true);
+        let err = load_config(Vec::new(), Some(true), false, |overrides| {
+            codex.load(overrides)
+        })
+        .await
+        .expect_err("a broken config still fails");
+        assert_eq!(err.to_string(), "config.toml: expected a table");
+
+        // Without the flag there is nothing to retry.
+        let codex = OldCodex::new(false);
+        let loaded = load_config(Vec::new(), None, false, |overrides| codex.load(overrides))
+            .await
+            .expect("loads");
+        assert!(loaded.warning.is_none());
+        assert_eq!(codex.attempts().len(), 1);
+    }
+}
@@ -0,0 +1,289 @@
+//! Prompt cache effectiveness per conversation, for `GET /stats/conversations`. Clients resend
+//! the whole history every turn, so a conversation is recognized by what all of its requests
+//! start with: the system prompt and the first user message. Only a hash of them is kept, in a
+//! bounded map whose entries expire after [`IDLE_TTL`] without a turn.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        Arc, Mutex, MutexGuard,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use axum::http::{HeaderName, HeaderValue};
+use codex_core::protocol::TokenUsage;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{persist::WallClock, response::Usage};
+use crate::openai::chat::PromptPayload;
+
+/// Set on non-streaming chat replies: Codex's raw token counts for the turn, including how many
+/// prompt tokens were served from the cache.
+pub const USAGE_HEADER: HeaderName = HeaderName::from_static("x-codex-serve-usage");
+
+/// How long a conversation is kept after its last turn.
+const IDLE_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// Conversations tracked at once; past this, the least recently active one is dropped.
+const MAX_CONVERSATIONS: usize = 1024;
+
+/// The hashed key of the conversation `payload` belongs to, if it has a first user message.
+pub(super) fn key(payload: &PromptPayload) -> Option<String> {
+    let first_user_message = payload.first_user_message.as_deref()?;
+    let mut hasher = Sha256::new();
+    hasher.update(payload.system_prompt.as_deref().unwrap_or_default());
+    hasher.update([0]);
+    hasher.update(first_user_message);
+    let digest = format!("{:x}", hasher.finalize());
+    Some(digest[..16].to_string())
+}
+
+/// The [`USAGE_HEADER`] value for a turn, when Codex reported raw counts.
+pub(super) fn usage_header(usage: &Usage) -> Option<HeaderValue> {
+    let raw = usage.codex.as_ref()?;
+    HeaderValue::from_str(&format!(
+        "input_tokens={}, cached_input_tokens={}, output_tokens={}",
+        raw.input_tokens, raw.cached_input_tokens, raw.output_tokens
+    ))
+    .ok()
+}
+
+#[derive(Default)]
+pub(super) struct Conversations {
+    entries: Mutex<HashMap<String, Entry>>,
+    /// Numbers turns, so recency does not depend on the clock's resolution.
+    turns_recorded: AtomicU64,
+}
+
+struct Entry {
+    turns: u64,
+    input_tokens: i64,
+    cached_input_tokens: i64,
+    output_tokens: i64,
+    last_turn_cached_tokens: i64,
+    last_seen: Instant,
+    last_turn: u64,
+}
+
+/// A conversation as `--state-file` keeps it, its last turn stamped in unix milliseconds.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct SavedConversation {
+    pub(super) turns: u64,
+    pub(super) input_tokens: i64,
+    pub(super) cached_input_tokens: i64,
+    pub(super) output_tokens: i64,
+    pub(super) last_turn_cached_tokens: i64,
+    pub(super) last_seen_ms: u64,
+}
+
+/// One conversation as `GET /stats/conversations` lists it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(super) struct ConversationStats {
+    pub(super) conversation: String,
+    pub(super) turns: u64,
+    /// Prompt tokens over all turns: uncached input plus cached input, as in `usage`.
+    pub(super) prompt_tokens: i64,
+    pub(super) cached_tokens: i64,
+    pub(super) output_tokens: i64,
+    /// Share of the prompt tokens that were served from the cache.
+    pub(super) cache_hit_ratio: f64,
+    pub(super) last_turn_cached_tokens: i64,
+    pub(super) idle_seconds: u64,
+}
+
+impl Conversations {
+    /// Adds one turn's counts to the conversation `key`.
+    pub(super) fn record(&self, key: &str, usage: &TokenUsage) {
+        let now = Instant::now();
+        let turn = self.turns_recorded.fetch_add(1, Ordering::Relaxed);
+        let mut entries = self.entries();
+        if !entries.contains_key(key) && entries.len() >= MAX_CONVERSATIONS {
+            entries.retain(|_, entry| now.duration_since(entry.last_seen) < IDLE_TTL);
+            if entries.len() >= MAX_CONVERSATIONS
+                && let Some(oldest) = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.last_turn)
+                    .map(|(key, _)| key.clone())
+            {
+                entries.remove(&oldest);
+            }
+        }
+        let entry = entries.entry(key.to_string()).or_insert(Entry {
+            turns: 0,
+            input_tokens: 0,
+            cached_input_tokens: 0,
+            output_tokens: 0,
+            last_turn_cached_tokens: 0,
+            last_seen: now,
+            last_turn: turn,
+        });
+        entry.turns += 1;
+        entry.input_tokens += usage.input_tokens;
+        entry.cached_input_tokens += usage.cached_input_tokens;
+        entry.output_tokens += usage.output_tokens;
+        entry.last_turn_cached_tokens = usage.cached_input_tokens;
+        entry.last_seen = now;
+        entry.last_turn = turn;
+    }
+
+    /// Conversations that have not expired, most recently active first.
+    pub(super) fn snapshot(&self) -> Vec<ConversationStats> {
+        let now = Instant::now();
+        let mut entries = self.entries();
+        entries.retain(|_, entry| now.duration_since(entry.last_seen) < IDLE_TTL);
+        let mut stats: Vec<(u64, ConversationStats)> = entries
+            .iter()
+            .map(|(key, entry)| (entry.last_turn, entry.stats(key, now)))
+            .collect();
+        stats.sort_by(|(a, _), (b, _)| b.cmp(a));
+        stats.into_iter().map(|(_, stats)| stats).collect()
+    }
+
+    /// The conversations that have not expired, for `--state-file`.
+    pub(super) fn save(&self, clock: &WallClock) -> BTreeMap<String, SavedConversation> {
+        self.entries()
+            .iter()
+            .filter(|(_, entry)| clock.now().duration_since(entry.last_seen) < IDLE_TTL)
+            .map(|(key, entry)| {
+                let saved = SavedConversation {
+                    turns: entry.turns,
+                    input_tokens: entry.input_tokens,
+                    cached_input_tokens: entry.cached_input_tokens,
+                    output_tokens: entry.output_tokens,
+                    last_turn_cached_tokens: entry.last_turn_cached_tokens,
+                    last_seen_ms: clock.unix_ms(entry.last_seen),
+                };
+                (key.clone(), saved)
+            })
+            .collect()
+    }
+
+    /// Tracks `saved` again, except conversations that have been idle too long since, and those
+    /// already tracked. The most recently active ones win when there are too many.
+    pub(super) fn restore(&self, saved: BTreeMap<String, SavedConversation>, clock: &WallClock) {
+        let mut saved: Vec<(String, SavedConversation)> = saved.into_iter().collect();
+        saved.sort_by_key(|(_, conversation)| conversation.last_seen_ms);
+        let mut entries = self.entries();
+        for (key, conversation) in saved {
+            let Some(last_seen) = clock.instant(conversation.last_seen_ms, IDLE_TTL) else {
+                continue;
+            };
+            if entries.contains_key(&key) {
+                continue;
+            }
+            if entries.len() >= MAX_CONVERSATIONS
+                && let Some(oldest) = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.last_turn)
+                    .map(|(key, _)| key.clone())
+            {
+                entries.remove(&oldest);
+            }
+            let turn = self.turns_recorded.fetch_add(1, Ordering::Relaxed);
+            entries.insert(
+                key,
+                Entry {
+                    turns: conversation.turns,
+                    input_tokens: conversation.input_tokens,
+                    cached_input_tokens: conversation.cached_input_tokens,
+                    output_tokens: conversation.output_tokens,
+                    last_turn_cached_tokens: conversation.last_turn_cached_tokens,
+                    last_seen,
+                    last_turn: turn,
+                },
+            );
+        }
+    }
+
+    fn entries(&self) -> MutexGuard<'_, HashMap<String, Entry>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Entry {
+    fn stats(&self, key: &str, now: Instant) -> ConversationStats {
+        let prompt_tokens = self.input_tokens + self.cached_input_tokens;
+        let cache_hit_ratio = if prompt_tokens > 0 {
+            self.cached_input_tokens as f64 / prompt_tokens as f64
+        } else {
+            0.0
+        };
+        ConversationStats {
+            conversation: key.to_string(),
+            turns: self.turns,
+            prompt_tokens,
+            cached_tokens: self.cached_input_tokens,
+            output_tokens: self.output_tokens,
+            cache_hit_ratio,
+            last_turn_cached_tokens: self.last_turn_cached_tokens,
+            idle_seconds: now.duration_since(self.last_seen).as_secs(),
+        }
+    }
+}
+
+/// Where a request's usage is booked when it finishes; see [`super::InFlightGuard`].
+#[derive(Clone)]
+pub(super) struct ConversationTurn {
+    pub(super) conversations: Arc<Conversations>,
+    pub(super) key: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(input: i64, cached: i64) -> TokenUsage {
+        TokenUsage {
+            input_tokens: input,
+            cached_input_tokens: cached,
+            output_tokens: 10,
+            reasoning_output_tokens: 0,
+            total_tokens: input + cached + 10,
+        }
+    }
+
+    #[test]
+    fn cache_hit_ratio_covers_every_turn() {
+        let conversations = Conversations::default();
+        // A cold first turn, then turns that reuse the growing prefix.
+        conversations.record("a", &usage(1000, 0));
+        conversations.record("a", &usage(200, 1000));
+        conversations.record("a", &usage(100, 1300));
+        conversations.record("b", &usage(0, 0));
+
+        let stats = conversations.snapshot();
+        let a = stats
+            .iter()
+            .find(|stats| stats.conversation == "a")
+            .unwrap();
+        assert_eq!(a.turns, 3);
+        assert_eq!(a.prompt_tokens, 3600);
+        assert_eq!(a.cached_tokens, 2300);
+        assert_eq!(a.output_tokens, 30);
+        assert_eq!(a.last_turn_cached_tokens, 1300);
+        assert!((a.cache_hit_ratio - 2300.0 / 3600.0).abs() < 1e-9);
+
+        let b = stats
+            .iter()
+            .find(|stats| stats.conversation == "b")
+            .unwrap();
+        assert_eq!(b.cache_hit_ratio, 0.0);
+        assert_eq!(stats[0].conversation, "b", "most recent first");
+    }
+
+    #[test]
+    fn oldest_conversation_makes_room() {
+        let conversations = Conversations::default();
+        for index in 0..=MAX_CONVERSATIONS {
+            conversations.record(&index.to_string(), &usage(1, 0));
+        }
+        let stats = conversations.snapshot();
+        assert_eq!(stats.len(), MAX_CONVERSATIONS);
+        assert!(stats.iter().all(|stats| stats.conversation != "0"));
+    }
+}
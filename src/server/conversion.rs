@@ -0,0 +1,243 @@
+//! Converting a request into a Codex prompt, which for a long conversation with many tool schemas
+//! is enough synchronous work to hold up the other requests on a runtime worker. Large requests
+//! are converted on the blocking pool instead, and every conversion's duration is logged and
+//! added to the `/stats/conversion` histogram. A conversion cannot be stopped once it runs, so
+//! the work is bounded up front: requests past the message, tool or byte caps are refused with a
+//! `400` before any of it.
+
+use std::time::Instant;
+
+use serde_json::Value;
+use tracing::debug;
+
+use super::AppState;
+use crate::{
+    error::ApiError,
+    openai::chat::{ChatCompletionRequest, ConversionOptions, PromptPayload},
+};
+
+/// Past any of these a request is converted on the blocking pool.
+const OFFLOAD_MESSAGES: usize = 100;
+const OFFLOAD_TOOLS: usize = 32;
+const OFFLOAD_BYTES: usize = 256 * 1024;
+
+/// Past any of these a request is refused outright.
+const MAX_MESSAGES: usize = 20_000;
+const MAX_TOOLS: usize = 1_000;
+const MAX_BYTES: usize = 16 * 1024 * 1024;
+
+/// Where a request is converted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Placement {
+    Inline,
+    Blocking,
+}
+
+/// Refuses requests too large to convert, and says where the rest should be converted.
+pub(super) fn placement(request: &ChatCompletionRequest) -> Result<Placement, ApiError> {
+    let messages = request.messages.len();
+    let tools = request.tools.len();
+    if messages > MAX_MESSAGES {
+        return Err(ApiError::bad_request(format!(
+            "messages: {messages} messages is more than the {MAX_MESSAGES} Codex Serve converts"
+        )));
+    }
+    if tools > MAX_TOOLS {
+        return Err(ApiError::bad_request(format!(
+            "tools: {tools} tools is more than the {MAX_TOOLS} Codex Serve converts"
+        )));
+    }
+    let bytes = text_bytes(request);
+    if bytes > MAX_BYTES {
+        return Err(ApiError::bad_request(format!(
+            "messages: {bytes} bytes of message text and tool schemas is more than the {MAX_BYTES} \
+             Codex Serve converts"
+        )));
+    }
+    if messages > OFFLOAD_MESSAGES || tools > OFFLOAD_TOOLS || bytes > OFFLOAD_BYTES {
+        Ok(Placement::Blocking)
+    } else {
+        Ok(Placement::Inline)
+    }
+}
+
+/// Converts `request` under `options`, off the runtime worker when it is large.
+pub(super) async fn convert(
+    state: &AppState,
+    request: ChatCompletionRequest,
+    options: ConversionOptions,
+) -> Result<PromptPayload, ApiError> {
+    let placement = placement(&request)?;
+    let messages = request.messages.len();
+    let tools = request.tools.len();
+    let started = Instant::now();
+    let payload = convert_at(request, options, placement).await;
+    let elapsed = started.elapsed();
+    state.metrics().record_conversion(elapsed);
+    debug!(
+        conversion_us = elapsed.as_micros() as u64,
+        offloaded = placement == Placement::Blocking,
+        messages,
+        tools,
+        "converted request to a Codex prompt"
+    );
+    payload
+}
+
+async fn convert_at(
+    request: ChatCompletionRequest,
+    options: ConversionOptions,
+    placement: Placement,
+) -> Result<PromptPayload, ApiError> {
+    match placement {
+        Placement::Inline => request.into_prompt_for(options),
+        Placement::Blocking => {
+            tokio::task::spawn_blocking(move || request.into_prompt_for(options))
+                .await
+                .map_err(|err| ApiError::internal(format!("request conversion failed: {err}")))?
+        }
+    }
+}
+
+/// The text the request carries, as a cheap measure of the conversion work ahead.
+fn text_bytes(request: &ChatCompletionRequest) -> usize {
+    let messages: usize = request
+        .messages
+        .iter()
+        .map(|message| value_bytes(&message.content))
+        .sum();
+    let tools: usize = request
+        .tools
+        .iter()
+        .filter_map(|tool| tool.function.as_ref()?.parameters.as_ref())
+        .map(value_bytes)
+        .sum();
+    messages + tools
+}
+
+fn value_bytes(value: &Value) -> usize {
+    match value {
+        Value::String(text) => text.len(),
+        Value::Array(items) => items.iter().map(value_bytes).sum(),
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, value)| key.len() + value_bytes(value))
+            .sum(),
+        Value::Null | Value::Bool(_) | Value::Number(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{openai::chat::PromptEndpoint, server::describe_input};
+
+    /// 300 messages and 80 tools with nested schemas.
+    fn large_request() -> ChatCompletionRequest {
+        let mut messages = vec![json!({"role": "system", "content": "You are a build bot."})];
+        for turn in 0..150 {
+            messages.push(
+                json!({"role": "user", "content": format!("step {turn}: {}", "x".repeat(200))}),
+            );
+            messages
+                .push(json!({"role": "assistant", "content": format!("done with step {turn}")}));
+        }
+        let tools: Vec<Value> = (0..80)
+            .map(|i| {
+                json!({
+                    "type": "function",
+                    "function": {
+                        "name": format!("tool_{i}"),
+                        "description": "Does one thing.",
+                        "parameters": {
+                            "type": "object",
+                            "properties": {
+                                "path": {"type": "string"},
+                                "options": {
+                                    "type": "object",
+                                    "properties": {"depth": {"type": "integer", "minimum": 0}}
+                                }
+                            },
+                            "required": ["path"]
+                        }
+                    }
+                })
+            })
+            .collect();
+        serde_json::from_value(json!({"model": "gpt-5", "messages": messages, "tools": tools}))
+            .unwrap()
+    }
+
+    #[test]
+    fn large_requests_are_offloaded_and_small_ones_are_not() {
+        assert!(matches!(
+            placement(&large_request()),
+            Ok(Placement::Blocking)
+        ));
+
+        let small: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "gpt-5",
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap();
+        assert!(matches!(placement(&small), Ok(Placement::Inline)));
+
+        let long_text: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "gpt-5",
+            "messages": [{"role": "user", "content": "x".repeat(OFFLOAD_BYTES + 1)}]
+        }))
+        .unwrap();
+        assert!(matches!(placement(&long_text), Ok(Placement::Blocking)));
+    }
+
+    #[tokio::test]
+    async fn offloaded_conversion_matches_the_inline_one() {
+        let state = AppState::insecure_mock(true);
+        let options = state.config().conversion_options(PromptEndpoint::Chat);
+        let inline = convert_at(large_request(), options, Placement::Inline)
+            .await
+            .unwrap();
+        let offloaded = convert(&state, large_request(), options).await.unwrap();
+
+        assert_eq!(
+            describe_input(&offloaded.prompt.input),
+            describe_input(&inline.prompt.input)
+        );
+        assert_eq!(
+            format!("{:?}", offloaded.prompt.tools),
+            format!("{:?}", inline.prompt.tools)
+        );
+        assert_eq!(offloaded.system_prompt, inline.system_prompt);
+        assert_eq!(offloaded.first_user_message, inline.first_user_message);
+        assert_eq!(state.metrics().conversion_times().count, 1);
+    }
+
+    #[test]
+    fn absurd_requests_are_refused() {
+        let messages: Vec<Value> = (0..=MAX_MESSAGES)
+            .map(|_| json!({"role": "user", "content": "hi"}))
+            .collect();
+        let request: ChatCompletionRequest =
+            serde_json::from_value(json!({"model": "gpt-5", "messages": messages})).unwrap();
+        match placement(&request) {
+            Err(ApiError::BadRequest(message)) => {
+                assert!(message.starts_with("messages: 20001 messages"), "{message}")
+            }
+            other => panic!("expected a bad request, got {other:?}"),
+        }
+
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "gpt-5",
+            "messages": [{"role": "user", "content": "x".repeat(MAX_BYTES + 1)}]
+        }))
+        .unwrap();
+        match placement(&request) {
+            Err(ApiError::BadRequest(message)) => {
+                assert!(message.contains("more than the 16777216"), "{message}")
+            }
+            other => panic!("expected a bad request, got {other:?}"),
+        }
+    }
+}
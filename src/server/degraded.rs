@@ -0,0 +1,175 @@
+//! Degraded startup (`--allow-degraded`): when Codex cannot be initialized, e.g. on a machine where
+//! `codex` never ran or with a broken `config.toml`, the server starts anyway so `/healthz` can say
+//! what is wrong. Chat routes answer 503 until `/admin/reload` initializes Codex successfully; from
+//! then on the recovered login and executor serve every request.
+
+use std::sync::{Arc, Mutex, OnceLock};
+
+use async_trait::async_trait;
+
+use super::{
+    executor::{
+        ChatExecutor, CompleteLatency, ModelSettings, PreparedPrompt, ReloadOutcome,
+        SharedChatExecutor, StreamingHandle, delegate_executor,
+    },
+    response::ChatCompletionResponse,
+    state::{AppState, AuthController, InitOptions},
+};
+use crate::{error::ApiError, openai::chat::PromptPayload, serve_config::DeveloperPromptMode};
+
+/// Why startup failed, and what replaced the missing pieces once a reload succeeded.
+pub struct DegradedStartup {
+    options: InitOptions,
+    error: Mutex<String>,
+    recovered: OnceLock<Recovered>,
+}
+
+struct Recovered {
+    auth: AuthController,
+    /// The recovered state's executor as built, without its wrappers: this state's own wrap it.
+    engine: SharedChatExecutor,
+}
+
+impl DegradedStartup {
+    pub(super) fn new(options: InitOptions, error: &anyhow::Error) -> Self {
+        Self {
+            options,
+            error: Mutex::new(format!("{error:#}")),
+            recovered: OnceLock::new(),
+        }
+    }
+
+    /// The login that took over after a successful reload.
+    pub(super) fn recovered_auth(&self) -> Option<&AuthController> {
+        self.recovered.get().map(|recovered| &recovered.auth)
+    }
+
+    /// The latest initialization error.
+    pub(super) fn error(&self) -> String {
+        self.error
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    pub(super) fn remediation(&self) -> &'static str {
+        if self.options.config.enable_admin {
+            "run `codex login` (or `codex` once) to create the Codex home and fix any \
+             `config.toml` errors, then POST /admin/reload or restart Codex Serve"
+        } else {
+            "run `codex login` (or `codex` once) to create the Codex home and fix any \
+             `config.toml` errors, then restart Codex Serve"
+        }
+    }
+
+    pub(super) fn unavailable(&self) -> ApiError {
+        ApiError::service_unavailable(format!(
+            "Codex Serve could not initialize Codex: {}; {}",
+            self.error(),
+            self.remediation()
+        ))
+    }
+
+    fn engine(&self) -> Option<&SharedChatExecutor> {
+        self.recovered.get().map(|recovered| &recovered.engine)
+    }
+}
+
+/// Executor of a degraded state: unavailable until a reload initializes Codex, then a pass-through.
+pub(super) struct DegradedExecutor(pub(super) Arc<DegradedStartup>);
+
+impl DegradedExecutor {
+    /// The recovered executor, or the startup standing in for it until a reload succeeds.
+    fn current(&self) -> &(dyn ChatExecutor + Send + Sync) {
+        match self.0.engine() {
+            Some(engine) => engine.as_ref(),
+            None => self.0.as_ref(),
+        }
+    }
+}
+
+delegate_executor!(DegradedExecutor, |this| this.current(), {
+    async fn complete(&self, payload: PromptPayload) -> Result<ChatCompletionResponse, ApiError> {
+        self.current().complete(payload).await
+    }
+
+    async fn complete_observed(
+        &self,
+        payload: PromptPayload,
+        latency: &CompleteLatency,
+    ) -> Result<ChatCompletionResponse, ApiError> {
+        self.current().complete_observed(payload, latency).await
+    }
+
+    async fn stream(&self, payload: PromptPayload) -> Result<StreamingHandle, ApiError> {
+        self.current().stream(payload).await
+    }
+});
+
+/// Stands in for the executor until Codex initializes: chat requests are refused, metadata routes
+/// keep serving the static model list, and a reload tries to initialize Codex again.
+#[async_trait]
+impl ChatExecutor for DegradedStartup {
+    async fn complete(&self, _payload: PromptPayload) -> Result<ChatCompletionResponse, ApiError> {
+        Err(self.unavailable())
+    }
+
+    async fn complete_observed(
+        &self,
+        _payload: PromptPayload,
+        _latency: &CompleteLatency,
+    ) -> Result<ChatCompletionResponse, ApiError> {
+        Err(self.unavailable())
+    }
+
+    async fn stream(&self, _payload: PromptPayload) -> Result<StreamingHandle, ApiError> {
+        Err(self.unavailable())
+    }
+
+    async fn prepare(
+        &self,
+        _payload: PromptPayload,
+        _mode: DeveloperPromptMode,
+    ) -> Result<PreparedPrompt, ApiError> {
+        Err(self.unavailable())
+    }
+
+    async fn reload(&self) -> Result<ReloadOutcome, ApiError> {
+        let mut options = self.options.clone();
+        // The retry must fail loudly rather than produce another degraded state.
+        options.config.allow_degraded = false;
+        match AppState::initialize_with(options).await {
+            Ok(state) => {
+                let web_search_enabled = state.web_search_enabled();
+                // A concurrent reload may have won; either recovered state is as good.
+                let _ = self.recovered.set(Recovered {
+                    auth: state.auth().clone(),
+                    engine: state.executor(),
+                });
+                Ok(ReloadOutcome {
+                    cleared_configs: 0,
+                    web_search_enabled: Some(web_search_enabled),
+                })
+            }
+            Err(err) => {
+                *self
+                    .error
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()) = format!("{err:#}");
+                Err(self.unavailable())
+            }
+        }
+    }
+
+    async fn keepalive(&self) -> Result<(), ApiError> {
+        Err(self.unavailable())
+    }
+
+    async fn model_settings(
+        &self,
+        _model: &str,
+        _profile: Option<&str>,
+    ) -> Result<ModelSettings, ApiError> {
+        Err(self.unavailable())
+    }
+}
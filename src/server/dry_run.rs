@@ -0,0 +1,168 @@
+//! Dry runs of `/v1/chat/completions`, asked for with `x-codex-serve-dry-run: true` or
+//! `?dry_run=true`: the request goes through conversion and the executor's prompt preparation,
+//! and the prompt that would have been sent upstream comes back as a `codex.dry_run` object
+//! instead of a completion. `--disable-dry-run` refuses them.
+
+use axum::{
+    Json,
+    http::{HeaderMap, Uri},
+    response::{IntoResponse, Response},
+};
+use codex_core::{ContentItem, ResponseItem, compact::content_items_to_text};
+use serde_json::{Value, json};
+
+use super::{executor::PreparedPrompt, redact::redact_json, state::AppState};
+use crate::{error::ApiError, openai::chat::PromptPayload};
+
+pub const DRY_RUN_HEADER: &str = "x-codex-serve-dry-run";
+
+/// Characters of each text kept in the description; `chars` gives the full length.
+const PREVIEW_CHARS: usize = 2_000;
+
+/// Whether the request asked for a dry run, refusing it under `--disable-dry-run`.
+pub(super) fn requested(
+    state: &AppState,
+    headers: &HeaderMap,
+    uri: &Uri,
+) -> Result<bool, ApiError> {
+    let header = headers
+        .get(DRY_RUN_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(is_true);
+    let query = uri.query().is_some_and(|query| {
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .any(|(key, value)| key == "dry_run" && is_true(value))
+    });
+    let requested = header || query;
+    if requested && state.config().disable_dry_run {
+        return Err(ApiError::bad_request(
+            "dry runs are disabled on this server (--disable-dry-run)",
+        ));
+    }
+    Ok(requested)
+}
+
+fn is_true(value: &str) -> bool {
+    let value = value.trim();
+    value == "1" || value.eq_ignore_ascii_case("true")
+}
+
+/// Prepares `payload` as the executor would send it and describes the result, redacted under
+/// `--verbose-redact`.
+pub(super) async fn respond(
+    state: &AppState,
+    payload: PromptPayload,
+) -> Result<Response, ApiError> {
+    let requested_model = payload.model.clone();
+    let profile = payload.profile.clone();
+    let warnings = payload.warnings.clone();
+    let prepared = state
+        .engine()
+        .prepare(payload, state.config().developer_prompt_mode)
+        .await?;
+    let mut body = describe(&prepared);
+    body["requested_model"] = json!(requested_model);
+    body["profile"] = json!(profile);
+    body["warnings"] = json!(warnings.snapshot());
+    if state.config().verbose_redact {
+        redact_json(&mut body);
+    }
+    Ok(Json(body).into_response())
+}
+
+fn describe(prepared: &PreparedPrompt) -> Value {
+    let prompt = &prepared.prompt;
+    json!({
+        "object": "codex.dry_run",
+        "model": prepared.model,
+        "reasoning_effort": prepared.reasoning_effort,
+        "reasoning_summary": prepared.reasoning_summary,
+        "instructions": prompt.base_instructions_override,
+        "input": prompt.input.iter().map(describe_item).collect::<Vec<_>>(),
+        "tools": serde_json::to_value(&prompt.tools).unwrap_or_default(),
+    })
+}
+
+fn describe_item(item: &ResponseItem) -> Value {
+    match item {
+        ResponseItem::Message { role, content, .. } => {
+            let text = content_items_to_text(content).unwrap_or_default();
+            let images = content
+                .iter()
+                .filter(|item| matches!(item, ContentItem::InputImage { .. }))
+                .count();
+            let mut message = json!({
+                "type": "message",
+                "role": role,
+                "text": preview(&text),
+                "chars": text.chars().count(),
+            });
+            if images > 0 {
+                message["images"] = json!(images);
+            }
+            message
+        }
+        ResponseItem::FunctionCall {
+            name,
+            arguments,
+            call_id,
+            ..
+        } => json!({
+            "type": "function_call",
+            "call_id": call_id,
+            "name": name,
+            "arguments": preview(arguments),
+        }),
+        ResponseItem::FunctionCallOutput { call_id, output } => json!({
+            "type": "function_call_output",
+            "call_id": call_id,
+            "output": preview(&output.content),
+        }),
+        other => serde_json::to_value(other).unwrap_or_else(|_| json!({"type": "other"})),
+    }
+}
+
+fn preview(text: &str) -> String {
+    match text.char_indices().nth(PREVIEW_CHARS) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ServeConfig;
+
+    #[test]
+    fn the_header_or_the_query_asks_for_a_dry_run() {
+        let state = AppState::insecure_mock(true);
+        let mut headers = HeaderMap::new();
+        let plain: Uri = "/v1/chat/completions".parse().unwrap();
+        assert!(!requested(&state, &headers, &plain).unwrap());
+
+        let query: Uri = "/v1/chat/completions?stream=1&dry_run=true"
+            .parse()
+            .unwrap();
+        assert!(requested(&state, &headers, &query).unwrap());
+        let off: Uri = "/v1/chat/completions?dry_run=false".parse().unwrap();
+        assert!(!requested(&state, &headers, &off).unwrap());
+
+        headers.insert(DRY_RUN_HEADER, "True".parse().unwrap());
+        assert!(requested(&state, &headers, &plain).unwrap());
+
+        let locked = state.with_config(ServeConfig::builder().disable_dry_run(true).build());
+        assert!(requested(&locked, &headers, &plain).is_err());
+    }
+
+    #[test]
+    fn long_texts_are_cut_to_a_preview() {
+        let text = "é".repeat(PREVIEW_CHARS + 5);
+        let cut = preview(&text);
+        assert_eq!(cut.chars().count(), PREVIEW_CHARS + 1);
+        assert!(cut.ends_with('…'));
+        assert_eq!(preview("short"), "short");
+    }
+}
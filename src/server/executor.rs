@@ -1,38 +1,73 @@
 use std::collections::{BTreeMap, HashMap};
-use std::sync::Arc;
+use std::fmt;
+use std::future::Future;
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicUsize, Ordering},
+};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use arc_swap::ArcSwap;
 use async_trait::async_trait;
 use codex_app_server_protocol::AuthMode;
 use codex_core::{
-    ModelClient, Prompt, ResponseEvent, ResponseItem, ResponseStream,
+    ContentItem, ModelClient, Prompt, ResponseEvent, ResponseItem,
     auth::{AuthManager, CodexAuth},
     compact::content_items_to_text,
     config::{Config, ConfigOverrides},
-    protocol::SessionSource,
+    error::CodexErr,
+    model_family::{ModelFamily, find_family_for_model},
+    protocol::{RateLimitSnapshot, RateLimitWindow, SessionSource},
+    protocol_config_types::{ReasoningEffort, ReasoningSummary},
 };
 use codex_otel::otel_event_manager::OtelEventManager;
-use codex_protocol::ConversationId;
-use futures_util::StreamExt;
+use codex_protocol::{ConversationId, config_types::Verbosity};
+use futures_util::{StreamExt, stream::BoxStream};
+use serde::Serialize;
 use serde_json::{Value, json};
 use tokio::sync::RwLock;
 use toml::Value as TomlValue;
 use tracing::{error, info, warn};
 
-use super::parse_reasoning_variant;
+use super::{
+    capabilities::ParamSupport,
+    latency::CompleteLatency,
+    loaded::ModelExpiry,
+    parse_reasoning_variant,
+    tool_calls::{DEFAULT_MAX_TRACKED_TOOL_CALLS, Slot, ToolCallTracker},
+};
 use crate::{
     error::ApiError,
-    openai::chat::PromptPayload,
+    openai::{
+        chat::PromptPayload, render::describe_input, tool_names::ToolNames, warnings::Warnings,
+    },
     prompt::{ensure_web_search_tool, inject_developer_prompt},
-    serve_config::{developer_prompt_mode, verbose_logging_enabled},
+    serve_config::{DeveloperPromptMode, ServeConfig},
     server::response::{AssistantReasoning, ChatCompletionResponse, ToolCall, Usage},
 };
 
 pub type SharedChatExecutor = Arc<dyn ChatExecutor + Send + Sync>;
 
-/// Streaming response returned by the real executor.
+/// Upstream event stream; boxed so executors other than codex-core's client can produce one.
+pub type EventStream = BoxStream<'static, Result<ResponseEvent, CodexErr>>;
+
+/// Streaming response returned by an executor.
 pub struct StreamingHandle {
     pub response_model: String,
-    pub stream: ResponseStream,
+    /// The model the request runs on once aliases and reasoning suffixes are resolved, when the
+    /// executor knows it (`--report-model`).
+    pub resolved_model: Option<String>,
+    pub stream: EventStream,
+}
+
+/// A prompt as [`ChatExecutor::stream`] would send it upstream, for dry runs.
+#[derive(Clone, Debug)]
+pub struct PreparedPrompt {
+    /// The model sent upstream, without its reasoning suffix.
+    pub model: String,
+    pub prompt: Prompt,
+    pub reasoning_effort: Option<ReasoningEffort>,
+    pub reasoning_summary: Option<ReasoningSummary>,
 }
 
 /// Executes Codex prompts either to completion or as an SSE stream.
@@ -40,7 +75,302 @@ pub struct StreamingHandle {
 pub trait ChatExecutor {
     async fn complete(&self, payload: PromptPayload) -> Result<ChatCompletionResponse, ApiError>;
 
+    /// [`Self::complete`], handing every upstream stream read for the reply to `latency`, so a
+    /// reply resumed over two streams is booked as one request. Executors that do not answer
+    /// from upstream streams leave `latency` alone and run `complete`; wrappers hand it on.
+    async fn complete_observed(
+        &self,
+        payload: PromptPayload,
+        latency: &CompleteLatency,
+    ) -> Result<ChatCompletionResponse, ApiError>;
+
     async fn stream(&self, payload: PromptPayload) -> Result<StreamingHandle, ApiError>;
+
+    /// The prompt [`Self::stream`] would send for `payload` under the `--developer-prompt-mode`
+    /// `mode`, with the web search tool and the developer prompt added and the reasoning settings
+    /// resolved, without calling upstream. Executors without model configuration offer web
+    /// search only when the request asks for it.
+    async fn prepare(
+        &self,
+        payload: PromptPayload,
+        mode: DeveloperPromptMode,
+    ) -> Result<PreparedPrompt, ApiError> {
+        let PromptPayload {
+            model,
+            prompt,
+            system_prompt,
+            reasoning_effort,
+            reasoning_summary,
+            web_search,
+            ..
+        } = payload;
+        let prompt = upstream_prompt(
+            prompt,
+            web_search.unwrap_or(false),
+            system_prompt.as_deref(),
+            mode,
+        );
+        let (model, suffix_effort) = split_reasoning_variant(&model);
+        Ok(PreparedPrompt {
+            model,
+            prompt,
+            reasoning_effort: reasoning_effort.or(suffix_effort),
+            reasoning_summary,
+        })
+    }
+
+    /// Whether [`ChatExecutor::stream`] can stream at all. Streaming requests to an executor
+    /// that cannot are refused up front with a 503, or completed as JSON with `--stream-fallback`.
+    fn supports_streaming(&self) -> bool {
+        true
+    }
+
+    /// Re-reads Codex configuration from disk and drops anything cached from it. Executors that
+    /// do not load configuration have nothing to reload.
+    async fn reload(&self) -> Result<ReloadOutcome, ApiError> {
+        Ok(ReloadOutcome::default())
+    }
+
+    /// Keys of the cached per-model configurations (`profile/model` or `model`).
+    async fn cache_keys(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Applies an Ollama `keep_alive` to the cached configurations of `model` under every
+    /// profile: [`ModelExpiry::Unload`] drops them at once, a duration once it has passed. Returns
+    /// how many were dropped. Executors without a cache have nothing to drop.
+    async fn set_model_expiry(&self, _model: &str, _expiry: ModelExpiry) -> usize {
+        0
+    }
+
+    /// Drops the cached per-model configurations no request has used for `idle`, returning their
+    /// keys; a later request for one loads it again. Configurations an Ollama `keep_alive` set a
+    /// lifetime for are left to it. Executors without a cache have nothing to drop.
+    async fn evict_idle(&self, _idle: Duration) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Cheap upstream no-op run by `--keepalive-interval` so the first request after an idle
+    /// spell does not pay for credential and config loading. Executors without either have
+    /// nothing to keep warm.
+    async fn keepalive(&self) -> Result<(), ApiError> {
+        Ok(())
+    }
+
+    /// What `model` (under `profile`) can do, for the Ollama `/api/show` metadata and the
+    /// `/v1/models` capabilities. Executors without model configuration report the full Codex
+    /// feature set and no context window.
+    async fn model_info(
+        &self,
+        _model: &str,
+        _profile: Option<&str>,
+    ) -> Result<ModelInfo, ApiError> {
+        Ok(ModelInfo::default())
+    }
+
+    /// [`Self::model_info`] as far as it is known without loading `model`'s config, for the
+    /// `/v1/models` listing. `None` when nothing is known about the model.
+    async fn listed_model_info(&self, model: &str) -> Option<ModelInfo> {
+        self.model_info(model, None).await.ok()
+    }
+
+    /// The effective settings requests for `model` (under `profile`) run with, for the admin
+    /// `/v1/models/{id}/settings` route. Executors without model configuration only resolve the
+    /// reasoning suffix.
+    async fn model_settings(
+        &self,
+        model: &str,
+        _profile: Option<&str>,
+    ) -> Result<ModelSettings, ApiError> {
+        let (model, reasoning_effort) = split_reasoning_variant(model);
+        Ok(ModelSettings {
+            model,
+            reasoning_effort,
+            ..ModelSettings::default()
+        })
+    }
+}
+
+/// Implements [`ChatExecutor`] for an executor that wraps another: the methods in braces, at least
+/// `complete`, `complete_observed` and `stream`, as written, and every other method passed
+/// straight to the executor `|this| inner` names, so a method added to the trait cannot silently
+/// fall back to its default on a wrapper.
+macro_rules! delegate_executor {
+    ($wrapper:ty, |$this:ident| $inner:expr, { $($methods:tt)* }) => {
+        #[async_trait::async_trait]
+        impl $crate::server::ChatExecutor for $wrapper {
+            $($methods)*
+
+            async fn prepare(
+                &self,
+                payload: $crate::openai::chat::PromptPayload,
+                mode: $crate::serve_config::DeveloperPromptMode,
+            ) -> Result<$crate::server::PreparedPrompt, $crate::error::ApiError> {
+                let $this = self;
+                $inner.prepare(payload, mode).await
+            }
+
+            fn supports_streaming(&self) -> bool {
+                let $this = self;
+                $inner.supports_streaming()
+            }
+
+            async fn reload(&self) -> Result<$crate::server::ReloadOutcome, $crate::error::ApiError> {
+                let $this = self;
+                $inner.reload().await
+            }
+
+            async fn cache_keys(&self) -> Vec<String> {
+                let $this = self;
+                $inner.cache_keys().await
+            }
+
+            async fn set_model_expiry(&self, model: &str, expiry: $crate::server::ModelExpiry) -> usize {
+                let $this = self;
+                $inner.set_model_expiry(model, expiry).await
+            }
+
+            async fn evict_idle(&self, idle: std::time::Duration) -> Vec<String> {
+                let $this = self;
+                $inner.evict_idle(idle).await
+            }
+
+            async fn keepalive(&self) -> Result<(), $crate::error::ApiError> {
+                let $this = self;
+                $inner.keepalive().await
+            }
+
+            async fn model_info(
+                &self,
+                model: &str,
+                profile: Option<&str>,
+            ) -> Result<$crate::server::ModelInfo, $crate::error::ApiError> {
+                let $this = self;
+                $inner.model_info(model, profile).await
+            }
+
+            async fn listed_model_info(&self, model: &str) -> Option<$crate::server::ModelInfo> {
+                let $this = self;
+                $inner.listed_model_info(model).await
+            }
+
+            async fn model_settings(
+                &self,
+                model: &str,
+                profile: Option<&str>,
+            ) -> Result<$crate::server::ModelSettings, $crate::error::ApiError> {
+                let $this = self;
+                $inner.model_settings(model, profile).await
+            }
+        }
+    };
+}
+pub(super) use delegate_executor;
+
+/// The serving-relevant part of one resolved model's config, as reported by
+/// [`ChatExecutor::model_settings`]. Credentials and override values are never included.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ModelSettings {
+    /// The model sent upstream, without its reasoning suffix.
+    pub model: String,
+    pub reasoning_effort: Option<ReasoningEffort>,
+    pub reasoning_summary: Option<ReasoningSummary>,
+    pub verbosity: Option<Verbosity>,
+    pub model_family: Option<String>,
+    pub context_window: Option<u64>,
+    pub provider: Option<String>,
+    /// `tools_web_search_request` from the model's config.
+    pub web_search: Option<bool>,
+    /// Keys of the `-c key=value` overrides applied over `config.toml`; the values are left out
+    /// because they may hold secrets.
+    pub cli_overrides: Vec<String>,
+}
+
+/// `requested` split into its base model and the effort of its reasoning suffix, if any.
+fn split_reasoning_variant(requested: &str) -> (String, Option<ReasoningEffort>) {
+    parse_reasoning_variant(requested)
+        .map(|(base, effort)| (base, Some(effort)))
+        .unwrap_or_else(|| (requested.to_string(), None))
+}
+
+/// Capabilities of one resolved model, as reported by [`ChatExecutor::model_info`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelInfo {
+    /// Context window in tokens, when the model's config declares one.
+    pub context_window: Option<u64>,
+    /// Most tokens one reply may hold, when known.
+    pub max_output_tokens: Option<u64>,
+    /// Whether the model emits reasoning summaries.
+    pub reasoning: bool,
+    /// Whether the model accepts image input.
+    pub vision: bool,
+    /// Whether the model takes function tools.
+    pub tools: bool,
+    /// Whether the model's config turns on the web search tool.
+    pub web_search: bool,
+}
+
+impl Default for ModelInfo {
+    fn default() -> Self {
+        Self {
+            context_window: None,
+            max_output_tokens: None,
+            reasoning: true,
+            vision: true,
+            tools: true,
+            web_search: false,
+        }
+    }
+}
+
+impl ModelInfo {
+    fn for_config(config: &Config) -> Self {
+        Self {
+            context_window: config
+                .model_context_window
+                .and_then(|tokens| u64::try_from(tokens).ok()),
+            max_output_tokens: config
+                .model_max_output_tokens
+                .and_then(|tokens| u64::try_from(tokens).ok()),
+            web_search: config.tools_web_search_request,
+            ..Self::for_family(&config.model_family)
+        }
+    }
+
+    /// What any model of `family` can do. The token limits come from the model's config, so they
+    /// are left unknown.
+    fn for_family(family: &ModelFamily) -> Self {
+        Self {
+            context_window: None,
+            max_output_tokens: None,
+            reasoning: family.supports_reasoning_summaries,
+            vision: takes_images(family),
+            // Every model Codex serves takes function tools.
+            tools: true,
+            web_search: false,
+        }
+    }
+}
+
+/// Families whose models only take text input. Codex's `ModelFamily` does not record input
+/// modalities, so the text-only ones are listed here and every other family takes
+/// `input_image` content.
+const TEXT_ONLY_FAMILIES: &[&str] = &["gpt-oss", "gpt-3.5"];
+
+fn takes_images(family: &ModelFamily) -> bool {
+    !TEXT_ONLY_FAMILIES
+        .iter()
+        .any(|text_only| family.family.starts_with(text_only))
+}
+
+/// What [`ChatExecutor::reload`] changed.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ReloadOutcome {
+    /// Number of cached per-model configurations that were discarded.
+    pub cleared_configs: usize,
+    /// `tools_web_search_request` from the reloaded base config, when the executor has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub web_search_enabled: Option<bool>,
 }
 
 /// In-memory executor used by the test harness.
@@ -64,19 +394,591 @@ impl ChatExecutor for MockChatExecutor {
         Ok(ChatCompletionResponse::stub(payload.model, reply))
     }
 
+    async fn complete_observed(
+        &self,
+        payload: PromptPayload,
+        _latency: &CompleteLatency,
+    ) -> Result<ChatCompletionResponse, ApiError> {
+        self.complete(payload).await
+    }
+
     async fn stream(&self, _payload: PromptPayload) -> Result<StreamingHandle, ApiError> {
-        Err(ApiError::bad_request(
-            "Streaming is not available in test mode",
-        ))
+        Err(streaming_unsupported())
+    }
+
+    fn supports_streaming(&self) -> bool {
+        false
+    }
+}
+
+/// The error for a streaming request to an executor that cannot stream: a server capability
+/// (503), not a fault in the request.
+pub(super) fn streaming_unsupported() -> ApiError {
+    ApiError::service_unavailable(
+        "This Codex Serve executor cannot stream responses; retry with `stream: false`, or \
+         start the server with `--stream-fallback` to answer streaming requests with a complete \
+         JSON response",
+    )
+}
+
+/// Builds the events (or the up-front error) for one scripted call, plus an error to break the
+/// stream with after them.
+type Script = dyn Fn(&PromptPayload) -> Result<(Vec<ResponseEvent>, Option<CodexErr>), ApiError>
+    + Send
+    + Sync;
+
+/// Executor that replays canned upstream events, optionally pausing before each one, so tests can
+/// drive both handlers without a Codex backend.
+pub struct ScriptedChatExecutor {
+    script: Box<Script>,
+    delay: Duration,
+    handshake_delay: Duration,
+    stream_error: Option<Box<dyn Fn() -> CodexErr + Send + Sync>>,
+    keepalives: Arc<AtomicUsize>,
+}
+
+impl ScriptedChatExecutor {
+    /// Streams `deltas` as assistant text, then completes.
+    pub fn new<I, S>(deltas: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let deltas: Vec<String> = deltas.into_iter().map(Into::into).collect();
+        Self::from_events(move || {
+            deltas
+                .iter()
+                .cloned()
+                .map(ResponseEvent::OutputTextDelta)
+                .chain(std::iter::once(ResponseEvent::Completed {
+                    response_id: "resp_scripted".to_string(),
+                    token_usage: None,
+                }))
+                .collect()
+        })
+    }
+
+    /// Replays whatever `events` returns on every call; include a `Completed` event to finish.
+    pub fn from_events<F>(events: F) -> Self
+    where
+        F: Fn() -> Vec<ResponseEvent> + Send + Sync + 'static,
+    {
+        Self {
+            script: Box::new(move |_| Ok((events(), None))),
+            delay: Duration::ZERO,
+            handshake_delay: Duration::ZERO,
+            stream_error: None,
+            keepalives: Arc::default(),
+        }
+    }
+
+    /// Fails every call with the error `error` returns, before any event is produced.
+    pub fn failing<F>(error: F) -> Self
+    where
+        F: Fn() -> ApiError + Send + Sync + 'static,
+    {
+        Self {
+            script: Box::new(move |_| Err(error())),
+            delay: Duration::ZERO,
+            handshake_delay: Duration::ZERO,
+            stream_error: None,
+            keepalives: Arc::default(),
+        }
+    }
+
+    /// Plays one turn per request, in order, like an upstream that remembers the conversation.
+    /// A request whose prompt input differs from the turn's, or one past the last turn, fails
+    /// with a 500 whose message diffs the received items against the expected ones.
+    pub fn conversation(turns: Vec<ScriptedTurn>) -> Self {
+        let next_turn = AtomicUsize::new(0);
+        Self {
+            script: Box::new(move |payload| {
+                let index = next_turn.fetch_add(1, Ordering::SeqCst);
+                let turn = turns.get(index).ok_or_else(|| {
+                    ApiError::internal(format!(
+                        "unexpected request {}: the script has {} turns",
+                        index + 1,
+                        turns.len()
+                    ))
+                })?;
+                let received = describe_input(&payload.prompt.input);
+                if received != turn.expected_input {
+                    return Err(ApiError::internal(format!(
+                        "turn {} prompt does not match the script (- expected, + received):\n{}",
+                        index + 1,
+                        line_diff(&turn.expected_input, &received)
+                    )));
+                }
+                let error = turn.stream_error.as_ref().map(|error| error());
+                Ok(((turn.events)(), error))
+            }),
+            delay: Duration::ZERO,
+            handshake_delay: Duration::ZERO,
+            stream_error: None,
+            keepalives: Arc::default(),
+        }
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Holds `stream()` for `delay` before it returns, like a slow upstream connection.
+    pub fn with_handshake_delay(mut self, delay: Duration) -> Self {
+        self.handshake_delay = delay;
+        self
+    }
+
+    /// Fails the stream with `error` after the scripted events, like a connection dropped
+    /// mid-response; leave `Completed` out of the script for this. Applies to every call; see
+    /// [`ScriptedTurn::with_stream_error`] to break a single turn.
+    pub fn with_stream_error<F>(mut self, error: F) -> Self
+    where
+        F: Fn() -> CodexErr + Send + Sync + 'static,
+    {
+        self.stream_error = Some(Box::new(error));
+        self
+    }
+
+    /// Handle to the number of [`ChatExecutor::keepalive`] calls; stays valid after the executor
+    /// moves into a server.
+    pub fn keepalive_count(&self) -> Arc<AtomicUsize> {
+        Arc::clone(&self.keepalives)
+    }
+}
+
+#[async_trait]
+impl ChatExecutor for ScriptedChatExecutor {
+    async fn complete(&self, payload: PromptPayload) -> Result<ChatCompletionResponse, ApiError> {
+        complete_resuming(self, payload, DEFAULT_MAX_TRACKED_TOOL_CALLS, None).await
+    }
+
+    async fn complete_observed(
+        &self,
+        payload: PromptPayload,
+        latency: &CompleteLatency,
+    ) -> Result<ChatCompletionResponse, ApiError> {
+        complete_resuming(self, payload, DEFAULT_MAX_TRACKED_TOOL_CALLS, Some(latency)).await
+    }
+
+    async fn stream(&self, payload: PromptPayload) -> Result<StreamingHandle, ApiError> {
+        if !self.handshake_delay.is_zero() {
+            tokio::time::sleep(self.handshake_delay).await;
+        }
+        let delay = self.delay;
+        let (events, turn_error) = (self.script)(&payload)?;
+        let error = turn_error.or_else(|| self.stream_error.as_ref().map(|error| error()));
+        let stream = futures_util::stream::iter(events.into_iter().map(Ok).chain(error.map(Err)))
+            .then(move |event| async move {
+                if !delay.is_zero() {
+                    tokio::time::sleep(delay).await;
+                }
+                event
+            })
+            .boxed();
+        // Scripts run on the base model of a reasoning variant, like the real executor.
+        let (resolved_model, _) = split_reasoning_variant(&payload.model);
+        Ok(StreamingHandle {
+            response_model: payload.model,
+            resolved_model: Some(resolved_model),
+            stream,
+        })
+    }
+
+    async fn keepalive(&self) -> Result<(), ApiError> {
+        self.keepalives.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// One request of a [`ScriptedChatExecutor::conversation`]: the prompt input it must carry, as
+/// [`describe_input`] lines, and the events that answer it.
+pub struct ScriptedTurn {
+    expected_input: Vec<String>,
+    events: Box<dyn Fn() -> Vec<ResponseEvent> + Send + Sync>,
+    stream_error: Option<Box<dyn Fn() -> CodexErr + Send + Sync>>,
+}
+
+impl ScriptedTurn {
+    pub fn new<I, S, F>(expected_input: I, events: F) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+        F: Fn() -> Vec<ResponseEvent> + Send + Sync + 'static,
+    {
+        Self {
+            expected_input: expected_input.into_iter().map(Into::into).collect(),
+            events: Box::new(events),
+            stream_error: None,
+        }
+    }
+
+    /// Fails this turn's stream with `error` after its events, like a connection dropped
+    /// mid-response.
+    pub fn with_stream_error<F>(mut self, error: F) -> Self
+    where
+        F: Fn() -> CodexErr + Send + Sync + 'static,
+    {
+        self.stream_error = Some(Box::new(error));
+        self
+    }
+}
+
+/// Pairs lines by position; good enough for prompts of a few items.
+fn line_diff(expected: &[String], received: &[String]) -> String {
+    let mut diff = String::new();
+    for index in 0..expected.len().max(received.len()) {
+        match (expected.get(index), received.get(index)) {
+            (Some(expected), Some(received)) if expected == received => {
+                diff.push_str(&format!("  {expected}\n"));
+            }
+            (expected, received) => {
+                if let Some(expected) = expected {
+                    diff.push_str(&format!("- {expected}\n"));
+                }
+                if let Some(received) = received {
+                    diff.push_str(&format!("+ {received}\n"));
+                }
+            }
+        }
+    }
+    diff
+}
+
+/// Records every [`PromptPayload`] before handing it to an inner executor, so tests can assert on
+/// exactly what the HTTP layer produced.
+pub struct CapturingExecutor {
+    inner: SharedChatExecutor,
+    captured: Arc<Mutex<Vec<PromptPayload>>>,
+}
+
+impl CapturingExecutor {
+    pub fn new(inner: SharedChatExecutor) -> Self {
+        Self {
+            inner,
+            captured: Arc::default(),
+        }
+    }
+
+    /// Handle to the recorded payloads; stays valid after the executor moves into a server.
+    pub fn captured(&self) -> Arc<Mutex<Vec<PromptPayload>>> {
+        Arc::clone(&self.captured)
+    }
+
+    fn record(&self, payload: &PromptPayload) {
+        self.captured
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(payload.clone());
+    }
+}
+
+delegate_executor!(CapturingExecutor, |this| this.inner, {
+    async fn complete(&self, payload: PromptPayload) -> Result<ChatCompletionResponse, ApiError> {
+        self.record(&payload);
+        self.inner.complete(payload).await
+    }
+
+    async fn complete_observed(
+        &self,
+        payload: PromptPayload,
+        latency: &CompleteLatency,
+    ) -> Result<ChatCompletionResponse, ApiError> {
+        self.record(&payload);
+        self.inner.complete_observed(payload, latency).await
+    }
+
+    async fn stream(&self, payload: PromptPayload) -> Result<StreamingHandle, ApiError> {
+        self.record(&payload);
+        self.inner.stream(payload).await
+    }
+});
+
+/// Gives the model's tool calls back the names the client declared, for requests whose tool names
+/// `--sanitize-tool-names` rewrote. Wraps every executor the server runs, so each front-end only
+/// ever sees the declared names.
+pub(super) struct RestoreToolNames(pub(super) SharedChatExecutor);
+
+impl RestoreToolNames {
+    fn restore(item: &mut ResponseItem, names: &ToolNames) {
+        if let ResponseItem::FunctionCall { name, .. } = item {
+            *name = names.restore(name).to_string();
+        }
+    }
+}
+
+delegate_executor!(RestoreToolNames, |this| this.0, {
+    async fn complete(&self, payload: PromptPayload) -> Result<ChatCompletionResponse, ApiError> {
+        let names = payload.tool_names.clone();
+        let mut response = self.0.complete(payload).await?;
+        response.restore_tool_names(&names);
+        Ok(response)
+    }
+
+    async fn complete_observed(
+        &self,
+        payload: PromptPayload,
+        latency: &CompleteLatency,
+    ) -> Result<ChatCompletionResponse, ApiError> {
+        let names = payload.tool_names.clone();
+        let mut response = self.0.complete_observed(payload, latency).await?;
+        response.restore_tool_names(&names);
+        Ok(response)
+    }
+
+    async fn stream(&self, payload: PromptPayload) -> Result<StreamingHandle, ApiError> {
+        let names = payload.tool_names.clone();
+        let mut handle = self.0.stream(payload).await?;
+        if !names.is_empty() {
+            handle.stream = handle
+                .stream
+                .map(move |event| {
+                    event.map(|mut event| {
+                        if let ResponseEvent::OutputItemAdded(item)
+                        | ResponseEvent::OutputItemDone(item) = &mut event
+                        {
+                            Self::restore(item, &names);
+                        }
+                        event
+                    })
+                })
+                .boxed();
+        }
+        Ok(handle)
+    }
+});
+
+/// `--hide-reasoning`: asks upstream for no reasoning summary, whatever the payload says, and
+/// drops whatever reasoning still comes back, from replies and from every event of a stream,
+/// before any front-end, log or capture sees it. `wrap_engine` adds it while the flag is set.
+pub(super) struct HideReasoning(pub(super) SharedChatExecutor);
+
+impl HideReasoning {
+    fn is_reasoning(event: &ResponseEvent) -> bool {
+        matches!(
+            event,
+            ResponseEvent::ReasoningSummaryDelta { .. }
+                | ResponseEvent::ReasoningSummaryPartAdded { .. }
+                | ResponseEvent::ReasoningContentDelta { .. }
+                | ResponseEvent::OutputItemAdded(ResponseItem::Reasoning { .. })
+                | ResponseEvent::OutputItemDone(ResponseItem::Reasoning { .. })
+        )
+    }
+}
+
+delegate_executor!(HideReasoning, |this| this.0, {
+    async fn complete(
+        &self,
+        mut payload: PromptPayload,
+    ) -> Result<ChatCompletionResponse, ApiError> {
+        payload.reasoning_summary = Some(ReasoningSummary::None);
+        let mut response = self.0.complete(payload).await?;
+        response.hide_reasoning();
+        Ok(response)
+    }
+
+    async fn complete_observed(
+        &self,
+        mut payload: PromptPayload,
+        latency: &CompleteLatency,
+    ) -> Result<ChatCompletionResponse, ApiError> {
+        payload.reasoning_summary = Some(ReasoningSummary::None);
+        let mut response = self.0.complete_observed(payload, latency).await?;
+        response.hide_reasoning();
+        Ok(response)
+    }
+
+    async fn stream(&self, mut payload: PromptPayload) -> Result<StreamingHandle, ApiError> {
+        payload.reasoning_summary = Some(ReasoningSummary::None);
+        let mut handle = self.0.stream(payload).await?;
+        handle.stream = handle
+            .stream
+            .filter(|event| {
+                std::future::ready(!matches!(event, Ok(event) if Self::is_reasoning(event)))
+            })
+            .boxed();
+        Ok(handle)
+    }
+});
+
+/// Identifies one resolved Codex configuration: a requested model under an optional profile.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct ConfigKey {
+    profile: Option<String>,
+    model: String,
+}
+
+impl fmt::Display for ConfigKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.profile {
+            Some(profile) => write!(f, "{profile}/{}", self.model),
+            None => f.write_str(&self.model),
+        }
+    }
+}
+
+/// The model part of a [`ConfigKey`]: both spellings of a reasoning variant (`-high`, `:high`)
+/// share one cached config.
+fn cache_model_name(requested: &str) -> String {
+    let requested = requested.trim();
+    match split_reasoning_variant(requested) {
+        (model, Some(effort)) => format!("{model}-{effort}"),
+        _ => requested.to_string(),
+    }
+}
+
+/// Memoizes per-(profile, model) configs so each combination is only loaded from disk once, or
+/// again once an Ollama `keep_alive` has let it expire or it sat idle past `--cache-idle-ttl`.
+struct ConfigCache<T> {
+    entries: RwLock<HashMap<ConfigKey, CacheEntry<T>>>,
+}
+
+struct CacheEntry<T> {
+    value: Arc<T>,
+    /// Set by a `keep_alive` duration; entries without one stay until a reload.
+    expires_at: Option<Instant>,
+    /// Whether a `keep_alive` governs the entry, which idle eviction then leaves alone.
+    kept_alive: bool,
+    last_used: Mutex<Instant>,
+}
+
+impl<T> CacheEntry<T> {
+    fn is_live(&self, now: Instant) -> bool {
+        self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+
+    fn touch(&self, now: Instant) {
+        *self
+            .last_used
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = now;
+    }
+
+    fn is_idle(&self, idle: Duration, now: Instant) -> bool {
+        let last_used = *self
+            .last_used
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        !self.kept_alive && now.saturating_duration_since(last_used) >= idle
+    }
+}
+
+impl<T> Default for ConfigCache<T> {
+    fn default() -> Self {
+        Self {
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T> ConfigCache<T> {
+    async fn get_or_try_load<F, Fut>(&self, key: ConfigKey, load: F) -> Result<Arc<T>, ApiError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, ApiError>>,
+    {
+        let now = Instant::now();
+        if let Some(existing) = self.entries.read().await.get(&key)
+            && existing.is_live(now)
+        {
+            existing.touch(now);
+            return Ok(Arc::clone(&existing.value));
+        }
+        let value = Arc::new(load().await?);
+        let entry = CacheEntry {
+            value: Arc::clone(&value),
+            expires_at: None,
+            kept_alive: false,
+            last_used: Mutex::new(Instant::now()),
+        };
+        self.entries.write().await.insert(key, entry);
+        Ok(value)
+    }
+
+    /// Applies `expiry` to the entries of `model` under every profile, returning how many were
+    /// dropped.
+    async fn set_model_expiry(&self, model: &str, expiry: ModelExpiry) -> usize {
+        let now = Instant::now();
+        let mut entries = self.entries.write().await;
+        let before = entries.len();
+        entries.retain(|key, entry| {
+            if key.model == model {
+                entry.expires_at = match expiry {
+                    ModelExpiry::Unload => Some(now),
+                    ModelExpiry::For(duration) => Some(now + duration),
+                    ModelExpiry::Forever => None,
+                };
+                entry.kept_alive = true;
+            }
+            entry.is_live(now)
+        });
+        before - entries.len()
+    }
+
+    /// Drops the entries unused for `idle` as of `now`, and any a `keep_alive` let expire,
+    /// returning their keys. A request holding an entry's config keeps it until it finishes; one
+    /// resolving the key while it is dropped loads the config again and caches it afresh.
+    async fn evict_idle(&self, idle: Duration, now: Instant) -> Vec<String> {
+        let mut evicted = Vec::new();
+        self.entries.write().await.retain(|key, entry| {
+            let keep = entry.is_live(now) && !entry.is_idle(idle, now);
+            if !keep {
+                evicted.push(key.to_string());
+            }
+            keep
+        });
+        evicted.sort();
+        evicted
+    }
+
+    /// The live entry for `key`, without loading one.
+    async fn peek(&self, key: &ConfigKey) -> Option<Arc<T>> {
+        let entries = self.entries.read().await;
+        let entry = entries.get(key)?;
+        entry
+            .is_live(Instant::now())
+            .then(|| Arc::clone(&entry.value))
+    }
+
+    /// Empties the cache, returning how many entries were dropped.
+    async fn clear(&self) -> usize {
+        let mut entries = self.entries.write().await;
+        let cleared = entries.len();
+        entries.clear();
+        cleared
+    }
+
+    async fn keys(&self) -> Vec<String> {
+        let now = Instant::now();
+        let mut keys: Vec<String> = self
+            .entries
+            .read()
+            .await
+            .iter()
+            .filter(|(_, entry)| entry.is_live(now))
+            .map(|(key, _)| key.to_string())
+            .collect();
+        keys.sort();
+        keys
     }
 }
 
 /// Production executor backed by `codex-core::ModelClient`.
 pub struct RealChatExecutor {
-    config: Arc<Config>,
+    /// Base config for requests without overrides; swapped wholesale by [`ChatExecutor::reload`].
+    config: ArcSwap<Config>,
     auth_manager: Arc<AuthManager>,
-    config_cache: RwLock<HashMap<String, Arc<Config>>>,
+    config_cache: ConfigCache<Config>,
     cli_overrides: Vec<(String, TomlValue)>,
+    prompt_mode: DeveloperPromptMode,
+    /// Reject parameters the model family does not take instead of dropping them.
+    strict_params: bool,
+    fail_on_warnings: bool,
+    /// Let requests turn web search on when the model's config leaves it off.
+    allow_per_request_web_search: bool,
+    max_tracked_tool_calls: usize,
+    verbose: bool,
 }
 
 impl RealChatExecutor {
@@ -84,98 +986,282 @@ impl RealChatExecutor {
         config: Arc<Config>,
         auth_manager: Arc<AuthManager>,
         cli_overrides: Vec<(String, TomlValue)>,
+        serve_config: &ServeConfig,
     ) -> Self {
         Self {
-            config,
+            config: ArcSwap::new(config),
             auth_manager,
-            config_cache: RwLock::new(HashMap::new()),
+            config_cache: ConfigCache::default(),
             cli_overrides,
+            prompt_mode: serve_config.developer_prompt_mode,
+            strict_params: serve_config.strict_params,
+            fail_on_warnings: serve_config.fail_on_warnings,
+            allow_per_request_web_search: serve_config.allow_per_request_web_search,
+            max_tracked_tool_calls: serve_config.max_tracked_tool_calls,
+            verbose: serve_config.verbose,
         }
     }
 
-    async fn config_for_model(&self, requested: &str) -> Result<Arc<Config>, ApiError> {
+    async fn config_for_model(
+        &self,
+        requested: &str,
+        profile: Option<&str>,
+    ) -> Result<Arc<Config>, ApiError> {
         let requested = requested.trim();
         if requested.is_empty() {
             return Err(ApiError::bad_request("model must be provided"));
         }
 
-        let (model_override, reasoning_effort) = parse_reasoning_variant(requested)
-            .map(|(base, effort)| (base, Some(effort)))
-            .unwrap_or_else(|| (requested.to_string(), None));
-        if verbose_logging_enabled() && (model_override != requested || reasoning_effort.is_some())
-        {
+        let (model_override, reasoning_effort) = split_reasoning_variant(requested);
+        if self.verbose && (model_override != requested || reasoning_effort.is_some()) {
             info!(
                 requested_model = %requested,
                 resolved_model = %model_override,
                 reasoning_effort = ?reasoning_effort,
+                profile = ?profile,
                 "resolved overridden model for upstream request (upstream)"
             );
         }
-        let cache_key = requested.to_string();
 
-        if model_override == self.config.model && reasoning_effort.is_none() {
-            return Ok(Arc::clone(&self.config));
+        let base = self.config.load_full();
+        if profile.is_none() && model_override == base.model && reasoning_effort.is_none() {
+            return Ok(base);
         }
 
-        if let Some(existing) = self.config_cache.read().await.get(&cache_key) {
-            return Ok(Arc::clone(existing));
-        }
-
-        let overrides = ConfigOverrides {
-            model: Some(model_override.clone()),
-            ..ConfigOverrides::default()
+        let key = ConfigKey {
+            profile: profile.map(str::to_string),
+            model: cache_model_name(requested),
         };
+        self.config_cache
+            .get_or_try_load(key, || async {
+                let overrides = ConfigOverrides {
+                    model: Some(model_override.clone()),
+                    config_profile: profile.map(str::to_string),
+                    ..ConfigOverrides::default()
+                };
+
+                let mut config =
+                    Config::load_with_cli_overrides(self.cli_overrides.clone(), overrides)
+                        .await
+                        .map_err(|err| match profile {
+                            Some(profile) => ApiError::bad_request(format!(
+                                "Codex profile `{profile}` could not be loaded for model \
+                                 `{requested}`: {err}"
+                            )),
+                            None => ApiError::bad_request(format!(
+                                "model `{requested}` is not configured for Codex Serve. \
+                                 Use `codex config set model {requested}` to enable it."
+                            )),
+                        })?;
 
-        let mut config = Config::load_with_cli_overrides(self.cli_overrides.clone(), overrides)
+                if let Some(effort) = reasoning_effort {
+                    config.model_reasoning_effort = Some(effort);
+                }
+                Ok(config)
+            })
             .await
-            .map_err(|_| {
-                ApiError::bad_request(format!(
-                    "model `{requested}` is not configured for Codex Serve. \
-                     Use `codex config set model {requested}` to enable it."
-                ))
-            })?;
+    }
 
-        if let Some(effort) = reasoning_effort {
-            config.model_reasoning_effort = Some(effort);
+    fn auth_snapshot(&self) -> Option<CodexAuth> {
+        self.auth_manager.auth()
+    }
+
+    /// Loads `payload`'s config and builds the prompt it sends upstream under `mode`.
+    async fn upstream_request(
+        &self,
+        mut payload: PromptPayload,
+        mode: DeveloperPromptMode,
+    ) -> Result<UpstreamRequest, ApiError> {
+        let config = self
+            .config_for_model(&payload.model, payload.profile.as_deref())
+            .await?;
+        // Codex's `Prompt` has no sampling fields yet, so supported `temperature`/`top_p` values
+        // stop here too; the check still tells clients which ones the model would refuse.
+        ParamSupport::for_family(&config.model_family).apply(
+            &mut payload,
+            &config.model_family.family,
+            self.strict_params,
+        )?;
+        let web_search = web_search_allowed(
+            payload.web_search,
+            config.tools_web_search_request,
+            self.allow_per_request_web_search,
+            &payload.warnings,
+        );
+        if self.fail_on_warnings {
+            payload.warnings.reject_any()?;
         }
 
-        let config = Arc::new(config);
+        let PromptPayload {
+            model,
+            prompt,
+            system_prompt,
+            reasoning_effort,
+            reasoning_summary,
+            verbosity,
+            ..
+        } = payload;
+        let config = with_verbosity(config, verbosity);
 
-        let mut cache = self.config_cache.write().await;
-        cache.insert(cache_key, Arc::clone(&config));
-        Ok(config)
+        Ok(UpstreamRequest {
+            response_model: model,
+            prompt: upstream_prompt(prompt, web_search, system_prompt.as_deref(), mode),
+            reasoning_effort: reasoning_effort.or(config.model_reasoning_effort),
+            reasoning_summary: reasoning_summary.unwrap_or(config.model_reasoning_summary),
+            config,
+        })
     }
+}
 
-    fn auth_snapshot(&self) -> Option<CodexAuth> {
-        self.auth_manager.auth()
-    }
+/// What [`RealChatExecutor::stream`] sends upstream.
+struct UpstreamRequest {
+    config: Arc<Config>,
+    /// The model as the request named it, for the response.
+    response_model: String,
+    prompt: Prompt,
+    reasoning_effort: Option<ReasoningEffort>,
+    reasoning_summary: ReasoningSummary,
 }
 
 #[async_trait]
 impl ChatExecutor for RealChatExecutor {
     async fn complete(&self, payload: PromptPayload) -> Result<ChatCompletionResponse, ApiError> {
-        let handle = self.stream(payload).await?;
-        aggregate_response_stream(handle).await
+        complete_resuming(self, payload, self.max_tracked_tool_calls, None).await
     }
 
-    async fn stream(&self, payload: PromptPayload) -> Result<StreamingHandle, ApiError> {
-        let config = self.config_for_model(&payload.model).await?;
+    async fn complete_observed(
+        &self,
+        payload: PromptPayload,
+        latency: &CompleteLatency,
+    ) -> Result<ChatCompletionResponse, ApiError> {
+        complete_resuming(self, payload, self.max_tracked_tool_calls, Some(latency)).await
+    }
 
-        let PromptPayload {
-            model,
-            mut prompt,
-            system_prompt,
-            ..
-        } = payload;
+    async fn reload(&self) -> Result<ReloadOutcome, ApiError> {
+        let config =
+            Config::load_with_cli_overrides(self.cli_overrides.clone(), ConfigOverrides::default())
+                .await
+                .map_err(|err| {
+                    ApiError::internal(format!("failed to reload Codex config: {err}"))
+                })?;
+        let web_search_enabled = config.tools_web_search_request;
+        // Swap the base config before clearing so a racing request cannot repopulate the cache
+        // from the old one.
+        self.config.store(Arc::new(config));
+        let cleared_configs = self.config_cache.clear().await;
+        Ok(ReloadOutcome {
+            cleared_configs,
+            web_search_enabled: Some(web_search_enabled),
+        })
+    }
 
-        let has_web_search = ensure_web_search_tool(&mut prompt, config.tools_web_search_request);
-        let prompt_mode = developer_prompt_mode();
-        inject_developer_prompt(
-            &mut prompt,
-            has_web_search,
-            system_prompt.as_deref(),
-            prompt_mode,
-        );
+    async fn cache_keys(&self) -> Vec<String> {
+        self.config_cache.keys().await
+    }
+
+    async fn set_model_expiry(&self, model: &str, expiry: ModelExpiry) -> usize {
+        self.config_cache
+            .set_model_expiry(&cache_model_name(model), expiry)
+            .await
+    }
+
+    async fn evict_idle(&self, idle: Duration) -> Vec<String> {
+        self.config_cache.evict_idle(idle, Instant::now()).await
+    }
+
+    /// Re-reads the login (picking up a `codex login` made while idle), loads its tokens, and
+    /// checks that the default model's config still loads, without touching the cached configs.
+    async fn keepalive(&self) -> Result<(), ApiError> {
+        self.auth_manager.reload();
+        let auth = self.auth_snapshot().ok_or_else(|| {
+            ApiError::unauthorized("Codex Serve has no Codex login; run `codex login`")
+        })?;
+        if auth.mode == AuthMode::ChatGPT {
+            auth.get_token_data().await.map_err(|err| {
+                ApiError::token_expired(format!("Codex tokens could not be read: {err}"))
+            })?;
+        }
+        let model = self.config.load().model.clone();
+        let overrides = ConfigOverrides {
+            model: Some(model.clone()),
+            ..ConfigOverrides::default()
+        };
+        Config::load_with_cli_overrides(self.cli_overrides.clone(), overrides)
+            .await
+            .map_err(|err| {
+                ApiError::internal(format!("config for model `{model}` no longer loads: {err}"))
+            })?;
+        Ok(())
+    }
+
+    async fn model_info(&self, model: &str, profile: Option<&str>) -> Result<ModelInfo, ApiError> {
+        let config = self.config_for_model(model, profile).await?;
+        Ok(ModelInfo::for_config(&config))
+    }
+
+    /// Reads the model's config only if it is already loaded (the base model's always is, and
+    /// `--preload-models` loads the rest at startup); otherwise answers from the model family.
+    async fn listed_model_info(&self, model: &str) -> Option<ModelInfo> {
+        let base = self.config.load_full();
+        let (model_override, reasoning_effort) = split_reasoning_variant(model.trim());
+        if model_override == base.model && reasoning_effort.is_none() {
+            return Some(ModelInfo::for_config(&base));
+        }
+        let key = ConfigKey {
+            profile: None,
+            model: cache_model_name(model.trim()),
+        };
+        if let Some(config) = self.config_cache.peek(&key).await {
+            return Some(ModelInfo::for_config(&config));
+        }
+        let family = find_family_for_model(&model_override)?;
+        Some(ModelInfo {
+            web_search: base.tools_web_search_request,
+            ..ModelInfo::for_family(&family)
+        })
+    }
+
+    /// The config a request for `model` would run with, through the same cache, so the settings
+    /// shown are the ones requests get. A model whose config does not load is reported missing.
+    async fn model_settings(
+        &self,
+        model: &str,
+        profile: Option<&str>,
+    ) -> Result<ModelSettings, ApiError> {
+        let config = self
+            .config_for_model(model, profile)
+            .await
+            .map_err(|err| match err {
+                ApiError::BadRequest(message) => ApiError::not_found(message),
+                other => other,
+            })?;
+        Ok(ModelSettings {
+            model: config.model.clone(),
+            reasoning_effort: config.model_reasoning_effort,
+            reasoning_summary: Some(config.model_reasoning_summary),
+            verbosity: config.model_verbosity,
+            model_family: Some(config.model_family.family.clone()),
+            context_window: config
+                .model_context_window
+                .and_then(|tokens| u64::try_from(tokens).ok()),
+            provider: Some(config.model_provider.name.clone()),
+            web_search: Some(config.tools_web_search_request),
+            cli_overrides: self
+                .cli_overrides
+                .iter()
+                .map(|(key, _)| key.clone())
+                .collect(),
+        })
+    }
+
+    async fn stream(&self, payload: PromptPayload) -> Result<StreamingHandle, ApiError> {
+        let UpstreamRequest {
+            config,
+            response_model,
+            prompt,
+            reasoning_effort,
+            reasoning_summary,
+        } = self.upstream_request(payload, self.prompt_mode).await?;
 
         let conversation_id = ConversationId::default();
         let auth_snapshot = self.auth_snapshot();
@@ -200,8 +1286,8 @@ impl ChatExecutor for RealChatExecutor {
             Some(Arc::clone(&self.auth_manager)),
             otel,
             config.model_provider.clone(),
-            config.model_reasoning_effort,
-            config.model_reasoning_summary,
+            reasoning_effort,
+            reasoning_summary,
             conversation_id,
             SessionSource::Exec,
         );
@@ -212,112 +1298,385 @@ impl ChatExecutor for RealChatExecutor {
                 prompt = %prompt_debug_snapshot(&prompt),
                 "Codex upstream error: {err}"
             );
-            ApiError::internal(format!("Codex request failed: {err}"))
+            classify_codex_error(&err, None, "Codex request failed")
         })?;
 
         Ok(StreamingHandle {
-            response_model: model,
-            stream,
+            response_model,
+            resolved_model: Some(config.model.clone()),
+            stream: stream.boxed(),
+        })
+    }
+
+    async fn prepare(
+        &self,
+        payload: PromptPayload,
+        mode: DeveloperPromptMode,
+    ) -> Result<PreparedPrompt, ApiError> {
+        let request = self.upstream_request(payload, mode).await?;
+        Ok(PreparedPrompt {
+            model: request.config.model.clone(),
+            prompt: request.prompt,
+            reasoning_effort: request.reasoning_effort,
+            reasoning_summary: Some(request.reasoning_summary),
         })
     }
 }
 
-async fn aggregate_response_stream(
-    mut handle: StreamingHandle,
-) -> Result<ChatCompletionResponse, ApiError> {
-    let mut streamed_text = String::new();
-    let mut final_text: Option<String> = None;
-    let mut response_id: Option<String> = None;
-    let mut usage = Usage::default();
-    let mut tool_calls: Vec<ToolCall> = Vec::new();
-    let mut tool_call_indices: HashMap<String, usize> = HashMap::new();
-    let mut reasoning_summary_parts: BTreeMap<i64, String> = BTreeMap::new();
-
-    while let Some(event) = handle.stream.next().await {
-        let event =
-            event.map_err(|err| ApiError::internal(format!("Codex stream error: {err}")))?;
-        match event {
-            ResponseEvent::OutputTextDelta(delta) => streamed_text.push_str(&delta),
-            ResponseEvent::OutputItemAdded(item) | ResponseEvent::OutputItemDone(item) => {
-                if matches!(item, ResponseItem::Reasoning { .. }) {
-                    continue;
+/// `prompt` as it goes upstream: with the `web_search` tool when `web_search` allows it, and the
+/// developer prompt `mode` calls for.
+fn upstream_prompt(
+    mut prompt: Prompt,
+    web_search: bool,
+    system_prompt: Option<&str>,
+    mode: DeveloperPromptMode,
+) -> Prompt {
+    let has_web_search = ensure_web_search_tool(&mut prompt, web_search);
+    inject_developer_prompt(&mut prompt, has_web_search, system_prompt, mode);
+    prompt
+}
+
+/// `config` with the request's `verbosity`, if any, as its `model_verbosity`, which the model
+/// client reads. The copy is per request, so the cached config keeps its own setting.
+fn with_verbosity(config: Arc<Config>, verbosity: Option<Verbosity>) -> Arc<Config> {
+    match verbosity {
+        Some(verbosity) if config.model_verbosity != Some(verbosity) => {
+            let mut config = Config::clone(&config);
+            config.model_verbosity = Some(verbosity);
+            Arc::new(config)
+        }
+        _ => config,
+    }
+}
+
+/// Whether to offer the `web_search` tool: as the model's config says, unless the request turns
+/// it off, or turns it on and `--allow-per-request-web-search` lets it.
+fn web_search_allowed(
+    requested: Option<bool>,
+    configured: bool,
+    allow_enable: bool,
+    warnings: &Warnings,
+) -> bool {
+    match requested {
+        Some(false) => false,
+        Some(true) if !configured && !allow_enable => {
+            warnings.push(
+                "web_search_not_allowed",
+                "web search was requested but the server leaves it off; start it with \
+                 --allow-per-request-web-search to allow that",
+            );
+            false
+        }
+        Some(true) => true,
+        None => configured,
+    }
+}
+
+/// Continuation request sent after a stream broke; the partial reply precedes it as an assistant
+/// message.
+const RESUME_HINT: &str = "Your previous reply was cut off by a connection error. Continue it \
+                           from exactly where it stopped, without repeating any of it.";
+
+/// Shortest repeat [`stitch`] removes; shorter matches are more likely coincidence.
+const MIN_RESUME_OVERLAP: usize = 8;
+
+/// Longest repeat [`stitch`] looks for at the seam, short of the continuation repeating the whole
+/// partial reply.
+const MAX_RESUME_OVERLAP: usize = 4096;
+
+/// Runs `payload` to completion on `executor`. When the stream breaks on a transient error after
+/// some text but before any tool call, the request is sent once more with that text as a partial
+/// assistant reply, and the continuation is stitched on; the response is marked `resumed`. At
+/// most `max_tool_calls` distinct tool calls are tracked.
+async fn complete_resuming<E>(
+    executor: &E,
+    payload: PromptPayload,
+    max_tool_calls: usize,
+    latency: Option<&CompleteLatency>,
+) -> Result<ChatCompletionResponse, ApiError>
+where
+    E: ChatExecutor + ?Sized,
+{
+    let mut retry = payload.clone();
+    let mut handle = executor.stream(payload).await?;
+    if let Some(latency) = latency {
+        latency.observe(&mut handle);
+    }
+    let mut partial = Aggregate::new(max_tool_calls);
+    let err = match partial.read(&mut handle.stream).await {
+        Ok(()) => {
+            return Ok(partial.into_response(handle.response_model, handle.resolved_model));
+        }
+        Err(err) => err,
+    };
+    let text = partial.text();
+    if !is_transient(&err) || !partial.tool_calls.is_empty() || text.trim().is_empty() {
+        return Err(partial.classify(&err));
+    }
+    warn!(
+        model = handle.response_model.as_str(),
+        partial_bytes = text.len(),
+        "Codex stream broke mid-reply; resuming once: {err}"
+    );
+
+    retry.prompt.input.push(ResponseItem::Message {
+        id: None,
+        role: "assistant".to_string(),
+        content: vec![ContentItem::OutputText { text: text.clone() }],
+    });
+    retry.prompt.input.push(ResponseItem::Message {
+        id: None,
+        role: "developer".to_string(),
+        content: vec![ContentItem::InputText {
+            text: RESUME_HINT.to_string(),
+        }],
+    });
+    let mut handle = executor.stream(retry).await?;
+    if let Some(latency) = latency {
+        latency.observe(&mut handle);
+    }
+    let mut continuation = Aggregate::new(max_tool_calls);
+    if let Err(err) = continuation.read(&mut handle.stream).await {
+        return Err(continuation.classify(&err));
+    }
+
+    let stitched = stitch(&text, &continuation.text());
+    continuation.final_text = None;
+    continuation.streamed_text = stitched;
+    continuation.usage.add(&partial.usage);
+    let mut summary_parts: Vec<String> = partial.reasoning_summary_parts.into_values().collect();
+    summary_parts.extend(std::mem::take(&mut continuation.reasoning_summary_parts).into_values());
+    continuation.reasoning_summary_parts = (0..).zip(summary_parts).collect();
+    let mut response = continuation.into_response(handle.response_model, handle.resolved_model);
+    response.mark_resumed();
+    Ok(response)
+}
+
+/// Disconnects and timeouts, which a second request may get past; limits and auth failures
+/// would only fail again.
+fn is_transient(err: &CodexErr) -> bool {
+    matches!(
+        err,
+        CodexErr::Stream(_, None) | CodexErr::Timeout | CodexErr::Io(_)
+    )
+}
+
+/// `continuation` appended to `partial`, minus what it repeats of the end of `partial`.
+fn stitch(partial: &str, continuation: &str) -> String {
+    if let Some(rest) = continuation.strip_prefix(partial) {
+        return format!("{partial}{rest}");
+    }
+    let longest = partial
+        .len()
+        .min(continuation.len())
+        .min(MAX_RESUME_OVERLAP);
+    let overlap = (MIN_RESUME_OVERLAP..=longest)
+        .rev()
+        .filter(|&len| continuation.is_char_boundary(len))
+        .find(|&len| partial.ends_with(&continuation[..len]))
+        .unwrap_or(0);
+    format!("{partial}{}", &continuation[overlap..])
+}
+
+/// What a stream has produced so far, folded into one reply.
+struct Aggregate {
+    streamed_text: String,
+    final_text: Option<String>,
+    response_id: Option<String>,
+    usage: Usage,
+    tool_calls: Vec<ToolCall>,
+    tracker: ToolCallTracker,
+    reasoning_summary_parts: BTreeMap<i64, String>,
+    rate_limits: Option<RateLimitSnapshot>,
+}
+
+impl Aggregate {
+    /// Tracks at most `max_tool_calls` distinct calls (`--max-tracked-tool-calls`).
+    fn new(max_tool_calls: usize) -> Self {
+        Self {
+            streamed_text: String::new(),
+            final_text: None,
+            response_id: None,
+            usage: Usage::default(),
+            tool_calls: Vec::new(),
+            tracker: ToolCallTracker::new(max_tool_calls),
+            reasoning_summary_parts: BTreeMap::new(),
+            rate_limits: None,
+        }
+    }
+
+    /// Folds in events until `Completed` or the end of the stream, stopping at the first error.
+    async fn read(&mut self, stream: &mut EventStream) -> Result<(), CodexErr> {
+        while let Some(event) = stream.next().await {
+            match event? {
+                ResponseEvent::OutputTextDelta(delta) => self.streamed_text.push_str(&delta),
+                ResponseEvent::OutputItemAdded(item) => self.add_item(item, false),
+                ResponseEvent::OutputItemDone(item) => self.add_item(item, true),
+                ResponseEvent::ReasoningSummaryDelta {
+                    delta,
+                    summary_index,
+                } => {
+                    self.reasoning_summary_parts
+                        .entry(summary_index)
+                        .or_default()
+                        .push_str(&delta);
                 }
-                if let Some(text) = assistant_text_from_item(item.clone()) {
-                    final_text = Some(text);
+                ResponseEvent::ReasoningSummaryPartAdded { summary_index } => {
+                    self.reasoning_summary_parts
+                        .entry(summary_index)
+                        .or_default();
                 }
-                if let Some(call) = super::tool_call_from_item(&item) {
-                    if let Some(idx) = tool_call_indices.get(&call.id) {
-                        if let Some(existing) = tool_calls.get_mut(*idx) {
-                            *existing = call;
-                        }
-                    } else {
-                        let idx = tool_calls.len();
-                        tool_call_indices.insert(call.id.clone(), idx);
-                        tool_calls.push(call);
+                ResponseEvent::Completed {
+                    response_id: rid,
+                    token_usage,
+                } => {
+                    self.response_id = Some(rid);
+                    if let Some(tokens) = token_usage {
+                        self.usage = Usage::from(tokens);
                     }
+                    break;
                 }
-            }
-            ResponseEvent::ReasoningSummaryDelta {
-                delta,
-                summary_index,
-            } => {
-                reasoning_summary_parts
-                    .entry(summary_index)
-                    .or_default()
-                    .push_str(&delta);
-            }
-            ResponseEvent::ReasoningSummaryPartAdded { summary_index } => {
-                reasoning_summary_parts.entry(summary_index).or_default();
-            }
-            ResponseEvent::Completed {
-                response_id: rid,
-                token_usage,
-            } => {
-                response_id = Some(rid);
-                if let Some(tokens) = token_usage {
-                    usage = Usage::from(tokens);
+                ResponseEvent::RateLimits(snapshot) => self.rate_limits = Some(snapshot),
+                ResponseEvent::Created => {}
+                other => {
+                    warn!("Unhandled Codex response event in aggregation: {other:?}");
                 }
-                break;
             }
-            ResponseEvent::RateLimits(_) | ResponseEvent::Created => {}
-            other => {
-                warn!("Unhandled Codex response event in aggregation: {other:?}");
+        }
+        Ok(())
+    }
+
+    fn add_item(&mut self, item: ResponseItem, done: bool) {
+        if matches!(item, ResponseItem::Reasoning { .. }) {
+            return;
+        }
+        if let Some(text) = assistant_text_from_item(item.clone()) {
+            self.final_text = Some(text);
+        }
+        if let Some(call) = self.tracker.call_from_item(&item, done) {
+            match self.tracker.slot(&call.id, done) {
+                Slot::Tracked { first: true, .. } | Slot::Untracked { .. } => {
+                    self.tool_calls.push(call);
+                }
+                Slot::Tracked { index, .. } => {
+                    if let Some(existing) = self.tool_calls.get_mut(index) {
+                        *existing = call;
+                    }
+                }
+                Slot::Skipped => {}
             }
         }
     }
 
-    let response_id = response_id.unwrap_or_else(|| "resp_local".to_string());
-    let mut content = final_text.or_else(|| {
-        if streamed_text.trim().is_empty() {
-            None
+    fn classify(&self, err: &CodexErr) -> ApiError {
+        classify_codex_error(err, self.rate_limits.as_ref(), "Codex stream error")
+    }
+
+    /// The reply text so far: the finished message if one arrived, else the streamed deltas.
+    fn text(&self) -> String {
+        self.final_text
+            .clone()
+            .unwrap_or_else(|| self.streamed_text.clone())
+    }
+
+    fn into_response(
+        self,
+        model: String,
+        resolved_model: Option<String>,
+    ) -> ChatCompletionResponse {
+        let response_id = self.response_id.unwrap_or_else(|| "resp_local".to_string());
+        let streamed_text = self.streamed_text;
+        let mut content = self.final_text.or_else(|| {
+            if streamed_text.trim().is_empty() {
+                None
+            } else {
+                Some(streamed_text)
+            }
+        });
+        // ensure we don't return empty string content
+        if content.as_ref().is_some_and(|text| text.trim().is_empty()) {
+            content = None;
+        }
+
+        let finish_reason = if !self.tool_calls.is_empty() {
+            "tool_calls"
         } else {
-            Some(streamed_text)
+            "stop"
+        };
+        let reasoning_summary = self
+            .reasoning_summary_parts
+            .into_values()
+            .filter(|text| !text.trim().is_empty())
+            .collect::<Vec<_>>();
+        let reasoning = AssistantReasoning::from_summary_parts(reasoning_summary);
+
+        let mut response = ChatCompletionResponse::with_metadata(
+            model,
+            content,
+            self.tool_calls,
+            finish_reason,
+            response_id,
+            self.usage,
+            reasoning,
+        );
+        response.set_tool_call_overflow(self.tracker.overflow());
+        response.set_resolved_model(resolved_model);
+        response
+    }
+}
+
+/// Maps codex-core failures onto the HTTP error surface. Plan and usage limits become 429s so
+/// clients back off instead of hammering retries; everything else stays a 500.
+pub(super) fn classify_codex_error(
+    err: &CodexErr,
+    rate_limits: Option<&RateLimitSnapshot>,
+    context: &str,
+) -> ApiError {
+    match err {
+        CodexErr::UsageLimitReached(_) | CodexErr::QuotaExceeded => ApiError::rate_limited(
+            format!("{context}: {err}"),
+            rate_limits.and_then(retry_after_from_snapshot),
+        ),
+        CodexErr::RefreshTokenFailed(_) => ApiError::token_expired(format!(
+            "{context}: the Codex session expired and could not be refreshed. \
+             Re-authenticate with `codex login` and try again."
+        )),
+        CodexErr::Stream(_, Some(delay)) => {
+            ApiError::rate_limited(format!("{context}: {err}"), Some(*delay))
         }
-    });
-    // ensure we don't return empty string content
-    if content.as_ref().is_some_and(|text| text.trim().is_empty()) {
-        content = None;
+        _ => ApiError::internal(format!("{context}: {err}")),
     }
+}
 
-    let finish_reason = if !tool_calls.is_empty() {
-        "tool_calls"
-    } else {
-        "stop"
-    };
-    let reasoning_summary = reasoning_summary_parts
-        .into_values()
-        .filter(|text| !text.trim().is_empty())
-        .collect::<Vec<_>>();
-    let reasoning = AssistantReasoning::from_summary_parts(reasoning_summary);
-
-    Ok(ChatCompletionResponse::with_metadata(
-        handle.response_model,
-        content,
-        tool_calls,
-        finish_reason,
-        response_id,
-        usage,
-        reasoning,
+fn retry_after_from_snapshot(snapshot: &RateLimitSnapshot) -> Option<Duration> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    retry_after_from_windows(
+        [snapshot.primary.as_ref(), snapshot.secondary.as_ref()],
+        now,
+    )
+}
+
+/// Picks the reset time of the exhausted rate-limit window(s), falling back to the soonest reset
+/// when none of the windows report full usage.
+fn retry_after_from_windows(windows: [Option<&RateLimitWindow>; 2], now: i64) -> Option<Duration> {
+    let exhausted_reset = windows
+        .iter()
+        .flatten()
+        .filter(|window| window.used_percent >= 100.0)
+        .filter_map(|window| window.resets_at)
+        .max();
+    let reset_at = exhausted_reset.or_else(|| {
+        windows
+            .iter()
+            .flatten()
+            .filter_map(|window| window.resets_at)
+            .min()
+    })?;
+    Some(Duration::from_secs(
+        reset_at.saturating_sub(now).max(0) as u64
     ))
 }
 
@@ -338,3 +1697,305 @@ fn prompt_debug_snapshot(prompt: &Prompt) -> Value {
         "base_instructions_override": prompt.base_instructions_override,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codex_core::{auth::AuthCredentialsStoreMode, config::ConfigToml};
+
+    /// A real executor over Codex's default config in a fresh, logged-out home.
+    fn real_executor(serve_config: &ServeConfig) -> RealChatExecutor {
+        let codex_home =
+            std::env::temp_dir().join(format!("codex-serve-home-{}", uuid::Uuid::new_v4()));
+        let config = Config::load_from_base_config_with_overrides(
+            ConfigToml::default(),
+            ConfigOverrides::default(),
+            codex_home.clone(),
+        )
+        .expect("default Codex config");
+        let auth_manager = AuthManager::shared(codex_home, false, AuthCredentialsStoreMode::File);
+        RealChatExecutor::new(Arc::new(config), auth_manager, Vec::new(), serve_config)
+    }
+
+    #[tokio::test]
+    async fn vision_follows_the_model_family() {
+        let executor = real_executor(&ServeConfig::default());
+        let gpt_5 = executor
+            .listed_model_info("gpt-5")
+            .await
+            .expect("gpt-5 has a family");
+        assert!(gpt_5.vision);
+        let gpt_oss = executor
+            .listed_model_info("gpt-oss-120b")
+            .await
+            .expect("gpt-oss has a family");
+        assert!(!gpt_oss.vision);
+        assert!(gpt_oss.tools);
+    }
+
+    #[tokio::test]
+    async fn prepare_builds_the_prompt_under_the_given_mode() {
+        let executor = real_executor(&ServeConfig::default());
+        let payload = || {
+            serde_json::from_value::<crate::openai::chat::ChatCompletionRequest>(json!({
+                "model": "gpt-5",
+                "messages": [{"role": "user", "content": "hi"}]
+            }))
+            .unwrap()
+            .into_prompt()
+            .unwrap()
+        };
+        let roles = |prepared: PreparedPrompt| -> Vec<String> {
+            prepared
+                .prompt
+                .input
+                .iter()
+                .filter_map(|item| match item {
+                    ResponseItem::Message { role, .. } => Some(role.clone()),
+                    _ => None,
+                })
+                .collect()
+        };
+        let default = executor
+            .prepare(payload(), DeveloperPromptMode::Default)
+            .await
+            .expect("prompt is prepared");
+        assert_eq!(roles(default), ["developer", "user"]);
+        let disabled = executor
+            .prepare(payload(), DeveloperPromptMode::Disabled)
+            .await
+            .expect("prompt is prepared");
+        assert_eq!(roles(disabled), ["user"]);
+    }
+
+    #[test]
+    fn requests_turn_web_search_off_but_on_only_when_allowed() {
+        for (requested, configured, allow_enable, expected, warned) in [
+            (None, true, false, true, false),
+            (None, false, true, false, false),
+            (Some(false), true, false, false, false),
+            (Some(false), true, true, false, false),
+            (Some(true), true, false, true, false),
+            (Some(true), false, true, true, false),
+            (Some(true), false, false, false, true),
+        ] {
+            let warnings = Warnings::default();
+            let case = format!("{requested:?} configured={configured} allow={allow_enable}");
+            assert_eq!(
+                web_search_allowed(requested, configured, allow_enable, &warnings),
+                expected,
+                "{case}"
+            );
+            let codes: Vec<_> = warnings.snapshot().iter().map(|w| w.code).collect();
+            assert_eq!(codes == ["web_search_not_allowed"], warned, "{case}");
+        }
+    }
+
+    #[test]
+    fn resumed_text_drops_the_repeated_seam() {
+        assert_eq!(
+            stitch("Once upon a time, there ", "a time, there lived a fox."),
+            "Once upon a time, there lived a fox."
+        );
+        // The whole partial reply said again.
+        assert_eq!(stitch("Once upon", "Once upon a time"), "Once upon a time");
+        // Short matches are kept: the model may well have meant them.
+        assert_eq!(stitch("to be or no", "o, not"), "to be or noo, not");
+        assert_eq!(stitch("Voilà, ", "café"), "Voilà, café");
+    }
+
+    #[test]
+    fn stream_retry_hint_becomes_rate_limit() {
+        let err = CodexErr::Stream("rate limit".to_string(), Some(Duration::from_secs(7)));
+        match classify_codex_error(&err, None, "Codex stream error") {
+            ApiError::RateLimited { retry_after, .. } => {
+                assert_eq!(retry_after, Some(Duration::from_secs(7)));
+            }
+            other => panic!("expected rate limit, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn quota_errors_map_to_rate_limit() {
+        assert!(matches!(
+            classify_codex_error(&CodexErr::QuotaExceeded, None, "Codex"),
+            ApiError::RateLimited {
+                retry_after: None,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn retry_after_prefers_exhausted_window() {
+        let primary = RateLimitWindow {
+            used_percent: 40.0,
+            window_minutes: Some(300),
+            resets_at: Some(1_060),
+        };
+        let secondary = RateLimitWindow {
+            used_percent: 100.0,
+            window_minutes: Some(10_080),
+            resets_at: Some(4_600),
+        };
+        assert_eq!(
+            retry_after_from_windows([Some(&primary), Some(&secondary)], 1_000),
+            Some(Duration::from_secs(3_600))
+        );
+        assert_eq!(
+            retry_after_from_windows([Some(&primary), None], 1_000),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(retry_after_from_windows([None, None], 1_000), None);
+    }
+
+    #[test]
+    fn other_errors_stay_internal() {
+        let err = CodexErr::Stream("disconnected".to_string(), None);
+        assert!(matches!(
+            classify_codex_error(&err, None, "Codex stream error"),
+            ApiError::Internal(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn config_cache_keys_on_profile_and_model() {
+        let cache = ConfigCache::<String>::default();
+        let key = |profile: Option<&str>, model: &str| ConfigKey {
+            profile: profile.map(str::to_string),
+            model: model.to_string(),
+        };
+        let load = |label: &str| {
+            let label = label.to_string();
+            move || async move { Ok(label) }
+        };
+
+        let base = cache
+            .get_or_try_load(key(None, "gpt-5"), load("base"))
+            .await
+            .unwrap();
+        let work = cache
+            .get_or_try_load(key(Some("work"), "gpt-5"), load("work"))
+            .await
+            .unwrap();
+        let cached = cache
+            .get_or_try_load(key(Some("work"), "gpt-5"), load("reloaded"))
+            .await
+            .unwrap();
+        assert_eq!(base.as_str(), "base");
+        assert_eq!(work.as_str(), "work");
+        assert!(Arc::ptr_eq(&work, &cached));
+    }
+
+    #[tokio::test]
+    async fn config_cache_does_not_store_failures() {
+        let cache = ConfigCache::<String>::default();
+        let key = ConfigKey {
+            profile: Some("missing".to_string()),
+            model: "gpt-5".to_string(),
+        };
+        let failed = cache
+            .get_or_try_load(key.clone(), || async {
+                Err(ApiError::bad_request("Unknown Codex profile `missing`"))
+            })
+            .await;
+        assert!(matches!(failed, Err(ApiError::BadRequest(_))));
+        let loaded = cache
+            .get_or_try_load(key, || async { Ok("ok".to_string()) })
+            .await
+            .unwrap();
+        assert_eq!(loaded.as_str(), "ok");
+    }
+
+    #[tokio::test]
+    async fn config_cache_keep_alive_expires_one_model_under_every_profile() {
+        let cache = ConfigCache::<String>::default();
+        for (profile, model) in [
+            (Some("work"), "gpt-5-high"),
+            (None, "gpt-5-high"),
+            (None, "o3"),
+        ] {
+            let key = ConfigKey {
+                profile: profile.map(str::to_string),
+                model: model.to_string(),
+            };
+            cache
+                .get_or_try_load(key, || async { Ok(model.to_string()) })
+                .await
+                .unwrap();
+        }
+        let hour = Duration::from_secs(3600);
+        assert_eq!(
+            cache.set_model_expiry("o3", ModelExpiry::For(hour)).await,
+            0
+        );
+        assert_eq!(
+            cache.keys().await,
+            vec!["gpt-5-high", "o3", "work/gpt-5-high"]
+        );
+
+        assert_eq!(
+            cache
+                .set_model_expiry(&cache_model_name("gpt-5:high"), ModelExpiry::Unload)
+                .await,
+            2
+        );
+        assert_eq!(cache.keys().await, vec!["o3"]);
+    }
+
+    #[tokio::test]
+    async fn config_cache_evicts_idle_entries_and_reloads_them_on_demand() {
+        let cache = ConfigCache::<String>::default();
+        let loads = &AtomicUsize::new(0);
+        let key = |model: &str| ConfigKey {
+            profile: None,
+            model: model.to_string(),
+        };
+        let get = |model: &'static str| {
+            cache.get_or_try_load(key(model), move || async move {
+                loads.fetch_add(1, Ordering::SeqCst);
+                Ok(model.to_string())
+            })
+        };
+        get("gpt-5").await.unwrap();
+        get("o3").await.unwrap();
+        get("gpt-5.1").await.unwrap();
+        cache
+            .set_model_expiry("gpt-5.1", ModelExpiry::Forever)
+            .await;
+
+        let idle = Duration::from_secs(30 * 60);
+        let now = Instant::now();
+        assert!(cache.evict_idle(idle, now).await.is_empty());
+        // Used again just before the sweep, `o3` is not idle yet.
+        get("o3").await.unwrap();
+        let later = Instant::now() + idle - Duration::from_secs(1);
+        assert!(cache.evict_idle(idle, later).await.is_empty());
+        let evicted = cache.evict_idle(idle, Instant::now() + idle).await;
+        assert_eq!(evicted, vec!["gpt-5", "o3"]);
+        // A `keep_alive` governs `gpt-5.1` instead.
+        assert_eq!(cache.keys().await, vec!["gpt-5.1"]);
+
+        assert_eq!(loads.load(Ordering::SeqCst), 3);
+        assert_eq!(get("gpt-5").await.unwrap().as_str(), "gpt-5");
+        assert_eq!(loads.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn config_cache_clear_reports_dropped_keys() {
+        let cache = ConfigCache::<String>::default();
+        for (profile, model) in [(Some("work"), "gpt-5"), (None, "gpt-5-high")] {
+            let key = ConfigKey {
+                profile: profile.map(str::to_string),
+                model: model.to_string(),
+            };
+            cache
+                .get_or_try_load(key, || async { Ok(model.to_string()) })
+                .await
+                .unwrap();
+        }
+        assert_eq!(cache.keys().await, vec!["gpt-5-high", "work/gpt-5"]);
+        assert_eq!(cache.clear().await, 2);
+        assert!(cache.keys().await.is_empty());
+    }
+}
@@ -0,0 +1,62 @@
+use axum::{
+    Json,
+    extract::{FromRequest, Request, rejection::JsonRejection},
+    http::StatusCode,
+};
+use serde::de::DeserializeOwned;
+
+use crate::error::ApiError;
+
+/// Body size limit applied to a route group, recorded so rejections can name the limit.
+#[derive(Clone, Copy, Debug)]
+pub struct BodyLimit(pub usize);
+
+/// JSON body extractor that reports malformed payloads as OpenAI-style 400s instead of axum's
+/// plain-text rejections, so SDKs can surface the real problem.
+pub struct ApiJson<T>(pub T);
+
+impl<S, T> FromRequest<S> for ApiJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let limit = req.extensions().get::<BodyLimit>().copied();
+        match Json::<T>::from_request(req, state).await {
+            Ok(Json(value)) => Ok(Self(value)),
+            Err(rejection) => Err(json_rejection_to_api_error(rejection, limit)),
+        }
+    }
+}
+
+/// The 413 returned when a body outgrows its route group's [`BodyLimit`].
+pub(super) fn body_too_large(limit: Option<BodyLimit>) -> ApiError {
+    ApiError::payload_too_large(match limit {
+        Some(BodyLimit(bytes)) => {
+            format!("Request body exceeds the maximum allowed size of {bytes} bytes")
+        }
+        None => "Request body exceeds the maximum allowed size".to_string(),
+    })
+}
+
+fn json_rejection_to_api_error(rejection: JsonRejection, limit: Option<BodyLimit>) -> ApiError {
+    if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        return body_too_large(limit);
+    }
+
+    // `JsonDataError` messages already carry the failing JSON path (e.g. `messages[0].role`)
+    // courtesy of axum's serde_path_to_error integration.
+    let detail = rejection.body_text();
+    match rejection {
+        JsonRejection::JsonDataError(_) => {
+            ApiError::bad_request(format!("Invalid request body: {detail}"))
+        }
+        JsonRejection::JsonSyntaxError(_) => {
+            ApiError::bad_request(format!("Request body is not valid JSON: {detail}"))
+        }
+        JsonRejection::MissingJsonContentType(_) => ApiError::bad_request(detail),
+        _ => ApiError::bad_request(format!("Failed to read request body: {detail}")),
+    }
+}
@@ -0,0 +1,511 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    hash::{DefaultHasher, Hash, Hasher},
+    net::SocketAddr,
+    pin::pin,
+    sync::{
+        Arc, Mutex, MutexGuard,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderName, HeaderValue, Method, header::AUTHORIZATION},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures_util::StreamExt;
+use serde::Serialize;
+use tokio::sync::Notify;
+
+use super::{
+    extract::{BodyLimit, body_too_large},
+    middleware::is_stream_response,
+    organization::OpenAiOrganization,
+    state::AppState,
+};
+use crate::error::ApiError;
+
+/// The route whose handler waits on [`QueuedRequest`] itself.
+const OPENAI_CHAT_PATH: &str = "/v1/chat/completions";
+
+/// Suggested back-off for clients that hit their concurrency cap; a slot usually frees up as soon
+/// as one of their own requests finishes.
+const RETRY_AFTER: Duration = Duration::from_secs(1);
+
+/// Idle clients are forgotten once this many have been seen, so the table stays bounded.
+const MAX_TRACKED_CLIENTS: usize = 1024;
+
+/// How long a queued request waited for its slot, in milliseconds (`--queue-requests`).
+pub const QUEUE_WAIT_HEADER: HeaderName = HeaderName::from_static("x-codex-serve-queue-wait-ms");
+
+/// Per-client in-flight caps for the chat routes (`--per-client-concurrency`). Requests over the
+/// cap are refused, or with `--queue-requests` wait their turn, first come first served.
+pub struct ClientLimiter {
+    limit: usize,
+    queue: bool,
+    clients: Mutex<HashMap<String, ClientSlots>>,
+    /// Woken whenever a slot frees up or a queue moves.
+    changed: Notify,
+    next_ticket: AtomicU64,
+}
+
+/// Counters for one client, as reported by `/stats/clients`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct ClientStats {
+    pub in_flight: usize,
+    pub admitted: u64,
+    pub rejected: u64,
+    /// Requests that had to wait for a slot; they count as admitted once they get one.
+    pub queued: u64,
+}
+
+#[derive(Default)]
+struct ClientSlots {
+    stats: ClientStats,
+    /// Tickets of the requests waiting for a slot, oldest first.
+    waiting: VecDeque<u64>,
+}
+
+/// What [`ClientLimiter::acquire`] got a request.
+enum Admission {
+    Admitted(ClientPermit),
+    Queued(QueueTicket),
+    Refused,
+}
+
+impl ClientLimiter {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit: limit.max(1),
+            queue: false,
+            clients: Mutex::default(),
+            changed: Notify::new(),
+            next_ticket: AtomicU64::new(0),
+        }
+    }
+
+    /// Queues requests over the cap instead of refusing them (`--queue-requests`).
+    pub fn with_queue(mut self, queue: bool) -> Self {
+        self.queue = queue;
+        self
+    }
+
+    /// Takes one of `client`'s slots, or returns `None` if all of them are in use.
+    #[cfg(test)]
+    fn try_acquire(self: &Arc<Self>, client: &str) -> Option<ClientPermit> {
+        match self.admit(client, false) {
+            Admission::Admitted(permit) => Some(permit),
+            Admission::Queued(_) | Admission::Refused => None,
+        }
+    }
+
+    /// Takes one of `client`'s slots, or puts the request at the back of its queue when the
+    /// limiter queues.
+    fn acquire(self: &Arc<Self>, client: &str) -> Admission {
+        self.admit(client, self.queue)
+    }
+
+    fn admit(self: &Arc<Self>, client: &str, queue: bool) -> Admission {
+        let mut clients = self.clients();
+        if clients.len() >= MAX_TRACKED_CLIENTS && !clients.contains_key(client) {
+            clients.retain(|_, slots| slots.stats.in_flight > 0 || !slots.waiting.is_empty());
+        }
+        let slots = clients.entry(client.to_string()).or_default();
+        if slots.stats.in_flight < self.limit && slots.waiting.is_empty() {
+            slots.stats.in_flight += 1;
+            slots.stats.admitted += 1;
+            return Admission::Admitted(self.permit(client));
+        }
+        if !queue {
+            slots.stats.rejected += 1;
+            return Admission::Refused;
+        }
+        let id = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        slots.waiting.push_back(id);
+        slots.stats.queued += 1;
+        Admission::Queued(QueueTicket {
+            limiter: Arc::clone(self),
+            client: client.to_string(),
+            id,
+            enqueued_at: Instant::now(),
+        })
+    }
+
+    /// Admits ticket `id` if it is first in `client`'s queue and a slot is free, else returns
+    /// its 1-based place in the queue.
+    fn admit_ticket(self: &Arc<Self>, client: &str, id: u64) -> Result<ClientPermit, usize> {
+        let mut clients = self.clients();
+        let slots = clients.entry(client.to_string()).or_default();
+        let position = slots
+            .waiting
+            .iter()
+            .position(|ticket| *ticket == id)
+            .map_or(slots.waiting.len() + 1, |index| index + 1);
+        if position > 1 || slots.stats.in_flight >= self.limit {
+            return Err(position);
+        }
+        slots.waiting.pop_front();
+        slots.stats.in_flight += 1;
+        slots.stats.admitted += 1;
+        self.changed.notify_waiters();
+        Ok(self.permit(client))
+    }
+
+    fn permit(self: &Arc<Self>, client: &str) -> ClientPermit {
+        ClientPermit {
+            limiter: Arc::clone(self),
+            client: client.to_string(),
+        }
+    }
+
+    /// Requests one client may have in flight.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, ClientStats> {
+        self.clients()
+            .iter()
+            .map(|(client, slots)| (client.clone(), slots.stats))
+            .collect()
+    }
+
+    fn clients(&self) -> MutexGuard<'_, HashMap<String, ClientSlots>> {
+        self.clients
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// One occupied slot; released on drop.
+pub(super) struct ClientPermit {
+    limiter: Arc<ClientLimiter>,
+    client: String,
+}
+
+impl Drop for ClientPermit {
+    fn drop(&mut self) {
+        if let Some(slots) = self.limiter.clients().get_mut(&self.client) {
+            slots.stats.in_flight = slots.stats.in_flight.saturating_sub(1);
+        }
+        self.limiter.changed.notify_waiters();
+    }
+}
+
+/// A request's place in its client's queue; dropping it leaves the queue.
+pub(super) struct QueueTicket {
+    limiter: Arc<ClientLimiter>,
+    client: String,
+    id: u64,
+    enqueued_at: Instant,
+}
+
+impl QueueTicket {
+    /// Waits for the slot, calling `on_position` with the request's 1-based place in the queue
+    /// first and then whenever it changes. Returns the slot and how long the wait took.
+    pub(super) async fn wait(self, mut on_position: impl FnMut(usize)) -> (ClientPermit, Duration) {
+        let mut reported = None;
+        loop {
+            let mut changed = pin!(self.limiter.changed.notified());
+            changed.as_mut().enable();
+            match self.limiter.admit_ticket(&self.client, self.id) {
+                Ok(permit) => return (permit, self.enqueued_at.elapsed()),
+                Err(position) => {
+                    if reported != Some(position) {
+                        reported = Some(position);
+                        on_position(position);
+                    }
+                }
+            }
+            changed.await;
+        }
+    }
+}
+
+impl Drop for QueueTicket {
+    fn drop(&mut self) {
+        if let Some(slots) = self.limiter.clients().get_mut(&self.client) {
+            let before = slots.waiting.len();
+            slots.waiting.retain(|ticket| *ticket != self.id);
+            if slots.waiting.len() == before {
+                return;
+            }
+        }
+        self.limiter.changed.notify_waiters();
+    }
+}
+
+/// A queued OpenAI chat request, handed to the handler so a stream can report its place in the
+/// queue before it goes upstream. The handler takes the ticket and waits on it.
+#[derive(Clone)]
+pub(super) struct QueuedRequest(Arc<Mutex<Option<QueueTicket>>>);
+
+impl QueuedRequest {
+    pub(super) fn take(&self) -> Option<QueueTicket> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .take()
+    }
+}
+
+/// The `x-codex-serve-queue-wait-ms` value for a request that waited `waited`.
+pub(super) fn queue_wait_header(waited: Duration) -> HeaderValue {
+    HeaderValue::from(u64::try_from(waited.as_millis()).unwrap_or(u64::MAX))
+}
+
+/// The caller's identity on the chat routes, as [`limit_per_client`] worked it out. Only present
+/// when per-client limits, `--usage-extended` or `--max-tokens-per-hour` need it.
+#[derive(Clone, Debug)]
+pub(super) struct ClientId {
+    /// Who the caller is, for stats, logs and `/admin/requests`.
+    pub(super) name: String,
+    /// What its concurrency and token limits are charged to: its API key when it sent one,
+    /// whatever headers came with it, so those cannot buy it more room.
+    pub(super) account: String,
+}
+
+impl ClientId {
+    /// An identity whose limits are its own.
+    pub(super) fn new(name: String) -> Self {
+        Self {
+            account: name.clone(),
+            name,
+        }
+    }
+}
+
+/// Sheds chat requests from clients that already have `--per-client-concurrency` requests in
+/// flight, so one bursty caller cannot take every upstream slot. Streams keep their slot until the
+/// SSE or NDJSON body finishes.
+///
+/// With `--queue-requests` such requests wait for a slot instead, and their response says how long
+/// in `x-codex-serve-queue-wait-ms`. OpenAI chat requests are passed on queued, so that a stream
+/// can send its headers and `: queued position=N` comments while it waits.
+pub(super) async fn limit_per_client(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let limiter = state.client_limiter();
+    let config = state.config();
+    if limiter.is_none() && !config.usage_extended && config.max_tokens_per_hour.is_none() {
+        return next.run(request).await;
+    }
+    let (mut request, client) = match identify_client(request).await {
+        Ok(identified) => identified,
+        Err(err) => return err.into_response(),
+    };
+    let account = client.account.clone();
+    request.extensions_mut().insert(client);
+    let Some(limiter) = limiter else {
+        return next.run(request).await;
+    };
+    let (permit, waited) = match limiter.acquire(&account) {
+        Admission::Admitted(permit) => (permit, None),
+        Admission::Queued(ticket) if request.uri().path() == OPENAI_CHAT_PATH => {
+            request
+                .extensions_mut()
+                .insert(QueuedRequest(Arc::new(Mutex::new(Some(ticket)))));
+            return next.run(request).await;
+        }
+        Admission::Queued(ticket) => {
+            let (permit, waited) = ticket.wait(|_| {}).await;
+            (permit, Some(waited))
+        }
+        Admission::Refused => {
+            let message = format!(
+                "Client `{account}` already has {} request(s) in flight; retry when one finishes",
+                limiter.limit
+            );
+            return ApiError::client_rate_limited(account, message, RETRY_AFTER).into_response();
+        }
+    };
+
+    let mut response = next.run(request).await;
+    if let Some(waited) = waited {
+        response
+            .headers_mut()
+            .insert(QUEUE_WAIT_HEADER, queue_wait_header(waited));
+    }
+    if !is_stream_response(&response) {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let body = body.into_data_stream().map(move |chunk| {
+        let _held = &permit;
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(body))
+}
+
+/// Names the caller: a hash of its bearer token when it sends one, with its `OpenAI-Organization`
+/// and `OpenAI-Project` scoped under it (`key:<hash>/project:<id>`) but the limits still charged
+/// to the key; else those headers; else the request's `user` field, else its IP address.
+async fn identify_client(request: Request) -> Result<(Request, ClientId), ApiError> {
+    let organization = request
+        .extensions()
+        .get::<OpenAiOrganization>()
+        .map(OpenAiOrganization::client_id);
+    if let Some(token) = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    {
+        let mut hasher = DefaultHasher::new();
+        token.trim().hash(&mut hasher);
+        let key = format!("key:{:016x}", hasher.finish());
+        let client = ClientId {
+            name: organization.map_or_else(
+                || key.clone(),
+                |organization| format!("{key}/{organization}"),
+            ),
+            account: key,
+        };
+        return Ok((request, client));
+    }
+    if let Some(organization) = organization {
+        return Ok((request, ClientId::new(organization)));
+    }
+
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| format!("ip:{}", addr.ip()))
+        .unwrap_or_else(|| "anonymous".to_string());
+    if request.method() != Method::POST {
+        return Ok((request, ClientId::new(peer)));
+    }
+
+    // The handler still parses the body; this copy is only read for `user`.
+    let limit = request.extensions().get::<BodyLimit>().copied();
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, limit.map_or(usize::MAX, |BodyLimit(bytes)| bytes))
+        .await
+        .map_err(|_| body_too_large(limit))?;
+    let user = serde_json::from_slice::<serde_json::Value>(&bytes)
+        .ok()
+        .and_then(|body| body.get("user")?.as_str().map(str::to_string))
+        .filter(|user| !user.trim().is_empty());
+    let client = user.map_or(peer, |user| format!("user:{user}"));
+    Ok((
+        Request::from_parts(parts, Body::from(bytes)),
+        ClientId::new(client),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{ScriptedChatExecutor, TestServer};
+    use axum::http::StatusCode;
+    use serde_json::{Value, json};
+
+    fn stream_request(user: &str) -> Value {
+        json!({
+            "model": "gpt-5",
+            "stream": true,
+            "user": user,
+            "messages": [{"role": "user", "content": "hi"}]
+        })
+    }
+
+    async fn client_stats(server: &TestServer) -> Value {
+        reqwest::get(format!("{}/stats/clients", server.base_url()))
+            .await
+            .expect("client stats")
+            .json()
+            .await
+            .expect("client stats body")
+    }
+
+    #[test]
+    fn permits_release_slots_on_drop() {
+        let limiter = Arc::new(ClientLimiter::new(1));
+        let first = limiter.try_acquire("user:a").expect("first slot");
+        assert!(limiter.try_acquire("user:a").is_none());
+        drop(first);
+        let _second = limiter.try_acquire("user:a").expect("slot freed");
+        assert_eq!(
+            limiter.snapshot()["user:a"],
+            ClientStats {
+                in_flight: 1,
+                admitted: 2,
+                rejected: 1,
+                queued: 0,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn busy_client_cannot_starve_another() {
+        let executor =
+            ScriptedChatExecutor::new(["slow", " reply"]).with_delay(Duration::from_millis(300));
+        let state = AppState::insecure_mock(true)
+            .with_executor(Arc::new(executor))
+            .with_client_limiter(ClientLimiter::new(1));
+        let server = TestServer::spawn_with_state(state)
+            .await
+            .expect("Codex Serve test server should start");
+        let client = reqwest::Client::new();
+        let url = format!("{}/v1/chat/completions", server.base_url());
+
+        // Client A holds its only slot with an open stream...
+        let open = client
+            .post(&url)
+            .json(&stream_request("a"))
+            .send()
+            .await
+            .expect("first stream");
+        assert_eq!(open.status(), StatusCode::OK);
+
+        // ...so its next request is shed with its identity and a retry hint...
+        let shed = client
+            .post(&url)
+            .json(&stream_request("a"))
+            .send()
+            .await
+            .expect("second stream");
+        assert_eq!(shed.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(
+            shed.headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok()),
+            Some("1")
+        );
+        let body: Value = shed.json().await.expect("error body");
+        assert_eq!(body["error"]["client"], "user:a");
+        assert_eq!(body["error"]["code"], "RATE_LIMITED");
+
+        // ...while client B is still served.
+        let other = client
+            .post(&url)
+            .json(&stream_request("b"))
+            .send()
+            .await
+            .expect("other client");
+        assert_eq!(other.status(), StatusCode::OK);
+
+        let counters = client_stats(&server).await;
+        assert_eq!(counters["per_client_concurrency"], 1);
+        assert_eq!(counters["clients"]["user:a"]["rejected"], 1);
+        assert_eq!(counters["clients"]["user:a"]["in_flight"], 1);
+        assert_eq!(counters["clients"]["user:b"]["in_flight"], 1);
+
+        open.text().await.expect("first stream completes");
+        other.text().await.expect("other stream completes");
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(2);
+        loop {
+            if client_stats(&server).await["clients"]["user:a"]["in_flight"] == 0 {
+                break;
+            }
+            assert!(tokio::time::Instant::now() < deadline, "slot not released");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use axum::http::{Method, Uri};
+
+use crate::{
+    error::ApiError,
+    serve_config::{ApiSurface, ApiSurfaces},
+};
+
+/// A route registered by [`super::router`].
+pub(super) struct KnownRoute {
+    /// The path as registered, `{param}` and `{*rest}` segments included.
+    pub(super) path: &'static str,
+    pub(super) methods: &'static [&'static str],
+    /// The API surface it belongs to; `None` for routes every listener serves.
+    pub(super) surface: Option<ApiSurface>,
+    /// The flag that mounts it, for routes that are off by default.
+    pub(super) flag: Option<&'static str>,
+}
+
+const fn route(
+    path: &'static str,
+    methods: &'static [&'static str],
+    surface: Option<ApiSurface>,
+) -> KnownRoute {
+    KnownRoute {
+        path,
+        methods,
+        surface,
+        flag: None,
+    }
+}
+
+const fn optional(
+    path: &'static str,
+    methods: &'static [&'static str],
+    surface: Option<ApiSurface>,
+    flag: &'static str,
+) -> KnownRoute {
+    KnownRoute {
+        path,
+        methods,
+        surface,
+        flag: Some(flag),
+    }
+}
+
+const GET: &[&str] = &["GET", "HEAD"];
+const POST: &[&str] = &["POST"];
+const DELETE: &[&str] = &["DELETE"];
+const OPENAI: Option<ApiSurface> = Some(ApiSurface::OpenAi);
+const OLLAMA: Option<ApiSurface> = Some(ApiSurface::Ollama);
+
+/// Every route [`super::router`] can register. Used to explain 405s and to suggest the closest
+/// route on 404s; `known_routes_are_registered` keeps it honest in both directions.
+pub(super) const KNOWN_ROUTES: &[KnownRoute] = &[
+    route("/openapi.json", GET, None),
+    route("/healthz", GET, None),
+    route("/stats", GET, None),
+    route("/stats/conversations", GET, None),
+    route("/stats/budget", GET, None),
+    route("/stats/latency", GET, None),
+    route("/stats/clients", GET, None),
+    route("/stats/conversion", GET, None),
+    route("/metrics", GET, None),
+    route("/api/version", GET, OLLAMA),
+    route("/api/tags", GET, OLLAMA),
+    route("/api/show", POST, OLLAMA),
+    route("/api/ps", GET, OLLAMA),
+    route("/api/pull", POST, OLLAMA),
+    route("/api/delete", DELETE, OLLAMA),
+    route("/api/chat", POST, OLLAMA),
+    route("/api/generate", POST, OLLAMA),
+    route("/v1/models", GET, OPENAI),
+    route("/v1/chat/completions", POST, OPENAI),
+    optional("/v1/codex/stream", POST, OPENAI, "--enable-codex-stream"),
+    route("/v1beta/models/{*target}", POST, OPENAI),
+    optional("/", GET, None, "--playground"),
+    optional("/admin/reload", POST, None, "--enable-admin"),
+    optional("/admin/gc", POST, None, "--enable-admin"),
+    optional("/admin/state", GET, None, "--enable-admin"),
+    optional("/admin/requests", GET, None, "--enable-admin"),
+    optional("/admin/requests/{id}/cancel", POST, None, "--enable-admin"),
+    optional("/v1/models/{id}/settings", GET, None, "--enable-admin"),
+];
+
+/// The known route `path` requests.
+fn known_route(path: &str) -> Option<&'static KnownRoute> {
+    KNOWN_ROUTES
+        .iter()
+        .find(|route| route_matches(route.path, path))
+}
+
+/// Whether `path` names `route`: a `{param}` segment matches any one segment, a `{*rest}` segment
+/// everything after it.
+fn route_matches(route: &str, path: &str) -> bool {
+    let mut segments = path.split('/');
+    for pattern in route.split('/') {
+        let segment = segments.next();
+        if pattern.starts_with("{*") {
+            return segment.is_some_and(|segment| !segment.is_empty());
+        }
+        match segment {
+            Some(segment) if pattern.starts_with('{') && !segment.is_empty() => {}
+            Some(segment) if segment == pattern => {}
+            _ => return false,
+        }
+    }
+    segments.next().is_none()
+}
+
+/// Fallback for registered paths hit with an unsupported method. axum still adds the `Allow`
+/// header after this runs; we only replace the empty body with an OpenAI-style error.
+pub(super) async fn method_not_allowed(method: Method, uri: Uri) -> ApiError {
+    let path = uri.path();
+    let message = match known_route(path) {
+        Some(route) => format!(
+            "This endpoint only supports {}; received {method}",
+            route.methods.join(", ")
+        ),
+        None => format!("Method {method} is not supported for `{path}`"),
+    };
+    ApiError::method_not_allowed(message)
+}
+
+/// Fallback for unknown paths: a JSON 404 naming the disabled surface or the flag a known route
+/// needs, or else pointing at the most similar route `surfaces` serves.
+pub(super) async fn not_found(uri: Uri, surfaces: ApiSurfaces) -> ApiError {
+    let path = uri.path();
+    if let Some(route) = known_route(path) {
+        if let Some(surface) = route.surface.filter(|surface| !surfaces.contains(*surface)) {
+            return ApiError::not_found(format!(
+                "`{path}` belongs to the {} API, which this listener does not serve \
+                 (see `--api-surface` and `--ollama-addr`)",
+                surface.label()
+            ));
+        }
+        if let Some(flag) = route.flag {
+            return ApiError::not_found(format!(
+                "`{path}` is not served on this listener (see `{flag}`)"
+            ));
+        }
+    }
+    let message = match closest_route(path, surfaces) {
+        Some(route) => format!("Unknown route `{path}`. Did you mean `{route}`?"),
+        None => format!("Unknown route `{path}`"),
+    };
+    ApiError::not_found(message)
+}
+
+fn closest_route(path: &str, surfaces: ApiSurfaces) -> Option<&'static str> {
+    KNOWN_ROUTES
+        .iter()
+        .filter(|route| {
+            route.flag.is_none()
+                && route
+                    .surface
+                    .is_none_or(|surface| surfaces.contains(surface))
+        })
+        .map(|route| (route.path, edit_distance(path, route.path)))
+        .filter(|(route, distance)| *distance <= route.len() / 2)
+        .min_by_key(|(_, distance)| *distance)
+        .map(|(route, _)| route)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b_chars: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b_chars.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = Vec::with_capacity(b_chars.len() + 1);
+        current.push(i + 1);
+        for (j, b_char) in b_chars.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b_chars.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggests_closest_route_for_typos() {
+        let all = ApiSurfaces::ALL;
+        assert_eq!(
+            closest_route("/v1/chat/completion", all),
+            Some("/v1/chat/completions")
+        );
+        assert_eq!(closest_route("/v1/model", all), Some("/v1/models"));
+        assert_eq!(closest_route("/totally/unrelated/path/here", all), None);
+        assert_eq!(
+            closest_route("/api/tag", ApiSurfaces::only(ApiSurface::OpenAi)),
+            None
+        );
+    }
+
+    #[test]
+    fn parameters_match_one_segment_and_wildcards_the_rest() {
+        assert!(route_matches(
+            "/admin/requests/{id}/cancel",
+            "/admin/requests/req-1/cancel"
+        ));
+        assert!(!route_matches(
+            "/admin/requests/{id}/cancel",
+            "/admin/requests//cancel"
+        ));
+        assert!(!route_matches(
+            "/admin/requests/{id}/cancel",
+            "/admin/requests/a/b/cancel"
+        ));
+        assert!(route_matches(
+            "/v1beta/models/{*target}",
+            "/v1beta/models/models/gpt-5:x"
+        ));
+        assert!(!route_matches(
+            "/v1beta/models/{*target}",
+            "/v1beta/models/"
+        ));
+        assert!(route_matches("/", "/"));
+        assert!(!route_matches("/", "/v1"));
+        assert!(!route_matches("/v1/models", "/v1/models/gpt-5"));
+    }
+}
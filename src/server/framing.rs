@@ -0,0 +1,277 @@
+//! How a streamed chat completion is written to the wire. The forwarding task produces
+//! [`StreamFrame`]s without knowing the framing; [`StreamFraming`] turns them into SSE events
+//! (the default) or NDJSON lines for clients that send `Accept: application/x-ndjson`.
+//! [`sized_frames`] keeps events under `--max-sse-event-bytes` by splitting what a chunk carries.
+
+use std::convert::Infallible;
+
+use axum::{
+    body::{Body, Bytes},
+    http::{
+        HeaderMap, HeaderValue,
+        header::{ACCEPT, CACHE_CONTROL, CONTENT_TYPE},
+    },
+    response::{
+        IntoResponse, Response,
+        sse::{Event, Sse},
+    },
+};
+use futures_util::{Stream, StreamExt};
+use serde::Serialize;
+use serde_json::json;
+use tracing::error;
+
+const NDJSON: &str = "application/x-ndjson";
+
+/// What SSE adds around a frame's JSON: `data: ` and the blank line ending the event.
+const SSE_EVENT_OVERHEAD: usize = "data: ".len() + "\n\n".len();
+
+/// One item of a chat stream, not yet framed.
+pub(super) enum StreamFrame {
+    /// A serialized chunk or error object.
+    Json(String),
+    /// An SSE comment, which clients skip; NDJSON has no comments, so it leaves them out.
+    Comment(String),
+    /// The end of a successful or failed stream; SSE writes `[DONE]`, NDJSON writes nothing.
+    Done,
+}
+
+impl StreamFrame {
+    /// Serializes a chunk payload, degrading to an in-stream error object rather than panicking
+    /// so a single bad chunk can't take down the whole stream.
+    pub(super) fn json(payload: impl Serialize) -> Self {
+        match serde_json::to_string(&payload) {
+            Ok(text) => Self::Json(text),
+            Err(err) => {
+                error!("failed to serialize stream chunk: {err}");
+                Self::error("Codex Serve failed to serialize a stream chunk")
+            }
+        }
+    }
+
+    fn error(message: &str) -> Self {
+        let payload = json!({
+            "error": {
+                "message": message,
+                "type": "server_error",
+                "code": "INTERNAL_ERROR",
+            }
+        });
+        Self::Json(payload.to_string())
+    }
+}
+
+/// The frames carrying `text`, each built by `frame` from a piece of it. With `max_event_bytes`,
+/// a frame whose event would be larger is replaced by several in order, each carrying as much of
+/// `text` as fits, cut on character boundaries. A piece carries at least one character, so a
+/// limit smaller than an empty chunk is exceeded rather than looping.
+pub(super) fn sized_frames(
+    text: &str,
+    max_event_bytes: Option<usize>,
+    frame: impl Fn(&str) -> StreamFrame,
+) -> Vec<StreamFrame> {
+    headed_frames(text, max_event_bytes, &frame, &frame)
+}
+
+/// Like [`sized_frames`], but the first frame is built by `head` and any after it by `rest`, for
+/// fields a client must see only once, such as a tool call's id and name.
+pub(super) fn headed_frames(
+    text: &str,
+    max_event_bytes: Option<usize>,
+    head: impl Fn(&str) -> StreamFrame,
+    rest: impl Fn(&str) -> StreamFrame,
+) -> Vec<StreamFrame> {
+    let whole = head(text);
+    let Some(max_event_bytes) = max_event_bytes else {
+        return vec![whole];
+    };
+    if text.is_empty() || event_bytes(&whole) <= max_event_bytes {
+        return vec![whole];
+    }
+    let mut budget = max_event_bytes.saturating_sub(event_bytes(&head("")));
+    let mut frames = Vec::new();
+    let mut start = 0;
+    let mut width = 0;
+    for (at, ch) in text.char_indices() {
+        let escaped = escaped_len(ch);
+        if at > start && width + escaped > budget {
+            let piece = &text[start..at];
+            frames.push(if frames.is_empty() {
+                head(piece)
+            } else {
+                rest(piece)
+            });
+            budget = max_event_bytes.saturating_sub(event_bytes(&rest("")));
+            start = at;
+            width = 0;
+        }
+        width += escaped;
+    }
+    let piece = &text[start..];
+    frames.push(if frames.is_empty() {
+        head(piece)
+    } else {
+        rest(piece)
+    });
+    frames
+}
+
+fn event_bytes(frame: &StreamFrame) -> usize {
+    match frame {
+        StreamFrame::Json(text) => text.len() + SSE_EVENT_OVERHEAD,
+        StreamFrame::Comment(_) | StreamFrame::Done => 0,
+    }
+}
+
+/// How many bytes `ch` takes inside a JSON string as `serde_json` writes it.
+fn escaped_len(ch: char) -> usize {
+    match ch {
+        '"' | '\\' | '\n' | '\r' | '\t' | '\u{8}' | '\u{c}' => 2,
+        '\0'..='\u{1f}' => 6,
+        _ => ch.len_utf8(),
+    }
+}
+
+/// The wire format of a streamed chat completion.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum StreamFraming {
+    /// `data:` events ending with `data: [DONE]`, as OpenAI streams.
+    Sse,
+    /// One chunk object per line; the last line is the chunk carrying usage.
+    Ndjson,
+}
+
+impl StreamFraming {
+    /// NDJSON when the `Accept` header asks for it, SSE for anything else (including `*/*`).
+    pub(super) fn from_headers(headers: &HeaderMap) -> Self {
+        let wants_ndjson = headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|range| range.split(';').next())
+            .any(|media| media.trim().eq_ignore_ascii_case(NDJSON));
+        if wants_ndjson {
+            Self::Ndjson
+        } else {
+            Self::Sse
+        }
+    }
+
+    /// Wraps `frames` in a response of this framing.
+    pub(super) fn respond(
+        self,
+        frames: impl Stream<Item = StreamFrame> + Send + 'static,
+    ) -> Response {
+        match self {
+            Self::Sse => Sse::new(frames.map(|frame| {
+                Ok::<_, Infallible>(match frame {
+                    StreamFrame::Json(text) => Event::default().data(text),
+                    StreamFrame::Comment(text) => Event::default().comment(text),
+                    StreamFrame::Done => Event::default().data("[DONE]"),
+                })
+            }))
+            .into_response(),
+            Self::Ndjson => {
+                let lines = frames.filter_map(|frame| async move {
+                    match frame {
+                        StreamFrame::Json(mut line) => {
+                            line.push('\n');
+                            Some(Ok::<_, Infallible>(Bytes::from(line)))
+                        }
+                        StreamFrame::Comment(_) | StreamFrame::Done => None,
+                    }
+                });
+                let mut response = Body::from_stream(lines).into_response();
+                let headers = response.headers_mut();
+                headers.insert(CONTENT_TYPE, HeaderValue::from_static(NDJSON));
+                headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+                response
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn framing(accept: &str) -> StreamFraming {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_str(accept).unwrap());
+        StreamFraming::from_headers(&headers)
+    }
+
+    fn json_frame(piece: &str) -> StreamFrame {
+        StreamFrame::json(json!({"delta": piece}))
+    }
+
+    fn frame_text(frame: &StreamFrame) -> String {
+        match frame {
+            StreamFrame::Json(text) => {
+                let value: serde_json::Value = serde_json::from_str(text).unwrap();
+                value["delta"].as_str().unwrap().to_string()
+            }
+            _ => panic!("expected a JSON frame"),
+        }
+    }
+
+    #[test]
+    fn oversized_frames_are_split_on_escaped_widths() {
+        // Quotes and control characters grow when escaped; multibyte characters must not be cut.
+        let text = "\"é\u{1}ok\n".repeat(500);
+        for limit in [40, 64, 100, 1000] {
+            let frames = sized_frames(&text, Some(limit), json_frame);
+            assert!(frames.len() > 1);
+            assert!(
+                frames.iter().all(|frame| event_bytes(frame) <= limit),
+                "{limit}"
+            );
+            let joined: String = frames.iter().map(frame_text).collect();
+            assert_eq!(joined, text);
+        }
+    }
+
+    #[test]
+    fn only_the_first_piece_gets_the_head() {
+        let head = |piece: &str| StreamFrame::json(json!({"id": "call_1", "delta": piece}));
+        let text = "x".repeat(200);
+        let frames = headed_frames(&text, Some(60), head, json_frame);
+        assert!(frames.len() > 2);
+        assert!(frames.iter().all(|frame| event_bytes(frame) <= 60));
+        let ids: Vec<bool> = frames
+            .iter()
+            .map(|frame| match frame {
+                StreamFrame::Json(text) => text.contains("call_1"),
+                _ => panic!("expected a JSON frame"),
+            })
+            .collect();
+        assert!(ids[0] && !ids[1..].contains(&true));
+        let joined: String = frames.iter().map(frame_text).collect();
+        assert_eq!(joined, text);
+    }
+
+    #[test]
+    fn frames_within_the_limit_are_left_whole() {
+        assert_eq!(sized_frames("hi", None, json_frame).len(), 1);
+        assert_eq!(sized_frames("hi", Some(1000), json_frame).len(), 1);
+        assert_eq!(sized_frames("", Some(1), json_frame).len(), 1);
+        // A limit below the empty chunk still makes progress, a character at a time.
+        assert_eq!(sized_frames("abc", Some(1), json_frame).len(), 3);
+    }
+
+    #[test]
+    fn only_an_explicit_ndjson_accept_switches_framing() {
+        assert_eq!(
+            StreamFraming::from_headers(&HeaderMap::new()),
+            StreamFraming::Sse
+        );
+        assert_eq!(framing("*/*"), StreamFraming::Sse);
+        assert_eq!(framing("text/event-stream"), StreamFraming::Sse);
+        assert_eq!(framing("application/x-ndjson"), StreamFraming::Ndjson);
+        assert_eq!(
+            framing("application/json, Application/X-NDJSON;q=0.9"),
+            StreamFraming::Ndjson
+        );
+    }
+}
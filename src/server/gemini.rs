@@ -0,0 +1,916 @@
+//! Gemini-compatible `POST /v1beta/models/{model}:generateContent` and `:streamGenerateContent`.
+//! Requests are translated into a Chat Completions request, so they go through the same
+//! conversion as every other front-end; replies are folded from the upstream events with the
+//! Ollama [`Translator`] and rendered as `candidates[].content.parts[]`. Streams always use the
+//! `alt=sse` framing, which is what the Gemini SDKs ask for.
+
+use std::{collections::VecDeque, convert::Infallible, time::Instant};
+
+use axum::{
+    Extension, Json,
+    body::{Body, Bytes},
+    extract::{Path, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header::CONTENT_TYPE},
+    response::{IntoResponse, Response},
+};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{Instrument, Span, info, warn};
+
+use super::{
+    access_log::AccessLog,
+    conversations::{self, USAGE_HEADER},
+    conversion,
+    executor::StreamingHandle,
+    extract::ApiJson,
+    fairness::ClientId,
+    in_flight::TrackedRequest,
+    metrics::InFlightGuard,
+    ollama::{Output, Step, Translator},
+    profiles::resolve_profile,
+    response::{ToolCall, Usage},
+    state::AppState,
+};
+use crate::{
+    clock::LogContext,
+    error::ApiError,
+    openai::{
+        chat::{
+            ChatCompletionRequest, ChatMessage, ChatToolCall, ChatToolFunction, PromptEndpoint,
+            PromptPayload, RequestTool, RequestToolFunction, log_function_tools,
+        },
+        convert::ConversionError,
+    },
+    telemetry,
+};
+
+const EVENT_STREAM: &str = "text/event-stream";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct GenerateContentRequest {
+    #[serde(default)]
+    contents: Vec<Content>,
+    #[serde(default, alias = "system_instruction")]
+    system_instruction: Option<Content>,
+    #[serde(default)]
+    tools: Vec<Tool>,
+    #[serde(default, alias = "generation_config")]
+    generation_config: Option<GenerationConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Content {
+    /// `user` or `model`; Gemini treats a missing role as `user`.
+    #[serde(default)]
+    role: Option<String>,
+    #[serde(default)]
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Part {
+    #[serde(default)]
+    text: Option<String>,
+    /// Set on replayed thought summaries, which are not sent back upstream.
+    #[serde(default)]
+    thought: bool,
+    #[serde(default, alias = "inline_data")]
+    inline_data: Option<Blob>,
+    #[serde(default, alias = "function_call")]
+    function_call: Option<FunctionCall>,
+    #[serde(default, alias = "function_response")]
+    function_response: Option<FunctionResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Blob {
+    #[serde(default, alias = "mime_type")]
+    mime_type: String,
+    #[serde(default)]
+    data: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct FunctionCall {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    args: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct FunctionResponse {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    response: Value,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Tool {
+    #[serde(default, alias = "function_declarations")]
+    function_declarations: Vec<FunctionDeclaration>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FunctionDeclaration {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
+    /// Gemini's OpenAPI subset, with upper-case type names.
+    #[serde(default)]
+    parameters: Option<Value>,
+    /// Plain JSON Schema, which newer SDKs send instead.
+    #[serde(default, alias = "parameters_json_schema")]
+    parameters_json_schema: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    #[serde(default)]
+    temperature: Option<f64>,
+    #[serde(default, alias = "top_p")]
+    top_p: Option<f64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Method {
+    Generate,
+    Stream,
+}
+
+/// Splits `models/gpt-5:generateContent` (the `models/` prefix is optional) into the model to
+/// resolve and the method.
+fn parse_target(target: &str) -> Result<(String, Method), ApiError> {
+    let target = target.trim_start_matches('/');
+    let Some((model, method)) = target.rsplit_once(':') else {
+        return Err(ApiError::not_found(format!(
+            "`{target}` names no method; expected `<model>:generateContent` or \
+             `<model>:streamGenerateContent`"
+        )));
+    };
+    let method = match method {
+        "generateContent" => Method::Generate,
+        "streamGenerateContent" => Method::Stream,
+        other => {
+            return Err(ApiError::not_found(format!(
+                "unsupported method `{other}`; expected generateContent or streamGenerateContent"
+            )));
+        }
+    };
+    let model = model.strip_prefix("models/").unwrap_or(model);
+    Ok((model.to_string(), method))
+}
+
+impl GenerateContentRequest {
+    fn into_openai(
+        self,
+        model: String,
+        stream: bool,
+    ) -> Result<ChatCompletionRequest, ConversionError> {
+        let mut messages = Vec::new();
+        if let Some(system) = self.system_instruction {
+            let text = part_texts(&system.parts).join("\n\n");
+            if !text.trim().is_empty() {
+                messages.push(text_message("system", text));
+            }
+        }
+        let mut calls = CallIds::default();
+        for (index, content) in self.contents.into_iter().enumerate() {
+            let converted = calls
+                .convert(content)
+                .map_err(|err| err.at("contents", index))?;
+            messages.extend(converted);
+        }
+
+        let tools = self
+            .tools
+            .into_iter()
+            .flat_map(|tool| tool.function_declarations)
+            .map(|declaration| RequestTool {
+                kind: "function".to_string(),
+                function: Some(RequestToolFunction {
+                    name: declaration.name,
+                    description: declaration.description,
+                    strict: None,
+                    parameters: declaration
+                        .parameters_json_schema
+                        .or(declaration.parameters.map(lowercase_types)),
+                }),
+            })
+            .collect();
+        let config = self.generation_config;
+        Ok(ChatCompletionRequest {
+            model,
+            messages,
+            stream,
+            tools,
+            parallel_tool_calls: None,
+            reasoning_effort: None,
+            reasoning: None,
+            temperature: config.as_ref().and_then(|config| config.temperature),
+            top_p: config.as_ref().and_then(|config| config.top_p),
+            verbosity: None,
+            web_search_options: None,
+            tool_choice: None,
+            prediction: None,
+            response_format: None,
+            codex: None,
+            stream_options: None,
+        })
+    }
+}
+
+/// Gemini schemas spell types `OBJECT`, `STRING`, ...; JSON Schema wants them lower-case.
+fn lowercase_types(mut schema: Value) -> Value {
+    fn walk(value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                if let Some(Value::String(kind)) = map.get_mut("type") {
+                    *kind = kind.to_ascii_lowercase();
+                }
+                map.values_mut().for_each(walk);
+            }
+            Value::Array(items) => items.iter_mut().for_each(walk),
+            _ => {}
+        }
+    }
+    walk(&mut schema);
+    schema
+}
+
+fn part_texts(parts: &[Part]) -> Vec<&str> {
+    parts
+        .iter()
+        .filter(|part| !part.thought)
+        .filter_map(|part| part.text.as_deref())
+        .collect()
+}
+
+fn text_message(role: &str, text: String) -> ChatMessage {
+    ChatMessage {
+        role: role.to_string(),
+        content: Value::String(text),
+        ..ChatMessage::default()
+    }
+}
+
+/// Gives replayed function calls ids and pairs function responses with them. Gemini clients
+/// often send neither, so calls without an id get `call_<n>` in conversation order, and a
+/// response answers the call with its id, else the oldest open call to its function, else the
+/// oldest open call.
+#[derive(Debug, Default)]
+struct CallIds {
+    generated: usize,
+    /// Calls without a response yet, oldest first, as (call id, function name).
+    open: VecDeque<(String, String)>,
+}
+
+impl CallIds {
+    /// One `contents[]` entry as chat messages: a `model` turn becomes one assistant message, a
+    /// `user` turn its function results followed by its text and images.
+    fn convert(&mut self, content: Content) -> Result<Vec<ChatMessage>, ConversionError> {
+        let role = content
+            .role
+            .as_deref()
+            .map(|role| role.trim().to_ascii_lowercase())
+            .filter(|role| !role.is_empty())
+            .unwrap_or_else(|| "user".to_string());
+        match role.as_str() {
+            "model" => Ok(vec![self.model_turn(content.parts)]),
+            "user" | "function" | "tool" => self.user_turn(content.parts),
+            other => Err(ConversionError::new(format!(
+                "unsupported role `{other}`; expected user or model"
+            ))
+            .field("role")),
+        }
+    }
+
+    fn model_turn(&mut self, parts: Vec<Part>) -> ChatMessage {
+        let text = part_texts(&parts).concat();
+        let tool_calls: Vec<ChatToolCall> = parts
+            .into_iter()
+            .filter_map(|part| part.function_call)
+            .map(|call| {
+                let id = call
+                    .id
+                    .filter(|id| !id.trim().is_empty())
+                    .unwrap_or_else(|| {
+                        self.generated += 1;
+                        format!("call_{}", self.generated)
+                    });
+                let name = call.name.trim().to_string();
+                let arguments = match call.args {
+                    Value::Null => "{}".to_string(),
+                    args => args.to_string(),
+                };
+                self.open.push_back((id.clone(), name.clone()));
+                ChatToolCall {
+                    id: Some(id),
+                    r#type: Some("function".to_string()),
+                    function: Some(ChatToolFunction {
+                        name: Some(name),
+                        arguments: Some(arguments),
+                    }),
+                }
+            })
+            .collect();
+        ChatMessage {
+            role: "assistant".to_string(),
+            content: if text.is_empty() {
+                Value::Null
+            } else {
+                Value::String(text)
+            },
+            tool_calls: (!tool_calls.is_empty()).then(|| tool_calls.into()),
+            ..ChatMessage::default()
+        }
+    }
+
+    fn user_turn(&mut self, parts: Vec<Part>) -> Result<Vec<ChatMessage>, ConversionError> {
+        let mut messages = Vec::new();
+        let mut content = Vec::new();
+        for (index, part) in parts.into_iter().enumerate() {
+            if let Some(response) = part.function_response {
+                messages.push(self.function_result(response));
+            } else if let Some(blob) = part.inline_data {
+                let url = image_data_url(&blob)
+                    .map_err(|err| err.field("inlineData").at("parts", index))?;
+                content.push(json!({"type": "image_url", "image_url": {"url": url}}));
+            } else if let Some(text) = part.text.filter(|_| !part.thought) {
+                content.push(json!({"type": "text", "text": text}));
+            }
+        }
+        if !content.is_empty() {
+            messages.push(ChatMessage {
+                role: "user".to_string(),
+                content: Value::Array(content),
+                ..ChatMessage::default()
+            });
+        }
+        Ok(messages)
+    }
+
+    fn function_result(&mut self, response: FunctionResponse) -> ChatMessage {
+        let name = response.name.trim();
+        let by_id = response
+            .id
+            .as_deref()
+            .and_then(|call_id| self.open.iter().position(|(id, _)| id == call_id.trim()));
+        let by_name = || self.open.iter().position(|(_, open)| open == name);
+        let output = match response.response {
+            Value::String(text) => text,
+            response => response.to_string(),
+        };
+        let Some((call_id, _)) = by_id
+            .or_else(by_name)
+            .or((!self.open.is_empty()).then_some(0))
+            .and_then(|position| self.open.remove(position))
+        else {
+            warn!(
+                function = name,
+                "Gemini function response follows no function call; passing it on as a user \
+                 message"
+            );
+            return text_message("user", format!("Result of function `{name}`: {output}"));
+        };
+        ChatMessage {
+            tool_call_id: Some(call_id),
+            ..text_message("tool", output)
+        }
+    }
+}
+
+/// Codex only takes images, as data URLs.
+fn image_data_url(blob: &Blob) -> Result<String, ConversionError> {
+    let mime_type = blob.mime_type.trim();
+    if !mime_type.starts_with("image/") {
+        return Err(ConversionError::new(format!(
+            "unsupported MIME type `{mime_type}`; only images can be sent inline"
+        ))
+        .field("mimeType"));
+    }
+    if blob.data.trim().is_empty() {
+        return Err(ConversionError::new("is required").field("data"));
+    }
+    Ok(format!("data:{mime_type};base64,{}", blob.data.trim()))
+}
+
+/// One `generateContent` reply, or one chunk of a `streamGenerateContent` stream.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateContentResponse<'a> {
+    candidates: [Candidate; 1],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    usage_metadata: Option<UsageMetadata>,
+    model_version: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Candidate {
+    content: CandidateContent,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    finish_reason: Option<&'static str>,
+    index: u32,
+}
+
+#[derive(Debug, Serialize)]
+struct CandidateContent {
+    role: &'static str,
+    parts: Vec<ResponsePart>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResponsePart {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    thought: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    function_call: Option<ResponseFunctionCall>,
+}
+
+#[derive(Debug, Serialize)]
+struct ResponseFunctionCall {
+    id: String,
+    name: String,
+    args: Value,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    prompt_token_count: u32,
+    candidates_token_count: u32,
+    total_token_count: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cached_content_token_count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thoughts_token_count: Option<u32>,
+}
+
+impl From<&Usage> for UsageMetadata {
+    /// Gemini counts thoughts apart from the candidates, which Codex's raw counts allow.
+    fn from(usage: &Usage) -> Self {
+        let count = |tokens: i64| u32::try_from(tokens.max(0)).unwrap_or(u32::MAX);
+        match &usage.codex {
+            Some(raw) => Self {
+                prompt_token_count: usage.prompt_tokens,
+                candidates_token_count: count(raw.output_tokens),
+                total_token_count: usage.total_tokens,
+                cached_content_token_count: Some(count(raw.cached_input_tokens)),
+                thoughts_token_count: Some(count(raw.reasoning_output_tokens)),
+            },
+            None => Self {
+                prompt_token_count: usage.prompt_tokens,
+                candidates_token_count: usage.completion_tokens,
+                total_token_count: usage.total_tokens,
+                cached_content_token_count: None,
+                thoughts_token_count: None,
+            },
+        }
+    }
+}
+
+/// Builds the reply for `output`; `usage` marks it as the final one.
+fn render(model: &str, output: Output, usage: Option<&Usage>) -> Value {
+    let mut parts = Vec::new();
+    if let Some(thinking) = output.thinking {
+        parts.push(ResponsePart {
+            text: Some(thinking),
+            thought: true,
+            ..ResponsePart::default()
+        });
+    }
+    if !output.content.is_empty() {
+        parts.push(ResponsePart {
+            text: Some(output.content),
+            ..ResponsePart::default()
+        });
+    }
+    parts.extend(output.tool_calls.into_iter().map(function_call_part));
+    if parts.is_empty() {
+        parts.push(ResponsePart {
+            text: Some(String::new()),
+            ..ResponsePart::default()
+        });
+    }
+    let record = GenerateContentResponse {
+        candidates: [Candidate {
+            content: CandidateContent {
+                role: "model",
+                parts,
+            },
+            // Gemini finishes turns that end in function calls with `STOP` too.
+            finish_reason: usage.map(|_| "STOP"),
+            index: 0,
+        }],
+        usage_metadata: usage.map(UsageMetadata::from),
+        model_version: model,
+    };
+    serde_json::to_value(record)
+        .unwrap_or_else(|err| json!({ "error": format!("failed to encode reply: {err}") }))
+}
+
+fn function_call_part(call: ToolCall) -> ResponsePart {
+    let args = serde_json::from_str(&call.function.arguments)
+        .unwrap_or(Value::String(call.function.arguments));
+    ResponsePart {
+        function_call: Some(ResponseFunctionCall {
+            id: call.id,
+            name: call.function.name,
+            args,
+        }),
+        ..ResponsePart::default()
+    }
+}
+
+fn sse_event(record: &impl Serialize) -> Bytes {
+    let mut event = b"data: ".to_vec();
+    event.extend(serde_json::to_vec(record).unwrap_or_default());
+    event.extend_from_slice(b"\r\n\r\n");
+    Bytes::from(event)
+}
+
+/// Gemini's error body: the HTTP status as `code` plus its canonical status name.
+fn error_record(err: &ApiError) -> Value {
+    let code = err.status();
+    let status = match code {
+        StatusCode::BAD_REQUEST | StatusCode::PAYLOAD_TOO_LARGE => "INVALID_ARGUMENT",
+        StatusCode::UNAUTHORIZED => "UNAUTHENTICATED",
+        StatusCode::FORBIDDEN => "PERMISSION_DENIED",
+        StatusCode::NOT_FOUND => "NOT_FOUND",
+        StatusCode::CONFLICT => "ABORTED",
+        StatusCode::TOO_MANY_REQUESTS => "RESOURCE_EXHAUSTED",
+        StatusCode::SERVICE_UNAVAILABLE | StatusCode::BAD_GATEWAY => "UNAVAILABLE",
+        StatusCode::GATEWAY_TIMEOUT => "DEADLINE_EXCEEDED",
+        _ => "INTERNAL",
+    };
+    json!({
+        "error": {
+            "code": code.as_u16(),
+            "message": err.message(),
+            "status": status,
+        }
+    })
+}
+
+fn error_response(err: ApiError) -> Response {
+    (err.status(), Json(error_record(&err))).into_response()
+}
+
+pub(super) async fn generate_content(
+    State(state): State<AppState>,
+    access_log: Option<Extension<AccessLog>>,
+    client: Option<Extension<ClientId>>,
+    Path(target): Path<String>,
+    headers: HeaderMap,
+    ApiJson(payload): ApiJson<GenerateContentRequest>,
+) -> Response {
+    let (model, method) = match parse_target(&target) {
+        Ok(target) => target,
+        Err(err) => return error_response(err),
+    };
+    let request = match payload.into_openai(model.clone(), method == Method::Stream) {
+        Ok(request) => request,
+        Err(err) => return error_response(err.into()),
+    };
+    let access_log = access_log.map(|Extension(log)| log);
+    let client = client.map(|Extension(client)| client);
+    respond(state, access_log, client, &headers, model, request)
+        .await
+        .unwrap_or_else(error_response)
+}
+
+async fn respond(
+    state: AppState,
+    access_log: Option<AccessLog>,
+    client: Option<ClientId>,
+    headers: &HeaderMap,
+    requested_model: String,
+    mut request: ChatCompletionRequest,
+) -> Result<Response, ApiError> {
+    let started = Instant::now();
+    state.ensure_authenticated()?;
+    let log_context = LogContext::current(&requested_model);
+    super::log_verbose_json(state.config(), &log_context, "gemini.request", &request);
+
+    let (profile, model) = resolve_profile(headers, &request.model, state.profiles())?;
+    request.model = model;
+
+    let stream_requested = request.stream;
+    let mut prompt_payload = conversion::convert(
+        &state,
+        request,
+        state
+            .config()
+            .conversion_options(PromptEndpoint::Instructions),
+    )
+    .await?;
+    prompt_payload.profile = profile;
+    let warnings = prompt_payload.warnings.clone();
+    if state.config().fail_on_warnings {
+        warnings.reject_any()?;
+    }
+    let budget = state.admit_tokens(&prompt_payload, client.as_ref())?;
+    state.loaded_models().touch(&prompt_payload.model);
+    if let Some(log) = &access_log {
+        log.record_request(&prompt_payload.model, stream_requested);
+    }
+    if state.config().verbose {
+        log_function_tools(&log_context, &prompt_payload.prompt.tools);
+        info!(
+            model = %prompt_payload.model,
+            stream = stream_requested,
+            "forwarding Gemini request to Codex (upstream)"
+        );
+    }
+
+    let tracked = state.track_request(&prompt_payload.model, client.as_ref(), stream_requested);
+    let account = state.usage_account(&prompt_payload.model, client, access_log.clone());
+    let conversation = state.conversation_turn(&prompt_payload);
+    let upstream = telemetry::upstream_span(&prompt_payload.model, stream_requested);
+    if stream_requested {
+        let guard = state
+            .metrics()
+            .start_stream()
+            .with_usage_account(account)
+            .with_conversation(conversation)
+            .with_budget(budget);
+        let mut response = stream_response(
+            state.clone(),
+            prompt_payload,
+            requested_model,
+            started,
+            guard,
+            tracked,
+            access_log,
+            upstream,
+        );
+        super::warnings::report(
+            state.config(),
+            &log_context,
+            "gemini.warnings",
+            &warnings,
+            &mut response,
+        );
+        return Ok(response);
+    }
+
+    let guard = state
+        .metrics()
+        .start_request()
+        .with_usage_account(account)
+        .with_conversation(conversation)
+        .with_budget(budget);
+    let collect = async {
+        let handle = state
+            .engine()
+            .stream(prompt_payload)
+            .await
+            .inspect_err(|err| state.note_upstream_error(err))?;
+        let collected = collect_events(handle, started).await;
+        state.note_upstream_outcome(&collected);
+        collected
+    };
+    let (output, usage) = tracked.run(collect.instrument(upstream.clone())).await?;
+    telemetry::record_usage(&upstream, usage.prompt_tokens, usage.completion_tokens);
+    guard.record_usage(&usage);
+    if let Some(log) = &access_log {
+        log.record_outcome(&usage, Some("stop"));
+    }
+    let record = render(&requested_model, output, Some(&usage));
+    super::log_verbose_json(state.config(), &log_context, "gemini.response", &record);
+    let mut response = Json(record).into_response();
+    if let Some(value) = conversations::usage_header(&usage) {
+        response.headers_mut().insert(USAGE_HEADER, value);
+    }
+    super::warnings::report(
+        state.config(),
+        &log_context,
+        "gemini.warnings",
+        &warnings,
+        &mut response,
+    );
+    Ok(response)
+}
+
+/// Streams SSE chunks from a spawned task, like the other streaming routes: the response goes
+/// out before the upstream handshake, and the task stops as soon as the client disconnects.
+#[allow(clippy::too_many_arguments)]
+fn stream_response(
+    state: AppState,
+    payload: PromptPayload,
+    model: String,
+    started: Instant,
+    guard: InFlightGuard,
+    tracked: TrackedRequest,
+    access_log: Option<AccessLog>,
+    upstream: Span,
+) -> Response {
+    let (tx, rx) = mpsc::channel::<Bytes>(32);
+
+    let task_log = access_log.clone();
+    let task = async move {
+        let forward = async {
+            let handle = state
+                .engine()
+                .stream(payload)
+                .await
+                .inspect_err(|err| state.note_upstream_error(err))?;
+            let forwarded = forward_events(handle, &model, started, &tx).await;
+            // A stream the client walked away from says nothing about the upstream.
+            if !tx.is_closed() {
+                state.note_upstream_outcome(&forwarded);
+            }
+            forwarded
+        };
+        let forward = tracked.run(forward);
+        tokio::select! {
+            result = forward => match result {
+                Ok(usage) => {
+                    guard.record_usage(&usage);
+                    telemetry::record_usage(
+                        &Span::current(),
+                        usage.prompt_tokens,
+                        usage.completion_tokens,
+                    );
+                    if let Some(log) = &task_log {
+                        log.record_outcome(&usage, Some("stop"));
+                    }
+                }
+                Err(err) => {
+                    warn!("Gemini streaming error: {err:?}");
+                    if let Some(log) = &task_log {
+                        log.record_finish_reason("error");
+                    }
+                    let _ = tx.send(sse_event(&error_record(&err))).await;
+                }
+            },
+            _ = tx.closed() => {
+                if let Some(log) = &task_log {
+                    log.record_finish_reason("client_disconnected");
+                }
+            }
+        }
+    };
+    tokio::spawn(task.instrument(upstream));
+
+    let body = ReceiverStream::new(rx)
+        .inspect(move |_| {
+            if let Some(log) = &access_log {
+                log.mark_first_byte();
+            }
+        })
+        .map(Ok::<_, Infallible>);
+    let mut response = Body::from_stream(body).into_response();
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(EVENT_STREAM));
+    response
+}
+
+/// Sends one chunk per upstream delta and a final chunk with the finish reason and usage.
+async fn forward_events(
+    handle: StreamingHandle,
+    model: &str,
+    started: Instant,
+    tx: &mpsc::Sender<Bytes>,
+) -> Result<Usage, ApiError> {
+    let mut stream = handle.stream;
+    let mut translator = Translator::new(started);
+
+    while let Some(event) = stream.next().await {
+        let output = match translator.translate(event)? {
+            Step::Output(output) => output,
+            Step::Completed(usage) => {
+                let record = render(model, Output::default(), Some(&usage));
+                let _ = tx.send(sse_event(&record)).await;
+                return Ok(usage);
+            }
+            Step::Skip => continue,
+        };
+        if tx
+            .send(sse_event(&render(model, output, None)))
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+    Err(ApiError::internal(
+        "Codex stream ended before the response completed",
+    ))
+}
+
+/// Folds the whole upstream stream into one output, for `generateContent`.
+async fn collect_events(
+    handle: StreamingHandle,
+    started: Instant,
+) -> Result<(Output, Usage), ApiError> {
+    let mut stream = handle.stream;
+    let mut translator = Translator::new(started);
+    let mut collected = Output::default();
+
+    while let Some(event) = stream.next().await {
+        match translator.translate(event)? {
+            Step::Output(output) => collected.append(output),
+            Step::Completed(usage) => return Ok((collected, usage)),
+            Step::Skip => {}
+        }
+    }
+    Err(ApiError::internal(
+        "Codex stream ended before the response completed",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn targets_name_a_model_and_a_method() {
+        assert_eq!(
+            parse_target("gpt-5:generateContent").unwrap(),
+            ("gpt-5".to_string(), Method::Generate)
+        );
+        assert_eq!(
+            parse_target("models/gpt-5.1-codex:high:streamGenerateContent").unwrap(),
+            ("gpt-5.1-codex:high".to_string(), Method::Stream)
+        );
+        assert!(matches!(
+            parse_target("gpt-5:countTokens"),
+            Err(ApiError::NotFound(_))
+        ));
+        assert!(matches!(parse_target("gpt-5"), Err(ApiError::NotFound(_))));
+    }
+
+    #[test]
+    fn gemini_schemas_become_json_schema() {
+        let schema = lowercase_types(json!({
+            "type": "OBJECT",
+            "properties": {"city": {"type": "STRING"}, "days": {"type": "ARRAY", "items": {"type": "INTEGER"}}},
+        }));
+        assert_eq!(
+            schema,
+            json!({
+                "type": "object",
+                "properties": {"city": {"type": "string"}, "days": {"type": "array", "items": {"type": "integer"}}},
+            })
+        );
+    }
+
+    #[test]
+    fn non_image_inline_data_is_rejected() {
+        let request: GenerateContentRequest = serde_json::from_value(json!({
+            "contents": [{"parts": [
+                {"text": "summarize"},
+                {"inline_data": {"mime_type": "application/pdf", "data": "JVBERi0="}}
+            ]}]
+        }))
+        .unwrap();
+        let err = request.into_openai("gpt-5".to_string(), false).unwrap_err();
+        assert_eq!(
+            err.path(),
+            "contents[0].parts[1].inlineData.mimeType",
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn usage_metadata_splits_out_thoughts() {
+        let usage = Usage::from(codex_core::protocol::TokenUsage {
+            input_tokens: 10,
+            cached_input_tokens: 30,
+            output_tokens: 5,
+            reasoning_output_tokens: 7,
+            total_tokens: 52,
+        });
+        assert_eq!(
+            serde_json::to_value(UsageMetadata::from(&usage)).unwrap(),
+            json!({
+                "promptTokenCount": 40,
+                "candidatesTokenCount": 5,
+                "totalTokenCount": 52,
+                "cachedContentTokenCount": 30,
+                "thoughtsTokenCount": 7,
+            })
+        );
+    }
+}
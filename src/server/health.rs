@@ -0,0 +1,155 @@
+//! Component statuses for `/healthz`: whether Codex auth, the upstream and the configuration are
+//! each `ok`, `degraded` or `failing`, so a dashboard can tell "fine" from "logged in, but the
+//! last calls failed". `/healthz` answers `200` whatever they say.
+
+use std::{
+    collections::VecDeque,
+    sync::{Mutex, MutexGuard},
+    time::SystemTime,
+};
+
+use serde::Serialize;
+
+use crate::clock::rfc3339_nanos;
+
+/// Upstream calls the success rate is taken over.
+pub(super) const UPSTREAM_WINDOW: usize = 20;
+
+/// Ordered from best to worst, so the overall status is the greatest of the components'.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum ComponentStatus {
+    Ok,
+    Degraded,
+    Failing,
+}
+
+/// The outcomes of the latest upstream calls, shared by every clone of `AppState`.
+#[derive(Debug, Default)]
+pub(super) struct UpstreamHealth {
+    record: Mutex<UpstreamRecord>,
+}
+
+#[derive(Debug, Default)]
+struct UpstreamRecord {
+    /// `true` for each call that succeeded, oldest first.
+    outcomes: VecDeque<bool>,
+    last_error: Option<UpstreamError>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(super) struct UpstreamError {
+    message: String,
+    at: String,
+}
+
+/// The `upstream` component of `/healthz`.
+#[derive(Debug, Serialize)]
+pub(super) struct UpstreamComponent {
+    status: ComponentStatus,
+    /// Calls the success rate covers, at most [`UPSTREAM_WINDOW`].
+    requests: usize,
+    /// Absent until the first call.
+    success_rate: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_error: Option<UpstreamError>,
+}
+
+impl UpstreamComponent {
+    pub(super) fn status(&self) -> ComponentStatus {
+        self.status
+    }
+}
+
+impl UpstreamHealth {
+    pub(super) fn record_success(&self) {
+        self.push(true);
+    }
+
+    pub(super) fn record_failure(&self, message: &str) {
+        self.record().last_error = Some(UpstreamError {
+            message: message.to_string(),
+            at: rfc3339_nanos(SystemTime::now()),
+        });
+        self.push(false);
+    }
+
+    /// `ok` while every call in the window succeeded, `failing` once fewer than half did, and
+    /// `degraded` in between. The last error is kept after it leaves the window.
+    pub(super) fn snapshot(&self) -> UpstreamComponent {
+        let record = self.record();
+        let requests = record.outcomes.len();
+        let succeeded = record.outcomes.iter().filter(|ok| **ok).count();
+        let status = if succeeded == requests {
+            ComponentStatus::Ok
+        } else if succeeded * 2 < requests {
+            ComponentStatus::Failing
+        } else {
+            ComponentStatus::Degraded
+        };
+        UpstreamComponent {
+            status,
+            requests,
+            success_rate: (requests > 0).then(|| succeeded as f64 / requests as f64),
+            last_error: record.last_error.clone(),
+        }
+    }
+
+    fn push(&self, succeeded: bool) {
+        let mut record = self.record();
+        if record.outcomes.len() == UPSTREAM_WINDOW {
+            record.outcomes.pop_front();
+        }
+        record.outcomes.push_back(succeeded);
+    }
+
+    fn record(&self) -> MutexGuard<'_, UpstreamRecord> {
+        self.record
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_status_follows_the_recent_success_rate() {
+        let health = UpstreamHealth::default();
+        let snapshot = health.snapshot();
+        assert_eq!(snapshot.status, ComponentStatus::Ok);
+        assert_eq!(snapshot.success_rate, None);
+
+        health.record_success();
+        health.record_failure("Codex usage limit reached");
+        let snapshot = health.snapshot();
+        assert_eq!(snapshot.status, ComponentStatus::Degraded);
+        assert_eq!(snapshot.success_rate, Some(0.5));
+
+        health.record_failure("Codex usage limit reached");
+        assert_eq!(health.snapshot().status, ComponentStatus::Failing);
+
+        // A window of successes pushes the failures out, but the last error stays visible.
+        for _ in 0..UPSTREAM_WINDOW {
+            health.record_success();
+        }
+        let snapshot = health.snapshot();
+        assert_eq!(snapshot.status, ComponentStatus::Ok);
+        assert_eq!(snapshot.requests, UPSTREAM_WINDOW);
+        assert_eq!(
+            snapshot.last_error.map(|error| error.message).as_deref(),
+            Some("Codex usage limit reached")
+        );
+    }
+
+    #[test]
+    fn the_worst_status_wins() {
+        let statuses = [
+            ComponentStatus::Ok,
+            ComponentStatus::Failing,
+            ComponentStatus::Degraded,
+        ];
+        assert_eq!(statuses.into_iter().max(), Some(ComponentStatus::Failing));
+    }
+}
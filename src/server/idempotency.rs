@@ -0,0 +1,331 @@
+//! Duplicate request suppression for `/v1/chat/completions`. A request that carries an
+//! `Idempotency-Key`, or failing that a client-chosen `X-Request-Id` (which the OpenAI SDKs keep
+//! when they retry), holds its key while it runs. A repeat of a non-streaming request waits for
+//! the original and gets the same reply instead of starting a second generation; a repeat of a
+//! streaming request is refused with a 409 naming the original, since a stream cannot be shared.
+//! Finished non-streaming replies are replayed for [`REPLAY_TTL`]. Reusing an `Idempotency-Key`
+//! for a different body is refused; an `X-Request-Id` only stands in for one, so a different body
+//! under it is a new request.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Json,
+    body::Body,
+    http::{HeaderMap, HeaderName, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use futures_util::StreamExt;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::sync::watch;
+use tracing::warn;
+
+use super::middleware::REQUEST_ID_HEADER;
+use crate::error::ApiError;
+
+pub const IDEMPOTENCY_KEY_HEADER: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// Set on replies that were replayed rather than generated for the request.
+pub const IDEMPOTENT_REPLAYED_HEADER: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+/// How long a finished reply is replayed to repeats of its request.
+const REPLAY_TTL: Duration = Duration::from_secs(120);
+
+/// Keys tracked at once; past this, new keys are served without suppression.
+const MAX_KEYS: usize = 1024;
+
+/// A request's key, and whether it came from an `Idempotency-Key` rather than an
+/// `X-Request-Id`.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) struct RequestKey {
+    pub(super) key: String,
+    pub(super) explicit: bool,
+}
+
+/// The key of a request, if the client sent one.
+pub(super) fn request_key(headers: &HeaderMap) -> Option<RequestKey> {
+    [(&IDEMPOTENCY_KEY_HEADER, true), (&REQUEST_ID_HEADER, false)]
+        .into_iter()
+        .find_map(|(header, explicit)| {
+            let key = headers
+                .get(header)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())?;
+            Some(RequestKey {
+                key: key.to_string(),
+                explicit,
+            })
+        })
+}
+
+/// Answers a repeat with the original reply.
+pub(super) fn replay(response: Value) -> Response {
+    let mut response = Json(response).into_response();
+    response
+        .headers_mut()
+        .insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+    response
+}
+
+/// Keeps `guard` until the body of the streamed `response` ends.
+pub(super) fn hold_for_body(response: Response, guard: KeyGuard) -> Response {
+    let (parts, body) = response.into_parts();
+    let body = body.into_data_stream().map(move |chunk| {
+        let _held = &guard;
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(body))
+}
+
+/// Identifies a request body, so a key reused for a different request is caught.
+pub(super) fn fingerprint(body: &impl serde::Serialize) -> [u8; 32] {
+    Sha256::digest(serde_json::to_vec(body).unwrap_or_default()).into()
+}
+
+#[derive(Default)]
+pub(super) struct IdempotencyKeys {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+struct Entry {
+    fingerprint: [u8; 32],
+    request_id: String,
+    state: EntryState,
+}
+
+enum EntryState {
+    /// Repeats of a non-streaming request wait on `done`; streams have nothing to share.
+    Running {
+        done: Option<watch::Receiver<Option<Value>>>,
+    },
+    Finished {
+        response: Value,
+        expires_at: Instant,
+    },
+}
+
+/// What a request with a key should do.
+pub(super) enum Claim {
+    /// Run the request; the guard holds the key until it is finished or dropped.
+    Run(KeyGuard),
+    /// Answer with the original request's reply.
+    Replay(Value),
+    /// Too many keys are tracked; run the request without suppression.
+    Untracked,
+}
+
+impl IdempotencyKeys {
+    /// Claims `key` for the request `request_id`, waiting for a running original when the
+    /// request is a non-streaming repeat.
+    pub(super) async fn claim(
+        self: &Arc<Self>,
+        RequestKey { key, explicit }: &RequestKey,
+        fingerprint: [u8; 32],
+        request_id: &str,
+        stream: bool,
+    ) -> Result<Claim, ApiError> {
+        loop {
+            let mut done = {
+                let mut entries = self.entries();
+                let now = Instant::now();
+                if entries.get(key).is_some_and(|entry| entry.expired(now)) {
+                    entries.remove(key);
+                }
+                match entries.get(key) {
+                    // A request id reused for another body is a new request, not a retry.
+                    Some(entry) if entry.fingerprint != fingerprint && !explicit => {
+                        return Ok(Claim::Untracked);
+                    }
+                    Some(entry) if entry.fingerprint != fingerprint => {
+                        return Err(ApiError::conflict(
+                            format!(
+                                "Idempotency key `{key}` was already used for a different request"
+                            ),
+                            Some(entry.request_id.clone()),
+                        ));
+                    }
+                    Some(Entry {
+                        state: EntryState::Finished { response, .. },
+                        ..
+                    }) => return Ok(Claim::Replay(response.clone())),
+                    Some(Entry {
+                        state: EntryState::Running { done: Some(done) },
+                        ..
+                    }) => done.clone(),
+                    Some(entry) => {
+                        return Err(ApiError::conflict(
+                            format!(
+                                "Request `{}` with idempotency key `{key}` is still streaming; \
+                                 retry once it has finished",
+                                entry.request_id
+                            ),
+                            Some(entry.request_id.clone()),
+                        ));
+                    }
+                    None => {
+                        if entries.len() >= MAX_KEYS {
+                            entries.retain(|_, entry| !entry.expired(now));
+                        }
+                        if entries.len() >= MAX_KEYS {
+                            warn!(
+                                max_keys = MAX_KEYS,
+                                "too many idempotency keys in use; not suppressing duplicates"
+                            );
+                            return Ok(Claim::Untracked);
+                        }
+                        let (sender, receiver) = watch::channel(None);
+                        entries.insert(
+                            key.clone(),
+                            Entry {
+                                fingerprint,
+                                request_id: request_id.to_string(),
+                                state: EntryState::Running {
+                                    done: (!stream).then_some(receiver),
+                                },
+                            },
+                        );
+                        return Ok(Claim::Run(KeyGuard {
+                            keys: Arc::clone(self),
+                            key: key.clone(),
+                            done: (!stream).then_some(sender),
+                        }));
+                    }
+                }
+            };
+            // A closed channel means the original failed or was cancelled: claim the key anew.
+            if let Ok(response) = done.wait_for(Option::is_some).await
+                && let Some(response) = response.as_ref()
+            {
+                return Ok(Claim::Replay(response.clone()));
+            }
+        }
+    }
+
+    fn entries(&self) -> MutexGuard<'_, HashMap<String, Entry>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Entry {
+    fn expired(&self, now: Instant) -> bool {
+        matches!(self.state, EntryState::Finished { expires_at, .. } if expires_at <= now)
+    }
+}
+
+/// A claimed key. Dropping it without [`KeyGuard::finish`] releases the key, so a failed or
+/// cancelled request can be retried; streams hold it until their body ends.
+pub(super) struct KeyGuard {
+    keys: Arc<IdempotencyKeys>,
+    key: String,
+    done: Option<watch::Sender<Option<Value>>>,
+}
+
+impl KeyGuard {
+    /// Hands a non-streaming reply to waiting repeats and keeps it for later ones.
+    pub(super) fn finish(mut self, response: &Value) {
+        let Some(done) = self.done.take() else {
+            return;
+        };
+        if let Some(entry) = self.keys.entries().get_mut(&self.key) {
+            entry.state = EntryState::Finished {
+                response: response.clone(),
+                expires_at: Instant::now() + REPLAY_TTL,
+            };
+        }
+        done.send_replace(Some(response.clone()));
+    }
+}
+
+impl Drop for KeyGuard {
+    fn drop(&mut self) {
+        let mut entries = self.keys.entries();
+        if entries
+            .get(&self.key)
+            .is_some_and(|entry| matches!(entry.state, EntryState::Running { .. }))
+        {
+            entries.remove(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{HeaderValue, StatusCode};
+    use serde_json::json;
+
+    fn key(key: &str, explicit: bool) -> RequestKey {
+        RequestKey {
+            key: key.to_string(),
+            explicit,
+        }
+    }
+
+    #[test]
+    fn idempotency_key_wins_over_request_id() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(request_key(&headers), None);
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_static("req-1"));
+        assert_eq!(request_key(&headers), Some(key("req-1", false)));
+        headers.insert(IDEMPOTENCY_KEY_HEADER, HeaderValue::from_static(" key-1 "));
+        assert_eq!(request_key(&headers), Some(key("key-1", true)));
+    }
+
+    #[tokio::test]
+    async fn failed_requests_release_their_key() {
+        let keys = Arc::new(IdempotencyKeys::default());
+        let k = key("k", true);
+        let body = fingerprint(&json!({"stream": false}));
+        let Ok(Claim::Run(guard)) = keys.claim(&k, body, "req-1", false).await else {
+            panic!("first claim runs");
+        };
+        drop(guard);
+
+        let Ok(Claim::Run(guard)) = keys.claim(&k, body, "req-2", false).await else {
+            panic!("released key is claimed again");
+        };
+        guard.finish(&json!({"id": "resp"}));
+        assert!(matches!(
+            keys.claim(&k, body, "req-3", false).await,
+            Ok(Claim::Replay(response)) if response == json!({"id": "resp"})
+        ));
+
+        let Err(err) = keys
+            .claim(&k, fingerprint(&json!({"stream": true})), "req-4", true)
+            .await
+        else {
+            panic!("a different body is refused");
+        };
+        assert_eq!(err.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn a_request_id_reused_for_another_body_is_a_new_request() {
+        let keys = Arc::new(IdempotencyKeys::default());
+        let k = key("req-1", false);
+        let body = fingerprint(&json!({"stream": false}));
+        let Ok(Claim::Run(guard)) = keys.claim(&k, body, "req-1", false).await else {
+            panic!("first claim runs");
+        };
+        guard.finish(&json!({"id": "resp"}));
+
+        let other = fingerprint(&json!({"stream": false, "model": "gpt-5"}));
+        assert!(matches!(
+            keys.claim(&k, other, "req-2", false).await,
+            Ok(Claim::Untracked)
+        ));
+        // The original's reply is still replayed to its own retries.
+        assert!(matches!(
+            keys.claim(&k, body, "req-3", false).await,
+            Ok(Claim::Replay(response)) if response == json!({"id": "resp"})
+        ));
+    }
+}
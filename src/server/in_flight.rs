@@ -0,0 +1,194 @@
+//! Chat requests that are running right now, for `GET /admin/requests`, and the cancellation an
+//! operator fires through `POST /admin/requests/{id}/cancel`.
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{
+        Arc, Mutex, MutexGuard,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::Serialize;
+use tokio::sync::watch;
+
+use super::fairness::ClientId;
+use crate::{clock::rfc3339_nanos, error::ApiError};
+
+/// Every tracked request, oldest first. Requests are keyed by registration order rather than by
+/// request id, since a client-chosen `X-Request-Id` need not be unique.
+#[derive(Debug, Default)]
+pub(super) struct InFlightRequests {
+    next: AtomicU64,
+    requests: Mutex<BTreeMap<u64, Entry>>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    request_id: String,
+    model: String,
+    client: Option<String>,
+    stream: bool,
+    started_at: SystemTime,
+    started: Instant,
+    cancel: watch::Sender<bool>,
+}
+
+/// One running request as `GET /admin/requests` lists it.
+#[derive(Debug, Serialize)]
+pub(super) struct RequestSummary {
+    request_id: String,
+    model: String,
+    /// Who the request is booked to, when the server identifies clients.
+    client: Option<String>,
+    stream: bool,
+    started_at: String,
+    elapsed_ms: u64,
+}
+
+impl InFlightRequests {
+    /// Tracks a request until the returned handle is dropped.
+    pub(super) fn track(
+        self: &Arc<Self>,
+        request_id: String,
+        model: &str,
+        client: Option<&ClientId>,
+        stream: bool,
+    ) -> TrackedRequest {
+        let key = self.next.fetch_add(1, Ordering::Relaxed);
+        let (cancel, cancelled) = watch::channel(false);
+        self.requests().insert(
+            key,
+            Entry {
+                request_id,
+                model: model.to_string(),
+                client: client.map(|client| client.name.clone()),
+                stream,
+                started_at: SystemTime::now(),
+                started: Instant::now(),
+                cancel,
+            },
+        );
+        TrackedRequest {
+            requests: Arc::clone(self),
+            key,
+            cancelled,
+        }
+    }
+
+    pub(super) fn list(&self) -> Vec<RequestSummary> {
+        self.requests()
+            .values()
+            .map(|entry| RequestSummary {
+                request_id: entry.request_id.clone(),
+                model: entry.model.clone(),
+                client: entry.client.clone(),
+                stream: entry.stream,
+                started_at: rfc3339_nanos(entry.started_at),
+                elapsed_ms: duration_ms(entry.started.elapsed()),
+            })
+            .collect()
+    }
+
+    /// Cancels every running request with `request_id`, returning how many there were.
+    pub(super) fn cancel(&self, request_id: &str) -> usize {
+        let requests = self.requests();
+        let matching = requests
+            .values()
+            .filter(|entry| entry.request_id == request_id);
+        let mut cancelled = 0;
+        for entry in matching {
+            entry.cancel.send_replace(true);
+            cancelled += 1;
+        }
+        cancelled
+    }
+
+    fn requests(&self) -> MutexGuard<'_, BTreeMap<u64, Entry>> {
+        self.requests
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// A request's place in [`InFlightRequests`]; dropping it takes the request off the list.
+#[derive(Debug)]
+pub(super) struct TrackedRequest {
+    requests: Arc<InFlightRequests>,
+    key: u64,
+    cancelled: watch::Receiver<bool>,
+}
+
+impl TrackedRequest {
+    /// Runs `work` unless the request is cancelled first. Cancelling drops `work`, and with it
+    /// any upstream stream it holds, and fails the request with [`ApiError::Cancelled`].
+    pub(super) async fn run<T>(
+        &self,
+        work: impl Future<Output = Result<T, ApiError>>,
+    ) -> Result<T, ApiError> {
+        let mut cancelled = self.cancelled.clone();
+        tokio::select! {
+            result = work => result,
+            // The sender lives in the registry entry, which outlives `self`.
+            _ = cancelled.wait_for(|cancelled| *cancelled) => Err(ApiError::cancelled(
+                "The request was cancelled by the server operator.",
+            )),
+        }
+    }
+}
+
+impl Drop for TrackedRequest {
+    fn drop(&mut self) {
+        self.requests.requests().remove(&self.key);
+    }
+}
+
+fn duration_ms(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::pending;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn requests_are_listed_until_dropped() {
+        let requests = Arc::new(InFlightRequests::default());
+        let client = ClientId::new("user:alice".to_string());
+        let first = requests.track("req_1".to_string(), "gpt-5", Some(&client), true);
+        let second = requests.track("req_2".to_string(), "gpt-5-codex", None, false);
+
+        let listed = requests.list();
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].request_id, "req_1");
+        assert_eq!(listed[0].client.as_deref(), Some("user:alice"));
+        assert!(listed[0].stream);
+        assert_eq!(listed[1].model, "gpt-5-codex");
+
+        drop(first);
+        let listed = requests.list();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].request_id, "req_2");
+        drop(second);
+        assert!(requests.list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn cancelling_fails_the_running_work() {
+        let requests = Arc::new(InFlightRequests::default());
+        let tracked = requests.track("req_1".to_string(), "gpt-5", None, true);
+        assert_eq!(requests.cancel("req_other"), 0);
+        assert_eq!(requests.cancel("req_1"), 1);
+
+        // A request cancelled before its work starts still stops it.
+        let result = tracked.run(pending::<Result<(), ApiError>>()).await;
+        assert!(matches!(result, Err(ApiError::Cancelled(_))));
+
+        let untouched = requests.track("req_2".to_string(), "gpt-5", None, false);
+        assert_eq!(untouched.run(async { Ok(7) }).await.unwrap(), 7);
+    }
+}
@@ -0,0 +1,199 @@
+//! `--keepalive-interval`: a background task that runs [`ChatExecutor::keepalive`] while the
+//! server is idle, so the first request after a quiet spell does not pay for the credential and
+//! config loading. Failures are logged once, back the task off, and show up on `/healthz`; they
+//! never reach a request or stop the server.
+//!
+//! [`ChatExecutor::keepalive`]: super::ChatExecutor::keepalive
+
+use std::{
+    panic::AssertUnwindSafe,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, SystemTime},
+};
+
+use futures_util::FutureExt;
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use super::SharedChatExecutor;
+use crate::{clock::rfc3339_nanos, error::ApiError};
+
+/// A failing keepalive doubles its wait up to this many times (8x the interval).
+const MAX_BACKOFF_DOUBLINGS: u32 = 3;
+
+/// The keepalive task of a state, if one was started, and what it last did.
+#[derive(Default)]
+pub(super) struct Keepalive {
+    status: Arc<Mutex<Option<KeepaliveStatus>>>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+/// The keepalive as `/healthz` reports it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct KeepaliveStatus {
+    pub interval_secs: u64,
+    /// When the last keepalive finished (RFC 3339, UTC); absent until the first one runs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_run: Option<String>,
+    /// `ok` or `error`; absent until the first keepalive runs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_result: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub consecutive_failures: u32,
+}
+
+impl Keepalive {
+    /// Runs `engine`'s keepalive every `interval` until [`Keepalive::stop`], replacing any task
+    /// already running.
+    pub(super) fn start(&self, engine: SharedChatExecutor, interval: Duration) {
+        *lock(&self.status) = Some(KeepaliveStatus {
+            interval_secs: interval.as_secs(),
+            last_run: None,
+            last_result: None,
+            error: None,
+            consecutive_failures: 0,
+        });
+        let task = tokio::spawn(run(engine, interval, Arc::clone(&self.status)));
+        if let Some(previous) = lock(&self.task).replace(task) {
+            previous.abort();
+        }
+    }
+
+    /// Cancels the task; a keepalive in flight is dropped with it.
+    pub(super) fn stop(&self) {
+        if let Some(task) = lock(&self.task).take() {
+            task.abort();
+        }
+    }
+
+    pub(super) fn status(&self) -> Option<KeepaliveStatus> {
+        lock(&self.status).clone()
+    }
+}
+
+impl Drop for Keepalive {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+async fn run(
+    engine: SharedChatExecutor,
+    interval: Duration,
+    status: Arc<Mutex<Option<KeepaliveStatus>>>,
+) {
+    let mut failures = 0u32;
+    loop {
+        let backoff = 2u32.pow(failures.min(MAX_BACKOFF_DOUBLINGS));
+        tokio::time::sleep(interval.saturating_mul(backoff)).await;
+        // A panicking executor counts as a failed keepalive rather than ending the task.
+        let result = AssertUnwindSafe(engine.keepalive())
+            .catch_unwind()
+            .await
+            .unwrap_or_else(|_| Err(ApiError::internal("the keepalive panicked")));
+        let error = match result {
+            Ok(()) => {
+                if failures > 0 {
+                    info!(failures, "upstream keepalive recovered");
+                }
+                failures = 0;
+                None
+            }
+            Err(err) => {
+                failures = failures.saturating_add(1);
+                let message = err.message().to_string();
+                if failures == 1 {
+                    warn!(error = %message, "upstream keepalive failed; backing off");
+                } else {
+                    debug!(error = %message, failures, "upstream keepalive still failing");
+                }
+                Some(message)
+            }
+        };
+        if let Some(status) = lock(&status).as_mut() {
+            status.last_run = Some(rfc3339_nanos(SystemTime::now()));
+            status.last_result = Some(if error.is_some() { "error" } else { "ok" });
+            status.error = error;
+            status.consecutive_failures = failures;
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::{
+        openai::chat::PromptPayload,
+        server::{
+            executor::{ChatExecutor, StreamingHandle},
+            latency::CompleteLatency,
+            response::ChatCompletionResponse,
+        },
+    };
+
+    /// Panics on its first keepalive and fails every later one.
+    #[derive(Default)]
+    struct FailingKeepalive {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl ChatExecutor for FailingKeepalive {
+        async fn complete(&self, _: PromptPayload) -> Result<ChatCompletionResponse, ApiError> {
+            Err(ApiError::internal("unused"))
+        }
+
+        async fn complete_observed(
+            &self,
+            _: PromptPayload,
+            _: &CompleteLatency,
+        ) -> Result<ChatCompletionResponse, ApiError> {
+            Err(ApiError::internal("unused"))
+        }
+
+        async fn stream(&self, _: PromptPayload) -> Result<StreamingHandle, ApiError> {
+            Err(ApiError::internal("unused"))
+        }
+
+        async fn keepalive(&self) -> Result<(), ApiError> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                panic!("first keepalive");
+            }
+            Err(ApiError::service_unavailable("upstream is down"))
+        }
+    }
+
+    #[tokio::test]
+    async fn failures_are_reported_and_do_not_end_the_task() {
+        let keepalive = Keepalive::default();
+        keepalive.start(
+            Arc::new(FailingKeepalive::default()),
+            Duration::from_millis(10),
+        );
+        let status = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                match keepalive.status() {
+                    Some(status) if status.consecutive_failures >= 2 => break status,
+                    _ => tokio::time::sleep(Duration::from_millis(5)).await,
+                }
+            }
+        })
+        .await
+        .expect("the task should outlive a panic and keep retrying");
+        assert_eq!(status.last_result, Some("error"));
+        assert_eq!(status.error.as_deref(), Some("upstream is down"));
+        keepalive.stop();
+    }
+}
@@ -0,0 +1,531 @@
+//! Time to first token and output tokens per second per model, measured on the upstream event
+//! streams of both streaming and non-streaming requests, for `/stats/latency` and as Prometheus
+//! histograms on `/metrics`. Streams that fail or are dropped before `Completed` are only counted,
+//! so they do not skew the percentiles.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write,
+    sync::{
+        Arc, Mutex, MutexGuard,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Instant,
+};
+
+use codex_core::ResponseEvent;
+use futures_util::StreamExt;
+use serde::Serialize;
+
+use super::{
+    executor::{SharedChatExecutor, StreamingHandle, delegate_executor},
+    response::ChatCompletionResponse,
+};
+use crate::{error::ApiError, openai::chat::PromptPayload};
+
+/// Completed streams per model the percentiles are taken over, most recent first.
+const SAMPLES: usize = 500;
+
+/// Upper bounds of the time to first token histogram buckets, in seconds.
+const TTFT_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+/// Upper bounds of the output tokens per second histogram buckets.
+const TOKENS_PER_SECOND_BUCKETS: &[f64] = &[5.0, 10.0, 25.0, 50.0, 100.0, 200.0, 500.0];
+
+/// Per-model latency samples, shared by every clone of `AppState`.
+#[derive(Debug, Default)]
+pub(super) struct LatencyStats {
+    models: Mutex<BTreeMap<String, ModelLatency>>,
+}
+
+#[derive(Debug)]
+struct ModelLatency {
+    completed: u64,
+    failed: u64,
+    cancelled: u64,
+    ttft_ms: Samples,
+    tokens_per_second: Samples,
+    ttft_seconds_histogram: Histogram,
+    tokens_per_second_histogram: Histogram,
+}
+
+#[derive(Debug, Default)]
+struct Samples(VecDeque<f64>);
+
+/// Every sample since startup, counted into fixed buckets as Prometheus histograms are.
+#[derive(Debug)]
+struct Histogram {
+    bounds: &'static [f64],
+    /// Samples per bucket, not cumulative; the last one counts those above every bound.
+    counts: Vec<u64>,
+    sum: f64,
+}
+
+/// One model's entry in `/stats/latency`. The percentiles are absent until a completed stream
+/// provides a sample.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(super) struct ModelLatencySnapshot {
+    pub(super) completed: u64,
+    pub(super) failed: u64,
+    pub(super) cancelled: u64,
+    pub(super) ttft_ms: Option<Percentiles>,
+    pub(super) tokens_per_second: Option<Percentiles>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub(super) struct Percentiles {
+    pub(super) p50: f64,
+    pub(super) p95: f64,
+}
+
+/// How one observed stream ended.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Outcome {
+    Completed {
+        ttft_ms: Option<f64>,
+        tokens_per_second: Option<f64>,
+    },
+    Failed,
+    Cancelled,
+}
+
+impl LatencyStats {
+    pub(super) fn snapshot(&self) -> BTreeMap<String, ModelLatencySnapshot> {
+        self.models()
+            .iter()
+            .map(|(model, latency)| {
+                let snapshot = ModelLatencySnapshot {
+                    completed: latency.completed,
+                    failed: latency.failed,
+                    cancelled: latency.cancelled,
+                    ttft_ms: latency.ttft_ms.percentiles(),
+                    tokens_per_second: latency.tokens_per_second.percentiles(),
+                };
+                (model.clone(), snapshot)
+            })
+            .collect()
+    }
+
+    fn record(&self, model: &str, outcome: Outcome) {
+        let mut models = self.models();
+        let latency = models.entry(model.to_string()).or_default();
+        match outcome {
+            Outcome::Completed {
+                ttft_ms,
+                tokens_per_second,
+            } => {
+                latency.completed += 1;
+                if let Some(ttft_ms) = ttft_ms {
+                    latency.ttft_ms.push(ttft_ms);
+                    latency.ttft_seconds_histogram.observe(ttft_ms / 1000.0);
+                }
+                if let Some(rate) = tokens_per_second {
+                    latency.tokens_per_second.push(rate);
+                    latency.tokens_per_second_histogram.observe(rate);
+                }
+            }
+            Outcome::Failed => latency.failed += 1,
+            Outcome::Cancelled => latency.cancelled += 1,
+        }
+    }
+
+    /// The histograms and outcome counters of every model in the Prometheus text format.
+    pub(super) fn prometheus(&self) -> String {
+        let models = self.models();
+        let mut out = String::new();
+        let metric = |out: &mut String, name: &str, kind: &str, help: &str| {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+        };
+
+        let name = "codex_serve_time_to_first_token_seconds";
+        metric(
+            &mut out,
+            name,
+            "histogram",
+            "Time from a request's start to its first token.",
+        );
+        for (model, latency) in models.iter() {
+            latency.ttft_seconds_histogram.render(&mut out, name, model);
+        }
+        let name = "codex_serve_output_tokens_per_second";
+        metric(
+            &mut out,
+            name,
+            "histogram",
+            "Output tokens per second after the first token.",
+        );
+        for (model, latency) in models.iter() {
+            latency
+                .tokens_per_second_histogram
+                .render(&mut out, name, model);
+        }
+        let name = "codex_serve_latency_requests_total";
+        metric(
+            &mut out,
+            name,
+            "counter",
+            "Observed requests by how their upstream stream ended.",
+        );
+        for (model, latency) in models.iter() {
+            let model = label_value(model);
+            for (outcome, count) in [
+                ("completed", latency.completed),
+                ("failed", latency.failed),
+                ("cancelled", latency.cancelled),
+            ] {
+                let _ = writeln!(
+                    out,
+                    "{name}{{model=\"{model}\",outcome=\"{outcome}\"}} {count}"
+                );
+            }
+        }
+        out
+    }
+
+    fn models(&self) -> MutexGuard<'_, BTreeMap<String, ModelLatency>> {
+        self.models
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Default for ModelLatency {
+    fn default() -> Self {
+        Self {
+            completed: 0,
+            failed: 0,
+            cancelled: 0,
+            ttft_ms: Samples::default(),
+            tokens_per_second: Samples::default(),
+            ttft_seconds_histogram: Histogram::new(TTFT_BUCKETS),
+            tokens_per_second_histogram: Histogram::new(TOKENS_PER_SECOND_BUCKETS),
+        }
+    }
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len() + 1],
+            sum: 0.0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        let bucket = self.bounds.partition_point(|bound| *bound < value);
+        self.counts[bucket] += 1;
+        self.sum += value;
+    }
+
+    /// Appends the `_bucket`, `_sum` and `_count` series of `model` under `name`.
+    fn render(&self, out: &mut String, name: &str, model: &str) {
+        let model = label_value(model);
+        let mut cumulative = 0;
+        let bounds = self.bounds.iter().map(f64::to_string);
+        for (le, count) in bounds.chain(["+Inf".to_string()]).zip(&self.counts) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{model=\"{model}\",le=\"{le}\"}} {cumulative}"
+            );
+        }
+        let _ = writeln!(out, "{name}_sum{{model=\"{model}\"}} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{{model=\"{model}\"}} {cumulative}");
+    }
+}
+
+/// `value` escaped for a Prometheus label.
+fn label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Samples {
+    fn push(&mut self, value: f64) {
+        if self.0.len() == SAMPLES {
+            self.0.pop_front();
+        }
+        self.0.push_back(value);
+    }
+
+    /// Nearest-rank percentiles.
+    fn percentiles(&self) -> Option<Percentiles> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<f64> = self.0.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let rank = |p: f64| sorted[((p * sorted.len() as f64).ceil() as usize).max(1) - 1];
+        Some(Percentiles {
+            p50: rank(0.5),
+            p95: rank(0.95),
+        })
+    }
+}
+
+/// The timing one or more upstream streams showed.
+#[derive(Debug)]
+struct Timing {
+    started: Instant,
+    first_token: Option<Instant>,
+    completed: Option<Outcome>,
+}
+
+impl Timing {
+    fn new(started: Instant) -> Self {
+        Self {
+            started,
+            first_token: None,
+            completed: None,
+        }
+    }
+
+    /// The first text delta or output item is the first token; `Completed` ends the delta span
+    /// its output tokens are divided by.
+    fn see(&mut self, event: &ResponseEvent) {
+        if self.completed.is_some() {
+            return;
+        }
+        match event {
+            ResponseEvent::OutputTextDelta(_)
+            | ResponseEvent::OutputItemAdded(_)
+            | ResponseEvent::OutputItemDone(_) => {
+                self.first_token.get_or_insert_with(Instant::now);
+            }
+            ResponseEvent::Completed { token_usage, .. } => {
+                let tokens_per_second = self.first_token.and_then(|first| {
+                    let span = first.elapsed().as_secs_f64();
+                    let tokens = token_usage.as_ref()?.output_tokens;
+                    (span > 0.0 && tokens > 0).then(|| tokens as f64 / span)
+                });
+                self.completed = Some(Outcome::Completed {
+                    ttft_ms: self
+                        .first_token
+                        .map(|first| (first - self.started).as_secs_f64() * 1000.0),
+                    tokens_per_second,
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Watches one upstream stream and books how it ended when it is dropped.
+struct Probe {
+    stats: Arc<LatencyStats>,
+    model: String,
+    timing: Timing,
+    failed: bool,
+}
+
+impl Probe {
+    fn see(&mut self, event: &Result<ResponseEvent, codex_core::error::CodexErr>) {
+        if self.failed {
+            return;
+        }
+        match event {
+            Ok(event) => self.timing.see(event),
+            Err(_) => self.failed = self.timing.completed.is_none(),
+        }
+    }
+}
+
+impl Drop for Probe {
+    fn drop(&mut self) {
+        let outcome = match self.timing.completed {
+            Some(outcome) => outcome,
+            None if self.failed => Outcome::Failed,
+            None => Outcome::Cancelled,
+        };
+        self.stats.record(&self.model, outcome);
+    }
+}
+
+/// Books `handle`'s stream, opened at `started`, into `stats` under its response model.
+fn observe(stats: Arc<LatencyStats>, handle: &mut StreamingHandle, started: Instant) {
+    let mut probe = Probe {
+        stats,
+        model: handle.response_model.clone(),
+        timing: Timing::new(started),
+        failed: false,
+    };
+    let stream = std::mem::replace(&mut handle.stream, futures_util::stream::empty().boxed());
+    handle.stream = stream.inspect(move |event| probe.see(event)).boxed();
+}
+
+/// The upstream streams read to answer one [`complete`](super::ChatExecutor::complete) call,
+/// booked as a single sample when dropped: from the call's start and the first token of any
+/// stream to the `Completed` that ended the reply. A stream that broke and was resumed does not
+/// count as a failure; only the call failing does. Calls that read no stream are not booked.
+pub struct CompleteLatency {
+    stats: Arc<LatencyStats>,
+    model: String,
+    timing: Arc<Mutex<Timing>>,
+    observed: AtomicBool,
+    failed: bool,
+}
+
+impl CompleteLatency {
+    fn new(stats: Arc<LatencyStats>, model: String) -> Self {
+        Self {
+            stats,
+            model,
+            timing: Arc::new(Mutex::new(Timing::new(Instant::now()))),
+            observed: AtomicBool::new(false),
+            failed: false,
+        }
+    }
+
+    /// Feeds `handle`'s stream into this call's timing.
+    pub(super) fn observe(&self, handle: &mut StreamingHandle) {
+        self.observed.store(true, Ordering::Relaxed);
+        let timing = Arc::clone(&self.timing);
+        let stream = std::mem::replace(&mut handle.stream, futures_util::stream::empty().boxed());
+        handle.stream = stream
+            .inspect(move |event| {
+                if let Ok(event) = event {
+                    lock(&timing).see(event);
+                }
+            })
+            .boxed();
+    }
+}
+
+impl Drop for CompleteLatency {
+    fn drop(&mut self) {
+        if !self.observed.load(Ordering::Relaxed) {
+            return;
+        }
+        let outcome = if self.failed {
+            Outcome::Failed
+        } else {
+            lock(&self.timing).completed.unwrap_or(Outcome::Cancelled)
+        };
+        self.stats.record(&self.model, outcome);
+    }
+}
+
+fn lock(timing: &Mutex<Timing>) -> MutexGuard<'_, Timing> {
+    timing
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Observes the upstream streams of every request; wraps every executor the server runs.
+pub(super) struct ObserveLatency {
+    pub(super) inner: SharedChatExecutor,
+    pub(super) stats: Arc<LatencyStats>,
+}
+
+delegate_executor!(ObserveLatency, |this| this.inner, {
+    async fn complete(&self, payload: PromptPayload) -> Result<ChatCompletionResponse, ApiError> {
+        let mut latency = CompleteLatency::new(Arc::clone(&self.stats), payload.model.clone());
+        let result = self.inner.complete_observed(payload, &latency).await;
+        latency.failed = result.is_err();
+        result
+    }
+
+    async fn complete_observed(
+        &self,
+        payload: PromptPayload,
+        latency: &CompleteLatency,
+    ) -> Result<ChatCompletionResponse, ApiError> {
+        self.inner.complete_observed(payload, latency).await
+    }
+
+    async fn stream(&self, payload: PromptPayload) -> Result<StreamingHandle, ApiError> {
+        let started = Instant::now();
+        let mut handle = self.inner.stream(payload).await?;
+        observe(Arc::clone(&self.stats), &mut handle, started);
+        Ok(handle)
+    }
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let mut samples = Samples::default();
+        assert_eq!(samples.percentiles(), None);
+        for value in (1..=100).rev() {
+            samples.push(f64::from(value));
+        }
+        assert_eq!(
+            samples.percentiles(),
+            Some(Percentiles {
+                p50: 50.0,
+                p95: 95.0
+            })
+        );
+
+        // Only the latest samples count.
+        for _ in 0..SAMPLES {
+            samples.push(7.0);
+        }
+        assert_eq!(samples.percentiles().map(|p| p.p95), Some(7.0));
+    }
+
+    #[test]
+    fn failed_and_cancelled_streams_are_only_counted() {
+        let stats = LatencyStats::default();
+        stats.record("gpt-5", Outcome::Failed);
+        stats.record("gpt-5", Outcome::Cancelled);
+        stats.record(
+            "gpt-5",
+            Outcome::Completed {
+                ttft_ms: Some(120.0),
+                tokens_per_second: None,
+            },
+        );
+
+        let snapshot = &stats.snapshot()["gpt-5"];
+        assert_eq!(
+            (snapshot.completed, snapshot.failed, snapshot.cancelled),
+            (1, 1, 1)
+        );
+        assert_eq!(
+            snapshot.ttft_ms,
+            Some(Percentiles {
+                p50: 120.0,
+                p95: 120.0
+            })
+        );
+        assert_eq!(snapshot.tokens_per_second, None);
+    }
+
+    #[test]
+    fn histograms_render_cumulative_buckets() {
+        let stats = LatencyStats::default();
+        for ttft_ms in [80.0, 300.0, 90_000.0] {
+            stats.record(
+                "gpt-5",
+                Outcome::Completed {
+                    ttft_ms: Some(ttft_ms),
+                    tokens_per_second: Some(40.0),
+                },
+            );
+        }
+        stats.record("gpt-5", Outcome::Failed);
+
+        let text = stats.prometheus();
+        for line in [
+            r#"codex_serve_time_to_first_token_seconds_bucket{model="gpt-5",le="0.1"} 1"#,
+            r#"codex_serve_time_to_first_token_seconds_bucket{model="gpt-5",le="0.5"} 2"#,
+            r#"codex_serve_time_to_first_token_seconds_bucket{model="gpt-5",le="60"} 2"#,
+            r#"codex_serve_time_to_first_token_seconds_bucket{model="gpt-5",le="+Inf"} 3"#,
+            r#"codex_serve_time_to_first_token_seconds_count{model="gpt-5"} 3"#,
+            r#"codex_serve_output_tokens_per_second_bucket{model="gpt-5",le="25"} 0"#,
+            r#"codex_serve_output_tokens_per_second_bucket{model="gpt-5",le="50"} 3"#,
+            r#"codex_serve_latency_requests_total{model="gpt-5",outcome="failed"} 1"#,
+        ] {
+            assert!(text.lines().any(|series| series == line), "{line}\n{text}");
+        }
+        assert_eq!(label_value("a\"b\\c\nd"), r#"a\"b\\c\nd"#);
+    }
+}
@@ -0,0 +1,83 @@
+//! Several listeners over one state (repeated `--addr`), each with its own auth requirement.
+//! The requirement rides on the request as an [`Extension`](axum::Extension) set per listener,
+//! so every listener shares the same router code.
+
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{Request, State},
+    http::header::AUTHORIZATION,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use super::state::AppState;
+use crate::{
+    error::ApiError,
+    serve_config::{ApiSurfaces, ListenerAuth},
+};
+
+/// A bound listener as `/healthz` reports it.
+#[derive(Clone, Debug, Serialize)]
+pub struct ListenerInfo {
+    pub addr: SocketAddr,
+    pub auth: ListenerAuth,
+    pub api_surfaces: ApiSurfaces,
+}
+
+/// Answers `401` unless the request carries `--client-api-key` as a bearer token, on listeners
+/// bound with `auth=required`. Other listeners, and routers used without one, pass everything.
+pub(super) async fn require_client_key(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if request.extensions().get::<ListenerAuth>() != Some(&ListenerAuth::Required) {
+        return next.run(request).await;
+    }
+    let presented = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim);
+    let Some(presented) = presented else {
+        return ApiError::invalid_api_key(
+            "This listener requires an API key; send it as `Authorization: Bearer <key>`.",
+        )
+        .into_response();
+    };
+    match &state.config().client_api_key {
+        Some(key) if same_key(presented, key.expose()) => next.run(request).await,
+        _ => ApiError::invalid_api_key("Incorrect API key provided.").into_response(),
+    }
+}
+
+/// Compares digests so the time taken does not depend on how much of the key matched.
+fn same_key(presented: &str, expected: &str) -> bool {
+    Sha256::digest(presented.as_bytes()) == Sha256::digest(expected.as_bytes())
+}
+
+/// Resolves on Ctrl-C, or SIGTERM on Unix, to start draining the listeners.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+}
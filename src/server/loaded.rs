@@ -0,0 +1,181 @@
+//! Which models served a request recently, for Ollama's `/api/ps`. Codex models are never really
+//! loaded, so a model counts as loaded for Ollama's default keep-alive after each use, or for the
+//! request's `keep_alive`. The same `keep_alive` governs the executor's cached configs.
+
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, SystemTime},
+};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::ollama_model_name;
+use crate::serve_config::{OllamaTagStyle, parse_interval};
+
+/// Ollama keeps a model in memory this long after its last request unless told otherwise.
+pub(super) const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(5 * 60);
+
+/// How far ahead `/api/ps` puts the expiry of a model kept alive indefinitely.
+const FOREVER: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+/// An Ollama `keep_alive`: how long a model stays loaded after a request. Ollama takes a number
+/// of seconds or a duration string; zero unloads the model and a negative value never does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "Value")]
+pub enum ModelExpiry {
+    Unload,
+    For(Duration),
+    Forever,
+}
+
+impl TryFrom<Value> for ModelExpiry {
+    type Error = String;
+
+    fn try_from(value: Value) -> Result<Self, String> {
+        match &value {
+            Value::Number(number) => {
+                let seconds = number.as_f64().unwrap_or_default();
+                Ok(if seconds < 0.0 {
+                    Self::Forever
+                } else if seconds == 0.0 {
+                    Self::Unload
+                } else {
+                    Self::For(Duration::try_from_secs_f64(seconds).unwrap_or(FOREVER))
+                })
+            }
+            Value::String(text) => {
+                let text = text.trim();
+                if text.starts_with('-') {
+                    return Ok(Self::Forever);
+                }
+                let count = text.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+                if count.trim().parse::<u64>() == Ok(0) {
+                    return Ok(Self::Unload);
+                }
+                parse_interval(text).map(Self::For)
+            }
+            _ => Err(format!(
+                "invalid keep_alive {value} (expected e.g. \"5m\", a number of seconds, 0 to \
+                 unload or -1 to keep loaded)"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(super) struct LoadedModels {
+    expires_at: Mutex<HashMap<String, SystemTime>>,
+}
+
+impl LoadedModels {
+    /// Marks `model` as used just now. Reasoning variants are kept in their hyphen spelling, so
+    /// `model:high` and `model-high` are one entry.
+    pub(super) fn touch(&self, model: &str) {
+        let expires_at = SystemTime::now() + DEFAULT_KEEP_ALIVE;
+        let model = ollama_model_name(model, OllamaTagStyle::Hyphen);
+        self.entries().insert(model, expires_at);
+    }
+
+    /// Applies a request's `keep_alive` to `model`, which counts as used just now.
+    pub(super) fn set_model_expiry(&self, model: &str, expiry: ModelExpiry) {
+        let model = ollama_model_name(model, OllamaTagStyle::Hyphen);
+        let now = SystemTime::now();
+        let mut entries = self.entries();
+        match expiry {
+            ModelExpiry::Unload => {
+                entries.remove(&model);
+            }
+            ModelExpiry::For(duration) => {
+                entries.insert(model, now + duration);
+            }
+            ModelExpiry::Forever => {
+                entries.insert(model, now + FOREVER);
+            }
+        }
+    }
+
+    /// Models that have not expired yet, with their expiry, sorted by name.
+    pub(super) fn list(&self) -> Vec<(String, SystemTime)> {
+        let now = SystemTime::now();
+        let mut entries = self.entries();
+        entries.retain(|_, expires_at| *expires_at > now);
+        let mut loaded: Vec<_> = entries
+            .iter()
+            .map(|(model, expires_at)| (model.clone(), *expires_at))
+            .collect();
+        loaded.sort();
+        loaded
+    }
+
+    fn entries(&self) -> MutexGuard<'_, HashMap<String, SystemTime>> {
+        self.expires_at
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn lists_touched_models_until_they_expire() {
+        let loaded = LoadedModels::default();
+        assert!(loaded.list().is_empty());
+
+        loaded.touch("gpt-5");
+        loaded.touch("gpt-5-codex");
+        loaded.touch("gpt-5");
+        let models: Vec<String> = loaded.list().into_iter().map(|(model, _)| model).collect();
+        assert_eq!(models, ["gpt-5", "gpt-5-codex"]);
+
+        loaded.entries().insert(
+            "stale".to_string(),
+            SystemTime::now() - Duration::from_secs(1),
+        );
+        assert_eq!(loaded.list().len(), 2);
+    }
+
+    #[test]
+    fn keep_alive_sets_the_expiry_or_unloads() {
+        let loaded = LoadedModels::default();
+        loaded.touch("gpt-5:high");
+        loaded.set_model_expiry("gpt-5-codex", ModelExpiry::For(Duration::from_secs(3600)));
+        let expiry = |model: &str| {
+            loaded
+                .list()
+                .into_iter()
+                .find_map(|(name, expires_at)| (name == model).then_some(expires_at))
+        };
+        let in_an_hour = expiry("gpt-5-codex").expect("kept alive");
+        assert!(in_an_hour > SystemTime::now() + Duration::from_secs(3500));
+
+        loaded.set_model_expiry("gpt-5:high", ModelExpiry::Unload);
+        assert_eq!(expiry("gpt-5-high"), None);
+        loaded.set_model_expiry("gpt-5", ModelExpiry::Forever);
+        assert!(expiry("gpt-5").expect("kept alive") > in_an_hour);
+    }
+
+    #[test]
+    fn keep_alive_takes_seconds_or_durations() {
+        let parse = |value: Value| serde_json::from_value::<ModelExpiry>(value);
+        assert_eq!(parse(json!(0)).unwrap(), ModelExpiry::Unload);
+        assert_eq!(parse(json!("0")).unwrap(), ModelExpiry::Unload);
+        assert_eq!(parse(json!("0s")).unwrap(), ModelExpiry::Unload);
+        assert_eq!(parse(json!(-1)).unwrap(), ModelExpiry::Forever);
+        assert_eq!(parse(json!("-1m")).unwrap(), ModelExpiry::Forever);
+        assert_eq!(
+            parse(json!(90)).unwrap(),
+            ModelExpiry::For(Duration::from_secs(90))
+        );
+        assert_eq!(
+            parse(json!("5m")).unwrap(),
+            ModelExpiry::For(Duration::from_secs(300))
+        );
+        assert!(parse(json!("soon")).is_err());
+        assert!(parse(json!(true)).is_err());
+    }
+}
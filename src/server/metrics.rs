@@ -0,0 +1,418 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use codex_core::protocol::TokenUsage;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    access_log::AccessLog, budget::BudgetCharge, conversations::ConversationTurn, response::Usage,
+};
+
+/// Process-wide request counters shared by every clone of `AppState`.
+#[derive(Debug, Default)]
+pub struct ServerMetrics {
+    requests_total: AtomicU64,
+    active_requests: AtomicU64,
+    active_streams: AtomicU64,
+    tokens_total: AtomicU64,
+    config_evictions: AtomicU64,
+    codex_usage: Mutex<CodexUsageBreakdown>,
+    conversions: ConversionHistogram,
+}
+
+/// Upper bounds of the request conversion histogram's buckets, in microseconds; a last bucket
+/// takes the rest.
+const CONVERSION_BUCKETS_US: [u64; 5] = [100, 1_000, 10_000, 100_000, 1_000_000];
+
+/// How long converting requests into Codex prompts took.
+#[derive(Debug, Default)]
+struct ConversionHistogram {
+    buckets: [AtomicU64; CONVERSION_BUCKETS_US.len() + 1],
+    total_us: AtomicU64,
+}
+
+/// [`ConversionHistogram`] as `/stats/conversion` reports it.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct ConversionTimes {
+    pub count: u64,
+    pub total_us: u64,
+    pub buckets: Vec<ConversionBucket>,
+}
+
+/// The conversions that took at most `le_us` microseconds and more than the previous bucket's
+/// bound; `le_us` is `null` for the last bucket.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+pub struct ConversionBucket {
+    pub le_us: Option<u64>,
+    pub count: u64,
+}
+
+/// Point-in-time copy of [`ServerMetrics`], as reported by `/healthz`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct MetricsSnapshot {
+    pub requests_total: u64,
+    pub active_requests: u64,
+    pub active_streams: u64,
+    pub tokens_total: u64,
+    /// Cached per-model configs dropped after sitting idle (`--cache-idle-ttl`, `/admin/gc`).
+    pub config_evictions: u64,
+}
+
+/// The cumulative counters `--state-file` carries over a restart; the gauges of requests in
+/// flight start from zero again.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub(super) struct SavedMetrics {
+    pub(super) requests_total: u64,
+    pub(super) tokens_total: u64,
+    pub(super) codex_usage: CodexUsageBreakdown,
+}
+
+/// Raw Codex token counts summed over requests, as `--usage-extended` reports them.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CodexUsageTotals {
+    pub requests: u64,
+    pub input_tokens: i64,
+    pub cached_input_tokens: i64,
+    pub output_tokens: i64,
+    pub reasoning_output_tokens: i64,
+    pub total_tokens: i64,
+}
+
+impl CodexUsageTotals {
+    fn merge(&mut self, other: &Self) {
+        self.requests += other.requests;
+        self.input_tokens += other.input_tokens;
+        self.cached_input_tokens += other.cached_input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.reasoning_output_tokens += other.reasoning_output_tokens;
+        self.total_tokens += other.total_tokens;
+    }
+
+    fn add(&mut self, usage: &TokenUsage) {
+        self.requests += 1;
+        self.input_tokens += usage.input_tokens;
+        self.cached_input_tokens += usage.cached_input_tokens;
+        self.output_tokens += usage.output_tokens;
+        self.reasoning_output_tokens += usage.reasoning_output_tokens;
+        self.total_tokens += usage.total_tokens;
+    }
+}
+
+/// [`CodexUsageTotals`] per model and per client identity (see `fairness::ClientId`).
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CodexUsageBreakdown {
+    pub models: BTreeMap<String, CodexUsageTotals>,
+    pub clients: BTreeMap<String, CodexUsageTotals>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Gauge {
+    Requests,
+    Streams,
+}
+
+impl ServerMetrics {
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            requests_total: self.requests_total.load(Ordering::Relaxed),
+            active_requests: self.active_requests.load(Ordering::Relaxed),
+            active_streams: self.active_streams.load(Ordering::Relaxed),
+            tokens_total: self.tokens_total.load(Ordering::Relaxed),
+            config_evictions: self.config_evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Counts a non-streaming chat request as active until the returned guard is dropped.
+    pub fn start_request(self: &Arc<Self>) -> InFlightGuard {
+        self.start(Gauge::Requests)
+    }
+
+    /// Counts an SSE stream as active until the returned guard is dropped, which happens when the
+    /// stream finishes, errors, or the client disconnects.
+    pub fn start_stream(self: &Arc<Self>) -> InFlightGuard {
+        self.start(Gauge::Streams)
+    }
+
+    pub fn record_config_evictions(&self, evicted: usize) {
+        self.config_evictions
+            .fetch_add(evicted as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_tokens(&self, tokens: u64) {
+        self.tokens_total.fetch_add(tokens, Ordering::Relaxed);
+    }
+
+    pub fn codex_usage(&self) -> CodexUsageBreakdown {
+        self.codex_usage
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    pub(super) fn record_conversion(&self, elapsed: Duration) {
+        let micros = u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX);
+        let bucket = CONVERSION_BUCKETS_US
+            .iter()
+            .position(|bound| micros <= *bound)
+            .unwrap_or(CONVERSION_BUCKETS_US.len());
+        self.conversions.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.conversions
+            .total_us
+            .fetch_add(micros, Ordering::Relaxed);
+    }
+
+    pub fn conversion_times(&self) -> ConversionTimes {
+        let buckets: Vec<ConversionBucket> = self
+            .conversions
+            .buckets
+            .iter()
+            .enumerate()
+            .map(|(index, count)| ConversionBucket {
+                le_us: CONVERSION_BUCKETS_US.get(index).copied(),
+                count: count.load(Ordering::Relaxed),
+            })
+            .collect();
+        ConversionTimes {
+            count: buckets.iter().map(|bucket| bucket.count).sum(),
+            total_us: self.conversions.total_us.load(Ordering::Relaxed),
+            buckets,
+        }
+    }
+
+    pub(super) fn save(&self) -> SavedMetrics {
+        SavedMetrics {
+            requests_total: self.requests_total.load(Ordering::Relaxed),
+            tokens_total: self.tokens_total.load(Ordering::Relaxed),
+            codex_usage: self.codex_usage(),
+        }
+    }
+
+    /// Adds `saved` onto the counters, which a fresh process has only just started.
+    pub(super) fn restore(&self, saved: SavedMetrics) {
+        self.requests_total
+            .fetch_add(saved.requests_total, Ordering::Relaxed);
+        self.tokens_total
+            .fetch_add(saved.tokens_total, Ordering::Relaxed);
+        let mut breakdown = self
+            .codex_usage
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        for (totals, saved) in [
+            (&mut breakdown.models, saved.codex_usage.models),
+            (&mut breakdown.clients, saved.codex_usage.clients),
+        ] {
+            for (name, saved) in saved {
+                totals.entry(name).or_default().merge(&saved);
+            }
+        }
+    }
+
+    fn record_codex_usage(&self, model: &str, client: &str, usage: &TokenUsage) {
+        let mut breakdown = self
+            .codex_usage
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        breakdown
+            .models
+            .entry(model.to_string())
+            .or_default()
+            .add(usage);
+        breakdown
+            .clients
+            .entry(client.to_string())
+            .or_default()
+            .add(usage);
+    }
+
+    fn start(self: &Arc<Self>, gauge: Gauge) -> InFlightGuard {
+        self.requests_total.fetch_add(1, Ordering::Relaxed);
+        self.gauge(gauge).fetch_add(1, Ordering::Relaxed);
+        InFlightGuard {
+            metrics: Arc::clone(self),
+            gauge,
+            account: None,
+            conversation: None,
+            budget: None,
+        }
+    }
+
+    fn gauge(&self, gauge: Gauge) -> &AtomicU64 {
+        match gauge {
+            Gauge::Requests => &self.active_requests,
+            Gauge::Streams => &self.active_streams,
+        }
+    }
+}
+
+/// Where one request's raw Codex usage is booked with `--usage-extended`: the per-model and
+/// per-client totals, plus the request's access log line.
+#[derive(Clone)]
+pub(super) struct UsageAccount {
+    pub(super) model: String,
+    pub(super) client: String,
+    pub(super) access_log: Option<AccessLog>,
+}
+
+/// Decrements its gauge on drop so early returns, panics and disconnects cannot leak a count.
+pub struct InFlightGuard {
+    metrics: Arc<ServerMetrics>,
+    gauge: Gauge,
+    account: Option<UsageAccount>,
+    conversation: Option<ConversationTurn>,
+    budget: Option<BudgetCharge>,
+}
+
+impl InFlightGuard {
+    pub(super) fn with_usage_account(mut self, account: Option<UsageAccount>) -> Self {
+        self.account = account;
+        self
+    }
+
+    /// Books the request's raw usage as a turn of `conversation` for `/stats/conversations`.
+    pub(super) fn with_conversation(mut self, conversation: Option<ConversationTurn>) -> Self {
+        self.conversation = conversation;
+        self
+    }
+
+    /// Books the request's tokens against its client's `--max-tokens-per-hour` budget.
+    pub(super) fn with_budget(mut self, budget: Option<BudgetCharge>) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    pub fn record_tokens(&self, tokens: u64) {
+        self.metrics.record_tokens(tokens);
+    }
+
+    /// Counts the request's tokens, books them against its budget, and books Codex's raw counts
+    /// with its conversation and, with a [`UsageAccount`], its model and client.
+    pub(super) fn record_usage(&self, usage: &Usage) {
+        self.record_tokens(u64::from(usage.total_tokens));
+        if let Some(budget) = &self.budget {
+            budget.settle(u64::from(usage.total_tokens));
+        }
+        let Some(raw) = &usage.codex else {
+            return;
+        };
+        if let Some(turn) = &self.conversation {
+            turn.conversations.record(&turn.key, raw);
+        }
+        let Some(account) = &self.account else {
+            return;
+        };
+        self.metrics
+            .record_codex_usage(&account.model, &account.client, raw);
+        if let Some(log) = &account.access_log {
+            log.record_codex_usage(raw);
+        }
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.metrics
+            .gauge(self.gauge)
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions_land_in_their_buckets() {
+        let metrics = ServerMetrics::default();
+        metrics.record_conversion(Duration::from_micros(50));
+        metrics.record_conversion(Duration::from_micros(100));
+        metrics.record_conversion(Duration::from_millis(5));
+        metrics.record_conversion(Duration::from_secs(3));
+
+        let times = metrics.conversion_times();
+        assert_eq!(times.count, 4);
+        assert_eq!(times.total_us, 3_005_150);
+        let counts: Vec<u64> = times.buckets.iter().map(|bucket| bucket.count).collect();
+        assert_eq!(counts, [2, 0, 1, 0, 0, 1]);
+        assert_eq!(times.buckets[0].le_us, Some(100));
+        assert_eq!(times.buckets[5].le_us, None);
+    }
+
+    #[test]
+    fn codex_usage_adds_up_per_model_and_client() {
+        let metrics = Arc::new(ServerMetrics::default());
+        let usage = |input, cached| {
+            Usage::from(TokenUsage {
+                input_tokens: input,
+                cached_input_tokens: cached,
+                output_tokens: 5,
+                reasoning_output_tokens: 2,
+                total_tokens: input + cached + 7,
+            })
+        };
+        let record = |model: &str, client: &str, usage: Usage| {
+            let account = UsageAccount {
+                model: model.to_string(),
+                client: client.to_string(),
+                access_log: None,
+            };
+            metrics
+                .start_request()
+                .with_usage_account(Some(account))
+                .record_usage(&usage);
+        };
+        record("gpt-5", "user:a", usage(10, 90));
+        record("gpt-5", "user:b", usage(20, 0));
+        record("gpt-5-codex", "user:a", usage(1, 1));
+        record("gpt-5-codex", "user:a", Usage::default());
+        metrics.start_request().record_usage(&usage(3, 3));
+
+        let breakdown = metrics.codex_usage();
+        assert_eq!(
+            breakdown.models["gpt-5"],
+            CodexUsageTotals {
+                requests: 2,
+                input_tokens: 30,
+                cached_input_tokens: 90,
+                output_tokens: 10,
+                reasoning_output_tokens: 4,
+                total_tokens: 134,
+            }
+        );
+        assert_eq!(breakdown.models["gpt-5-codex"].requests, 1);
+        assert_eq!(breakdown.models.len(), 2);
+        assert_eq!(breakdown.clients["user:a"].requests, 2);
+        assert_eq!(breakdown.clients["user:a"].cached_input_tokens, 91);
+        assert_eq!(breakdown.clients["user:b"].input_tokens, 20);
+    }
+
+    #[test]
+    fn guards_restore_gauges_on_drop() {
+        let metrics = Arc::new(ServerMetrics::default());
+        let request = metrics.start_request();
+        let stream = metrics.start_stream();
+        stream.record_tokens(12);
+        assert_eq!(
+            metrics.snapshot(),
+            MetricsSnapshot {
+                requests_total: 2,
+                active_requests: 1,
+                active_streams: 1,
+                tokens_total: 12,
+                config_evictions: 0,
+            }
+        );
+
+        drop(request);
+        drop(stream);
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.active_requests, 0);
+        assert_eq!(snapshot.active_streams, 0);
+        assert_eq!(snapshot.requests_total, 2);
+    }
+}
@@ -0,0 +1,120 @@
+use std::{any::Any, panic::AssertUnwindSafe};
+
+use axum::{
+    body::Body,
+    http::{HeaderName, HeaderValue, Request, header::CONTENT_TYPE},
+    middleware::Next,
+    response::Response,
+};
+use futures_util::FutureExt;
+use tracing::{Instrument, error, info_span};
+use uuid::Uuid;
+
+use crate::{error::ApiError, telemetry};
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest client-supplied request id we are willing to echo back.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Identifier attached to every request, stored in the request extensions.
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+tokio::task_local! {
+    static CURRENT_REQUEST_ID: String;
+}
+
+/// Returns the id of the request currently being handled, if called from within one.
+pub(crate) fn current_request_id() -> Option<String> {
+    CURRENT_REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+fn generate_request_id() -> String {
+    format!("req_{}", Uuid::new_v4().simple())
+}
+
+/// Propagates the client's `X-Request-Id` (or generates one), exposes it to handlers and the
+/// tracing span, and echoes it on the response so failures can be matched to server logs.
+pub(super) async fn assign_request_id(mut request: Request<Body>, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty() && value.len() <= MAX_REQUEST_ID_LEN)
+        .map(str::to_string)
+        .unwrap_or_else(generate_request_id);
+    request
+        .extensions_mut()
+        .insert(RequestId(request_id.clone()));
+
+    let span = info_span!("request", request_id = %request_id);
+    telemetry::start_http_span(
+        &span,
+        request.headers(),
+        request.method().as_str(),
+        request.uri().path(),
+    );
+    let mut response = CURRENT_REQUEST_ID
+        .scope(request_id.clone(), next.run(request))
+        .instrument(span.clone())
+        .await;
+    telemetry::finish_http_span(&span, response.status().as_u16());
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// Whether `response` is an SSE or NDJSON stream, whose body outlives the handler.
+pub(super) fn is_stream_response(response: &Response) -> bool {
+    response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value.starts_with("text/event-stream") || value.starts_with("application/x-ndjson")
+        })
+}
+
+/// Converts a panic anywhere in the handler stack into the standard OpenAI-format 500 instead of
+/// tearing the connection down with an empty reply.
+pub(super) async fn catch_panics(request: Request<Body>, next: Next) -> Response {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    match AssertUnwindSafe(next.run(request)).catch_unwind().await {
+        Ok(response) => response,
+        Err(payload) => {
+            let request_id = current_request_id().unwrap_or_else(generate_request_id);
+            error!(
+                method = %method,
+                path = path,
+                request_id = %request_id,
+                panic = %panic_message(payload.as_ref()),
+                "request handler panicked"
+            );
+            ApiError::internal("Codex Serve hit an internal error while handling this request")
+                .into_response_with_request_id(Some(request_id))
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&'static str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.as_str()
+    } else {
+        "<non-string panic payload>"
+    }
+}
+
+/// Routes panic reports (with a captured backtrace) through `tracing` so they land in the same
+/// structured log stream as everything else.
+pub fn install_panic_logging_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        let backtrace = std::backtrace::Backtrace::force_capture();
+        error!(panic = %info, backtrace = %backtrace, "panic");
+    }));
+}
@@ -1,33 +1,74 @@
+mod access_log;
+mod admin;
+mod budget;
+mod cache_gc;
+mod capabilities;
+mod capture;
+mod codex_stream;
+mod conditional;
+mod config_load;
+mod conversations;
+mod conversion;
+mod degraded;
+mod dry_run;
 mod executor;
+mod extract;
+mod fairness;
+mod fallback;
+mod framing;
+mod gemini;
+mod health;
+mod idempotency;
+mod in_flight;
+mod keepalive;
+mod latency;
+mod listeners;
+mod loaded;
+mod metrics;
+mod middleware;
+mod ollama;
+mod openapi;
+mod organization;
+mod persist;
+mod profiles;
+mod progress;
+mod redact;
 pub mod response;
+mod sampling;
 mod state;
 mod test_server;
+mod tool_calls;
+mod verbose;
+mod version;
+mod warnings;
 
 use std::{
-    collections::{HashMap, HashSet},
-    convert::Infallible,
+    collections::HashSet,
+    net::SocketAddr,
+    time::{Duration, UNIX_EPOCH},
 };
 
 use anyhow::{Context, Result};
 use axum::{
-    Json, Router,
-    body::Body,
-    extract::State,
-    http::{Request, StatusCode},
-    middleware::Next,
-    response::{
-        IntoResponse, Response,
-        sse::{Event, Sse},
-    },
-    routing::{get, post},
+    Extension, Json, Router,
+    extract::{DefaultBodyLimit, State},
+    http::{HeaderMap, StatusCode, Uri, header::CONTENT_TYPE},
+    response::{Html, IntoResponse, Response},
+    routing::{delete, get, post},
+};
+use futures_util::{
+    StreamExt as FuturesStreamExt,
+    future::{join_all, try_join_all},
 };
-use futures_util::StreamExt as FuturesStreamExt;
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value, json};
-use tokio::{net::TcpListener, sync::mpsc};
+use sha2::{Digest, Sha256};
+use tokio::{
+    net::TcpListener,
+    sync::{mpsc, watch},
+};
 use tokio_stream::wrappers::ReceiverStream;
-use tracing::{error, info, warn};
-use uuid::Uuid;
+use tracing::{Instrument, Span, error, info, warn};
 
 use codex_app_server_protocol::AuthMode;
 use codex_common::model_presets::{ModelPreset, builtin_model_presets};
@@ -39,116 +80,813 @@ use codex_protocol::models::WebSearchAction;
 use strum::IntoEnumIterator;
 
 use crate::{
+    clock::rfc3339_nanos,
     error::ApiError,
-    openai::chat::ChatCompletionRequest,
-    serve_config::{developer_prompt_mode, expose_reasoning_models, verbose_logging_enabled},
+    openai::chat::{ChatCompletionRequest, PromptEndpoint, log_function_tools},
+    prompt::inject_prediction_hint,
+    serve_config::{
+        ApiSurface, ApiSurfaces, ListenerAuth, OllamaTagStyle, ServeConfig, ToolCallFallback,
+    },
+    telemetry,
 };
-use executor::{SharedChatExecutor, StreamingHandle};
-use response::{ToolCall, Usage};
-use state::AppState;
-
-pub use test_server::TestServer;
+use access_log::AccessLog;
+use capabilities::ModelCapabilities;
+use capture::Capture;
+use executor::streaming_unsupported;
+use extract::{ApiJson, BodyLimit};
+use fairness::{ClientId, QueueTicket, QueuedRequest, queue_wait_header};
+use framing::{StreamFrame, StreamFraming, headed_frames, sized_frames};
+use health::{ComponentStatus, UpstreamComponent};
+use idempotency::Claim;
+use in_flight::TrackedRequest;
+use metrics::{CodexUsageBreakdown, InFlightGuard, MetricsSnapshot};
+use profiles::resolve_profile;
+use progress::ProgressMeter;
+use response::{
+    ChunkDelta, ChunkTemplate, ContentDigest, ContentIntegrity, ToolCall, Usage,
+    tool_call_description,
+};
+use state::{AccountDetails, AuthStatus};
+use tool_calls::{Slot, ToolCallTracker, WebSearchPositions};
+use verbose::{log_verbose_json, log_verbose_stream_response, log_verbose_summary};
 
-type SseStream = ReceiverStream<Result<Event, Infallible>>;
+pub use state::{AppState, InitOptions};
 
-/// Build the Axum router that powers Codex Serve.
+pub use crate::{
+    clock::{LogContext, RequestClock},
+    openai::render::describe_input,
+};
+pub use budget::{COMPLETION_RESERVE_TOKENS, ClientBudget, TokenBudgets};
+pub use capture::CaptureSink;
+pub use conversations::USAGE_HEADER;
+pub use dry_run::DRY_RUN_HEADER;
+pub use executor::{
+    CapturingExecutor, ChatExecutor, EventStream, ModelInfo, ModelSettings, PreparedPrompt,
+    ReloadOutcome, ScriptedChatExecutor, ScriptedTurn, SharedChatExecutor, StreamingHandle,
+};
+pub use fairness::{ClientLimiter, ClientStats, QUEUE_WAIT_HEADER};
+pub use idempotency::{IDEMPOTENCY_KEY_HEADER, IDEMPOTENT_REPLAYED_HEADER};
+pub use keepalive::KeepaliveStatus;
+pub use latency::CompleteLatency;
+pub use listeners::{ListenerInfo, shutdown_signal};
+pub use loaded::ModelExpiry;
+pub(crate) use middleware::current_request_id;
+pub use middleware::{REQUEST_ID_HEADER, RequestId, install_panic_logging_hook};
+pub use organization::{OPENAI_ORGANIZATION_HEADER, OPENAI_PROJECT_HEADER};
+pub use profiles::{PROFILE_HEADER, ProfileCatalog};
+pub use test_server::{TestServer, normalize_snapshot};
+pub use tool_calls::{DEFAULT_MAX_TRACKED_TOOL_CALLS, ToolCallOverflow};
+pub use warnings::WARNINGS_HEADER;
+
+/// Build the Axum router that powers Codex Serve, with the API surfaces its config enables.
 pub fn router(state: AppState) -> Router {
-    Router::new()
+    let surfaces = state.config().api_surfaces;
+    surface_router(state, surfaces, true)
+}
+
+/// Build a router for the Ollama routes alone, for serving them on their own listener
+/// (`--ollama-addr`). It also answers `/healthz`.
+pub fn ollama_router(state: AppState) -> Router {
+    surface_router(state, ApiSurfaces::only(ApiSurface::Ollama), false)
+}
+
+/// The routes of `surfaces` plus `/healthz`; the playground and admin routes only go on the
+/// main listener (`operator_routes`).
+fn surface_router(state: AppState, surfaces: ApiSurfaces, operator_routes: bool) -> Router {
+    let chat_body_limit = state.config().max_body_size;
+    let metadata_body_limit = state.config().max_metadata_body_size;
+    let mut metadata_routes = Router::new()
+        .route(
+            "/openapi.json",
+            get(move || openapi::openapi_json(surfaces)),
+        )
         .route("/healthz", get(healthz))
-        .route("/api/version", get(api_version))
-        .route("/api/tags", get(api_tags))
-        .route("/api/show", post(api_show))
-        .route("/v1/models", get(list_models))
-        .route("/v1/chat/completions", post(chat_completions))
-        .layer(axum::middleware::from_fn(log_requests))
-        .with_state(state)
+        .route("/stats", get(all_stats))
+        .route("/stats/conversations", get(conversation_stats))
+        .route("/stats/budget", get(budget_stats))
+        .route("/stats/latency", get(latency_stats))
+        .route("/stats/clients", get(client_stats))
+        .route("/stats/conversion", get(conversion_stats))
+        .route("/metrics", get(prometheus_metrics));
+    if surfaces.ollama {
+        metadata_routes = metadata_routes
+            .route("/api/version", get(version::api_version))
+            .route("/api/tags", get(api_tags))
+            .route("/api/show", post(api_show))
+            .route("/api/ps", get(api_ps))
+            .route("/api/pull", post(api_pull))
+            .route("/api/delete", delete(api_delete));
+    }
+    if surfaces.openai {
+        metadata_routes = metadata_routes.route("/v1/models", get(list_models));
+    }
+    let metadata_routes = metadata_routes
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            version::check_client_version,
+        ))
+        .layer(DefaultBodyLimit::max(metadata_body_limit))
+        .layer(Extension(BodyLimit(metadata_body_limit)));
+
+    let mut routes = Router::new().merge(metadata_routes);
+    if surfaces.openai {
+        routes = routes.merge(
+            Router::new()
+                .route("/v1/chat/completions", post(chat_completions))
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    fairness::limit_per_client,
+                ))
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    capture::capture_exchange,
+                ))
+                .layer(DefaultBodyLimit::max(chat_body_limit))
+                .layer(Extension(BodyLimit(chat_body_limit))),
+        );
+        if state.config().enable_codex_stream {
+            routes = routes.merge(
+                Router::new()
+                    .route("/v1/codex/stream", post(codex_stream::codex_stream))
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        fairness::limit_per_client,
+                    ))
+                    .layer(DefaultBodyLimit::max(chat_body_limit))
+                    .layer(Extension(BodyLimit(chat_body_limit))),
+            );
+        }
+        // The Gemini-style routes ride along with the OpenAI ones, without capture, which only
+        // understands Chat Completions chunks.
+        routes = routes.merge(
+            Router::new()
+                .route("/v1beta/models/{*target}", post(gemini::generate_content))
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    fairness::limit_per_client,
+                ))
+                .layer(DefaultBodyLimit::max(chat_body_limit))
+                .layer(Extension(BodyLimit(chat_body_limit))),
+        );
+    }
+    if surfaces.ollama {
+        // Same limits as the OpenAI route, minus capture, which only understands SSE.
+        routes = routes.merge(
+            Router::new()
+                .route("/api/chat", post(ollama::api_chat))
+                .route("/api/generate", post(ollama::api_generate))
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    fairness::limit_per_client,
+                ))
+                .layer(DefaultBodyLimit::max(chat_body_limit))
+                .layer(Extension(BodyLimit(chat_body_limit))),
+        );
+    }
+    let mut routes = routes
+        .method_not_allowed_fallback(fallback::method_not_allowed)
+        .fallback(move |uri: Uri| fallback::not_found(uri, surfaces));
+    if operator_routes && state.playground_enabled() {
+        routes = routes.route("/", get(playground));
+    }
+    if operator_routes && state.admin_enabled() {
+        routes = routes.merge(
+            admin::routes()
+                .layer(DefaultBodyLimit::max(metadata_body_limit))
+                .layer(Extension(BodyLimit(metadata_body_limit))),
+        );
+    }
+    let routes = routes.layer(axum::middleware::from_fn_with_state(
+        state.clone(),
+        listeners::require_client_key,
+    ));
+    with_common_layers(routes).with_state(state)
+}
+
+/// Middleware shared by every route: panic recovery innermost, then the `OpenAI-Organization`
+/// headers (which the access log records), then access logging, with the request id assigned
+/// outermost so every other layer can see it.
+fn with_common_layers(router: Router<AppState>) -> Router<AppState> {
+    router
+        .layer(axum::middleware::from_fn(middleware::catch_panics))
+        .layer(axum::middleware::from_fn(
+            organization::read_openai_organization,
+        ))
+        .layer(axum::middleware::from_fn(access_log::log_requests))
+        .layer(axum::middleware::from_fn(middleware::assign_request_id))
 }
 
 /// Run the HTTP server on the provided TCP listener until shutdown.
 pub async fn serve(listener: TcpListener) -> Result<()> {
+    serve_split(listener, None).await
+}
+
+/// Like [`serve`], with the Ollama routes on `ollama_listener` instead when it is set
+/// (`--ollama-addr`).
+pub async fn serve_split(
+    listener: TcpListener,
+    ollama_listener: Option<TcpListener>,
+) -> Result<()> {
     let state = AppState::initialize()
         .await
         .context("failed to initialize Codex Serve state")?;
-    serve_with_state(listener, state)
+    serve_with_state_split(listener, ollama_listener, state)
         .await
         .context("axum server error")
 }
 
-pub async fn serve_with_state(listener: TcpListener, state: AppState) -> Result<()> {
-    axum::serve(listener, router(state))
+/// Like [`serve_split`], on every listener of `listeners` with its auth requirement (repeated
+/// `--addr`), until Ctrl-C or SIGTERM; see [`serve_with_state_listeners`].
+pub async fn serve_listeners(
+    listeners: Vec<(TcpListener, ListenerAuth)>,
+    ollama_listener: Option<TcpListener>,
+) -> Result<()> {
+    let state = AppState::initialize()
         .await
-        .context("axum server error")?;
-    Ok(())
+        .context("failed to initialize Codex Serve state")?;
+    serve_with_state_listeners(listeners, ollama_listener, state, shutdown_signal()).await
+}
+
+pub async fn serve_with_state(listener: TcpListener, state: AppState) -> Result<()> {
+    serve_with_state_split(listener, None, state).await
+}
+
+/// Serves `state` on `listener`, and its Ollama routes on `ollama_listener` when it is set. Both
+/// listeners share the state, so metrics, limits and loaded models are counted once.
+pub async fn serve_with_state_split(
+    listener: TcpListener,
+    ollama_listener: Option<TcpListener>,
+    state: AppState,
+) -> Result<()> {
+    serve_with_state_listeners(
+        vec![(listener, ListenerAuth::None)],
+        ollama_listener,
+        state,
+        std::future::pending(),
+    )
+    .await
+}
+
+/// Serves `state` on each of `listeners`, those bound with [`ListenerAuth::Required`] only to
+/// clients presenting `--client-api-key`, and the Ollama routes on `ollama_listener` when it is
+/// set. All of them share the state. Once `shutdown` resolves every listener stops accepting, and
+/// this returns when the requests they were serving have finished. The `--keepalive-interval`
+/// task, when configured, and the `--cache-idle-ttl` sweep run for exactly as long. With
+/// `--state-file`, the saved state is restored before the first request and saved again once the
+/// last one has finished.
+pub async fn serve_with_state_listeners(
+    listeners: Vec<(TcpListener, ListenerAuth)>,
+    ollama_listener: Option<TcpListener>,
+    state: AppState,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<()> {
+    let main_surfaces = ApiSurfaces {
+        ollama: state.config().api_surfaces.ollama && ollama_listener.is_none(),
+        ..state.config().api_surfaces
+    };
+    let mut active = Vec::with_capacity(listeners.len() + 1);
+    for (listener, auth) in &listeners {
+        active.push(ListenerInfo {
+            addr: listener.local_addr()?,
+            auth: *auth,
+            api_surfaces: main_surfaces,
+        });
+    }
+    if let Some(listener) = &ollama_listener {
+        active.push(ListenerInfo {
+            addr: listener.local_addr()?,
+            auth: ListenerAuth::None,
+            api_surfaces: ApiSurfaces::only(ApiSurface::Ollama),
+        });
+    }
+    let state = state.with_listeners(active);
+
+    let (stop_tx, stop_rx) = watch::channel(false);
+    let mut servers = Vec::new();
+    for (listener, auth) in listeners {
+        let app = surface_router(state.clone(), main_surfaces, true).layer(Extension(auth));
+        servers.push(serve_until_stopped(listener, app, stop_rx.clone()));
+    }
+    if let Some(listener) = ollama_listener {
+        servers.push(serve_until_stopped(
+            listener,
+            ollama_router(state.clone()),
+            stop_rx,
+        ));
+    }
+    if let Some(interval) = state.config().keepalive_interval {
+        state.start_keepalive(interval);
+    }
+    state.start_cache_gc(state.config().cache_idle_ttl);
+    if state.config().preload_models {
+        tokio::spawn(preload_models(state.clone()));
+    }
+    let state_file = state.config().state_file.clone();
+    let saver = state_file.as_ref().map(|path| {
+        persist::load(&state, path);
+        persist::start(state.clone(), path.clone())
+    });
+    let signal = tokio::spawn(async move {
+        shutdown.await;
+        let _ = stop_tx.send(true);
+    });
+    let result = try_join_all(servers).await;
+    signal.abort();
+    state.stop_keepalive();
+    state.stop_cache_gc();
+    if let Some(saver) = saver {
+        saver.stop().await;
+    }
+    if let Some(path) = &state_file
+        && let Err(err) = persist::save(&state, path)
+    {
+        warn!(path = %path.display(), "failed to write state file: {err}");
+    }
+    result.map(|_| ())
+}
+
+/// Loads the config of every listed model (`--preload-models`), so `/v1/models` reports what
+/// the configs say rather than what the model families do.
+async fn preload_models(state: AppState) {
+    let ids = codex_model_ids(state.config().expose_reasoning_models, state.auth_mode());
+    let engine = state.engine();
+    for id in &ids {
+        if let Err(err) = engine.model_info(id, None).await {
+            warn!(model = %id, "preloading the model's config failed: {err:?}");
+        }
+    }
+    info!(models = ids.len(), "preloaded model configs");
+}
+
+async fn serve_until_stopped(
+    listener: TcpListener,
+    app: Router,
+    mut stop: watch::Receiver<bool>,
+) -> Result<()> {
+    let addr = listener.local_addr()?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(async move {
+        let _ = stop.wait_for(|stopped| *stopped).await;
+    })
+    .await
+    .with_context(|| format!("axum server error on {addr}"))
+}
+
+/// Single-file chat page for poking at the server from a browser (`--playground`).
+const PLAYGROUND_HTML: &str = include_str!("playground.html");
+
+async fn playground() -> Html<&'static str> {
+    Html(PLAYGROUND_HTML)
 }
 
 async fn chat_completions(
     State(state): State<AppState>,
-    Json(payload): Json<ChatCompletionRequest>,
+    access_log: Option<Extension<AccessLog>>,
+    client: Option<Extension<ClientId>>,
+    capture: Option<Extension<Capture>>,
+    queued: Option<Extension<QueuedRequest>>,
+    uri: Uri,
+    headers: HeaderMap,
+    ApiJson(mut payload): ApiJson<ChatCompletionRequest>,
 ) -> Result<Response, ApiError> {
     state.ensure_authenticated()?;
-    log_verbose_json("chat.request", &payload);
+    let dry_run = dry_run::requested(&state, &headers, &uri)?;
+    let log_context = LogContext::current(&payload.model);
+    log_verbose_json(state.config(), &log_context, "chat.request", &payload);
+    if payload.stream && !state.engine().supports_streaming() {
+        if !state.config().stream_fallback {
+            return Err(streaming_unsupported());
+        }
+        // `--stream-fallback`: the request runs as a non-streaming one from here on, so the reply
+        // (and any idempotent replay of it) is a plain JSON completion.
+        payload.stream = false;
+    }
+    let key_guard = match idempotency::request_key(&headers).filter(|_| !dry_run) {
+        Some(key) => {
+            let request_id = current_request_id().unwrap_or_default();
+            let fingerprint = idempotency::fingerprint(&payload);
+            match state
+                .idempotency()
+                .claim(&key, fingerprint, &request_id, payload.stream)
+                .await?
+            {
+                Claim::Run(guard) => Some(guard),
+                Claim::Replay(response) => return Ok(idempotency::replay(response)),
+                Claim::Untracked => None,
+            }
+        }
+        None => None,
+    };
+
+    let (profile, model) = resolve_profile(&headers, &payload.model, state.profiles())?;
+    payload.model = model;
 
     let stream_requested = payload.stream;
-    let prompt_payload = payload.into_prompt()?;
+    let describe_tool_calls =
+        state.config().tool_call_fallback == ToolCallFallback::Describe && payload.tools.is_empty();
+    let mut prompt_payload = conversion::convert(
+        &state,
+        payload,
+        state.config().conversion_options(PromptEndpoint::Chat),
+    )
+    .await?;
+    prompt_payload.profile = profile;
+    if let Some(prediction) = prompt_payload.prediction.as_deref() {
+        if state.config().use_prediction_hint {
+            inject_prediction_hint(&mut prompt_payload.prompt, prediction);
+        } else {
+            prompt_payload.warnings.push(
+                "prediction_ignored",
+                "Codex has no predicted outputs, so `prediction` was ignored; start the server \
+                 with --use-prediction-hint to pass it to the model as a draft",
+            );
+        }
+    }
+    // SDKs that send `prediction` read the prediction counts back, so they are always present.
+    let usage_details = state.config().compat_nulls || prompt_payload.prediction.is_some();
+    let warnings = prompt_payload.warnings.clone();
+    if state.config().fail_on_warnings {
+        warnings.reject_any()?;
+    }
+    if dry_run {
+        return dry_run::respond(&state, prompt_payload).await;
+    }
+    let client = client.map(|Extension(client)| client);
+    let budget = state.admit_tokens(&prompt_payload, client.as_ref())?;
+    state.loaded_models().touch(&prompt_payload.model);
+    if let Some(Extension(capture)) = &capture {
+        capture.record_prompt(&prompt_payload);
+    }
+    let access_log = access_log.map(|Extension(log)| log);
+    if let Some(log) = &access_log {
+        log.record_request(&prompt_payload.model, stream_requested);
+        log.record_clock(&prompt_payload.clock);
+    }
+    if state.config().verbose {
+        log_function_tools(&log_context, &prompt_payload.prompt.tools);
+    }
+    let tracked = state.track_request(&prompt_payload.model, client.as_ref(), stream_requested);
+    let account = state.usage_account(&prompt_payload.model, client, access_log.clone());
+    let conversation = state.conversation_turn(&prompt_payload);
+    let queued = queued.and_then(|Extension(queued)| queued.take());
 
     if stream_requested {
-        if verbose_logging_enabled() {
+        if state.config().verbose {
             info!(
                 model = %prompt_payload.model,
                 "forwarding streaming chat request to Codex (upstream)"
             );
         }
-        let stream = stream_chat_response(state.engine(), prompt_payload).await?;
-        return Ok(stream.into_response());
+        let guard = state
+            .metrics()
+            .start_stream()
+            .with_usage_account(account)
+            .with_conversation(conversation)
+            .with_budget(budget);
+        let upstream = telemetry::upstream_span(&prompt_payload.model, true);
+        let progress = prompt_payload
+            .stream_progress
+            .then_some(state.config().progress_interval);
+        let mut response = stream_chat_response(
+            state.clone(),
+            prompt_payload,
+            guard,
+            tracked,
+            access_log,
+            upstream,
+            describe_tool_calls,
+            usage_details,
+            progress,
+            queued,
+            StreamFraming::from_headers(&headers),
+            log_context.clone(),
+        );
+        warnings::report(
+            state.config(),
+            &log_context,
+            "chat.warnings",
+            &warnings,
+            &mut response,
+        );
+        return Ok(match key_guard {
+            Some(key_guard) => idempotency::hold_for_body(response, key_guard),
+            None => response,
+        });
     }
 
-    if verbose_logging_enabled() {
+    if state.config().verbose {
         info!(
             model = %prompt_payload.model,
             "forwarding chat request to Codex (upstream)"
         );
     }
 
-    let response = state.engine().complete(prompt_payload).await?;
-    log_verbose_json("chat.response", &response);
-    Ok(Json(response).into_response())
+    let (_permit, queue_wait) = match queued {
+        Some(ticket) => {
+            let (permit, waited) = ticket.wait(|_| {}).await;
+            (Some(permit), Some(waited))
+        }
+        None => (None, None),
+    };
+    let guard = state
+        .metrics()
+        .start_request()
+        .with_usage_account(account)
+        .with_conversation(conversation)
+        .with_budget(budget);
+    let upstream = telemetry::upstream_span(&prompt_payload.model, false);
+    let clock = prompt_payload.clock.clone();
+    let complete = async {
+        let result = match prompt_payload.sampling {
+            Some(sampling) => sampling::complete(state.engine(), prompt_payload, sampling).await,
+            None => state.engine().complete(prompt_payload).await,
+        };
+        result.inspect_err(|err| state.note_upstream_error(err))
+    };
+    let mut response = tracked
+        .run(complete.instrument(upstream.clone()))
+        .await
+        .inspect_err(|_| {
+            log_verbose_summary(
+                state.config(),
+                &log_context,
+                &Usage::default(),
+                Some("error"),
+                None,
+            );
+        })?;
+    state.note_upstream_success();
+    response.stamp(&clock);
+    if describe_tool_calls {
+        response.describe_tool_calls();
+    }
+    response.report_model(state.config().report_model);
+    let usage = response.usage();
+    telemetry::record_usage(&upstream, usage.prompt_tokens, usage.completion_tokens);
+    guard.record_usage(response.usage());
+    if let Some(log) = &access_log {
+        log.record_outcome(response.usage(), response.finish_reason());
+    }
+    if state.config().usage_extended {
+        response.include_codex_usage();
+    }
+    if state.config().compat_nulls {
+        response.include_compat_nulls();
+    } else if usage_details {
+        response.include_usage_details();
+    }
+    log_verbose_json(state.config(), &log_context, "chat.response", &response);
+    let summary = (
+        response.usage().clone(),
+        response.finish_reason().map(str::to_string),
+    );
+    let usage_header = conversations::usage_header(response.usage());
+    let mut http_response = match key_guard {
+        Some(key_guard) => {
+            let body = serde_json::to_value(&response).map_err(|err| {
+                ApiError::internal(format!("failed to serialize response: {err}"))
+            })?;
+            key_guard.finish(&body);
+            Json(body).into_response()
+        }
+        None => Json(response).into_response(),
+    };
+    if let Some(value) = usage_header {
+        http_response.headers_mut().insert(USAGE_HEADER, value);
+    }
+    if let Some(waited) = queue_wait {
+        http_response
+            .headers_mut()
+            .insert(QUEUE_WAIT_HEADER, queue_wait_header(waited));
+    }
+    warnings::report(
+        state.config(),
+        &log_context,
+        "chat.warnings",
+        &warnings,
+        &mut http_response,
+    );
+    let (usage, finish_reason) = summary;
+    log_verbose_summary(
+        state.config(),
+        &log_context,
+        &usage,
+        finish_reason.as_deref(),
+        None,
+    );
+    Ok(http_response)
 }
 
+/// Always sent with a `200`: `status` is the worst of the `auth`, `upstream` and `config`
+/// component statuses, and the fields before them are kept as they were for older dashboards.
 #[derive(Debug, serde::Serialize)]
 struct HealthzResponse {
+    status: ComponentStatus,
     ok: bool,
+    version: &'static str,
     authenticated: bool,
     message: String,
+    /// Why Codex could not be initialized, while running degraded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    account: Option<AccountDetails>,
+    stats: MetricsSnapshot,
+    /// Raw Codex token totals; only present with `--usage-extended`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    codex_usage: Option<CodexUsageBreakdown>,
+    /// The last upstream keepalive; only present with `--keepalive-interval`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keepalive: Option<KeepaliveStatus>,
+    auth: HealthzAuth,
+    upstream: UpstreamComponent,
     config: HealthzConfig,
 }
 
+/// `failing` whenever Codex cannot be called with the saved login.
+#[derive(Debug, serde::Serialize)]
+struct HealthzAuth {
+    status: ComponentStatus,
+    message: String,
+}
+
+/// `failing` when Codex could not be initialized, `degraded` when it loaded with warnings.
 #[derive(Debug, serde::Serialize)]
 struct HealthzConfig {
+    status: ComponentStatus,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
     expose_reasoning_models: bool,
+    /// `false` under `--hide-reasoning`: no reasoning text is sent, logged or captured.
+    reasoning_exposed: bool,
     web_search_request: bool,
     developer_prompt_mode: String,
+    ollama_version: String,
+    api_surfaces: ApiSurfaces,
+    /// The addresses served and whether each requires `--client-api-key`; empty when the router
+    /// is mounted by an embedding app.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    listeners: Vec<ListenerInfo>,
     models: Vec<String>,
 }
 
+/// Prompt cache statistics per conversation, most recently active first.
+async fn conversation_stats(State(state): State<AppState>) -> Json<Value> {
+    Json(conversation_report(&state))
+}
+
+fn conversation_report(state: &AppState) -> Value {
+    json!({ "conversations": state.conversations().snapshot() })
+}
+
+/// Time to first token and output tokens per second per model, with the streams that failed or
+/// were cancelled counted apart.
+async fn latency_stats(State(state): State<AppState>) -> Json<Value> {
+    Json(latency_report(&state))
+}
+
+fn latency_report(state: &AppState) -> Value {
+    json!({ "models": state.latency().snapshot() })
+}
+
+/// The `--per-client-concurrency` cap and each client's in-flight, admitted, rejected and queued
+/// requests.
+async fn client_stats(State(state): State<AppState>) -> Json<Value> {
+    Json(client_report(&state))
+}
+
+fn client_report(state: &AppState) -> Value {
+    let limiter = state.client_limiter();
+    json!({
+        "per_client_concurrency": limiter.map(|limiter| limiter.limit()),
+        "clients": limiter.map(|limiter| limiter.snapshot()).unwrap_or_default(),
+    })
+}
+
+/// How long converting requests into Codex prompts took.
+async fn conversion_stats(State(state): State<AppState>) -> Json<Value> {
+    Json(conversion_report(&state))
+}
+
+fn conversion_report(state: &AppState) -> Value {
+    json!(state.metrics().conversion_times())
+}
+
+/// The token limits and, with `--max-tokens-per-hour`, each client's spend in the last hour.
+async fn budget_stats(State(state): State<AppState>) -> Json<Value> {
+    Json(budget_report(&state))
+}
+
+fn budget_report(state: &AppState) -> Value {
+    let config = state.config();
+    let clients = config
+        .max_tokens_per_hour
+        .map(|per_hour| state.token_budgets().snapshot(per_hour))
+        .unwrap_or_default();
+    json!({
+        "max_tokens_per_request": config.max_tokens_per_request,
+        "max_tokens_per_hour": config.max_tokens_per_hour,
+        "clients": clients,
+    })
+}
+
+/// Every `/stats/*` document in one, each under the last segment of its route.
+async fn all_stats(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({
+        "budget": budget_report(&state),
+        "conversations": conversation_report(&state),
+        "latency": latency_report(&state),
+        "clients": client_report(&state),
+        "conversion": conversion_report(&state),
+    }))
+}
+
+/// The latency histograms in the Prometheus text format.
+async fn prometheus_metrics(State(state): State<AppState>) -> Response {
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        state.latency().prometheus(),
+    )
+        .into_response()
+}
+
 async fn healthz(State(state): State<AppState>) -> Json<HealthzResponse> {
-    let authenticated = state.auth().is_authenticated();
-    let message = if authenticated {
-        "Codex auth detected".to_string()
-    } else {
-        "Codex auth missing; run `codex login`".to_string()
+    let auth_status = state.auth().status();
+    let authenticated = auth_status == AuthStatus::Active;
+    let message = match auth_status {
+        AuthStatus::Active => match state.auth_mode() {
+            Some(AuthMode::ApiKey) => "Codex auth detected (API key)".to_string(),
+            Some(AuthMode::ChatGPT) => "Codex auth detected (ChatGPT login)".to_string(),
+            None => "Codex auth detected".to_string(),
+        },
+        AuthStatus::Missing => "Codex auth missing; run `codex login`".to_string(),
+        AuthStatus::Expired => {
+            "Codex auth expired and could not be refreshed; run `codex login` again".to_string()
+        }
+        AuthStatus::Unavailable => state
+            .auth()
+            .degraded_startup()
+            .map_or("Codex is unavailable", |startup| startup.remediation())
+            .to_string(),
     };
-    let expose_reasoning = expose_reasoning_models();
+    let error = state
+        .auth()
+        .degraded_startup()
+        .map(|startup| startup.error());
+    let expose_reasoning = state.config().expose_reasoning_models;
     let auth_mode = state.auth_mode();
+    let auth = HealthzAuth {
+        status: if authenticated {
+            ComponentStatus::Ok
+        } else {
+            ComponentStatus::Failing
+        },
+        message: message.clone(),
+    };
+    let upstream = state.upstream_health().snapshot();
+    let warnings: Vec<String> = state
+        .config_warnings()
+        .iter()
+        .cloned()
+        .chain(state.profiles().load_warning().map(str::to_string))
+        .collect();
     let config = HealthzConfig {
+        status: if error.is_some() {
+            ComponentStatus::Failing
+        } else if !warnings.is_empty() {
+            ComponentStatus::Degraded
+        } else {
+            ComponentStatus::Ok
+        },
+        warnings,
         expose_reasoning_models: expose_reasoning,
+        reasoning_exposed: !state.config().hide_reasoning,
         web_search_request: state.web_search_enabled(),
-        developer_prompt_mode: developer_prompt_mode().to_string(),
+        developer_prompt_mode: state.config().developer_prompt_mode.to_string(),
+        ollama_version: state.config().ollama_version.clone(),
+        api_surfaces: state.config().api_surfaces,
+        listeners: state.listeners().to_vec(),
         models: codex_model_ids(expose_reasoning, auth_mode),
     };
     Json(HealthzResponse {
-        ok: true,
+        status: auth.status.max(upstream.status()).max(config.status),
+        ok: error.is_none(),
+        version: version::CRATE_VERSION,
         authenticated,
         message,
+        error,
+        account: state.account_details().await,
+        stats: state.metrics().snapshot(),
+        codex_usage: state
+            .config()
+            .usage_extended
+            .then(|| state.metrics().codex_usage()),
+        keepalive: state.keepalive_status(),
+        auth,
+        upstream,
         config,
     })
 }
@@ -163,108 +901,267 @@ struct ModelsResponse {
 struct ModelEntry {
     id: String,
     object: &'static str,
+    /// Vendor extension; left out for models the executor knows nothing about.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    capabilities: Option<ModelCapabilities>,
 }
 
-async fn list_models(State(state): State<AppState>) -> Json<ModelsResponse> {
-    let include_reasoning = expose_reasoning_models();
-    let data = codex_model_ids(include_reasoning, state.auth_mode())
+/// Lists the Codex models, each with the capabilities known without loading its config. Profiled
+/// entries (`--expose-profiles`) repeat their model's capabilities.
+async fn list_models(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let include_reasoning = state.config().expose_reasoning_models;
+    let ids = codex_model_ids(include_reasoning, state.auth_mode());
+    let engine = state.engine();
+    let capabilities: Vec<Option<ModelCapabilities>> =
+        join_all(ids.iter().map(|id| engine.listed_model_info(id)))
+            .await
+            .iter()
+            .map(|info| info.as_ref().map(ModelCapabilities::from))
+            .collect();
+    let mut entries: Vec<(String, Option<ModelCapabilities>)> = ids
+        .iter()
+        .cloned()
+        .zip(capabilities.iter().cloned())
+        .collect();
+    if state.config().expose_profiles {
+        for profile in state.profiles().names() {
+            entries.extend(
+                ids.iter()
+                    .zip(&capabilities)
+                    .map(|(id, capabilities)| (format!("{profile}/{id}"), capabilities.clone())),
+            );
+        }
+    }
+    let data = entries
         .into_iter()
-        .map(|id| ModelEntry {
+        .map(|(id, capabilities)| ModelEntry {
             id,
             object: "model",
+            capabilities,
         })
         .collect();
-    Json(ModelsResponse {
-        object: "list",
-        data,
-    })
+    conditional::cached_json(
+        &headers,
+        &ModelsResponse {
+            object: "list",
+            data,
+        },
+    )
 }
 
 #[derive(Debug, serde::Serialize)]
-struct VersionResponse {
-    version: &'static str,
+struct OllamaTagsResponse {
+    models: Vec<OllamaModelEntry>,
 }
 
-const CHATMOCK_VERSION: &str = "0.12.10";
-
-async fn api_version() -> Json<VersionResponse> {
-    Json(VersionResponse {
-        version: CHATMOCK_VERSION,
-    })
+#[derive(Debug, serde::Serialize)]
+struct OllamaModelEntry {
+    name: String,
+    model: String,
+    modified_at: String,
+    size: u64,
+    digest: String,
+    details: OllamaModelDetails,
 }
 
 #[derive(Debug, serde::Serialize)]
-struct OllamaTagsResponse {
-    models: Vec<OllamaModelEntry>,
+struct OllamaPsResponse {
+    models: Vec<OllamaRunningModel>,
 }
 
+/// One `/api/ps` entry: the `/api/tags` fields minus `modified_at`, plus when it "unloads".
 #[derive(Debug, serde::Serialize)]
-struct OllamaModelEntry {
+struct OllamaRunningModel {
     name: String,
     model: String,
-    modified_at: &'static str,
     size: u64,
-    digest: &'static str,
+    digest: String,
     details: OllamaModelDetails,
+    expires_at: String,
+    size_vram: u64,
 }
 
-#[derive(Debug, serde::Serialize, Clone, Copy)]
+#[derive(Debug, serde::Serialize, Clone)]
 struct OllamaModelDetails {
     parent_model: &'static str,
     format: &'static str,
     family: &'static str,
-    families: &'static [&'static str],
+    families: Vec<String>,
     parameter_size: &'static str,
     quantization_level: &'static str,
 }
 
-#[derive(Clone, Copy)]
-struct OllamaModelMetadata {
-    modified_at: &'static str,
-    size: u64,
-    digest: &'static str,
-    details: OllamaModelDetails,
-}
+/// The `size` every model reports; Ollama clients expect a plausible llama build.
+const OLLAMA_MODEL_SIZE: u64 = 815_319_791;
 
-const OLLAMA_MODEL_METADATA: OllamaModelMetadata = OllamaModelMetadata {
-    modified_at: "2023-10-01T00:00:00Z",
-    size: 815_319_791,
-    digest: "8648f39daa8fbf5b18c7b4e6a8fb4990c692751d49917417b8842ca5758e7ffc",
-    details: OllamaModelDetails {
+/// The llama-shaped details Ollama clients expect. Reasoning variants add a `reasoning-<effort>`
+/// family so clients can tell them apart from their base model.
+fn ollama_details(model_id: &str) -> OllamaModelDetails {
+    let mut families = vec!["llama".to_string()];
+    if let Some((_, effort)) = parse_reasoning_variant(model_id) {
+        families.push(format!("reasoning-{effort}"));
+    }
+    OllamaModelDetails {
         parent_model: "",
         format: "gguf",
         family: "llama",
-        families: &["llama"],
+        families,
         parameter_size: "8.0B",
         quantization_level: "Q4_0",
-    },
-};
+    }
+}
 
 #[derive(Debug, Deserialize)]
 struct OllamaShowRequest {
     model: Option<String>,
 }
 
-async fn api_tags(State(state): State<AppState>) -> Json<OllamaTagsResponse> {
-    let models = codex_model_ids(expose_reasoning_models(), state.auth_mode());
-    let entries = models
+/// `/api/pull` and `/api/delete`; older Ollama clients send the model as `name`.
+#[derive(Debug, Deserialize)]
+struct OllamaModelRequest {
+    #[serde(alias = "name")]
+    model: Option<String>,
+    stream: Option<bool>,
+}
+
+async fn api_tags(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let models = codex_model_ids(state.config().expose_reasoning_models, state.auth_mode());
+    let entries = join_all(models.iter().map(|model_id| ollama_entry(&state, model_id))).await;
+    conditional::cached_json(&headers, &OllamaTagsResponse { models: entries })
+}
+
+/// Lists the models that served a request within Ollama's keep-alive window.
+async fn api_ps(State(state): State<AppState>) -> Json<OllamaPsResponse> {
+    let loaded = state.loaded_models().list();
+    let models = join_all(loaded.into_iter().map(|(model_id, expires_at)| {
+        let state = &state;
+        async move {
+            let entry = ollama_entry(state, &model_id).await;
+            OllamaRunningModel {
+                name: entry.name,
+                model: entry.model,
+                size: entry.size,
+                digest: entry.digest,
+                details: entry.details,
+                expires_at: rfc3339_nanos(expires_at),
+                size_vram: entry.size,
+            }
+        }
+    }))
+    .await;
+    Json(OllamaPsResponse { models })
+}
+
+async fn ollama_entry(state: &AppState, model_id: &str) -> OllamaModelEntry {
+    let info = state
+        .engine()
+        .model_info(model_id, None)
+        .await
+        .unwrap_or_else(|err| {
+            warn!(
+                model = model_id,
+                "model info unavailable for Ollama metadata: {err:?}"
+            );
+            ModelInfo::default()
+        });
+    let name = ollama_model_name(model_id, state.config().ollama_tag_style);
+    OllamaModelEntry {
+        digest: ollama_digest(&name, &info, state.config().ollama_digest_salt.as_deref()),
+        model: name.clone(),
+        name,
+        modified_at: ollama_modified_at(),
+        size: OLLAMA_MODEL_SIZE,
+        details: ollama_details(model_id),
+    }
+}
+
+/// How Ollama listings spell `model_id`: reasoning variants follow `--ollama-tag-style`
+/// whichever way the client asked for them, other models are listed as they are.
+fn ollama_model_name(model_id: &str, style: OllamaTagStyle) -> String {
+    match parse_reasoning_variant(model_id) {
+        Some((base, effort)) => match style {
+            OllamaTagStyle::Hyphen => format!("{base}-{effort}"),
+            OllamaTagStyle::Tag => format!("{base}:{effort}"),
+        },
+        None => model_id.trim().to_string(),
+    }
+}
+
+/// Models named by the Ollama API's unknown-model errors.
+const SUGGESTED_MODELS: usize = 5;
+
+/// The Ollama API's `404` for a model Codex Serve cannot serve. Ollama's own message sends users
+/// to `ollama pull`, so this one names the models `/api/tags` lists instead.
+pub(super) fn unknown_ollama_model(state: &AppState, requested: &str) -> ApiError {
+    ApiError::not_found(format!(
+        "model '{requested}' not found; {}",
+        served_models_hint(state)
+    ))
+}
+
+/// The first [`SUGGESTED_MODELS`] models `/api/tags` lists, spelled as it spells them.
+fn served_models_hint(state: &AppState) -> String {
+    let config = state.config();
+    let names: Vec<String> = codex_model_ids(config.expose_reasoning_models, state.auth_mode())
         .iter()
-        .map(|model_id| build_ollama_entry(model_id))
+        .map(|model_id| ollama_model_name(model_id, config.ollama_tag_style))
         .collect();
-    Json(OllamaTagsResponse { models: entries })
+    let mut listed = names
+        .iter()
+        .take(SUGGESTED_MODELS)
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(", ");
+    if names.len() > SUGGESTED_MODELS {
+        listed.push_str(&format!(" and {} more", names.len() - SUGGESTED_MODELS));
+    }
+    format!(
+        "this server is Codex Serve, which serves {listed} (see /api/tags); models cannot be \
+         pulled here"
+    )
 }
 
-fn build_ollama_entry(model_id: &str) -> OllamaModelEntry {
-    OllamaModelEntry {
-        name: model_id.to_string(),
-        model: model_id.to_string(),
-        modified_at: OLLAMA_MODEL_METADATA.modified_at,
-        size: OLLAMA_MODEL_METADATA.size,
-        digest: OLLAMA_MODEL_METADATA.digest,
-        details: OLLAMA_MODEL_METADATA.details,
+/// Refuses an Ollama request for a model the executor cannot load with
+/// [`unknown_ollama_model`], before any prompt work.
+pub(super) async fn ensure_ollama_model(
+    state: &AppState,
+    requested: &str,
+    model: &str,
+    profile: Option<&str>,
+) -> Result<(), ApiError> {
+    match state.engine().model_info(model, profile).await {
+        Ok(_) => Ok(()),
+        Err(ApiError::BadRequest(message) | ApiError::NotFound(message)) => {
+            warn!(
+                model = requested,
+                "unknown model requested over the Ollama API: {message}"
+            );
+            Err(unknown_ollama_model(state, requested))
+        }
+        Err(err) => Err(err),
     }
 }
 
+/// SHA-256 over the model name, everything we advertise about it and `--ollama-digest-salt`.
+/// Clients that cache model metadata by digest then refetch exactly when that metadata changes,
+/// not on every restart.
+fn ollama_digest(model_id: &str, info: &ModelInfo, salt: Option<&str>) -> String {
+    let advertised = json!({
+        "model": model_id,
+        "details": ollama_details(model_id),
+        "capabilities": ollama_capabilities(info),
+        "context_window": info.context_window,
+        "salt": salt,
+    });
+    format!("{:x}", Sha256::digest(advertised.to_string()))
+}
+
+/// The build time stamped by `build.rs`, reported as every model's `modified_at`.
+fn ollama_modified_at() -> String {
+    let epoch = env!("CODEX_SERVE_BUILD_EPOCH").parse().unwrap_or(0);
+    rfc3339_nanos(UNIX_EPOCH + Duration::from_secs(epoch))
+}
+
 fn codex_model_ids(include_reasoning_variants: bool, auth_mode: Option<AuthMode>) -> Vec<String> {
     let mut seen = HashSet::new();
     let mut models = Vec::new();
@@ -303,9 +1200,13 @@ fn reasoning_suffix(effort: ReasoningEffort) -> Option<String> {
     Some(effort.to_string())
 }
 
+/// Splits a reasoning variant into its base model and effort. Both `model-high` and Ollama's
+/// `model:high` spellings are accepted.
 fn parse_reasoning_variant(model: &str) -> Option<(String, ReasoningEffort)> {
     let trimmed = model.trim();
-    let (base, suffix) = trimmed.rsplit_once('-')?;
+    let (base, suffix) = trimmed
+        .rsplit_once(':')
+        .or_else(|| trimmed.rsplit_once('-'))?;
     let normalized_suffix = suffix.to_ascii_lowercase();
 
     ReasoningEffort::iter()
@@ -313,41 +1214,13 @@ fn parse_reasoning_variant(model: &str) -> Option<(String, ReasoningEffort)> {
         .map(|effort| (base.to_string(), effort))
 }
 
-fn log_verbose_json<T>(event: &str, value: &T)
-where
-    T: ?Sized + Serialize,
-{
-    if !verbose_logging_enabled() {
-        return;
-    }
-    match serde_json::to_string(value) {
-        Ok(serialized) => info!(event = event, payload = %serialized, "verbose emit"),
-        Err(err) => warn!(event = event, "failed to serialize verbose payload: {err}"),
-    }
-}
-
-fn log_verbose_stream_response(
-    model: &str,
-    response_id: &str,
-    text: Option<String>,
-    reasoning_summary: Option<String>,
-    reasoning_content: Option<String>,
-    tool_calls: Vec<ToolCall>,
-    usage: &Usage,
-) {
-    let payload = json!({
-        "model": model,
-        "response_id": response_id,
-        "text": text,
-        "reasoning_summary": reasoning_summary,
-        "reasoning_content": reasoning_content,
-        "tool_calls": if tool_calls.is_empty() { Value::Null } else { serde_json::to_value(tool_calls).unwrap_or(Value::Null) },
-        "usage": usage,
-    });
-    log_verbose_json("chat.stream.response", &payload);
-}
-
-pub(super) fn tool_call_from_item(item: &ResponseItem) -> Option<ToolCall> {
+/// The tool call `item` carries, if any; `done` for `OutputItemDone` items. Every web search
+/// takes the next position in `searches`, which names it when the upstream sent no id.
+pub(super) fn tool_call_from_item(
+    item: &ResponseItem,
+    searches: &mut WebSearchPositions,
+    done: bool,
+) -> Option<ToolCall> {
     match item {
         ResponseItem::FunctionCall {
             call_id,
@@ -366,17 +1239,28 @@ pub(super) fn tool_call_from_item(item: &ResponseItem) -> Option<ToolCall> {
             ..
         } => Some(ToolCall::new(call_id.clone(), name.clone(), input.clone())),
         ResponseItem::WebSearchCall { id, action, .. } => {
+            let position = searches.next(done);
+            let arguments = web_search_arguments(action);
             let call_id = id
-                .clone()
+                .as_deref()
+                .map(str::trim)
                 .filter(|value| !value.is_empty())
-                .unwrap_or_else(|| format!("ws_call_{}", Uuid::new_v4()));
-            let arguments = web_search_arguments(action);
+                .map_or_else(|| web_search_call_id(position), str::to_string);
             Some(ToolCall::new(call_id, "web_search".to_string(), arguments))
         }
         _ => None,
     }
 }
 
+/// The id of a search the upstream sent without one, derived from its position in the response
+/// rather than from what it searched for: two identical searches stay two calls, and the
+/// `OutputItemAdded` of a search names the same call as its `OutputItemDone` even when it carries
+/// less of the action.
+fn web_search_call_id(position: usize) -> String {
+    let digest = format!("{:x}", Sha256::digest(format!("web_search:{position}")));
+    format!("ws_call_{}", &digest[..24])
+}
+
 fn web_search_arguments(action: &WebSearchAction) -> String {
     match action {
         WebSearchAction::Search { query } => {
@@ -413,164 +1297,374 @@ fn web_search_arguments(action: &WebSearchAction) -> String {
     }
 }
 
-fn tool_call_delta_chunk(
-    response_id: &str,
-    created: i64,
-    model: &str,
-    call: &ToolCall,
-    index: usize,
-) -> Event {
-    let payload = json!({
-        "id": response_id,
-        "object": "chat.completion.chunk",
-        "created": created,
-        "model": model,
-        "choices": [{
-            "index": 0,
-            "delta": {
-                "tool_calls": [{
-                    "index": index,
-                    "id": call.id,
-                    "type": call.call_type,
-                    "function": {
-                        "name": call.function.name,
-                        "arguments": call.function.arguments,
-                    }
-                }]
-            },
-            "finish_reason": Value::Null,
-        }],
-    });
-    Event::default()
-        .json_data(payload)
-        .expect("serialize tool call chunk")
-}
-
-const OLLAMA_SHOW_MODELFILE: &str = r#"# Modelfile generated by "ollama show"
-# To build a new Modelfile based on this one, replace the FROM line with:
-# FROM llava:latest
-
-FROM /models/blobs/sha256:placeholder
-TEMPLATE """{{ .System }}
-USER: {{ .Prompt }}
-ASSISTANT: """
-PARAMETER num_ctx 100000
-PARAMETER stop "</s>"
-PARAMETER stop "USER:"
-PARAMETER stop "ASSISTANT:""#;
-
-const OLLAMA_SHOW_PARAMETERS: &str = r#"num_keep 24
-stop "<|start_header_id|>"
-stop "<|end_header_id|>"
-stop "<|eot_id|>""#;
-
-const OLLAMA_SHOW_TEMPLATE: &str = r#"{{ if .System }}<|start_header_id|>system<|end_header_id|>
-
-{{ .System }}<|eot_id|>{{ end }}{{ if .Prompt }}<|start_header_id|>user<|end_header_id|>
-
-{{ .Prompt }}<|eot_id|>{{ end }}<|start_header_id|>assistant<|end_header_id|>
-
-{{ .Response }}<|eot_id|>"#;
-
-async fn api_show(Json(payload): Json<OllamaShowRequest>) -> Response {
-    let model_valid = payload
-        .model
-        .as_deref()
-        .map(|value| !value.trim().is_empty())
-        .unwrap_or(false);
-    if !model_valid {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(json!({ "error": "Model not found" })),
+/// A generic chat template. Codex formats prompts itself, so Ollama clients only display it.
+const OLLAMA_SHOW_TEMPLATE: &str = r#"{{- range .Messages }}{{ .Role }}: {{ .Content }}
+{{ end }}assistant: "#;
+
+async fn api_show(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ApiJson(payload): ApiJson<OllamaShowRequest>,
+) -> Response {
+    let requested = match required_ollama_model(&state, payload.model.as_deref()) {
+        Ok(requested) => requested,
+        Err(err) => return ollama::error_response(err),
+    };
+
+    let info = async {
+        let (profile, model) = resolve_profile(&headers, requested, state.profiles())?;
+        state.engine().model_info(&model, profile.as_deref()).await
+    };
+    match info.await {
+        Ok(info) => {
+            conditional::cached_json(&headers, &build_ollama_show_payload(requested, &info))
+        }
+        // A config that fails to load is our fault, not an unknown model.
+        Err(err) if err.status().is_server_error() => {
+            warn!(model = requested, "ollama show failed: {err:?}");
+            ollama::error_response(err)
+        }
+        // Ollama answers unknown models with a bare `{"error": ...}` 404.
+        Err(err) => {
+            warn!(model = requested, "ollama show failed: {err:?}");
+            ollama::error_response(unknown_ollama_model(&state, requested))
+        }
+    }
+}
+
+/// The trimmed `model` of an Ollama request, or a `400` naming the models to pick from.
+fn required_ollama_model<'a>(
+    state: &AppState,
+    model: Option<&'a str>,
+) -> Result<&'a str, ApiError> {
+    model
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| {
+            ApiError::bad_request(format!("model is required; {}", served_models_hint(state)))
+        })
+}
+
+/// Codex models are never downloaded, so pulling one that is served succeeds at once, with the
+/// final `{"status": "success"}` record Ollama ends a pull with.
+async fn api_pull(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ApiJson(payload): ApiJson<OllamaModelRequest>,
+) -> Response {
+    let pulled = async {
+        let requested = required_ollama_model(&state, payload.model.as_deref())?;
+        let (profile, model) = resolve_profile(&headers, requested, state.profiles())?;
+        ensure_ollama_model(&state, requested, &model, profile.as_deref()).await
+    };
+    if let Err(err) = pulled.await {
+        return ollama::error_response(err);
+    }
+    let status = json!({ "status": "success" });
+    if payload.stream.unwrap_or(true) {
+        (
+            [(CONTENT_TYPE, "application/x-ndjson")],
+            format!("{status}\n"),
         )
-            .into_response();
+            .into_response()
+    } else {
+        Json(status).into_response()
     }
+}
 
-    Json(build_ollama_show_payload()).into_response()
+/// Codex models are not stored locally, so there is nothing to delete: a served model is a `400`
+/// saying so, an unknown one the usual `404`.
+async fn api_delete(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ApiJson(payload): ApiJson<OllamaModelRequest>,
+) -> Response {
+    let served = async {
+        let requested = required_ollama_model(&state, payload.model.as_deref())?;
+        let (profile, model) = resolve_profile(&headers, requested, state.profiles())?;
+        ensure_ollama_model(&state, requested, &model, profile.as_deref()).await?;
+        Ok::<_, ApiError>(requested)
+    };
+    let err = match served.await {
+        Ok(requested) => ApiError::bad_request(format!(
+            "model '{requested}' is served by Codex Serve and cannot be deleted here"
+        )),
+        Err(err) => err,
+    };
+    ollama::error_response(err)
 }
 
-fn build_ollama_show_payload() -> Value {
-    let details = serde_json::to_value(OLLAMA_MODEL_METADATA.details)
-        .expect("static model details should serialize");
+/// Ollama's `/api/show` for `model_id`. The modelfile and `parameters` describe the Codex model
+/// as it is served: no weights, no stop tokens (Codex models do not use chat header tokens), and
+/// a `num_ctx` only when the real context window is known, since clients size prompts by it.
+fn build_ollama_show_payload(model_id: &str, info: &ModelInfo) -> Value {
+    let (base, effort) = match parse_reasoning_variant(model_id) {
+        Some((base, effort)) => (base, Some(effort)),
+        None => (model_id.to_string(), None),
+    };
+    let mut model_info = json!({
+        "general.architecture": "llama",
+        "general.file_type": 2,
+    });
+    let mut parameters = Vec::new();
+    if let Some(context_window) = info.context_window {
+        parameters.push(("num_ctx", context_window.to_string()));
+        model_info["llama.context_length"] = json!(context_window);
+    }
+    if let Some(effort) = effort {
+        parameters.push(("reasoning_effort", effort.to_string()));
+    }
+
+    let mut modelfile = format!(
+        "# {model_id} is a virtual model served by codex-serve: requests go to Codex, and no \
+         weights are stored locally.\n\nFROM {base}\n"
+    );
+    modelfile.push_str(&format!("TEMPLATE \"\"\"{OLLAMA_SHOW_TEMPLATE}\"\"\"\n"));
+    for (name, value) in &parameters {
+        modelfile.push_str(&format!("PARAMETER {name} {value}\n"));
+    }
+    let parameters = parameters
+        .iter()
+        .map(|(name, value)| format!("{name:<30}{value}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+
     json!({
-        "modelfile": OLLAMA_SHOW_MODELFILE,
-        "parameters": OLLAMA_SHOW_PARAMETERS,
+        "modelfile": modelfile,
+        "parameters": parameters,
         "template": OLLAMA_SHOW_TEMPLATE,
-        "details": details,
-        "model_info": {
-            "general.architecture": "llama",
-            "general.file_type": 2,
-            "llama.context_length": 2000000,
-        },
-        "capabilities": ["completion", "vision", "tools", "thinking"],
+        "details": ollama_details(model_id),
+        "model_info": model_info,
+        "capabilities": ollama_capabilities(info),
+        "modified_at": ollama_modified_at(),
     })
 }
 
-async fn stream_chat_response(
-    executor: SharedChatExecutor,
-    payload: crate::openai::chat::PromptPayload,
-) -> Result<Sse<SseStream>, ApiError> {
-    let handle = executor.stream(payload).await?;
-    Ok(build_sse_stream(handle))
+fn ollama_capabilities(info: &ModelInfo) -> Vec<&'static str> {
+    let mut capabilities = vec!["completion"];
+    if info.vision {
+        capabilities.push("vision");
+    }
+    if info.tools {
+        capabilities.push("tools");
+    }
+    if info.reasoning {
+        capabilities.push("thinking");
+    }
+    capabilities
 }
 
-fn build_sse_stream(handle: StreamingHandle) -> Sse<SseStream> {
-    let (tx, rx) = mpsc::channel::<Result<Event, Infallible>>(32);
+/// Returns the SSE response straight away and runs the upstream handshake (config load and
+/// connection) inside the forwarding task, so clients get headers and the role chunk while Codex
+/// is still connecting. Handshake failures arrive as an in-stream OpenAI error event.
+///
+/// A `queued` request first waits for its client's slot, sending `: queued position=N` comments
+/// as its place in the queue changes, and holds the slot until the stream ends.
+///
+/// `guard` and `tracked` live as long as the forwarding task, and the task ends as soon as the
+/// client goes away, so neither the active-stream gauge nor `/admin/requests` can leak. A stream
+/// cancelled through `tracked` ends with the usual in-stream error event and `[DONE]`. The body
+/// keeps `access_log` alive, so the access log line is written once the stream is over. The
+/// forwarding task runs inside `upstream`, the (possibly disabled) OpenTelemetry span.
+#[allow(clippy::too_many_arguments)]
+fn stream_chat_response(
+    state: AppState,
+    payload: crate::openai::chat::PromptPayload,
+    guard: InFlightGuard,
+    tracked: TrackedRequest,
+    access_log: Option<AccessLog>,
+    upstream: Span,
+    describe_tool_calls: bool,
+    usage_details: bool,
+    progress: Option<Duration>,
+    queued: Option<QueueTicket>,
+    framing: StreamFraming,
+    log_context: LogContext,
+) -> Response {
+    let (tx, rx) = mpsc::channel::<StreamFrame>(32);
+    let clock = payload.clock.clone();
+    // `--report-model` names a model only the executor knows, so the role chunk waits for its
+    // handle instead.
+    if state.config().report_model.is_none() {
+        let role_template = ChunkTemplate::new(&clock, payload.model.clone())
+            .with_compat_nulls(state.config().compat_nulls);
+        let role_chunk = StreamFrame::json(role_template.chunk(ChunkDelta::role(), None));
+        // The channel is empty, so this cannot fail for lack of capacity.
+        let _ = tx.try_send(role_chunk);
+    }
+    let request_id = current_request_id();
+
+    let task_log = access_log.clone();
+    let task = async move {
+        let forward = async {
+            let _permit = match queued {
+                Some(ticket) => {
+                    let (permit, _) = ticket
+                        .wait(|position| {
+                            let comment = format!("queued position={position}");
+                            // Only a client that is not reading can fill the channel, and it
+                            // does not miss a position it will never see.
+                            let _ = tx.try_send(StreamFrame::Comment(comment));
+                        })
+                        .await;
+                    Some(permit)
+                }
+                None => None,
+            };
+            let handle = state
+                .engine()
+                .stream(payload)
+                .await
+                .inspect_err(|err| state.note_upstream_error(err))?;
+            forward_stream_chunks(
+                handle,
+                tx.clone(),
+                &clock,
+                state.config(),
+                &log_context,
+                describe_tool_calls,
+                usage_details,
+                progress.map(ProgressMeter::new),
+            )
+            .await
+        };
+        let forward = tracked.run(forward);
+        tokio::select! {
+            result = forward => match result {
+                Ok(outcome) => {
+                    if outcome.finish_reason == Some("error") {
+                        state.note_upstream_stream_error();
+                    } else {
+                        state.note_upstream_success();
+                    }
+                    guard.record_usage(&outcome.usage);
+                    telemetry::record_usage(
+                        &Span::current(),
+                        outcome.usage.prompt_tokens,
+                        outcome.usage.completion_tokens,
+                    );
+                    if let Some(log) = &task_log {
+                        log.record_outcome(&outcome.usage, outcome.finish_reason);
+                    }
+                    log_verbose_summary(
+                        state.config(),
+                        &log_context,
+                        &outcome.usage,
+                        outcome.finish_reason,
+                        outcome.content.as_ref(),
+                    );
+                }
+                Err(err) => {
+                    warn!("streaming error: {err:?}");
+                    if let Some(log) = &task_log {
+                        log.record_finish_reason("error");
+                    }
+                    log_verbose_summary(
+                        state.config(),
+                        &log_context,
+                        &Usage::default(),
+                        Some("error"),
+                        None,
+                    );
+                    let _ = tx.send(StreamFrame::json(err.into_body_json(request_id))).await;
+                }
+            },
+            _ = tx.closed() => {
+                if let Some(log) = &task_log {
+                    log.record_finish_reason("client_disconnected");
+                }
+                log_verbose_summary(
+                    state.config(),
+                    &log_context,
+                    &Usage::default(),
+                    Some("client_disconnected"),
+                    None,
+                );
+                return;
+            }
+        }
+        let _ = tx.send(StreamFrame::Done).await;
+    };
+    tokio::spawn(task.instrument(upstream));
 
-    tokio::spawn(async move {
-        if let Err(err) = forward_sse_events(handle, tx.clone()).await {
-            warn!("streaming error: {err:?}");
+    let frames = ReceiverStream::new(rx).inspect(move |_| {
+        if let Some(log) = &access_log {
+            log.mark_first_byte();
         }
-        let _ = tx.send(Ok(done_event())).await;
     });
+    framing.respond(frames)
+}
 
-    Sse::new(ReceiverStream::new(rx))
+/// What a finished stream reported, for metrics and the access log.
+struct StreamOutcome {
+    usage: Usage,
+    finish_reason: Option<&'static str>,
+    /// What the finish chunk advertised under `--stream-integrity`.
+    content: Option<ContentIntegrity>,
 }
 
-async fn forward_sse_events(
+#[allow(clippy::too_many_arguments)]
+async fn forward_stream_chunks(
     handle: StreamingHandle,
-    tx: mpsc::Sender<Result<Event, Infallible>>,
-) -> Result<(), ApiError> {
+    tx: mpsc::Sender<StreamFrame>,
+    clock: &RequestClock,
+    config: &ServeConfig,
+    log_context: &LogContext,
+    describe_tool_calls: bool,
+    usage_details: bool,
+    mut progress: Option<ProgressMeter>,
+) -> Result<StreamOutcome, ApiError> {
     let StreamingHandle {
         mut stream,
         response_model,
+        resolved_model,
     } = handle;
-    let created = current_timestamp();
-    let mut stream_response_id = "resp_stream".to_string();
-    let mut sent_role = false;
+    let template = ChunkTemplate::new(clock, response_model)
+        .with_report_model(resolved_model, config.report_model)
+        .with_compat_nulls(config.compat_nulls)
+        .with_usage_details(usage_details);
+    if config.report_model.is_some() {
+        // A closed channel is noticed at the next send.
+        let _ = tx
+            .send(StreamFrame::json(template.chunk(ChunkDelta::role(), None)))
+            .await;
+    }
     let mut usage = Usage::default();
-    let verbose_enabled = verbose_logging_enabled();
+    let mut outcome_reason = None;
+    let mut outcome_content = None;
+    let mut digest = config.stream_integrity.then(ContentDigest::default);
+    let verbose_enabled = config.verbose;
     let mut verbose_text = verbose_enabled.then(String::new);
     let mut text_deltas_since_last_message = false;
-    let mut verbose_reasoning_summary = verbose_enabled.then(String::new);
-    let mut reasoning_content = verbose_enabled.then(String::new);
+    let mut text_sent = false;
+    // Under `--hide-reasoning` the stream carries no reasoning, and the log has no field for it.
+    let log_reasoning = verbose_enabled && !config.hide_reasoning;
+    let mut verbose_reasoning_summary = log_reasoning.then(String::new);
+    let mut reasoning_content = log_reasoning.then(String::new);
     let mut streamed_tool_calls: Vec<ToolCall> = Vec::new();
-    let mut tool_call_indices: HashMap<String, usize> = HashMap::new();
-    let mut tool_call_arg_progress: HashMap<String, usize> = HashMap::new();
-    let mut next_tool_index = 0usize;
+    let mut tool_calls = ToolCallTracker::new(config.max_tracked_tool_calls);
+    let max_event_bytes = config.max_sse_event_bytes;
 
     while let Some(event) = FuturesStreamExt::next(&mut stream).await {
         match event {
             Ok(ResponseEvent::OutputTextDelta(delta)) => {
                 text_deltas_since_last_message = true;
-                let mut delta_obj = Map::new();
-                delta_obj.insert("content".to_string(), Value::String(delta.clone()));
-                if !sent_role {
-                    delta_obj.insert("role".to_string(), Value::String("assistant".to_string()));
-                    sent_role = true;
-                }
+                text_sent = true;
                 if let Some(buffer) = verbose_text.as_mut() {
                     buffer.push_str(&delta);
                 }
-                let chunk = chunk_event(
-                    &stream_response_id,
-                    created,
-                    &response_model,
-                    Value::Object(delta_obj),
-                    None,
-                    None,
-                );
-                if tx.send(Ok(chunk)).await.is_err() {
+                if let Some(digest) = digest.as_mut() {
+                    digest.update(&delta);
+                }
+                let chunks = sized_frames(&delta, max_event_bytes, |piece| {
+                    StreamFrame::json(template.chunk(ChunkDelta::content(piece), None))
+                });
+                if send_frames(&tx, chunks).await.is_err() {
+                    break;
+                }
+                if let Some(comment) = progress.as_mut().and_then(|meter| meter.record(&delta))
+                    && tx.send(comment).await.is_err()
+                {
                     break;
                 }
             }
@@ -580,15 +1674,14 @@ async fn forward_sse_events(
                 }
                 if forward_tool_call_chunk(
                     &item,
+                    false,
                     &tx,
-                    &stream_response_id,
-                    created,
-                    &response_model,
-                    &mut tool_call_indices,
-                    &mut next_tool_index,
+                    &template,
+                    &mut tool_calls,
                     &mut streamed_tool_calls,
-                    &mut tool_call_arg_progress,
                     verbose_enabled,
+                    describe_tool_calls,
+                    max_event_bytes,
                 )
                 .await
                 {
@@ -605,22 +1698,24 @@ async fn forward_sse_events(
                         if let Some(buffer) = verbose_text.as_mut() {
                             buffer.push_str(&text);
                         }
-                        let mut delta_obj = Map::new();
-                        if !sent_role {
-                            delta_obj
-                                .insert("role".to_string(), Value::String("assistant".to_string()));
-                            sent_role = true;
+                        if let Some(digest) = digest.as_mut() {
+                            digest.update(&text);
+                        }
+                        text_sent = true;
+                        // No deltas arrived, so replay the finished message in bounded pieces
+                        // rather than one huge event.
+                        let mut client_gone = false;
+                        for piece in split_on_char_boundaries(&text, config.fallback_chunk_bytes) {
+                            let chunks = sized_frames(piece, max_event_bytes, |piece| {
+                                StreamFrame::json(template.chunk(ChunkDelta::content(piece), None))
+                            });
+                            if send_frames(&tx, chunks).await.is_err() {
+                                client_gone = true;
+                                break;
+                            }
+                            tokio::task::yield_now().await;
                         }
-                        delta_obj.insert("content".to_string(), Value::String(text));
-                        let chunk = chunk_event(
-                            &stream_response_id,
-                            created,
-                            &response_model,
-                            Value::Object(delta_obj),
-                            None,
-                            None,
-                        );
-                        if tx.send(Ok(chunk)).await.is_err() {
+                        if client_gone {
                             break;
                         }
                     }
@@ -629,15 +1724,14 @@ async fn forward_sse_events(
                 }
                 if forward_tool_call_chunk(
                     &item,
+                    true,
                     &tx,
-                    &stream_response_id,
-                    created,
-                    &response_model,
-                    &mut tool_call_indices,
-                    &mut next_tool_index,
+                    &template,
+                    &mut tool_calls,
                     &mut streamed_tool_calls,
-                    &mut tool_call_arg_progress,
                     verbose_enabled,
+                    describe_tool_calls,
+                    max_event_bytes,
                 )
                 .await
                 {
@@ -648,22 +1742,15 @@ async fn forward_sse_events(
                 if let Some(buffer) = verbose_reasoning_summary.as_mut() {
                     buffer.push_str(&delta);
                 }
-                let chunk = chunk_event(
-                    &stream_response_id,
-                    created,
-                    &response_model,
-                    json!({
-                        "reasoning": {
-                            "summary": [{
-                                "type": "text",
-                                "text": delta
-                            }]
-                        }
-                    }),
-                    None,
-                    None,
-                );
-                if tx.send(Ok(chunk)).await.is_err() {
+                let chunks = sized_frames(&delta, max_event_bytes, |piece| {
+                    StreamFrame::json(template.chunk(ChunkDelta::reasoning_summary(piece), None))
+                });
+                if send_frames(&tx, chunks).await.is_err() {
+                    break;
+                }
+                if let Some(comment) = progress.as_mut().and_then(|meter| meter.record(&delta))
+                    && tx.send(comment).await.is_err()
+                {
                     break;
                 }
             }
@@ -678,19 +1765,15 @@ async fn forward_sse_events(
                 if let Some(buffer) = reasoning_content.as_mut() {
                     buffer.push_str(&delta);
                 }
-                let chunk = chunk_event(
-                    &stream_response_id,
-                    created,
-                    &response_model,
-                    json!({
-                        "reasoning": {
-                            "content": [{"type": "text", "text": delta}]
-                        }
-                    }),
-                    None,
-                    None,
-                );
-                if tx.send(Ok(chunk)).await.is_err() {
+                let chunks = sized_frames(&delta, max_event_bytes, |piece| {
+                    StreamFrame::json(template.chunk(ChunkDelta::reasoning_content(piece), None))
+                });
+                if send_frames(&tx, chunks).await.is_err() {
+                    break;
+                }
+                if let Some(comment) = progress.as_mut().and_then(|meter| meter.record(&delta))
+                    && tx.send(comment).await.is_err()
+                {
                     break;
                 }
             }
@@ -698,24 +1781,41 @@ async fn forward_sse_events(
                 response_id: rid,
                 token_usage,
             }) => {
-                stream_response_id = rid.clone();
                 if let Some(tokens) = token_usage {
                     usage = Usage::from(tokens);
                 }
-                let finish_reason = if !streamed_tool_calls.is_empty() {
-                    Some("tool_calls")
-                } else {
+                let finish_reason = if streamed_tool_calls.is_empty() {
+                    Some("stop")
+                } else if describe_tool_calls {
+                    // The calls were held back; tell the client about them in text instead.
+                    let mut description = tool_call_description(&streamed_tool_calls);
+                    if text_sent {
+                        description.insert_str(0, "\n\n");
+                    }
+                    if let Some(digest) = digest.as_mut() {
+                        digest.update(&description);
+                    }
+                    let chunks = sized_frames(&description, max_event_bytes, |piece| {
+                        StreamFrame::json(template.chunk(ChunkDelta::content(piece), None))
+                    });
+                    if send_frames(&tx, chunks).await.is_err() {
+                        break;
+                    }
                     Some("stop")
+                } else {
+                    Some("tool_calls")
                 };
-                let chunk = chunk_event(
-                    &stream_response_id,
-                    created,
-                    &response_model,
-                    json!({}),
-                    finish_reason,
-                    Some(&usage),
-                );
-                let _ = tx.send(Ok(chunk)).await;
+                outcome_reason = finish_reason;
+                outcome_content = digest.take().map(ContentDigest::finish);
+                let mut chunk = template
+                    .chunk(ChunkDelta::default(), finish_reason)
+                    .with_usage(&usage)
+                    .with_tool_call_overflow(tool_calls.overflow())
+                    .with_content_integrity(outcome_content.as_ref());
+                if config.usage_extended {
+                    chunk = chunk.with_codex_usage(&usage);
+                }
+                let _ = tx.send(StreamFrame::json(chunk)).await;
                 let text_snapshot = verbose_text.take();
                 let reasoning_snapshot = verbose_reasoning_summary.take();
                 let reasoning_content_snapshot = reasoning_content.take();
@@ -725,8 +1825,11 @@ async fn forward_sse_events(
                     || !streamed_tool_calls.is_empty()
                 {
                     log_verbose_stream_response(
-                        &response_model,
-                        &stream_response_id,
+                        config,
+                        log_context,
+                        template.model(),
+                        clock,
+                        &rid,
                         text_snapshot,
                         reasoning_snapshot,
                         reasoning_content_snapshot,
@@ -738,63 +1841,111 @@ async fn forward_sse_events(
             }
             Ok(ResponseEvent::RateLimits(_)) | Ok(ResponseEvent::Created) => {}
             Err(err) => {
-                let chunk = chunk_event(
-                    &stream_response_id,
-                    created,
-                    &response_model,
-                    json!({}),
-                    Some("error"),
-                    None,
-                );
-                let _ = tx.send(Ok(chunk)).await;
+                let chunk = StreamFrame::json(template.chunk(ChunkDelta::default(), Some("error")));
+                let _ = tx.send(chunk).await;
                 error!("Codex stream error: {err:?}");
+                outcome_reason = Some("error");
                 break;
             }
         }
     }
 
+    Ok(StreamOutcome {
+        usage,
+        finish_reason: outcome_reason,
+        content: outcome_content,
+    })
+}
+
+/// Sends `frames` in order, stopping at the first one the client is no longer there for.
+async fn send_frames(
+    tx: &mpsc::Sender<StreamFrame>,
+    frames: Vec<StreamFrame>,
+) -> Result<(), mpsc::error::SendError<StreamFrame>> {
+    for frame in frames {
+        tx.send(frame).await?;
+    }
     Ok(())
 }
 
+/// Splits `text` into pieces of at most `max_bytes` (at least one character each), never
+/// cutting a UTF-8 character in half.
+fn split_on_char_boundaries(text: &str, max_bytes: usize) -> impl Iterator<Item = &str> {
+    let max_bytes = max_bytes.max(1);
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let mut end = max_bytes.min(rest.len());
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end == 0 {
+            end = rest.chars().next().map_or(rest.len(), char::len_utf8);
+        }
+        let (piece, tail) = rest.split_at(end);
+        rest = tail;
+        Some(piece)
+    })
+}
+
+/// Sends the new part of `item`'s tool call, if it is one; `done` for `OutputItemDone` items.
+/// Calls past the tracking limit are sent whole when done and not kept. Returns `true` once the
+/// client is gone.
 #[allow(clippy::too_many_arguments)]
 async fn forward_tool_call_chunk(
     item: &ResponseItem,
-    tx: &mpsc::Sender<Result<Event, Infallible>>,
-    response_id: &str,
-    created: i64,
-    response_model: &str,
-    tool_call_indices: &mut HashMap<String, usize>,
-    next_tool_index: &mut usize,
+    done: bool,
+    tx: &mpsc::Sender<StreamFrame>,
+    template: &ChunkTemplate,
+    tool_calls: &mut ToolCallTracker,
     streamed_tool_calls: &mut Vec<ToolCall>,
-    tool_call_arg_progress: &mut HashMap<String, usize>,
     verbose_enabled: bool,
+    hold_back: bool,
+    max_event_bytes: Option<usize>,
 ) -> bool {
     if matches!(item, ResponseItem::Reasoning { .. }) {
         return false;
     }
 
-    if let Some(call) = tool_call_from_item(item) {
-        if !tool_call_indices.contains_key(&call.id) {
-            tool_call_indices.insert(call.id.clone(), *next_tool_index);
-            *next_tool_index += 1;
-        }
-        let index = *tool_call_indices
-            .get(&call.id)
-            .expect("tool index should exist");
-        let full_arguments = call.function.arguments.clone();
-        let prev_len = tool_call_arg_progress.get(&call.id).copied().unwrap_or(0);
-        if full_arguments.len() <= prev_len {
-            return false;
-        }
-        let delta = full_arguments[prev_len..].to_string();
-        tool_call_arg_progress.insert(call.id.clone(), full_arguments.len());
-        let mut delta_call = call.clone();
-        delta_call.function.arguments = delta;
-        let chunk = tool_call_delta_chunk(response_id, created, response_model, &delta_call, index);
-        if tx.send(Ok(chunk)).await.is_err() {
-            return true;
-        }
-        streamed_tool_calls.push(call);
+    if let Some(call) = tool_calls.call_from_item(item, done) {
+        let full_arguments = &call.function.arguments;
+        let (index, unsent, tracked) = match tool_calls.slot(&call.id, done) {
+            Slot::Tracked { index, .. } => {
+                match tool_calls.unsent_arguments(&call.id, full_arguments) {
+                    Some(unsent) => (index, unsent, true),
+                    None => return false,
+                }
+            }
+            Slot::Untracked { index } => (index, 0, false),
+            Slot::Skipped => return false,
+        };
+        if !hold_back {
+            // The id and name go out once; later pieces keep the call's index, so clients append
+            // them to the same call.
+            let announce = !tracked || tool_calls.announce(&call.id);
+            let head = |piece: &str| {
+                let delta = if announce {
+                    ChunkDelta::tool_call(index, &call, piece)
+                } else {
+                    ChunkDelta::tool_call_arguments(index, piece)
+                };
+                StreamFrame::json(template.chunk(delta, None))
+            };
+            let rest = |piece: &str| {
+                StreamFrame::json(
+                    template.chunk(ChunkDelta::tool_call_arguments(index, piece), None),
+                )
+            };
+            let chunks = headed_frames(&full_arguments[unsent..], max_event_bytes, head, rest);
+            if send_frames(tx, chunks).await.is_err() {
+                return true;
+            }
+        }
+        if tracked {
+            streamed_tool_calls.push(call);
+        }
     } else if verbose_enabled {
         warn!("Unhandled Codex output item in stream: {item:?}");
     }
@@ -802,82 +1953,887 @@ async fn forward_tool_call_chunk(
     false
 }
 
-fn chunk_event(
-    response_id: &str,
-    created: i64,
-    model: &str,
-    delta: Value,
-    finish_reason: Option<&str>,
-    usage: Option<&Usage>,
-) -> Event {
-    let mut choice = json!({
-        "index": 0,
-        "delta": delta,
-        "finish_reason": finish_reason,
-    });
-    if finish_reason.is_none() {
-        choice["finish_reason"] = Value::Null;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codex_core::error::CodexErr;
+    use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+
+    #[tokio::test]
+    async fn panicking_handler_returns_json_500() {
+        async fn explode() -> &'static str {
+            panic!("boom");
+        }
+
+        let app = with_common_layers(Router::new().route("/panic", get(explode)))
+            .with_state(AppState::insecure_mock(true));
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind test listener");
+        let addr = listener.local_addr().expect("listener address");
+        let server = tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+
+        let response = reqwest::get(format!("http://{addr}/panic"))
+            .await
+            .expect("panicking route should still respond");
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body: Value = response.json().await.expect("body should be JSON");
+        assert_eq!(body["error"]["type"], "server_error");
+        assert!(
+            body["error"]["request_id"]
+                .as_str()
+                .is_some_and(|id| id.starts_with("req_"))
+        );
+        server.abort();
     }
 
-    let mut payload = json!({
-        "id": response_id,
-        "object": "chat.completion.chunk",
-        "created": created,
-        "model": model,
-        "choices": [choice],
-    });
+    async fn spawn_scripted(deltas: usize) -> (SocketAddr, Arc<metrics::ServerMetrics>) {
+        let executor =
+            ScriptedChatExecutor::new(vec!["tick "; deltas]).with_delay(Duration::from_millis(50));
+        let state = AppState::insecure_mock(true).with_executor(Arc::new(executor));
+        let metrics = Arc::clone(state.metrics());
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind test listener");
+        let addr = listener.local_addr().expect("listener address");
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, router(state)).await;
+        });
+        (addr, metrics)
+    }
+
+    async fn open_stream(addr: SocketAddr) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("http://{addr}/v1/chat/completions"))
+            .json(&json!({
+                "model": "gpt-5",
+                "stream": true,
+                "messages": [{"role": "user", "content": "hello"}]
+            }))
+            .send()
+            .await
+            .expect("stream request should succeed")
+    }
 
-    if let Some(usage) = usage {
-        payload["usage"] = json!({
-            "prompt_tokens": usage.prompt_tokens,
-            "completion_tokens": usage.completion_tokens,
-            "total_tokens": usage.total_tokens,
+    async fn wait_for_active_streams(metrics: &metrics::ServerMetrics, expected: u64) {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(2);
+        while metrics.snapshot().active_streams != expected {
+            assert!(
+                tokio::time::Instant::now() < deadline,
+                "active_streams stuck at {}",
+                metrics.snapshot().active_streams
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn stream_gauge_rises_and_falls_on_completion() {
+        let (addr, metrics) = spawn_scripted(4).await;
+        let response = open_stream(addr).await;
+        assert_eq!(metrics.snapshot().active_streams, 1);
+
+        let body = response.text().await.expect("stream body");
+        assert!(body.contains("[DONE]"));
+        wait_for_active_streams(&metrics, 0).await;
+        assert_eq!(metrics.snapshot().requests_total, 1);
+    }
+
+    #[tokio::test]
+    async fn stream_gauge_falls_when_client_disconnects() {
+        let (addr, metrics) = spawn_scripted(200).await;
+        let response = open_stream(addr).await;
+        assert_eq!(metrics.snapshot().active_streams, 1);
+
+        drop(response);
+        wait_for_active_streams(&metrics, 0).await;
+    }
+
+    /// Collects the fields of every access log line emitted while installed.
+    #[derive(Clone, Default)]
+    struct CapturedAccessLogs(Arc<std::sync::Mutex<Vec<HashMap<String, String>>>>);
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for CapturedAccessLogs {
+        fn on_event(
+            &self,
+            event: &tracing::Event<'_>,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            struct Fields(HashMap<String, String>);
+            impl tracing::field::Visit for Fields {
+                fn record_debug(
+                    &mut self,
+                    field: &tracing::field::Field,
+                    value: &dyn std::fmt::Debug,
+                ) {
+                    self.0
+                        .insert(field.name().to_string(), format!("{value:?}"));
+                }
+                fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+                    self.0.insert(field.name().to_string(), value.to_string());
+                }
+            }
+            let mut fields = Fields(HashMap::new());
+            event.record(&mut fields);
+            if fields.0.contains_key("request_id") {
+                self.0.lock().unwrap().push(fields.0);
+            }
+        }
+    }
+
+    impl CapturedAccessLogs {
+        fn lines(&self, message: &str) -> Vec<HashMap<String, String>> {
+            self.0
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|line| line.get("message").map(String::as_str) == Some(message))
+                .cloned()
+                .collect()
+        }
+
+        /// Waits for the final access log line, written once the last handle is dropped.
+        async fn wait_for(&self, message: &str) -> HashMap<String, String> {
+            let deadline = tokio::time::Instant::now() + Duration::from_secs(2);
+            loop {
+                if let Some(line) = self.lines(message).pop() {
+                    return line;
+                }
+                assert!(
+                    tokio::time::Instant::now() < deadline,
+                    "no `{message}` access log line"
+                );
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn access_log_is_written_when_stream_finishes() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let captured = CapturedAccessLogs::default();
+        let _subscriber =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(captured.clone()));
+        let (addr, _) = spawn_scripted(3).await;
+
+        let response = open_stream(addr).await;
+        assert!(captured.lines("handled request").is_empty());
+        assert_eq!(captured.lines("stream started").len(), 1);
+        response.text().await.expect("stream body");
+
+        let line = &captured.wait_for("handled request").await;
+        assert_eq!(line["outcome"], "completed");
+        assert_eq!(line["path"], "/v1/chat/completions");
+        assert_eq!(line["status"], "200");
+        assert_eq!(line["model"], "gpt-5");
+        assert_eq!(line["stream"], "true");
+        assert_eq!(line["finish_reason"], "stop");
+        for field in [
+            "request_id",
+            "duration_ms",
+            "ttfb_ms",
+            "prompt_tokens",
+            "completion_tokens",
+        ] {
+            assert!(line.contains_key(field), "missing {field}: {line:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn verbose_events_of_one_request_share_its_id() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let captured = CapturedAccessLogs::default();
+        let _subscriber =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(captured.clone()));
+        let state = AppState::insecure_mock(true)
+            .with_config(ServeConfig::builder().verbose(true).build())
+            .with_executor(Arc::new(ScriptedChatExecutor::new(["hi"])));
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind test listener");
+        let addr = listener.local_addr().expect("listener address");
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, router(state)).await;
         });
+
+        let client = reqwest::Client::new();
+        for (request_id, stream) in [("req-complete", false), ("req-stream", true)] {
+            let response = client
+                .post(format!("http://{addr}/v1/chat/completions"))
+                .header("x-request-id", request_id)
+                .json(&json!({
+                    "model": "gpt-5",
+                    "stream": stream,
+                    "messages": [{"role": "user", "content": "hi"}],
+                    "tools": [{"type": "function", "function": {
+                        "name": "lookup",
+                        "parameters": {"type": "object", "properties": {}}
+                    }}]
+                }))
+                .send()
+                .await
+                .expect("chat request");
+            assert_eq!(response.status(), StatusCode::OK);
+            response.text().await.expect("response body");
+        }
+
+        for (request_id, response_event) in [
+            ("req-complete", "chat.response"),
+            ("req-stream", "chat.stream.response"),
+        ] {
+            let deadline = tokio::time::Instant::now() + Duration::from_secs(2);
+            let events = loop {
+                let events: Vec<HashMap<String, String>> = captured
+                    .0
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter(|line| {
+                        line.contains_key("event")
+                            && line.get("request_id").map(String::as_str) == Some(request_id)
+                    })
+                    .cloned()
+                    .collect();
+                let done = events
+                    .last()
+                    .is_some_and(|line| line["event"] == "chat.summary");
+                if done || tokio::time::Instant::now() >= deadline {
+                    break events;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            };
+            let names: Vec<&str> = events
+                .iter()
+                .map(|line| line["event"].as_str())
+                .filter(|name| *name != "chat.warnings")
+                .collect();
+            assert_eq!(
+                names,
+                ["chat.request", "chat.tools", response_event, "chat.summary"],
+                "{request_id}"
+            );
+            assert!(
+                events.iter().all(|line| line["model"] == "gpt-5"),
+                "{events:?}"
+            );
+            let summary: Value = serde_json::from_str(&events[events.len() - 1]["payload"])
+                .expect("summary payload is JSON");
+            assert_eq!(summary["finish_reason"], "stop", "{summary}");
+            for field in ["duration_ms", "prompt_tokens", "completion_tokens"] {
+                assert!(summary.get(field).is_some(), "missing {field}: {summary}");
+            }
+        }
     }
 
-    Event::default()
-        .json_data(payload)
-        .expect("serialize chunk")
-}
+    #[tokio::test]
+    async fn access_log_reports_streams_that_fail_midway() {
+        use tracing_subscriber::layer::SubscriberExt;
 
-fn done_event() -> Event {
-    Event::default().data("[DONE]")
-}
+        let captured = CapturedAccessLogs::default();
+        let _subscriber =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(captured.clone()));
+        let executor = ScriptedChatExecutor::from_events(|| {
+            vec![ResponseEvent::OutputTextDelta("partial".to_string())]
+        })
+        .with_delay(Duration::from_millis(20))
+        .with_stream_error(|| CodexErr::Stream("connection reset".to_string(), None));
+        let state = AppState::insecure_mock(true).with_executor(Arc::new(executor));
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind test listener");
+        let addr = listener.local_addr().expect("listener address");
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, router(state)).await;
+        });
 
-fn current_timestamp() -> i64 {
-    use std::time::{SystemTime, UNIX_EPOCH};
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_secs() as i64)
-        .unwrap_or_default()
-}
+        let response = open_stream(addr).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        response.text().await.expect("stream body");
 
-async fn log_requests(request: Request<Body>, next: Next) -> Result<Response, Infallible> {
-    let method = request.method().clone();
-    let path = request.uri().path().to_string();
-    let response = next.run(request).await;
-    let status = response.status();
-    if status.is_success() {
-        info!(
-            method = %method,
-            path = path,
-            status = %status,
-            "handled request"
+        let line = captured.wait_for("request failed").await;
+        assert_eq!(line["status"], "200");
+        assert_eq!(line["outcome"], "error");
+        assert_eq!(line["finish_reason"], "error");
+        assert!(line.contains_key("duration_ms"));
+        assert!(captured.lines("handled request").is_empty());
+    }
+
+    #[tokio::test]
+    async fn hidden_reasoning_stays_out_of_verbose_logs() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let captured = CapturedAccessLogs::default();
+        let _subscriber =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(captured.clone()));
+        let executor = ScriptedChatExecutor::from_events(|| {
+            vec![
+                ResponseEvent::ReasoningSummaryPartAdded { summary_index: 0 },
+                ResponseEvent::ReasoningSummaryDelta {
+                    delta: "secret summary".to_string(),
+                    summary_index: 0,
+                },
+                ResponseEvent::ReasoningContentDelta {
+                    delta: "secret thought".to_string(),
+                    content_index: 0,
+                },
+                ResponseEvent::OutputTextDelta("answer".to_string()),
+                ResponseEvent::Completed {
+                    response_id: "resp_hidden".to_string(),
+                    token_usage: None,
+                },
+            ]
+        });
+        let server = TestServer::spawn_with_config(
+            ServeConfig::builder()
+                .verbose(true)
+                .hide_reasoning(true)
+                .build(),
+            Arc::new(executor),
+        )
+        .await
+        .expect("Codex Serve test server should start");
+
+        let client = reqwest::Client::new();
+        for (request_id, stream) in [("req-complete", false), ("req-stream", true)] {
+            let response = client
+                .post(format!("{}/v1/chat/completions", server.base_url()))
+                .header("x-request-id", request_id)
+                .json(&json!({
+                    "model": "gpt-5",
+                    "stream": stream,
+                    "messages": [{"role": "user", "content": "hi"}]
+                }))
+                .send()
+                .await
+                .expect("chat request");
+            assert_eq!(response.status(), StatusCode::OK);
+            response.text().await.expect("response body");
+        }
+
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(2);
+        let payloads = loop {
+            let payloads: Vec<String> = captured
+                .0
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|line| line.contains_key("event"))
+                .filter_map(|line| line.get("payload").cloned())
+                .collect();
+            let logged = |event: &str| {
+                captured.0.lock().unwrap().iter().any(|line| {
+                    line.get("event").map(String::as_str) == Some(event)
+                        && line.get("request_id").map(String::as_str) == Some("req-stream")
+                })
+            };
+            if logged("chat.stream.response") || tokio::time::Instant::now() >= deadline {
+                break payloads;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert!(
+            payloads.iter().any(|payload| payload.contains("answer")),
+            "{payloads:?}"
         );
-    } else {
-        error!(
-            method = %method,
-            path = path,
-            status = %status,
-            "request failed"
+        for payload in &payloads {
+            assert!(!payload.contains("secret"), "{payload}");
+        }
+    }
+
+    #[tokio::test]
+    async fn known_routes_are_registered() {
+        let config = ServeConfig::builder().enable_codex_stream(true).build();
+        let state = AppState::insecure_mock(true)
+            .with_config(config)
+            .with_playground(true)
+            .with_admin(true);
+        let server = TestServer::spawn_with_state(state)
+            .await
+            .expect("Codex Serve test server should start");
+
+        // Every known route answers, if only with an error of its own handler.
+        let client = reqwest::Client::new();
+        for route in fallback::KNOWN_ROUTES {
+            let path = route
+                .path
+                .replace("{id}", "example")
+                .replace("{*target}", "gpt-5:generateContent");
+            for method in route.methods {
+                let method = reqwest::Method::from_bytes(method.as_bytes()).expect("method");
+                let response = client
+                    .request(method.clone(), format!("{}{path}", server.base_url()))
+                    .send()
+                    .await
+                    .expect("route should respond");
+                let status = response.status();
+                let body = response.text().await.unwrap_or_default();
+                assert!(!body.contains("Unknown route"), "{method} {path}: {body}");
+                assert_ne!(status, StatusCode::METHOD_NOT_ALLOWED, "{method} {path}");
+            }
+        }
+
+        // And every route the router registers is known.
+        let sources = [include_str!("mod.rs"), include_str!("admin.rs")];
+        for source in sources {
+            let routes = source.split("#[cfg(test)]").next().unwrap_or_default();
+            for registered in routes.split(".route(").skip(1) {
+                let Some(path) = registered.trim_start().strip_prefix('"') else {
+                    continue;
+                };
+                let path = &path[..path.find('"').expect("closing quote")];
+                assert!(
+                    fallback::KNOWN_ROUTES
+                        .iter()
+                        .any(|route| route.path == path),
+                    "{path} is registered but missing from KNOWN_ROUTES"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn playground_is_served_only_when_enabled() {
+        for enabled in [false, true] {
+            let app = router(AppState::insecure_mock(true).with_playground(enabled));
+            let listener = TcpListener::bind("127.0.0.1:0")
+                .await
+                .expect("bind test listener");
+            let addr = listener.local_addr().expect("listener address");
+            let server = tokio::spawn(async move {
+                let _ = axum::serve(listener, app).await;
+            });
+
+            let response = reqwest::get(format!("http://{addr}/"))
+                .await
+                .expect("root should respond");
+            server.abort();
+            if !enabled {
+                assert_eq!(response.status(), StatusCode::NOT_FOUND);
+                continue;
+            }
+            assert_eq!(response.status(), StatusCode::OK);
+            let content_type = response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            assert!(content_type.starts_with("text/html"), "{content_type}");
+            let page = response.text().await.expect("page body");
+            for endpoint in ["/v1/models", "/v1/chat/completions", "/healthz"] {
+                assert!(page.contains(&format!("\"{endpoint}\"")), "{endpoint}");
+            }
+        }
+    }
+
+    /// Reports a large reasoning model and a smaller non-reasoning one, like two Codex presets.
+    struct PresetInfoExecutor;
+
+    #[async_trait::async_trait]
+    impl ChatExecutor for PresetInfoExecutor {
+        async fn complete(
+            &self,
+            payload: crate::openai::chat::PromptPayload,
+        ) -> Result<response::ChatCompletionResponse, ApiError> {
+            executor::MockChatExecutor::new().complete(payload).await
+        }
+
+        async fn complete_observed(
+            &self,
+            payload: crate::openai::chat::PromptPayload,
+            _latency: &CompleteLatency,
+        ) -> Result<response::ChatCompletionResponse, ApiError> {
+            self.complete(payload).await
+        }
+
+        async fn stream(
+            &self,
+            payload: crate::openai::chat::PromptPayload,
+        ) -> Result<StreamingHandle, ApiError> {
+            executor::MockChatExecutor::new().stream(payload).await
+        }
+
+        async fn model_info(
+            &self,
+            model: &str,
+            _profile: Option<&str>,
+        ) -> Result<ModelInfo, ApiError> {
+            match model {
+                "gpt-5.1-codex-max" => Ok(ModelInfo {
+                    context_window: Some(272_000),
+                    max_output_tokens: Some(128_000),
+                    web_search: true,
+                    ..ModelInfo::default()
+                }),
+                "gpt-5.1-codex" => Ok(ModelInfo {
+                    context_window: Some(200_000),
+                    vision: false,
+                    ..ModelInfo::default()
+                }),
+                "gpt-4.1" => Ok(ModelInfo {
+                    context_window: Some(128_000),
+                    reasoning: false,
+                    ..ModelInfo::default()
+                }),
+                "broken" => Err(ApiError::internal(
+                    "config for model `broken` no longer loads",
+                )),
+                other => Err(ApiError::bad_request(format!("unknown model {other}"))),
+            }
+        }
+    }
+
+    #[test]
+    fn ollama_digests_follow_advertised_metadata() {
+        let info = ModelInfo::default();
+        let digest = ollama_digest("gpt-5", &info, None);
+        assert_eq!(digest.len(), 64);
+        assert!(digest.bytes().all(|byte| byte.is_ascii_hexdigit()));
+        assert_eq!(digest, ollama_digest("gpt-5", &info, None));
+
+        assert_ne!(digest, ollama_digest("gpt-5-codex", &info, None));
+        assert_ne!(digest, ollama_digest("gpt-5", &info, Some("2")));
+        let bigger_window = ModelInfo {
+            context_window: Some(400_000),
+            ..ModelInfo::default()
+        };
+        assert_ne!(digest, ollama_digest("gpt-5", &bigger_window, None));
+        let no_thinking = ModelInfo {
+            reasoning: false,
+            ..ModelInfo::default()
+        };
+        assert_ne!(digest, ollama_digest("gpt-5", &no_thinking, None));
+    }
+
+    #[tokio::test]
+    async fn ollama_show_reports_per_model_context_and_capabilities() {
+        let app = router(AppState::insecure_mock(true).with_executor(Arc::new(PresetInfoExecutor)));
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind test listener");
+        let addr = listener.local_addr().expect("listener address");
+        let server = tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+        let show = |model: &'static str| async move {
+            reqwest::Client::new()
+                .post(format!("http://{addr}/api/show"))
+                .json(&json!({ "model": model }))
+                .send()
+                .await
+                .expect("show should respond")
+        };
+
+        // Both the modelfile and `parameters` carry the advertised window, and nothing from a
+        // local model: no blob, no stop tokens.
+        let num_ctx = |show: &Value| {
+            let modelfile = show["modelfile"].as_str().expect("modelfile");
+            let parameters = show["parameters"].as_str().expect("parameters");
+            assert!(!modelfile.contains("PARAMETER stop"), "{modelfile}");
+            assert!(!modelfile.contains("blobs"), "{modelfile}");
+            let from_modelfile = modelfile
+                .lines()
+                .find_map(|line| line.strip_prefix("PARAMETER num_ctx "))
+                .map(str::to_string);
+            let from_parameters = parameters
+                .lines()
+                .find_map(|line| line.strip_prefix("num_ctx"))
+                .map(|value| value.trim().to_string());
+            assert_eq!(from_modelfile, from_parameters, "{show}");
+            from_modelfile.map(|value| value.parse::<u64>().expect("num_ctx is a number"))
+        };
+
+        let max: Value = show("gpt-5.1-codex-max").await.json().await.expect("JSON");
+        assert_eq!(max["model_info"]["llama.context_length"], 272_000);
+        assert_eq!(num_ctx(&max), Some(272_000));
+        assert!(
+            max["modelfile"]
+                .as_str()
+                .is_some_and(|text| text.contains("virtual model served by codex-serve")
+                    && text.contains("\nFROM gpt-5.1-codex-max\n"))
         );
+        assert_eq!(
+            max["capabilities"],
+            json!(["completion", "vision", "tools", "thinking"])
+        );
+
+        let small: Value = show("gpt-4.1").await.json().await.expect("JSON");
+        assert_eq!(small["model_info"]["llama.context_length"], 128_000);
+        assert_eq!(num_ctx(&small), Some(128_000));
+        assert_eq!(
+            small["capabilities"],
+            json!(["completion", "vision", "tools"])
+        );
+
+        let unknown = show("nope").await;
+        assert_eq!(unknown.status(), StatusCode::NOT_FOUND);
+        let broken = show("broken").await;
+        assert_eq!(broken.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        server.abort();
     }
-    Ok(response)
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[tokio::test]
+    async fn unknown_ollama_models_are_answered_with_the_listed_ones() {
+        let app = router(AppState::insecure_mock(true).with_executor(Arc::new(PresetInfoExecutor)));
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind test listener");
+        let addr = listener.local_addr().expect("listener address");
+        let server = tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+        let post = |path: &'static str, body: Value| async move {
+            let response = reqwest::Client::new()
+                .post(format!("http://{addr}{path}"))
+                .json(&body)
+                .send()
+                .await
+                .expect("Ollama route should respond");
+            let status = response.status();
+            let body: Value = response.json().await.expect("JSON");
+            (status, body["error"].as_str().expect("error").to_string())
+        };
+        let tags: Value = reqwest::get(format!("http://{addr}/api/tags"))
+            .await
+            .expect("tags should respond")
+            .json()
+            .await
+            .expect("JSON");
+        let listed: Vec<String> = tags["models"]
+            .as_array()
+            .expect("models array")
+            .iter()
+            .map(|entry| entry["name"].as_str().expect("name").to_string())
+            .collect();
+        // The names between "serves " and " (see /api/tags)", less any " and N more".
+        let suggested = |message: &str| -> Vec<String> {
+            let start = message.find("serves ").expect("suggestions") + "serves ".len();
+            let end = message.find(" (see /api/tags)").expect("suggestions end");
+            let names = &message[start..end];
+            let names = names.split(" and ").next().unwrap_or_default();
+            names.split(", ").map(str::to_string).collect()
+        };
+        let expected: Vec<String> = listed.iter().take(SUGGESTED_MODELS).cloned().collect();
+
+        let (status, message) = post("/api/show", json!({ "model": "llama3.2" })).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(
+            message.starts_with("model 'llama3.2' not found; "),
+            "{message}"
+        );
+        assert_eq!(suggested(&message), expected);
+        if listed.len() > SUGGESTED_MODELS {
+            let more = format!(" and {} more", listed.len() - SUGGESTED_MODELS);
+            assert!(message.contains(&more), "{message}");
+        }
+
+        let (status, chat_message) = post(
+            "/api/chat",
+            json!({
+                "model": "llama3.2",
+                "stream": false,
+                "messages": [{"role": "user", "content": "hi"}]
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(chat_message, message);
+
+        let (status, message) = post("/api/show", json!({})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(message.starts_with("model is required; "), "{message}");
+        assert_eq!(suggested(&message), expected);
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn pull_and_delete_name_the_listed_models() {
+        let app = router(AppState::insecure_mock(true).with_executor(Arc::new(PresetInfoExecutor)));
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind test listener");
+        let addr = listener.local_addr().expect("listener address");
+        let server = tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+        let client = reqwest::Client::new();
+        let send = |method: reqwest::Method, path: &'static str, body: Value| {
+            client
+                .request(method, format!("http://{addr}{path}"))
+                .json(&body)
+                .send()
+        };
+
+        let pulled = send(
+            reqwest::Method::POST,
+            "/api/pull",
+            json!({ "model": "gpt-5.1-codex" }),
+        )
+        .await
+        .expect("pull should respond");
+        assert_eq!(pulled.status(), StatusCode::OK);
+        assert_eq!(
+            pulled.text().await.expect("body"),
+            "{\"status\":\"success\"}\n"
+        );
+        let pulled: Value = send(
+            reqwest::Method::POST,
+            "/api/pull",
+            json!({ "name": "gpt-5.1-codex", "stream": false }),
+        )
+        .await
+        .expect("pull should respond")
+        .json()
+        .await
+        .expect("JSON");
+        assert_eq!(pulled, json!({ "status": "success" }));
+
+        for (method, path) in [
+            (reqwest::Method::POST, "/api/pull"),
+            (reqwest::Method::DELETE, "/api/delete"),
+        ] {
+            let response = send(method, path, json!({ "model": "llama3.2" }))
+                .await
+                .expect("route should respond");
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+            let body: Value = response.json().await.expect("JSON");
+            let message = body["error"].as_str().expect("error");
+            assert!(
+                message.starts_with("model 'llama3.2' not found; this server is Codex Serve"),
+                "{message}"
+            );
+        }
+
+        let deleted = send(
+            reqwest::Method::DELETE,
+            "/api/delete",
+            json!({ "model": "gpt-5.1-codex" }),
+        )
+        .await
+        .expect("delete should respond");
+        assert_eq!(deleted.status(), StatusCode::BAD_REQUEST);
+        let body: Value = deleted.json().await.expect("JSON");
+        assert_eq!(
+            body["error"],
+            "model 'gpt-5.1-codex' is served by Codex Serve and cannot be deleted here"
+        );
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn listed_models_carry_the_capabilities_ollama_shows() {
+        let app = router(AppState::insecure_mock(true).with_executor(Arc::new(PresetInfoExecutor)));
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind test listener");
+        let addr = listener.local_addr().expect("listener address");
+        let server = tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+        let models: Value = reqwest::get(format!("http://{addr}/v1/models"))
+            .await
+            .expect("models should respond")
+            .json()
+            .await
+            .expect("JSON");
+        let capabilities = |id: &str| {
+            models["data"]
+                .as_array()
+                .expect("data array")
+                .iter()
+                .find(|entry| entry["id"] == id)
+                .map(|entry| entry["capabilities"].clone())
+                .expect("listed model")
+        };
+
+        assert_eq!(
+            capabilities("gpt-5.1-codex-max"),
+            json!({
+                "vision": true,
+                "tools": true,
+                "reasoning": true,
+                "web_search": true,
+                "context_window": 272_000,
+                "max_output_tokens": 128_000
+            })
+        );
+        assert_eq!(
+            capabilities("gpt-5.1-codex"),
+            json!({
+                "vision": false,
+                "tools": true,
+                "reasoning": true,
+                "web_search": false,
+                "context_window": 200_000,
+                "max_output_tokens": null
+            })
+        );
+
+        let shown: Value = reqwest::Client::new()
+            .post(format!("http://{addr}/api/show"))
+            .json(&json!({ "model": "gpt-5.1-codex" }))
+            .send()
+            .await
+            .expect("show should respond")
+            .json()
+            .await
+            .expect("JSON");
+        assert_eq!(
+            shown["capabilities"],
+            json!(["completion", "tools", "thinking"])
+        );
+        server.abort();
+    }
+
+    #[test]
+    fn web_searches_without_an_id_are_named_after_their_position() {
+        let search = |id: Option<&str>, query: Option<&str>| ResponseItem::WebSearchCall {
+            id: id.map(str::to_string),
+            status: None,
+            action: WebSearchAction::Search {
+                query: query.map(str::to_string),
+            },
+        };
+        let mut searches = WebSearchPositions::default();
+        let mut id = |item: &ResponseItem, done: bool| {
+            tool_call_from_item(item, &mut searches, done).unwrap().id
+        };
+
+        // Added without the query, done with it: one call.
+        let first = id(&search(None, None), false);
+        assert_eq!(
+            first,
+            id(&search(Some(""), Some("rust release date")), true)
+        );
+        assert!(first.starts_with("ws_call_"), "{first}");
+
+        // The same search again is another call.
+        let second = id(&search(None, Some("rust release date")), false);
+        assert_eq!(second, id(&search(None, Some("rust release date")), true));
+        assert_ne!(first, second);
+
+        // The upstream's own id wins whenever it sends one.
+        assert_eq!(
+            id(&search(Some(" ws_123 "), Some("rust release date")), true),
+            "ws_123"
+        );
+    }
+
+    #[test]
+    fn splits_text_on_char_boundaries() {
+        let text = "aé✓😀b";
+        let pieces: Vec<&str> = split_on_char_boundaries(text, 3).collect();
+        assert_eq!(pieces, ["aé", "✓", "😀", "b"]);
+        assert!(split_on_char_boundaries("", 8).next().is_none());
+        assert_eq!(
+            split_on_char_boundaries("abcdef", 4).collect::<Vec<_>>(),
+            ["abcd", "ef"]
+        );
+    }
 
     #[test]
     fn chatgpt_auth_exposes_reasoning_variants() {
@@ -896,4 +2852,63 @@ mod tests {
         assert_eq!(parsed.1, ReasoningEffort::Low);
         assert_eq!(parse_reasoning_variant("gpt-5.1"), None);
     }
+
+    #[test]
+    fn tag_spelling_resolves_to_the_same_effort() {
+        assert_eq!(
+            parse_reasoning_variant("gpt-5.1-codex-max:high"),
+            Some(("gpt-5.1-codex-max".to_string(), ReasoningEffort::High))
+        );
+        assert_eq!(parse_reasoning_variant("gpt-5.1:latest"), None);
+        for requested in ["gpt-5.1-codex-max-high", "gpt-5.1-codex-max:HIGH"] {
+            assert_eq!(
+                ollama_model_name(requested, OllamaTagStyle::Tag),
+                "gpt-5.1-codex-max:high"
+            );
+            assert_eq!(
+                ollama_model_name(requested, OllamaTagStyle::Hyphen),
+                "gpt-5.1-codex-max-high"
+            );
+        }
+        assert_eq!(
+            ollama_model_name("gpt-5.1-codex-mini", OllamaTagStyle::Tag),
+            "gpt-5.1-codex-mini"
+        );
+    }
+
+    #[test]
+    fn reasoning_variants_get_their_own_ollama_metadata() {
+        let info = ModelInfo::default();
+        assert_eq!(ollama_details("gpt-5.1-codex").families, ["llama"]);
+        assert_eq!(
+            ollama_details("gpt-5.1-codex:low").families,
+            ["llama", "reasoning-low"]
+        );
+        assert_ne!(
+            ollama_digest("gpt-5.1-codex-low", &info, None),
+            ollama_digest("gpt-5.1-codex-high", &info, None)
+        );
+
+        let show = build_ollama_show_payload("gpt-5.1-codex:high", &info);
+        let modelfile = show["modelfile"].as_str().expect("modelfile");
+        assert!(
+            modelfile.contains("\nFROM gpt-5.1-codex\n")
+                && modelfile.contains("PARAMETER reasoning_effort high\n"),
+            "{modelfile}"
+        );
+        // Without a known context window there is no `num_ctx` to guess.
+        assert!(!modelfile.contains("num_ctx"), "{modelfile}");
+        assert!(
+            show["parameters"]
+                .as_str()
+                .is_some_and(|text| text.starts_with("reasoning_effort") && text.ends_with("high"))
+        );
+        let base = build_ollama_show_payload("gpt-5.1-codex", &info);
+        assert!(
+            !base["modelfile"]
+                .as_str()
+                .expect("modelfile")
+                .contains("reasoning_effort")
+        );
+    }
 }
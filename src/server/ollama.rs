@@ -0,0 +1,1268 @@
+//! Ollama-compatible `/api/chat` and `/api/generate`. Every record these routes write, streamed
+//! or not, is built from the typed structs below, so both endpoints share one set of field names,
+//! `created_at` format and done statistics.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    convert::Infallible,
+    time::{Duration, Instant, SystemTime},
+};
+
+use axum::{
+    Extension, Json,
+    body::{Body, Bytes},
+    extract::State,
+    http::{HeaderMap, HeaderValue, header::CONTENT_TYPE},
+    response::{IntoResponse, Response},
+};
+use codex_core::{
+    ResponseEvent, ResponseItem, compact::content_items_to_text, error::CodexErr,
+    protocol::RateLimitSnapshot,
+};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{Instrument, Span, info, warn};
+
+use super::{
+    access_log::AccessLog,
+    conversations::{self, USAGE_HEADER},
+    conversion,
+    executor::{StreamingHandle, classify_codex_error},
+    extract::ApiJson,
+    fairness::ClientId,
+    in_flight::TrackedRequest,
+    loaded::ModelExpiry,
+    metrics::InFlightGuard,
+    profiles::resolve_profile,
+    response::{ReportedModel, ToolCall, Usage},
+    state::AppState,
+    tool_call_from_item,
+    tool_calls::WebSearchPositions,
+};
+use crate::{
+    clock::{LogContext, rfc3339_nanos},
+    error::ApiError,
+    openai::{
+        chat::{
+            ChatCompletionRequest, ChatMessage, ChatToolCall, ChatToolFunction, PromptEndpoint,
+            PromptPayload, RequestTool, log_function_tools,
+        },
+        convert::ConversionError,
+    },
+    serve_config::ReportModel,
+    telemetry,
+};
+
+const NDJSON: &str = "application/x-ndjson";
+
+#[derive(Debug, Deserialize)]
+pub(super) struct ChatRequest {
+    #[serde(default)]
+    model: String,
+    #[serde(default)]
+    messages: Vec<RequestMessage>,
+    /// Ollama streams unless the client sends `"stream": false`.
+    #[serde(default)]
+    stream: Option<bool>,
+    #[serde(default)]
+    tools: Vec<RequestTool>,
+    #[serde(default)]
+    keep_alive: Option<ModelExpiry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RequestMessage {
+    #[serde(default)]
+    role: String,
+    #[serde(default)]
+    content: String,
+    /// Base64-encoded images, without a `data:` prefix.
+    #[serde(default)]
+    images: Vec<String>,
+    /// Calls a replayed assistant turn made.
+    #[serde(default)]
+    tool_calls: Vec<RequestToolCall>,
+    /// The tool a `tool` turn answers. Ollama clients name the tool rather than the call.
+    #[serde(default)]
+    tool_name: Option<String>,
+    #[serde(default)]
+    tool_call_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RequestToolCall {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    function: RequestToolCallFunction,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RequestToolCallFunction {
+    #[serde(default)]
+    name: String,
+    /// A JSON object in Ollama's format; a string is taken to be encoded already.
+    #[serde(default)]
+    arguments: Value,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct GenerateRequest {
+    #[serde(default)]
+    model: String,
+    #[serde(default)]
+    prompt: String,
+    #[serde(default)]
+    system: Option<String>,
+    #[serde(default)]
+    images: Vec<String>,
+    #[serde(default)]
+    stream: Option<bool>,
+    #[serde(default)]
+    keep_alive: Option<ModelExpiry>,
+}
+
+impl ChatRequest {
+    /// Like `/api/generate` without a prompt, a chat without messages loads (or unloads) the model.
+    fn is_load_only(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    fn into_openai(self) -> Result<ChatCompletionRequest, ConversionError> {
+        let mut history = ToolHistory::default();
+        let messages = self
+            .messages
+            .into_iter()
+            .enumerate()
+            .map(|(index, message)| {
+                check_images(&message.images).map_err(|err| err.in_message(index))?;
+                Ok(history.convert(message))
+            })
+            .collect::<Result<_, ConversionError>>()?;
+        Ok(ChatCompletionRequest {
+            model: self.model,
+            messages,
+            stream: self.stream.unwrap_or(true),
+            tools: self.tools.into(),
+            parallel_tool_calls: None,
+            reasoning_effort: None,
+            reasoning: None,
+            temperature: None,
+            top_p: None,
+            verbosity: None,
+            web_search_options: None,
+            tool_choice: None,
+            prediction: None,
+            response_format: None,
+            codex: None,
+            stream_options: None,
+        })
+    }
+}
+
+impl GenerateRequest {
+    /// Ollama treats a request without a prompt as "load the model" and answers at once. A
+    /// `system` prompt alone is a prompt.
+    fn is_load_only(&self) -> bool {
+        self.prompt.trim().is_empty()
+            && self.images.is_empty()
+            && self
+                .system
+                .as_deref()
+                .is_none_or(|system| system.trim().is_empty())
+    }
+
+    fn into_openai(self) -> Result<ChatCompletionRequest, ConversionError> {
+        check_images(&self.images)?;
+        let mut messages = Vec::new();
+        if let Some(system) = self.system.filter(|system| !system.trim().is_empty()) {
+            messages.push(chat_message("system".to_string(), system, Vec::new()));
+        }
+        if !self.prompt.trim().is_empty() || !self.images.is_empty() {
+            messages.push(chat_message("user".to_string(), self.prompt, self.images));
+        }
+        Ok(ChatCompletionRequest {
+            model: self.model,
+            messages,
+            stream: self.stream.unwrap_or(true),
+            tools: Default::default(),
+            parallel_tool_calls: None,
+            reasoning_effort: None,
+            reasoning: None,
+            temperature: None,
+            top_p: None,
+            verbosity: None,
+            web_search_options: None,
+            tool_choice: None,
+            prediction: None,
+            response_format: None,
+            codex: None,
+            stream_options: None,
+        })
+    }
+}
+
+/// Ollama rejects images that are not base64 up front, so we do too rather than letting Codex
+/// fail on a broken data URL.
+fn check_images(images: &[String]) -> Result<(), ConversionError> {
+    for (index, image) in images.iter().enumerate() {
+        let image = image.trim();
+        let data = match image.strip_prefix("data:") {
+            Some(rest) => rest.split_once(";base64,").map_or("", |(_, data)| data),
+            None => image,
+        };
+        let is_base64 = !data.is_empty()
+            && data
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'+' | b'/' | b'='));
+        if !is_base64 {
+            return Err(
+                ConversionError::new("is not base64-encoded image data").at("images", index)
+            );
+        }
+    }
+    Ok(())
+}
+
+fn chat_message(role: String, content: String, images: Vec<String>) -> ChatMessage {
+    let content = if images.is_empty() {
+        Value::String(content)
+    } else {
+        let text = (!content.is_empty()).then(|| json!({"type": "text", "text": content}));
+        let images = images
+            .iter()
+            .map(|image| json!({"type": "image_url", "image_url": {"url": image_data_url(image)}}));
+        Value::Array(text.into_iter().chain(images).collect())
+    };
+    ChatMessage {
+        role,
+        content,
+        ..ChatMessage::default()
+    }
+}
+
+/// Pairs replayed tool calls with their results. Ollama clients usually send neither call ids nor
+/// result ids, so calls without one get `call_<n>` in conversation order (the same history always
+/// gets the same ids) and each result answers the oldest open call to its `tool_name`. Histories
+/// that do not pair up are converted as well as possible with a warning: the client cannot fix
+/// its format, and Codex would reject a result it cannot match to a call.
+#[derive(Debug, Default)]
+struct ToolHistory {
+    generated: usize,
+    /// Calls without a result yet, oldest first, as (call id, tool name).
+    open: VecDeque<(String, String)>,
+}
+
+impl ToolHistory {
+    fn convert(&mut self, message: RequestMessage) -> ChatMessage {
+        let role = message.role.trim().to_ascii_lowercase();
+        if role == "tool" {
+            return self.tool_result(message);
+        }
+        if role != "assistant" || message.tool_calls.is_empty() {
+            return chat_message(message.role, message.content, message.images);
+        }
+        let tool_calls = self.open_calls(message.tool_calls);
+        let mut converted = chat_message(message.role, message.content, message.images);
+        // Ollama sends `""` alongside tool calls; that is no message of its own.
+        if converted
+            .content
+            .as_str()
+            .is_some_and(|text| text.trim().is_empty())
+        {
+            converted.content = Value::Null;
+        }
+        ChatMessage {
+            tool_calls: Some(tool_calls.into()),
+            ..converted
+        }
+    }
+
+    fn open_calls(&mut self, calls: Vec<RequestToolCall>) -> Vec<ChatToolCall> {
+        calls
+            .into_iter()
+            .map(|call| {
+                let id = call
+                    .id
+                    .filter(|id| !id.trim().is_empty())
+                    .unwrap_or_else(|| {
+                        self.generated += 1;
+                        format!("call_{}", self.generated)
+                    });
+                let name = call.function.name.trim().to_string();
+                let arguments = match call.function.arguments {
+                    Value::String(encoded) => encoded,
+                    Value::Null => "{}".to_string(),
+                    arguments => arguments.to_string(),
+                };
+                self.open.push_back((id.clone(), name.clone()));
+                ChatToolCall {
+                    id: Some(id),
+                    r#type: Some("function".to_string()),
+                    function: Some(ChatToolFunction {
+                        name: Some(name),
+                        arguments: Some(arguments),
+                    }),
+                }
+            })
+            .collect()
+    }
+
+    fn tool_result(&mut self, message: RequestMessage) -> ChatMessage {
+        let name = message
+            .tool_name
+            .as_deref()
+            .map(str::trim)
+            .filter(|name| !name.is_empty());
+        let by_id = message
+            .tool_call_id
+            .as_deref()
+            .and_then(|call_id| self.open.iter().position(|(id, _)| id == call_id.trim()));
+        let by_name = || name.and_then(|name| self.open.iter().position(|(_, open)| open == name));
+        let position = match by_id.or_else(by_name) {
+            Some(position) => Some(position),
+            None if self.open.is_empty() => None,
+            // Neither an id nor a name: results come back in call order.
+            None if name.is_none() && message.tool_call_id.is_none() => Some(0),
+            None => {
+                warn!(
+                    tool = name,
+                    "Ollama tool result matches no open tool call; pairing it with the oldest one"
+                );
+                Some(0)
+            }
+        };
+        let Some((call_id, _)) = position.and_then(|position| self.open.remove(position)) else {
+            warn!(
+                tool = name,
+                "Ollama tool result follows no tool call; passing it on as a user message"
+            );
+            let content = match name {
+                Some(name) => format!("Result of tool `{name}`: {}", message.content),
+                None => format!("Tool result: {}", message.content),
+            };
+            return chat_message("user".to_string(), content, message.images);
+        };
+        ChatMessage {
+            tool_call_id: Some(call_id),
+            ..chat_message(message.role, message.content, message.images)
+        }
+    }
+}
+
+/// Ollama sends bare base64; Codex wants a data URL, so guess the type from the magic bytes.
+fn image_data_url(image: &str) -> String {
+    let image = image.trim();
+    if image.starts_with("data:") {
+        return image.to_string();
+    }
+    let mime = if image.starts_with("iVBOR") {
+        "image/png"
+    } else if image.starts_with("R0lGOD") {
+        "image/gif"
+    } else if image.starts_with("UklGR") {
+        "image/webp"
+    } else {
+        "image/jpeg"
+    };
+    format!("data:{mime};base64,{image}")
+}
+
+/// One `/api/chat` record: a streamed piece of the reply, or the whole reply plus stats.
+#[derive(Debug, Serialize)]
+struct ChatChunk<'a> {
+    #[serde(flatten)]
+    model: &'a ReportedModel,
+    created_at: String,
+    message: ChunkMessage,
+    done: bool,
+    #[serde(flatten)]
+    stats: Option<DoneStats>,
+}
+
+#[derive(Debug, Serialize)]
+struct ChunkMessage {
+    role: &'static str,
+    content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<OllamaToolCall>,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaToolCall {
+    function: OllamaFunction,
+}
+
+/// Ollama carries tool arguments as a JSON object rather than an encoded string.
+#[derive(Debug, Serialize)]
+struct OllamaFunction {
+    name: String,
+    arguments: Value,
+}
+
+/// One `/api/generate` record.
+#[derive(Debug, Serialize)]
+struct GenerateChunk<'a> {
+    #[serde(flatten)]
+    model: &'a ReportedModel,
+    created_at: String,
+    response: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<String>,
+    done: bool,
+    #[serde(flatten)]
+    stats: Option<DoneStats>,
+}
+
+/// Fields of the final (`done: true`) record. Durations are nanoseconds, as in Ollama.
+#[derive(Debug, Serialize)]
+struct DoneStats {
+    done_reason: &'static str,
+    total_duration: u64,
+    load_duration: u64,
+    prompt_eval_count: u32,
+    prompt_eval_duration: u64,
+    eval_count: u32,
+    eval_duration: u64,
+}
+
+impl DoneStats {
+    /// Token counts come from Codex; the durations are what the proxy observed (see [`Timings`]).
+    fn new(done_reason: &'static str, usage: &Usage, timings: &Timings) -> Self {
+        let connected = timings.connected.unwrap_or(timings.received);
+        let first_event = timings.first_event.unwrap_or(connected);
+        let eval = match (timings.first_delta, timings.last_delta) {
+            (Some(first), Some(last)) => last.duration_since(first),
+            _ => Duration::ZERO,
+        };
+        Self {
+            done_reason,
+            total_duration: nanos(timings.received.elapsed()),
+            load_duration: nanos(connected.duration_since(timings.received)),
+            prompt_eval_count: usage.prompt_tokens,
+            prompt_eval_duration: nanos(first_event.duration_since(connected)),
+            eval_count: usage.completion_tokens,
+            eval_duration: nanos(eval),
+        }
+    }
+}
+
+/// When things happened during one request. Ollama's phases map onto what the proxy can see:
+/// loading is the upstream handshake, prompt evaluation the wait from there to the first upstream
+/// event, and evaluation the span from the first output delta to the last, so the three never
+/// overlap and add up to no more than the total.
+#[derive(Debug, Clone, Copy)]
+struct Timings {
+    received: Instant,
+    connected: Option<Instant>,
+    first_event: Option<Instant>,
+    first_delta: Option<Instant>,
+    last_delta: Option<Instant>,
+}
+
+impl Timings {
+    fn new(received: Instant) -> Self {
+        Self {
+            received,
+            connected: None,
+            first_event: None,
+            first_delta: None,
+            last_delta: None,
+        }
+    }
+}
+
+fn nanos(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorRecord {
+    error: String,
+}
+
+/// Assistant output carried by one record, whichever endpoint it is rendered for. The Gemini
+/// routes render it too.
+#[derive(Debug, Default)]
+pub(super) struct Output {
+    pub(super) content: String,
+    pub(super) thinking: Option<String>,
+    pub(super) tool_calls: Vec<ToolCall>,
+}
+
+impl Output {
+    pub(super) fn append(&mut self, other: Output) {
+        self.content.push_str(&other.content);
+        if let Some(thinking) = other.thinking {
+            self.thinking
+                .get_or_insert_with(String::new)
+                .push_str(&thinking);
+        }
+        self.tool_calls.extend(other.tool_calls);
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Endpoint {
+    Chat,
+    Generate,
+}
+
+impl Endpoint {
+    fn name(self) -> &'static str {
+        match self {
+            Endpoint::Chat => "ollama.chat",
+            Endpoint::Generate => "ollama.generate",
+        }
+    }
+
+    fn prompt_endpoint(self) -> PromptEndpoint {
+        match self {
+            Endpoint::Chat => PromptEndpoint::Chat,
+            Endpoint::Generate => PromptEndpoint::Instructions,
+        }
+    }
+
+    /// Builds this endpoint's record for `output`; `stats` marks it as the final one.
+    fn record(self, model: &ReportedModel, output: Output, stats: Option<DoneStats>) -> Value {
+        let created_at = rfc3339_nanos(SystemTime::now());
+        let done = stats.is_some();
+        let record = match self {
+            Endpoint::Chat => serde_json::to_value(ChatChunk {
+                model,
+                created_at,
+                message: ChunkMessage {
+                    role: "assistant",
+                    content: output.content,
+                    thinking: output.thinking,
+                    tool_calls: output
+                        .tool_calls
+                        .into_iter()
+                        .map(ollama_tool_call)
+                        .collect(),
+                },
+                done,
+                stats,
+            }),
+            // `/api/generate` has no tools, so any calls are dropped.
+            Endpoint::Generate => serde_json::to_value(GenerateChunk {
+                model,
+                created_at,
+                response: output.content,
+                thinking: output.thinking,
+                done,
+                stats,
+            }),
+        };
+        record.unwrap_or_else(|err| json!({ "error": format!("failed to encode record: {err}") }))
+    }
+}
+
+fn ollama_tool_call(call: ToolCall) -> OllamaToolCall {
+    let arguments = serde_json::from_str(&call.function.arguments)
+        .unwrap_or(Value::String(call.function.arguments));
+    OllamaToolCall {
+        function: OllamaFunction {
+            name: call.function.name,
+            arguments,
+        },
+    }
+}
+
+fn ndjson_line(record: &impl Serialize) -> Bytes {
+    let mut line = serde_json::to_vec(record).unwrap_or_default();
+    line.push(b'\n');
+    Bytes::from(line)
+}
+
+/// Ollama reports failures as a bare `{"error": "..."}` with the matching status.
+pub(super) fn error_response(err: ApiError) -> Response {
+    let status = err.status();
+    let record = ErrorRecord {
+        error: err.message().to_string(),
+    };
+    (status, Json(record)).into_response()
+}
+
+pub(super) async fn api_chat(
+    State(state): State<AppState>,
+    access_log: Option<Extension<AccessLog>>,
+    client: Option<Extension<ClientId>>,
+    headers: HeaderMap,
+    ApiJson(payload): ApiJson<ChatRequest>,
+) -> Response {
+    if payload.is_load_only() {
+        return load(
+            &state,
+            &headers,
+            Endpoint::Chat,
+            &payload.model,
+            payload.keep_alive,
+        )
+        .await;
+    }
+    let keep_alive = payload.keep_alive;
+    let request = match payload.into_openai() {
+        Ok(request) => request,
+        Err(err) => return error_response(err.into()),
+    };
+    let access_log = access_log.map(|Extension(log)| log);
+    let client = client.map(|Extension(client)| client);
+    respond(
+        state,
+        access_log,
+        client,
+        &headers,
+        Endpoint::Chat,
+        request,
+        keep_alive,
+    )
+    .await
+    .unwrap_or_else(error_response)
+}
+
+pub(super) async fn api_generate(
+    State(state): State<AppState>,
+    access_log: Option<Extension<AccessLog>>,
+    client: Option<Extension<ClientId>>,
+    headers: HeaderMap,
+    ApiJson(payload): ApiJson<GenerateRequest>,
+) -> Response {
+    if payload.is_load_only() {
+        return load(
+            &state,
+            &headers,
+            Endpoint::Generate,
+            &payload.model,
+            payload.keep_alive,
+        )
+        .await;
+    }
+    let keep_alive = payload.keep_alive;
+    let request = match payload.into_openai() {
+        Ok(request) => request,
+        Err(err) => return error_response(err.into()),
+    };
+    let access_log = access_log.map(|Extension(log)| log);
+    let client = client.map(|Extension(client)| client);
+    respond(
+        state,
+        access_log,
+        client,
+        &headers,
+        Endpoint::Generate,
+        request,
+        keep_alive,
+    )
+    .await
+    .unwrap_or_else(error_response)
+}
+
+/// Answers a request without a prompt as Ollama does: the model is loaded for the request's
+/// `keep_alive`, or unloaded when it is zero, which also drops its cached configs.
+async fn load(
+    state: &AppState,
+    headers: &HeaderMap,
+    endpoint: Endpoint,
+    requested_model: &str,
+    keep_alive: Option<ModelExpiry>,
+) -> Response {
+    let (profile, model) = match resolve_profile(headers, requested_model, state.profiles()) {
+        Ok(resolved) => resolved,
+        Err(err) => return error_response(err),
+    };
+    if let Err(err) =
+        super::ensure_ollama_model(state, requested_model, &model, profile.as_deref()).await
+    {
+        return error_response(err);
+    }
+    let done_reason = match keep_alive {
+        Some(ModelExpiry::Unload) => {
+            let evicted = state.set_model_expiry(&model, ModelExpiry::Unload).await;
+            if state.config().verbose {
+                info!(model = %model, evicted, "unloaded model and its cached configs");
+            }
+            "unload"
+        }
+        Some(keep_alive) => {
+            state.set_model_expiry(&model, keep_alive).await;
+            "load"
+        }
+        None => {
+            state.loaded_models().touch(&model);
+            "load"
+        }
+    };
+    let stats = DoneStats::new(
+        done_reason,
+        &Usage::default(),
+        &Timings::new(Instant::now()),
+    );
+    let reported = ReportedModel::unresolved(requested_model.to_string());
+    let record = endpoint.record(&reported, Output::default(), Some(stats));
+    Json(record).into_response()
+}
+
+async fn respond(
+    state: AppState,
+    access_log: Option<AccessLog>,
+    client: Option<ClientId>,
+    headers: &HeaderMap,
+    endpoint: Endpoint,
+    mut request: ChatCompletionRequest,
+    keep_alive: Option<ModelExpiry>,
+) -> Result<Response, ApiError> {
+    let started = Instant::now();
+    state.ensure_authenticated()?;
+    let log_context = LogContext::current(request.model.trim());
+    super::log_verbose_json(
+        state.config(),
+        &log_context,
+        &format!("{}.request", endpoint.name()),
+        &request,
+    );
+
+    // Ollama echoes the model name exactly as the client sent it.
+    let requested_model = request.model.trim().to_string();
+    let (profile, model) = resolve_profile(headers, &request.model, state.profiles())?;
+    super::ensure_ollama_model(&state, &requested_model, &model, profile.as_deref()).await?;
+    request.model = model;
+
+    let stream_requested = request.stream;
+    let mut prompt_payload = conversion::convert(
+        &state,
+        request,
+        state
+            .config()
+            .conversion_options(endpoint.prompt_endpoint()),
+    )
+    .await?;
+    prompt_payload.profile = profile;
+    let warnings = prompt_payload.warnings.clone();
+    if state.config().fail_on_warnings {
+        warnings.reject_any()?;
+    }
+    let budget = state.admit_tokens(&prompt_payload, client.as_ref())?;
+    let warnings_event = format!("{}.warnings", endpoint.name());
+    state.loaded_models().touch(&prompt_payload.model);
+    if let Some(log) = &access_log {
+        log.record_request(&prompt_payload.model, stream_requested);
+    }
+    if state.config().verbose {
+        log_function_tools(&log_context, &prompt_payload.prompt.tools);
+        info!(
+            model = %prompt_payload.model,
+            endpoint = endpoint.name(),
+            stream = stream_requested,
+            "forwarding Ollama request to Codex (upstream)"
+        );
+    }
+
+    let tracked = state.track_request(&prompt_payload.model, client.as_ref(), stream_requested);
+    let account = state.usage_account(&prompt_payload.model, client, access_log.clone());
+    let conversation = state.conversation_turn(&prompt_payload);
+    let upstream = telemetry::upstream_span(&prompt_payload.model, stream_requested);
+    if stream_requested {
+        let guard = state
+            .metrics()
+            .start_stream()
+            .with_usage_account(account)
+            .with_conversation(conversation)
+            .with_budget(budget);
+        let mut response = stream_response(
+            state.clone(),
+            endpoint,
+            prompt_payload,
+            requested_model,
+            started,
+            guard,
+            tracked,
+            access_log,
+            upstream,
+            keep_alive,
+        );
+        super::warnings::report(
+            state.config(),
+            &log_context,
+            &warnings_event,
+            &warnings,
+            &mut response,
+        );
+        return Ok(response);
+    }
+
+    // The single reply folds the same event stream the streaming path forwards, so both report
+    // the same content and the same timings.
+    let guard = state
+        .metrics()
+        .start_request()
+        .with_usage_account(account)
+        .with_conversation(conversation)
+        .with_budget(budget);
+    let model = prompt_payload.model.clone();
+    let collect = async {
+        let handle = state
+            .engine()
+            .stream(prompt_payload)
+            .await
+            .inspect_err(|err| state.note_upstream_error(err))?;
+        let reported = reported_model(&handle, &requested_model, state.config().report_model);
+        let collected = collect_events(handle, started).await;
+        state.note_upstream_outcome(&collected);
+        Ok::<_, ApiError>((reported, collected?))
+    };
+    let (reported, (output, usage, stats)) =
+        tracked.run(collect.instrument(upstream.clone())).await?;
+    if let Some(keep_alive) = keep_alive {
+        state.set_model_expiry(&model, keep_alive).await;
+    }
+    telemetry::record_usage(&upstream, usage.prompt_tokens, usage.completion_tokens);
+    guard.record_usage(&usage);
+    if let Some(log) = &access_log {
+        log.record_outcome(&usage, Some("stop"));
+    }
+    let record = endpoint.record(&reported, output, Some(stats));
+    super::log_verbose_json(
+        state.config(),
+        &log_context,
+        &format!("{}.response", endpoint.name()),
+        &record,
+    );
+    let mut response = Json(record).into_response();
+    if let Some(value) = conversations::usage_header(&usage) {
+        response.headers_mut().insert(USAGE_HEADER, value);
+    }
+    super::warnings::report(
+        state.config(),
+        &log_context,
+        &warnings_event,
+        &warnings,
+        &mut response,
+    );
+    Ok(response)
+}
+
+/// Streams NDJSON records from a spawned task, like the SSE path: the response goes out before
+/// the upstream handshake, and the task stops as soon as the client disconnects.
+#[allow(clippy::too_many_arguments)]
+fn stream_response(
+    state: AppState,
+    endpoint: Endpoint,
+    payload: PromptPayload,
+    model: String,
+    started: Instant,
+    guard: InFlightGuard,
+    tracked: TrackedRequest,
+    access_log: Option<AccessLog>,
+    upstream: Span,
+    keep_alive: Option<ModelExpiry>,
+) -> Response {
+    let (tx, rx) = mpsc::channel::<Bytes>(32);
+
+    let task_log = access_log.clone();
+    let task = async move {
+        let loaded_model = payload.model.clone();
+        let forward = async {
+            let handle = state
+                .engine()
+                .stream(payload)
+                .await
+                .inspect_err(|err| state.note_upstream_error(err))?;
+            let reported = reported_model(&handle, &model, state.config().report_model);
+            let forwarded = forward_events(handle, endpoint, &reported, started, &tx).await;
+            // A stream the client walked away from says nothing about the upstream.
+            if !tx.is_closed() {
+                state.note_upstream_outcome(&forwarded);
+            }
+            forwarded
+        };
+        let forward = tracked.run(forward);
+        tokio::select! {
+            result = forward => match result {
+                Ok(usage) => {
+                    guard.record_usage(&usage);
+                    telemetry::record_usage(
+                        &Span::current(),
+                        usage.prompt_tokens,
+                        usage.completion_tokens,
+                    );
+                    if let Some(log) = &task_log {
+                        log.record_outcome(&usage, Some("stop"));
+                    }
+                    if let Some(keep_alive) = keep_alive {
+                        state.set_model_expiry(&loaded_model, keep_alive).await;
+                    }
+                }
+                Err(err) => {
+                    warn!("Ollama streaming error: {err:?}");
+                    if let Some(log) = &task_log {
+                        log.record_finish_reason("error");
+                    }
+                    let record = ErrorRecord {
+                        error: err.message().to_string(),
+                    };
+                    let _ = tx.send(ndjson_line(&record)).await;
+                }
+            },
+            _ = tx.closed() => {
+                if let Some(log) = &task_log {
+                    log.record_finish_reason("client_disconnected");
+                }
+            }
+        }
+    };
+    tokio::spawn(task.instrument(upstream));
+
+    let body = ReceiverStream::new(rx)
+        .inspect(move |_| {
+            if let Some(log) = &access_log {
+                log.mark_first_byte();
+            }
+        })
+        .map(Ok::<_, Infallible>);
+    let mut response = Body::from_stream(body).into_response();
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(NDJSON));
+    response
+}
+
+/// Writes one record per upstream delta and a final record with stats; returns the usage once
+/// Codex reports completion.
+async fn forward_events(
+    handle: StreamingHandle,
+    endpoint: Endpoint,
+    model: &ReportedModel,
+    started: Instant,
+    tx: &mpsc::Sender<Bytes>,
+) -> Result<Usage, ApiError> {
+    let mut stream = handle.stream;
+    let mut translator = Translator::new(started);
+
+    while let Some(event) = stream.next().await {
+        let output = match translator.translate(event)? {
+            Step::Output(output) => output,
+            Step::Completed(usage) => {
+                let stats = DoneStats::new("stop", &usage, &translator.timings);
+                let record = endpoint.record(model, Output::default(), Some(stats));
+                let _ = tx.send(ndjson_line(&record)).await;
+                return Ok(usage);
+            }
+            Step::Skip => continue,
+        };
+        if tx
+            .send(ndjson_line(&endpoint.record(model, output, None)))
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+    Err(ApiError::internal(
+        "Codex stream ended before the response completed",
+    ))
+}
+
+/// Names the model of `handle`'s records per `--report-model`; Ollama's requested name is the one
+/// the client sent, profile prefix and all.
+fn reported_model(
+    handle: &StreamingHandle,
+    requested: &str,
+    report: Option<ReportModel>,
+) -> ReportedModel {
+    ReportedModel::new(requested.to_string(), handle.resolved_model.clone(), report)
+}
+
+/// Folds the whole upstream stream into one output, for `"stream": false`.
+async fn collect_events(
+    handle: StreamingHandle,
+    started: Instant,
+) -> Result<(Output, Usage, DoneStats), ApiError> {
+    let mut stream = handle.stream;
+    let mut translator = Translator::new(started);
+    let mut collected = Output::default();
+
+    while let Some(event) = stream.next().await {
+        match translator.translate(event)? {
+            Step::Output(output) => collected.append(output),
+            Step::Completed(usage) => {
+                let stats = DoneStats::new("stop", &usage, &translator.timings);
+                return Ok((collected, usage, stats));
+            }
+            Step::Skip => {}
+        }
+    }
+    Err(ApiError::internal(
+        "Codex stream ended before the response completed",
+    ))
+}
+
+pub(super) enum Step {
+    Output(Output),
+    Completed(Usage),
+    Skip,
+}
+
+/// Turns upstream events into record contents, noting the [`Timings`] as it goes.
+pub(super) struct Translator {
+    timings: Timings,
+    rate_limits: Option<RateLimitSnapshot>,
+    text_since_message: bool,
+    thinking_started: bool,
+    sent_tool_calls: HashSet<String>,
+    web_searches: WebSearchPositions,
+}
+
+impl Translator {
+    /// Call once the upstream handshake has returned.
+    pub(super) fn new(received: Instant) -> Self {
+        let mut timings = Timings::new(received);
+        timings.connected = Some(Instant::now());
+        Self {
+            timings,
+            rate_limits: None,
+            text_since_message: false,
+            thinking_started: false,
+            sent_tool_calls: HashSet::new(),
+            web_searches: WebSearchPositions::default(),
+        }
+    }
+
+    pub(super) fn translate(
+        &mut self,
+        event: Result<ResponseEvent, CodexErr>,
+    ) -> Result<Step, ApiError> {
+        let now = Instant::now();
+        self.timings.first_event.get_or_insert(now);
+        let event = event.map_err(|err| {
+            classify_codex_error(&err, self.rate_limits.as_ref(), "Codex stream error")
+        })?;
+        let output = match event {
+            ResponseEvent::OutputTextDelta(delta) => {
+                self.text_since_message = true;
+                Output {
+                    content: delta,
+                    ..Output::default()
+                }
+            }
+            ResponseEvent::ReasoningSummaryDelta { delta, .. } => {
+                self.thinking_started = true;
+                Output {
+                    thinking: Some(delta),
+                    ..Output::default()
+                }
+            }
+            // Separate summary parts the same way the Chat Completions reply joins them.
+            ResponseEvent::ReasoningSummaryPartAdded { .. } if self.thinking_started => Output {
+                thinking: Some("\n".to_string()),
+                ..Output::default()
+            },
+            ResponseEvent::OutputItemDone(ResponseItem::Message { role, content, .. }) => {
+                let streamed = std::mem::take(&mut self.text_since_message);
+                match content_items_to_text(&content) {
+                    Some(text) if role == "assistant" && !streamed && !text.trim().is_empty() => {
+                        Output {
+                            content: text,
+                            ..Output::default()
+                        }
+                    }
+                    _ => return Ok(Step::Skip),
+                }
+            }
+            ResponseEvent::OutputItemDone(item) => {
+                match tool_call_from_item(&item, &mut self.web_searches, true) {
+                    Some(call) if self.sent_tool_calls.insert(call.id.clone()) => Output {
+                        tool_calls: vec![call],
+                        ..Output::default()
+                    },
+                    _ => return Ok(Step::Skip),
+                }
+            }
+            ResponseEvent::RateLimits(snapshot) => {
+                self.rate_limits = Some(snapshot);
+                return Ok(Step::Skip);
+            }
+            ResponseEvent::Completed { token_usage, .. } => {
+                return Ok(Step::Completed(
+                    token_usage.map(Usage::from).unwrap_or_default(),
+                ));
+            }
+            _ => return Ok(Step::Skip),
+        };
+        self.timings.first_delta.get_or_insert(now);
+        self.timings.last_delta = Some(now);
+        Ok(Step::Output(output))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    /// Records captured from Ollama 0.12 (`llama3.2`), trimmed to one of each kind.
+    const OLLAMA_CHAT_CHUNK: &str = r#"{"model":"llama3.2","created_at":"2025-10-14T09:12:03.501538Z","message":{"role":"assistant","content":"The"},"done":false}"#;
+    const OLLAMA_CHAT_DONE: &str = r#"{"model":"llama3.2","created_at":"2025-10-14T09:12:04.118826Z","message":{"role":"assistant","content":""},"done_reason":"stop","done":true,"total_duration":812447250,"load_duration":29017333,"prompt_eval_count":26,"prompt_eval_duration":153640750,"eval_count":31,"eval_duration":627611458}"#;
+    const OLLAMA_GENERATE_CHUNK: &str = r#"{"model":"llama3.2","created_at":"2025-10-14T09:13:41.226417Z","response":"The","done":false}"#;
+    const OLLAMA_GENERATE_DONE: &str = r#"{"model":"llama3.2","created_at":"2025-10-14T09:13:41.842016Z","response":"","done":true,"done_reason":"stop","total_duration":700127833,"load_duration":23804291,"prompt_eval_count":29,"prompt_eval_duration":61279542,"eval_count":33,"eval_duration":613827167}"#;
+    const OLLAMA_TOOL_CALL: &str = r#"{"model":"llama3.2","created_at":"2025-10-14T09:15:22.674411Z","message":{"role":"assistant","content":"","tool_calls":[{"function":{"name":"get_weather","arguments":{"city":"Paris"}}}]},"done":false}"#;
+
+    /// Every key path in `value`, so nested objects are compared too.
+    fn field_paths(value: &Value) -> BTreeSet<String> {
+        fn walk(value: &Value, prefix: &str, paths: &mut BTreeSet<String>) {
+            match value {
+                Value::Object(map) => {
+                    for (key, value) in map {
+                        let path = format!("{prefix}{key}");
+                        paths.insert(path.clone());
+                        walk(value, &format!("{path}."), paths);
+                    }
+                }
+                Value::Array(items) => {
+                    for item in items {
+                        walk(item, &format!("{prefix}[]."), paths);
+                    }
+                }
+                _ => {}
+            }
+        }
+        let mut paths = BTreeSet::new();
+        walk(value, "", &mut paths);
+        paths
+    }
+
+    fn assert_same_fields(sample: &str, ours: &Value) {
+        let sample: Value = serde_json::from_str(sample).expect("sample is JSON");
+        assert_eq!(field_paths(&sample), field_paths(ours), "{ours}");
+    }
+
+    fn text(content: &str) -> Output {
+        Output {
+            content: content.to_string(),
+            ..Output::default()
+        }
+    }
+
+    fn done() -> Option<DoneStats> {
+        let usage = Usage {
+            prompt_tokens: 26,
+            completion_tokens: 31,
+            total_tokens: 57,
+            codex: None,
+            details: None,
+        };
+        Some(DoneStats::new(
+            "stop",
+            &usage,
+            &Timings::new(Instant::now()),
+        ))
+    }
+
+    #[test]
+    fn records_match_real_ollama_fields() {
+        let model = ReportedModel::unresolved("gpt-5".to_string());
+        assert_same_fields(
+            OLLAMA_CHAT_CHUNK,
+            &Endpoint::Chat.record(&model, text("The"), None),
+        );
+        assert_same_fields(
+            OLLAMA_CHAT_DONE,
+            &Endpoint::Chat.record(&model, Output::default(), done()),
+        );
+        assert_same_fields(
+            OLLAMA_GENERATE_CHUNK,
+            &Endpoint::Generate.record(&model, text("The"), None),
+        );
+        assert_same_fields(
+            OLLAMA_GENERATE_DONE,
+            &Endpoint::Generate.record(&model, Output::default(), done()),
+        );
+        let call = Output {
+            tool_calls: vec![ToolCall::new(
+                "call_1".to_string(),
+                "get_weather".to_string(),
+                r#"{"city":"Paris"}"#.to_string(),
+            )],
+            ..Output::default()
+        };
+        let record = Endpoint::Chat.record(&model, call, None);
+        assert_same_fields(OLLAMA_TOOL_CALL, &record);
+        assert_eq!(
+            record["message"]["tool_calls"][0]["function"]["arguments"]["city"],
+            "Paris"
+        );
+    }
+
+    fn converted(messages: Value) -> Vec<String> {
+        let request: ChatRequest =
+            serde_json::from_value(json!({"model": "gpt-5", "messages": messages})).unwrap();
+        let payload = request.into_openai().unwrap().into_prompt().unwrap();
+        crate::server::describe_input(&payload.prompt.input)
+    }
+
+    #[test]
+    fn id_less_tool_results_pair_with_calls_by_name_then_order() {
+        let input = converted(json!([
+            {"role": "user", "content": "weather and time?"},
+            {"role": "assistant", "content": "", "tool_calls": [
+                {"function": {"name": "get_weather", "arguments": {"city": "Paris"}}},
+                {"function": {"name": "get_time", "arguments": "{\"tz\":\"CET\"}"}}
+            ]},
+            {"role": "tool", "tool_name": "get_time", "content": "09:00"},
+            {"role": "tool", "content": "18°C"}
+        ]));
+        assert_eq!(
+            input,
+            [
+                "user: weather and time?",
+                r#"function_call call_1 get_weather({"city":"Paris"})"#,
+                r#"function_call call_2 get_time({"tz":"CET"})"#,
+                "function_call_output call_2: 09:00",
+                "function_call_output call_1: 18°C",
+            ]
+        );
+    }
+
+    #[test]
+    fn mismatched_tool_results_degrade_instead_of_failing() {
+        let input = converted(json!([
+            {"role": "user", "content": "weather?"},
+            {"role": "assistant", "tool_calls": [
+                {"id": "abc", "function": {"name": "get_weather", "arguments": {}}}
+            ]},
+            {"role": "tool", "tool_name": "get_wether", "content": "18°C"},
+            {"role": "tool", "tool_name": "get_weather", "content": "again"}
+        ]));
+        assert_eq!(
+            input,
+            [
+                "user: weather?",
+                "function_call abc get_weather({})",
+                "function_call_output abc: 18°C",
+                "user: Result of tool `get_weather`: again",
+            ]
+        );
+    }
+
+    #[test]
+    fn bare_base64_images_become_data_urls() {
+        assert_eq!(
+            image_data_url("iVBORw0KGgo="),
+            "data:image/png;base64,iVBORw0KGgo="
+        );
+        assert_eq!(
+            image_data_url("/9j/4AAQ"),
+            "data:image/jpeg;base64,/9j/4AAQ"
+        );
+        assert_eq!(
+            image_data_url("data:image/gif;base64,R0lGOD"),
+            "data:image/gif;base64,R0lGOD"
+        );
+    }
+}
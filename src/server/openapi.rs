@@ -0,0 +1,761 @@
+//! `GET /openapi.json`: an OpenAPI 3.1 description of the routes this server answers, for client
+//! generators and gateway configs. Request fields are marked with `x-codex-serve-support`:
+//! `ignored` for OpenAI fields that are accepted but have no effect, `vendor` for Codex Serve's own
+//! extensions; unmarked fields are honored. The unit tests below hold the schemas to the request
+//! and response types, so a field added to either without being described here fails them.
+
+use axum::Json;
+use serde_json::{Map, Value, json};
+
+use super::version::CRATE_VERSION;
+use crate::serve_config::ApiSurfaces;
+
+/// OpenAI Chat Completions fields that deserialize fine and are then dropped: Codex has no
+/// equivalent, or picks the value itself.
+const IGNORED_CHAT_FIELDS: &[(&str, &str)] = &[
+    ("max_tokens", "Codex picks the output length."),
+    ("max_completion_tokens", "Codex picks the output length."),
+    ("n", "Always one choice; see the `codex.samples` extension."),
+    ("stop", "Not supported upstream."),
+    ("presence_penalty", "Not supported upstream."),
+    ("frequency_penalty", "Not supported upstream."),
+    ("logit_bias", "Not supported upstream."),
+    ("logprobs", "Not supported upstream."),
+    ("top_logprobs", "Not supported upstream."),
+    ("seed", "Not supported upstream."),
+    ("service_tier", "Codex picks the tier."),
+    ("store", "Nothing is stored."),
+    ("metadata", "Nothing is stored."),
+    ("modalities", "Text only."),
+    ("audio", "Text only."),
+];
+
+pub(super) async fn openapi_json(surfaces: ApiSurfaces) -> Json<Value> {
+    Json(document(surfaces))
+}
+
+/// The document for a router serving `surfaces`.
+pub(super) fn document(surfaces: ApiSurfaces) -> Value {
+    let mut paths = Map::new();
+    paths.insert(
+        "/openapi.json".into(),
+        get_json("This document.", json!({"type": "object"})),
+    );
+    paths.insert(
+        "/healthz".into(),
+        get_json(
+            "Readiness, Codex auth and upstream status, and server counters.",
+            schema_ref("Health"),
+        ),
+    );
+    paths.insert(
+        "/stats/budget".into(),
+        get_json(
+            "Token limits and each client's spend in the last hour.",
+            json!({"type": "object", "properties": {
+                "max_tokens_per_request": {"type": ["integer", "null"]},
+                "max_tokens_per_hour": {"type": ["integer", "null"]},
+                "clients": {"type": "object", "additionalProperties": {"type": "object"}}
+            }}),
+        ),
+    );
+    paths.insert(
+        "/stats/conversations".into(),
+        get_json(
+            "Prompt cache effectiveness per conversation, most recently active first.",
+            json!({"type": "object", "properties": {
+                "conversations": {"type": "array", "items": {"type": "object"}}
+            }}),
+        ),
+    );
+    paths.insert(
+        "/stats/latency".into(),
+        get_json(
+            "Time to first token and output tokens per second per model.",
+            json!({"type": "object", "properties": {
+                "models": {"type": "object", "additionalProperties": {"type": "object"}}
+            }}),
+        ),
+    );
+    paths.insert(
+        "/stats/clients".into(),
+        get_json(
+            "The per-client concurrency cap and each client's request counters.",
+            json!({"type": "object", "properties": {
+                "per_client_concurrency": {"type": ["integer", "null"]},
+                "clients": {"type": "object", "additionalProperties": {"type": "object"}}
+            }}),
+        ),
+    );
+    paths.insert(
+        "/stats/conversion".into(),
+        get_json(
+            "How long converting requests into Codex prompts took.",
+            json!({"type": "object", "properties": {
+                "count": {"type": "integer"},
+                "total_us": {"type": "integer"},
+                "buckets": {"type": "array", "items": {"type": "object"}}
+            }}),
+        ),
+    );
+    paths.insert(
+        "/stats".into(),
+        get_json(
+            "Every `/stats/*` document in one, each under the last segment of its route.",
+            json!({"type": "object", "properties": {
+                "budget": {"type": "object"},
+                "conversations": {"type": "object"},
+                "latency": {"type": "object"},
+                "clients": {"type": "object"}
+            }}),
+        ),
+    );
+    paths.insert(
+        "/metrics".into(),
+        json!({"get": {
+            "summary": "Latency histograms and request counters in the Prometheus text format.",
+            "responses": {
+                "200": {
+                    "description": "Prometheus text exposition format 0.0.4.",
+                    "content": {"text/plain": {"schema": {"type": "string"}}},
+                },
+                "default": error_response(),
+            },
+        }}),
+    );
+    if surfaces.openai {
+        paths.insert("/v1/chat/completions".into(), chat_completions_path());
+        paths.insert(
+            "/v1/models".into(),
+            get_json(
+                "The Codex models requests may name.",
+                schema_ref("ModelList"),
+            ),
+        );
+    }
+    if surfaces.ollama {
+        paths.insert(
+            "/api/chat".into(),
+            ollama_path(
+                "Ollama-style chat.",
+                "OllamaChatRequest",
+                "OllamaChatRecord",
+            ),
+        );
+        paths.insert(
+            "/api/generate".into(),
+            ollama_path(
+                "Ollama-style completion.",
+                "OllamaGenerateRequest",
+                "OllamaGenerateRecord",
+            ),
+        );
+        paths.insert(
+            "/api/version".into(),
+            get_json(
+                "The Ollama release this server is compatible with.",
+                json!({"type": "object", "properties": {"version": {"type": "string"}}}),
+            ),
+        );
+        paths.insert(
+            "/api/tags".into(),
+            get_json(
+                "The models, as Ollama lists them.",
+                json!({"type": "object", "properties": {
+                    "models": {"type": "array", "items": {"type": "object"}}
+                }}),
+            ),
+        );
+        paths.insert(
+            "/api/ps".into(),
+            get_json(
+                "The models requests have kept loaded.",
+                json!({"type": "object", "properties": {
+                    "models": {"type": "array", "items": {"type": "object"}}
+                }}),
+            ),
+        );
+        paths.insert(
+            "/api/show".into(),
+            json!({"post": {
+                "summary": "One model's details, modelfile and capabilities.",
+                "requestBody": {"required": true, "content": {"application/json": {
+                    "schema": {"type": "object", "required": ["model"], "properties": {
+                        "model": {"type": "string"}
+                    }}
+                }}},
+                "responses": {
+                    "200": json_response(json!({"type": "object"})),
+                    "default": error_response()
+                }
+            }}),
+        );
+        paths.insert(
+            "/api/pull".into(),
+            json!({"post": {
+                "summary": "Succeeds at once for a served model; Codex models are never \
+                            downloaded.",
+                "requestBody": {"required": true, "content": {"application/json": {
+                    "schema": ollama_model_request()
+                }}},
+                "responses": {
+                    "200": json_response(json!({"type": "object", "properties": {
+                        "status": {"const": "success"}
+                    }})),
+                    "default": error_response()
+                }
+            }}),
+        );
+        paths.insert(
+            "/api/delete".into(),
+            json!({"delete": {
+                "summary": "Always an error: Codex models are not stored locally.",
+                "requestBody": {"required": true, "content": {"application/json": {
+                    "schema": ollama_model_request()
+                }}},
+                "responses": {
+                    "200": {"description": "Never sent."},
+                    "default": error_response()
+                }
+            }}),
+        );
+    }
+
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "Codex Serve",
+            "version": CRATE_VERSION,
+            "description": "OpenAI- and Ollama-compatible routes served by Codex.",
+        },
+        "paths": paths,
+        "components": {"schemas": schemas()},
+    })
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({"$ref": format!("#/components/schemas/{name}")})
+}
+
+fn json_response(schema: Value) -> Value {
+    json!({"description": "OK", "content": {"application/json": {"schema": schema}}})
+}
+
+fn error_response() -> Value {
+    json!({
+        "description": "An OpenAI-style error.",
+        "content": {"application/json": {"schema": schema_ref("ErrorResponse")}},
+    })
+}
+
+fn get_json(summary: &str, schema: Value) -> Value {
+    json!({"get": {
+        "summary": summary,
+        "responses": {"200": json_response(schema), "default": error_response()},
+    }})
+}
+
+fn chat_completions_path() -> Value {
+    json!({"post": {
+        "summary": "Chat completion; streams Server-Sent Events when `stream` is true.",
+        "requestBody": {"required": true, "content": {"application/json": {
+            "schema": schema_ref("ChatCompletionRequest")
+        }}},
+        "responses": {
+            "200": {
+                "description": "A completion, or with `stream: true` a stream of `data:` chunks \
+                                ended by `data: [DONE]`.",
+                "content": {
+                    "application/json": {"schema": schema_ref("ChatCompletionResponse")},
+                    "text/event-stream": {"schema": schema_ref("ChatCompletionChunk")},
+                },
+            },
+            "default": error_response(),
+        },
+    }})
+}
+
+fn ollama_model_request() -> Value {
+    json!({"type": "object", "required": ["model"], "properties": {
+        "model": {"type": "string"},
+        "stream": {"type": "boolean"}
+    }})
+}
+
+fn ollama_path(summary: &str, request: &str, record: &str) -> Value {
+    json!({"post": {
+        "summary": summary,
+        "requestBody": {"required": true, "content": {"application/json": {
+            "schema": schema_ref(request)
+        }}},
+        "responses": {
+            "200": {
+                "description": "NDJSON records unless `stream` is false, ending with a \
+                                `done: true` record.",
+                "content": {
+                    "application/json": {"schema": schema_ref(record)},
+                    "application/x-ndjson": {"schema": schema_ref(record)},
+                },
+            },
+            "default": error_response(),
+        },
+    }})
+}
+
+fn vendor(mut schema: Value, description: &str) -> Value {
+    schema["description"] = json!(description);
+    schema["x-codex-serve-support"] = json!("vendor");
+    schema
+}
+
+fn schemas() -> Value {
+    let mut request = json!({
+        "model": {
+            "type": "string",
+            "description": "A Codex model, as `profile/model` or with a reasoning suffix."
+        },
+        "messages": {"type": "array", "items": schema_ref("ChatMessage")},
+        "stream": {
+            "type": "boolean",
+            "default": false,
+            "description": "Stream the reply as Server-Sent Events of `ChatCompletionChunk`."
+        },
+        "tools": {"type": "array", "items": {"type": "object", "properties": {
+            "type": {"const": "function"},
+            "function": {"type": "object", "properties": {
+                "name": {"type": "string"},
+                "description": {"type": "string"},
+                "strict": {"type": "boolean"},
+                "parameters": {"type": "object"}
+            }}
+        }}},
+        "parallel_tool_calls": {"type": "boolean", "default": true},
+        "reasoning_effort": {"type": "string", "description": "Wins over `reasoning.effort`."},
+        "reasoning": {"type": "object", "properties": {
+            "effort": {"type": "string"},
+            "summary": {"type": "string"}
+        }},
+        "temperature": {"type": "number", "minimum": 0, "maximum": 2},
+        "top_p": {"type": "number", "minimum": 0, "maximum": 1},
+        "verbosity": {"enum": ["low", "medium", "high"]},
+        "web_search_options": {"type": "object", "properties": {"enabled": {"type": "boolean"}}},
+        "tool_choice": {
+            "description": "Only `\"none\"` is acted on; it also withholds the web search tool."
+        },
+        "prediction": {
+            "type": "object",
+            "description": "Passed to the model as a draft with `--use-prediction-hint`."
+        },
+        "response_format": {
+            "type": "object",
+            "description": "Not enforced; ask for the format in the prompt. A JSON format makes \
+                            `first_valid_json` the default `codex.select`."
+        },
+        "user": {
+            "type": "string",
+            "description": "Identifies the client for per-client limits and budgets."
+        },
+        "codex": vendor(
+            json!({"type": "object", "properties": {
+                "samples": {"type": "integer", "minimum": 1, "maximum": 8},
+                "select": {"enum": ["majority", "first_valid_json", "longest"]},
+                "truncate_tool_output": {
+                    "type": "boolean",
+                    "description": "`false` forwards tool results past `--max-tool-output-bytes`."
+                }
+            }}),
+            "Run several completions and answer with one (non-streaming only), or forward long \
+             tool results whole.",
+        ),
+        "stream_options": {"type": "object", "properties": {
+            "include_usage": {
+                "type": "boolean",
+                "description": "Usage is always sent on the last chunk."
+            },
+            "codex_progress": vendor(
+                json!({"type": "boolean"}),
+                "Interleave `: progress` SSE comments with a running token estimate.",
+            ),
+        }},
+    });
+    for &(field, reason) in IGNORED_CHAT_FIELDS {
+        request[field] = json!({"description": reason, "x-codex-serve-support": "ignored"});
+    }
+
+    let usage = json!({"type": "object", "properties": {
+        "prompt_tokens": {"type": "integer"},
+        "completion_tokens": {"type": "integer"},
+        "total_tokens": {"type": "integer"},
+        "prompt_tokens_details": {"type": "object"},
+        "completion_tokens_details": {"type": "object"}
+    }});
+    let tool_call = json!({"type": "object", "properties": {
+        "index": {"type": "integer"},
+        "id": {"type": "string"},
+        "type": {"const": "function"},
+        "function": {"type": "object", "properties": {
+            "name": {"type": "string"},
+            "arguments": {"type": "string"}
+        }}
+    }});
+    let reasoning = vendor(
+        json!({"type": "object", "properties": {
+            "summary": {"type": "array", "items": {"type": "object"}},
+            "content": {"type": "array", "items": {"type": "object"}}
+        }}),
+        "The model's reasoning summary and content.",
+    );
+    let model_names = [
+        (
+            "codex_requested_model",
+            "The model the client named, when `model` is the resolved one (`--report-model`).",
+        ),
+        (
+            "codex_resolved_model",
+            "The Codex model the request ran on, when `model` is the requested one.",
+        ),
+    ];
+    let mut response = json!({
+        "id": {"type": "string"},
+        "object": {"const": "chat.completion"},
+        "created": {"type": "integer"},
+        "model": {"type": "string"},
+        "choices": {"type": "array", "items": {"type": "object", "properties": {
+            "index": {"type": "integer"},
+            "finish_reason": {"type": "string"},
+            "logprobs": {"type": "null"},
+            "message": {"type": "object", "properties": {
+                "role": {"const": "assistant"},
+                "content": {"type": ["string", "null"]},
+                "refusal": {"type": "null"},
+                "tool_calls": {"type": "array", "items": tool_call.clone()},
+                "annotations": {"type": "array", "maxItems": 0},
+                "audio": {"type": "null"},
+                "function_call": {"type": "null"},
+                "reasoning": reasoning.clone()
+            }}
+        }}},
+        "usage": usage.clone(),
+        "service_tier": {"type": "null"},
+        "system_fingerprint": {"type": "null"},
+        "codex_usage": vendor(
+            json!({"type": "object"}),
+            "Codex's raw token counts (`--usage-extended`).",
+        ),
+        "resumed": vendor(
+            json!({"type": "boolean"}),
+            "The upstream stream broke and a second request finished the reply.",
+        ),
+        "tool_call_overflow": vendor(
+            json!({"type": "object"}),
+            "More tool calls than `--max-tracked-tool-calls`.",
+        ),
+        "codex_selection": vendor(
+            json!({"type": "object"}),
+            "How `codex.samples` picked the reply.",
+        ),
+    });
+    let mut chunk = json!({
+        "id": {"type": "string"},
+        "object": {"const": "chat.completion.chunk"},
+        "created": {"type": "integer"},
+        "model": {"type": "string"},
+        "choices": {"type": "array", "items": {"type": "object", "properties": {
+            "index": {"type": "integer"},
+            "finish_reason": {"type": ["string", "null"]},
+            "logprobs": {"type": "null"},
+            "delta": {"type": "object", "properties": {
+                "role": {"const": "assistant"},
+                "content": {"type": "string"},
+                "refusal": {"type": "null"},
+                "tool_calls": {"type": "array", "items": tool_call.clone()},
+                "reasoning": reasoning
+            }}
+        }}},
+        "usage": {"oneOf": [usage, {"type": "null"}]},
+        "service_tier": {"type": "null"},
+        "system_fingerprint": {"type": "null"},
+        "codex_usage": vendor(
+            json!({"type": "object"}),
+            "Codex's raw token counts (`--usage-extended`).",
+        ),
+        "tool_call_overflow": vendor(
+            json!({"type": "object"}),
+            "More tool calls than `--max-tracked-tool-calls`.",
+        ),
+        "codex_content_length": vendor(
+            json!({"type": "integer"}),
+            "Bytes of `delta.content` sent (`--stream-integrity`).",
+        ),
+        "codex_content_sha256": vendor(
+            json!({"type": "string"}),
+            "SHA-256 of the `delta.content` strings in order (`--stream-integrity`).",
+        ),
+    });
+    for (field, description) in model_names {
+        let schema = vendor(json!({"type": "string"}), description);
+        response[field] = schema.clone();
+        chunk[field] = schema;
+    }
+
+    json!({
+        "ChatCompletionRequest": {
+            "type": "object",
+            "required": ["model", "messages"],
+            "properties": request,
+        },
+        "ChatMessage": {"type": "object", "required": ["role"], "properties": {
+            "role": {
+                "type": "string",
+                "description": "`system`, `developer`, `user`, `assistant` or `tool`; \
+                                aliases such as `human` are read unless `--strict-params`."
+            },
+            "content": {"type": ["string", "array", "null"]},
+            "name": {"type": "string"},
+            "tool_call_id": {"type": "string"},
+            "tool_calls": {"type": "array", "items": tool_call}
+        }},
+        "ChatCompletionResponse": {"type": "object", "properties": response},
+        "ChatCompletionChunk": {"type": "object", "properties": chunk},
+        "ModelList": {"type": "object", "properties": {
+            "object": {"const": "list"},
+            "data": {"type": "array", "items": {"type": "object", "properties": {
+                "id": {"type": "string"},
+                "object": {"const": "model"},
+                "capabilities": {"type": "object"}
+            }}}
+        }},
+        "Health": {"type": "object", "properties": {
+            "status": {"enum": ["ok", "degraded", "failing"]},
+            "ok": {"type": "boolean"},
+            "version": {"type": "string"},
+            "authenticated": {"type": "boolean"},
+            "message": {"type": "string"}
+        }},
+        "OllamaChatRequest": {"type": "object", "required": ["model"], "properties": {
+            "model": {"type": "string"},
+            "messages": {"type": "array", "items": {"type": "object", "properties": {
+                "role": {"type": "string"},
+                "content": {"type": "string"},
+                "images": {"type": "array", "items": {
+                    "type": "string",
+                    "contentEncoding": "base64"
+                }},
+                "tool_calls": {"type": "array", "items": {"type": "object"}},
+                "tool_name": {"type": "string"},
+                "tool_call_id": {"type": "string"}
+            }}},
+            "stream": {"type": "boolean", "default": true},
+            "tools": {"type": "array", "items": {"type": "object"}},
+            "keep_alive": {"type": ["string", "number"]}
+        }},
+        "OllamaGenerateRequest": {"type": "object", "required": ["model"], "properties": {
+            "model": {"type": "string"},
+            "prompt": {"type": "string"},
+            "system": {"type": "string"},
+            "images": {"type": "array", "items": {
+                "type": "string",
+                "contentEncoding": "base64"
+            }},
+            "stream": {"type": "boolean", "default": true},
+            "keep_alive": {"type": ["string", "number"]}
+        }},
+        "OllamaChatRecord": {"type": "object", "properties": {
+            "model": {"type": "string"},
+            "created_at": {"type": "string", "format": "date-time"},
+            "message": {"type": "object"},
+            "done": {"type": "boolean"},
+            "done_reason": {"type": "string"}
+        }},
+        "OllamaGenerateRecord": {"type": "object", "properties": {
+            "model": {"type": "string"},
+            "created_at": {"type": "string", "format": "date-time"},
+            "response": {"type": "string"},
+            "done": {"type": "boolean"},
+            "done_reason": {"type": "string"}
+        }},
+        "ErrorResponse": {"type": "object", "required": ["error"], "properties": {
+            "error": {"type": "object", "required": ["message", "type", "code"], "properties": {
+                "message": {"type": "string"},
+                "type": {"type": "string"},
+                "code": {"type": "string"},
+                "request_id": {"type": "string"},
+                "client": {"type": "string"},
+                "original_request_id": {"type": "string"}
+            }}
+        }},
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::{
+        error::ApiError,
+        openai::chat::{
+            ChatCompletionRequest, ChatMessage, CodexOptions, Prediction, ReasoningOptions,
+            StreamOptions, WebSearchOptions,
+        },
+        serve_config::ReportModel,
+        server::{
+            RequestClock,
+            response::{
+                ChatCompletionResponse, ChunkDelta, ChunkTemplate, ContentDigest, ToolCall, Usage,
+            },
+            sampling::SampleSelection,
+            tool_calls::ToolCallOverflow,
+        },
+    };
+
+    fn keys(value: &Value) -> BTreeSet<String> {
+        value
+            .as_object()
+            .map(|object| object.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn properties(schema: &str) -> Value {
+        schemas()[schema]["properties"].clone()
+    }
+
+    fn assert_documented(emitted: &Value, documented: &Value, what: &str) {
+        let missing: Vec<String> = keys(emitted)
+            .difference(&keys(documented))
+            .cloned()
+            .collect();
+        assert!(missing.is_empty(), "{what} emits undocumented {missing:?}");
+    }
+
+    #[test]
+    fn every_request_field_is_described() {
+        // Every field set, so a new one has to be added here and then described.
+        let request = ChatCompletionRequest {
+            model: "gpt-5".into(),
+            messages: vec![ChatMessage::default()],
+            stream: true,
+            tools: Default::default(),
+            parallel_tool_calls: Some(true),
+            reasoning_effort: Some("high".into()),
+            reasoning: Some(ReasoningOptions::default()),
+            temperature: Some(1.0),
+            top_p: Some(1.0),
+            verbosity: Some("low".into()),
+            web_search_options: Some(WebSearchOptions::default()),
+            tool_choice: Some(json!("none")),
+            prediction: Some(Prediction::default()),
+            response_format: Some(json!({"type": "json_object"})),
+            codex: Some(CodexOptions::default()),
+            stream_options: Some(StreamOptions::default()),
+        };
+        let request = serde_json::to_value(&request).unwrap();
+        let documented = properties("ChatCompletionRequest");
+        assert_documented(&request, &documented, "ChatCompletionRequest");
+        assert_documented(
+            &serde_json::to_value(ChatMessage::default()).unwrap(),
+            &properties("ChatMessage"),
+            "ChatMessage",
+        );
+
+        // Fields described as honored are the ones the request reads, plus `user`, which the
+        // client identity is taken from.
+        let honored: BTreeSet<String> = documented
+            .as_object()
+            .unwrap()
+            .iter()
+            .filter(|(_, schema)| schema["x-codex-serve-support"] != "ignored")
+            .map(|(field, _)| field.clone())
+            .filter(|field| field != "user")
+            .collect();
+        assert_eq!(honored, keys(&request));
+    }
+
+    #[test]
+    fn every_response_field_is_described() {
+        let mut response = ChatCompletionResponse::stub("gpt-5:high".into(), "hi".into());
+        response.set_resolved_model(Some("gpt-5".into()));
+        response.report_model(Some(ReportModel::Requested));
+        response.mark_resumed();
+        response.set_tool_call_overflow(Some(ToolCallOverflow {
+            limit: 1,
+            untracked: 1,
+        }));
+        response.set_sample_selection(
+            Usage::default(),
+            SampleSelection {
+                select: Default::default(),
+                samples: 2,
+                failed: 0,
+                reason: "majority".into(),
+                discarded_lengths: vec![2],
+            },
+        );
+        response.include_compat_nulls();
+        let response = serde_json::to_value(&response).unwrap();
+        let documented = properties("ChatCompletionResponse");
+        assert_documented(&response, &documented, "ChatCompletionResponse");
+        let choice = &documented["choices"]["items"]["properties"];
+        assert_documented(&response["choices"][0], choice, "a choice");
+        assert_documented(
+            &response["choices"][0]["message"],
+            &choice["message"]["properties"],
+            "a message",
+        );
+        assert_documented(
+            &response["usage"],
+            &documented["usage"]["properties"],
+            "usage",
+        );
+
+        let template = ChunkTemplate::new(&RequestClock::start(), "gpt-5:high".into())
+            .with_report_model(Some("gpt-5".into()), Some(ReportModel::Resolved))
+            .with_compat_nulls(true);
+        let mut digest = ContentDigest::default();
+        digest.update("hi");
+        let integrity = digest.finish();
+        let call = ToolCall::new("call_1".into(), "get_weather".into(), "{}".into());
+        let documented = properties("ChatCompletionChunk");
+        let delta = &documented["choices"]["items"]["properties"]["delta"]["properties"];
+        for chunk in [
+            template.chunk(ChunkDelta::role(), None),
+            template.chunk(ChunkDelta::reasoning_summary("thinking"), None),
+            template.chunk(ChunkDelta::tool_call(0, &call, "{}"), None),
+            template
+                .chunk(ChunkDelta::default(), Some("stop"))
+                .with_usage(&Usage::default())
+                .with_tool_call_overflow(Some(ToolCallOverflow {
+                    limit: 1,
+                    untracked: 1,
+                }))
+                .with_content_integrity(Some(&integrity)),
+        ] {
+            let chunk = serde_json::to_value(&chunk).unwrap();
+            assert_documented(&chunk, &documented, "ChatCompletionChunk");
+            assert_documented(&chunk["choices"][0]["delta"], delta, "a delta");
+        }
+    }
+
+    #[test]
+    fn the_error_body_is_described() {
+        let body = ApiError::bad_request("nope").into_body_json(Some("req_1".into()));
+        let documented = properties("ErrorResponse");
+        assert_documented(&body, &documented, "an error");
+        assert_documented(
+            &body["error"],
+            &documented["error"]["properties"],
+            "an error",
+        );
+    }
+
+    #[test]
+    fn only_the_served_surfaces_are_described() {
+        let ollama = document(ApiSurfaces {
+            openai: false,
+            ollama: true,
+        });
+        assert!(ollama["paths"].get("/api/chat").is_some());
+        assert!(ollama["paths"].get("/v1/chat/completions").is_none());
+        assert!(ollama["paths"].get("/healthz").is_some());
+    }
+}
@@ -0,0 +1,124 @@
+//! `OpenAI-Organization` and `OpenAI-Project`: tools built for the OpenAI API send them, and in a
+//! shared deployment they name the caller better than its address. They become the client
+//! identity for the per-client limits, budgets and stats (scoped under the API key when there is
+//! one, which the limits stay charged to), go in the access log and captures, and are echoed on
+//! the response as OpenAI does.
+
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderName, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+
+use super::access_log::AccessLog;
+
+pub const OPENAI_ORGANIZATION_HEADER: HeaderName = HeaderName::from_static("openai-organization");
+pub const OPENAI_PROJECT_HEADER: HeaderName = HeaderName::from_static("openai-project");
+
+/// Longest organization or project id we take as an identity.
+const MAX_ID_LEN: usize = 128;
+
+/// The organization and project a request named, stored in the request extensions when it named
+/// either.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(super) struct OpenAiOrganization {
+    pub(super) organization: Option<String>,
+    pub(super) project: Option<String>,
+}
+
+impl OpenAiOrganization {
+    /// Reads both headers; `None` when neither holds a usable id.
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let read = |name: &HeaderName| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+                .filter(|value| !value.is_empty() && value.len() <= MAX_ID_LEN)
+                .map(str::to_string)
+        };
+        let found = Self {
+            organization: read(&OPENAI_ORGANIZATION_HEADER),
+            project: read(&OPENAI_PROJECT_HEADER),
+        };
+        (found != Self::default()).then_some(found)
+    }
+
+    /// The client identity: `org:<id>`, `project:<id>`, or both joined with `/`.
+    pub(super) fn client_id(&self) -> String {
+        let parts: Vec<String> = [
+            self.organization.as_ref().map(|org| format!("org:{org}")),
+            self.project
+                .as_ref()
+                .map(|project| format!("project:{project}")),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        parts.join("/")
+    }
+}
+
+/// Picks up the headers for the handlers and the access log, and echoes them on the response.
+pub(super) async fn read_openai_organization(mut request: Request<Body>, next: Next) -> Response {
+    let Some(organization) = OpenAiOrganization::from_headers(request.headers()) else {
+        return next.run(request).await;
+    };
+    if let Some(log) = request.extensions().get::<AccessLog>() {
+        log.record_organization(&organization);
+    }
+    request.extensions_mut().insert(organization.clone());
+
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    for (name, value) in [
+        (OPENAI_ORGANIZATION_HEADER, organization.organization),
+        (OPENAI_PROJECT_HEADER, organization.project),
+    ] {
+        if let Some(value) = value.and_then(|value| HeaderValue::from_str(&value).ok()) {
+            headers.insert(name, value);
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(HeaderName, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_str(value).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn either_header_names_the_client() {
+        let both = headers(&[
+            (OPENAI_ORGANIZATION_HEADER, "org-acme"),
+            (OPENAI_PROJECT_HEADER, " proj_web "),
+        ]);
+        let read = OpenAiOrganization::from_headers(&both).unwrap();
+        assert_eq!(read.client_id(), "org:org-acme/project:proj_web");
+
+        let org = headers(&[(OPENAI_ORGANIZATION_HEADER, "org-acme")]);
+        let read = OpenAiOrganization::from_headers(&org).unwrap();
+        assert_eq!(read.client_id(), "org:org-acme");
+
+        let project = headers(&[(OPENAI_PROJECT_HEADER, "proj_web")]);
+        let read = OpenAiOrganization::from_headers(&project).unwrap();
+        assert_eq!(read.client_id(), "project:proj_web");
+    }
+
+    #[test]
+    fn blank_and_oversized_ids_are_ignored() {
+        assert_eq!(OpenAiOrganization::from_headers(&HeaderMap::new()), None);
+        let blank = headers(&[(OPENAI_ORGANIZATION_HEADER, "  ")]);
+        assert_eq!(OpenAiOrganization::from_headers(&blank), None);
+        let long = "o".repeat(MAX_ID_LEN + 1);
+        let oversized = headers(&[(OPENAI_PROJECT_HEADER, long.as_str())]);
+        assert_eq!(OpenAiOrganization::from_headers(&oversized), None);
+    }
+}
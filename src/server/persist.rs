@@ -0,0 +1,330 @@
+//! `--state-file`: the request counters, token budget windows and conversation stats survive a
+//! restart. Only these stats are kept: every request starts a fresh Codex conversation, so there
+//! is no conversation to resume. They are written as one JSON document every [`SAVE_INTERVAL`]
+//! and on graceful shutdown, to a temporary file synced and renamed over the old one so a crash
+//! mid-write leaves the last complete snapshot. At startup the file is read back; entries that
+//! expired while the server was down are dropped, and a file that is corrupt or from another
+//! format version is ignored with a warning rather than keeping the server from starting.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{sync::oneshot, task::JoinHandle};
+use tracing::{info, warn};
+
+use super::{
+    AppState, budget::SavedBooking, conversations::SavedConversation, metrics::SavedMetrics,
+};
+
+/// Bumped whenever the layout of [`SavedState`] changes; files of another version are ignored.
+const STATE_VERSION: u32 = 1;
+
+/// How often the state is written while serving.
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SavedState {
+    version: u32,
+    saved_at_ms: u64,
+    metrics: SavedMetrics,
+    budgets: BTreeMap<String, Vec<SavedBooking>>,
+    conversations: BTreeMap<String, SavedConversation>,
+}
+
+/// Pairs the monotonic clock the maps keep time with to the wall clock a file can carry over a
+/// restart.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct WallClock {
+    now: Instant,
+    unix_ms: u64,
+}
+
+impl WallClock {
+    pub(super) fn read() -> Self {
+        let unix_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or_default();
+        Self {
+            now: Instant::now(),
+            unix_ms,
+        }
+    }
+
+    pub(super) fn now(&self) -> Instant {
+        self.now
+    }
+
+    /// `at` in unix milliseconds.
+    pub(super) fn unix_ms(&self, at: Instant) -> u64 {
+        let age = self.now.saturating_duration_since(at).as_millis() as u64;
+        self.unix_ms.saturating_sub(age)
+    }
+
+    /// The instant of `unix_ms`, or `None` once it is `ttl` or more in the past. A time from
+    /// before the monotonic clock's start, as on a host booted since, is read as now rather than
+    /// dropped.
+    pub(super) fn instant(&self, unix_ms: u64, ttl: Duration) -> Option<Instant> {
+        let age = Duration::from_millis(self.unix_ms.saturating_sub(unix_ms));
+        if age >= ttl {
+            return None;
+        }
+        Some(self.now.checked_sub(age).unwrap_or(self.now))
+    }
+}
+
+/// Writes `state`'s counters, budgets and conversations to `path`.
+pub(super) fn save(state: &AppState, path: &Path) -> io::Result<()> {
+    let clock = WallClock::read();
+    let saved = SavedState {
+        version: STATE_VERSION,
+        saved_at_ms: clock.unix_ms,
+        metrics: state.metrics().save(),
+        budgets: state.token_budgets().save(&clock),
+        conversations: state.conversations().save(&clock),
+    };
+    let json = serde_json::to_vec_pretty(&saved).map_err(io::Error::other)?;
+    let temp = temp_path(path);
+    let mut file = fs::File::create(&temp)?;
+    file.write_all(&json)?;
+    // On disk before the rename, or a crash could leave `path` naming an empty file.
+    file.sync_all()?;
+    fs::rename(&temp, path)
+}
+
+/// Restores what `path` holds into `state`. A missing file is a first start; one that cannot be
+/// used is logged and left alone, to be replaced by the next save.
+pub(super) fn load(state: &AppState, path: &Path) {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return,
+        Err(err) => {
+            warn!(path = %path.display(), "failed to read state file; starting fresh: {err}");
+            return;
+        }
+    };
+    let saved = match parse(&bytes) {
+        Ok(saved) => saved,
+        Err(reason) => {
+            warn!(path = %path.display(), "ignoring state file: {reason}");
+            return;
+        }
+    };
+    let clock = WallClock::read();
+    let conversations = saved.conversations.len();
+    state.metrics().restore(saved.metrics);
+    state.token_budgets().restore(saved.budgets, &clock);
+    state.conversations().restore(saved.conversations, &clock);
+    info!(path = %path.display(), conversations, "restored state file");
+}
+
+/// The periodic saver [`start`] runs.
+pub(super) struct Saver {
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl Saver {
+    /// Ends the saver once any save in flight has finished, so no later write races the caller's
+    /// own over the temporary file.
+    pub(super) async fn stop(self) {
+        let _ = self.stop.send(());
+        let _ = self.task.await;
+    }
+}
+
+/// Saves `state` to `path` every [`SAVE_INTERVAL`] until [`Saver::stop`].
+pub(super) fn start(state: AppState, path: PathBuf) -> Saver {
+    let (stop, mut stopped) = oneshot::channel();
+    let task = tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(SAVE_INTERVAL);
+        ticks.tick().await;
+        loop {
+            tokio::select! {
+                _ = ticks.tick() => {}
+                _ = &mut stopped => return,
+            }
+            let state = state.clone();
+            let path = path.clone();
+            let saved = tokio::task::spawn_blocking(move || save(&state, &path)).await;
+            if let Ok(Err(err)) = saved {
+                warn!("failed to write state file: {err}");
+            }
+        }
+    });
+    Saver { stop, task }
+}
+
+fn parse(bytes: &[u8]) -> Result<SavedState, String> {
+    let value: serde_json::Value =
+        serde_json::from_slice(bytes).map_err(|err| format!("not valid JSON ({err})"))?;
+    let version = value.get("version").and_then(serde_json::Value::as_u64);
+    if version != Some(u64::from(STATE_VERSION)) {
+        return Err(format!(
+            "version {} where {STATE_VERSION} was expected",
+            version.map_or_else(|| "missing".to_string(), |version| version.to_string())
+        ));
+    }
+    serde_json::from_value(value).map_err(|err| format!("unexpected layout ({err})"))
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use codex_core::protocol::TokenUsage;
+
+    use super::*;
+
+    fn state_path() -> PathBuf {
+        std::env::temp_dir().join(format!("codex-serve-state-{}.json", uuid::Uuid::new_v4()))
+    }
+
+    fn usage() -> TokenUsage {
+        TokenUsage {
+            input_tokens: 300,
+            cached_input_tokens: 200,
+            output_tokens: 40,
+            reasoning_output_tokens: 0,
+            total_tokens: 540,
+        }
+    }
+
+    #[test]
+    fn a_snapshot_reloads_into_a_fresh_state() {
+        let path = state_path();
+        let before = AppState::insecure_mock(true);
+        drop(before.metrics().start_request());
+        before.metrics().record_tokens(540);
+        before.token_budgets().book("user:a", 540, Instant::now());
+        before.conversations().record("abc", &usage());
+        before.conversations().record("abc", &usage());
+        save(&before, &path).expect("state file is written");
+        assert!(!temp_path(&path).exists());
+
+        let after = AppState::insecure_mock(true);
+        load(&after, &path);
+        assert_eq!(after.metrics().snapshot().requests_total, 1);
+        assert_eq!(after.metrics().snapshot().tokens_total, 540);
+        assert_eq!(after.token_budgets().snapshot(1000)["user:a"].spent, 540);
+        let conversations = after.conversations().snapshot();
+        assert_eq!(conversations.len(), 1);
+        assert_eq!(conversations[0].conversation, "abc");
+        assert_eq!(conversations[0].turns, 2);
+        assert_eq!(conversations[0].cached_tokens, 400);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn entries_past_their_ttl_are_dropped_on_load() {
+        let path = state_path();
+        let now = WallClock::read().unix_ms;
+        let minute = 60 * 1000;
+        let conversation = |last_seen_ms| SavedConversation {
+            turns: 1,
+            input_tokens: 10,
+            cached_input_tokens: 0,
+            output_tokens: 5,
+            last_turn_cached_tokens: 0,
+            last_seen_ms,
+        };
+        let saved = SavedState {
+            version: STATE_VERSION,
+            saved_at_ms: now - 45 * minute,
+            metrics: SavedMetrics {
+                requests_total: 7,
+                ..SavedMetrics::default()
+            },
+            budgets: BTreeMap::from([(
+                "user:a".to_string(),
+                vec![
+                    SavedBooking {
+                        at_ms: now - 90 * minute,
+                        tokens: 100,
+                    },
+                    SavedBooking {
+                        at_ms: now - 50 * minute,
+                        tokens: 20,
+                    },
+                ],
+            )]),
+            conversations: BTreeMap::from([
+                ("stale".to_string(), conversation(now - 45 * minute)),
+                ("fresh".to_string(), conversation(now - minute)),
+            ]),
+        };
+        fs::write(&path, serde_json::to_vec(&saved).unwrap()).unwrap();
+
+        let state = AppState::insecure_mock(true);
+        load(&state, &path);
+        // Counters never expire; the hour-old booking and the idle conversation do.
+        assert_eq!(state.metrics().snapshot().requests_total, 7);
+        assert_eq!(state.token_budgets().snapshot(1000)["user:a"].spent, 20);
+        let conversations = state.conversations().snapshot();
+        assert_eq!(conversations.len(), 1);
+        assert_eq!(conversations[0].conversation, "fresh");
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn times_before_the_monotonic_clock_are_kept_as_now() {
+        let clock = WallClock::read();
+        assert_eq!(clock.instant(0, Duration::MAX), Some(clock.now()));
+        assert_eq!(clock.instant(0, Duration::from_secs(60)), None);
+    }
+
+    #[test]
+    fn corrupt_and_foreign_files_are_ignored() {
+        let path = state_path();
+        for contents in [
+            "{\"version\": 1, \"metrics\": ".to_string(),
+            "not json at all".to_string(),
+            json_with_version(STATE_VERSION + 1),
+            "{\"version\": 1, \"metrics\": []}".to_string(),
+        ] {
+            fs::write(&path, &contents).unwrap();
+            let state = AppState::insecure_mock(true);
+            load(&state, &path);
+            assert_eq!(state.metrics().snapshot().requests_total, 0, "{contents}");
+            assert!(state.conversations().snapshot().is_empty(), "{contents}");
+        }
+        let _ = fs::remove_file(&path);
+
+        // No file at all is a first start.
+        let state = AppState::insecure_mock(true);
+        load(&state, &path);
+        assert_eq!(state.metrics().snapshot().requests_total, 0);
+    }
+
+    #[tokio::test]
+    async fn a_stopped_saver_has_finished_writing() {
+        let path = state_path();
+        let state = AppState::insecure_mock(true);
+        start(state.clone(), path.clone()).stop().await;
+        save(&state, &path).expect("state file is written");
+        assert!(!temp_path(&path).exists());
+        let _ = fs::remove_file(path);
+    }
+
+    fn json_with_version(version: u32) -> String {
+        let saved = SavedState {
+            version,
+            metrics: SavedMetrics {
+                requests_total: 3,
+                ..SavedMetrics::default()
+            },
+            ..SavedState::default()
+        };
+        serde_json::to_string(&saved).unwrap()
+    }
+}
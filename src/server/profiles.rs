@@ -0,0 +1,187 @@
+use std::{collections::BTreeSet, fs, path::Path};
+
+use axum::http::{HeaderMap, HeaderName};
+use toml::Value as TomlValue;
+use tracing::warn;
+
+use crate::error::ApiError;
+
+/// Selects a Codex config profile for a single request.
+pub const PROFILE_HEADER: HeaderName = HeaderName::from_static("x-codex-profile");
+
+/// Profile names declared under `[profiles.*]` in the Codex `config.toml`.
+#[derive(Clone, Debug, Default)]
+pub struct ProfileCatalog {
+    names: BTreeSet<String>,
+    /// Why `config.toml` could not be read for profiles, reported by `/healthz`.
+    load_warning: Option<String>,
+}
+
+impl ProfileCatalog {
+    /// Reads the profile table from `<codex_home>/config.toml`. A missing or unreadable file
+    /// simply means no profiles are available.
+    pub fn from_codex_home(codex_home: &Path) -> Self {
+        let path = codex_home.join("config.toml");
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(_) => return Self::default(),
+        };
+        match contents.parse::<toml::Table>() {
+            Ok(table) => Self::from_names(
+                table
+                    .get("profiles")
+                    .and_then(TomlValue::as_table)
+                    .into_iter()
+                    .flat_map(|profiles| profiles.keys().cloned()),
+            ),
+            Err(err) => {
+                warn!(path = %path.display(), "could not parse Codex config profiles: {err}");
+                Self {
+                    load_warning: Some(format!(
+                        "could not parse Codex config profiles in {}: {err}",
+                        path.display()
+                    )),
+                    ..Self::default()
+                }
+            }
+        }
+    }
+
+    pub fn from_names<I, S>(names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            names: names.into_iter().map(Into::into).collect(),
+            load_warning: None,
+        }
+    }
+
+    pub fn load_warning(&self) -> Option<&str> {
+        self.load_warning.as_deref()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(String::as_str)
+    }
+
+    pub fn validate(&self, profile: &str) -> Result<(), ApiError> {
+        if self.names.contains(profile) {
+            return Ok(());
+        }
+        let known = if self.names.is_empty() {
+            "no profiles are defined in the Codex config.toml".to_string()
+        } else {
+            format!(
+                "available profiles: {}",
+                self.names().collect::<Vec<_>>().join(", ")
+            )
+        };
+        Err(ApiError::bad_request(format!(
+            "Unknown Codex profile `{profile}` ({known})"
+        )))
+    }
+}
+
+/// Splits the profile selection out of a request: either the `X-Codex-Profile` header or a
+/// `profile/model` prefix on the model name. Returns `(profile, model)`. A prefix `profiles` does
+/// not define is part of the model name (`openai/gpt-5`), while a header naming an unknown profile
+/// is refused with a 400 that names it.
+pub(crate) fn resolve_profile(
+    headers: &HeaderMap,
+    model: &str,
+    profiles: &ProfileCatalog,
+) -> Result<(Option<String>, String), ApiError> {
+    let from_header = match headers.get(&PROFILE_HEADER) {
+        Some(value) => {
+            let value = value.to_str().map_err(|_| {
+                ApiError::bad_request(format!("{PROFILE_HEADER} header must be valid ASCII"))
+            })?;
+            Some(value.trim()).filter(|value| !value.is_empty())
+        }
+        None => None,
+    };
+    let (from_model, model) = match model.split_once('/') {
+        Some((profile, base)) if !base.is_empty() && profiles.names.contains(profile) => {
+            (Some(profile), base)
+        }
+        _ => (None, model),
+    };
+
+    let profile = match (from_header, from_model) {
+        (Some(header), Some(prefix)) if header != prefix => {
+            return Err(ApiError::bad_request(format!(
+                "Conflicting Codex profiles: {PROFILE_HEADER} selects `{header}` but the model \
+                 name selects `{prefix}`"
+            )));
+        }
+        (header, prefix) => header.or(prefix).map(str::to_string),
+    };
+    if let Some(profile) = profile.as_deref() {
+        profiles.validate(profile)?;
+    }
+    Ok((profile, model.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn profile_comes_from_header_or_model_prefix() {
+        let catalog = ProfileCatalog::from_names(["work", "home"]);
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            resolve_profile(&headers, "work/gpt-5", &catalog).unwrap(),
+            (Some("work".to_string()), "gpt-5".to_string())
+        );
+        assert_eq!(
+            resolve_profile(&headers, "gpt-5", &catalog).unwrap(),
+            (None, "gpt-5".to_string())
+        );
+
+        headers.insert(PROFILE_HEADER, HeaderValue::from_static("home"));
+        assert_eq!(
+            resolve_profile(&headers, "gpt-5", &catalog).unwrap(),
+            (Some("home".to_string()), "gpt-5".to_string())
+        );
+        assert!(resolve_profile(&headers, "work/gpt-5", &catalog).is_err());
+    }
+
+    #[test]
+    fn header_profiles_are_validated() {
+        let catalog = ProfileCatalog::from_names(["work"]);
+        let mut headers = HeaderMap::new();
+        headers.insert(PROFILE_HEADER, HeaderValue::from_static("play"));
+        assert!(resolve_profile(&headers, "gpt-5", &catalog).is_err());
+    }
+
+    #[test]
+    fn undefined_prefixes_stay_part_of_the_model_name() {
+        let headers = HeaderMap::new();
+        assert_eq!(
+            resolve_profile(&headers, "openai/gpt-5", &ProfileCatalog::default()).unwrap(),
+            (None, "openai/gpt-5".to_string())
+        );
+        let catalog = ProfileCatalog::from_names(["work"]);
+        assert_eq!(
+            resolve_profile(&headers, "play/gpt-5", &catalog).unwrap(),
+            (None, "play/gpt-5".to_string())
+        );
+    }
+
+    #[test]
+    fn unknown_profiles_are_named_in_the_error() {
+        let catalog = ProfileCatalog::from_names(["work"]);
+        assert!(catalog.validate("work").is_ok());
+        match catalog.validate("play") {
+            Err(ApiError::BadRequest(message)) => {
+                assert!(message.contains("`play`"));
+                assert!(message.contains("work"));
+            }
+            other => panic!("expected a 400, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! `stream_options.codex_progress`: SSE comments such as
+//! `: progress {"output_tokens_estimate": 310, "elapsed_ms": 4200}` interleaved with a stream's
+//! chunks, so dashboards can show a long generation moving without counting tokens themselves.
+//! Clients skip comments, so streams that did not ask for them read the same either way. The
+//! estimate uses the same bytes-per-token rule as the token budgets; the usage on the final
+//! chunk stays authoritative.
+
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use super::{budget::tokens_for_bytes, framing::StreamFrame};
+
+/// Counts a stream's generated text and says when the next progress comment is due.
+pub(super) struct ProgressMeter {
+    interval: Duration,
+    started: Instant,
+    last_sent: Instant,
+    bytes: usize,
+}
+
+#[derive(Serialize)]
+struct Progress {
+    output_tokens_estimate: u64,
+    elapsed_ms: u64,
+}
+
+impl ProgressMeter {
+    /// Sends at most one comment per `interval`, the first one `interval` after the start.
+    pub(super) fn new(interval: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            interval,
+            started: now,
+            last_sent: now,
+            bytes: 0,
+        }
+    }
+
+    /// Counts `text` as generated, returning a progress comment if one is due.
+    pub(super) fn record(&mut self, text: &str) -> Option<StreamFrame> {
+        self.bytes += text.len();
+        let now = Instant::now();
+        if now.duration_since(self.last_sent) < self.interval {
+            return None;
+        }
+        self.last_sent = now;
+        let progress = Progress {
+            output_tokens_estimate: tokens_for_bytes(self.bytes),
+            elapsed_ms: u64::try_from(now.duration_since(self.started).as_millis())
+                .unwrap_or(u64::MAX),
+        };
+        let json = serde_json::to_string(&progress).ok()?;
+        Some(StreamFrame::Comment(format!("progress {json}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comment(frame: Option<StreamFrame>) -> Option<String> {
+        match frame? {
+            StreamFrame::Comment(text) => Some(text),
+            _ => panic!("expected a comment frame"),
+        }
+    }
+
+    #[test]
+    fn reports_the_running_estimate_once_per_interval() {
+        let mut meter = ProgressMeter::new(Duration::from_secs(3600));
+        assert!(comment(meter.record("not yet")).is_none());
+
+        let mut meter = ProgressMeter::new(Duration::ZERO);
+        let first = comment(meter.record("12345678")).expect("due");
+        assert!(
+            first.starts_with(r#"progress {"output_tokens_estimate":2,"elapsed_ms":"#),
+            "{first}"
+        );
+        let second = comment(meter.record("9")).expect("due");
+        assert!(second.contains(r#""output_tokens_estimate":3"#), "{second}");
+    }
+}
@@ -0,0 +1,66 @@
+use serde_json::Value;
+
+/// JSON keys whose string values carry conversation text or tool payloads.
+const SENSITIVE_KEYS: &[&str] = &[
+    "arguments",
+    "content",
+    "first_user_message",
+    "input",
+    "instructions",
+    "output",
+    "reasoning",
+    "reasoning_content",
+    "reasoning_summary",
+    "system_prompt",
+    "text",
+];
+
+/// Replaces conversation text in `value` with a length marker, keeping the surrounding structure
+/// (roles, tool names, ids, usage) intact so redacted payloads stay useful for debugging.
+pub(crate) fn redact_json(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, entry) in map.iter_mut() {
+                match entry {
+                    Value::String(text) if SENSITIVE_KEYS.contains(&key.as_str()) => {
+                        *text = format!("[redacted: {} chars]", text.chars().count());
+                    }
+                    _ => redact_json(entry),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn redacts_text_but_keeps_structure() {
+        let mut value = json!({
+            "model": "gpt-5",
+            "messages": [
+                {"role": "user", "content": "secret plans"},
+                {"role": "user", "content": [{"type": "text", "text": "more"}]}
+            ],
+            "tool_calls": [{"function": {"name": "lookup", "arguments": "{\"q\":1}"}}]
+        });
+        redact_json(&mut value);
+        assert_eq!(value["model"], "gpt-5");
+        assert_eq!(value["messages"][0]["role"], "user");
+        assert_eq!(value["messages"][0]["content"], "[redacted: 12 chars]");
+        assert_eq!(
+            value["messages"][1]["content"][0]["text"],
+            "[redacted: 4 chars]"
+        );
+        assert_eq!(value["tool_calls"][0]["function"]["name"], "lookup");
+        assert_eq!(
+            value["tool_calls"][0]["function"]["arguments"],
+            "[redacted: 7 chars]"
+        );
+    }
+}
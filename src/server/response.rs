@@ -1,7 +1,11 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use codex_core::protocol::TokenUsage;
-use serde::Serialize;
+use serde::{Serialize, Serializer};
+use sha2::{Digest, Sha256};
+
+use super::{sampling::SampleSelection, tool_calls::ToolCallOverflow};
+use crate::{clock::RequestClock, openai::tool_names::ToolNames, serve_config::ReportModel};
 
 #[derive(Debug, Serialize)]
 pub struct ChatCompletionResponse {
@@ -11,22 +15,83 @@ pub struct ChatCompletionResponse {
     model: String,
     choices: Vec<Choice>,
     usage: Usage,
+    /// Vendor extension carrying Codex's raw token counts (`--usage-extended`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    codex_usage: Option<TokenUsage>,
+    /// Vendor extension: the upstream stream broke and a second request finished the reply.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    resumed: bool,
+    /// Vendor extension: the response named more tool calls than `--max-tracked-tool-calls`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_overflow: Option<ToolCallOverflow>,
+    /// Vendor extension: how `codex.samples` picked this reply among the samples.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    codex_selection: Option<SampleSelection>,
+    /// Vendor extension: the model the client named, when `model` is the resolved one and differs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    codex_requested_model: Option<String>,
+    /// Vendor extension: the Codex model the request ran on, when `model` is the requested one and
+    /// differs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    codex_resolved_model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    service_tier: CompatNull,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_fingerprint: CompatNull,
+}
+
+/// A key OpenAI always sends that Codex has no value for: left out by default, written as `null`
+/// under `--compat-nulls` (`Some(())` serializes as `null`).
+type CompatNull = Option<()>;
+
+/// An optional value that `--compat-nulls` writes as `null` when unset instead of leaving out.
+#[derive(Debug)]
+struct OrNull<T> {
+    value: Option<T>,
+    null: bool,
+}
+
+impl<T> OrNull<T> {
+    fn new(value: Option<T>) -> Self {
+        Self { value, null: false }
+    }
+
+    fn is_omitted(&self) -> bool {
+        self.value.is_none() && !self.null
+    }
+}
+
+impl<T: Serialize> Serialize for OrNull<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value.serialize(serializer)
+    }
 }
 
 #[derive(Debug, Serialize)]
 struct Choice {
     index: usize,
     message: AssistantMessage,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logprobs: CompatNull,
     finish_reason: String,
 }
 
 #[derive(Debug, Serialize)]
 struct AssistantMessage {
     role: &'static str,
+    #[serde(skip_serializing_if = "OrNull::is_omitted")]
+    content: OrNull<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    content: Option<String>,
+    refusal: CompatNull,
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     tool_calls: Vec<ToolCall>,
+    /// Always empty: Codex's URL citations are not passed on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    annotations: Option<[(); 0]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    audio: CompatNull,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    function_call: CompatNull,
     #[serde(skip_serializing_if = "Option::is_none")]
     reasoning: Option<AssistantReasoning>,
 }
@@ -66,6 +131,33 @@ pub struct Usage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
+    /// The counts exactly as Codex reported them, for `--usage-extended`.
+    #[serde(skip)]
+    pub codex: Option<TokenUsage>,
+    /// OpenAI's per-kind breakdown, sent under `--compat-nulls`.
+    #[serde(flatten)]
+    pub details: Option<UsageDetails>,
+}
+
+/// `prompt_tokens_details` and `completion_tokens_details`; the kinds Codex does not count are 0.
+#[derive(Debug, Serialize, Clone, Copy, Default)]
+pub struct UsageDetails {
+    prompt_tokens_details: PromptTokensDetails,
+    completion_tokens_details: CompletionTokensDetails,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, Default)]
+struct PromptTokensDetails {
+    cached_tokens: u32,
+    audio_tokens: u32,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, Default)]
+struct CompletionTokensDetails {
+    reasoning_tokens: u32,
+    audio_tokens: u32,
+    accepted_prediction_tokens: u32,
+    rejected_prediction_tokens: u32,
 }
 
 impl From<TokenUsage> for Usage {
@@ -78,6 +170,49 @@ impl From<TokenUsage> for Usage {
             prompt_tokens: clamp(value.input_tokens + value.cached_input_tokens),
             completion_tokens: clamp(value.output_tokens + value.reasoning_output_tokens),
             total_tokens: clamp(value.total_tokens),
+            codex: Some(value),
+            details: None,
+        }
+    }
+}
+
+impl Usage {
+    /// Adds `other`'s counts, for a reply that took several completions.
+    pub fn add(&mut self, other: &Usage) {
+        self.prompt_tokens = self.prompt_tokens.saturating_add(other.prompt_tokens);
+        self.completion_tokens = self
+            .completion_tokens
+            .saturating_add(other.completion_tokens);
+        self.total_tokens = self.total_tokens.saturating_add(other.total_tokens);
+        self.codex = match (self.codex.take(), other.codex.as_ref()) {
+            (Some(mine), Some(theirs)) => Some(TokenUsage {
+                input_tokens: mine.input_tokens.saturating_add(theirs.input_tokens),
+                cached_input_tokens: mine
+                    .cached_input_tokens
+                    .saturating_add(theirs.cached_input_tokens),
+                output_tokens: mine.output_tokens.saturating_add(theirs.output_tokens),
+                reasoning_output_tokens: mine
+                    .reasoning_output_tokens
+                    .saturating_add(theirs.reasoning_output_tokens),
+                total_tokens: mine.total_tokens.saturating_add(theirs.total_tokens),
+            }),
+            (mine, theirs) => mine.or_else(|| theirs.cloned()),
+        };
+    }
+
+    /// The breakdown OpenAI reports, from Codex's raw counts when it gave them.
+    fn breakdown(&self) -> UsageDetails {
+        let clamp = |v: i64| if v <= 0 { 0 } else { v as u32 };
+        let codex = self.codex.as_ref();
+        UsageDetails {
+            prompt_tokens_details: PromptTokensDetails {
+                cached_tokens: codex.map_or(0, |raw| clamp(raw.cached_input_tokens)),
+                audio_tokens: 0,
+            },
+            completion_tokens_details: CompletionTokensDetails {
+                reasoning_tokens: codex.map_or(0, |raw| clamp(raw.reasoning_output_tokens)),
+                ..CompletionTokensDetails::default()
+            },
         }
     }
 }
@@ -119,12 +254,194 @@ impl ChatCompletionResponse {
                 finish_reason: finish_reason.to_string(),
                 message: AssistantMessage {
                     role: "assistant",
-                    content,
+                    content: OrNull::new(content),
+                    refusal: None,
                     tool_calls,
+                    annotations: None,
+                    audio: None,
+                    function_call: None,
                     reasoning,
                 },
+                logprobs: None,
             }],
             usage,
+            codex_usage: None,
+            resumed: false,
+            tool_call_overflow: None,
+            codex_selection: None,
+            codex_requested_model: None,
+            codex_resolved_model: None,
+            service_tier: None,
+            system_fingerprint: None,
+        }
+    }
+
+    pub fn usage(&self) -> &Usage {
+        &self.usage
+    }
+
+    /// Adds the `codex_usage` extension when Codex reported raw counts.
+    pub fn include_codex_usage(&mut self) {
+        self.codex_usage = self.usage.codex.clone();
+    }
+
+    /// Writes the token breakdowns, with zero counts where Codex reports none.
+    pub fn include_usage_details(&mut self) {
+        self.usage.details = Some(self.usage.breakdown());
+    }
+
+    /// Writes every key a real OpenAI completion carries, as `null` (or zero counts) where Codex
+    /// has nothing to put there (`--compat-nulls`).
+    pub fn include_compat_nulls(&mut self) {
+        self.service_tier = Some(());
+        self.system_fingerprint = Some(());
+        self.include_usage_details();
+        for choice in &mut self.choices {
+            choice.logprobs = Some(());
+            let message = &mut choice.message;
+            message.content.null = true;
+            message.refusal = Some(());
+            message.annotations = Some([]);
+            message.audio = Some(());
+            message.function_call = Some(());
+        }
+    }
+
+    /// Makes this the reply picked among several samples: `usage` covers all of them.
+    pub fn set_sample_selection(&mut self, usage: Usage, selection: SampleSelection) {
+        self.usage = usage;
+        self.codex_selection = Some(selection);
+    }
+
+    /// Drops the reasoning summary from the reply (`--hide-reasoning`).
+    pub fn hide_reasoning(&mut self) {
+        for choice in &mut self.choices {
+            choice.message.reasoning = None;
+        }
+    }
+
+    /// Gives the reply the request's `id` and `created`, the ones its stream would have carried.
+    pub fn stamp(&mut self, clock: &RequestClock) {
+        self.id = clock.id().to_string();
+        self.created = clock.created();
+    }
+
+    pub fn mark_resumed(&mut self) {
+        self.resumed = true;
+    }
+
+    pub fn set_tool_call_overflow(&mut self, overflow: Option<ToolCallOverflow>) {
+        self.tool_call_overflow = overflow;
+    }
+
+    /// Records the model the executor ran the request on, reported per `--report-model`.
+    pub fn set_resolved_model(&mut self, model: Option<String>) {
+        self.codex_resolved_model = model;
+    }
+
+    /// Puts the model `report` picks in `model` and the other in its vendor field. A reply whose
+    /// executor named no resolved model reports the requested one as both.
+    pub fn report_model(&mut self, report: Option<ReportModel>) {
+        let requested = std::mem::take(&mut self.model);
+        let resolved = self.codex_resolved_model.take();
+        let reported = ReportedModel::new(requested, resolved, report);
+        self.model = reported.model;
+        self.codex_requested_model = reported.codex_requested_model;
+        self.codex_resolved_model = reported.codex_resolved_model;
+    }
+
+    pub fn finish_reason(&self) -> Option<&str> {
+        self.choices
+            .first()
+            .map(|choice| choice.finish_reason.as_str())
+    }
+
+    pub fn content(&self) -> Option<&str> {
+        self.choices.first()?.message.content.value.as_deref()
+    }
+
+    pub fn tool_calls(&self) -> &[ToolCall] {
+        self.choices
+            .first()
+            .map_or(&[], |choice| choice.message.tool_calls.as_slice())
+    }
+
+    /// Gives the tool calls the names the client declared (`--sanitize-tool-names`).
+    pub fn restore_tool_names(&mut self, names: &ToolNames) {
+        for choice in &mut self.choices {
+            for call in &mut choice.message.tool_calls {
+                call.function.name = names.restore(&call.function.name).to_string();
+            }
+        }
+    }
+
+    /// Replaces the tool calls with a description of them in the content, finishing with `stop`
+    /// (`--tool-call-fallback describe`).
+    pub fn describe_tool_calls(&mut self) {
+        let Some(choice) = self.choices.first_mut() else {
+            return;
+        };
+        if choice.message.tool_calls.is_empty() {
+            return;
+        }
+        let description = tool_call_description(&std::mem::take(&mut choice.message.tool_calls));
+        choice.message.content.value = Some(match choice.message.content.value.take() {
+            Some(text) => format!("{text}\n\n{description}"),
+            None => description,
+        });
+        choice.finish_reason = "stop".to_string();
+    }
+
+    /// Reasoning summary parts joined with newlines, if the model reported any.
+    pub fn reasoning_summary(&self) -> Option<String> {
+        let reasoning = self.choices.first()?.message.reasoning.as_ref()?;
+        let parts: Vec<&str> = reasoning
+            .summary
+            .iter()
+            .map(|part| part.text.as_str())
+            .collect();
+        (!parts.is_empty()).then(|| parts.join("\n"))
+    }
+}
+
+/// The `model` a reply reports and the vendor field naming the other model (`--report-model`),
+/// for the translators to flatten into their records.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ReportedModel {
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub codex_requested_model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub codex_resolved_model: Option<String>,
+}
+
+impl ReportedModel {
+    /// For a request for `requested` that ran on `resolved`; an executor that named no resolved
+    /// model ran the requested one. With a `report`, the other name is in its vendor field even
+    /// when both are the same; without one, replies keep OpenAI's field set.
+    pub fn new(requested: String, resolved: Option<String>, report: Option<ReportModel>) -> Self {
+        let Some(report) = report else {
+            return Self::unresolved(requested);
+        };
+        let resolved = resolved.unwrap_or_else(|| requested.clone());
+        let (model, codex_requested_model, codex_resolved_model) = match report {
+            ReportModel::Requested => (requested, None, Some(resolved)),
+            ReportModel::Resolved => (resolved, Some(requested), None),
+        };
+        Self {
+            model,
+            codex_requested_model,
+            codex_resolved_model,
+        }
+    }
+
+    /// A name reported as sent, without a vendor field: one no request ran on yet, or any name
+    /// without `--report-model`.
+    pub fn unresolved(model: String) -> Self {
+        Self {
+            model,
+            codex_requested_model: None,
+            codex_resolved_model: None,
         }
     }
 }
@@ -139,6 +456,46 @@ impl ToolCall {
     }
 }
 
+/// One line per attempted call, e.g. ``The model attempted to call `web_search` with query
+/// "rust".`` A call streamed in several pieces is described once, with its final arguments.
+pub fn tool_call_description(calls: &[ToolCall]) -> String {
+    let mut latest: Vec<&ToolCall> = Vec::new();
+    for call in calls {
+        match latest.iter_mut().find(|seen| seen.id == call.id) {
+            Some(seen) => *seen = call,
+            None => latest.push(call),
+        }
+    }
+    latest
+        .iter()
+        .map(|call| {
+            let name = &call.function.name;
+            match describe_arguments(&call.function.arguments) {
+                Some(arguments) => {
+                    format!("The model attempted to call `{name}` with {arguments}.")
+                }
+                None => format!("The model attempted to call `{name}`."),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// `key value` pairs for an object of arguments, the raw text for anything else.
+fn describe_arguments(arguments: &str) -> Option<String> {
+    let arguments = arguments.trim();
+    match serde_json::from_str::<serde_json::Value>(arguments) {
+        Ok(serde_json::Value::Object(map)) => {
+            let pairs: Vec<String> = map
+                .iter()
+                .map(|(key, value)| format!("{key} {value}"))
+                .collect();
+            (!pairs.is_empty()).then(|| pairs.join(", "))
+        }
+        _ => (!arguments.is_empty()).then(|| arguments.to_string()),
+    }
+}
+
 impl AssistantReasoning {
     pub fn from_summary_parts(parts: Vec<String>) -> Option<Self> {
         if parts.is_empty() {
@@ -164,3 +521,545 @@ impl ReasoningTracePart {
         Self { kind: "text", text }
     }
 }
+
+/// The parts of a stream's chunks that stay the same from one delta to the next, built once per
+/// stream so each chunk only borrows them.
+#[derive(Debug)]
+pub struct ChunkTemplate {
+    id: String,
+    created: i64,
+    model: ReportedModel,
+    compat_nulls: bool,
+    usage_details: bool,
+}
+
+/// One `chat.completion.chunk` event. Fields are declared in the order the earlier
+/// `Value`-built chunks serialized them (sorted), so the bytes on the wire are unchanged.
+#[derive(Debug, Serialize)]
+pub struct ChatCompletionChunk<'a> {
+    choices: [ChunkChoice<'a>; 1],
+    /// Vendor extensions on the finish chunk checking the stream's content
+    /// (`--stream-integrity`): see [`ContentDigest`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    codex_content_length: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    codex_content_sha256: Option<&'a str>,
+    /// Vendor extensions naming the model `model` does not: see [`ChatCompletionResponse`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    codex_requested_model: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    codex_resolved_model: Option<&'a str>,
+    /// Vendor extension carrying Codex's raw token counts (`--usage-extended`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    codex_usage: Option<serde_json::Value>,
+    created: i64,
+    id: &'a str,
+    model: &'a str,
+    object: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    service_tier: CompatNull,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_fingerprint: CompatNull,
+    /// Vendor extension on the finish chunk: see [`ChatCompletionResponse`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_overflow: Option<ToolCallOverflow>,
+    #[serde(skip_serializing_if = "OrNull::is_omitted")]
+    usage: OrNull<ChunkUsage>,
+    #[serde(skip)]
+    usage_details: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ChunkChoice<'a> {
+    delta: ChunkDelta<'a>,
+    finish_reason: Option<&'a str>,
+    index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logprobs: CompatNull,
+}
+
+/// What a chunk adds to the message; an empty delta serializes as `{}`.
+#[derive(Debug, Default, Serialize)]
+pub struct ChunkDelta<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning: Option<ChunkReasoning<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refusal: CompatNull,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_calls: Option<[ChunkToolCall<'a>; 1]>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct ChunkReasoning<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<[ChunkText<'a>; 1]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<[ChunkText<'a>; 1]>,
+}
+
+#[derive(Debug, Serialize)]
+struct ChunkText<'a> {
+    text: &'a str,
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+/// A tool call delta. Only a call's first delta carries its `id`, `type` and `name`: clients
+/// append every string field of later deltas to the call at `index`.
+#[derive(Debug, Serialize)]
+struct ChunkToolCall<'a> {
+    function: ChunkToolCallFunction<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<&'a str>,
+    index: usize,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    call_type: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+struct ChunkToolCallFunction<'a> {
+    arguments: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+}
+
+/// [`Usage`] with its fields in chunk order.
+#[derive(Debug, Serialize)]
+struct ChunkUsage {
+    completion_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    completion_tokens_details: Option<CompletionTokensDetails>,
+    prompt_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prompt_tokens_details: Option<PromptTokensDetails>,
+    total_tokens: u32,
+}
+
+impl ChunkTemplate {
+    /// Chunks of the reply `clock` stamps.
+    pub fn new(clock: &RequestClock, model: String) -> Self {
+        Self {
+            id: clock.id().to_string(),
+            created: clock.created(),
+            model: ReportedModel::unresolved(model),
+            compat_nulls: false,
+            usage_details: false,
+        }
+    }
+
+    /// Names the model per `--report-model` once the executor says what the request runs on,
+    /// with the other name in a vendor field on every chunk.
+    pub fn with_report_model(
+        mut self,
+        resolved: Option<String>,
+        report: Option<ReportModel>,
+    ) -> Self {
+        let requested = std::mem::take(&mut self.model.model);
+        self.model = ReportedModel::new(requested, resolved, report);
+        self
+    }
+
+    /// Makes every chunk carry the keys a real OpenAI chunk does (`--compat-nulls`).
+    pub fn with_compat_nulls(mut self, enabled: bool) -> Self {
+        self.compat_nulls = enabled;
+        self
+    }
+
+    /// Makes the usage chunk carry the token breakdowns even without `--compat-nulls`.
+    pub fn with_usage_details(mut self, enabled: bool) -> Self {
+        self.usage_details = enabled;
+        self
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model.model
+    }
+
+    pub fn chunk<'a>(
+        &'a self,
+        mut delta: ChunkDelta<'a>,
+        finish_reason: Option<&'a str>,
+    ) -> ChatCompletionChunk<'a> {
+        let compat = self.compat_nulls.then_some(());
+        if self.compat_nulls && delta.role.is_some() {
+            // OpenAI opens every stream with an empty content and no refusal.
+            delta.content.get_or_insert("");
+            delta.refusal = Some(());
+        }
+        ChatCompletionChunk {
+            choices: [ChunkChoice {
+                delta,
+                finish_reason,
+                index: 0,
+                logprobs: compat,
+            }],
+            codex_content_length: None,
+            codex_content_sha256: None,
+            codex_requested_model: self.model.codex_requested_model.as_deref(),
+            codex_resolved_model: self.model.codex_resolved_model.as_deref(),
+            codex_usage: None,
+            created: self.created,
+            id: &self.id,
+            model: &self.model.model,
+            object: "chat.completion.chunk",
+            service_tier: compat,
+            system_fingerprint: compat,
+            tool_call_overflow: None,
+            usage: OrNull {
+                value: None,
+                null: self.compat_nulls,
+            },
+            usage_details: self.compat_nulls || self.usage_details,
+        }
+    }
+}
+
+impl<'a> ChatCompletionChunk<'a> {
+    pub fn with_usage(mut self, usage: &Usage) -> Self {
+        let details = self.usage_details.then(|| usage.breakdown());
+        self.usage.value = Some(ChunkUsage {
+            completion_tokens: usage.completion_tokens,
+            completion_tokens_details: details.map(|d| d.completion_tokens_details),
+            prompt_tokens: usage.prompt_tokens,
+            prompt_tokens_details: details.map(|d| d.prompt_tokens_details),
+            total_tokens: usage.total_tokens,
+        });
+        self
+    }
+
+    pub fn with_tool_call_overflow(mut self, overflow: Option<ToolCallOverflow>) -> Self {
+        self.tool_call_overflow = overflow;
+        self
+    }
+
+    /// Adds the `codex_usage` extension when Codex reported raw counts. It goes through a
+    /// `Value` so its keys are sorted like the rest of the chunk; it is sent once per stream.
+    pub fn with_codex_usage(mut self, usage: &Usage) -> Self {
+        self.codex_usage = usage
+            .codex
+            .as_ref()
+            .and_then(|raw| serde_json::to_value(raw).ok());
+        self
+    }
+
+    pub fn with_content_integrity(mut self, integrity: Option<&'a ContentIntegrity>) -> Self {
+        self.codex_content_length = integrity.map(|integrity| integrity.length);
+        self.codex_content_sha256 = integrity.map(|integrity| integrity.sha256.as_str());
+        self
+    }
+}
+
+/// Running SHA-256 and byte count of the `delta.content` strings a stream sent, in order;
+/// reasoning and tool-call deltas are left out. Hashing the concatenation is hashing the pieces,
+/// so a client can check the text it reassembled however the chunks were split.
+#[derive(Clone, Default)]
+pub struct ContentDigest {
+    hasher: Sha256,
+    length: usize,
+}
+
+impl ContentDigest {
+    pub fn update(&mut self, content: &str) {
+        self.hasher.update(content.as_bytes());
+        self.length += content.len();
+    }
+
+    pub fn finish(self) -> ContentIntegrity {
+        ContentIntegrity {
+            sha256: format!("{:x}", self.hasher.finalize()),
+            length: self.length,
+        }
+    }
+}
+
+/// What [`ContentDigest`] advertises at the end of a stream.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ContentIntegrity {
+    pub sha256: String,
+    pub length: usize,
+}
+
+impl<'a> ChunkDelta<'a> {
+    pub fn role() -> Self {
+        Self {
+            role: Some("assistant"),
+            ..Self::default()
+        }
+    }
+
+    pub fn content(text: &'a str) -> Self {
+        Self {
+            content: Some(text),
+            ..Self::default()
+        }
+    }
+
+    pub fn reasoning_summary(text: &'a str) -> Self {
+        Self {
+            reasoning: Some(ChunkReasoning {
+                summary: Some([ChunkText::new(text)]),
+                ..ChunkReasoning::default()
+            }),
+            ..Self::default()
+        }
+    }
+
+    pub fn reasoning_content(text: &'a str) -> Self {
+        Self {
+            reasoning: Some(ChunkReasoning {
+                content: Some([ChunkText::new(text)]),
+                ..ChunkReasoning::default()
+            }),
+            ..Self::default()
+        }
+    }
+
+    /// The first piece of the tool call at `index`: its id and name with `arguments`, the part
+    /// of its arguments not streamed yet.
+    pub fn tool_call(index: usize, call: &'a ToolCall, arguments: &'a str) -> Self {
+        Self {
+            tool_calls: Some([ChunkToolCall {
+                function: ChunkToolCallFunction {
+                    arguments,
+                    name: Some(&call.function.name),
+                },
+                id: Some(&call.id),
+                index,
+                call_type: Some(call.call_type),
+            }]),
+            ..Self::default()
+        }
+    }
+
+    /// A later piece of the tool call at `index`: only more of its arguments.
+    pub fn tool_call_arguments(index: usize, arguments: &'a str) -> Self {
+        Self {
+            tool_calls: Some([ChunkToolCall {
+                function: ChunkToolCallFunction {
+                    arguments,
+                    name: None,
+                },
+                id: None,
+                index,
+                call_type: None,
+            }]),
+            ..Self::default()
+        }
+    }
+}
+
+impl<'a> ChunkText<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text, kind: "text" }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    /// The chunk as it was built before the typed structs, as a `Value`.
+    fn legacy_chunk(
+        template: &ChunkTemplate,
+        delta: Value,
+        finish_reason: Option<&str>,
+        usage: Option<&Usage>,
+    ) -> Value {
+        let mut payload = json!({
+            "id": template.id(),
+            "object": "chat.completion.chunk",
+            "created": template.created,
+            "model": template.model(),
+            "choices": [{
+                "index": 0,
+                "delta": delta,
+                "finish_reason": finish_reason,
+            }],
+        });
+        if let Some(usage) = usage {
+            payload["usage"] = json!({
+                "prompt_tokens": usage.prompt_tokens,
+                "completion_tokens": usage.completion_tokens,
+                "total_tokens": usage.total_tokens,
+            });
+        }
+        payload
+    }
+
+    fn assert_same_bytes(chunk: ChatCompletionChunk<'_>, legacy: Value) {
+        assert_eq!(
+            serde_json::to_string(&chunk).unwrap(),
+            serde_json::to_string(&legacy).unwrap()
+        );
+    }
+
+    #[test]
+    fn chunks_serialize_like_the_value_built_ones() {
+        let template = ChunkTemplate::new(&RequestClock::start(), "gpt-5".into());
+        assert_same_bytes(
+            template.chunk(ChunkDelta::role(), None),
+            legacy_chunk(&template, json!({"role": "assistant"}), None, None),
+        );
+        assert_same_bytes(
+            template.chunk(ChunkDelta::content("héllo \"world\"\n"), None),
+            legacy_chunk(
+                &template,
+                json!({"content": "héllo \"world\"\n"}),
+                None,
+                None,
+            ),
+        );
+        assert_same_bytes(
+            template.chunk(ChunkDelta::reasoning_summary("thinking"), None),
+            legacy_chunk(
+                &template,
+                json!({"reasoning": {"summary": [{"type": "text", "text": "thinking"}]}}),
+                None,
+                None,
+            ),
+        );
+        assert_same_bytes(
+            template.chunk(ChunkDelta::reasoning_content("step"), None),
+            legacy_chunk(
+                &template,
+                json!({"reasoning": {"content": [{"type": "text", "text": "step"}]}}),
+                None,
+                None,
+            ),
+        );
+
+        let call = ToolCall::new("call_1".into(), "lookup".into(), r#"{"q":"x"}"#.into());
+        assert_same_bytes(
+            template.chunk(ChunkDelta::tool_call(2, &call, r#""x"}"#), None),
+            legacy_chunk(
+                &template,
+                json!({"tool_calls": [{
+                    "index": 2,
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {"name": "lookup", "arguments": r#""x"}"#},
+                }]}),
+                None,
+                None,
+            ),
+        );
+        assert_same_bytes(
+            template.chunk(ChunkDelta::tool_call_arguments(2, "}"), None),
+            legacy_chunk(
+                &template,
+                json!({"tool_calls": [{"index": 2, "function": {"arguments": "}"}}]}),
+                None,
+                None,
+            ),
+        );
+
+        assert_same_bytes(
+            template.chunk(ChunkDelta::default(), Some("error")),
+            legacy_chunk(&template, json!({}), Some("error"), None),
+        );
+
+        let usage = Usage::from(TokenUsage {
+            input_tokens: 10,
+            cached_input_tokens: 4,
+            output_tokens: 5,
+            reasoning_output_tokens: 2,
+            total_tokens: 21,
+        });
+        assert_same_bytes(
+            template
+                .chunk(ChunkDelta::default(), Some("stop"))
+                .with_usage(&usage),
+            legacy_chunk(&template, json!({}), Some("stop"), Some(&usage)),
+        );
+        let mut legacy = legacy_chunk(&template, json!({}), Some("tool_calls"), Some(&usage));
+        legacy["codex_usage"] = json!(usage.codex);
+        assert_same_bytes(
+            template
+                .chunk(ChunkDelta::default(), Some("tool_calls"))
+                .with_usage(&usage)
+                .with_codex_usage(&usage),
+            legacy,
+        );
+    }
+
+    #[test]
+    fn compat_nulls_write_content_null_only_when_asked() {
+        let call = ToolCall::new("call_1".into(), "lookup".into(), "{}".into());
+        let build = || {
+            ChatCompletionResponse::with_metadata(
+                "gpt-5".into(),
+                None,
+                vec![call.clone()],
+                "tool_calls",
+                "resp_1".into(),
+                Usage::default(),
+                None,
+            )
+        };
+        let compact = serde_json::to_value(build()).unwrap();
+        assert!(compact["choices"][0]["message"].get("content").is_none());
+        assert!(compact["usage"].get("prompt_tokens_details").is_none());
+
+        let mut response = build();
+        response.include_compat_nulls();
+        let compat = serde_json::to_value(&response).unwrap();
+        let message = &compat["choices"][0]["message"];
+        for key in ["content", "refusal", "audio", "function_call"] {
+            assert_eq!(message.get(key), Some(&Value::Null), "{key}");
+        }
+        assert_eq!(message["annotations"], json!([]));
+        assert_eq!(compat["choices"][0].get("logprobs"), Some(&Value::Null));
+        assert_eq!(
+            compat["usage"]["prompt_tokens_details"],
+            json!({"cached_tokens": 0, "audio_tokens": 0})
+        );
+    }
+
+    #[test]
+    fn the_other_model_goes_in_a_vendor_field() {
+        let report = |resolved: Option<&str>, report| {
+            let mut response = ChatCompletionResponse::stub("gpt-5:high".into(), "hi".into());
+            response.set_resolved_model(resolved.map(str::to_string));
+            response.report_model(report);
+            let value = serde_json::to_value(&response).unwrap();
+            (
+                value["model"].clone(),
+                value.get("codex_requested_model").cloned(),
+                value.get("codex_resolved_model").cloned(),
+            )
+        };
+        assert_eq!(
+            report(Some("gpt-5"), Some(ReportModel::Requested)),
+            (json!("gpt-5:high"), None, Some(json!("gpt-5")))
+        );
+        assert_eq!(
+            report(Some("gpt-5"), Some(ReportModel::Resolved)),
+            (json!("gpt-5"), Some(json!("gpt-5:high")), None)
+        );
+        assert_eq!(
+            report(None, Some(ReportModel::Resolved)),
+            (json!("gpt-5:high"), Some(json!("gpt-5:high")), None)
+        );
+        assert_eq!(
+            report(Some("gpt-5"), None),
+            (json!("gpt-5:high"), None, None)
+        );
+
+        // Chunks carry the vendor field in sorted key order too.
+        let template = ChunkTemplate::new(&RequestClock::start(), "gpt-5:high".into())
+            .with_report_model(Some("gpt-5".into()), Some(ReportModel::Resolved));
+        let mut legacy = legacy_chunk(&template, json!({"content": "hi"}), None, None);
+        legacy["codex_requested_model"] = json!("gpt-5:high");
+        assert_eq!(template.model(), "gpt-5");
+        assert_same_bytes(template.chunk(ChunkDelta::content("hi"), None), legacy);
+    }
+}
@@ -0,0 +1,202 @@
+//! `codex.samples`: one chat request run as several concurrent completions, of which one reply
+//! is returned, picked by `codex.select`. The reply's usage covers every sample, and its
+//! `codex_selection` extension says why it won and how long the discarded replies were.
+
+use futures_util::future::join_all;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::{
+    executor::SharedChatExecutor,
+    response::{ChatCompletionResponse, Usage},
+};
+use crate::{
+    error::ApiError,
+    openai::{
+        chat::{PromptPayload, Sampling, Selection},
+        warnings::Warnings,
+    },
+};
+
+/// How the reply was picked, as the `codex_selection` vendor extension reports it.
+#[derive(Debug, Clone, Serialize)]
+pub struct SampleSelection {
+    pub select: Selection,
+    pub samples: usize,
+    /// Samples whose completion failed; they had no say in the pick.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub failed: usize,
+    pub reason: String,
+    /// Character counts of the replies not returned, in sample order.
+    pub discarded_lengths: Vec<usize>,
+}
+
+fn is_zero(count: &usize) -> bool {
+    *count == 0
+}
+
+/// Runs `payload` `sampling.samples` times at once and answers with the reply `sampling.select`
+/// picks. Fails only when every sample does, with the first sample's error.
+pub(super) async fn complete(
+    engine: SharedChatExecutor,
+    payload: PromptPayload,
+    sampling: Sampling,
+) -> Result<ChatCompletionResponse, ApiError> {
+    // Each sample warns on its own list; the request's gets each warning once.
+    let samples: Vec<Warnings> = (0..sampling.samples).map(|_| Warnings::default()).collect();
+    let runs = samples.iter().map(|warnings| {
+        let mut sample = payload.clone();
+        sample.warnings = warnings.clone();
+        engine.complete(sample)
+    });
+    let results = join_all(runs).await;
+    for warnings in &samples {
+        payload.warnings.merge(warnings);
+    }
+    let mut replies = Vec::with_capacity(sampling.samples);
+    let mut first_error = None;
+    for result in results {
+        match result {
+            Ok(reply) => replies.push(reply),
+            Err(err) => {
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+    if let Some(err) = first_error.filter(|_| replies.is_empty()) {
+        return Err(err);
+    }
+
+    let texts: Vec<&str> = replies
+        .iter()
+        .map(|reply| reply.content().unwrap_or_default())
+        .collect();
+    let (index, reason) = pick(sampling.select, &texts);
+    let discarded_lengths = texts
+        .iter()
+        .enumerate()
+        .filter(|(other, _)| *other != index)
+        .map(|(_, text)| text.chars().count())
+        .collect();
+    let mut usage = Usage::default();
+    for reply in &replies {
+        usage.add(reply.usage());
+    }
+    let selection = SampleSelection {
+        select: sampling.select,
+        samples: sampling.samples,
+        failed: sampling.samples - replies.len(),
+        reason,
+        discarded_lengths,
+    };
+    let mut reply = replies.swap_remove(index);
+    reply.set_sample_selection(usage, selection);
+    Ok(reply)
+}
+
+/// The index of the reply `select` picks among `texts`, with the reason. Ties go to the earlier
+/// sample.
+fn pick(select: Selection, texts: &[&str]) -> (usize, String) {
+    let total = texts.len();
+    match select {
+        Selection::Majority => {
+            let keys: Vec<String> = texts.iter().map(|text| normalize(text)).collect();
+            let (index, votes) = keys
+                .iter()
+                .enumerate()
+                .map(|(index, key)| (index, keys.iter().filter(|other| *other == key).count()))
+                .fold((0, 0), |best, candidate| {
+                    if candidate.1 > best.1 {
+                        candidate
+                    } else {
+                        best
+                    }
+                });
+            if votes > 1 {
+                (index, format!("{votes} of {total} samples agreed"))
+            } else {
+                (
+                    0,
+                    format!("no two of {total} samples agreed; kept the first"),
+                )
+            }
+        }
+        Selection::FirstValidJson => {
+            match texts
+                .iter()
+                .position(|text| serde_json::from_str::<Value>(text.trim()).is_ok())
+            {
+                Some(index) => (
+                    index,
+                    format!(
+                        "sample {} of {total} was the first to parse as JSON",
+                        index + 1
+                    ),
+                ),
+                None => (
+                    0,
+                    format!("none of {total} samples parsed as JSON; kept the first"),
+                ),
+            }
+        }
+        Selection::Longest => {
+            let (index, length) = texts
+                .iter()
+                .map(|text| text.chars().count())
+                .enumerate()
+                .fold((0, 0), |best, candidate| {
+                    if candidate.1 > best.1 {
+                        candidate
+                    } else {
+                        best
+                    }
+                });
+            (
+                index,
+                format!("the longest of {total} samples ({length} characters)"),
+            )
+        }
+    }
+}
+
+/// What majority voting compares: the text without case or runs of whitespace.
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn majority_compares_normalized_text() {
+        let (index, reason) = pick(Selection::Majority, &["41", "The  answer", "the answer\n"]);
+        assert_eq!(index, 1);
+        assert_eq!(reason, "2 of 3 samples agreed");
+
+        let (index, reason) = pick(Selection::Majority, &["a", "b", "c"]);
+        assert_eq!(index, 0);
+        assert!(reason.starts_with("no two of 3"), "{reason}");
+    }
+
+    #[test]
+    fn first_valid_json_skips_prose() {
+        let texts = ["Sure! Here it is:", " {\"city\": \"Paris\"} ", "[1, 2]"];
+        assert_eq!(pick(Selection::FirstValidJson, &texts).0, 1);
+        let (index, reason) = pick(Selection::FirstValidJson, &["no", "nope"]);
+        assert_eq!(index, 0);
+        assert!(reason.contains("kept the first"), "{reason}");
+    }
+
+    #[test]
+    fn longest_counts_characters_and_keeps_the_earlier_on_ties() {
+        assert_eq!(pick(Selection::Longest, &["ab", "été!", "abcd"]).0, 1);
+        assert_eq!(
+            pick(Selection::Longest, &["ab", "abc", "xyz"]),
+            (1, "the longest of 3 samples (3 characters)".to_string())
+        );
+    }
+}
@@ -1,90 +1,583 @@
-use std::sync::Arc;
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
 
 use anyhow::{Context, Result};
 use codex_app_server_protocol::AuthMode;
 use codex_core::{
-    auth::{AuthCredentialsStoreMode, AuthManager},
+    auth::{AuthCredentialsStoreMode, AuthManager, login_with_api_key},
     config::{Config, ConfigOverrides, find_codex_home},
 };
 
-use crate::{error::ApiError, serve_config::web_search_request_override};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::{
+    error::ApiError,
+    openai::chat::PromptPayload,
+    serve_config::{ServeConfig, effective_config},
+};
 
-use super::executor::{MockChatExecutor, RealChatExecutor, SharedChatExecutor};
+use super::{
+    access_log::AccessLog,
+    budget::{self, BudgetCharge, TokenBudgets},
+    cache_gc::{self, CacheGc},
+    capture::CaptureSink,
+    config_load::{LoadedConfig, load_config},
+    conversations::{self, ConversationTurn, Conversations},
+    degraded::{DegradedExecutor, DegradedStartup},
+    executor::{
+        HideReasoning, MockChatExecutor, RealChatExecutor, ReloadOutcome, RestoreToolNames,
+        SharedChatExecutor,
+    },
+    fairness::{ClientId, ClientLimiter},
+    health::UpstreamHealth,
+    idempotency::IdempotencyKeys,
+    in_flight::{InFlightRequests, TrackedRequest},
+    keepalive::{Keepalive, KeepaliveStatus},
+    latency::{LatencyStats, ObserveLatency},
+    listeners::ListenerInfo,
+    loaded::{LoadedModels, ModelExpiry},
+    metrics::{ServerMetrics, UsageAccount},
+    middleware::current_request_id,
+    profiles::ProfileCatalog,
+};
 use toml::Value as TomlValue;
 
+/// `executor` as the server runs it under `config`: observed for `/stats/latency`, with declared
+/// tool names restored and, under `--hide-reasoning`, its reasoning dropped.
+fn wrap_engine(
+    executor: SharedChatExecutor,
+    latency: &Arc<LatencyStats>,
+    config: &ServeConfig,
+) -> SharedChatExecutor {
+    let mut engine: SharedChatExecutor = Arc::new(ObserveLatency {
+        inner: executor,
+        stats: Arc::clone(latency),
+    });
+    if config.hide_reasoning {
+        engine = Arc::new(HideReasoning(engine));
+    }
+    Arc::new(RestoreToolNames(engine))
+}
+
 /// Shared application state for the Axum router.
 #[derive(Clone)]
 pub struct AppState {
     auth: AuthController,
+    /// The executor as given; [`Self::with_config`] wraps it again for the new settings.
+    executor: SharedChatExecutor,
+    /// `executor` wrapped by [`wrap_engine`] for `config`.
     engine: SharedChatExecutor,
-    web_search_enabled: bool,
+    /// Shared so `/admin/reload` can update it for every clone of the state.
+    web_search_enabled: Arc<AtomicBool>,
+    metrics: Arc<ServerMetrics>,
+    loaded_models: Arc<LoadedModels>,
+    idempotency: Arc<IdempotencyKeys>,
+    conversations: Arc<Conversations>,
+    /// Tokens booked per client for `--max-tokens-per-hour`.
+    budgets: Arc<TokenBudgets>,
+    /// Recent upstream outcomes for `/healthz`.
+    upstream: Arc<UpstreamHealth>,
+    /// Time to first token and tokens per second per model for `/stats/latency`.
+    latency: Arc<LatencyStats>,
+    /// Chat requests running now, for `/admin/requests`.
+    in_flight: Arc<InFlightRequests>,
+    profiles: Arc<ProfileCatalog>,
+    capture: Option<CaptureSink>,
+    playground: bool,
+    admin: bool,
+    client_limiter: Option<Arc<ClientLimiter>>,
+    /// What the server is bound to, for `/healthz`; set once serving starts.
+    listeners: Arc<[ListenerInfo]>,
+    /// `--keepalive-interval` task; shared so any clone can stop it.
+    keepalive: Arc<Keepalive>,
+    /// `--cache-idle-ttl` task; shared so any clone can stop it.
+    cache_gc: Arc<CacheGc>,
+    config: Arc<ServeConfig>,
+    /// Config overrides ignored at startup, for `/healthz`.
+    config_warnings: Arc<[String]>,
+}
+
+/// Inputs for [`AppState::initialize_with`].
+#[derive(Clone, Debug, Default)]
+pub struct InitOptions {
+    /// Codex home holding the login and `config.toml` profiles; discovered like the Codex CLI
+    /// does when unset.
+    pub codex_home: Option<PathBuf>,
+    /// Extra `key.path = value` overrides applied on top of `config.toml`.
+    pub cli_overrides: Vec<(String, TomlValue)>,
+    pub config: ServeConfig,
 }
 
 impl AppState {
-    /// Loads the Codex configuration and constructs the backing executor.
+    /// Loads the Codex configuration and constructs the backing executor, using the process-wide
+    /// [`ServeConfig`] set by `configure`.
     pub async fn initialize() -> Result<Self> {
-        let codex_home = find_codex_home()
-            .context("could not determine Codex home directory (run `codex` once)")?;
-        let auth_manager =
-            AuthManager::shared(codex_home.clone(), true, AuthCredentialsStoreMode::File);
-
-        let mut cli_overrides = Vec::new();
-        if let Some(flag) = web_search_request_override() {
-            cli_overrides.push((
-                "features.web_search_request".to_string(),
-                TomlValue::Boolean(flag),
-            ));
-        }
-        let config =
-            Config::load_with_cli_overrides(cli_overrides.clone(), ConfigOverrides::default())
-                .await?;
+        Self::initialize_with(InitOptions {
+            config: effective_config(),
+            ..InitOptions::default()
+        })
+        .await
+    }
+
+    /// Builds the state from explicit options instead of process globals, for embedding the
+    /// [`super::router`] in another binary. With `allow_degraded`, a failure to initialize Codex
+    /// yields a degraded state instead of an error; see [`super::degraded`].
+    pub async fn initialize_with(options: InitOptions) -> Result<Self> {
+        if !options.config.allow_degraded {
+            return Self::load(options).await;
+        }
+        match Self::load(options.clone()).await {
+            Ok(state) => Ok(state),
+            Err(err) => {
+                warn!(
+                    error = format!("{err:#}"),
+                    "starting degraded: Codex could not be initialized"
+                );
+                Ok(Self::degraded(options, &err))
+            }
+        }
+    }
+
+    async fn load(options: InitOptions) -> Result<Self> {
+        let InitOptions {
+            codex_home,
+            cli_overrides,
+            config: serve_config,
+        } = options;
+        let codex_home = match codex_home {
+            Some(codex_home) => codex_home,
+            None => find_codex_home()
+                .context("could not determine Codex home directory (run `codex` once)")?,
+        };
+        let api_key = serve_config
+            .openai_api_key
+            .as_ref()
+            .map(|key| key.expose().to_string());
+        let (auth_manager, auth) = build_auth(codex_home.clone(), api_key)?;
+
+        let LoadedConfig {
+            mut config,
+            cli_overrides,
+            warning,
+        } = load_config(
+            cli_overrides,
+            serve_config.web_search_request,
+            serve_config.strict_config,
+            |overrides| Config::load_with_cli_overrides(overrides, ConfigOverrides::default()),
+        )
+        .await?;
+        if warning.is_some() {
+            config.tools_web_search_request = false;
+        }
         let web_search_enabled = config.tools_web_search_request;
+        let profiles = ProfileCatalog::from_codex_home(&codex_home);
         let config = Arc::new(config);
+        let capture = serve_config
+            .capture_dir
+            .clone()
+            .map(|dir| {
+                CaptureSink::spawn(
+                    dir.clone(),
+                    serve_config.capture_max_body_bytes,
+                    serve_config.verbose_redact,
+                )
+                .with_context(|| format!("failed to create capture dir {}", dir.display()))
+            })
+            .transpose()?;
 
-        let engine = Arc::new(RealChatExecutor::new(
+        let executor: SharedChatExecutor = Arc::new(RealChatExecutor::new(
             Arc::clone(&config),
             Arc::clone(&auth_manager),
             cli_overrides,
+            &serve_config,
         ));
 
+        let latency = Arc::default();
         Ok(Self {
-            auth: AuthController::Real(auth_manager),
-            engine,
-            web_search_enabled,
+            auth,
+            engine: wrap_engine(Arc::clone(&executor), &latency, &serve_config),
+            executor,
+            web_search_enabled: Arc::new(AtomicBool::new(web_search_enabled)),
+            metrics: Arc::default(),
+            loaded_models: Arc::default(),
+            idempotency: Arc::default(),
+            conversations: Arc::default(),
+            budgets: Arc::default(),
+            upstream: Arc::default(),
+            latency,
+            in_flight: Arc::default(),
+            profiles: Arc::new(profiles),
+            capture,
+            playground: serve_config.playground,
+            admin: serve_config.enable_admin,
+            client_limiter: serve_config.per_client_concurrency.map(|limit| {
+                Arc::new(ClientLimiter::new(limit).with_queue(serve_config.queue_requests))
+            }),
+            listeners: Arc::new([]),
+            keepalive: Arc::default(),
+            cache_gc: Arc::default(),
+            config: Arc::new(serve_config),
+            config_warnings: warning.into_iter().collect(),
         })
     }
 
+    /// A state without Codex: `/healthz` reports `error`, chat routes answer 503 and metadata
+    /// routes serve the static model list until a reload succeeds.
+    pub fn degraded(options: InitOptions, error: &anyhow::Error) -> Self {
+        let serve_config = options.config.clone();
+        let startup = Arc::new(DegradedStartup::new(options, error));
+        let latency = Arc::default();
+        let executor: SharedChatExecutor = Arc::new(DegradedExecutor(Arc::clone(&startup)));
+        Self {
+            auth: AuthController::Degraded(startup),
+            engine: wrap_engine(Arc::clone(&executor), &latency, &serve_config),
+            executor,
+            web_search_enabled: Arc::default(),
+            metrics: Arc::default(),
+            loaded_models: Arc::default(),
+            idempotency: Arc::default(),
+            conversations: Arc::default(),
+            budgets: Arc::default(),
+            upstream: Arc::default(),
+            latency,
+            in_flight: Arc::default(),
+            profiles: Arc::default(),
+            capture: None,
+            playground: serve_config.playground,
+            admin: serve_config.enable_admin,
+            client_limiter: serve_config.per_client_concurrency.map(|limit| {
+                Arc::new(ClientLimiter::new(limit).with_queue(serve_config.queue_requests))
+            }),
+            listeners: Arc::new([]),
+            keepalive: Arc::default(),
+            cache_gc: Arc::default(),
+            config: Arc::new(serve_config),
+            config_warnings: Arc::new([]),
+        }
+    }
+
     /// Test-only constructor that avoids hitting the real Codex CLI.
     pub fn insecure_mock(authenticated: bool) -> Self {
         Self::insecure_mock_with_mode(authenticated, None)
     }
 
     pub fn insecure_mock_with_mode(authenticated: bool, auth_mode: Option<AuthMode>) -> Self {
+        let status = if authenticated {
+            AuthStatus::Active
+        } else {
+            AuthStatus::Missing
+        };
+        Self::insecure_mock_with_status(status, auth_mode)
+    }
+
+    pub fn insecure_mock_with_status(status: AuthStatus, auth_mode: Option<AuthMode>) -> Self {
+        let latency = Arc::default();
+        let executor: SharedChatExecutor = Arc::new(MockChatExecutor::new());
         Self {
             auth: AuthController::Mock {
-                authenticated,
+                status: Arc::new(Mutex::new(status)),
                 mode: auth_mode,
             },
-            engine: Arc::new(MockChatExecutor::new()),
-            web_search_enabled: false,
+            engine: wrap_engine(Arc::clone(&executor), &latency, &ServeConfig::default()),
+            executor,
+            web_search_enabled: Arc::default(),
+            metrics: Arc::default(),
+            loaded_models: Arc::default(),
+            idempotency: Arc::default(),
+            conversations: Arc::default(),
+            budgets: Arc::default(),
+            upstream: Arc::default(),
+            latency,
+            in_flight: Arc::default(),
+            profiles: Arc::default(),
+            capture: None,
+            playground: false,
+            admin: false,
+            client_limiter: None,
+            listeners: Arc::new([]),
+            keepalive: Arc::default(),
+            cache_gc: Arc::default(),
+            config: Arc::default(),
+            config_warnings: Arc::new([]),
         }
     }
 
+    /// Swaps the backing executor, e.g. for a scripted one in tests.
+    pub fn with_executor(mut self, executor: SharedChatExecutor) -> Self {
+        self.engine = wrap_engine(Arc::clone(&executor), &self.latency, &self.config);
+        self.executor = executor;
+        self
+    }
+
     pub fn ensure_authenticated(&self) -> Result<(), ApiError> {
-        if self.auth.is_authenticated() {
-            Ok(())
-        } else {
-            Err(ApiError::unauthorized(
+        match self.auth.status() {
+            AuthStatus::Active => Ok(()),
+            AuthStatus::Missing => Err(ApiError::unauthorized(
                 "Codex Serve requires an active Codex login. \
                  Run `codex login` (or sign in via the Codex CLI) and try again.",
-            ))
+            )),
+            AuthStatus::Expired => Err(ApiError::token_expired(
+                "The saved Codex session has expired and could not be refreshed. \
+                 Re-authenticate with `codex login` and try again.",
+            )),
+            AuthStatus::Unavailable => Err(self.auth.degraded_startup().map_or_else(
+                || ApiError::service_unavailable("Codex is unavailable"),
+                DegradedStartup::unavailable,
+            )),
         }
     }
 
+    /// Records a failed upstream call for `/healthz`; failures that reveal stale credentials
+    /// also make later requests fail fast.
+    pub fn note_upstream_error(&self, err: &ApiError) {
+        self.upstream.record_failure(err.message());
+        if matches!(err, ApiError::TokenExpired(_)) {
+            self.auth.record_refresh_failure();
+        }
+    }
+
+    /// Records a successful upstream call for `/healthz`.
+    pub(super) fn note_upstream_success(&self) {
+        self.upstream.record_success();
+    }
+
+    /// Records how reading a stream that got past the handshake ended, once it has.
+    pub(super) fn note_upstream_outcome<T>(&self, result: &Result<T, ApiError>) {
+        match result {
+            Ok(_) => self.note_upstream_success(),
+            Err(err) => self.note_upstream_error(err),
+        }
+    }
+
+    /// Records a stream that failed after it started, which no [`ApiError`] describes.
+    pub(super) fn note_upstream_stream_error(&self) {
+        self.upstream
+            .record_failure("the Codex stream ended with an error");
+    }
+
+    pub(super) fn upstream_health(&self) -> &UpstreamHealth {
+        &self.upstream
+    }
+
+    pub(super) fn latency(&self) -> &LatencyStats {
+        &self.latency
+    }
+
+    pub(super) fn in_flight(&self) -> &Arc<InFlightRequests> {
+        &self.in_flight
+    }
+
+    /// Lists the request on `/admin/requests` until the returned handle is dropped; the handle
+    /// also carries the request's cancellation.
+    pub(super) fn track_request(
+        &self,
+        model: &str,
+        client: Option<&ClientId>,
+        stream: bool,
+    ) -> TrackedRequest {
+        self.in_flight.track(
+            current_request_id().unwrap_or_default(),
+            model,
+            client,
+            stream,
+        )
+    }
+
     pub fn engine(&self) -> SharedChatExecutor {
         Arc::clone(&self.engine)
     }
 
+    /// The executor as given, without the wrappers [`Self::engine`] adds.
+    pub(super) fn executor(&self) -> SharedChatExecutor {
+        Arc::clone(&self.executor)
+    }
+
+    /// Replaces the known Codex config profiles, e.g. with a fixed list in tests.
+    pub fn with_profiles(mut self, profiles: ProfileCatalog) -> Self {
+        self.profiles = Arc::new(profiles);
+        self
+    }
+
+    /// Enables request/response capture through `sink`.
+    pub fn with_capture(mut self, sink: CaptureSink) -> Self {
+        self.capture = Some(sink);
+        self
+    }
+
+    /// Replaces the serving options (body limits, model listing) for a mock state.
+    pub fn with_config(mut self, config: ServeConfig) -> Self {
+        self.engine = wrap_engine(Arc::clone(&self.executor), &self.latency, &config);
+        self.config = Arc::new(config);
+        self
+    }
+
+    pub fn config(&self) -> &ServeConfig {
+        &self.config
+    }
+
+    /// Turns the `GET /` playground page on or off.
+    pub fn with_playground(mut self, enabled: bool) -> Self {
+        self.playground = enabled;
+        self
+    }
+
+    pub fn playground_enabled(&self) -> bool {
+        self.playground
+    }
+
+    /// Mounts or hides the `/admin/*` routes.
+    pub fn with_admin(mut self, enabled: bool) -> Self {
+        self.admin = enabled;
+        self
+    }
+
+    pub fn admin_enabled(&self) -> bool {
+        self.admin
+    }
+
+    /// Enforces per-client concurrency on the chat routes.
+    pub fn with_client_limiter(mut self, limiter: ClientLimiter) -> Self {
+        self.client_limiter = Some(Arc::new(limiter));
+        self
+    }
+
+    pub fn client_limiter(&self) -> Option<&Arc<ClientLimiter>> {
+        self.client_limiter.as_ref()
+    }
+
+    /// Records the addresses being served, as `/healthz` lists them.
+    pub fn with_listeners(mut self, listeners: Vec<ListenerInfo>) -> Self {
+        self.listeners = listeners.into();
+        self
+    }
+
+    pub fn listeners(&self) -> &[ListenerInfo] {
+        &self.listeners
+    }
+
+    /// Records config overrides ignored at startup, as `/healthz` reports them.
+    pub fn with_config_warnings(mut self, warnings: Vec<String>) -> Self {
+        self.config_warnings = warnings.into();
+        self
+    }
+
+    pub fn config_warnings(&self) -> &[String] {
+        &self.config_warnings
+    }
+
+    /// Starts running the executor's keepalive every `interval` in the background, replacing
+    /// any keepalive already running; [`AppState::stop_keepalive`] or dropping the last clone of
+    /// the state cancels it. Must be called inside a Tokio runtime.
+    pub fn start_keepalive(&self, interval: Duration) {
+        self.keepalive.start(self.engine(), interval);
+    }
+
+    pub fn stop_keepalive(&self) {
+        self.keepalive.stop();
+    }
+
+    /// The keepalive's interval and last result, once one has been started.
+    pub fn keepalive_status(&self) -> Option<KeepaliveStatus> {
+        self.keepalive.status()
+    }
+
+    /// Starts dropping the executor's cached configs once idle for `ttl`, replacing any such task
+    /// already running; [`AppState::stop_cache_gc`] or dropping the last clone of the state
+    /// cancels it. Must be called inside a Tokio runtime.
+    pub fn start_cache_gc(&self, ttl: Duration) {
+        self.cache_gc
+            .start(self.engine(), Arc::clone(&self.metrics), ttl);
+    }
+
+    pub fn stop_cache_gc(&self) {
+        self.cache_gc.stop();
+    }
+
+    /// Drops the cached configs idle for `--cache-idle-ttl` now, returning their keys.
+    pub async fn evict_idle_configs(&self) -> Vec<String> {
+        cache_gc::sweep(&self.engine(), &self.metrics, self.config.cache_idle_ttl).await
+    }
+
+    pub fn capture(&self) -> Option<&CaptureSink> {
+        self.capture.as_ref()
+    }
+
+    pub fn profiles(&self) -> &ProfileCatalog {
+        &self.profiles
+    }
+
+    pub fn metrics(&self) -> &Arc<ServerMetrics> {
+        &self.metrics
+    }
+
+    /// Where to book the raw Codex usage of a request for `model` from `client`, with
+    /// `--usage-extended`.
+    pub(super) fn usage_account(
+        &self,
+        model: &str,
+        client: Option<ClientId>,
+        access_log: Option<AccessLog>,
+    ) -> Option<UsageAccount> {
+        self.config.usage_extended.then(|| UsageAccount {
+            model: model.to_string(),
+            client: client.map_or_else(|| "anonymous".to_string(), |client| client.name),
+            access_log,
+        })
+    }
+
+    /// Keys of the chat requests that are running or can be replayed.
+    pub(super) fn idempotency(&self) -> &Arc<IdempotencyKeys> {
+        &self.idempotency
+    }
+
+    /// Prompt cache statistics per conversation, for `/stats/conversations`.
+    pub(super) fn conversations(&self) -> &Conversations {
+        &self.conversations
+    }
+
+    /// Checks `payload` against `--max-tokens-per-request` and `client`'s `--max-tokens-per-hour`
+    /// budget, returning where its usage is booked once it finishes.
+    pub(super) fn admit_tokens(
+        &self,
+        payload: &PromptPayload,
+        client: Option<&ClientId>,
+    ) -> Result<Option<BudgetCharge>, ApiError> {
+        let client = client.map_or("anonymous", |client| client.account.as_str());
+        budget::admit(&self.budgets, &self.config, payload, client)
+    }
+
+    /// Tokens booked per client in the current `--max-tokens-per-hour` window.
+    pub fn token_budgets(&self) -> &TokenBudgets {
+        &self.budgets
+    }
+
+    /// Where `payload`'s usage is booked as a conversation turn.
+    pub(super) fn conversation_turn(&self, payload: &PromptPayload) -> Option<ConversationTurn> {
+        conversations::key(payload).map(|key| ConversationTurn {
+            conversations: Arc::clone(&self.conversations),
+            key,
+        })
+    }
+
+    /// Models that served a request recently, as Ollama's `/api/ps` reports them.
+    pub(super) fn loaded_models(&self) -> &LoadedModels {
+        &self.loaded_models
+    }
+
+    /// Applies an Ollama `keep_alive` for `model` to `/api/ps` and to the executor's cached
+    /// configs, returning how many of those were dropped.
+    pub(super) async fn set_model_expiry(&self, model: &str, expiry: ModelExpiry) -> usize {
+        self.loaded_models.set_model_expiry(model, expiry);
+        self.engine().set_model_expiry(model, expiry).await
+    }
+
     pub fn auth(&self) -> &AuthController {
         &self.auth
     }
@@ -93,45 +586,274 @@ impl AppState {
         self.auth.auth_mode()
     }
 
+    pub async fn account_details(&self) -> Option<AccountDetails> {
+        self.auth.account_details().await
+    }
+
     pub fn web_search_enabled(&self) -> bool {
-        self.web_search_enabled
+        self.web_search_enabled.load(Ordering::Relaxed)
     }
+
+    /// Reloads the executor's Codex config and applies the re-evaluated web search flag.
+    pub async fn reload(&self) -> Result<ReloadOutcome, ApiError> {
+        let outcome = self.engine.reload().await?;
+        if let Some(enabled) = outcome.web_search_enabled {
+            self.web_search_enabled.store(enabled, Ordering::Relaxed);
+        }
+        Ok(outcome)
+    }
+}
+
+/// Picks the credential source: an explicit API key wins and is held in memory only, so the
+/// ChatGPT `auth.json` in `codex_home` is never read or written; otherwise the saved `codex login`.
+fn build_auth(
+    codex_home: PathBuf,
+    api_key: Option<String>,
+) -> Result<(Arc<AuthManager>, AuthController)> {
+    if let Some(api_key) = api_key {
+        let manager = api_key_auth_manager(&codex_home, &api_key)?;
+        return Ok((manager, AuthController::ApiKey { codex_home }));
+    }
+    let manager = AuthManager::shared(codex_home.clone(), true, AuthCredentialsStoreMode::File);
+    let controller = AuthController::Real {
+        manager: Arc::clone(&manager),
+        codex_home,
+        refresh_failed: Arc::new(AtomicBool::new(false)),
+        reload: Arc::new(ReloadThrottle::new(AUTH_RELOAD_INTERVAL)),
+    };
+    Ok((manager, controller))
+}
+
+/// An auth manager serving `api_key`, logged in through Codex's ephemeral credential store: the
+/// key lives in this process only, and the `CODEX_API_KEY` environment variable cannot replace it.
+fn api_key_auth_manager(codex_home: &Path, api_key: &str) -> Result<Arc<AuthManager>> {
+    login_with_api_key(codex_home, api_key, AuthCredentialsStoreMode::Ephemeral)
+        .context("could not store the --openai-api-key credentials in memory")?;
+    Ok(AuthManager::shared(
+        codex_home.to_path_buf(),
+        false,
+        AuthCredentialsStoreMode::Ephemeral,
+    ))
+}
+
+/// How long a credential reload stays fresh before `AuthController::status` re-reads the store.
+/// Short enough that `codex login`/`logout` take effect almost immediately, long enough that a
+/// burst of requests does not hit the keychain or `auth.json` once per call.
+const AUTH_RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether the Codex credentials can currently be used for upstream calls.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AuthStatus {
+    Active,
+    Missing,
+    Expired,
+    /// Codex could not be initialized at startup (degraded mode).
+    Unavailable,
 }
 
 #[derive(Clone)]
 pub enum AuthController {
-    Real(Arc<AuthManager>),
+    Real {
+        manager: Arc<AuthManager>,
+        codex_home: PathBuf,
+        /// Set when an upstream call reported that the token could not be refreshed.
+        refresh_failed: Arc<AtomicBool>,
+        reload: Arc<ReloadThrottle>,
+    },
+    /// Authenticated with an OpenAI API key supplied at startup; never expires or reloads.
+    ApiKey { codex_home: PathBuf },
     Mock {
-        authenticated: bool,
+        /// Shared so tests can flip the login state while the server is running.
+        status: Arc<Mutex<AuthStatus>>,
         mode: Option<AuthMode>,
     },
+    /// Started without Codex; defers to the recovered login once `/admin/reload` succeeds.
+    Degraded(Arc<DegradedStartup>),
+}
+
+/// Which account a logged-in instance is bound to, as reported by `/healthz`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct AccountDetails {
+    pub auth_mode: &'static str,
+    /// First few characters of the ChatGPT account id; never the full value.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plan_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub codex_home: Option<String>,
+}
+
+fn auth_mode_label(mode: AuthMode) -> &'static str {
+    match mode {
+        AuthMode::ApiKey => "api_key",
+        AuthMode::ChatGPT => "chatgpt",
+    }
+}
+
+fn mask_account_id(account_id: &str) -> String {
+    let prefix: String = account_id.chars().take(4).collect();
+    format!("{prefix}…")
+}
+
+/// Rate-limits credential reloads to at most one per interval.
+pub struct ReloadThrottle {
+    interval: Duration,
+    last_reload: Mutex<Option<Instant>>,
+}
+
+impl ReloadThrottle {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_reload: Mutex::new(None),
+        }
+    }
+
+    /// Returns true (and restarts the interval) when the cached credentials are stale at `now`.
+    fn should_reload(&self, now: Instant) -> bool {
+        let mut last_reload = self
+            .last_reload
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        match *last_reload {
+            Some(at) if now.saturating_duration_since(at) < self.interval => false,
+            _ => {
+                *last_reload = Some(now);
+                true
+            }
+        }
+    }
 }
 
 impl AuthController {
+    pub fn status(&self) -> AuthStatus {
+        match self {
+            Self::Real {
+                manager,
+                refresh_failed,
+                reload,
+                ..
+            } => {
+                // Pick up `codex login`/`codex logout` performed after the server started.
+                let changed = reload.should_reload(Instant::now()) && manager.reload();
+                if refresh_failed.load(Ordering::Acquire) {
+                    // Stay expired until the credentials on disk change (e.g. after `codex login`).
+                    if !changed {
+                        return AuthStatus::Expired;
+                    }
+                    refresh_failed.store(false, Ordering::Release);
+                }
+                if manager.auth().is_some() {
+                    AuthStatus::Active
+                } else {
+                    AuthStatus::Missing
+                }
+            }
+            Self::ApiKey { .. } => AuthStatus::Active,
+            Self::Mock { status, .. } => *lock_status(status),
+            Self::Degraded(startup) => startup
+                .recovered_auth()
+                .map_or(AuthStatus::Unavailable, AuthController::status),
+        }
+    }
+
+    /// The failed startup, while it has not been recovered from.
+    pub(super) fn degraded_startup(&self) -> Option<&DegradedStartup> {
+        match self {
+            Self::Degraded(startup) if startup.recovered_auth().is_none() => Some(startup),
+            _ => None,
+        }
+    }
+
     pub fn is_authenticated(&self) -> bool {
+        self.status() == AuthStatus::Active
+    }
+
+    /// Account metadata for the active login; `None` whenever the credentials are unusable.
+    pub async fn account_details(&self) -> Option<AccountDetails> {
+        if self.status() != AuthStatus::Active {
+            return None;
+        }
         match self {
-            Self::Real(manager) => manager.auth().is_some(),
-            Self::Mock { authenticated, .. } => *authenticated,
+            Self::Degraded(startup) => Box::pin(startup.recovered_auth()?.account_details()).await,
+            Self::Real {
+                manager,
+                codex_home,
+                ..
+            } => {
+                let auth = manager.auth()?;
+                let plan_type = match auth.mode {
+                    AuthMode::ChatGPT => auth
+                        .get_token_data()
+                        .await
+                        .ok()
+                        .and_then(|tokens| tokens.id_token.get_chatgpt_plan_type()),
+                    AuthMode::ApiKey => None,
+                };
+                Some(AccountDetails {
+                    auth_mode: auth_mode_label(auth.mode),
+                    account_id: auth.get_account_id().as_deref().map(mask_account_id),
+                    plan_type,
+                    codex_home: Some(codex_home.display().to_string()),
+                })
+            }
+            Self::ApiKey { codex_home } => Some(AccountDetails {
+                auth_mode: auth_mode_label(AuthMode::ApiKey),
+                account_id: None,
+                plan_type: None,
+                codex_home: Some(codex_home.display().to_string()),
+            }),
+            Self::Mock { mode, .. } => mode.map(|mode| AccountDetails {
+                auth_mode: auth_mode_label(mode),
+                account_id: None,
+                plan_type: None,
+                codex_home: None,
+            }),
+        }
+    }
+
+    /// Changes the simulated login state of a mock controller; a no-op for real credentials.
+    pub fn set_mock_status(&self, new_status: AuthStatus) {
+        if let Self::Mock { status, .. } = self {
+            *lock_status(status) = new_status;
+        }
+    }
+
+    pub fn record_refresh_failure(&self) {
+        match self {
+            Self::Real { refresh_failed, .. } => refresh_failed.store(true, Ordering::Release),
+            Self::Degraded(startup) => {
+                if let Some(auth) = startup.recovered_auth() {
+                    auth.record_refresh_failure();
+                }
+            }
+            Self::ApiKey { .. } | Self::Mock { .. } => {}
         }
     }
 
     pub fn auth_mode(&self) -> Option<AuthMode> {
         match self {
-            Self::Real(manager) => manager.auth().map(|auth| auth.mode),
-            Self::Mock {
-                authenticated,
-                mode,
-            } => {
-                if *authenticated {
+            Self::Real { manager, .. } => manager.auth().map(|auth| auth.mode),
+            Self::ApiKey { .. } => Some(AuthMode::ApiKey),
+            Self::Mock { status, mode } => {
+                if *lock_status(status) == AuthStatus::Active {
                     *mode
                 } else {
                     None
                 }
             }
+            Self::Degraded(startup) => startup.recovered_auth()?.auth_mode(),
         }
     }
 }
 
+fn lock_status(status: &Mutex<AuthStatus>) -> std::sync::MutexGuard<'_, AuthStatus> {
+    status
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -144,4 +866,138 @@ mod tests {
         assert_send::<AppState>();
         assert_sync::<AppState>();
     }
+
+    #[tokio::test]
+    async fn settings_apply_to_an_executor_swapped_in_before_them() {
+        use codex_core::ResponseEvent;
+        use futures_util::StreamExt;
+
+        use crate::{openai::chat::ChatCompletionRequest, server::ScriptedChatExecutor};
+
+        let executor = ScriptedChatExecutor::from_events(|| {
+            vec![
+                ResponseEvent::ReasoningSummaryDelta {
+                    delta: "thinking".to_string(),
+                    summary_index: 0,
+                },
+                ResponseEvent::OutputTextDelta("hi".to_string()),
+            ]
+        });
+        let state = AppState::insecure_mock(true)
+            .with_executor(Arc::new(executor))
+            .with_config(ServeConfig::builder().hide_reasoning(true).build());
+        let request: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-5",
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap();
+
+        let handle = state
+            .engine()
+            .stream(request.into_prompt().unwrap())
+            .await
+            .expect("stream");
+        let events: Vec<_> = handle.stream.collect().await;
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], Ok(ResponseEvent::OutputTextDelta(text)) if text == "hi"));
+    }
+
+    #[test]
+    fn missing_auth_reports_not_logged_in() {
+        let state = AppState::insecure_mock_with_status(AuthStatus::Missing, None);
+        match state.ensure_authenticated() {
+            Err(ApiError::Unauthorized(message)) => assert!(message.contains("codex login")),
+            other => panic!("expected NOT_LOGGED_IN, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn expired_auth_reports_token_expired() {
+        let state =
+            AppState::insecure_mock_with_status(AuthStatus::Expired, Some(AuthMode::ChatGPT));
+        match state.ensure_authenticated() {
+            Err(ApiError::TokenExpired(message)) => assert!(message.contains("Re-authenticate")),
+            other => panic!("expected TOKEN_EXPIRED, got {other:?}"),
+        }
+        assert_eq!(state.auth_mode(), None);
+    }
+
+    #[test]
+    fn login_state_changes_apply_without_restart() {
+        let state = AppState::insecure_mock_with_status(AuthStatus::Missing, None);
+        let handle = state.clone();
+        assert!(matches!(
+            state.ensure_authenticated(),
+            Err(ApiError::Unauthorized(_))
+        ));
+
+        handle.auth().set_mock_status(AuthStatus::Active);
+        assert!(state.ensure_authenticated().is_ok());
+        assert!(state.auth().is_authenticated());
+
+        handle.auth().set_mock_status(AuthStatus::Missing);
+        assert!(matches!(
+            state.ensure_authenticated(),
+            Err(ApiError::Unauthorized(_))
+        ));
+    }
+
+    #[test]
+    fn reload_throttle_caches_within_interval() {
+        let throttle = ReloadThrottle::new(Duration::from_secs(1));
+        let start = Instant::now();
+        assert!(throttle.should_reload(start));
+        assert!(!throttle.should_reload(start + Duration::from_millis(500)));
+        assert!(throttle.should_reload(start + Duration::from_millis(1_500)));
+        assert!(!throttle.should_reload(start + Duration::from_millis(1_600)));
+    }
+
+    #[tokio::test]
+    async fn account_details_only_reported_when_active() {
+        let active =
+            AppState::insecure_mock_with_status(AuthStatus::Active, Some(AuthMode::ApiKey));
+        let details = active
+            .account_details()
+            .await
+            .expect("active login should report account details");
+        assert_eq!(details.auth_mode, "api_key");
+
+        for status in [AuthStatus::Missing, AuthStatus::Expired] {
+            let state = AppState::insecure_mock_with_status(status, Some(AuthMode::ChatGPT));
+            assert_eq!(state.account_details().await, None);
+        }
+    }
+
+    #[test]
+    fn account_ids_are_masked() {
+        assert_eq!(mask_account_id("acct_1234567890"), "acct…");
+        assert_eq!(mask_account_id("ab"), "ab…");
+    }
+
+    #[tokio::test]
+    async fn api_key_auth_skips_the_login_file() {
+        let codex_home = std::env::temp_dir().join(format!("codex-serve-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&codex_home).expect("create temp codex home");
+
+        let (manager, auth) =
+            build_auth(codex_home.clone(), Some("sk-test".to_string())).expect("API-key auth");
+        assert_eq!(manager.auth().map(|auth| auth.mode), Some(AuthMode::ApiKey));
+        assert_eq!(auth.status(), AuthStatus::Active);
+        assert_eq!(auth.auth_mode(), Some(AuthMode::ApiKey));
+        let details = auth
+            .account_details()
+            .await
+            .expect("API-key auth should report account details");
+        assert_eq!(details.auth_mode, "api_key");
+        assert_eq!(
+            details.codex_home.as_deref(),
+            Some(codex_home.display().to_string().as_str())
+        );
+        assert!(!codex_home.join("auth.json").exists());
+
+        let (_, auth) = build_auth(codex_home.clone(), None).expect("login-file auth");
+        assert!(matches!(auth, AuthController::Real { .. }));
+
+        let _ = std::fs::remove_dir_all(&codex_home);
+    }
 }
@@ -1,4 +1,8 @@
+use std::net::SocketAddr;
+
 use anyhow::Result;
+use axum::Router;
+use serde_json::Value;
 use tokio::{
     net::TcpListener,
     sync::oneshot,
@@ -6,14 +10,20 @@ use tokio::{
 };
 
 use codex_app_server_protocol::AuthMode;
+use uuid::Uuid;
 
-use super::{router, state::AppState};
+use super::{
+    capture::CaptureSink, executor::SharedChatExecutor, ollama_router, router, state::AppState,
+    surface_router,
+};
+use crate::serve_config::{ApiSurfaces, ServeConfig};
 
 /// Helper for integration tests to run the server in the background.
 pub struct TestServer {
     base_url: String,
     shutdown: Option<oneshot::Sender<()>>,
     task: JoinHandle<()>,
+    ollama: Option<Box<TestServer>>,
 }
 
 impl TestServer {
@@ -33,12 +43,58 @@ impl TestServer {
         Self::spawn_with_state(state).await
     }
 
+    /// Runs an authenticated server backed by `executor`, e.g. a [`super::CapturingExecutor`] or
+    /// [`super::ScriptedChatExecutor`].
+    pub async fn spawn_with_executor(executor: SharedChatExecutor) -> Result<Self> {
+        Self::spawn_with_state(AppState::insecure_mock(true).with_executor(executor)).await
+    }
+
+    /// Like [`TestServer::spawn_with_executor`], with `config` in place of the default settings.
+    /// A `capture_dir` in it gets a capture sink, as it would at startup.
+    pub async fn spawn_with_config(
+        config: ServeConfig,
+        executor: SharedChatExecutor,
+    ) -> Result<Self> {
+        let capture = config
+            .capture_dir
+            .clone()
+            .map(|dir| {
+                CaptureSink::spawn(dir, config.capture_max_body_bytes, config.verbose_redact)
+            })
+            .transpose()?;
+        let mut state = AppState::insecure_mock(true)
+            .with_config(config)
+            .with_executor(executor);
+        if let Some(sink) = capture {
+            state = state.with_capture(sink);
+        }
+        Self::spawn_with_state(state).await
+    }
+
     pub async fn spawn_with_state(state: AppState) -> Result<Self> {
+        Self::spawn_router(router(state)).await
+    }
+
+    /// Runs `state` like `--ollama-addr` does: the Ollama routes on a second listener, at
+    /// [`TestServer::ollama_base_url`], and everything else at [`TestServer::base_url`].
+    pub async fn spawn_split(state: AppState) -> Result<Self> {
+        let main_surfaces = ApiSurfaces {
+            ollama: false,
+            ..state.config().api_surfaces
+        };
+        let ollama = Self::spawn_router(ollama_router(state.clone())).await?;
+        let mut server = Self::spawn_router(surface_router(state, main_surfaces, true)).await?;
+        server.ollama = Some(Box::new(ollama));
+        Ok(server)
+    }
+
+    async fn spawn_router(router: Router) -> Result<Self> {
         let listener = TcpListener::bind("127.0.0.1:0").await?;
         let addr = listener.local_addr()?;
         let (shutdown_tx, shutdown_rx) = oneshot::channel();
 
-        let server = axum::serve(listener, router(state)).with_graceful_shutdown(async move {
+        let app = router.into_make_service_with_connect_info::<SocketAddr>();
+        let server = axum::serve(listener, app).with_graceful_shutdown(async move {
             let _ = shutdown_rx.await;
         });
 
@@ -52,12 +108,21 @@ impl TestServer {
             base_url: format!("http://{}", addr),
             shutdown: Some(shutdown_tx),
             task,
+            ollama: None,
         })
     }
 
     pub fn base_url(&self) -> &str {
         &self.base_url
     }
+
+    /// Where the Ollama routes are served: their own listener for [`TestServer::spawn_split`],
+    /// otherwise [`TestServer::base_url`].
+    pub fn ollama_base_url(&self) -> &str {
+        self.ollama
+            .as_ref()
+            .map_or(&self.base_url, |ollama| &ollama.base_url)
+    }
 }
 
 impl Drop for TestServer {
@@ -68,3 +133,73 @@ impl Drop for TestServer {
         self.task.abort();
     }
 }
+
+/// Replaces the volatile parts of a response with fixed placeholders so whole payloads can be
+/// compared against golden files: `created` timestamps become `0`, request ids become
+/// `<request-id>`, and any hyphenated UUID inside a string becomes `<uuid>`.
+pub fn normalize_snapshot(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, entry) in map.iter_mut() {
+                match (key.as_str(), &*entry) {
+                    ("created", Value::Number(_)) => *entry = Value::from(0),
+                    ("request_id", Value::String(_)) => {
+                        *entry = Value::String("<request-id>".to_string());
+                    }
+                    _ => normalize_snapshot(entry),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(normalize_snapshot),
+        Value::String(text) => *text = replace_uuids(text),
+        _ => {}
+    }
+}
+
+fn replace_uuids(text: &str) -> String {
+    const UUID_LEN: usize = 36;
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while !rest.is_empty() {
+        if let Some(candidate) = rest.get(..UUID_LEN)
+            && candidate.as_bytes()[8] == b'-'
+            && Uuid::try_parse(candidate).is_ok()
+        {
+            out.push_str("<uuid>");
+            rest = &rest[UUID_LEN..];
+            continue;
+        }
+        let mut chars = rest.chars();
+        if let Some(ch) = chars.next() {
+            out.push(ch);
+        }
+        rest = chars.as_str();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn normalizes_timestamps_and_generated_ids() {
+        let mut value = json!({
+            "id": "chatcmpl-67e55044-10b1-426f-9247-bb680e5fe0c8",
+            "created": 1_760_000_000,
+            "error": {"request_id": "req_0123456789abcdef0123456789abcdef"},
+            "calls": [{"id": "ws_call_67e55044-10b1-426f-9247-bb680e5fe0c8", "name": "é"}]
+        });
+        normalize_snapshot(&mut value);
+        assert_eq!(
+            value,
+            json!({
+                "id": "chatcmpl-<uuid>",
+                "created": 0,
+                "error": {"request_id": "<request-id>"},
+                "calls": [{"id": "ws_call_<uuid>", "name": "é"}]
+            })
+        );
+    }
+}
@@ -0,0 +1,251 @@
+//! Tool calls seen in one upstream response, keyed by call id so the events of one call (added,
+//! then done, possibly with more arguments) update a single entry. A response can name thousands
+//! of distinct calls (every web search gets a fresh id), so only the first
+//! `--max-tracked-tool-calls` are tracked; later ones are passed on once, when done, and counted.
+
+use std::collections::{HashMap, HashSet};
+
+use codex_core::ResponseItem;
+use serde::Serialize;
+use tracing::warn;
+
+use super::{response::ToolCall, tool_call_from_item};
+
+pub const DEFAULT_MAX_TRACKED_TOOL_CALLS: usize = 256;
+
+/// Where an event's tool call goes in the reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Slot {
+    /// A tracked call; `first` when this is its first event.
+    Tracked { index: usize, first: bool },
+    /// A finished call past the limit, to pass on whole under a fresh index.
+    Untracked { index: usize },
+    /// An unfinished call past the limit; it is passed on when done.
+    Skipped,
+}
+
+/// Counts the web searches of one response, by event, so a search the upstream sent without an
+/// id can be named after its position: the n-th search `OutputItemAdded` announces is the one the
+/// n-th `OutputItemDone` finishes, however much of its action each carries.
+#[derive(Debug, Default)]
+pub(super) struct WebSearchPositions {
+    added: usize,
+    done: usize,
+}
+
+impl WebSearchPositions {
+    /// The position of the next search; `done` for `OutputItemDone` events.
+    pub(super) fn next(&mut self, done: bool) -> usize {
+        let seen = if done {
+            &mut self.done
+        } else {
+            &mut self.added
+        };
+        let position = *seen;
+        *seen += 1;
+        position
+    }
+}
+
+/// Vendor field on replies whose response named more calls than were tracked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ToolCallOverflow {
+    pub limit: usize,
+    pub untracked: usize,
+}
+
+pub(super) struct ToolCallTracker {
+    limit: usize,
+    indices: HashMap<String, usize>,
+    /// Each tracked call's arguments as already streamed to the client.
+    arguments_sent: HashMap<String, String>,
+    /// Tracked calls whose id and name the client has been sent.
+    announced: HashSet<String>,
+    web_searches: WebSearchPositions,
+    next_index: usize,
+    untracked: usize,
+}
+
+impl ToolCallTracker {
+    pub(super) fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            indices: HashMap::new(),
+            arguments_sent: HashMap::new(),
+            announced: HashSet::new(),
+            web_searches: WebSearchPositions::default(),
+            next_index: 0,
+            untracked: 0,
+        }
+    }
+
+    /// The tool call `item` carries, if any; `done` for `OutputItemDone` items.
+    pub(super) fn call_from_item(&mut self, item: &ResponseItem, done: bool) -> Option<ToolCall> {
+        tool_call_from_item(item, &mut self.web_searches, done)
+    }
+
+    /// The slot for an event of call `id`; `done` for `OutputItemDone` events.
+    pub(super) fn slot(&mut self, id: &str, done: bool) -> Slot {
+        if let Some(&index) = self.indices.get(id) {
+            return Slot::Tracked {
+                index,
+                first: false,
+            };
+        }
+        if self.indices.len() < self.limit {
+            let index = self.take_index();
+            self.indices.insert(id.to_string(), index);
+            return Slot::Tracked { index, first: true };
+        }
+        if !done {
+            return Slot::Skipped;
+        }
+        if self.untracked == 0 {
+            warn!(
+                limit = self.limit,
+                "response names more tool calls than --max-tracked-tool-calls; passing the rest \
+                 on untracked"
+            );
+        }
+        self.untracked += 1;
+        Slot::Untracked {
+            index: self.take_index(),
+        }
+    }
+
+    /// Records `arguments` as sent for tracked call `id` and returns where its unsent part starts,
+    /// or `None` when there is nothing new to send. The start is the end of the text sent before,
+    /// so it is always a char boundary. When a later version of the call no longer starts with
+    /// what was sent (say, its arguments were re-serialized with different escaping), the start
+    /// is 0: the whole arguments go out again as a corrective delta.
+    pub(super) fn unsent_arguments(&mut self, id: &str, arguments: &str) -> Option<usize> {
+        let sent = self.arguments_sent.entry(id.to_string()).or_default();
+        if sent.starts_with(arguments) {
+            return None;
+        }
+        let start = if arguments.starts_with(sent.as_str()) {
+            sent.len()
+        } else {
+            warn!(
+                call_id = id,
+                "tool call arguments changed after they were streamed; resending them whole"
+            );
+            0
+        };
+        arguments.clone_into(sent);
+        Some(start)
+    }
+
+    /// Whether tracked call `id` is yet to be introduced to the client, marking it introduced.
+    pub(super) fn announce(&mut self, id: &str) -> bool {
+        self.announced.insert(id.to_string())
+    }
+
+    pub(super) fn overflow(&self) -> Option<ToolCallOverflow> {
+        (self.untracked > 0).then_some(ToolCallOverflow {
+            limit: self.limit,
+            untracked: self.untracked,
+        })
+    }
+
+    fn take_index(&mut self) -> usize {
+        let index = self.next_index;
+        self.next_index += 1;
+        index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calls_past_the_limit_are_counted_not_kept() {
+        let mut tracker = ToolCallTracker::new(2);
+        assert_eq!(
+            tracker.slot("a", false),
+            Slot::Tracked {
+                index: 0,
+                first: true
+            }
+        );
+        assert_eq!(
+            tracker.slot("a", true),
+            Slot::Tracked {
+                index: 0,
+                first: false
+            }
+        );
+        assert_eq!(
+            tracker.slot("b", true),
+            Slot::Tracked {
+                index: 1,
+                first: true
+            }
+        );
+        assert_eq!(tracker.overflow(), None);
+
+        for _ in 0..1000 {
+            assert_eq!(tracker.slot("c", false), Slot::Skipped);
+        }
+        assert_eq!(tracker.slot("c", true), Slot::Untracked { index: 2 });
+        assert_eq!(tracker.slot("d", true), Slot::Untracked { index: 3 });
+        assert_eq!(tracker.indices.len(), 2);
+        assert_eq!(
+            tracker.overflow(),
+            Some(ToolCallOverflow {
+                limit: 2,
+                untracked: 2
+            })
+        );
+    }
+
+    /// The deltas the stream would send for successive versions of call `a`'s arguments.
+    fn deltas<'a>(tracker: &mut ToolCallTracker, versions: &[&'a str]) -> Vec<&'a str> {
+        versions
+            .iter()
+            .filter_map(|arguments| {
+                let start = tracker.unsent_arguments("a", arguments)?;
+                Some(&arguments[start..])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn arguments_are_sent_once() {
+        let mut tracker = ToolCallTracker::new(1);
+        assert_eq!(tracker.unsent_arguments("a", "{\"q\""), Some(0));
+        assert_eq!(tracker.unsent_arguments("a", "{\"q\""), None);
+        assert_eq!(tracker.unsent_arguments("a", "{\"q\":1}"), Some(4));
+    }
+
+    #[test]
+    fn multibyte_arguments_are_split_on_char_boundaries() {
+        let mut tracker = ToolCallTracker::new(1);
+        let sent = deltas(
+            &mut tracker,
+            &[
+                r#"{"city":"東"#,
+                r#"{"city":"東京"#,
+                r#"{"city":"東京 🌧"#,
+                r#"{"city":"東京 🌧"}"#,
+                r#"{"city":"東京"#,
+            ],
+        );
+        assert_eq!(sent, [r#"{"city":"東"#, "京", " 🌧", r#""}"#]);
+        assert_eq!(sent.concat(), r#"{"city":"東京 🌧"}"#);
+    }
+
+    #[test]
+    fn rewritten_arguments_are_resent_whole() {
+        let mut tracker = ToolCallTracker::new(1);
+        // The done item no longer starts with what the added item sent; cutting it at the
+        // length already sent would land inside `東`.
+        let sent = deltas(
+            &mut tracker,
+            &[r#"{"q":"ab"#, r#"{"q":"a東"}"#, r#"{"q":"a東"}"#],
+        );
+        assert_eq!(sent, [r#"{"q":"ab"#, r#"{"q":"a東"}"#]);
+        assert!(!r#"{"q":"a東"}"#.is_char_boundary(r#"{"q":"ab"#.len()));
+    }
+}
@@ -0,0 +1,108 @@
+//! `--verbose` payload logging. Every event of one request carries the same `request_id` and
+//! `model` fields from its [`LogContext`], so `chat.request`, `chat.tools`, the response event
+//! and the closing `chat.summary` can be pulled out of the log together.
+
+use serde::Serialize;
+use serde_json::{Value, json};
+use tracing::{info, warn};
+
+use super::{
+    current_request_id, redact,
+    response::{ContentIntegrity, ToolCall, Usage},
+};
+use crate::{
+    clock::{LogContext, RequestClock},
+    serve_config::ServeConfig,
+};
+
+impl LogContext {
+    /// The context of the request being handled, timed from now.
+    pub fn current(model: impl Into<String>) -> Self {
+        Self::new(current_request_id().unwrap_or_default(), model)
+    }
+}
+
+pub(super) fn log_verbose_json<T>(
+    config: &ServeConfig,
+    context: &LogContext,
+    event: &str,
+    value: &T,
+) where
+    T: ?Sized + Serialize,
+{
+    if !config.verbose {
+        return;
+    }
+    let serialized = serde_json::to_value(value).map(|mut value| {
+        if config.verbose_redact {
+            redact::redact_json(&mut value);
+        }
+        value.to_string()
+    });
+    match serialized {
+        Ok(serialized) => info!(
+            event = event,
+            request_id = %context.request_id,
+            model = %context.model,
+            payload = %serialized,
+            "verbose emit"
+        ),
+        Err(err) => warn!(
+            event = event,
+            request_id = %context.request_id,
+            model = %context.model,
+            "failed to serialize verbose payload: {err}"
+        ),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(super) fn log_verbose_stream_response(
+    config: &ServeConfig,
+    context: &LogContext,
+    model: &str,
+    clock: &RequestClock,
+    response_id: &str,
+    text: Option<String>,
+    reasoning_summary: Option<String>,
+    reasoning_content: Option<String>,
+    tool_calls: Vec<ToolCall>,
+    usage: &Usage,
+) {
+    let payload = json!({
+        "model": model,
+        "id": clock.id(),
+        "created": clock.created(),
+        "response_id": response_id,
+        "text": text,
+        "reasoning_summary": reasoning_summary,
+        "reasoning_content": reasoning_content,
+        "tool_calls": if tool_calls.is_empty() { Value::Null } else { serde_json::to_value(tool_calls).unwrap_or(Value::Null) },
+        "usage": usage,
+    });
+    log_verbose_json(config, context, "chat.stream.response", &payload);
+}
+
+/// The last verbose event of a chat request: how long it took, its token counts and why it
+/// finished (`error` or `client_disconnected` when it did not finish normally), plus the content
+/// digest a `--stream-integrity` stream ended with.
+pub(super) fn log_verbose_summary(
+    config: &ServeConfig,
+    context: &LogContext,
+    usage: &Usage,
+    finish_reason: Option<&str>,
+    content: Option<&ContentIntegrity>,
+) {
+    let mut payload = json!({
+        "duration_ms": context.started().elapsed().as_millis(),
+        "prompt_tokens": usage.prompt_tokens,
+        "completion_tokens": usage.completion_tokens,
+        "total_tokens": usage.total_tokens,
+        "finish_reason": finish_reason,
+    });
+    if let Some(content) = content {
+        payload["content_sha256"] = json!(content.sha256);
+        payload["content_length"] = json!(content.length);
+    }
+    log_verbose_json(config, context, "chat.summary", &payload);
+}
@@ -0,0 +1,104 @@
+//! `/api/version`: the Ollama release we claim to be compatible with, plus our own version.
+
+use axum::{
+    Json,
+    extract::{Request, State},
+    http::HeaderName,
+    middleware::Next,
+    response::Response,
+};
+use serde::Serialize;
+use tracing::warn;
+
+use super::state::AppState;
+
+/// Version header some Ollama clients send to say which server release they expect.
+static CLIENT_VERSION_HEADER: HeaderName = HeaderName::from_static("x-ollama-version");
+
+/// The real version of this crate, reported next to the Ollama-compatible one.
+pub(super) const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Serialize)]
+pub(super) struct VersionResponse {
+    version: String,
+    vendor: Vendor,
+}
+
+#[derive(Debug, Serialize)]
+struct Vendor {
+    name: &'static str,
+    version: &'static str,
+}
+
+pub(super) async fn api_version(State(state): State<AppState>) -> Json<VersionResponse> {
+    Json(VersionResponse {
+        version: state.config().ollama_version.clone(),
+        vendor: Vendor {
+            name: env!("CARGO_PKG_NAME"),
+            version: CRATE_VERSION,
+        },
+    })
+}
+
+/// Warns when a client asks (via `X-Ollama-Version`) for a newer Ollama than `--ollama-version`
+/// advertises, since it may then rely on features we do not implement. The request still goes
+/// through.
+pub(super) async fn check_client_version(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let advertised = &state.config().ollama_version;
+    if let Some(wanted) = request
+        .headers()
+        .get(&CLIENT_VERSION_HEADER)
+        .and_then(|value| value.to_str().ok())
+        && is_newer(wanted, advertised)
+    {
+        warn!(
+            path = %request.uri().path(),
+            client_version = wanted,
+            advertised_version = %advertised,
+            "client expects a newer Ollama than advertised; pass --ollama-version to change it"
+        );
+    }
+    next.run(request).await
+}
+
+/// Compares dotted numeric versions (`0.12.10` > `0.12.9`); a leading `v` and any pre-release
+/// suffix are ignored, and unparsable versions never count as newer.
+fn is_newer(candidate: &str, baseline: &str) -> bool {
+    match (parse_version(candidate), parse_version(baseline)) {
+        (Some(candidate), Some(baseline)) => candidate > baseline,
+        _ => false,
+    }
+}
+
+fn parse_version(value: &str) -> Option<Vec<u64>> {
+    let value = value.trim();
+    let value = value.strip_prefix('v').unwrap_or(value);
+    let core = value.split(['-', '+']).next()?;
+    let mut parts: Vec<u64> = core
+        .split('.')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    while parts.len() > 1 && parts.last() == Some(&0) {
+        parts.pop();
+    }
+    Some(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_versions_numerically() {
+        assert!(is_newer("0.12.10", "0.12.9"));
+        assert!(is_newer("v1.0", "0.13.0"));
+        assert!(!is_newer("0.13", "0.13.0"));
+        assert!(!is_newer("0.13.0-rc1", "0.13.0"));
+        assert!(!is_newer("latest", "0.13.0"));
+        assert!(!is_newer("0.9.0", "0.13.0"));
+    }
+}
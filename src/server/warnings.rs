@@ -0,0 +1,115 @@
+//! Reports a request's [`Warnings`] to the client in the `x-codex-serve-warnings` header, as a
+//! compact JSON array of `{code, message}` objects. Streams send the header with their first
+//! bytes, so it carries what was known then; warnings raised later only reach the server log.
+
+use std::fmt::Write as _;
+
+use axum::{
+    http::{HeaderName, HeaderValue},
+    response::Response,
+};
+use serde_json::json;
+
+use super::{LogContext, log_verbose_json};
+use crate::{
+    openai::warnings::{Warning, Warnings},
+    serve_config::ServeConfig,
+};
+
+pub const WARNINGS_HEADER: HeaderName = HeaderName::from_static("x-codex-serve-warnings");
+
+/// Longest header value we send; warnings past it are summarized by a count.
+const MAX_HEADER_BYTES: usize = 4096;
+
+/// Adds the warnings header to `response` and logs the full list with `--verbose`.
+pub(super) fn report(
+    config: &ServeConfig,
+    context: &LogContext,
+    event: &str,
+    warnings: &Warnings,
+    response: &mut Response,
+) {
+    let warnings = warnings.snapshot();
+    if warnings.is_empty() {
+        return;
+    }
+    log_verbose_json(config, context, event, &warnings);
+    if let Ok(value) = HeaderValue::from_str(&header_value(&warnings)) {
+        response.headers_mut().insert(WARNINGS_HEADER, value);
+    }
+}
+
+/// The JSON array, ASCII-only so it is a valid header value, cut to [`MAX_HEADER_BYTES`].
+fn header_value(warnings: &[Warning]) -> String {
+    let mut kept = warnings.len();
+    loop {
+        let mut entries: Vec<_> = warnings[..kept]
+            .iter()
+            .map(|warning| json!(warning))
+            .collect();
+        if kept < warnings.len() {
+            entries.push(json!({
+                "code": "truncated",
+                "message": format!("{} more warning(s) in the server log", warnings.len() - kept),
+            }));
+        }
+        let value = ascii_json(&serde_json::Value::Array(entries).to_string());
+        if value.len() <= MAX_HEADER_BYTES || kept == 0 {
+            return value;
+        }
+        kept -= 1;
+    }
+}
+
+/// Escapes non-ASCII characters as `\uXXXX`; the result is still the same JSON.
+fn ascii_json(json: &str) -> String {
+    let mut escaped = String::with_capacity(json.len());
+    for ch in json.chars() {
+        if ch.is_ascii() {
+            escaped.push(ch);
+        } else {
+            for unit in ch.encode_utf16(&mut [0; 2]) {
+                let _ = write!(escaped, "\\u{unit:04x}");
+            }
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn warning(message: &str) -> Warning {
+        Warning {
+            code: "tool_schema_sanitized",
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn header_is_ascii_json() {
+        let value = header_value(&[warning("tool `météo` changed")]);
+        assert!(value.is_ascii());
+        let parsed: serde_json::Value = serde_json::from_str(&value).unwrap();
+        assert_eq!(parsed[0]["message"], "tool `météo` changed");
+    }
+
+    #[test]
+    fn header_is_size_capped() {
+        let long = "x".repeat(1000);
+        let warnings: Vec<_> = (0..10).map(|_| warning(&long)).collect();
+        let value = header_value(&warnings);
+        assert!(value.len() <= MAX_HEADER_BYTES);
+        let parsed: Vec<serde_json::Value> = serde_json::from_str(&value).unwrap();
+        let summary = parsed.last().unwrap();
+        assert_eq!(summary["code"], "truncated");
+        assert_eq!(
+            summary["message"],
+            format!(
+                "{} more warning(s) in the server log",
+                10 - (parsed.len() - 1)
+            )
+        );
+    }
+}
@@ -0,0 +1,187 @@
+//! Optional OpenTelemetry export for the HTTP layer, enabled with `--otlp-endpoint`.
+//!
+//! Without an endpoint nothing here is installed: no exporter, no propagator, and the helper
+//! spans collapse to `Span::none()`, so logging output is unchanged.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Context, Result};
+use axum::http::{HeaderMap, HeaderName};
+use opentelemetry::{global, propagation::Extractor, trace::TracerProvider as _};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    Resource,
+    propagation::TraceContextPropagator,
+    trace::{SdkTracerProvider, Tracer},
+};
+use tracing::{Span, Subscriber, info_span};
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+const SERVICE_NAME: &str = "codex-serve";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Builds a tracer provider that batches spans to an OTLP/HTTP collector, e.g.
+/// `http://localhost:4318/v1/traces`.
+pub fn otlp_tracer_provider(endpoint: &str) -> Result<SdkTracerProvider> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .with_context(|| format!("failed to build OTLP exporter for {endpoint}"))?;
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+        .build())
+}
+
+/// Returns the `tracing` layer that forwards spans to `provider` and turns on W3C trace-context
+/// propagation for incoming requests.
+pub fn layer<S>(provider: &SdkTracerProvider) -> OpenTelemetryLayer<S, Tracer>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    ENABLED.store(true, Ordering::Release);
+    tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME))
+}
+
+fn enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(HeaderName::as_str).collect()
+    }
+}
+
+/// Parents the request span under the caller's `traceparent` and tags it with HTTP attributes.
+pub(crate) fn start_http_span(span: &Span, headers: &HeaderMap, method: &str, path: &str) {
+    if !enabled() {
+        return;
+    }
+    let parent =
+        global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)));
+    span.set_parent(parent);
+    span.set_attribute("http.request.method", method.to_string());
+    span.set_attribute("url.path", path.to_string());
+}
+
+pub(crate) fn finish_http_span(span: &Span, status: u16) {
+    if enabled() {
+        span.set_attribute("http.response.status_code", i64::from(status));
+    }
+}
+
+/// Child span covering the upstream Codex call; `Span::none()` when export is off.
+pub(crate) fn upstream_span(model: &str, stream: bool) -> Span {
+    if !enabled() {
+        return Span::none();
+    }
+    let span = info_span!("codex.upstream");
+    span.set_attribute("gen_ai.request.model", model.to_string());
+    span.set_attribute("codex.stream", stream);
+    span
+}
+
+/// Records token usage on an [`upstream_span`].
+pub(crate) fn record_usage(span: &Span, prompt_tokens: u32, completion_tokens: u32) {
+    if enabled() {
+        span.set_attribute("gen_ai.usage.input_tokens", i64::from(prompt_tokens));
+        span.set_attribute("gen_ai.usage.output_tokens", i64::from(completion_tokens));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use opentelemetry::{
+        Value,
+        trace::{SpanId, TraceId},
+    };
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SpanData};
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::server::TestServer;
+
+    fn attribute<'a>(span: &'a SpanData, key: &str) -> Option<&'a Value> {
+        span.attributes
+            .iter()
+            .find(|kv| kv.key.as_str() == key)
+            .map(|kv| &kv.value)
+    }
+
+    #[tokio::test]
+    async fn exports_request_and_upstream_spans() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let _subscriber =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(layer(&provider)));
+
+        let server = TestServer::spawn()
+            .await
+            .expect("Codex Serve test server should start");
+        let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+        let response = reqwest::Client::new()
+            .post(format!("{}/v1/chat/completions", server.base_url()))
+            .header("traceparent", format!("00-{trace_id}-00f067aa0ba902b7-01"))
+            .json(&serde_json::json!({
+                "model": "gpt-5",
+                "messages": [{"role": "user", "content": "hello"}]
+            }))
+            .send()
+            .await
+            .expect("request should reach Codex Serve");
+        assert!(response.status().is_success());
+
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(2);
+        let spans = loop {
+            let spans = exporter.get_finished_spans().expect("read exported spans");
+            if spans.iter().any(|span| span.name == "request") {
+                break spans;
+            }
+            assert!(tokio::time::Instant::now() < deadline, "no request span");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+
+        let request = spans
+            .iter()
+            .find(|span| span.name == "request")
+            .expect("request span");
+        assert_eq!(
+            request.span_context.trace_id(),
+            TraceId::from_hex(trace_id).unwrap()
+        );
+        assert_eq!(
+            request.parent_span_id,
+            SpanId::from_hex("00f067aa0ba902b7").unwrap()
+        );
+        assert_eq!(
+            attribute(request, "http.response.status_code"),
+            Some(&Value::I64(200))
+        );
+
+        let upstream = spans
+            .iter()
+            .find(|span| span.name == "codex.upstream")
+            .expect("upstream span");
+        assert_eq!(upstream.parent_span_id, request.span_context.span_id());
+        assert_eq!(
+            attribute(upstream, "gen_ai.request.model"),
+            Some(&Value::from("gpt-5"))
+        );
+        assert!(attribute(upstream, "gen_ai.usage.output_tokens").is_some());
+    }
+}
@@ -0,0 +1,162 @@
+//! `--api-surface` and `--ollama-addr`: each combination serves exactly its route groups, the
+//! others answer the standard JSON 404, and `/healthz` lists what is enabled.
+
+use std::sync::Arc;
+
+use codex_serve::{
+    AppState, ServeConfig,
+    serve_config::{ApiSurface, ApiSurfaces},
+    server::{ScriptedChatExecutor, TestServer},
+};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+
+fn state(surfaces: ApiSurfaces) -> AppState {
+    AppState::insecure_mock(true)
+        .with_config(ServeConfig::builder().api_surfaces(surfaces).build())
+        .with_executor(Arc::new(ScriptedChatExecutor::new(["hi"])))
+}
+
+async fn get(base_url: &str, path: &str) -> reqwest::Response {
+    reqwest::get(format!("{base_url}{path}"))
+        .await
+        .expect("request should reach Codex Serve")
+}
+
+async fn post(base_url: &str, path: &str, body: Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{base_url}{path}"))
+        .json(&body)
+        .send()
+        .await
+        .expect("request should reach Codex Serve")
+}
+
+fn chat_body() -> Value {
+    json!({
+        "model": "gpt-5",
+        "stream": false,
+        "messages": [{"role": "user", "content": "hello"}]
+    })
+}
+
+async fn assert_openai(base_url: &str, served: bool) {
+    let models = get(base_url, "/v1/models").await;
+    let chat = post(base_url, "/v1/chat/completions", chat_body()).await;
+    if served {
+        assert_eq!(models.status(), StatusCode::OK);
+        assert_eq!(chat.status(), StatusCode::OK);
+    } else {
+        assert_disabled(models, "OpenAI").await;
+        assert_disabled(chat, "OpenAI").await;
+    }
+}
+
+async fn assert_ollama(base_url: &str, served: bool) {
+    let tags = get(base_url, "/api/tags").await;
+    let chat = post(base_url, "/api/chat", chat_body()).await;
+    if served {
+        assert_eq!(tags.status(), StatusCode::OK);
+        assert_eq!(chat.status(), StatusCode::OK);
+    } else {
+        assert_disabled(tags, "Ollama").await;
+        assert_disabled(chat, "Ollama").await;
+    }
+}
+
+async fn assert_disabled(response: reqwest::Response, label: &str) {
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let body: Value = response.json().await.expect("404 body is JSON");
+    assert_eq!(body["error"]["code"], "NOT_FOUND");
+    let message = body["error"]["message"].as_str().expect("error message");
+    assert!(message.contains(&format!("the {label} API")), "{message}");
+}
+
+async fn healthz_surfaces(base_url: &str) -> Value {
+    let health: Value = get(base_url, "/healthz")
+        .await
+        .json()
+        .await
+        .expect("healthz must be JSON");
+    health["config"]["api_surfaces"].clone()
+}
+
+#[tokio::test]
+async fn both_surfaces_are_served_by_default() {
+    let server = TestServer::spawn_with_state(state(ApiSurfaces::default()))
+        .await
+        .expect("Codex Serve test server should start");
+    assert_openai(server.base_url(), true).await;
+    assert_ollama(server.base_url(), true).await;
+    assert_eq!(
+        healthz_surfaces(server.base_url()).await,
+        json!(["openai", "ollama"])
+    );
+}
+
+#[tokio::test]
+async fn openai_only_hides_the_ollama_routes() {
+    let server = TestServer::spawn_with_state(state(ApiSurfaces::only(ApiSurface::OpenAi)))
+        .await
+        .expect("Codex Serve test server should start");
+    assert_openai(server.base_url(), true).await;
+    assert_ollama(server.base_url(), false).await;
+    assert_eq!(healthz_surfaces(server.base_url()).await, json!(["openai"]));
+
+    // Typos are not pointed at routes that are switched off.
+    let typo: Value = get(server.base_url(), "/api/tag")
+        .await
+        .json()
+        .await
+        .expect("404 body is JSON");
+    assert_eq!(typo["error"]["message"], "Unknown route `/api/tag`");
+}
+
+#[tokio::test]
+async fn ollama_only_hides_the_openai_routes() {
+    let server = TestServer::spawn_with_state(state(ApiSurfaces::only(ApiSurface::Ollama)))
+        .await
+        .expect("Codex Serve test server should start");
+    assert_openai(server.base_url(), false).await;
+    assert_ollama(server.base_url(), true).await;
+    assert_eq!(healthz_surfaces(server.base_url()).await, json!(["ollama"]));
+}
+
+#[tokio::test]
+async fn ollama_routes_can_have_their_own_listener() {
+    let server = TestServer::spawn_split(state(ApiSurfaces::default()))
+        .await
+        .expect("Codex Serve test server should start");
+    assert_ne!(server.base_url(), server.ollama_base_url());
+
+    assert_openai(server.base_url(), true).await;
+    assert_ollama(server.base_url(), false).await;
+    assert_openai(server.ollama_base_url(), false).await;
+    assert_ollama(server.ollama_base_url(), true).await;
+
+    // Both listeners share one state, so each counts the chat requests served by the other.
+    for base_url in [server.base_url(), server.ollama_base_url()] {
+        let health: Value = get(base_url, "/healthz")
+            .await
+            .json()
+            .await
+            .expect("healthz must be JSON");
+        assert_eq!(
+            health["config"]["api_surfaces"],
+            json!(["openai", "ollama"])
+        );
+        assert_eq!(health["stats"]["requests_total"], 2);
+    }
+}
+
+#[test]
+fn surfaces_parse_from_the_command_line_form() {
+    assert_eq!("openai,ollama".parse(), Ok(ApiSurfaces::ALL));
+    assert_eq!(
+        " Ollama ".parse(),
+        Ok(ApiSurfaces::only(ApiSurface::Ollama))
+    );
+    assert_eq!(ApiSurfaces::ALL.to_string(), "openai,ollama");
+    assert!("".parse::<ApiSurfaces>().is_err());
+    assert!("openai,anthropic".parse::<ApiSurfaces>().is_err());
+}
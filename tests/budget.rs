@@ -0,0 +1,132 @@
+//! `--max-tokens-per-request` and `--max-tokens-per-hour`: oversized requests are refused with
+//! their estimate, and a client's hourly budget covers streamed and non-streamed replies alike,
+//! falling back to the estimate when the upstream reports no usage.
+
+use std::sync::Arc;
+
+use codex_core::{ResponseEvent, protocol::TokenUsage};
+use codex_serve::{
+    AppState, ServeConfig,
+    server::{COMPLETION_RESERVE_TOKENS, ScriptedChatExecutor, TestServer},
+};
+use reqwest::{StatusCode, header::RETRY_AFTER};
+use serde_json::{Value, json};
+
+/// Answers every request with usage adding up to `total` tokens.
+fn billed(total: i64) -> ScriptedChatExecutor {
+    ScriptedChatExecutor::from_events(move || {
+        vec![
+            ResponseEvent::OutputTextDelta("ok".to_string()),
+            ResponseEvent::Completed {
+                response_id: "resp_billed".to_string(),
+                token_usage: Some(TokenUsage {
+                    input_tokens: total - 10,
+                    cached_input_tokens: 0,
+                    output_tokens: 10,
+                    reasoning_output_tokens: 0,
+                    total_tokens: total,
+                }),
+            },
+        ]
+    })
+}
+
+async fn spawn(config: ServeConfig, executor: ScriptedChatExecutor) -> TestServer {
+    let state = AppState::insecure_mock(true)
+        .with_config(config)
+        .with_executor(Arc::new(executor));
+    TestServer::spawn_with_state(state)
+        .await
+        .expect("Codex Serve test server should start")
+}
+
+/// Sends a chat request as `user` and reads the whole body, so a stream has ended on return.
+async fn chat(server: &TestServer, user: &str, stream: bool) -> (StatusCode, Option<u64>, String) {
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&json!({
+            "model": "gpt-5",
+            "stream": stream,
+            "user": user,
+            "messages": [{"role": "user", "content": "hello"}]
+        }))
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok()?.parse().ok());
+    let body = response.text().await.expect("response body");
+    (status, retry_after, body)
+}
+
+async fn budget_stats(server: &TestServer) -> Value {
+    reqwest::get(format!("{}/stats/budget", server.base_url()))
+        .await
+        .expect("stats request")
+        .json()
+        .await
+        .expect("stats are JSON")
+}
+
+#[tokio::test]
+async fn oversized_requests_are_refused_with_their_estimate() {
+    let config = ServeConfig::builder().max_tokens_per_request(100).build();
+    let server = spawn(config, billed(50)).await;
+    let (status, _, body) = chat(&server, "a", false).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let body: Value = serde_json::from_str(&body).expect("error is JSON");
+    let message = body["error"]["message"].as_str().expect("message");
+    assert!(message.contains("estimated at"), "{message}");
+    assert!(
+        message.contains("--max-tokens-per-request limit of 100"),
+        "{message}"
+    );
+}
+
+#[tokio::test]
+async fn the_hourly_budget_counts_streams_and_replies() {
+    let config = ServeConfig::builder().max_tokens_per_hour(1000).build();
+    let server = spawn(config, billed(600)).await;
+
+    assert_eq!(chat(&server, "a", false).await.0, StatusCode::OK);
+    // 600 of 1000 spent: still admitted, and the stream is booked when it ends.
+    let (status, _, body) = chat(&server, "a", true).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("data: [DONE]"), "{body}");
+
+    let (status, retry_after, body) = chat(&server, "a", false).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS, "{body}");
+    let retry_after = retry_after.expect("Retry-After on the 429");
+    assert!((3500..=3600).contains(&retry_after), "{retry_after}");
+    assert!(body.contains("--max-tokens-per-hour"), "{body}");
+
+    // Other clients have budgets of their own.
+    assert_eq!(chat(&server, "b", true).await.0, StatusCode::OK);
+
+    let stats = budget_stats(&server).await;
+    assert_eq!(stats["max_tokens_per_hour"], 1000);
+    assert_eq!(stats["clients"]["user:a"]["spent"], 1200);
+    assert_eq!(stats["clients"]["user:a"]["remaining"], 0);
+    assert_eq!(stats["clients"]["user:b"]["spent"], 600);
+    assert_eq!(stats["clients"]["user:b"]["remaining"], 400);
+}
+
+#[tokio::test]
+async fn replies_without_usage_are_booked_at_their_estimate() {
+    let config = ServeConfig::builder()
+        .max_tokens_per_hour(COMPLETION_RESERVE_TOKENS)
+        .build();
+    let server = spawn(config, ScriptedChatExecutor::new(["no usage reported"])).await;
+
+    assert_eq!(chat(&server, "a", true).await.0, StatusCode::OK);
+    let stats = budget_stats(&server).await;
+    let spent = stats["clients"]["user:a"]["spent"].as_u64().expect("spent");
+    assert!(spent > COMPLETION_RESERVE_TOKENS, "{stats}");
+    assert_eq!(
+        chat(&server, "a", false).await.0,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+}
@@ -1,6 +1,20 @@
 use codex_app_server_protocol::AuthMode;
 use codex_common::model_presets::builtin_model_presets;
-use codex_serve::server::TestServer;
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+use codex_core::{ContentItem, ResponseItem, ToolSpec, protocol::TokenUsage};
+use codex_protocol::config_types::{ReasoningEffort, ReasoningSummary, Verbosity};
+use codex_serve::{
+    openai::chat::PromptPayload,
+    serve_config::{DEFAULT_OLLAMA_VERSION, OllamaTagStyle, ServeConfig, configure},
+    server::{
+        AppState, CapturingExecutor, InitOptions, ScriptedChatExecutor, ScriptedTurn, TestServer,
+        WARNINGS_HEADER, router,
+    },
+};
 use reqwest::StatusCode;
 use serde_json::Value;
 
@@ -13,6 +27,33 @@ fn sample_payload() -> Value {
     })
 }
 
+/// Spawns a server whose executor records the prompts it receives.
+async fn spawn_capturing() -> (TestServer, Arc<Mutex<Vec<PromptPayload>>>) {
+    let executor = CapturingExecutor::new(Arc::new(ScriptedChatExecutor::new(["ok"])));
+    let captured = executor.captured();
+    let server = TestServer::spawn_with_executor(Arc::new(executor))
+        .await
+        .expect("Codex Serve test server should start");
+    (server, captured)
+}
+
+async fn post_chat(server: &TestServer, payload: &Value) -> Value {
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(payload)
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(response.status(), StatusCode::OK);
+    response.json().await.expect("response must be JSON")
+}
+
+fn only_payload(captured: &Mutex<Vec<PromptPayload>>) -> PromptPayload {
+    let captured = captured.lock().unwrap();
+    assert_eq!(captured.len(), 1, "expected exactly one upstream call");
+    captured[0].clone()
+}
+
 fn extract_message_content(body: &Value) -> Option<String> {
     let choices = body.get("choices")?.as_array()?;
     let first = choices.first()?;
@@ -55,8 +96,8 @@ async fn chat_completions_curl_example() {
     assert!(
         body.get("id")
             .and_then(Value::as_str)
-            .is_some_and(|s| s.starts_with("resp_")),
-        "response id should resemble resp_*"
+            .is_some_and(|s| s.starts_with("chatcmpl-")),
+        "response id should resemble chatcmpl-*"
     );
     assert!(
         extract_message_content(&body)
@@ -102,11 +143,29 @@ async fn api_version_matches_crate() {
     );
 
     let body: Value = response.json().await.expect("response must be JSON");
-    let expected = "0.12.10";
     assert_eq!(
         body.get("version").and_then(Value::as_str),
-        Some(expected),
-        "/api/version should expose crate version"
+        Some(DEFAULT_OLLAMA_VERSION),
+        "/api/version should expose the Ollama-compatible version"
+    );
+    assert_eq!(
+        body["vendor"]["version"].as_str(),
+        Some(env!("CARGO_PKG_VERSION")),
+        "/api/version should expose the crate version under vendor"
+    );
+
+    let health: Value = client
+        .get(format!("{}/healthz", server.base_url()))
+        .send()
+        .await
+        .expect("healthz should respond")
+        .json()
+        .await
+        .expect("healthz must be JSON");
+    assert_eq!(health["version"].as_str(), Some(env!("CARGO_PKG_VERSION")));
+    assert_eq!(
+        health["config"]["ollama_version"].as_str(),
+        Some(DEFAULT_OLLAMA_VERSION)
     );
 }
 
@@ -150,6 +209,138 @@ async fn api_tags_lists_models() {
     }
 }
 
+async fn get_json(server: &TestServer, path: &str) -> Value {
+    reqwest::get(format!("{}{path}", server.base_url()))
+        .await
+        .expect("request should reach Codex Serve")
+        .json()
+        .await
+        .expect("response must be JSON")
+}
+
+/// `(name, digest, modified_at)` for every `/api/tags` entry.
+async fn tag_digests(server: &TestServer) -> Vec<(String, String, String)> {
+    let tags = get_json(server, "/api/tags").await;
+    tags["models"]
+        .as_array()
+        .expect("models array")
+        .iter()
+        .map(|entry| {
+            let field = |name: &str| entry[name].as_str().expect(name).to_string();
+            (field("name"), field("digest"), field("modified_at"))
+        })
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn ollama_tag_style_names_reasoning_variants() {
+    let server = TestServer::spawn_with_state(
+        AppState::insecure_mock(true).with_config(
+            ServeConfig::builder()
+                .expose_reasoning_models(true)
+                .ollama_tag_style(OllamaTagStyle::Tag)
+                .build(),
+        ),
+    )
+    .await
+    .expect("Codex Serve test server should start");
+
+    let tags = get_json(&server, "/api/tags").await;
+    let variant = tags["models"]
+        .as_array()
+        .expect("models array")
+        .iter()
+        .find(|entry| entry["name"] == "gpt-5.1-codex:high")
+        .expect("variant listed in tag style");
+    assert_eq!(variant["model"], "gpt-5.1-codex:high");
+    assert_eq!(
+        variant["details"]["families"],
+        serde_json::json!(["llama", "reasoning-high"])
+    );
+
+    // The OpenAI listing keeps the hyphen spelling; requests may use either.
+    let models = get_json(&server, "/v1/models").await;
+    assert!(
+        models["data"]
+            .as_array()
+            .expect("data array")
+            .iter()
+            .any(|entry| entry["id"] == "gpt-5.1-codex-high")
+    );
+    let mut payload = sample_payload();
+    payload["model"] = Value::from("gpt-5.1-codex-high");
+    post_chat(&server, &payload).await;
+    let ps = get_json(&server, "/api/ps").await;
+    assert_eq!(ps["models"][0]["name"], "gpt-5.1-codex:high");
+    assert_eq!(ps["models"][0]["digest"], variant["digest"]);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn ollama_digests_are_stable_and_per_model() {
+    let first = TestServer::spawn()
+        .await
+        .expect("Codex Serve test server should start");
+    let digests = tag_digests(&first).await;
+    let unique: HashSet<&str> = digests
+        .iter()
+        .map(|(_, digest, _)| digest.as_str())
+        .collect();
+    assert_eq!(unique.len(), digests.len(), "{digests:?}");
+    let modified_at = &digests[0].2;
+    assert!(digests.iter().all(|(_, _, stamp)| stamp == modified_at));
+
+    // A restart reports the same metadata, so client caches survive it.
+    let restarted = TestServer::spawn()
+        .await
+        .expect("Codex Serve test server should start");
+    assert_eq!(tag_digests(&restarted).await, digests);
+
+    let show: Value = reqwest::Client::new()
+        .post(format!("{}/api/show", first.base_url()))
+        .json(&serde_json::json!({"model": "gpt-5"}))
+        .send()
+        .await
+        .expect("request should reach Codex Serve")
+        .json()
+        .await
+        .expect("response must be JSON");
+    assert_eq!(show["modified_at"].as_str(), Some(modified_at.as_str()));
+
+    assert_eq!(
+        get_json(&first, "/api/ps").await["models"],
+        serde_json::json!([])
+    );
+    let (model, digest, _) = &digests[0];
+    let mut payload = sample_payload();
+    payload["model"] = Value::from(model.as_str());
+    post_chat(&first, &payload).await;
+    let ps = get_json(&first, "/api/ps").await;
+    let running = &ps["models"][0];
+    assert_eq!(running["name"].as_str(), Some(model.as_str()));
+    assert_eq!(running["digest"].as_str(), Some(digest.as_str()));
+    assert!(
+        running["expires_at"]
+            .as_str()
+            .is_some_and(|stamp| stamp.ends_with('Z'))
+    );
+
+    let salted =
+        TestServer::spawn_with_state(AppState::insecure_mock(true).with_config(ServeConfig {
+            ollama_digest_salt: Some("bust".to_string()),
+            ..ServeConfig::default()
+        }))
+        .await
+        .expect("Codex Serve test server should start");
+    let salted = tag_digests(&salted).await;
+    assert_eq!(salted.len(), digests.len());
+    assert!(
+        salted
+            .iter()
+            .zip(&digests)
+            .all(|(salted, plain)| salted.0 == plain.0 && salted.1 != plain.1)
+    );
+}
+
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
 async fn api_show_returns_metadata() {
     let server = TestServer::spawn()
@@ -234,3 +425,1333 @@ async fn chat_accepts_reasoning_suffix_for_chatgpt_auth() {
         "assistant reply text should be present"
     );
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn chat_rejects_truncated_json_with_structured_error() {
+    let server = TestServer::spawn()
+        .await
+        .expect("Codex Serve test server should start");
+
+    let client = reqwest::Client::new();
+    let url = format!("{}/v1/chat/completions", server.base_url());
+    let response = client
+        .post(url)
+        .header("content-type", "application/json")
+        .body(r#"{"model": "gpt-5", "messages": ["#)
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: Value = response.json().await.expect("error body must be JSON");
+    assert_eq!(
+        body["error"]["type"].as_str(),
+        Some("invalid_request_error")
+    );
+    assert!(
+        body["error"]["message"]
+            .as_str()
+            .is_some_and(|message| message.contains("not valid JSON")),
+        "message should describe the syntax error: {body}"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn chat_rejects_wrong_field_type_with_json_path() {
+    let server = TestServer::spawn()
+        .await
+        .expect("Codex Serve test server should start");
+
+    let client = reqwest::Client::new();
+    let url = format!("{}/v1/chat/completions", server.base_url());
+    let response = client
+        .post(url)
+        .json(&serde_json::json!({"model": "gpt-5", "messages": "hello"}))
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: Value = response.json().await.expect("error body must be JSON");
+    assert_eq!(
+        body["error"]["type"].as_str(),
+        Some("invalid_request_error")
+    );
+    assert!(
+        body["error"]["message"]
+            .as_str()
+            .is_some_and(|message| message.contains("messages")),
+        "message should name the failing field: {body}"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn oversized_metadata_body_returns_structured_413() {
+    let server = TestServer::spawn()
+        .await
+        .expect("Codex Serve test server should start");
+
+    let client = reqwest::Client::new();
+    let url = format!("{}/api/show", server.base_url());
+    let padding = "x".repeat(2 * 1024 * 1024);
+    let response = client
+        .post(url)
+        .json(&serde_json::json!({"model": "gpt-5", "padding": padding}))
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let body: Value = response.json().await.expect("error body must be JSON");
+    assert_eq!(
+        body["error"]["type"].as_str(),
+        Some("invalid_request_error")
+    );
+    assert!(
+        body["error"]["message"]
+            .as_str()
+            .is_some_and(|message| message.contains("1048576 bytes")),
+        "message should state the limit: {body}"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn echoes_client_request_id() {
+    let server = TestServer::spawn()
+        .await
+        .expect("Codex Serve test server should start");
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!("{}/healthz", server.base_url()))
+        .header("x-request-id", "client-trace-42")
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+
+    assert_eq!(
+        response
+            .headers()
+            .get("x-request-id")
+            .and_then(|value| value.to_str().ok()),
+        Some("client-trace-42")
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn generates_request_id_and_includes_it_in_errors() {
+    let server = TestServer::spawn_unauthenticated()
+        .await
+        .expect("Codex Serve test server should start");
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&sample_payload())
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let header_id = response
+        .headers()
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .expect("a request id should be generated");
+    assert!(header_id.starts_with("req_"));
+
+    let body: Value = response.json().await.expect("error body must be JSON");
+    assert_eq!(
+        body["error"]["request_id"].as_str(),
+        Some(header_id.as_str())
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn wrong_method_returns_json_405_with_allow_header() {
+    let server = TestServer::spawn()
+        .await
+        .expect("Codex Serve test server should start");
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!("{}/v1/chat/completions", server.base_url()))
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        response
+            .headers()
+            .get("allow")
+            .and_then(|value| value.to_str().ok()),
+        Some("POST")
+    );
+    let body: Value = response.json().await.expect("error body must be JSON");
+    assert!(
+        body["error"]["message"]
+            .as_str()
+            .is_some_and(|message| message.contains("only supports POST")),
+        "unexpected body: {body}"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn unknown_path_suggests_closest_route() {
+    let server = TestServer::spawn()
+        .await
+        .expect("Codex Serve test server should start");
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("{}/v1/chat/completion", server.base_url()))
+        .json(&sample_payload())
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let body: Value = response.json().await.expect("error body must be JSON");
+    assert_eq!(body["error"]["code"].as_str(), Some("NOT_FOUND"));
+    assert!(
+        body["error"]["message"]
+            .as_str()
+            .is_some_and(|message| message.contains("`/v1/chat/completions`")),
+        "unexpected body: {body}"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn healthz_reports_account_only_when_authenticated() {
+    let client = reqwest::Client::new();
+
+    let server = TestServer::spawn_with_auth_mode(true, Some(AuthMode::ChatGPT))
+        .await
+        .expect("Codex Serve test server should start");
+    let body: Value = client
+        .get(format!("{}/healthz", server.base_url()))
+        .send()
+        .await
+        .expect("request should reach Codex Serve")
+        .json()
+        .await
+        .expect("healthz must be JSON");
+    assert_eq!(body["account"]["auth_mode"].as_str(), Some("chatgpt"));
+
+    let server = TestServer::spawn_with_auth_mode(false, Some(AuthMode::ChatGPT))
+        .await
+        .expect("Codex Serve test server should start");
+    let body: Value = client
+        .get(format!("{}/healthz", server.base_url()))
+        .send()
+        .await
+        .expect("request should reach Codex Serve")
+        .json()
+        .await
+        .expect("healthz must be JSON");
+    assert_eq!(body["authenticated"], Value::Bool(false));
+    assert!(body.get("account").is_none(), "unexpected account: {body}");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn unknown_profile_is_rejected_by_name() {
+    let server = TestServer::spawn()
+        .await
+        .expect("Codex Serve test server should start");
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .header("x-codex-profile", "ghost")
+        .json(&sample_payload())
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: Value = response.json().await.expect("error body must be JSON");
+    assert!(
+        body["error"]["message"]
+            .as_str()
+            .is_some_and(|message| message.contains("`ghost`")),
+        "unexpected body: {body}"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn system_prompt_reaches_executor_as_developer_message() {
+    let (server, captured) = spawn_capturing().await;
+    let body = post_chat(
+        &server,
+        &serde_json::json!({
+            "model": "gpt-5",
+            "messages": [
+                {"role": "system", "content": "stay on topic"},
+                {"role": "user", "content": "hello"}
+            ]
+        }),
+    )
+    .await;
+    assert_eq!(extract_message_content(&body).as_deref(), Some("ok"));
+
+    let payload = only_payload(&captured);
+    assert_eq!(payload.model, "gpt-5");
+    // `system_prompt` is what `--developer-prompt-mode default` keys on to skip its helper text.
+    assert_eq!(payload.system_prompt.as_deref(), Some("stay on topic"));
+    assert_eq!(payload.first_user_message.as_deref(), Some("hello"));
+    let roles: Vec<&str> = payload
+        .prompt
+        .input
+        .iter()
+        .filter_map(|item| match item {
+            ResponseItem::Message { role, .. } => Some(role.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(roles, ["developer", "user"]);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn missing_system_prompt_is_reported_to_executor() {
+    let (server, captured) = spawn_capturing().await;
+    post_chat(&server, &sample_payload()).await;
+
+    let payload = only_payload(&captured);
+    assert!(payload.system_prompt.is_none());
+    match payload.prompt.input.as_slice() {
+        [ResponseItem::Message { role, content, .. }] => {
+            assert_eq!(role, "user");
+            assert_eq!(
+                content,
+                &vec![ContentItem::InputText {
+                    text: "hello world".to_string()
+                }]
+            );
+        }
+        other => panic!("unexpected prompt input: {other:?}"),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn tools_and_tool_history_reach_executor() {
+    let (server, captured) = spawn_capturing().await;
+    post_chat(
+        &server,
+        &serde_json::json!({
+            "model": "gpt-5",
+            "messages": [
+                {"role": "user", "content": "weather in Paris?"},
+                {
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}
+                    }]
+                },
+                {"role": "tool", "tool_call_id": "call_1", "content": "sunny"}
+            ],
+            "tools": [{
+                "type": "function",
+                "function": {
+                    "name": "get_weather",
+                    "description": "  Look up the weather  ",
+                    "parameters": {"properties": {"city": {"type": "string"}}}
+                }
+            }]
+        }),
+    )
+    .await;
+
+    let payload = only_payload(&captured);
+    match payload.prompt.tools.as_slice() {
+        [ToolSpec::Function(tool)] => {
+            assert_eq!(tool.name, "get_weather");
+            assert_eq!(tool.description, "Look up the weather");
+            let schema = serde_json::to_value(&tool.parameters).expect("schema serializes");
+            assert_eq!(schema["type"], "object");
+            assert_eq!(schema["properties"]["city"]["type"], "string");
+        }
+        other => panic!("unexpected tools: {other:?}"),
+    }
+
+    let call = payload.prompt.input.iter().find_map(|item| match item {
+        ResponseItem::FunctionCall {
+            name,
+            arguments,
+            call_id,
+            ..
+        } => Some((name.as_str(), arguments.as_str(), call_id.as_str())),
+        _ => None,
+    });
+    assert_eq!(
+        call,
+        Some(("get_weather", "{\"city\":\"Paris\"}", "call_1"))
+    );
+    let output = payload.prompt.input.iter().find_map(|item| match item {
+        ResponseItem::FunctionCallOutput { call_id, output } => {
+            Some((call_id.as_str(), output.content.as_str()))
+        }
+        _ => None,
+    });
+    assert_eq!(output, Some(("call_1", "sunny")));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn reasoning_options_reach_executor() {
+    let (server, captured) = spawn_capturing().await;
+    let mut payload = sample_payload();
+    payload["reasoning"] = serde_json::json!({"effort": "low", "summary": "Detailed"});
+    payload["reasoning_effort"] = Value::from("high");
+    post_chat(&server, &payload).await;
+
+    let payload = only_payload(&captured);
+    assert_eq!(payload.reasoning_summary, Some(ReasoningSummary::Detailed));
+    // The flat field wins when both are present.
+    assert_eq!(payload.reasoning_effort, Some(ReasoningEffort::High));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn verbosity_reaches_executor() {
+    let (server, captured) = spawn_capturing().await;
+    let mut payload = sample_payload();
+    payload["verbosity"] = Value::from("High");
+    post_chat(&server, &payload).await;
+    assert_eq!(only_payload(&captured).verbosity, Some(Verbosity::High));
+
+    let (server, captured) = spawn_capturing().await;
+    payload["verbosity"] = Value::from("loud");
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&payload)
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: Value = response.json().await.expect("error body is JSON");
+    assert_eq!(
+        body["error"]["message"],
+        "verbosity: unsupported value `loud`; expected low, medium or high"
+    );
+    assert!(captured.lock().unwrap().is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn web_search_can_be_overridden_per_request() {
+    for (fields, expected) in [
+        (serde_json::json!({}), None),
+        (
+            serde_json::json!({"web_search_options": {"enabled": false}}),
+            Some(false),
+        ),
+        (serde_json::json!({"tool_choice": "none"}), Some(false)),
+        (
+            serde_json::json!({"tool_choice": "none", "web_search_options": {}}),
+            Some(false),
+        ),
+        (
+            serde_json::json!({"web_search_options": {"enabled": true}}),
+            Some(true),
+        ),
+        (serde_json::json!({"web_search_options": {}}), Some(true)),
+        (serde_json::json!({"tool_choice": "auto"}), None),
+    ] {
+        let (server, captured) = spawn_capturing().await;
+        let mut payload = sample_payload();
+        for (key, value) in fields.as_object().unwrap() {
+            payload[key] = value.clone();
+        }
+        post_chat(&server, &payload).await;
+        assert_eq!(only_payload(&captured).web_search, expected, "{fields}");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn invalid_reasoning_options_are_rejected() {
+    let (server, captured) = spawn_capturing().await;
+    let mut payload = sample_payload();
+    payload["reasoning"] = serde_json::json!({"summary": "verbose"});
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&payload)
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: Value = response.json().await.expect("error body is JSON");
+    assert_eq!(
+        body["error"]["message"],
+        "reasoning.summary: unsupported value `verbose`; expected auto, concise, detailed or none"
+    );
+    assert!(captured.lock().unwrap().is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn router_can_be_nested_under_a_prefix() {
+    let state = AppState::insecure_mock(true)
+        .with_executor(Arc::new(ScriptedChatExecutor::new(["nested"])))
+        .with_config(ServeConfig {
+            max_body_size: 4 * 1024,
+            ..ServeConfig::default()
+        });
+    let app = axum::Router::new().nest("/llm", router(state));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind test listener");
+    let base_url = format!("http://{}/llm", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move {
+        let _ = axum::serve(listener, app).await;
+    });
+
+    let client = reqwest::Client::new();
+    let health: Value = client
+        .get(format!("{base_url}/healthz"))
+        .send()
+        .await
+        .expect("healthz should respond")
+        .json()
+        .await
+        .expect("healthz must be JSON");
+    assert_eq!(health["ok"], Value::Bool(true));
+
+    let body = client
+        .post(format!("{base_url}/v1/chat/completions"))
+        .json(&sample_payload())
+        .send()
+        .await
+        .expect("chat should respond")
+        .json::<Value>()
+        .await
+        .expect("chat must be JSON");
+    assert_eq!(extract_message_content(&body).as_deref(), Some("nested"));
+
+    // The embedding app's config applies, not the process-wide defaults.
+    let oversized = client
+        .post(format!("{base_url}/v1/chat/completions"))
+        .json(&serde_json::json!({
+            "model": "gpt-5",
+            "messages": [{"role": "user", "content": "x".repeat(8 * 1024)}]
+        }))
+        .send()
+        .await
+        .expect("oversized chat should respond");
+    assert_eq!(oversized.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let unknown = client
+        .get(format!("{base_url}/v1/model"))
+        .send()
+        .await
+        .expect("unknown route should respond");
+    assert_eq!(unknown.status(), StatusCode::NOT_FOUND);
+    server.abort();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn stream_sends_first_bytes_before_slow_handshake() {
+    let handshake = std::time::Duration::from_millis(800);
+    let executor = ScriptedChatExecutor::new(["late"]).with_handshake_delay(handshake);
+    let server = TestServer::spawn_with_executor(Arc::new(executor))
+        .await
+        .expect("Codex Serve test server should start");
+
+    let started = std::time::Instant::now();
+    let mut response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&serde_json::json!({
+            "model": "gpt-5",
+            "stream": true,
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .send()
+        .await
+        .expect("stream should start");
+    assert_eq!(response.status(), StatusCode::OK);
+    let first = response
+        .chunk()
+        .await
+        .expect("first chunk")
+        .expect("stream has a first chunk");
+    let first_byte = started.elapsed();
+    assert!(
+        first_byte < handshake,
+        "first bytes took {first_byte:?}, handshake is {handshake:?}"
+    );
+    let first = String::from_utf8_lossy(&first);
+    assert!(first.contains(r#""role":"assistant""#), "{first}");
+
+    let mut rest = String::new();
+    while let Some(chunk) = response.chunk().await.expect("stream chunk") {
+        rest.push_str(&String::from_utf8_lossy(&chunk));
+    }
+    assert!(rest.contains(r#""content":"late""#), "{rest}");
+    assert!(rest.contains("data: [DONE]"), "{rest}");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn stream_reports_handshake_failure_in_band() {
+    let executor = ScriptedChatExecutor::failing(|| {
+        codex_serve::error::ApiError::rate_limited("Codex usage limit reached", None)
+    });
+    let server = TestServer::spawn_with_executor(Arc::new(executor))
+        .await
+        .expect("Codex Serve test server should start");
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .header("x-request-id", "req-handshake")
+        .json(&serde_json::json!({
+            "model": "gpt-5",
+            "stream": true,
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .send()
+        .await
+        .expect("stream should start");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.text().await.expect("stream body");
+    let events: Vec<&str> = body
+        .split("\n\n")
+        .filter_map(|frame| frame.strip_prefix("data: "))
+        .collect();
+    assert_eq!(events.len(), 3, "{body}");
+    let error: Value = serde_json::from_str(events[1]).expect("error event is JSON");
+    assert_eq!(error["error"]["code"], "RATE_LIMITED");
+    assert_eq!(error["error"]["request_id"], "req-handshake");
+    assert_eq!(events[2], "[DONE]");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn whole_message_fallback_is_streamed_in_bounded_chunks() {
+    let answer: String = "héllo wörld ✓ 😀 ".repeat(50 * 1024 / 24);
+    let message = answer.clone();
+    let executor = ScriptedChatExecutor::from_events(move || {
+        vec![
+            codex_core::ResponseEvent::OutputItemDone(ResponseItem::Message {
+                id: None,
+                role: "assistant".to_string(),
+                content: vec![ContentItem::OutputText {
+                    text: message.clone(),
+                }],
+            }),
+            codex_core::ResponseEvent::Completed {
+                response_id: "resp_whole".to_string(),
+                token_usage: None,
+            },
+        ]
+    });
+    let server = TestServer::spawn_with_executor(Arc::new(executor))
+        .await
+        .expect("Codex Serve test server should start");
+
+    let body = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&serde_json::json!({
+            "model": "gpt-5",
+            "stream": true,
+            "messages": [{"role": "user", "content": "write a lot"}]
+        }))
+        .send()
+        .await
+        .expect("stream should start")
+        .text()
+        .await
+        .expect("stream body");
+    let pieces: Vec<String> = body
+        .split("\n\n")
+        .filter_map(|frame| frame.strip_prefix("data: "))
+        .filter_map(|data| serde_json::from_str::<Value>(data).ok())
+        .filter_map(|chunk| {
+            chunk["choices"][0]["delta"]["content"]
+                .as_str()
+                .map(str::to_string)
+        })
+        .collect();
+
+    assert!(pieces.len() > 40, "only {} chunks", pieces.len());
+    assert!(pieces.iter().all(|piece| piece.len() <= 1024));
+    assert_eq!(pieces.concat(), answer);
+}
+
+/// Model ids listed by a mock server built with `config`, plus what `/healthz` reports for the
+/// reasoning-model flag.
+async fn listed_models(config: ServeConfig) -> (Vec<String>, Value) {
+    let server = TestServer::spawn_with_state(AppState::insecure_mock(true).with_config(config))
+        .await
+        .expect("Codex Serve test server should start");
+    let models = get_json(&server, "/v1/models").await;
+    let ids = models["data"]
+        .as_array()
+        .expect("model list")
+        .iter()
+        .map(|model| model["id"].as_str().expect("model id").to_string())
+        .collect();
+    let healthz = get_json(&server, "/healthz").await;
+    (ids, healthz["config"]["expose_reasoning_models"].clone())
+}
+
+// The next two tests run concurrently in this binary with opposite settings, each also setting
+// the process-wide config to the other value: a server only sees the config it was built with.
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn state_config_exposes_reasoning_models() {
+    configure(
+        ServeConfig::builder()
+            .expose_reasoning_models(false)
+            .build(),
+    );
+    let (exposed, flag) =
+        listed_models(ServeConfig::builder().expose_reasoning_models(true).build()).await;
+    let (plain, _) = listed_models(ServeConfig::default()).await;
+
+    assert_eq!(flag, true);
+    assert!(exposed.len() > plain.len(), "{exposed:?}");
+    assert!(plain.iter().all(|id| exposed.contains(id)));
+    assert!(
+        exposed.iter().any(|id| id.ends_with("-high")),
+        "{exposed:?}"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn state_config_hides_reasoning_models() {
+    configure(ServeConfig::builder().expose_reasoning_models(true).build());
+    let (ids, flag) = listed_models(
+        ServeConfig::builder()
+            .expose_reasoning_models(false)
+            .build(),
+    )
+    .await;
+
+    assert_eq!(flag, false);
+    assert!(!ids.is_empty());
+    assert!(!ids.iter().any(|id| id.ends_with("-high")), "{ids:?}");
+}
+
+fn degraded_state() -> AppState {
+    AppState::degraded(
+        InitOptions::default(),
+        &anyhow::anyhow!("No such file or directory (os error 2)")
+            .context("could not determine Codex home directory (run `codex` once)"),
+    )
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn degraded_startup_reports_the_error_on_healthz() {
+    let server = TestServer::spawn_with_state(degraded_state())
+        .await
+        .expect("Codex Serve test server should start");
+
+    let health = get_json(&server, "/healthz").await;
+    assert_eq!(health["ok"], false);
+    assert_eq!(health["authenticated"], false);
+    assert_eq!(
+        health["error"],
+        "could not determine Codex home directory (run `codex` once): No such file or directory (os error 2)"
+    );
+    assert!(
+        health["message"]
+            .as_str()
+            .is_some_and(|message| message.contains("codex login")),
+        "{health}"
+    );
+
+    // Metadata routes keep serving the static model list.
+    let models = get_json(&server, "/v1/models").await;
+    assert!(!models["data"].as_array().expect("model list").is_empty());
+    assert!(!tag_digests(&server).await.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn degraded_startup_answers_chat_with_503() {
+    let server = TestServer::spawn_with_state(degraded_state())
+        .await
+        .expect("Codex Serve test server should start");
+
+    for (path, body) in [
+        ("/v1/chat/completions", sample_payload()),
+        (
+            "/api/chat",
+            serde_json::json!({"model": "gpt-5", "messages": [{"role": "user", "content": "hi"}]}),
+        ),
+    ] {
+        let response = reqwest::Client::new()
+            .post(format!("{}{path}", server.base_url()))
+            .json(&body)
+            .send()
+            .await
+            .expect("request should reach Codex Serve");
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE, "{path}");
+        let body: Value = response.json().await.expect("error body");
+        let message = body["error"]["message"]
+            .as_str()
+            .or_else(|| body["error"].as_str())
+            .expect("error message");
+        assert!(
+            message.starts_with(
+                "Codex Serve could not initialize Codex: could not determine Codex home directory"
+            ),
+            "{path}: {message}"
+        );
+    }
+}
+
+fn scripted_token_usage() -> TokenUsage {
+    TokenUsage {
+        input_tokens: 12,
+        cached_input_tokens: 4,
+        output_tokens: 5,
+        reasoning_output_tokens: 2,
+        total_tokens: 23,
+    }
+}
+
+async fn spawn_with_token_usage(usage_extended: bool) -> TestServer {
+    let executor = ScriptedChatExecutor::from_events(|| {
+        vec![
+            codex_core::ResponseEvent::OutputTextDelta("ok".to_string()),
+            codex_core::ResponseEvent::Completed {
+                response_id: "resp_usage".to_string(),
+                token_usage: Some(scripted_token_usage()),
+            },
+        ]
+    });
+    let state = AppState::insecure_mock(true)
+        .with_config(
+            ServeConfig::builder()
+                .usage_extended(usage_extended)
+                .build(),
+        )
+        .with_executor(Arc::new(executor));
+    TestServer::spawn_with_state(state)
+        .await
+        .expect("Codex Serve test server should start")
+}
+
+fn raw_usage_json() -> Value {
+    serde_json::json!({
+        "input_tokens": 12,
+        "cached_input_tokens": 4,
+        "output_tokens": 5,
+        "reasoning_output_tokens": 2,
+        "total_tokens": 23
+    })
+}
+
+/// The last chunk before `[DONE]` of a streamed completion.
+async fn final_stream_chunk(server: &TestServer, user: &str) -> Value {
+    let body = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&serde_json::json!({
+            "model": "gpt-5",
+            "stream": true,
+            "user": user,
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .send()
+        .await
+        .expect("stream should start")
+        .text()
+        .await
+        .expect("stream body");
+    let last = body
+        .lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .rfind(|data| *data != "[DONE]")
+        .expect("at least one chunk");
+    serde_json::from_str(last).expect("chunk is JSON")
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn usage_extended_reports_raw_codex_usage() {
+    let server = spawn_with_token_usage(true).await;
+    let mut payload = sample_payload();
+    payload["user"] = "alice".into();
+
+    let body = post_chat(&server, &payload).await;
+    assert_eq!(body["codex_usage"], raw_usage_json());
+    assert_eq!(
+        body["usage"],
+        serde_json::json!({"prompt_tokens": 16, "completion_tokens": 7, "total_tokens": 23})
+    );
+
+    let chunk = final_stream_chunk(&server, "bob").await;
+    assert_eq!(chunk["codex_usage"], raw_usage_json());
+    assert_eq!(chunk["usage"]["prompt_tokens"], 16);
+
+    let health = get_json(&server, "/healthz").await;
+    let totals = &health["codex_usage"];
+    let mut twice = raw_usage_json();
+    for (_, count) in twice.as_object_mut().expect("object").iter_mut() {
+        *count = (count.as_i64().expect("count") * 2).into();
+    }
+    twice["requests"] = 2.into();
+    assert_eq!(totals["models"]["gpt-5"], twice, "{health}");
+    let mut once = raw_usage_json();
+    once["requests"] = 1.into();
+    assert_eq!(totals["clients"]["user:alice"], once, "{health}");
+    assert_eq!(totals["clients"]["user:bob"], once, "{health}");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn raw_codex_usage_is_opt_in() {
+    let server = spawn_with_token_usage(false).await;
+
+    let body = post_chat(&server, &sample_payload()).await;
+    assert!(body.get("codex_usage").is_none(), "{body}");
+    assert_eq!(body["usage"]["total_tokens"], 23);
+
+    let chunk = final_stream_chunk(&server, "bob").await;
+    assert!(chunk.get("codex_usage").is_none(), "{chunk}");
+    assert_eq!(chunk["usage"]["total_tokens"], 23);
+
+    let health = get_json(&server, "/healthz").await;
+    assert!(health.get("codex_usage").is_none(), "{health}");
+}
+
+/// Two tools the bridge has to rewrite: one schema lacks `type`s, the other cannot be parsed.
+fn request_with_degraded_tools(stream: bool) -> Value {
+    serde_json::json!({
+        "model": "gpt-5",
+        "stream": stream,
+        "messages": [{"role": "user", "content": "hi"}],
+        "tools": [
+            {"type": "function", "function": {
+                "name": "search",
+                "parameters": {"type": "object", "properties": {"query": {"description": "terms"}}}
+            }},
+            {"type": "function", "function": {
+                "name": "lookup",
+                "parameters": {"type": "object", "properties": {"id": {"type": "string"}}, "required": "id"}
+            }}
+        ]
+    })
+}
+
+fn warnings_header(response: &reqwest::Response) -> Vec<Value> {
+    let header = response
+        .headers()
+        .get(WARNINGS_HEADER.as_str())
+        .expect("warnings header")
+        .to_str()
+        .expect("ASCII header");
+    serde_json::from_str(header).expect("header is a JSON array")
+}
+
+fn warning_codes(warnings: &[Value]) -> Vec<(&str, &str)> {
+    warnings
+        .iter()
+        .map(|warning| {
+            let message = warning["message"].as_str().unwrap_or_default();
+            let tool = if message.starts_with("tool `search`") {
+                "search"
+            } else {
+                "lookup"
+            };
+            (warning["code"].as_str().unwrap_or_default(), tool)
+        })
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn degradations_are_reported_in_the_warnings_header() {
+    let server = TestServer::spawn_with_executor(Arc::new(ScriptedChatExecutor::new(["ok"])))
+        .await
+        .expect("Codex Serve test server should start");
+
+    for stream in [false, true] {
+        let response = reqwest::Client::new()
+            .post(format!("{}/v1/chat/completions", server.base_url()))
+            .json(&request_with_degraded_tools(stream))
+            .send()
+            .await
+            .expect("request should reach Codex Serve");
+        assert_eq!(response.status(), StatusCode::OK);
+        let warnings = warnings_header(&response);
+        assert_eq!(
+            warning_codes(&warnings),
+            [
+                ("tool_schema_sanitized", "search"),
+                ("tool_schema_replaced", "lookup")
+            ],
+            "stream={stream}: {warnings:?}"
+        );
+    }
+
+    let plain = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&sample_payload())
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert!(plain.headers().get(WARNINGS_HEADER.as_str()).is_none());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn fail_on_warnings_rejects_degraded_requests() {
+    let state = AppState::insecure_mock(true)
+        .with_config(ServeConfig::builder().fail_on_warnings(true).build())
+        .with_executor(Arc::new(ScriptedChatExecutor::new(["ok"])));
+    let server = TestServer::spawn_with_state(state)
+        .await
+        .expect("Codex Serve test server should start");
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&request_with_degraded_tools(false))
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: Value = response.json().await.expect("error body");
+    let message = body["error"]["message"].as_str().expect("error message");
+    assert!(
+        message.contains("tool_schema_sanitized: tool `search`"),
+        "{message}"
+    );
+    assert!(
+        message.contains("tool_schema_replaced: tool `lookup`"),
+        "{message}"
+    );
+
+    post_chat(&server, &sample_payload()).await;
+}
+
+/// A story whose first stream drops after half the text; the second request must carry the
+/// partial reply and a hint to continue it.
+fn interrupted_story(error: fn() -> codex_core::error::CodexErr) -> ScriptedChatExecutor {
+    ScriptedChatExecutor::conversation(vec![
+        ScriptedTurn::new(["user: Tell me a story"], || {
+            vec![
+                codex_core::ResponseEvent::OutputTextDelta("Once upon ".to_string()),
+                codex_core::ResponseEvent::OutputTextDelta("a time, there ".to_string()),
+            ]
+        })
+        .with_stream_error(error),
+        ScriptedTurn::new(
+            [
+                "user: Tell me a story",
+                "assistant: Once upon a time, there ",
+                "developer: Your previous reply was cut off by a connection error. Continue it \
+                 from exactly where it stopped, without repeating any of it.",
+            ],
+            || {
+                vec![
+                    codex_core::ResponseEvent::OutputTextDelta(
+                        "a time, there lived a fox.".to_string(),
+                    ),
+                    codex_core::ResponseEvent::Completed {
+                        response_id: "resp_resumed".to_string(),
+                        token_usage: Some(scripted_token_usage()),
+                    },
+                ]
+            },
+        ),
+    ])
+}
+
+async fn post_story(executor: ScriptedChatExecutor) -> reqwest::Response {
+    let server = TestServer::spawn_with_executor(Arc::new(executor))
+        .await
+        .expect("Codex Serve test server should start");
+    reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&serde_json::json!({
+            "model": "gpt-5",
+            "messages": [{"role": "user", "content": "Tell me a story"}]
+        }))
+        .send()
+        .await
+        .expect("request should reach Codex Serve")
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn broken_streams_are_resumed_once() {
+    let response = post_story(interrupted_story(|| {
+        codex_core::error::CodexErr::Stream("connection reset".to_string(), None)
+    }))
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = response.json().await.expect("response must be JSON");
+    assert_eq!(
+        extract_message_content(&body).as_deref(),
+        Some("Once upon a time, there lived a fox.")
+    );
+    assert_eq!(body["resumed"], true);
+    // The id is the request's own, not either upstream response's.
+    assert!(
+        body["id"]
+            .as_str()
+            .is_some_and(|id| id.starts_with("chatcmpl-"))
+    );
+    assert_eq!(body["usage"]["prompt_tokens"], 16);
+
+    // Plain replies carry no marker.
+    let (server, _) = spawn_capturing().await;
+    let body = post_chat(&server, &sample_payload()).await;
+    assert!(body.get("resumed").is_none(), "{body}");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn rate_limited_streams_are_not_resumed() {
+    let response = post_story(interrupted_story(|| {
+        codex_core::error::CodexErr::Stream(
+            "rate limited".to_string(),
+            Some(std::time::Duration::from_secs(3)),
+        )
+    }))
+    .await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+/// Streams the same scripted turn (text, a tool call, usage) with the given `Accept` header and
+/// returns the content type and the raw body.
+async fn stream_with_accept(server: &TestServer, accept: Option<&str>) -> (String, String) {
+    let mut request = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&serde_json::json!({
+            "model": "gpt-5",
+            "stream": true,
+            "messages": [{"role": "user", "content": "weather in Paris?"}]
+        }));
+    if let Some(accept) = accept {
+        request = request.header(reqwest::header::ACCEPT, accept);
+    }
+    let response = request.send().await.expect("stream should start");
+    assert_eq!(response.status(), StatusCode::OK);
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    (content_type, response.text().await.expect("stream body"))
+}
+
+/// Parsed chunks without `created`, which can differ between two requests.
+fn comparable_chunks<'a>(records: impl Iterator<Item = &'a str>) -> Vec<Value> {
+    records
+        .map(|record| {
+            let mut chunk: Value = serde_json::from_str(record).expect("chunk is JSON");
+            chunk
+                .as_object_mut()
+                .expect("chunk object")
+                .remove("created");
+            chunk
+        })
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn ndjson_accept_streams_the_same_chunks_as_sse() {
+    let executor = ScriptedChatExecutor::from_events(|| {
+        vec![
+            codex_core::ResponseEvent::OutputTextDelta("Checking".to_string()),
+            codex_core::ResponseEvent::OutputTextDelta(" now.".to_string()),
+            codex_core::ResponseEvent::OutputItemDone(ResponseItem::FunctionCall {
+                id: None,
+                name: "get_weather".to_string(),
+                arguments: r#"{"city":"Paris"}"#.to_string(),
+                call_id: "call_1".to_string(),
+            }),
+            codex_core::ResponseEvent::Completed {
+                response_id: "resp_framing".to_string(),
+                token_usage: Some(scripted_token_usage()),
+            },
+        ]
+    });
+    let server = TestServer::spawn_with_executor(Arc::new(executor))
+        .await
+        .expect("Codex Serve test server should start");
+
+    let (sse_type, sse_body) = stream_with_accept(&server, None).await;
+    assert!(sse_type.starts_with("text/event-stream"), "{sse_type}");
+    let mut events: Vec<&str> = sse_body
+        .split("\n\n")
+        .filter_map(|frame| frame.strip_prefix("data: "))
+        .collect();
+    assert_eq!(events.pop(), Some("[DONE]"), "{sse_body}");
+    let sse_chunks = comparable_chunks(events.into_iter());
+
+    for accept in [
+        "application/x-ndjson",
+        "application/json, application/x-ndjson",
+    ] {
+        let (ndjson_type, ndjson_body) = stream_with_accept(&server, Some(accept)).await;
+        assert_eq!(ndjson_type, "application/x-ndjson", "{accept}");
+        assert!(!ndjson_body.contains("data:"), "{ndjson_body}");
+        assert!(!ndjson_body.contains("[DONE]"), "{ndjson_body}");
+        assert!(ndjson_body.ends_with('\n'), "{ndjson_body}");
+        let ndjson_chunks = comparable_chunks(ndjson_body.lines());
+        assert_eq!(ndjson_chunks, sse_chunks, "{accept}");
+        let last = ndjson_chunks.last().expect("at least one chunk");
+        assert_eq!(last["usage"]["total_tokens"], 23, "{last}");
+        assert_eq!(last["choices"][0]["finish_reason"], "tool_calls", "{last}");
+    }
+
+    let (star_type, _) = stream_with_accept(&server, Some("*/*")).await;
+    assert!(star_type.starts_with("text/event-stream"), "{star_type}");
+}
+
+fn stream_payload() -> Value {
+    serde_json::json!({
+        "model": "gpt-5",
+        "stream": true,
+        "messages": [{"role": "user", "content": "hello world"}]
+    })
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn streaming_without_stream_support_is_unavailable_not_a_bad_request() {
+    let server = TestServer::spawn()
+        .await
+        .expect("Codex Serve test server should start");
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&stream_payload())
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body: Value = response.json().await.expect("error body is JSON");
+    assert_eq!(body["error"]["type"], "server_error");
+    assert_eq!(body["error"]["code"], "SERVICE_UNAVAILABLE");
+    let message = body["error"]["message"].as_str().expect("error message");
+    assert!(message.contains("cannot stream"), "{message}");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn stream_fallback_answers_with_a_json_completion() {
+    let server = TestServer::spawn_with_state(
+        AppState::insecure_mock(true)
+            .with_config(ServeConfig::builder().stream_fallback(true).build()),
+    )
+    .await
+    .expect("Codex Serve test server should start");
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&stream_payload())
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(
+        response.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("application/json")
+    );
+    let body: Value = response.json().await.expect("completion is JSON");
+    assert_eq!(body["object"], "chat.completion");
+    assert_eq!(
+        extract_message_content(&body).as_deref(),
+        Some("Hi there! You said: hello world")
+    );
+}
+
+fn prediction_payload(stream: bool) -> Value {
+    serde_json::json!({
+        "model": "gpt-5",
+        "stream": stream,
+        "stream_options": {"include_usage": true},
+        "messages": [{"role": "user", "content": "rename x to count"}],
+        "prediction": {"type": "content", "content": "let count = 1;"}
+    })
+}
+
+async fn spawn_predicting(
+    use_prediction_hint: bool,
+) -> (TestServer, Arc<Mutex<Vec<PromptPayload>>>) {
+    let executor = CapturingExecutor::new(Arc::new(ScriptedChatExecutor::new(["ok"])));
+    let captured = executor.captured();
+    let state = AppState::insecure_mock(true)
+        .with_config(
+            ServeConfig::builder()
+                .use_prediction_hint(use_prediction_hint)
+                .build(),
+        )
+        .with_executor(Arc::new(executor));
+    let server = TestServer::spawn_with_state(state)
+        .await
+        .expect("Codex Serve test server should start");
+    (server, captured)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn prediction_reaches_executor_as_a_trailing_hint() {
+    let (server, captured) = spawn_predicting(true).await;
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&prediction_payload(false))
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(WARNINGS_HEADER.as_str()).is_none());
+
+    let payload = only_payload(&captured);
+    assert_eq!(payload.prediction.as_deref(), Some("let count = 1;"));
+    match payload.prompt.input.last() {
+        Some(ResponseItem::Message { role, content, .. }) => {
+            assert_eq!(role, "developer");
+            assert!(matches!(
+                &content[..],
+                [ContentItem::InputText { text }] if text.ends_with("\n\nlet count = 1;")
+            ));
+        }
+        other => panic!("expected the prediction hint last, got {other:?}"),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn prediction_is_ignored_with_a_warning_by_default() {
+    let (server, captured) = spawn_predicting(false).await;
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&prediction_payload(false))
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(response.status(), StatusCode::OK);
+    let warnings = warnings_header(&response);
+    assert_eq!(warnings[0]["code"], "prediction_ignored", "{warnings:?}");
+
+    let payload = only_payload(&captured);
+    assert!(matches!(
+        payload.prompt.input.last(),
+        Some(ResponseItem::Message { role, .. }) if role == "user"
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn predicted_requests_report_prediction_token_counts() {
+    let (server, _) = spawn_predicting(true).await;
+    let body = post_chat(&server, &prediction_payload(false)).await;
+    let details = &body["usage"]["completion_tokens_details"];
+    assert_eq!(details["accepted_prediction_tokens"], 0, "{body}");
+    assert_eq!(details["rejected_prediction_tokens"], 0, "{body}");
+
+    let stream = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&prediction_payload(true))
+        .send()
+        .await
+        .expect("request should reach Codex Serve")
+        .text()
+        .await
+        .expect("stream body");
+    let usage = stream
+        .lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .filter_map(|data| serde_json::from_str::<Value>(data).ok())
+        .find_map(|chunk| chunk.get("usage").filter(|usage| !usage.is_null()).cloned())
+        .expect("a usage chunk");
+    let details = &usage["completion_tokens_details"];
+    assert_eq!(details["accepted_prediction_tokens"], 0, "{stream}");
+    assert_eq!(details["rejected_prediction_tokens"], 0, "{stream}");
+
+    // Requests without a prediction keep the lean usage object.
+    let plain = post_chat(&server, &sample_payload()).await;
+    assert!(plain["usage"].get("completion_tokens_details").is_none());
+}
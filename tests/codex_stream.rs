@@ -0,0 +1,218 @@
+//! `POST /v1/codex/stream` (`--enable-codex-stream`) sends the upstream Codex events as typed SSE
+//! events with every field intact, where the Chat Completions chunks would drop some.
+
+use std::sync::Arc;
+
+use codex_core::{
+    ContentItem, ResponseEvent, ResponseItem,
+    protocol::{RateLimitSnapshot, RateLimitWindow, TokenUsage},
+};
+use codex_protocol::models::WebSearchAction;
+use codex_serve::{
+    AppState, ServeConfig,
+    server::{ScriptedChatExecutor, TestServer},
+};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+
+fn search() -> ResponseItem {
+    ResponseItem::WebSearchCall {
+        id: Some("ws_1".to_string()),
+        status: Some("completed".to_string()),
+        action: WebSearchAction::Search {
+            query: Some("codex-serve release".to_string()),
+        },
+    }
+}
+
+fn call() -> ResponseItem {
+    ResponseItem::FunctionCall {
+        id: Some("fc_1".to_string()),
+        name: "open_page".to_string(),
+        arguments: r#"{"url":"https://example.com"}"#.to_string(),
+        call_id: "call_1".to_string(),
+    }
+}
+
+fn message() -> ResponseItem {
+    ResponseItem::Message {
+        id: Some("msg_1".to_string()),
+        role: "assistant".to_string(),
+        content: vec![ContentItem::OutputText {
+            text: "Released today.".to_string(),
+        }],
+    }
+}
+
+fn usage() -> TokenUsage {
+    TokenUsage {
+        input_tokens: 12,
+        cached_input_tokens: 4,
+        output_tokens: 7,
+        reasoning_output_tokens: 3,
+        total_tokens: 19,
+    }
+}
+
+fn rate_limits() -> RateLimitSnapshot {
+    RateLimitSnapshot {
+        primary: Some(RateLimitWindow {
+            used_percent: 42.5,
+            window_minutes: Some(300),
+            resets_at: Some(1_700_000_000),
+        }),
+        secondary: None,
+    }
+}
+
+fn events() -> Vec<ResponseEvent> {
+    vec![
+        ResponseEvent::Created,
+        ResponseEvent::RateLimits(rate_limits()),
+        ResponseEvent::OutputItemAdded(search()),
+        ResponseEvent::OutputItemDone(search()),
+        ResponseEvent::ReasoningSummaryPartAdded { summary_index: 0 },
+        ResponseEvent::ReasoningSummaryDelta {
+            delta: "Looking it up".to_string(),
+            summary_index: 0,
+        },
+        ResponseEvent::ReasoningContentDelta {
+            delta: "raw thought".to_string(),
+            content_index: 1,
+        },
+        ResponseEvent::OutputItemDone(call()),
+        ResponseEvent::OutputTextDelta("Released today.".to_string()),
+        ResponseEvent::OutputItemDone(message()),
+        ResponseEvent::Completed {
+            response_id: "resp_raw".to_string(),
+            token_usage: Some(usage()),
+        },
+    ]
+}
+
+async fn spawn(enabled: bool) -> TestServer {
+    let state = AppState::insecure_mock(true)
+        .with_config(ServeConfig::builder().enable_codex_stream(enabled).build())
+        .with_executor(Arc::new(ScriptedChatExecutor::from_events(events)));
+    TestServer::spawn_with_state(state)
+        .await
+        .expect("Codex Serve test server should start")
+}
+
+async fn post(server: &TestServer, body: Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/v1/codex/stream", server.base_url()))
+        .json(&body)
+        .send()
+        .await
+        .expect("request should reach Codex Serve")
+}
+
+/// The `(event, data)` pairs of an SSE body.
+fn parse_events(body: &str) -> Vec<(String, Value)> {
+    body.split("\n\n")
+        .filter(|block| !block.trim().is_empty())
+        .map(|block| {
+            let field = |name: &str| {
+                block
+                    .lines()
+                    .find_map(|line| line.strip_prefix(name))
+                    .map(|value| value.trim_start().to_string())
+                    .unwrap_or_else(|| panic!("no {name} in {block:?}"))
+            };
+            let data = serde_json::from_str(&field("data:")).expect("data is JSON");
+            (field("event:"), data)
+        })
+        .collect()
+}
+
+fn item(item: ResponseItem) -> Value {
+    json!({ "item": item })
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn every_event_arrives_with_its_payload() {
+    let server = spawn(true).await;
+    let response = post(
+        &server,
+        json!({
+            "model": "gpt-5",
+            "messages": [{"role": "user", "content": "Is it out?"}]
+        }),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.text().await.expect("stream body");
+
+    let expected = vec![
+        ("created", json!({})),
+        ("rate_limits", json!(rate_limits())),
+        ("output_item.added", item(search())),
+        ("output_item.done", item(search())),
+        ("reasoning.summary.part_added", json!({"summary_index": 0})),
+        (
+            "reasoning.summary.delta",
+            json!({"delta": "Looking it up", "summary_index": 0}),
+        ),
+        (
+            "reasoning.content.delta",
+            json!({"delta": "raw thought", "content_index": 1}),
+        ),
+        ("output_item.done", item(call())),
+        ("output_text.delta", json!({"delta": "Released today."})),
+        ("output_item.done", item(message())),
+        (
+            "completed",
+            json!({"response_id": "resp_raw", "token_usage": usage()}),
+        ),
+    ];
+    let expected: Vec<(String, Value)> = expected
+        .into_iter()
+        .map(|(name, data)| (name.to_string(), data))
+        .collect();
+    assert_eq!(parse_events(&body), expected);
+
+    // Items survive as Codex serializes them, so they read back as the same values.
+    let (_, done) = &parse_events(&body)[7];
+    let read_back: ResponseItem =
+        serde_json::from_value(done["item"].clone()).expect("a Codex response item");
+    assert_eq!(json!(read_back), json!(call()));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn requests_are_converted_like_chat_requests() {
+    let server = spawn(true).await;
+    // The endpoint always streams, so `codex.samples` is refused as on a streaming chat request.
+    let response = post(
+        &server,
+        json!({
+            "model": "gpt-5",
+            "messages": [{"role": "user", "content": "hi"}],
+            "codex": {"samples": 3}
+        }),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = post(&server, json!({"model": "gpt-5", "messages": []})).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn the_endpoint_is_off_by_default() {
+    let server = spawn(false).await;
+    let response = post(
+        &server,
+        json!({
+            "model": "gpt-5",
+            "messages": [{"role": "user", "content": "hi"}]
+        }),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let body: Value = response.json().await.expect("JSON error");
+    assert_eq!(
+        body["error"]["message"],
+        "`/v1/codex/stream` is not served on this listener (see `--enable-codex-stream`)"
+    );
+}
@@ -0,0 +1,122 @@
+//! `GET /stats/conversations` and the `x-codex-serve-usage` header: turns that share a system
+//! prompt and first user message are booked together, with the share of cached prompt tokens.
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicI64, Ordering},
+};
+
+use codex_core::{ResponseEvent, protocol::TokenUsage};
+use codex_serve::{
+    AppState,
+    server::{ScriptedChatExecutor, TestServer, USAGE_HEADER},
+};
+use serde_json::{Value, json};
+
+/// Every turn sends 100 new prompt tokens; all earlier ones are served from the cache.
+async fn spawn() -> TestServer {
+    let turns = Arc::new(AtomicI64::new(0));
+    let executor = ScriptedChatExecutor::from_events(move || {
+        let cached = turns.fetch_add(1, Ordering::SeqCst) * 100;
+        vec![
+            ResponseEvent::OutputTextDelta("ok".to_string()),
+            ResponseEvent::Completed {
+                response_id: "resp_turn".to_string(),
+                token_usage: Some(TokenUsage {
+                    input_tokens: 100,
+                    cached_input_tokens: cached,
+                    output_tokens: 5,
+                    reasoning_output_tokens: 0,
+                    total_tokens: 105 + cached,
+                }),
+            },
+        ]
+    });
+    let state = AppState::insecure_mock(true).with_executor(Arc::new(executor));
+    TestServer::spawn_with_state(state)
+        .await
+        .expect("Codex Serve test server should start")
+}
+
+fn turn(first: &str, later: &[&str], stream: bool) -> Value {
+    let mut messages = vec![
+        json!({"role": "system", "content": "be brief"}),
+        json!({"role": "user", "content": first}),
+    ];
+    for message in later {
+        messages.push(json!({"role": "assistant", "content": "ok"}));
+        messages.push(json!({"role": "user", "content": message}));
+    }
+    json!({"model": "gpt-5", "stream": stream, "messages": messages})
+}
+
+async fn post(server: &TestServer, body: Value) -> reqwest::Response {
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&body)
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert!(response.status().is_success());
+    response
+}
+
+async fn stats(server: &TestServer) -> Vec<Value> {
+    let body: Value = reqwest::get(format!("{}/stats/conversations", server.base_url()))
+        .await
+        .expect("request should reach Codex Serve")
+        .json()
+        .await
+        .expect("stats must be JSON");
+    body["conversations"]
+        .as_array()
+        .expect("conversations list")
+        .clone()
+}
+
+#[tokio::test]
+async fn turns_of_a_conversation_share_its_cache_stats() {
+    let server = spawn().await;
+    let first = post(&server, turn("plan a trip", &[], false)).await;
+    assert_eq!(
+        first.headers()[USAGE_HEADER],
+        "input_tokens=100, cached_input_tokens=0, output_tokens=5"
+    );
+    let second = post(&server, turn("plan a trip", &["to Oslo"], false)).await;
+    assert_eq!(
+        second.headers()[USAGE_HEADER],
+        "input_tokens=100, cached_input_tokens=100, output_tokens=5"
+    );
+    // Streamed turns are booked too, once the stream completes.
+    post(&server, turn("plan a trip", &["to Oslo", "in May"], true))
+        .await
+        .text()
+        .await
+        .expect("stream body");
+
+    let conversations = stats(&server).await;
+    assert_eq!(conversations.len(), 1);
+    let conversation = &conversations[0];
+    assert_eq!(conversation["turns"], 3);
+    assert_eq!(conversation["prompt_tokens"], 600);
+    assert_eq!(conversation["cached_tokens"], 300);
+    assert_eq!(conversation["output_tokens"], 15);
+    assert_eq!(conversation["last_turn_cached_tokens"], 200);
+    assert_eq!(conversation["cache_hit_ratio"], 0.5);
+}
+
+#[tokio::test]
+async fn a_new_first_message_starts_a_new_conversation() {
+    let server = spawn().await;
+    post(&server, turn("plan a trip", &[], false)).await;
+    post(&server, turn("write a poem", &[], false)).await;
+
+    let conversations = stats(&server).await;
+    assert_eq!(conversations.len(), 2);
+    assert_ne!(
+        conversations[0]["conversation"],
+        conversations[1]["conversation"]
+    );
+    assert_eq!(conversations[0]["cached_tokens"], 100, "most recent first");
+    assert!(conversations.iter().all(|stats| stats["turns"] == 1));
+}
@@ -0,0 +1,211 @@
+//! Every front-end converts under the `ConversionOptions` its endpoint gets from the server's
+//! settings, so the same messages reach Codex the same way through the OpenAI and Ollama APIs.
+//! Where they differ, it is on purpose and pinned here.
+
+use std::sync::{Arc, Mutex};
+
+use codex_serve::{
+    AppState, PromptPayload, ServeConfig,
+    server::{CapturingExecutor, ScriptedChatExecutor, TestServer, describe_input},
+};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+
+const CHAT: &str = "/v1/chat/completions";
+const OLLAMA_CHAT: &str = "/api/chat";
+const OLLAMA_GENERATE: &str = "/api/generate";
+
+struct Server {
+    server: TestServer,
+    captured: Arc<Mutex<Vec<PromptPayload>>>,
+}
+
+/// What a request reached Codex as: its input items and the client's system prompt.
+#[derive(Debug, PartialEq)]
+struct Converted {
+    input: Vec<String>,
+    system_prompt: Option<String>,
+}
+
+impl Server {
+    async fn spawn(config: ServeConfig) -> Self {
+        let executor = CapturingExecutor::new(Arc::new(ScriptedChatExecutor::new(["ok"])));
+        let captured = executor.captured();
+        let state = AppState::insecure_mock(true)
+            .with_config(config)
+            .with_executor(Arc::new(executor));
+        let server = TestServer::spawn_with_state(state)
+            .await
+            .expect("Codex Serve test server should start");
+        Self { server, captured }
+    }
+
+    async fn post(&self, path: &str, mut body: Value) -> reqwest::Response {
+        body["model"] = json!("gpt-5");
+        body["stream"] = json!(false);
+        reqwest::Client::new()
+            .post(format!("{}{path}", self.server.base_url()))
+            .json(&body)
+            .send()
+            .await
+            .expect("request should reach Codex Serve")
+    }
+
+    async fn convert(&self, path: &str, body: Value) -> Converted {
+        let response = self.post(path, body).await;
+        let status = response.status();
+        assert_eq!(
+            status,
+            StatusCode::OK,
+            "{path}: {}",
+            response.text().await.unwrap()
+        );
+        let payload = self
+            .captured
+            .lock()
+            .unwrap()
+            .pop()
+            .expect("the request should reach the executor");
+        Converted {
+            input: describe_input(&payload.prompt.input),
+            system_prompt: payload.system_prompt,
+        }
+    }
+
+    /// Converts `messages` through the OpenAI and Ollama chat endpoints.
+    async fn convert_both(&self, messages: Value) -> (Converted, Converted) {
+        let openai = self.convert(CHAT, json!({"messages": messages})).await;
+        let ollama = self
+            .convert(OLLAMA_CHAT, json!({"messages": messages}))
+            .await;
+        (openai, ollama)
+    }
+}
+
+fn conversation() -> Value {
+    json!([
+        {"role": "system", "content": "Be terse."},
+        {"role": "user", "content": "2+2?"},
+        {"role": "assistant", "content": "4"},
+        {"role": "user", "content": "   "},
+        {"role": "Human", "content": "and 3+3?"}
+    ])
+}
+
+#[tokio::test]
+async fn the_same_messages_convert_the_same_way_under_each_setting() {
+    let lenient = Server::spawn(ServeConfig::default()).await;
+    let (openai, ollama) = lenient.convert_both(conversation()).await;
+    assert_eq!(openai, ollama);
+    assert_eq!(
+        openai.input,
+        [
+            "developer: Be terse.",
+            "user: 2+2?",
+            "assistant: 4",
+            "user: and 3+3?"
+        ]
+    );
+    assert_eq!(openai.system_prompt.as_deref(), Some("Be terse."));
+
+    let keeping = Server::spawn(ServeConfig::builder().keep_empty_messages(true).build()).await;
+    let (openai, ollama) = keeping.convert_both(conversation()).await;
+    assert_eq!(openai, ollama);
+    assert_eq!(openai.input[3], "user:    ");
+
+    let strict = Server::spawn(ServeConfig::builder().strict_params(true).build()).await;
+    for path in [CHAT, OLLAMA_CHAT] {
+        let response = strict.post(path, json!({"messages": conversation()})).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{path}");
+    }
+    assert!(strict.captured.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn tool_call_histories_pair_up_the_same_way() {
+    let server = Server::spawn(ServeConfig::default()).await;
+    let openai = server
+        .convert(
+            CHAT,
+            json!({"messages": [
+                {"role": "user", "content": "weather?"},
+                {"role": "assistant", "content": "", "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}
+                }]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "18°C"}
+            ]}),
+        )
+        .await;
+    // Ollama's own shapes: arguments as an object, the result naming its tool.
+    let ollama = server
+        .convert(
+            OLLAMA_CHAT,
+            json!({"messages": [
+                {"role": "user", "content": "weather?"},
+                {"role": "assistant", "content": "", "tool_calls": [
+                    {"function": {"name": "get_weather", "arguments": {"city": "Paris"}}}
+                ]},
+                {"role": "tool", "tool_name": "get_weather", "content": "18°C"}
+            ]}),
+        )
+        .await;
+    assert_eq!(openai, ollama);
+    assert_eq!(
+        openai.input,
+        [
+            "user: weather?",
+            r#"function_call call_1 get_weather({"city":"Paris"})"#,
+            "function_call_output call_1: 18°C"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn intentional_differences_between_the_front_ends() {
+    // Ollama clients send `""` beside every tool call, so it is never a message of its own; an
+    // OpenAI client that keeps empty messages gets its blank text sent.
+    let server = Server::spawn(ServeConfig::builder().keep_empty_messages(true).build()).await;
+    let calls = |arguments: Value| {
+        json!({"messages": [
+            {"role": "user", "content": "weather?"},
+            {"role": "assistant", "content": "", "tool_calls": [{
+                "id": "call_1",
+                "type": "function",
+                "function": {"name": "get_weather", "arguments": arguments}
+            }]}
+        ]})
+    };
+    let openai = server
+        .convert(CHAT, calls(json!("{\"city\":\"Paris\"}")))
+        .await;
+    let ollama = server
+        .convert(OLLAMA_CHAT, calls(json!({"city": "Paris"})))
+        .await;
+    assert_eq!(openai.input.len(), ollama.input.len() + 1);
+    assert_eq!(openai.input[2], "assistant: ");
+
+    // `/api/generate` is instruction-style: a system prompt alone is a prompt, converted as the
+    // chat endpoints convert a system-only conversation.
+    let server = Server::spawn(ServeConfig::default()).await;
+    let generate = server
+        .convert(OLLAMA_GENERATE, json!({"system": "Write a haiku."}))
+        .await;
+    let (openai, ollama) = server
+        .convert_both(json!([{"role": "system", "content": "Write a haiku."}]))
+        .await;
+    assert_eq!(generate, openai);
+    assert_eq!(generate, ollama);
+
+    // With nothing left to send, the chat endpoints refuse the request; Ollama's chat takes an
+    // empty message list as a model load instead, as Ollama does.
+    let blank = json!({"messages": [{"role": "user", "content": " "}]});
+    for path in [CHAT, OLLAMA_CHAT] {
+        let response = server.post(path, blank.clone()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{path}");
+    }
+    let load = server.post(OLLAMA_CHAT, json!({"messages": []})).await;
+    assert_eq!(load.status(), StatusCode::OK);
+    assert!(server.captured.lock().unwrap().is_empty());
+}
@@ -0,0 +1,200 @@
+//! `codex_serve::convert`: requests converted to a prompt and rendered back keep their roles,
+//! text, tool calls and tool outputs, and convert to the same prompt again.
+
+use codex_core::ToolSpec;
+use codex_serve::convert::{
+    ChatCompletionRequest, ChatMessage, PromptPayload, messages_from_items, request_from_payload,
+};
+use serde_json::{Value, json};
+
+/// Representative requests, written the way the converter renders them back (lower-case roles,
+/// ids on every tool call) so the messages can be compared directly.
+fn corpus() -> Vec<Value> {
+    vec![
+        json!({"messages": [{"role": "user", "content": "hello"}]}),
+        json!({
+            "model": "gpt-5-codex",
+            "messages": [
+                {"role": "system", "content": "Be terse."},
+                {"role": "user", "content": "2+2?"},
+                {"role": "assistant", "content": "4"},
+                {"role": "user", "content": "and 3+3?"}
+            ]
+        }),
+        json!({"messages": [{"role": "user", "content": [
+            {"type": "text", "text": "what is this?"},
+            {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}},
+            {"type": "text", "text": "be brief"}
+        ]}]}),
+        json!({
+            "messages": [
+                {"role": "user", "content": "weather in Paris?"},
+                {"role": "assistant", "content": "Let me check.", "tool_calls": [{
+                    "id": "call_1", "type": "function",
+                    "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}
+                }]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "18C, sunny"},
+                {"role": "assistant", "content": "18C and sunny."}
+            ],
+            "tools": [{"type": "function", "function": {
+                "name": "get_weather",
+                "description": "Current weather",
+                "parameters": {
+                    "type": "object",
+                    "properties": {"city": {"type": "string"}},
+                    "required": ["city"]
+                }
+            }}]
+        }),
+        json!({
+            "messages": [
+                {"role": "user", "content": "screenshot both pages"},
+                {"role": "assistant", "content": null, "tool_calls": [
+                    {"id": "call_a", "type": "function",
+                     "function": {"name": "screenshot", "arguments": "{\"page\":1}"}},
+                    {"id": "call_b", "type": "function",
+                     "function": {"name": "screenshot", "arguments": "{\"page\":2}"}}
+                ]},
+                {"role": "tool", "tool_call_id": "call_a", "content": "page one is blank"},
+                {"role": "tool", "tool_call_id": "call_b", "content": [
+                    {"type": "text", "text": "page two"},
+                    {"type": "image_url", "image_url": {"url": "https://example.com/2.png"}}
+                ]}
+            ],
+            "tools": [{"type": "function", "function": {
+                "name": "screenshot",
+                "strict": true,
+                "parameters": {"type": "object", "properties": {"page": {"type": "integer"}}}
+            }}],
+            "parallel_tool_calls": false,
+            "reasoning_effort": "high",
+            "reasoning": {"summary": "detailed"},
+            "temperature": 0.2,
+            "verbosity": "low",
+            "web_search_options": {"enabled": false}
+        }),
+    ]
+}
+
+/// Requests in the shapes clients actually send, which only need to convert the same way twice.
+fn loose_corpus() -> Vec<Value> {
+    vec![
+        json!({"messages": [
+            {"role": "System", "content": {"text": "rules"}},
+            {"role": "", "content": [{"text": "untyped part"}]}
+        ]}),
+        json!({"messages": [
+            {"role": "user", "content": "go"},
+            {"role": "assistant", "tool_calls": [
+                {"function": {"name": "run", "arguments": null}}
+            ]},
+            {"role": "tool", "tool_call_id": "call_0", "content": "done"}
+        ], "tools": [{"type": "function", "function": {
+            "name": "run", "parameters": {"properties": {"flag": true}}
+        }}]}),
+    ]
+}
+
+fn parse(value: Value) -> ChatCompletionRequest {
+    serde_json::from_value(value).expect("corpus requests deserialize")
+}
+
+fn convert(request: &ChatCompletionRequest) -> PromptPayload {
+    request.to_prompt().expect("corpus requests convert")
+}
+
+/// A message as compared across the round trip: content as typed parts, whatever its shape.
+fn shape(message: &ChatMessage) -> Value {
+    let parts: Vec<Value> = match &message.content {
+        Value::Null => Vec::new(),
+        Value::String(text) => vec![json!({"type": "text", "text": text})],
+        Value::Array(parts) => parts.clone(),
+        other => vec![other.clone()],
+    };
+    json!({
+        "role": message.role,
+        "content": parts,
+        "tool_calls": message.tool_calls,
+        "tool_call_id": message.tool_call_id,
+    })
+}
+
+/// Everything of a converted payload that reaches the upstream or picks the upstream options.
+fn prompt_fingerprint(payload: &PromptPayload) -> Value {
+    let tools: Vec<Value> = payload
+        .prompt
+        .tools
+        .iter()
+        .map(|spec| match spec {
+            ToolSpec::Function(tool) => json!({
+                "name": tool.name,
+                "description": tool.description,
+                "strict": tool.strict,
+                "parameters": tool.parameters,
+            }),
+            _ => json!("non-function tool"),
+        })
+        .collect();
+    json!({
+        "model": payload.model,
+        "input": payload.prompt.input,
+        "tools": tools,
+        "parallel_tool_calls": payload.prompt.parallel_tool_calls,
+        "system_prompt": payload.system_prompt,
+        "first_user_message": payload.first_user_message,
+        "reasoning_effort": payload.reasoning_effort,
+        "reasoning_summary": payload.reasoning_summary,
+        "temperature": payload.temperature,
+        "top_p": payload.top_p,
+        "verbosity": payload.verbosity,
+        "web_search": payload.web_search,
+    })
+}
+
+#[test]
+fn round_trips_preserve_roles_text_and_tool_traffic() {
+    for value in corpus() {
+        let request = parse(value.clone());
+        let rendered = request_from_payload(&convert(&request));
+        let expected: Vec<Value> = request.messages.iter().map(shape).collect();
+        let actual: Vec<Value> = rendered.messages.iter().map(shape).collect();
+        assert_eq!(actual, expected, "{value}");
+
+        let declared: Vec<_> = request
+            .tools
+            .iter()
+            .map(|tool| tool.function.as_ref().and_then(|f| f.name.clone()))
+            .collect();
+        let restored: Vec<_> = rendered
+            .tools
+            .iter()
+            .map(|tool| tool.function.as_ref().and_then(|f| f.name.clone()))
+            .collect();
+        assert_eq!(restored, declared, "{value}");
+    }
+}
+
+#[test]
+fn rendered_requests_convert_to_the_same_prompt() {
+    for value in corpus().into_iter().chain(loose_corpus()) {
+        let first = convert(&parse(value.clone()));
+        let rendered = request_from_payload(&first);
+        let reparsed = parse(serde_json::to_value(&rendered).expect("rendered request serializes"));
+        assert_eq!(
+            prompt_fingerprint(&convert(&reparsed)),
+            prompt_fingerprint(&first),
+            "{value}"
+        );
+    }
+}
+
+#[test]
+fn to_prompt_leaves_the_request_reusable() {
+    let request = parse(corpus().swap_remove(3));
+    let first = prompt_fingerprint(&convert(&request));
+    assert_eq!(prompt_fingerprint(&convert(&request)), first);
+    assert_eq!(
+        messages_from_items(&convert(&request).prompt.input).len(),
+        request.messages.len()
+    );
+}
@@ -0,0 +1,157 @@
+//! Dry runs: `x-codex-serve-dry-run` or `?dry_run=true` returns the prompt a chat request would
+//! send upstream, developer prompt and converted tools included, without calling upstream.
+
+use std::sync::{Arc, Mutex};
+
+use codex_serve::{
+    AppState, PromptPayload, ServeConfig,
+    serve_config::DeveloperPromptMode,
+    server::{CapturingExecutor, DRY_RUN_HEADER, ScriptedChatExecutor, TestServer},
+};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+
+fn request() -> Value {
+    json!({
+        "model": "gpt-5",
+        "stream": true,
+        "messages": [
+            {"role": "user", "content": "What is the weather in Paris?"},
+            {
+                "role": "assistant",
+                "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {"name": "lookup_weather", "arguments": "{\"city\":\"Paris\"}"}
+                }]
+            },
+            {"role": "tool", "tool_call_id": "call_1", "content": "18°C and sunny"}
+        ],
+        "tools": [{
+            "type": "function",
+            "function": {
+                "name": "lookup_weather",
+                "description": "Current weather for a city.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {"city": {"type": "string"}},
+                    "required": ["city"]
+                }
+            }
+        }]
+    })
+}
+
+async fn spawn(config: ServeConfig) -> (TestServer, Arc<Mutex<Vec<PromptPayload>>>) {
+    let executor = CapturingExecutor::new(Arc::new(ScriptedChatExecutor::new(["sunny"])));
+    let calls = executor.captured();
+    let state = AppState::insecure_mock(true)
+        .with_config(config)
+        .with_executor(Arc::new(executor));
+    let server = TestServer::spawn_with_state(state)
+        .await
+        .expect("Codex Serve test server should start");
+    (server, calls)
+}
+
+#[tokio::test]
+async fn a_dry_run_returns_the_prompt_without_calling_upstream() {
+    let (server, calls) = spawn(ServeConfig::default()).await;
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .header(DRY_RUN_HEADER, "true")
+        .json(&request())
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = response.json().await.expect("dry run body is JSON");
+
+    assert_eq!(body["object"], "codex.dry_run");
+    assert_eq!(body["model"], "gpt-5");
+    let input = body["input"].as_array().expect("input items");
+    assert_eq!(input[0]["type"], "message");
+    assert_eq!(input[0]["role"], "developer");
+    let developer = input[0]["text"].as_str().unwrap();
+    assert!(
+        developer.contains("Codex Serve compatibility mode"),
+        "{developer}"
+    );
+    assert!(developer.contains("lookup_weather"), "{developer}");
+    assert_eq!(input[1]["role"], "user");
+    assert_eq!(input[1]["text"], "What is the weather in Paris?");
+    assert_eq!(input[2]["type"], "function_call");
+    assert_eq!(input[2]["name"], "lookup_weather");
+    assert_eq!(input[2]["arguments"], "{\"city\":\"Paris\"}");
+    assert_eq!(input[3]["type"], "function_call_output");
+    assert_eq!(input[3]["output"], "18°C and sunny");
+
+    let tools = body["tools"].as_array().expect("tool specs");
+    assert_eq!(tools.len(), 1);
+    assert_eq!(tools[0]["type"], "function");
+    assert_eq!(tools[0]["name"], "lookup_weather");
+    assert_eq!(tools[0]["parameters"]["required"], json!(["city"]));
+
+    assert!(
+        calls.lock().unwrap().is_empty(),
+        "upstream must not be called"
+    );
+}
+
+#[tokio::test]
+async fn the_developer_prompt_mode_applies() {
+    let config = ServeConfig::builder()
+        .developer_prompt_mode(DeveloperPromptMode::Disabled)
+        .build();
+    let (server, _) = spawn(config).await;
+    let body: Value = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .header(DRY_RUN_HEADER, "true")
+        .json(&request())
+        .send()
+        .await
+        .expect("request should reach Codex Serve")
+        .json()
+        .await
+        .expect("dry run body is JSON");
+    let input = body["input"].as_array().expect("input items");
+    assert_eq!(input[0]["role"], "user", "{body}");
+    assert!(input.iter().all(|item| item["role"] != "developer"));
+}
+
+#[tokio::test]
+async fn the_query_parameter_works_and_redaction_applies() {
+    let (server, calls) = spawn(ServeConfig::builder().verbose_redact(true).build()).await;
+    let body: Value = reqwest::Client::new()
+        .post(format!(
+            "{}/v1/chat/completions?dry_run=true",
+            server.base_url()
+        ))
+        .json(&request())
+        .send()
+        .await
+        .expect("request should reach Codex Serve")
+        .json()
+        .await
+        .expect("dry run body is JSON");
+
+    assert_eq!(body["object"], "codex.dry_run");
+    assert_eq!(body["input"][1]["role"], "user");
+    assert_eq!(body["input"][1]["text"], "[redacted: 29 chars]");
+    assert_eq!(body["tools"][0]["name"], "lookup_weather");
+    assert!(calls.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn dry_runs_can_be_disabled() {
+    let (server, calls) = spawn(ServeConfig::builder().disable_dry_run(true).build()).await;
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .header(DRY_RUN_HEADER, "true")
+        .json(&request())
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(calls.lock().unwrap().is_empty());
+}
@@ -0,0 +1,282 @@
+//! Gemini `generateContent` and `streamGenerateContent`: a text reply and a function-calling loop
+//! must come out the same whether the client asks for one reply or an `alt=sse` stream.
+
+use std::sync::Arc;
+
+use codex_core::{ResponseEvent, ResponseItem, protocol::TokenUsage};
+use codex_serve::{
+    AppState,
+    server::{ScriptedChatExecutor, ScriptedTurn, TestServer},
+};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+
+const QUESTION: &str = "What's the weather in Paris?";
+
+fn completed(id: &str) -> ResponseEvent {
+    ResponseEvent::Completed {
+        response_id: id.to_string(),
+        token_usage: Some(TokenUsage {
+            input_tokens: 12,
+            cached_input_tokens: 4,
+            output_tokens: 5,
+            reasoning_output_tokens: 2,
+            total_tokens: 23,
+        }),
+    }
+}
+
+async fn spawn(executor: ScriptedChatExecutor) -> TestServer {
+    let state = AppState::insecure_mock(true).with_executor(Arc::new(executor));
+    TestServer::spawn_with_state(state)
+        .await
+        .expect("Codex Serve test server should start")
+}
+
+/// Posts one turn and returns the candidate the way a Gemini client rebuilds it: as is for a
+/// single reply, or with the parts of every streamed chunk merged.
+async fn send_turn(server: &TestServer, body: &Value, stream: bool) -> Value {
+    let method = if stream {
+        "streamGenerateContent?alt=sse"
+    } else {
+        "generateContent"
+    };
+    let response = reqwest::Client::new()
+        .post(format!(
+            "{}/v1beta/models/models/gpt-5:{method}",
+            server.base_url()
+        ))
+        .json(body)
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    let status = response.status();
+    let content_type = response.headers()["content-type"].clone();
+    let text = response.text().await.expect("response body");
+    assert_eq!(status, StatusCode::OK, "{text}");
+
+    if !stream {
+        let reply: Value = serde_json::from_str(&text).expect("reply is JSON");
+        assert_eq!(reply["modelVersion"], "gpt-5");
+        return reply;
+    }
+    assert_eq!(content_type, "text/event-stream");
+    let chunks: Vec<Value> = text
+        .lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .map(|data| serde_json::from_str(data).expect("chunk is JSON"))
+        .collect();
+    let (last, deltas) = chunks.split_last().expect("at least one chunk");
+    let mut thought = String::new();
+    let mut answer = String::new();
+    let mut parts = Vec::new();
+    for chunk in &chunks {
+        assert!(chunk.get("error").is_none(), "stream failed: {chunk}");
+        for part in chunk["candidates"][0]["content"]["parts"]
+            .as_array()
+            .expect("parts")
+        {
+            match part["text"].as_str() {
+                Some(text) if part["thought"] == true => thought.push_str(text),
+                Some(text) => answer.push_str(text),
+                None => parts.push(part.clone()),
+            }
+        }
+    }
+    assert!(
+        deltas
+            .iter()
+            .all(|chunk| chunk["candidates"][0].get("finishReason").is_none())
+    );
+    if !thought.is_empty() {
+        parts.insert(0, json!({"text": thought, "thought": true}));
+    }
+    if !answer.is_empty() {
+        parts.insert(usize::from(!thought.is_empty()), json!({"text": answer}));
+    }
+    json!({
+        "candidates": [{
+            "content": {"role": "model", "parts": parts},
+            "finishReason": last["candidates"][0]["finishReason"],
+            "index": 0,
+        }],
+        "usageMetadata": last["usageMetadata"],
+        "modelVersion": last["modelVersion"],
+    })
+}
+
+#[tokio::test]
+async fn text_round_trip() {
+    let executor = ScriptedChatExecutor::conversation(
+        (0..2)
+            .map(|_| {
+                ScriptedTurn::new(
+                    ["developer: Answer in one line.", "user: Say hello"],
+                    || {
+                        vec![
+                            ResponseEvent::ReasoningSummaryDelta {
+                                delta: "Greeting".to_string(),
+                                summary_index: 0,
+                            },
+                            ResponseEvent::OutputTextDelta("Hello, ".to_string()),
+                            ResponseEvent::OutputTextDelta("world".to_string()),
+                            completed("resp_text"),
+                        ]
+                    },
+                )
+            })
+            .collect(),
+    );
+    let server = spawn(executor).await;
+    let body = json!({
+        "systemInstruction": {"parts": [{"text": "Answer in one line."}]},
+        "contents": [{"role": "user", "parts": [{"text": "Say hello"}]}]
+    });
+
+    let expected = json!({
+        "candidates": [{
+            "content": {"role": "model", "parts": [
+                {"text": "Greeting", "thought": true},
+                {"text": "Hello, world"}
+            ]},
+            "finishReason": "STOP",
+            "index": 0
+        }],
+        "usageMetadata": {
+            "promptTokenCount": 16,
+            "candidatesTokenCount": 5,
+            "totalTokenCount": 23,
+            "cachedContentTokenCount": 4,
+            "thoughtsTokenCount": 2
+        },
+        "modelVersion": "gpt-5"
+    });
+    for stream in [false, true] {
+        assert_eq!(
+            send_turn(&server, &body, stream).await,
+            expected,
+            "{stream}"
+        );
+    }
+}
+
+fn weather_turns() -> Vec<ScriptedTurn> {
+    vec![
+        ScriptedTurn::new([format!("user: {QUESTION}")], || {
+            vec![
+                ResponseEvent::OutputItemDone(ResponseItem::FunctionCall {
+                    id: None,
+                    name: "get_weather".to_string(),
+                    arguments: r#"{"city":"Paris"}"#.to_string(),
+                    call_id: "call_1".to_string(),
+                }),
+                completed("resp_turn_1"),
+            ]
+        }),
+        ScriptedTurn::new(
+            [
+                format!("user: {QUESTION}"),
+                r#"function_call call_1 get_weather({"city":"Paris"})"#.to_string(),
+                r#"function_call_output call_1: {"forecast":"18°C and sunny"}"#.to_string(),
+            ],
+            || {
+                vec![
+                    ResponseEvent::OutputTextDelta("It is 18°C and sunny.".to_string()),
+                    completed("resp_turn_2"),
+                ]
+            },
+        ),
+    ]
+}
+
+fn tool_request(contents: &[Value]) -> Value {
+    json!({
+        "contents": contents,
+        "tools": [{"functionDeclarations": [{
+            "name": "get_weather",
+            "parameters": {
+                "type": "OBJECT",
+                "properties": {"city": {"type": "STRING"}},
+                "required": ["city"]
+            }
+        }]}]
+    })
+}
+
+#[tokio::test]
+async fn tool_round_trip() {
+    for stream in [false, true] {
+        let server = spawn(ScriptedChatExecutor::conversation(weather_turns())).await;
+        let question = json!({"role": "user", "parts": [{"text": QUESTION}]});
+
+        let first = send_turn(
+            &server,
+            &tool_request(std::slice::from_ref(&question)),
+            stream,
+        )
+        .await;
+        let candidate = &first["candidates"][0];
+        assert_eq!(candidate["finishReason"], "STOP");
+        assert_eq!(
+            candidate["content"]["parts"],
+            json!([{"functionCall": {
+                "id": "call_1",
+                "name": "get_weather",
+                "args": {"city": "Paris"}
+            }}]),
+            "{stream}"
+        );
+
+        // Gemini clients replay the call without its id and answer it by name.
+        let replayed = json!({"role": "model", "parts": [
+            {"functionCall": {"name": "get_weather", "args": {"city": "Paris"}}}
+        ]});
+        let result = json!({"role": "user", "parts": [{"functionResponse": {
+            "name": "get_weather",
+            "response": {"forecast": "18°C and sunny"}
+        }}]});
+        let second = send_turn(
+            &server,
+            &tool_request(&[question, replayed, result]),
+            stream,
+        )
+        .await;
+        assert_eq!(
+            second["candidates"][0]["content"]["parts"],
+            json!([{"text": "It is 18°C and sunny."}]),
+            "{stream}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn errors_use_the_gemini_shape() {
+    let server = spawn(ScriptedChatExecutor::new(["hi"])).await;
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!(
+            "{}/v1beta/models/gpt-5:countTokens",
+            server.base_url()
+        ))
+        .json(&json!({"contents": []}))
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let body: Value = response.json().await.expect("error body is JSON");
+    assert_eq!(body["error"]["code"], 404);
+    assert_eq!(body["error"]["status"], "NOT_FOUND");
+
+    let response = client
+        .post(format!(
+            "{}/v1beta/models/gpt-5:generateContent",
+            server.base_url()
+        ))
+        .json(&json!({"contents": []}))
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: Value = response.json().await.expect("error body is JSON");
+    assert_eq!(body["error"]["status"], "INVALID_ARGUMENT");
+}
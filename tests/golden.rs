@@ -0,0 +1,264 @@
+//! Golden-response tests: fixed scripted scenarios run through both chat handlers, with the exact
+//! serialized output compared against `tests/golden/*.json`.
+//!
+//! After an intentional protocol change, regenerate the files with
+//! `UPDATE_GOLDENS=1 cargo test --test golden` and review the diff.
+//!
+//! `tests/golden/openai_skeleton.*.json` are real OpenAI responses, kept by hand: the
+//! `--compat-nulls` output must carry exactly their keys. Only key paths are compared, not which
+//! chunk carries them: OpenAI sends streamed usage in a trailing chunk with no choices, where
+//! Codex Serve puts it on the finish chunk.
+
+use std::{collections::BTreeSet, path::PathBuf, sync::Arc, time::Duration};
+
+use codex_core::{ResponseEvent, ResponseItem, protocol::TokenUsage};
+use codex_protocol::models::WebSearchAction;
+use codex_serve::{
+    AppState, ServeConfig,
+    error::ApiError,
+    server::{ScriptedChatExecutor, TestServer, normalize_snapshot},
+};
+use serde_json::{Value, json};
+
+fn usage() -> Option<TokenUsage> {
+    Some(TokenUsage {
+        input_tokens: 12,
+        cached_input_tokens: 0,
+        output_tokens: 5,
+        reasoning_output_tokens: 0,
+        total_tokens: 17,
+    })
+}
+
+fn completed() -> ResponseEvent {
+    ResponseEvent::Completed {
+        response_id: "resp_golden".to_string(),
+        token_usage: usage(),
+    }
+}
+
+fn scenarios() -> Vec<(&'static str, ScriptedChatExecutor)> {
+    vec![
+        (
+            "text",
+            ScriptedChatExecutor::from_events(|| {
+                vec![
+                    ResponseEvent::Created,
+                    ResponseEvent::OutputTextDelta("Hello".to_string()),
+                    ResponseEvent::OutputTextDelta(", world".to_string()),
+                    completed(),
+                ]
+            }),
+        ),
+        (
+            "tool_call",
+            ScriptedChatExecutor::from_events(|| {
+                vec![
+                    ResponseEvent::OutputItemDone(ResponseItem::FunctionCall {
+                        id: None,
+                        name: "get_weather".to_string(),
+                        arguments: r#"{"city":"Paris"}"#.to_string(),
+                        call_id: "call_golden".to_string(),
+                    }),
+                    completed(),
+                ]
+            }),
+        ),
+        (
+            "reasoning",
+            ScriptedChatExecutor::from_events(|| {
+                vec![
+                    ResponseEvent::ReasoningSummaryPartAdded { summary_index: 0 },
+                    ResponseEvent::ReasoningSummaryDelta {
+                        delta: "Considering the question".to_string(),
+                        summary_index: 0,
+                    },
+                    ResponseEvent::OutputTextDelta("42".to_string()),
+                    completed(),
+                ]
+            }),
+        ),
+        (
+            "web_search",
+            ScriptedChatExecutor::from_events(|| {
+                vec![
+                    ResponseEvent::OutputItemDone(ResponseItem::WebSearchCall {
+                        id: Some("ws_golden".to_string()),
+                        status: Some("completed".to_string()),
+                        action: WebSearchAction::Search {
+                            query: Some("rust release date".to_string()),
+                        },
+                    }),
+                    completed(),
+                ]
+            }),
+        ),
+        (
+            "error",
+            ScriptedChatExecutor::failing(|| {
+                ApiError::rate_limited("Codex usage limit reached", Some(Duration::from_secs(30)))
+            }),
+        ),
+    ]
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{name}.json"))
+}
+
+/// Compares `actual` with the checked-in golden, or rewrites it when `UPDATE_GOLDENS` is set.
+fn assert_golden(name: &str, actual: &Value) {
+    let path = golden_path(name);
+    let rendered = format!(
+        "{}\n",
+        serde_json::to_string_pretty(actual).expect("snapshot serializes")
+    );
+    if std::env::var_os("UPDATE_GOLDENS").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).expect("create golden dir");
+        std::fs::write(&path, rendered).expect("write golden");
+        return;
+    }
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "missing golden {}; run `UPDATE_GOLDENS=1 cargo test --test golden`",
+            path.display()
+        )
+    });
+    assert_eq!(
+        expected,
+        rendered,
+        "{name} drifted from {}; if intentional, rerun with UPDATE_GOLDENS=1",
+        path.display()
+    );
+}
+
+/// Splits an SSE body into its `data:` payloads, parsing the JSON ones.
+fn sse_events(body: &str) -> Vec<Value> {
+    body.split("\n\n")
+        .filter_map(|frame| {
+            let data: Vec<&str> = frame
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(str::trim_start)
+                .collect();
+            (!data.is_empty()).then(|| data.join("\n"))
+        })
+        .map(|data| serde_json::from_str(&data).unwrap_or(Value::String(data)))
+        .collect()
+}
+
+async fn run_scenario(server: &TestServer, stream: bool) -> Value {
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&json!({
+            "model": "gpt-5",
+            "stream": stream,
+            "messages": [{"role": "user", "content": "golden"}]
+        }))
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    let status = response.status().as_u16();
+    let is_sse = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/event-stream"));
+    let text = response.text().await.expect("response body");
+    let body = if is_sse {
+        Value::Array(sse_events(&text))
+    } else {
+        serde_json::from_str(&text).expect("non-SSE bodies are JSON")
+    };
+    let mut snapshot = json!({"status": status, "sse": is_sse, "body": body});
+    normalize_snapshot(&mut snapshot);
+    snapshot
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn responses_match_goldens() {
+    for (name, executor) in scenarios() {
+        let server = TestServer::spawn_with_executor(Arc::new(executor))
+            .await
+            .expect("Codex Serve test server should start");
+        assert_golden(
+            &format!("{name}.complete"),
+            &run_scenario(&server, false).await,
+        );
+        assert_golden(
+            &format!("{name}.stream"),
+            &run_scenario(&server, true).await,
+        );
+    }
+}
+
+/// Every key path in `value`, with `[]` standing for any array element; the `[DONE]` sentinel and
+/// other scalars add nothing.
+fn key_paths(value: &Value, prefix: &str, paths: &mut BTreeSet<String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, entry) in map {
+                let path = format!("{prefix}.{key}");
+                key_paths(entry, &path, paths);
+                paths.insert(path);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                key_paths(item, &format!("{prefix}[]"), paths);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn skeleton_paths(name: &str) -> BTreeSet<String> {
+    let path = golden_path(&format!("openai_skeleton.{name}"));
+    let text = std::fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("missing skeleton {}", path.display()));
+    let mut paths = BTreeSet::new();
+    key_paths(
+        &serde_json::from_str(&text).expect("skeleton is JSON"),
+        "",
+        &mut paths,
+    );
+    paths
+}
+
+fn body_paths(snapshot: &Value) -> BTreeSet<String> {
+    let mut paths = BTreeSet::new();
+    key_paths(&snapshot["body"], "", &mut paths);
+    paths
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn compat_nulls_match_the_openai_field_set() {
+    for compat_nulls in [false, true] {
+        let (_, executor) = scenarios().swap_remove(0);
+        let state = AppState::insecure_mock(true)
+            .with_config(ServeConfig::builder().compat_nulls(compat_nulls).build())
+            .with_executor(Arc::new(executor));
+        let server = TestServer::spawn_with_state(state)
+            .await
+            .expect("Codex Serve test server should start");
+        for (name, stream) in [("complete", false), ("stream", true)] {
+            let actual = body_paths(&run_scenario(&server, stream).await);
+            let skeleton = skeleton_paths(name);
+            if compat_nulls {
+                assert_eq!(actual, skeleton, "{name} with --compat-nulls");
+            } else {
+                // The compact form only ever leaves keys out.
+                assert!(actual.is_subset(&skeleton), "{name}: {actual:?}");
+                assert!(
+                    !actual.iter().any(|path| path.ends_with(".logprobs")
+                        || path.ends_with(".refusal")
+                        || path.ends_with("_details")),
+                    "{name}: {actual:?}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! `/healthz` component statuses: the `upstream` component follows the outcomes of recent
+//! upstream calls while the response itself stays a `200`.
+
+use std::sync::Arc;
+
+use codex_core::error::CodexErr;
+use codex_serve::{
+    AppState,
+    error::ApiError,
+    server::{ScriptedChatExecutor, TestServer},
+};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+
+async fn chat(server: &TestServer) -> StatusCode {
+    reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&json!({
+            "model": "gpt-5",
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .send()
+        .await
+        .expect("request should reach Codex Serve")
+        .status()
+}
+
+async fn healthz(server: &TestServer) -> Value {
+    let response = reqwest::get(format!("{}/healthz", server.base_url()))
+        .await
+        .expect("healthz should respond");
+    assert_eq!(response.status(), StatusCode::OK);
+    response.json().await.expect("healthz must be JSON")
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn upstream_failures_degrade_then_fail_the_upstream_component() {
+    // Two servers over clones of one state: one upstream that answers, one that refuses.
+    let state = AppState::insecure_mock(true);
+    let working = TestServer::spawn_with_state(
+        state
+            .clone()
+            .with_executor(Arc::new(ScriptedChatExecutor::new(["hello"]))),
+    )
+    .await
+    .expect("Codex Serve test server should start");
+    let failing = TestServer::spawn_with_state(state.with_executor(Arc::new(
+        ScriptedChatExecutor::failing(|| ApiError::rate_limited("Codex usage limit reached", None)),
+    )))
+    .await
+    .expect("Codex Serve test server should start");
+
+    let health = healthz(&working).await;
+    assert_eq!(health["status"], "ok");
+    assert_eq!(health["auth"]["status"], "ok");
+    assert_eq!(health["config"]["status"], "ok");
+    assert_eq!(health["upstream"]["status"], "ok");
+    assert_eq!(health["upstream"]["success_rate"], Value::Null);
+
+    assert_eq!(chat(&working).await, StatusCode::OK);
+    let health = healthz(&working).await;
+    assert_eq!(health["upstream"]["status"], "ok");
+    assert_eq!(health["upstream"]["success_rate"], 1.0);
+
+    assert_eq!(chat(&failing).await, StatusCode::TOO_MANY_REQUESTS);
+    let health = healthz(&working).await;
+    assert_eq!(health["status"], "degraded");
+    assert_eq!(health["upstream"]["status"], "degraded");
+    assert_eq!(health["upstream"]["requests"], 2);
+    assert_eq!(health["upstream"]["success_rate"], 0.5);
+    assert_eq!(
+        health["upstream"]["last_error"]["message"],
+        "Codex usage limit reached"
+    );
+    assert!(health["upstream"]["last_error"]["at"].is_string());
+    // The older fields read as before.
+    assert_eq!(health["ok"], true);
+    assert_eq!(health["authenticated"], true);
+
+    assert_eq!(chat(&failing).await, StatusCode::TOO_MANY_REQUESTS);
+    let health = healthz(&failing).await;
+    assert_eq!(health["status"], "failing");
+    assert_eq!(health["upstream"]["status"], "failing");
+    assert_eq!(health["auth"]["status"], "ok");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn gemini_and_ollama_streams_that_break_count_as_failures() {
+    let server = TestServer::spawn_with_executor(Arc::new(
+        ScriptedChatExecutor::new(["hello"])
+            .with_stream_error(|| CodexErr::Stream("connection reset".to_string(), None)),
+    ))
+    .await
+    .expect("Codex Serve test server should start");
+    let client = reqwest::Client::new();
+    let gemini = json!({"contents": [{"role": "user", "parts": [{"text": "hi"}]}]});
+    let ollama = |stream: bool| {
+        json!({
+            "model": "gpt-5",
+            "stream": stream,
+            "messages": [{"role": "user", "content": "hi"}]
+        })
+    };
+    let requests = [
+        ("/v1beta/models/gpt-5:generateContent", gemini.clone()),
+        ("/v1beta/models/gpt-5:streamGenerateContent", gemini),
+        ("/api/chat", ollama(false)),
+        ("/api/chat", ollama(true)),
+    ];
+    for (path, body) in &requests {
+        // Streams answer 200 before the break; reading them to the end waits for the outcome.
+        client
+            .post(format!("{}{path}", server.base_url()))
+            .json(body)
+            .send()
+            .await
+            .expect("request should reach Codex Serve")
+            .text()
+            .await
+            .expect("response body");
+    }
+
+    // Each handshake succeeded, but none of the replies did.
+    let health = healthz(&server).await;
+    assert_eq!(health["upstream"]["requests"], requests.len());
+    assert_eq!(health["upstream"]["success_rate"], 0.0);
+    assert_eq!(health["upstream"]["status"], "failing");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn a_missing_login_fails_the_auth_component() {
+    let server = TestServer::spawn_unauthenticated()
+        .await
+        .expect("Codex Serve test server should start");
+    let health = healthz(&server).await;
+    assert_eq!(health["status"], "failing");
+    assert_eq!(health["auth"]["status"], "failing");
+    assert_eq!(health["upstream"]["status"], "ok");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn an_ignored_config_override_degrades_the_config_component() {
+    let warning = "ignored the `features.web_search_request = true` override from \
+                   --web-search-request: the Codex config rejected it";
+    let server = TestServer::spawn_with_state(
+        AppState::insecure_mock(true).with_config_warnings(vec![warning.to_string()]),
+    )
+    .await
+    .expect("Codex Serve test server should start");
+    let health = healthz(&server).await;
+    assert_eq!(health["status"], "degraded");
+    assert_eq!(health["config"]["status"], "degraded");
+    assert_eq!(health["config"]["warnings"], json!([warning]));
+    assert_eq!(health["config"]["web_search_request"], false);
+    assert_eq!(health["ok"], true);
+}
@@ -0,0 +1,148 @@
+//! `--hide-reasoning`: no reasoning summary is asked of upstream, and reasoning that still comes
+//! back reaches neither the reply, streamed or whole, nor the capture files.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use codex_core::ResponseEvent;
+use codex_protocol::config_types::ReasoningSummary;
+use codex_serve::{
+    ServeConfig,
+    openai::chat::PromptPayload,
+    server::{CapturingExecutor, ScriptedChatExecutor, TestServer},
+};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+
+fn events() -> Vec<ResponseEvent> {
+    vec![
+        ResponseEvent::Created,
+        ResponseEvent::ReasoningSummaryPartAdded { summary_index: 0 },
+        ResponseEvent::ReasoningSummaryDelta {
+            delta: "secret summary".to_string(),
+            summary_index: 0,
+        },
+        ResponseEvent::ReasoningContentDelta {
+            delta: "secret thought".to_string(),
+            content_index: 0,
+        },
+        ResponseEvent::OutputTextDelta("The answer.".to_string()),
+        ResponseEvent::Completed {
+            response_id: "resp_hidden".to_string(),
+            token_usage: None,
+        },
+    ]
+}
+
+struct Hidden {
+    server: TestServer,
+    captured: Arc<Mutex<Vec<PromptPayload>>>,
+    capture_dir: std::path::PathBuf,
+}
+
+async fn spawn(hide_reasoning: bool) -> Hidden {
+    let executor = CapturingExecutor::new(Arc::new(ScriptedChatExecutor::from_events(events)));
+    let captured = executor.captured();
+    let capture_dir =
+        std::env::temp_dir().join(format!("codex-serve-hidden-{}", uuid::Uuid::new_v4()));
+    let config = ServeConfig::builder()
+        .hide_reasoning(hide_reasoning)
+        .capture_dir(capture_dir.clone())
+        .build();
+    let server = TestServer::spawn_with_config(config, Arc::new(executor))
+        .await
+        .expect("Codex Serve test server should start");
+    Hidden {
+        server,
+        captured,
+        capture_dir,
+    }
+}
+
+async fn post(server: &TestServer, stream: bool) -> String {
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&json!({
+            "model": "gpt-5",
+            "stream": stream,
+            "reasoning": {"summary": "detailed"},
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(response.status(), StatusCode::OK);
+    response.text().await.expect("response body")
+}
+
+async fn healthz(server: &TestServer) -> Value {
+    reqwest::get(format!("{}/healthz", server.base_url()))
+        .await
+        .expect("healthz")
+        .json()
+        .await
+        .expect("healthz is JSON")
+}
+
+/// Every line the capture writer has written, once there are `count` of them.
+async fn capture_lines(dir: &std::path::Path, count: usize) -> Vec<String> {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(2);
+    loop {
+        let mut lines = Vec::new();
+        for entry in std::fs::read_dir(dir).expect("capture dir") {
+            let contents = std::fs::read_to_string(entry.expect("capture file").path())
+                .expect("capture file is readable");
+            lines.extend(contents.lines().map(str::to_string));
+        }
+        if lines.len() >= count {
+            return lines;
+        }
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "captures not written"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn reasoning_never_leaves_the_process() {
+    let hidden = spawn(true).await;
+
+    for stream in [false, true] {
+        let body = post(&hidden.server, stream).await;
+        assert!(body.contains("The answer."), "{body}");
+        assert!(!body.contains("secret"), "{body}");
+    }
+
+    // Both requests asked upstream for no summary, whatever the client wanted.
+    for payload in hidden.captured.lock().unwrap().iter() {
+        assert_eq!(payload.reasoning_summary, Some(ReasoningSummary::None));
+    }
+
+    let lines = capture_lines(&hidden.capture_dir, 2).await;
+    assert_eq!(lines.len(), 2);
+    for line in &lines {
+        assert!(!line.contains("secret"), "{line}");
+    }
+
+    let health = healthz(&hidden.server).await;
+    assert_eq!(health["config"]["reasoning_exposed"], false);
+
+    let _ = std::fs::remove_dir_all(&hidden.capture_dir);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn reasoning_is_exposed_by_default() {
+    let shown = spawn(false).await;
+
+    let body = post(&shown.server, true).await;
+    assert!(body.contains("secret summary"), "{body}");
+
+    let health = healthz(&shown.server).await;
+    assert_eq!(health["config"]["reasoning_exposed"], true);
+
+    let _ = std::fs::remove_dir_all(&shown.capture_dir);
+}
@@ -0,0 +1,120 @@
+//! Retried requests: a repeat of a running non-streaming request shares its upstream call, a
+//! repeat of a running stream is refused, and a finished reply is replayed.
+
+use std::{sync::Arc, time::Duration};
+
+use codex_serve::server::{
+    CapturingExecutor, IDEMPOTENCY_KEY_HEADER, IDEMPOTENT_REPLAYED_HEADER, REQUEST_ID_HEADER,
+    ScriptedChatExecutor, TestServer,
+};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+
+fn request(stream: bool) -> Value {
+    json!({
+        "model": "gpt-5",
+        "stream": stream,
+        "messages": [{"role": "user", "content": "write a long story"}]
+    })
+}
+
+async fn post(server: &TestServer, key: &str, body: &Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .header(IDEMPOTENCY_KEY_HEADER.as_str(), key)
+        .json(body)
+        .send()
+        .await
+        .expect("request should reach Codex Serve")
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn concurrent_duplicates_share_one_upstream_call() {
+    let scripted =
+        ScriptedChatExecutor::new(["once", " upon"]).with_delay(Duration::from_millis(150));
+    let executor = CapturingExecutor::new(Arc::new(scripted));
+    let calls = executor.captured();
+    let server = TestServer::spawn_with_executor(Arc::new(executor))
+        .await
+        .expect("Codex Serve test server should start");
+
+    let body = request(false);
+    let (first, retry) = tokio::join!(post(&server, "story-1", &body), async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        post(&server, "story-1", &body).await
+    });
+    assert_eq!(first.status(), StatusCode::OK);
+    assert_eq!(retry.status(), StatusCode::OK);
+    assert!(
+        first
+            .headers()
+            .get(IDEMPOTENT_REPLAYED_HEADER.as_str())
+            .is_none()
+    );
+    assert_eq!(
+        retry.headers().get(IDEMPOTENT_REPLAYED_HEADER.as_str()),
+        Some(&reqwest::header::HeaderValue::from_static("true"))
+    );
+    let first: Value = first.json().await.expect("first reply");
+    let retry: Value = retry.json().await.expect("retried reply");
+    assert_eq!(first["choices"][0]["message"]["content"], "once upon");
+    assert_eq!(first, retry);
+    assert_eq!(calls.lock().unwrap().len(), 1);
+
+    // Once finished, the reply is replayed rather than generated again...
+    let later: Value = post(&server, "story-1", &body)
+        .await
+        .json()
+        .await
+        .expect("replayed reply");
+    assert_eq!(later, first);
+    assert_eq!(calls.lock().unwrap().len(), 1);
+
+    // ...but not for a different request under the same key.
+    let mut other = body.clone();
+    other["messages"][0]["content"] = json!("write a short poem");
+    let conflict = post(&server, "story-1", &other).await;
+    assert_eq!(conflict.status(), StatusCode::CONFLICT);
+    assert_eq!(calls.lock().unwrap().len(), 1);
+
+    // Requests without a key are never suppressed.
+    let unkeyed = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&body)
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(unkeyed.status(), StatusCode::OK);
+    assert_eq!(calls.lock().unwrap().len(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn duplicate_of_a_running_stream_is_refused() {
+    let scripted =
+        ScriptedChatExecutor::new(["slow", " stream"]).with_delay(Duration::from_millis(300));
+    let server = TestServer::spawn_with_executor(Arc::new(scripted))
+        .await
+        .expect("Codex Serve test server should start");
+
+    let body = request(true);
+    let original = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .header(IDEMPOTENCY_KEY_HEADER.as_str(), "stream-1")
+        .header(REQUEST_ID_HEADER.as_str(), "req-original")
+        .json(&body)
+        .send()
+        .await
+        .expect("stream should start");
+    assert_eq!(original.status(), StatusCode::OK);
+
+    let duplicate = post(&server, "stream-1", &body).await;
+    assert_eq!(duplicate.status(), StatusCode::CONFLICT);
+    let error: Value = duplicate.json().await.expect("error body");
+    assert_eq!(error["error"]["code"], "IDEMPOTENCY_CONFLICT");
+    assert_eq!(error["error"]["original_request_id"], "req-original");
+
+    // The key is released once the original stream ends.
+    original.text().await.expect("original stream body");
+    let after = post(&server, "stream-1", &body).await;
+    assert_eq!(after.status(), StatusCode::OK);
+}
@@ -0,0 +1,191 @@
+//! `GET /admin/requests` lists the chat requests in flight, and
+//! `POST /admin/requests/{id}/cancel` stops one: the client gets the usual error promptly instead
+//! of waiting for the upstream to finish.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use codex_serve::{
+    AppState, ServeConfig,
+    serve_config::ListenerAuth,
+    server::{ScriptedChatExecutor, serve_with_state_listeners},
+};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use tokio::{net::TcpListener, sync::oneshot};
+
+const KEY: &str = "s3cret";
+/// Long enough that only a cancellation ends the request within the test's patience.
+const EVENT_DELAY: Duration = Duration::from_secs(3);
+
+struct Server {
+    base_url: String,
+    _stop: oneshot::Sender<()>,
+}
+
+async fn spawn() -> Server {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let state = AppState::insecure_mock(true)
+        .with_config(
+            ServeConfig::builder()
+                .client_api_key(KEY)
+                .per_client_concurrency(4)
+                .build(),
+        )
+        .with_admin(true)
+        .with_executor(Arc::new(
+            ScriptedChatExecutor::new(["slow", " and ", "steady"]).with_delay(EVENT_DELAY),
+        ));
+    let (stop, stopped) = oneshot::channel::<()>();
+    tokio::spawn(serve_with_state_listeners(
+        vec![(listener, ListenerAuth::Required)],
+        None,
+        state,
+        async move {
+            let _ = stopped.await;
+        },
+    ));
+    Server {
+        base_url,
+        _stop: stop,
+    }
+}
+
+impl Server {
+    fn chat(&self, request_id: &str, stream: bool) -> reqwest::RequestBuilder {
+        reqwest::Client::new()
+            .post(format!("{}/v1/chat/completions", self.base_url))
+            .bearer_auth(KEY)
+            .header("x-request-id", request_id)
+            .json(&json!({
+                "model": "gpt-5",
+                "stream": stream,
+                "messages": [{"role": "user", "content": "take your time"}]
+            }))
+    }
+
+    async fn requests(&self) -> Vec<Value> {
+        let list: Value = reqwest::Client::new()
+            .get(format!("{}/admin/requests", self.base_url))
+            .bearer_auth(KEY)
+            .send()
+            .await
+            .expect("request should reach Codex Serve")
+            .json()
+            .await
+            .expect("the list is JSON");
+        assert_eq!(list["object"], "list");
+        list["data"].as_array().expect("data").clone()
+    }
+
+    /// Waits for `request_id` to show up in `/admin/requests`.
+    async fn listed(&self, request_id: &str) -> Value {
+        for _ in 0..100 {
+            let requests = self.requests().await;
+            if let Some(request) = requests
+                .into_iter()
+                .find(|request| request["request_id"] == request_id)
+            {
+                return request;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("{request_id} was never listed");
+    }
+
+    async fn cancel(&self, request_id: &str, bearer: Option<&str>) -> reqwest::Response {
+        let mut request = reqwest::Client::new().post(format!(
+            "{}/admin/requests/{request_id}/cancel",
+            self.base_url
+        ));
+        if let Some(token) = bearer {
+            request = request.bearer_auth(token);
+        }
+        request
+            .send()
+            .await
+            .expect("request should reach Codex Serve")
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn cancelling_a_stream_ends_it_with_an_error_event() {
+    let server = spawn().await;
+    let stream = tokio::spawn(server.chat("req_slow_stream", true).send());
+
+    let listed = server.listed("req_slow_stream").await;
+    assert_eq!(listed["model"], "gpt-5");
+    assert_eq!(listed["stream"], true);
+    assert!(listed["client"].is_string(), "{listed}");
+    assert!(listed["started_at"].is_string(), "{listed}");
+    assert!(listed["elapsed_ms"].is_u64(), "{listed}");
+
+    // The admin routes take the listener's API key like every other route.
+    assert_eq!(
+        server.cancel("req_slow_stream", None).await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        server.cancel("req_unknown", Some(KEY)).await.status(),
+        StatusCode::NOT_FOUND
+    );
+
+    let cancelled_at = Instant::now();
+    let outcome = server.cancel("req_slow_stream", Some(KEY)).await;
+    assert_eq!(outcome.status(), StatusCode::OK);
+    let outcome: Value = outcome.json().await.expect("outcome is JSON");
+    assert_eq!(outcome["cancelled"], 1);
+
+    let response = stream.await.unwrap().expect("the stream should start");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = tokio::time::timeout(Duration::from_secs(2), response.text())
+        .await
+        .expect("the stream should end soon after the cancellation")
+        .expect("stream body");
+    assert!(cancelled_at.elapsed() < EVENT_DELAY, "{body}");
+    let events: Vec<&str> = body
+        .lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .collect();
+    assert_eq!(events.last(), Some(&"[DONE]"), "{body}");
+    let error: Value = serde_json::from_str(events[events.len() - 2]).expect("error event");
+    assert_eq!(error["error"]["code"], "REQUEST_CANCELLED");
+    assert_eq!(error["error"]["request_id"], "req_slow_stream");
+    assert!(!body.contains("steady"), "{body}");
+
+    for _ in 0..100 {
+        if server.requests().await.is_empty() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("the cancelled stream is still listed");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn cancelling_a_plain_request_answers_it_with_an_error() {
+    let server = spawn().await;
+    let request = tokio::spawn(server.chat("req_slow_reply", false).send());
+
+    let listed = server.listed("req_slow_reply").await;
+    assert_eq!(listed["stream"], false);
+    let cancelled_at = Instant::now();
+    assert_eq!(
+        server.cancel("req_slow_reply", Some(KEY)).await.status(),
+        StatusCode::OK
+    );
+
+    let response = tokio::time::timeout(Duration::from_secs(2), request)
+        .await
+        .expect("the request should end soon after the cancellation")
+        .unwrap()
+        .expect("request should reach Codex Serve");
+    assert!(cancelled_at.elapsed() < EVENT_DELAY);
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body: Value = response.json().await.expect("error body");
+    assert_eq!(body["error"]["code"], "REQUEST_CANCELLED");
+    assert!(server.requests().await.is_empty());
+}
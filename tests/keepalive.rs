@@ -0,0 +1,106 @@
+//! `--keepalive-interval`: the background keepalive runs on its interval while serving, is
+//! reported on `/healthz`, and stops with the server.
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use codex_serve::{
+    AppState, ServeConfig,
+    serve_config::{ListenerAuth, parse_interval},
+    server::{ScriptedChatExecutor, serve_with_state_listeners},
+};
+use serde_json::Value;
+use tokio::{net::TcpListener, sync::oneshot};
+
+#[test]
+fn intervals_parse_with_units() {
+    for (text, expected) in [
+        ("500ms", Duration::from_millis(500)),
+        ("45", Duration::from_secs(45)),
+        ("30s", Duration::from_secs(30)),
+        ("10m", Duration::from_secs(600)),
+        (" 1h ", Duration::from_secs(3_600)),
+    ] {
+        assert_eq!(parse_interval(text), Ok(expected), "{text}");
+    }
+    for text in ["", "m", "0s", "10d", "1.5m", "-1s"] {
+        assert!(parse_interval(text).is_err(), "{text}");
+    }
+}
+
+async fn wait_for_count(count: &AtomicUsize, at_least: usize) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while count.load(Ordering::SeqCst) < at_least {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("keepalive should fire on its interval");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn keepalive_fires_on_its_interval_and_stops_on_shutdown() {
+    let executor = ScriptedChatExecutor::new(["hi"]);
+    let count = executor.keepalive_count();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let state = AppState::insecure_mock(true)
+        .with_config(
+            ServeConfig::builder()
+                .keepalive_interval(Duration::from_millis(50))
+                .build(),
+        )
+        .with_executor(Arc::new(executor));
+    let (stop, stopped) = oneshot::channel::<()>();
+    let server = tokio::spawn(serve_with_state_listeners(
+        vec![(listener, ListenerAuth::None)],
+        None,
+        state,
+        async move {
+            let _ = stopped.await;
+        },
+    ));
+
+    wait_for_count(&count, 2).await;
+    let health: Value = reqwest::get(format!("{base_url}/healthz"))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let keepalive = &health["keepalive"];
+    assert_eq!(keepalive["last_result"], "ok", "{health}");
+    assert_eq!(keepalive["consecutive_failures"], 0, "{health}");
+    assert!(keepalive["last_run"].is_string(), "{health}");
+
+    stop.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), server)
+        .await
+        .expect("the server should drain on shutdown")
+        .unwrap()
+        .expect("serving should end cleanly");
+    let after_shutdown = count.load(Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert_eq!(count.load(Ordering::SeqCst), after_shutdown);
+}
+
+#[tokio::test]
+async fn stopping_the_keepalive_cancels_its_task() {
+    let state = AppState::insecure_mock(true);
+    assert_eq!(state.keepalive_status(), None);
+
+    let executor = ScriptedChatExecutor::new(["hi"]);
+    let count = executor.keepalive_count();
+    let state = state.with_executor(Arc::new(executor));
+    state.start_keepalive(Duration::from_millis(20));
+    wait_for_count(&count, 1).await;
+    state.stop_keepalive();
+    let stopped_at = count.load(Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(count.load(Ordering::SeqCst), stopped_at);
+}
@@ -0,0 +1,176 @@
+//! `/stats/latency` and `/metrics`: time to first token and output tokens per second per model,
+//! from streaming and non-streaming requests alike, with failed streams counted apart and a
+//! resumed reply booked once.
+
+use std::{sync::Arc, time::Duration};
+
+use codex_core::{ResponseEvent, error::CodexErr, protocol::TokenUsage};
+use codex_serve::{
+    AppState,
+    server::{ScriptedChatExecutor, ScriptedTurn, TestServer},
+};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+
+const HANDSHAKE: Duration = Duration::from_millis(200);
+const EVENT_DELAY: Duration = Duration::from_millis(50);
+
+fn events() -> Vec<ResponseEvent> {
+    ["The", " answer", " is 42."]
+        .into_iter()
+        .map(|text| ResponseEvent::OutputTextDelta(text.to_string()))
+        .chain([ResponseEvent::Completed {
+            response_id: "resp_timed".to_string(),
+            token_usage: Some(TokenUsage {
+                input_tokens: 10,
+                cached_input_tokens: 0,
+                output_tokens: 30,
+                reasoning_output_tokens: 0,
+                total_tokens: 40,
+            }),
+        }])
+        .collect()
+}
+
+async fn chat(server: &TestServer, stream: bool) -> String {
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&json!({
+            "model": "gpt-5-high",
+            "stream": stream,
+            "messages": [{"role": "user", "content": "what is the answer?"}]
+        }))
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(response.status(), StatusCode::OK);
+    response.text().await.expect("body")
+}
+
+async fn latency(server: &TestServer) -> Value {
+    reqwest::get(format!("{}/stats/latency", server.base_url()))
+        .await
+        .expect("latency stats should respond")
+        .json()
+        .await
+        .expect("latency stats are JSON")
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn ttft_and_throughput_follow_the_stream_timing() {
+    let state = AppState::insecure_mock(true);
+    let timed = ScriptedChatExecutor::from_events(events)
+        .with_handshake_delay(HANDSHAKE)
+        .with_delay(EVENT_DELAY);
+    let server = TestServer::spawn_with_state(state.clone().with_executor(Arc::new(timed)))
+        .await
+        .expect("Codex Serve test server should start");
+    let broken = ScriptedChatExecutor::from_events(|| {
+        vec![ResponseEvent::OutputTextDelta("The".to_string())]
+    })
+    .with_stream_error(|| CodexErr::Stream("connection reset".to_string(), None));
+    let broken = TestServer::spawn_with_state(state.with_executor(Arc::new(broken)))
+        .await
+        .expect("Codex Serve test server should start");
+
+    chat(&server, true).await;
+    chat(&server, false).await;
+    chat(&broken, true).await;
+
+    let stats = latency(&server).await;
+    let model = &stats["models"]["gpt-5-high"];
+    assert_eq!(model["completed"], 2, "{stats}");
+    assert_eq!(model["failed"], 1, "{stats}");
+    assert_eq!(model["cancelled"], 0, "{stats}");
+
+    // The first delta arrives after the handshake and one event delay.
+    let earliest = (HANDSHAKE + EVENT_DELAY).as_secs_f64() * 1000.0;
+    for percentile in ["p50", "p95"] {
+        let ttft = model["ttft_ms"][percentile].as_f64().expect("ttft");
+        assert!(
+            ttft >= earliest && ttft < earliest + 1000.0,
+            "{percentile} ttft {ttft}ms"
+        );
+    }
+    // 30 output tokens over at least the three event delays after the first delta.
+    let most = 30.0 / (3.0 * EVENT_DELAY.as_secs_f64());
+    let rate = model["tokens_per_second"]["p50"]
+        .as_f64()
+        .expect("tokens per second");
+    assert!(rate > 0.0 && rate <= most, "{rate} tokens/s");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn a_resumed_reply_is_one_sample() {
+    // The first stream breaks mid-reply; the second finishes it.
+    let resumed = ScriptedChatExecutor::conversation(vec![
+        ScriptedTurn::new(["user: what is the answer?"], || {
+            vec![ResponseEvent::OutputTextDelta("The answer".to_string())]
+        })
+        .with_stream_error(|| CodexErr::Stream("connection reset".to_string(), None)),
+        ScriptedTurn::new(
+            [
+                "user: what is the answer?",
+                "assistant: The answer",
+                "developer: Your previous reply was cut off by a connection error. Continue it \
+                 from exactly where it stopped, without repeating any of it.",
+            ],
+            || events().split_off(1),
+        ),
+    ])
+    .with_delay(HANDSHAKE);
+    let server = TestServer::spawn_with_executor(Arc::new(resumed))
+        .await
+        .expect("Codex Serve test server should start");
+
+    let body: Value = serde_json::from_str(&chat(&server, false).await).expect("JSON reply");
+    assert_eq!(body["resumed"], true, "{body}");
+
+    let stats = latency(&server).await;
+    let model = &stats["models"]["gpt-5-high"];
+    assert_eq!(model["completed"], 1, "{stats}");
+    assert_eq!(model["failed"], 0, "{stats}");
+    assert_eq!(model["cancelled"], 0, "{stats}");
+    // Timed to the first stream's first token, one delay in; the second's comes three in.
+    let ttft = model["ttft_ms"]["p50"].as_f64().expect("ttft");
+    assert!(
+        ttft < 2.0 * HANDSHAKE.as_secs_f64() * 1000.0,
+        "ttft {ttft}ms"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn stats_and_metrics_expose_the_same_samples() {
+    let server =
+        TestServer::spawn_with_executor(Arc::new(ScriptedChatExecutor::from_events(events)))
+            .await
+            .expect("Codex Serve test server should start");
+    chat(&server, true).await;
+
+    let stats: Value = reqwest::get(format!("{}/stats", server.base_url()))
+        .await
+        .expect("stats should respond")
+        .json()
+        .await
+        .expect("stats are JSON");
+    assert_eq!(stats["latency"], latency(&server).await);
+    assert!(stats["budget"].is_object() && stats["conversations"].is_object());
+
+    let metrics = reqwest::get(format!("{}/metrics", server.base_url()))
+        .await
+        .expect("metrics should respond");
+    assert!(
+        metrics.headers()[reqwest::header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain; version=0.0.4")
+    );
+    let text = metrics.text().await.expect("metrics body");
+    for line in [
+        "# TYPE codex_serve_time_to_first_token_seconds histogram",
+        r#"codex_serve_time_to_first_token_seconds_count{model="gpt-5-high"} 1"#,
+        r#"codex_serve_latency_requests_total{model="gpt-5-high",outcome="completed"} 1"#,
+    ] {
+        assert!(text.lines().any(|series| series == line), "{line}\n{text}");
+    }
+}
@@ -0,0 +1,133 @@
+//! Repeated `--addr`: listeners sharing one state, each with its own auth requirement, all
+//! drained together on shutdown.
+
+use std::{sync::Arc, time::Duration};
+
+use codex_serve::{
+    AppState, ServeConfig,
+    serve_config::{ListenerAuth, ListenerSpec},
+    server::{ScriptedChatExecutor, serve_with_state_listeners},
+};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use tokio::{net::TcpListener, sync::oneshot};
+
+async fn post_chat(base_url: &str, bearer: Option<&str>) -> reqwest::Response {
+    let mut request = reqwest::Client::new()
+        .post(format!("{base_url}/v1/chat/completions"))
+        .json(&json!({
+            "model": "gpt-5",
+            "messages": [{"role": "user", "content": "hello"}]
+        }));
+    if let Some(token) = bearer {
+        request = request.bearer_auth(token);
+    }
+    request
+        .send()
+        .await
+        .expect("request should reach Codex Serve")
+}
+
+#[test]
+fn listener_specs_parse_their_auth_annotation() {
+    for (text, addr, auth) in [
+        ("127.0.0.1:8001", "127.0.0.1:8001", ListenerAuth::None),
+        (
+            "0.0.0.0:8000,auth=required",
+            "0.0.0.0:8000",
+            ListenerAuth::Required,
+        ),
+        (
+            "127.0.0.1:8001, auth=NONE",
+            "127.0.0.1:8001",
+            ListenerAuth::None,
+        ),
+    ] {
+        let spec: ListenerSpec = text.parse().expect(text);
+        assert_eq!(spec.addr, addr, "{text}");
+        assert_eq!(spec.auth, auth, "{text}");
+    }
+    for text in [
+        "",
+        ",auth=required",
+        "0.0.0.0:8000,auth=maybe",
+        "0.0.0.0:8000,tls",
+    ] {
+        assert!(text.parse::<ListenerSpec>().is_err(), "{text}");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn listeners_apply_their_own_auth_and_drain_together() {
+    let protected = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let open = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let protected_url = format!("http://{}", protected.local_addr().unwrap());
+    let open_url = format!("http://{}", open.local_addr().unwrap());
+    let state = AppState::insecure_mock(true)
+        .with_config(ServeConfig::builder().client_api_key("s3cret").build())
+        .with_executor(Arc::new(ScriptedChatExecutor::new(["hi"])));
+    let (stop, stopped) = oneshot::channel::<()>();
+    let server = tokio::spawn(serve_with_state_listeners(
+        vec![
+            (protected, ListenerAuth::Required),
+            (open, ListenerAuth::None),
+        ],
+        None,
+        state,
+        async move {
+            let _ = stopped.await;
+        },
+    ));
+
+    let missing = post_chat(&protected_url, None).await;
+    assert_eq!(missing.status(), StatusCode::UNAUTHORIZED);
+    let body: Value = missing.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "INVALID_API_KEY");
+    assert_eq!(
+        post_chat(&protected_url, Some("wrong")).await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        post_chat(&protected_url, Some("s3cret")).await.status(),
+        StatusCode::OK
+    );
+    assert_eq!(post_chat(&open_url, None).await.status(), StatusCode::OK);
+
+    let health: Value = reqwest::get(format!("{open_url}/healthz"))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let listeners: Vec<(String, String)> = health["config"]["listeners"]
+        .as_array()
+        .expect("healthz lists the listeners")
+        .iter()
+        .map(|listener| {
+            (
+                format!("http://{}", listener["addr"].as_str().unwrap()),
+                listener["auth"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        listeners,
+        [
+            (protected_url.clone(), "required".to_string()),
+            (open_url.clone(), "none".to_string()),
+        ]
+    );
+
+    stop.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), server)
+        .await
+        .expect("every listener should drain on shutdown")
+        .unwrap()
+        .expect("serving should end cleanly");
+    for url in [&protected_url, &open_url] {
+        assert!(
+            reqwest::get(format!("{url}/healthz")).await.is_err(),
+            "{url} still accepts connections"
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! Metadata routes for monitoring probes and polling clients: `HEAD` answers with the headers of
+//! a `GET` and no body, and the model lists carry an `ETag` that `If-None-Match` turns into `304`.
+
+use codex_serve::{AppState, ServeConfig, server::TestServer};
+use reqwest::{
+    Method, StatusCode,
+    header::{CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+};
+use serde_json::json;
+
+async fn spawn(config: ServeConfig) -> TestServer {
+    TestServer::spawn_with_state(AppState::insecure_mock(true).with_config(config))
+        .await
+        .expect("Codex Serve test server should start")
+}
+
+fn etag(response: &reqwest::Response) -> String {
+    response.headers()[ETAG]
+        .to_str()
+        .expect("ETag is ASCII")
+        .to_string()
+}
+
+#[tokio::test]
+async fn head_requests_get_headers_without_a_body() {
+    let server = spawn(ServeConfig::default()).await;
+    let client = reqwest::Client::new();
+    for path in ["/healthz", "/v1/models", "/api/tags"] {
+        let get = client
+            .get(format!("{}{path}", server.base_url()))
+            .send()
+            .await
+            .expect("GET should reach Codex Serve");
+        let head = client
+            .request(Method::HEAD, format!("{}{path}", server.base_url()))
+            .send()
+            .await
+            .expect("HEAD should reach Codex Serve");
+        assert_eq!(head.status(), StatusCode::OK, "{path}");
+        assert_eq!(
+            head.headers()[CONTENT_TYPE],
+            get.headers()[CONTENT_TYPE],
+            "{path}"
+        );
+        if path != "/healthz" {
+            assert_eq!(etag(&head), etag(&get), "{path}");
+            assert_eq!(head.headers()[CACHE_CONTROL], "max-age=60", "{path}");
+        }
+        assert!(head.bytes().await.expect("HEAD body").is_empty(), "{path}");
+    }
+}
+
+#[tokio::test]
+async fn matching_etags_are_not_modified() {
+    let server = spawn(ServeConfig::default()).await;
+    let client = reqwest::Client::new();
+    let show = |etag: Option<&str>| {
+        let mut request = client
+            .post(format!("{}/api/show", server.base_url()))
+            .json(&json!({"model": "gpt-5"}));
+        if let Some(etag) = etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        request.send()
+    };
+    let list = |path: &str, etag: Option<&str>| {
+        let mut request = client.get(format!("{}{path}", server.base_url()));
+        if let Some(etag) = etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        request.send()
+    };
+
+    for path in ["/v1/models", "/api/tags"] {
+        let fresh = list(path, None).await.expect("list should respond");
+        assert_eq!(fresh.status(), StatusCode::OK);
+        let tag = etag(&fresh);
+        let cached = list(path, Some(&tag)).await.expect("list should respond");
+        assert_eq!(cached.status(), StatusCode::NOT_MODIFIED, "{path}");
+        assert_eq!(etag(&cached), tag);
+        assert!(cached.bytes().await.expect("304 body").is_empty());
+        let stale = list(path, Some("\"stale\""))
+            .await
+            .expect("list should respond");
+        assert_eq!(stale.status(), StatusCode::OK, "{path}");
+    }
+
+    let fresh = show(None).await.expect("show should respond");
+    assert_eq!(fresh.status(), StatusCode::OK);
+    let cached = show(Some(&etag(&fresh)))
+        .await
+        .expect("show should respond");
+    assert_eq!(cached.status(), StatusCode::NOT_MODIFIED);
+}
+
+#[tokio::test]
+async fn etags_change_with_the_exposed_models() {
+    let plain = spawn(ServeConfig::default()).await;
+    let reasoning = spawn(ServeConfig::builder().expose_reasoning_models(true).build()).await;
+    for path in ["/v1/models", "/api/tags"] {
+        let tag = |server: &TestServer| {
+            let url = format!("{}{path}", server.base_url());
+            async move { etag(&reqwest::get(url).await.expect("list should respond")) }
+        };
+        assert_ne!(tag(&plain).await, tag(&reasoning).await, "{path}");
+    }
+}
@@ -0,0 +1,570 @@
+//! Ollama `/api/chat` and `/api/generate`: the same scripted upstream must produce the same reply
+//! whether the client streams NDJSON or asks for a single record.
+
+use std::{sync::Arc, time::Duration};
+
+use codex_core::{ResponseEvent, ResponseItem, protocol::TokenUsage};
+use codex_serve::{
+    AppState, ServeConfig,
+    server::{ProfileCatalog, ScriptedChatExecutor, ScriptedTurn, TestServer},
+};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+
+fn scripted() -> ScriptedChatExecutor {
+    ScriptedChatExecutor::from_events(|| {
+        vec![
+            ResponseEvent::Created,
+            ResponseEvent::ReasoningSummaryPartAdded { summary_index: 0 },
+            ResponseEvent::ReasoningSummaryDelta {
+                delta: "Weighing".to_string(),
+                summary_index: 0,
+            },
+            ResponseEvent::ReasoningSummaryPartAdded { summary_index: 1 },
+            ResponseEvent::ReasoningSummaryDelta {
+                delta: "Answering".to_string(),
+                summary_index: 1,
+            },
+            ResponseEvent::OutputTextDelta("Hello".to_string()),
+            ResponseEvent::OutputTextDelta(", world".to_string()),
+            ResponseEvent::OutputItemDone(ResponseItem::FunctionCall {
+                id: None,
+                name: "get_weather".to_string(),
+                arguments: r#"{"city":"Paris"}"#.to_string(),
+                call_id: "call_1".to_string(),
+            }),
+            ResponseEvent::Completed {
+                response_id: "resp_ollama".to_string(),
+                token_usage: Some(TokenUsage {
+                    input_tokens: 12,
+                    cached_input_tokens: 0,
+                    output_tokens: 5,
+                    reasoning_output_tokens: 0,
+                    total_tokens: 17,
+                }),
+            },
+        ]
+    })
+}
+
+async fn post(server: &TestServer, path: &str, body: Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}{path}", server.base_url()))
+        .json(&body)
+        .send()
+        .await
+        .expect("request should reach Codex Serve")
+}
+
+/// Reads an NDJSON body, checking that only the last record is marked done.
+async fn ndjson(response: reqwest::Response) -> Vec<Value> {
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok()),
+        Some("application/x-ndjson")
+    );
+    let body = response.text().await.expect("stream body");
+    let records: Vec<Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("each line is JSON"))
+        .collect();
+    let (last, rest) = records.split_last().expect("at least one record");
+    assert_eq!(last["done"], true);
+    assert!(rest.iter().all(|record| record["done"] == false));
+    records
+}
+
+fn joined(records: &[Value], pointer: &str) -> String {
+    records
+        .iter()
+        .filter_map(|record| record.pointer(pointer).and_then(Value::as_str))
+        .collect()
+}
+
+fn assert_done_stats(record: &Value) {
+    assert_eq!(record["done_reason"], "stop");
+    assert_eq!(record["prompt_eval_count"], 12);
+    assert_eq!(record["eval_count"], 5);
+    assert!(record["total_duration"].as_u64().is_some_and(|ns| ns > 0));
+    assert!(
+        record["created_at"]
+            .as_str()
+            .is_some_and(|stamp| stamp.ends_with('Z') && stamp.contains('T'))
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn chat_stream_matches_single_response() {
+    let server = TestServer::spawn_with_executor(Arc::new(scripted()))
+        .await
+        .expect("Codex Serve test server should start");
+    let request = |stream: bool| {
+        json!({
+            "model": "gpt-5",
+            "stream": stream,
+            "messages": [{"role": "user", "content": "hi"}]
+        })
+    };
+
+    let single: Value = post(&server, "/api/chat", request(false))
+        .await
+        .json()
+        .await
+        .expect("single response is JSON");
+    assert_eq!(single["done"], true);
+    assert_eq!(single["model"], "gpt-5");
+    assert_done_stats(&single);
+
+    let records = ndjson(post(&server, "/api/chat", request(true)).await).await;
+    assert_eq!(
+        joined(&records, "/message/content"),
+        single["message"]["content"].as_str().unwrap()
+    );
+    assert_eq!(single["message"]["content"], "Hello, world");
+    assert_eq!(
+        joined(&records, "/message/thinking"),
+        single["message"]["thinking"].as_str().unwrap()
+    );
+    let streamed_calls: Vec<&Value> = records
+        .iter()
+        .filter_map(|record| record["message"]["tool_calls"].as_array())
+        .flatten()
+        .collect();
+    assert_eq!(
+        Value::from(streamed_calls.into_iter().cloned().collect::<Vec<_>>()),
+        single["message"]["tool_calls"]
+    );
+    assert_eq!(
+        single["message"]["tool_calls"][0]["function"],
+        json!({"name": "get_weather", "arguments": {"city": "Paris"}})
+    );
+    assert_done_stats(records.last().unwrap());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn generate_stream_matches_single_response() {
+    let server = TestServer::spawn_with_executor(Arc::new(scripted()))
+        .await
+        .expect("Codex Serve test server should start");
+    let request = |stream: bool| {
+        json!({
+            "model": "gpt-5",
+            "stream": stream,
+            "system": "be brief",
+            "prompt": "hi"
+        })
+    };
+
+    let single: Value = post(&server, "/api/generate", request(false))
+        .await
+        .json()
+        .await
+        .expect("single response is JSON");
+    assert_eq!(single["response"], "Hello, world");
+    assert_eq!(single["thinking"], "Weighing\nAnswering");
+    assert_done_stats(&single);
+
+    let records = ndjson(post(&server, "/api/generate", request(true)).await).await;
+    assert_eq!(joined(&records, "/response"), "Hello, world");
+    assert_eq!(joined(&records, "/thinking"), "Weighing\nAnswering");
+    assert_done_stats(records.last().unwrap());
+}
+
+/// Checks each phase against the scripted delays: the handshake is load time, the wait for the
+/// first event is prompt evaluation, and the deltas (five events apart) are evaluation.
+fn assert_measured_durations(record: &Value) {
+    let ms = |field: &str| {
+        Duration::from_nanos(record[field].as_u64().expect("duration is a number")).as_millis()
+    };
+    let (load, prompt, eval, total) = (
+        ms("load_duration"),
+        ms("prompt_eval_duration"),
+        ms("eval_duration"),
+        ms("total_duration"),
+    );
+    assert!(load >= 40, "load {load}ms");
+    assert!(prompt >= 10, "prompt eval {prompt}ms");
+    assert!(eval >= 50, "eval {eval}ms");
+    assert!(total >= load + prompt + eval, "{record}");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn done_records_report_measured_durations() {
+    let executor = scripted()
+        .with_handshake_delay(Duration::from_millis(40))
+        .with_delay(Duration::from_millis(10));
+    let server = TestServer::spawn_with_executor(Arc::new(executor))
+        .await
+        .expect("Codex Serve test server should start");
+    let request = |stream: bool| {
+        json!({
+            "model": "gpt-5",
+            "stream": stream,
+            "messages": [{"role": "user", "content": "hi"}]
+        })
+    };
+
+    let single: Value = post(&server, "/api/chat", request(false))
+        .await
+        .json()
+        .await
+        .expect("single response is JSON");
+    assert_measured_durations(&single);
+
+    let records = ndjson(post(&server, "/api/chat", request(true)).await).await;
+    assert_measured_durations(records.last().unwrap());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn ollama_errors_use_bare_error_bodies() {
+    let server = TestServer::spawn_with_executor(Arc::new(ScriptedChatExecutor::failing(|| {
+        codex_serve::error::ApiError::rate_limited("Codex usage limit reached", None)
+    })))
+    .await
+    .expect("Codex Serve test server should start");
+
+    let single = post(
+        &server,
+        "/api/chat",
+        json!({"model": "gpt-5", "stream": false, "messages": [{"role": "user", "content": "hi"}]}),
+    )
+    .await;
+    assert_eq!(single.status(), StatusCode::TOO_MANY_REQUESTS);
+    let body: Value = single.json().await.expect("error body");
+    assert_eq!(body, json!({"error": "Codex usage limit reached"}));
+
+    // Streams have already sent their headers, so the error is the last line instead.
+    let streamed = post(
+        &server,
+        "/api/generate",
+        json!({"model": "gpt-5", "prompt": "hi"}),
+    )
+    .await;
+    assert_eq!(streamed.status(), StatusCode::OK);
+    let body = streamed.text().await.expect("stream body");
+    let last: Value = serde_json::from_str(body.lines().last().expect("one line")).unwrap();
+    assert_eq!(last, json!({"error": "Codex usage limit reached"}));
+
+    // Conversion errors name the offending field with the same paths as the OpenAI routes.
+    let bad_image = post(
+        &server,
+        "/api/chat",
+        json!({
+            "model": "gpt-5",
+            "messages": [
+                {"role": "user", "content": "hi"},
+                {"role": "user", "content": "what is this?", "images": ["iVBORw0KGgo=", "not an image!"]}
+            ]
+        }),
+    )
+    .await;
+    assert_eq!(bad_image.status(), StatusCode::BAD_REQUEST);
+    let body: Value = bad_image.json().await.expect("error body");
+    assert_eq!(
+        body,
+        json!({"error": "messages[1].images[1]: is not base64-encoded image data"})
+    );
+
+    let load = post(&server, "/api/generate", json!({"model": "gpt-5"})).await;
+    let body: Value = load.json().await.expect("load body");
+    assert_eq!(body["done_reason"], "load");
+}
+
+/// Ollama clients replay tool calls without ids and with object arguments, and answer them with
+/// `tool_name` only; the upstream must still see one call paired with its result.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn id_less_tool_loop_round_trips() {
+    let question = "What's the weather in Paris?";
+    let conversation = ScriptedChatExecutor::conversation(vec![
+        ScriptedTurn::new([format!("user: {question}")], || {
+            vec![
+                ResponseEvent::OutputItemDone(ResponseItem::FunctionCall {
+                    id: None,
+                    name: "get_weather".to_string(),
+                    arguments: r#"{"city":"Paris"}"#.to_string(),
+                    call_id: "call_upstream".to_string(),
+                }),
+                ResponseEvent::Completed {
+                    response_id: "resp_turn_1".to_string(),
+                    token_usage: None,
+                },
+            ]
+        }),
+        ScriptedTurn::new(
+            [
+                format!("user: {question}"),
+                r#"function_call call_1 get_weather({"city":"Paris"})"#.to_string(),
+                "function_call_output call_1: 18°C and sunny".to_string(),
+            ],
+            || {
+                vec![
+                    ResponseEvent::OutputTextDelta("It is 18°C and sunny.".to_string()),
+                    ResponseEvent::Completed {
+                        response_id: "resp_turn_2".to_string(),
+                        token_usage: None,
+                    },
+                ]
+            },
+        ),
+    ]);
+    let server = TestServer::spawn_with_executor(Arc::new(conversation))
+        .await
+        .expect("Codex Serve test server should start");
+    let mut messages = vec![json!({"role": "user", "content": question})];
+
+    let first: Value = post(
+        &server,
+        "/api/chat",
+        json!({"model": "gpt-5", "stream": false, "messages": messages}),
+    )
+    .await
+    .json()
+    .await
+    .expect("first reply is JSON");
+    let assistant = first["message"].clone();
+    assert_eq!(
+        assistant["tool_calls"][0]["function"]["arguments"],
+        json!({"city": "Paris"}),
+        "{first}"
+    );
+    messages.push(assistant);
+    messages.push(json!({"role": "tool", "tool_name": "get_weather", "content": "18°C and sunny"}));
+
+    let records = ndjson(
+        post(
+            &server,
+            "/api/chat",
+            json!({"model": "gpt-5", "messages": messages}),
+        )
+        .await,
+    )
+    .await;
+    assert_eq!(
+        joined(&records, "/message/content"),
+        "It is 18°C and sunny."
+    );
+}
+
+/// `/api/generate` takes a `system` prompt alone as a prompt instead of a load request.
+#[tokio::test]
+async fn system_only_generate_is_answered() {
+    let conversation = ScriptedChatExecutor::conversation(vec![ScriptedTurn::new(
+        ["developer: Write a haiku about rust."],
+        || {
+            vec![
+                ResponseEvent::OutputTextDelta("Red bloom on iron".to_string()),
+                ResponseEvent::Completed {
+                    response_id: "resp_haiku".to_string(),
+                    token_usage: None,
+                },
+            ]
+        },
+    )]);
+    let server = TestServer::spawn_with_executor(Arc::new(conversation))
+        .await
+        .expect("Codex Serve test server should start");
+
+    let reply: Value = post(
+        &server,
+        "/api/generate",
+        json!({"model": "gpt-5", "stream": false, "system": "Write a haiku about rust."}),
+    )
+    .await
+    .json()
+    .await
+    .expect("reply is JSON");
+    assert_eq!(reply["response"], "Red bloom on iron");
+    assert_eq!(reply["done_reason"], "stop");
+}
+
+/// With `--sanitize-tool-names`, a dotted tool name reaches the model as `repo_search`, but the
+/// client's tool calls, replayed history and `tool_name` all keep saying `repo.search`.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn sanitized_tool_names_round_trip() {
+    let question = "Where is the retry logic?";
+    for stream in [false, true] {
+        let conversation = ScriptedChatExecutor::conversation(vec![
+            ScriptedTurn::new([format!("user: {question}")], || {
+                vec![
+                    ResponseEvent::OutputItemDone(ResponseItem::FunctionCall {
+                        id: None,
+                        name: "repo_search".to_string(),
+                        arguments: r#"{"query":"retry"}"#.to_string(),
+                        call_id: "call_upstream".to_string(),
+                    }),
+                    ResponseEvent::Completed {
+                        response_id: "resp_turn_1".to_string(),
+                        token_usage: None,
+                    },
+                ]
+            }),
+            ScriptedTurn::new(
+                [
+                    format!("user: {question}"),
+                    r#"function_call call_1 repo_search({"query":"retry"})"#.to_string(),
+                    "function_call_output call_1: src/retry.rs".to_string(),
+                ],
+                || {
+                    vec![
+                        ResponseEvent::OutputTextDelta("In src/retry.rs.".to_string()),
+                        ResponseEvent::Completed {
+                            response_id: "resp_turn_2".to_string(),
+                            token_usage: None,
+                        },
+                    ]
+                },
+            ),
+        ]);
+        let state = AppState::insecure_mock(true)
+            .with_config(ServeConfig::builder().sanitize_tool_names(true).build())
+            .with_executor(Arc::new(conversation));
+        let server = TestServer::spawn_with_state(state)
+            .await
+            .expect("Codex Serve test server should start");
+        let body = |messages: &[Value]| {
+            json!({
+                "model": "gpt-5",
+                "stream": stream,
+                "messages": messages,
+                "tools": [{"type": "function", "function": {
+                    "name": "repo.search",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {"query": {"type": "string"}}
+                    }
+                }}]
+            })
+        };
+        let reply = |records: Vec<Value>| {
+            records
+                .into_iter()
+                .find(|record| record["message"]["tool_calls"].is_array())
+                .expect("a record with the tool call")
+        };
+        let mut messages = vec![json!({"role": "user", "content": question})];
+
+        let response = post(&server, "/api/chat", body(&messages)).await;
+        let first = if stream {
+            reply(ndjson(response).await)
+        } else {
+            response.json().await.expect("first reply is JSON")
+        };
+        let assistant = first["message"].clone();
+        assert_eq!(
+            assistant["tool_calls"][0]["function"]["name"], "repo.search",
+            "stream: {stream}: {first}"
+        );
+        messages.push(assistant);
+        messages
+            .push(json!({"role": "tool", "tool_name": "repo.search", "content": "src/retry.rs"}));
+
+        let records = ndjson(
+            post(
+                &server,
+                "/api/chat",
+                json!({"model": "gpt-5", "messages": messages, "tools": body(&[])["tools"]}),
+            )
+            .await,
+        )
+        .await;
+        assert_eq!(joined(&records, "/message/content"), "In src/retry.rs.");
+    }
+}
+
+async fn running_models(server: &TestServer) -> Vec<(String, String)> {
+    let ps: Value = reqwest::get(format!("{}/api/ps", server.base_url()))
+        .await
+        .expect("request should reach Codex Serve")
+        .json()
+        .await
+        .expect("/api/ps is JSON");
+    ps["models"]
+        .as_array()
+        .expect("models")
+        .iter()
+        .map(|model| {
+            let field = |name: &str| model[name].as_str().unwrap_or_default().to_string();
+            (field("name"), field("expires_at"))
+        })
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn keep_alive_sets_the_expiry_and_zero_unloads() {
+    let server = TestServer::spawn_with_executor(Arc::new(scripted()))
+        .await
+        .expect("Codex Serve test server should start");
+    let chat = |stream: bool, keep_alive: Value| {
+        json!({
+            "model": "gpt-5",
+            "stream": stream,
+            "keep_alive": keep_alive,
+            "messages": [{"role": "user", "content": "hi"}]
+        })
+    };
+
+    post(&server, "/api/chat", chat(false, json!("5m"))).await;
+    let default = running_models(&server).await;
+    post(&server, "/api/chat", chat(false, json!("2h"))).await;
+    let kept = running_models(&server).await;
+    assert_eq!(kept.len(), 1);
+    assert_eq!(kept[0].0, "gpt-5");
+    // RFC 3339 stamps in UTC sort like the times they name.
+    assert!(kept[0].1 > default[0].1, "{kept:?} vs {default:?}");
+
+    // The request is still answered; the model unloads once it is done.
+    let records = ndjson(post(&server, "/api/chat", chat(true, json!(0))).await).await;
+    assert_eq!(joined(&records, "/message/content"), "Hello, world");
+    assert!(running_models(&server).await.is_empty());
+
+    // A chat without messages loads the model like `/api/generate` without a prompt.
+    let load: Value = post(
+        &server,
+        "/api/chat",
+        json!({"model": "gpt-5", "messages": []}),
+    )
+    .await
+    .json()
+    .await
+    .expect("load body");
+    assert_eq!(load["done_reason"], "load");
+    assert_eq!(running_models(&server).await[0].0, "gpt-5");
+    let unload: Value = post(
+        &server,
+        "/api/chat",
+        json!({"model": "gpt-5", "messages": [], "keep_alive": "0"}),
+    )
+    .await
+    .json()
+    .await
+    .expect("unload body");
+    assert_eq!(unload["done_reason"], "unload");
+    assert!(running_models(&server).await.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn loading_under_an_unknown_profile_names_it() {
+    let state = AppState::insecure_mock(true)
+        .with_profiles(ProfileCatalog::from_names(["work"]))
+        .with_executor(Arc::new(scripted()));
+    let server = TestServer::spawn_with_state(state)
+        .await
+        .expect("Codex Serve test server should start");
+
+    for path in ["/api/chat", "/api/generate"] {
+        let response = reqwest::Client::new()
+            .post(format!("{}{path}", server.base_url()))
+            .header("x-codex-profile", "play")
+            .json(&json!({"model": "gpt-5"}))
+            .send()
+            .await
+            .expect("request should reach Codex Serve");
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{path}");
+        let body: Value = response.json().await.expect("error body");
+        let error = body["error"].as_str().expect("bare error message");
+        assert!(error.contains("Unknown Codex profile `play`"), "{error}");
+    }
+}
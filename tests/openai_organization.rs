@@ -0,0 +1,166 @@
+//! `OpenAI-Organization` and `OpenAI-Project` name the client ahead of its `user` field and
+//! address, scoped under its API key when it sends one, and are echoed on the response the way
+//! OpenAI does.
+
+use std::sync::Arc;
+
+use codex_serve::{
+    AppState, ServeConfig,
+    server::{OPENAI_ORGANIZATION_HEADER, OPENAI_PROJECT_HEADER, ScriptedChatExecutor, TestServer},
+};
+use reqwest::{RequestBuilder, StatusCode, header::AUTHORIZATION};
+use serde_json::{Value, json};
+
+async fn spawn() -> TestServer {
+    let state = AppState::insecure_mock(true)
+        .with_config(
+            ServeConfig::builder()
+                .max_tokens_per_hour(1_000_000)
+                .build(),
+        )
+        .with_executor(Arc::new(ScriptedChatExecutor::new(["ok"])));
+    TestServer::spawn_with_state(state)
+        .await
+        .expect("Codex Serve test server should start")
+}
+
+fn chat(server: &TestServer, user: Option<&str>) -> RequestBuilder {
+    let mut body = json!({
+        "model": "gpt-5",
+        "messages": [{"role": "user", "content": "hi"}]
+    });
+    if let Some(user) = user {
+        body["user"] = json!(user);
+    }
+    reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&body)
+}
+
+/// The clients `/stats/budget` has booked spending for.
+async fn budget_clients(server: &TestServer) -> Vec<String> {
+    let stats: Value = reqwest::get(format!("{}/stats/budget", server.base_url()))
+        .await
+        .expect("stats request")
+        .json()
+        .await
+        .expect("stats are JSON");
+    stats["clients"]
+        .as_object()
+        .expect("clients")
+        .keys()
+        .cloned()
+        .collect()
+}
+
+async fn send(request: RequestBuilder) {
+    let response = request
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(response.status(), StatusCode::OK);
+    response.text().await.expect("body");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn organization_headers_come_after_the_key_in_the_identity_chain() {
+    let server = spawn().await;
+    send(
+        chat(&server, Some("alice"))
+            .header(AUTHORIZATION, "Bearer sk-shared")
+            .header(OPENAI_ORGANIZATION_HEADER, "org-acme")
+            .header(OPENAI_PROJECT_HEADER, "proj_web"),
+    )
+    .await;
+    send(chat(&server, Some("alice")).header(OPENAI_PROJECT_HEADER, "proj_batch")).await;
+    send(chat(&server, Some("alice")).header(AUTHORIZATION, "Bearer sk-shared")).await;
+    send(chat(&server, Some("alice"))).await;
+    send(chat(&server, None)).await;
+
+    let clients = budget_clients(&server).await;
+    for expected in ["project:proj_batch", "user:alice"] {
+        assert!(
+            clients.iter().any(|client| client == expected),
+            "{clients:?}"
+        );
+    }
+    assert!(
+        clients.iter().any(|client| client.starts_with("key:")),
+        "{clients:?}"
+    );
+    assert!(
+        clients.iter().any(|client| client.starts_with("ip:")),
+        "{clients:?}"
+    );
+    // Both requests with the key share its budget, headers or not.
+    assert_eq!(clients.len(), 4, "{clients:?}");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn rotating_the_project_under_one_key_stays_one_budget() {
+    let server = spawn().await;
+    for project in ["proj_1", "proj_2", "proj_3"] {
+        send(
+            chat(&server, None)
+                .header(AUTHORIZATION, "Bearer sk-shared")
+                .header(OPENAI_PROJECT_HEADER, project),
+        )
+        .await;
+    }
+
+    let clients = budget_clients(&server).await;
+    assert_eq!(clients.len(), 1, "{clients:?}");
+    assert!(clients[0].starts_with("key:"), "{clients:?}");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn organization_headers_are_echoed() {
+    let server = spawn().await;
+    let header = |response: &reqwest::Response, name| {
+        response
+            .headers()
+            .get(name)
+            .map(|value| value.to_str().expect("ASCII").to_string())
+    };
+
+    for stream in [false, true] {
+        let response = reqwest::Client::new()
+            .post(format!("{}/v1/chat/completions", server.base_url()))
+            .header(OPENAI_ORGANIZATION_HEADER, "org-acme")
+            .header(OPENAI_PROJECT_HEADER, "proj_web")
+            .json(&json!({
+                "model": "gpt-5",
+                "stream": stream,
+                "messages": [{"role": "user", "content": "hi"}]
+            }))
+            .send()
+            .await
+            .expect("request should reach Codex Serve");
+        assert_eq!(
+            header(&response, &OPENAI_ORGANIZATION_HEADER).as_deref(),
+            Some("org-acme")
+        );
+        assert_eq!(
+            header(&response, &OPENAI_PROJECT_HEADER).as_deref(),
+            Some("proj_web")
+        );
+    }
+
+    // Every route echoes them, errors included.
+    let missing = reqwest::Client::new()
+        .get(format!("{}/v1/nope", server.base_url()))
+        .header(OPENAI_ORGANIZATION_HEADER, "org-acme")
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    assert_eq!(
+        header(&missing, &OPENAI_ORGANIZATION_HEADER).as_deref(),
+        Some("org-acme")
+    );
+    assert_eq!(header(&missing, &OPENAI_PROJECT_HEADER), None);
+
+    let plain = chat(&server, None).send().await.expect("request");
+    assert_eq!(header(&plain, &OPENAI_ORGANIZATION_HEADER), None);
+    assert_eq!(header(&plain, &OPENAI_PROJECT_HEADER), None);
+}
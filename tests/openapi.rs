@@ -0,0 +1,140 @@
+//! `GET /openapi.json` describes the routes the server answers as a well-formed OpenAPI 3.1
+//! document, chat streaming included.
+
+use codex_serve::{
+    AppState, ServeConfig,
+    serve_config::{ApiSurface, ApiSurfaces},
+    server::TestServer,
+};
+use reqwest::StatusCode;
+use serde_json::Value;
+
+async fn document(surfaces: ApiSurfaces) -> Value {
+    let state = AppState::insecure_mock(true)
+        .with_config(ServeConfig::builder().api_surfaces(surfaces).build());
+    let server = TestServer::spawn_with_state(state)
+        .await
+        .expect("Codex Serve test server should start");
+    let response = reqwest::get(format!("{}/openapi.json", server.base_url()))
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(response.status(), StatusCode::OK);
+    response.json().await.expect("the document is JSON")
+}
+
+/// Every `$ref` in `value`.
+fn refs(value: &Value, found: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                match (key.as_str(), value) {
+                    ("$ref", Value::String(target)) => found.push(target.clone()),
+                    _ => refs(value, found),
+                }
+            }
+        }
+        Value::Array(items) => items.iter().for_each(|item| refs(item, found)),
+        _ => {}
+    }
+}
+
+/// The checks an OpenAPI 3.1 validator would fail first: the version, `info`, operations with
+/// responses, and references that resolve.
+fn assert_openapi_3_1(document: &Value) {
+    assert_eq!(document["openapi"], "3.1.0");
+    assert!(document["info"]["title"].is_string());
+    assert!(document["info"]["version"].is_string());
+    let paths = document["paths"].as_object().expect("paths");
+    assert!(!paths.is_empty());
+    for (path, item) in paths {
+        assert!(path.starts_with('/'), "{path}");
+        for (method, operation) in item.as_object().expect("path item") {
+            assert!(
+                ["get", "post", "delete"].contains(&method.as_str()),
+                "{path}: {method}"
+            );
+            let responses = operation["responses"].as_object().expect("responses");
+            assert!(responses.contains_key("200"), "{path}");
+        }
+    }
+
+    let mut found = Vec::new();
+    refs(document, &mut found);
+    assert!(!found.is_empty());
+    for target in found {
+        let name = target
+            .strip_prefix("#/components/schemas/")
+            .unwrap_or_else(|| panic!("unexpected reference {target}"));
+        assert!(
+            document["components"]["schemas"].get(name).is_some(),
+            "{target} does not resolve"
+        );
+    }
+}
+
+fn schema<'a>(document: &'a Value, reference: &Value) -> &'a Value {
+    let name = reference["$ref"]
+        .as_str()
+        .and_then(|target| target.strip_prefix("#/components/schemas/"))
+        .expect("a schema reference");
+    &document["components"]["schemas"][name]
+}
+
+#[tokio::test]
+async fn the_document_describes_chat_completions_and_its_streaming_flag() {
+    let document = document(ApiSurfaces::default()).await;
+    assert_openapi_3_1(&document);
+
+    let chat = &document["paths"]["/v1/chat/completions"]["post"];
+    let request = schema(
+        &document,
+        &chat["requestBody"]["content"]["application/json"]["schema"],
+    );
+    let stream = &request["properties"]["stream"];
+    assert_eq!(stream["type"], "boolean");
+    assert!(stream["description"].is_string());
+    let content = &chat["responses"]["200"]["content"];
+    assert!(content.get("application/json").is_some());
+    let chunk = schema(&document, &content["text/event-stream"]["schema"]);
+    assert_eq!(
+        chunk["properties"]["object"]["const"],
+        "chat.completion.chunk"
+    );
+    assert!(chat["responses"]["default"].is_object());
+
+    // Accepted-but-ignored fields and vendor extensions are told apart from honored ones.
+    let properties = &request["properties"];
+    assert_eq!(properties["max_tokens"]["x-codex-serve-support"], "ignored");
+    assert_eq!(properties["codex"]["x-codex-serve-support"], "vendor");
+    assert!(
+        properties["temperature"]
+            .get("x-codex-serve-support")
+            .is_none()
+    );
+
+    for path in [
+        "/v1/models",
+        "/healthz",
+        "/stats/budget",
+        "/stats/latency",
+        "/stats/clients",
+        "/stats/conversion",
+        "/stats",
+        "/metrics",
+        "/api/chat",
+        "/api/generate",
+        "/api/tags",
+        "/api/pull",
+        "/api/delete",
+    ] {
+        assert!(document["paths"].get(path).is_some(), "{path}");
+    }
+}
+
+#[tokio::test]
+async fn disabled_surfaces_are_left_out() {
+    let document = document(ApiSurfaces::only(ApiSurface::OpenAi)).await;
+    assert_openapi_3_1(&document);
+    assert!(document["paths"].get("/v1/chat/completions").is_some());
+    assert!(document["paths"].get("/api/chat").is_none());
+}
@@ -0,0 +1,88 @@
+//! `stream_options.codex_progress`: a stream interleaves `: progress {...}` SSE comments with
+//! its chunks, and only when asked to.
+
+use std::{sync::Arc, time::Duration};
+
+use codex_serve::{
+    AppState, ServeConfig,
+    server::{ScriptedChatExecutor, TestServer},
+};
+use serde_json::{Value, json};
+
+/// Streams a chat completion with `stream_options` and returns the raw SSE body.
+async fn stream(stream_options: Value) -> String {
+    let executor = ScriptedChatExecutor::new(["Once", " upon", " a", " time"])
+        .with_delay(Duration::from_millis(20));
+    let config = ServeConfig::builder()
+        .progress_interval(Duration::from_millis(1))
+        .build();
+    let state = AppState::insecure_mock(true)
+        .with_config(config)
+        .with_executor(Arc::new(executor));
+    let server = TestServer::spawn_with_state(state)
+        .await
+        .expect("Codex Serve test server should start");
+    reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&json!({
+            "model": "gpt-5",
+            "stream": true,
+            "stream_options": stream_options,
+            "messages": [{"role": "user", "content": "tell a story"}]
+        }))
+        .send()
+        .await
+        .expect("request should reach Codex Serve")
+        .text()
+        .await
+        .expect("stream body")
+}
+
+fn progress_comments(body: &str) -> Vec<Value> {
+    body.lines()
+        .filter(|line| line.starts_with(':'))
+        .filter_map(|line| {
+            line.trim_start_matches([':', ' '])
+                .strip_prefix("progress ")
+        })
+        .map(|json| serde_json::from_str(json).expect("progress is JSON"))
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn progress_comments_are_interleaved_when_requested() {
+    let body = stream(json!({"codex_progress": true})).await;
+    let comments = progress_comments(&body);
+    assert!(!comments.is_empty(), "{body}");
+
+    let estimates: Vec<u64> = comments
+        .iter()
+        .map(|comment| {
+            comment["output_tokens_estimate"]
+                .as_u64()
+                .expect("estimate")
+        })
+        .collect();
+    assert!(estimates.is_sorted(), "{estimates:?}");
+    assert!(
+        comments
+            .iter()
+            .all(|comment| comment["elapsed_ms"].is_u64())
+    );
+
+    // Comments arrive between chunks, never after the stream is done.
+    let first_comment = body.find("progress {").expect("a progress comment");
+    let done = body.find("data: [DONE]").expect("the stream finishes");
+    assert!(body[..first_comment].contains("data: "), "{body}");
+    assert!(first_comment < done);
+    assert!(!body[done..].contains("progress {"), "{body}");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn streams_carry_no_progress_unless_asked() {
+    for stream_options in [Value::Null, json!({"include_usage": true})] {
+        let body = stream(stream_options).await;
+        assert!(progress_comments(&body).is_empty(), "{body}");
+        assert!(body.contains("data: [DONE]"), "{body}");
+    }
+}
@@ -0,0 +1,129 @@
+//! `--queue-requests`: a client's requests over its concurrency cap wait their turn in order.
+//! Queued streams report their place in the queue as SSE comments; other replies say how long
+//! they waited in `x-codex-serve-queue-wait-ms`.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use codex_serve::{
+    AppState,
+    openai::chat::PromptPayload,
+    server::{
+        CapturingExecutor, ClientLimiter, QUEUE_WAIT_HEADER, ScriptedChatExecutor, TestServer,
+    },
+};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+
+/// A server whose only executor slot per client is held for a while by every request, with the
+/// prompts it ran in order.
+async fn saturated() -> (TestServer, Arc<Mutex<Vec<PromptPayload>>>) {
+    let scripted =
+        ScriptedChatExecutor::new(["slow", " reply"]).with_delay(Duration::from_millis(150));
+    let executor = CapturingExecutor::new(Arc::new(scripted));
+    let captured = executor.captured();
+    let state = AppState::insecure_mock(true)
+        .with_executor(Arc::new(executor))
+        .with_client_limiter(ClientLimiter::new(1).with_queue(true));
+    let server = TestServer::spawn_with_state(state)
+        .await
+        .expect("Codex Serve test server should start");
+    (server, captured)
+}
+
+async fn post(server: &TestServer, content: &str, stream: bool) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&json!({
+            "model": "gpt-5",
+            "stream": stream,
+            "user": "a",
+            "messages": [{"role": "user", "content": content}]
+        }))
+        .send()
+        .await
+        .expect("request should reach Codex Serve")
+}
+
+fn queue_positions(body: &str) -> Vec<u64> {
+    body.lines()
+        .filter(|line| line.starts_with(':'))
+        .filter_map(|line| {
+            line.trim_start_matches([':', ' '])
+                .strip_prefix("queued position=")
+        })
+        .map(|position| position.parse().expect("numeric position"))
+        .collect()
+}
+
+fn prompts(captured: &Mutex<Vec<PromptPayload>>) -> Vec<String> {
+    captured
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|payload| payload.first_user_message.clone())
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn queued_streams_report_their_position_and_run_in_order() {
+    let (server, captured) = saturated().await;
+
+    // Each stream answers with headers at once, queued or not.
+    let first = post(&server, "first", true).await;
+    let second = post(&server, "second", true).await;
+    let third = post(&server, "third", true).await;
+    for response in [&first, &second, &third] {
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    let (first, second, third) = tokio::join!(first.text(), second.text(), third.text());
+    let (first, second, third) = (first.unwrap(), second.unwrap(), third.unwrap());
+    assert!(queue_positions(&first).is_empty(), "{first}");
+    assert_eq!(queue_positions(&second), [1], "{second}");
+    assert_eq!(queue_positions(&third), [2, 1], "{third}");
+    for body in [&first, &second, &third] {
+        assert!(body.contains("slow"), "{body}");
+        assert!(body.contains("data: [DONE]"), "{body}");
+    }
+    // The comments come before the reply.
+    let comment = third.find("queued position=1").expect("last position");
+    assert!(comment < third.find("slow").expect("reply"), "{third}");
+
+    assert_eq!(prompts(&captured), ["first", "second", "third"]);
+
+    let stats: Value = reqwest::get(format!("{}/stats/clients", server.base_url()))
+        .await
+        .expect("client stats")
+        .json()
+        .await
+        .expect("client stats body");
+    assert_eq!(stats["clients"]["user:a"]["queued"], 2);
+    assert_eq!(stats["clients"]["user:a"]["rejected"], 0);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn queued_replies_say_how_long_they_waited() {
+    let (server, captured) = saturated().await;
+
+    let (first, second) = tokio::join!(post(&server, "first", false), async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        post(&server, "second", false).await
+    });
+    assert_eq!(first.status(), StatusCode::OK);
+    assert_eq!(second.status(), StatusCode::OK);
+    assert!(first.headers().get(QUEUE_WAIT_HEADER.as_str()).is_none());
+    let waited: u64 = second
+        .headers()
+        .get(QUEUE_WAIT_HEADER.as_str())
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .expect("queue wait header");
+    assert!(waited >= 100, "waited {waited}ms");
+
+    let second: Value = second.json().await.expect("reply");
+    assert_eq!(second["choices"][0]["message"]["content"], "slow reply");
+    assert_eq!(prompts(&captured), ["first", "second"]);
+}
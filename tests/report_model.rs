@@ -0,0 +1,147 @@
+//! `--report-model`: replies name either the model the client asked for or the Codex model it ran
+//! on, with the other in a `codex_*_model` vendor field, over the OpenAI and Ollama APIs alike.
+
+use std::sync::Arc;
+
+use codex_serve::{
+    AppState, ServeConfig,
+    serve_config::ReportModel,
+    server::{ScriptedChatExecutor, TestServer},
+};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+
+/// A reasoning variant: the scripted executor runs it on its base model, like the real one.
+const ALIAS: &str = "gpt-5:high";
+const RESOLVED: &str = "gpt-5";
+
+async fn server(report: Option<ReportModel>) -> TestServer {
+    let mut config = ServeConfig::builder();
+    if let Some(report) = report {
+        config = config.report_model(report);
+    }
+    let state = AppState::insecure_mock(true)
+        .with_config(config.build())
+        .with_executor(Arc::new(ScriptedChatExecutor::new(["Hello", ", world"])));
+    TestServer::spawn_with_state(state)
+        .await
+        .expect("Codex Serve test server should start")
+}
+
+async fn post(server: &TestServer, path: &str, model: &str, stream: bool) -> String {
+    let response = reqwest::Client::new()
+        .post(format!("{}{path}", server.base_url()))
+        .json(&json!({
+            "model": model,
+            "stream": stream,
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(response.status(), StatusCode::OK);
+    response.text().await.expect("body")
+}
+
+fn sse_chunks(body: &str) -> Vec<Value> {
+    body.lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .filter(|data| *data != "[DONE]")
+        .map(|data| serde_json::from_str(data).expect("chunk is JSON"))
+        .collect()
+}
+
+fn ndjson_records(body: &str) -> Vec<Value> {
+    body.lines()
+        .map(|line| serde_json::from_str(line).expect("record is JSON"))
+        .collect()
+}
+
+/// `model`, `codex_requested_model` and `codex_resolved_model` of a reply.
+fn names(value: &Value) -> (Value, Value, Value) {
+    (
+        value["model"].clone(),
+        value["codex_requested_model"].clone(),
+        value["codex_resolved_model"].clone(),
+    )
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn requested_mode_keeps_the_alias_and_names_the_resolved_model() {
+    let server = server(Some(ReportModel::Requested)).await;
+    let expected = (json!(ALIAS), Value::Null, json!(RESOLVED));
+
+    let body = post(&server, "/v1/chat/completions", ALIAS, false).await;
+    let response: Value = serde_json::from_str(&body).expect("completion is JSON");
+    assert_eq!(names(&response), expected, "{response}");
+
+    // The role chunk waits for the handshake, so it names the resolved model too.
+    let chunks = sse_chunks(&post(&server, "/v1/chat/completions", ALIAS, true).await);
+    assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+    for chunk in &chunks {
+        assert_eq!(names(chunk), expected, "{chunk}");
+    }
+
+    for stream in [false, true] {
+        for record in ndjson_records(&post(&server, "/api/chat", ALIAS, stream).await) {
+            assert_eq!(names(&record), expected, "{record}");
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn resolved_mode_reports_the_codex_model_and_keeps_the_alias() {
+    let server = server(Some(ReportModel::Resolved)).await;
+    let expected = (json!(RESOLVED), json!(ALIAS), Value::Null);
+
+    let body = post(&server, "/v1/chat/completions", ALIAS, false).await;
+    let response: Value = serde_json::from_str(&body).expect("completion is JSON");
+    assert_eq!(names(&response), expected, "{response}");
+
+    let chunks = sse_chunks(&post(&server, "/v1/chat/completions", ALIAS, true).await);
+    assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+    assert!(chunks.len() > 2, "{chunks:?}");
+    for chunk in &chunks {
+        assert_eq!(names(chunk), expected, "{chunk}");
+    }
+
+    for stream in [false, true] {
+        for record in ndjson_records(&post(&server, "/api/chat", ALIAS, stream).await) {
+            assert_eq!(names(&record), expected, "{record}");
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn models_that_resolve_to_themselves_still_name_both() {
+    for (report, expected) in [
+        (
+            ReportModel::Requested,
+            (json!(RESOLVED), Value::Null, json!(RESOLVED)),
+        ),
+        (
+            ReportModel::Resolved,
+            (json!(RESOLVED), json!(RESOLVED), Value::Null),
+        ),
+    ] {
+        let server = server(Some(report)).await;
+        let body = post(&server, "/v1/chat/completions", RESOLVED, false).await;
+        let response: Value = serde_json::from_str(&body).expect("completion is JSON");
+        assert_eq!(names(&response), expected, "{report}: {response}");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn without_the_flag_replies_keep_the_requested_name_alone() {
+    let server = server(None).await;
+    let expected = (json!(ALIAS), Value::Null, Value::Null);
+    let body = post(&server, "/v1/chat/completions", ALIAS, false).await;
+    let response: Value = serde_json::from_str(&body).expect("completion is JSON");
+    assert_eq!(names(&response), expected, "{response}");
+    for chunk in sse_chunks(&post(&server, "/v1/chat/completions", ALIAS, true).await) {
+        assert_eq!(names(&chunk), expected, "{chunk}");
+    }
+    for record in ndjson_records(&post(&server, "/api/chat", ALIAS, true).await) {
+        assert_eq!(names(&record), expected, "{record}");
+    }
+}
@@ -0,0 +1,104 @@
+//! One `id` and one `created` per chat request: the JSON reply and every chunk of a stream carry
+//! the pair stamped on the request's prompt payload, never a time of their own.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use codex_core::{ResponseEvent, protocol::TokenUsage};
+use codex_serve::{
+    openai::chat::PromptPayload,
+    server::{CapturingExecutor, ScriptedChatExecutor, TestServer},
+};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+
+fn events() -> Vec<ResponseEvent> {
+    vec![
+        ResponseEvent::Created,
+        ResponseEvent::OutputTextDelta("Hello".to_string()),
+        ResponseEvent::OutputTextDelta(", world".to_string()),
+        ResponseEvent::Completed {
+            response_id: "resp_upstream".to_string(),
+            token_usage: Some(TokenUsage {
+                input_tokens: 4,
+                cached_input_tokens: 0,
+                output_tokens: 3,
+                reasoning_output_tokens: 0,
+                total_tokens: 7,
+            }),
+        },
+    ]
+}
+
+/// A server whose scripted replies take over a second, long enough for a `created` taken late
+/// to differ from the request's.
+async fn spawn() -> (TestServer, Arc<Mutex<Vec<PromptPayload>>>) {
+    let executor = CapturingExecutor::new(Arc::new(
+        ScriptedChatExecutor::from_events(events).with_delay(Duration::from_millis(400)),
+    ));
+    let captured = executor.captured();
+    let server = TestServer::spawn_with_executor(Arc::new(executor))
+        .await
+        .expect("Codex Serve test server should start");
+    (server, captured)
+}
+
+async fn post(server: &TestServer, stream: bool) -> String {
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&json!({
+            "model": "gpt-5",
+            "stream": stream,
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(response.status(), StatusCode::OK);
+    response.text().await.expect("response body")
+}
+
+/// The `(id, created)` the executor was handed for the last request.
+fn stamped(captured: &Mutex<Vec<PromptPayload>>) -> (Value, Value) {
+    let captured = captured.lock().unwrap();
+    let clock = &captured.last().expect("an upstream call").clock;
+    (json!(clock.id()), json!(clock.created()))
+}
+
+fn pair(value: &Value) -> (Value, Value) {
+    (value["id"].clone(), value["created"].clone())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn streamed_and_whole_replies_report_the_request_clock() {
+    let (server, captured) = spawn().await;
+
+    let body: Value = serde_json::from_str(&post(&server, false).await).expect("JSON reply");
+    let whole = stamped(&captured);
+    assert_eq!(pair(&body), whole);
+    assert!(
+        whole
+            .0
+            .as_str()
+            .is_some_and(|id| id.starts_with("chatcmpl-"))
+    );
+
+    let body = post(&server, true).await;
+    let streamed = stamped(&captured);
+    let chunks: Vec<Value> = body
+        .lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .filter(|data| *data != "[DONE]")
+        .map(|data| serde_json::from_str(data).expect("chunk is JSON"))
+        .collect();
+    assert!(chunks.len() >= 4, "{body}");
+    // The finish chunk too: Codex's own response id stays out of the reply.
+    for chunk in &chunks {
+        assert_eq!(pair(chunk), streamed, "{chunk}");
+    }
+
+    // Each request has its own id.
+    assert_ne!(whole.0, streamed.0);
+}
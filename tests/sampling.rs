@@ -0,0 +1,139 @@
+//! `codex.samples` and `codex.select`: a chat request runs as several completions, one reply is
+//! picked by the chosen strategy, and the usage covers every sample.
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use codex_core::{ResponseEvent, protocol::TokenUsage};
+use codex_serve::server::{ScriptedChatExecutor, TestServer};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+
+/// Answers the n-th call with `replies[n % len]`, each costing 10 prompt and 5 completion tokens.
+fn divergent(replies: &'static [&'static str]) -> ScriptedChatExecutor {
+    let calls = AtomicUsize::new(0);
+    ScriptedChatExecutor::from_events(move || {
+        let reply = replies[calls.fetch_add(1, Ordering::SeqCst) % replies.len()];
+        vec![
+            ResponseEvent::OutputTextDelta(reply.to_string()),
+            ResponseEvent::Completed {
+                response_id: "resp_sample".to_string(),
+                token_usage: Some(TokenUsage {
+                    input_tokens: 10,
+                    cached_input_tokens: 0,
+                    output_tokens: 5,
+                    reasoning_output_tokens: 0,
+                    total_tokens: 15,
+                }),
+            },
+        ]
+    })
+}
+
+async fn sampled(replies: &'static [&'static str], codex: Value) -> (StatusCode, Value) {
+    let server = TestServer::spawn_with_executor(Arc::new(divergent(replies)))
+        .await
+        .expect("Codex Serve test server should start");
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&json!({
+            "model": "gpt-5",
+            "messages": [{"role": "user", "content": "extract the city"}],
+            "codex": codex
+        }))
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    let status = response.status();
+    (status, response.json().await.expect("response is JSON"))
+}
+
+fn content(body: &Value) -> &str {
+    body["choices"][0]["message"]["content"]
+        .as_str()
+        .expect("message content")
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn majority_returns_the_reply_most_samples_agree_on() {
+    let (status, body) = sampled(
+        &["Paris", "Lyon", "  paris "],
+        json!({"samples": 3, "select": "majority"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(content(&body).trim().to_lowercase(), "paris");
+    assert_eq!(body["choices"].as_array().map(Vec::len), Some(1));
+    let selection = &body["codex_selection"];
+    assert_eq!(selection["select"], "majority");
+    assert_eq!(selection["samples"], 3);
+    assert_eq!(selection["reason"], "2 of 3 samples agreed");
+    assert_eq!(
+        selection["discarded_lengths"].as_array().map(Vec::len),
+        Some(2)
+    );
+
+    assert_eq!(body["usage"]["prompt_tokens"], 30);
+    assert_eq!(body["usage"]["completion_tokens"], 15);
+    assert_eq!(body["usage"]["total_tokens"], 45);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn first_valid_json_skips_prose_replies() {
+    let (status, body) = sampled(
+        &[
+            "Sure, the city is Paris.",
+            "{\"city\": \"Paris\"}",
+            "city: Paris",
+        ],
+        json!({"samples": 3, "select": "first_valid_json"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(content(&body), "{\"city\": \"Paris\"}");
+    let mut discarded: Vec<u64> = body["codex_selection"]["discarded_lengths"]
+        .as_array()
+        .expect("discarded lengths")
+        .iter()
+        .filter_map(Value::as_u64)
+        .collect();
+    discarded.sort_unstable();
+    assert_eq!(discarded, [11, 24]);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn longest_returns_the_longest_reply() {
+    let (status, body) = sampled(
+        &["Paris", "Paris, France", "Paris, Île-de-France, France"],
+        json!({"samples": 3, "select": "longest"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(content(&body), "Paris, Île-de-France, France");
+    assert_eq!(
+        body["codex_selection"]["reason"],
+        "the longest of 3 samples (28 characters)"
+    );
+    assert_eq!(body["usage"]["total_tokens"], 45);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn sampling_options_are_validated() {
+    for (codex, field) in [
+        (json!({"samples": 9}), "codex.samples"),
+        (json!({"samples": 0}), "codex.samples"),
+        (json!({"samples": 2, "select": "best"}), "codex.select"),
+    ] {
+        let (status, body) = sampled(&["Paris"], codex).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+        let message = body["error"]["message"].as_str().expect("error message");
+        assert!(message.starts_with(field), "{message}");
+    }
+
+    // One sample is a plain completion.
+    let (status, body) = sampled(&["Paris"], json!({"samples": 1})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.get("codex_selection").is_none(), "{body}");
+}
@@ -0,0 +1,201 @@
+//! `--max-sse-event-bytes`: deltas too large for one event, like tool arguments that arrive
+//! whole, are split across consecutive chunks that reassemble to the original.
+
+use std::sync::Arc;
+
+use codex_core::{ContentItem, ResponseEvent, ResponseItem};
+use codex_serve::{
+    AppState, ServeConfig,
+    server::{ScriptedChatExecutor, TestServer},
+};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+
+const LIMIT: usize = 16 * 1024;
+
+/// About 200KB of JSON arguments with quotes, escapes and multibyte characters.
+fn argument_blob() -> String {
+    let lines: Vec<String> = (0..6000)
+        .map(|i| format!("line {i}: \"café\" \\ naïve ✓"))
+        .collect();
+    serde_json::to_string(&json!({"path": "notes.md", "content": lines.join("\n")})).unwrap()
+}
+
+fn reply_text() -> String {
+    "Un café crème, s'il vous plaît. ".repeat(6000)
+}
+
+async fn stream(events: impl Fn() -> Vec<ResponseEvent> + Send + Sync + 'static) -> String {
+    let executor = ScriptedChatExecutor::from_events(events);
+    let config = ServeConfig::builder()
+        .max_sse_event_bytes(LIMIT)
+        .fallback_chunk_bytes(usize::MAX)
+        .build();
+    let state = AppState::insecure_mock(true)
+        .with_config(config)
+        .with_executor(Arc::new(executor));
+    let server = TestServer::spawn_with_state(state)
+        .await
+        .expect("Codex Serve test server should start");
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&json!({
+            "model": "gpt-5",
+            "stream": true,
+            "messages": [{"role": "user", "content": "write the notes"}],
+            "tools": [{
+                "type": "function",
+                "function": {"name": "write_file", "parameters": {"type": "object"}}
+            }]
+        }))
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(response.status(), StatusCode::OK);
+    response.text().await.expect("stream body")
+}
+
+/// Every event's chunk, after checking that none is over the limit.
+fn chunks(body: &str) -> Vec<Value> {
+    body.split("\n\n")
+        .filter(|event| !event.is_empty())
+        .inspect(|event| {
+            let size = event.len() + "\n\n".len();
+            assert!(size <= LIMIT, "an event of {size} bytes");
+        })
+        .filter_map(|event| event.strip_prefix("data: "))
+        .take_while(|data| *data != "[DONE]")
+        .map(|data| serde_json::from_str(data).expect("chunk is JSON"))
+        .collect()
+}
+
+/// Folds the tool call deltas of `chunks` together the way the OpenAI SDKs' stream accumulators
+/// do: by `index`, appending every string field except `type`.
+fn accumulate_tool_calls(chunks: &[Value]) -> Vec<Value> {
+    fn merge(into: &mut Value, delta: &Value) {
+        let (Value::Object(into), Value::Object(delta)) = (into, delta) else {
+            return;
+        };
+        for (key, value) in delta {
+            match (into.get_mut(key), value) {
+                (Some(Value::String(acc)), Value::String(more)) if key != "type" => {
+                    acc.push_str(more);
+                }
+                (Some(acc @ Value::Object(_)), Value::Object(_)) => merge(acc, value),
+                _ => {
+                    into.insert(key.clone(), value.clone());
+                }
+            }
+        }
+    }
+
+    let mut calls: Vec<Value> = Vec::new();
+    for chunk in chunks {
+        let Some(deltas) = chunk["choices"][0]["delta"]["tool_calls"].as_array() else {
+            continue;
+        };
+        for delta in deltas {
+            let index = delta["index"].as_u64().expect("tool call index") as usize;
+            if calls.len() <= index {
+                calls.resize(index + 1, json!({}));
+            }
+            merge(&mut calls[index], delta);
+        }
+    }
+    calls
+}
+
+fn completed() -> ResponseEvent {
+    ResponseEvent::Completed {
+        response_id: "resp_large".to_string(),
+        token_usage: None,
+    }
+}
+
+#[tokio::test]
+async fn whole_tool_arguments_are_split_under_the_limit() {
+    let arguments = argument_blob();
+    assert!(arguments.len() > 200 * 1000);
+    let body = stream(|| {
+        vec![
+            ResponseEvent::OutputItemDone(ResponseItem::FunctionCall {
+                id: None,
+                name: "write_file".to_string(),
+                arguments: argument_blob(),
+                call_id: "call_1".to_string(),
+            }),
+            completed(),
+        ]
+    })
+    .await;
+
+    let chunks = chunks(&body);
+    let pieces = chunks
+        .iter()
+        .filter(|chunk| chunk["choices"][0]["delta"]["tool_calls"].is_array())
+        .count();
+    assert!(pieces > 1, "the arguments should need several events");
+    assert_eq!(
+        accumulate_tool_calls(&chunks),
+        vec![json!({
+            "index": 0,
+            "id": "call_1",
+            "type": "function",
+            "function": {"name": "write_file", "arguments": arguments},
+        })]
+    );
+}
+
+#[tokio::test]
+async fn a_call_streamed_in_parts_names_itself_once() {
+    let arguments = argument_blob();
+    let body = stream(|| {
+        let call = |arguments: &str| ResponseItem::FunctionCall {
+            id: None,
+            name: "write_file".to_string(),
+            arguments: arguments.to_string(),
+            call_id: "call_1".to_string(),
+        };
+        let arguments = argument_blob();
+        let (cut, _) = arguments.char_indices().nth(100).expect("long arguments");
+        vec![
+            ResponseEvent::OutputItemAdded(call(&arguments[..cut])),
+            ResponseEvent::OutputItemDone(call(&arguments)),
+            completed(),
+        ]
+    })
+    .await;
+
+    assert_eq!(
+        accumulate_tool_calls(&chunks(&body)),
+        vec![json!({
+            "index": 0,
+            "id": "call_1",
+            "type": "function",
+            "function": {"name": "write_file", "arguments": arguments},
+        })]
+    );
+}
+
+#[tokio::test]
+async fn the_single_message_fallback_is_split_under_the_limit() {
+    let body = stream(|| {
+        vec![
+            ResponseEvent::OutputItemDone(ResponseItem::Message {
+                id: None,
+                role: "assistant".to_string(),
+                content: vec![ContentItem::OutputText { text: reply_text() }],
+            }),
+            completed(),
+        ]
+    })
+    .await;
+
+    let contents: Vec<String> = chunks(&body)
+        .iter()
+        .filter_map(|chunk| chunk["choices"][0]["delta"]["content"].as_str())
+        .map(str::to_string)
+        .collect();
+    assert!(contents.len() > 1);
+    assert_eq!(contents.concat(), reply_text());
+}
@@ -0,0 +1,120 @@
+//! `--stream-integrity`: the finish chunk advertises the SHA-256 and byte length of the content
+//! deltas the stream sent, so a client can check the text it reassembled.
+
+use std::sync::Arc;
+
+use codex_core::{ResponseEvent, ResponseItem};
+use codex_serve::{
+    AppState, ServeConfig,
+    server::{ScriptedChatExecutor, TestServer},
+};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+
+const DELTAS: [&str; 3] = ["Un café crème, ", "s'il vous plaît ", "✓✓✓"];
+
+fn events() -> Vec<ResponseEvent> {
+    let mut events = vec![
+        ResponseEvent::ReasoningSummaryPartAdded { summary_index: 0 },
+        ResponseEvent::ReasoningSummaryDelta {
+            delta: "Ordering coffee".to_string(),
+            summary_index: 0,
+        },
+    ];
+    events.extend(
+        DELTAS
+            .iter()
+            .map(|delta| ResponseEvent::OutputTextDelta(delta.to_string())),
+    );
+    events.push(ResponseEvent::OutputItemDone(ResponseItem::FunctionCall {
+        id: None,
+        name: "pay".to_string(),
+        arguments: r#"{"amount":"3.50"}"#.to_string(),
+        call_id: "call_1".to_string(),
+    }));
+    events.push(ResponseEvent::Completed {
+        response_id: "resp_integrity".to_string(),
+        token_usage: None,
+    });
+    events
+}
+
+async fn stream(config: ServeConfig) -> Vec<Value> {
+    let state = AppState::insecure_mock(true)
+        .with_config(config)
+        .with_executor(Arc::new(ScriptedChatExecutor::from_events(events)));
+    let server = TestServer::spawn_with_state(state)
+        .await
+        .expect("Codex Serve test server should start");
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&json!({
+            "model": "gpt-5",
+            "stream": true,
+            "messages": [{"role": "user", "content": "order a coffee"}],
+            "tools": [{
+                "type": "function",
+                "function": {"name": "pay", "parameters": {"type": "object"}}
+            }]
+        }))
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.text().await.expect("stream body");
+    body.lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .filter(|data| *data != "[DONE]")
+        .map(|data| serde_json::from_str(data).expect("chunk is JSON"))
+        .collect()
+}
+
+fn sha256(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn the_finish_chunk_advertises_the_digest_of_the_content_deltas() {
+    let chunks = stream(ServeConfig::builder().stream_integrity(true).build()).await;
+
+    let reassembled: String = chunks
+        .iter()
+        .filter_map(|chunk| chunk["choices"][0]["delta"]["content"].as_str())
+        .collect();
+    assert_eq!(reassembled, DELTAS.concat());
+
+    let finish = chunks
+        .iter()
+        .find(|chunk| !chunk["choices"][0]["finish_reason"].is_null())
+        .expect("a finish chunk");
+    assert_eq!(finish["choices"][0]["finish_reason"], "tool_calls");
+    assert_eq!(finish["codex_content_sha256"], sha256(&reassembled));
+    assert_eq!(finish["codex_content_length"], reassembled.len());
+
+    // Reasoning and tool calls are left out, and only the finish chunk carries the fields.
+    assert!(
+        chunks
+            .iter()
+            .any(|chunk| !chunk["choices"][0]["delta"]["reasoning"].is_null())
+    );
+    assert!(
+        chunks
+            .iter()
+            .any(|chunk| !chunk["choices"][0]["delta"]["tool_calls"].is_null())
+    );
+    let advertising = chunks
+        .iter()
+        .filter(|chunk| chunk.get("codex_content_sha256").is_some())
+        .count();
+    assert_eq!(advertising, 1);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn streams_carry_no_digest_by_default() {
+    let chunks = stream(ServeConfig::default()).await;
+    for chunk in &chunks {
+        assert_eq!(chunk.get("codex_content_sha256"), None, "{chunk}");
+        assert_eq!(chunk.get("codex_content_length"), None, "{chunk}");
+    }
+}
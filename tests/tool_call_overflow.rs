@@ -0,0 +1,128 @@
+//! A response that names far more tool calls than `--max-tracked-tool-calls`: every call still
+//! reaches the client, but only the first ones are tracked by id and the reply says how many were
+//! not.
+
+use std::sync::Arc;
+
+use codex_core::{ResponseEvent, ResponseItem};
+use codex_protocol::models::WebSearchAction;
+use codex_serve::{
+    AppState, ServeConfig,
+    server::{ScriptedChatExecutor, TestServer},
+};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+
+const SEARCHES: usize = 1000;
+
+/// Distinct searches without an id, so each gets its own `ws_call_` id as the pathological streams
+/// do.
+fn searches() -> ScriptedChatExecutor {
+    ScriptedChatExecutor::from_events(|| {
+        let mut events: Vec<_> = (0..SEARCHES)
+            .map(|i| {
+                ResponseEvent::OutputItemDone(ResponseItem::WebSearchCall {
+                    id: None,
+                    status: Some("completed".to_string()),
+                    action: WebSearchAction::Search {
+                        query: Some(format!("query {i}")),
+                    },
+                })
+            })
+            .collect();
+        events.push(ResponseEvent::Completed {
+            response_id: "resp_searches".to_string(),
+            token_usage: None,
+        });
+        events
+    })
+}
+
+async fn post(config: ServeConfig, stream: bool) -> String {
+    let state = AppState::insecure_mock(true)
+        .with_config(config)
+        .with_executor(Arc::new(searches()));
+    let server = TestServer::spawn_with_state(state)
+        .await
+        .expect("Codex Serve test server should start");
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&json!({
+            "model": "gpt-5",
+            "stream": stream,
+            "messages": [{"role": "user", "content": "Search everything."}]
+        }))
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(response.status(), StatusCode::OK);
+    response.text().await.expect("response body")
+}
+
+#[tokio::test]
+async fn streamed_calls_past_the_limit_are_flagged() {
+    let body = post(
+        ServeConfig::builder().max_tracked_tool_calls(16).build(),
+        true,
+    )
+    .await;
+    let chunks: Vec<Value> = body
+        .lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .take_while(|data| *data != "[DONE]")
+        .map(|data| serde_json::from_str(data).expect("chunk is JSON"))
+        .collect();
+
+    let mut indices: Vec<u64> = chunks
+        .iter()
+        .flat_map(|chunk| {
+            chunk["choices"][0]["delta"]["tool_calls"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default()
+        })
+        .map(|call| call["index"].as_u64().expect("tool call index"))
+        .collect();
+    indices.dedup();
+    assert_eq!(indices, (0..SEARCHES as u64).collect::<Vec<_>>());
+
+    let finish = chunks
+        .iter()
+        .find(|chunk| !chunk["choices"][0]["finish_reason"].is_null())
+        .expect("finish chunk");
+    assert_eq!(finish["choices"][0]["finish_reason"], "tool_calls");
+    assert_eq!(
+        finish["tool_call_overflow"],
+        json!({"limit": 16, "untracked": SEARCHES - 16})
+    );
+    assert!(
+        chunks
+            .iter()
+            .filter(|chunk| chunk != &finish)
+            .all(|chunk| chunk.get("tool_call_overflow").is_none())
+    );
+}
+
+#[tokio::test]
+async fn aggregated_calls_past_the_limit_are_flagged() {
+    let body: Value =
+        serde_json::from_str(&post(ServeConfig::default(), false).await).expect("JSON response");
+    let calls = body["choices"][0]["message"]["tool_calls"]
+        .as_array()
+        .expect("tool calls");
+    assert_eq!(calls.len(), SEARCHES);
+    assert_eq!(calls[SEARCHES - 1]["function"]["name"], "web_search");
+    assert_eq!(
+        body["tool_call_overflow"],
+        json!({"limit": 256, "untracked": SEARCHES - 256})
+    );
+}
+
+#[tokio::test]
+async fn replies_within_the_limit_carry_no_flag() {
+    let config = ServeConfig::builder()
+        .max_tracked_tool_calls(SEARCHES)
+        .build();
+    let body = post(config, true).await;
+    assert!(!body.contains("tool_call_overflow"));
+}
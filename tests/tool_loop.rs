@@ -0,0 +1,378 @@
+//! A full tool loop as a client drives it: the assistant asks for a tool, the client replays that
+//! reply plus the tool result, and the assistant answers. The scripted upstream checks the prompt
+//! of every turn, so a conversion that drops or reorders the replayed items fails with a diff.
+
+use std::sync::Arc;
+
+use codex_core::{ResponseEvent, ResponseItem};
+use codex_serve::{
+    AppState, ServeConfig,
+    serve_config::ToolCallFallback,
+    server::{ScriptedChatExecutor, ScriptedTurn, TestServer},
+};
+use reqwest::StatusCode;
+use serde_json::{Map, Value, json};
+
+const QUESTION: &str = "What's the weather in Paris?";
+
+fn completed(id: &str) -> ResponseEvent {
+    ResponseEvent::Completed {
+        response_id: id.to_string(),
+        token_usage: None,
+    }
+}
+
+fn weather_conversation() -> ScriptedChatExecutor {
+    ScriptedChatExecutor::conversation(vec![
+        ScriptedTurn::new([format!("user: {QUESTION}")], || {
+            vec![
+                ResponseEvent::OutputItemDone(ResponseItem::FunctionCall {
+                    id: None,
+                    name: "get_weather".to_string(),
+                    arguments: r#"{"city":"Paris"}"#.to_string(),
+                    call_id: "call_1".to_string(),
+                }),
+                completed("resp_turn_1"),
+            ]
+        }),
+        ScriptedTurn::new(
+            [
+                format!("user: {QUESTION}"),
+                r#"function_call call_1 get_weather({"city":"Paris"})"#.to_string(),
+                "function_call_output call_1: 18°C and sunny".to_string(),
+            ],
+            || {
+                vec![
+                    ResponseEvent::OutputTextDelta("It is 18°C and sunny ".to_string()),
+                    ResponseEvent::OutputTextDelta("in Paris.".to_string()),
+                    completed("resp_turn_2"),
+                ]
+            },
+        ),
+    ])
+}
+
+fn request(messages: &[Value], stream: bool) -> Value {
+    json!({
+        "model": "gpt-5",
+        "stream": stream,
+        "messages": messages,
+        "tools": [{
+            "type": "function",
+            "function": {
+                "name": "get_weather",
+                "parameters": {
+                    "type": "object",
+                    "properties": {"city": {"type": "string"}},
+                    "required": ["city"]
+                }
+            }
+        }]
+    })
+}
+
+async fn send_turn(server: &TestServer, messages: &[Value], stream: bool) -> Value {
+    send_request(server, &request(messages, stream), stream).await
+}
+
+/// Posts one turn and returns the assistant message the way a client would rebuild it: as is for
+/// a single response, or folded from the deltas of a stream.
+async fn send_request(server: &TestServer, body: &Value, stream: bool) -> Value {
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(body)
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    let status = response.status();
+    let body = response.text().await.expect("response body");
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    if !stream {
+        let body: Value = serde_json::from_str(&body).expect("response is JSON");
+        return body["choices"][0]["message"].clone();
+    }
+    let mut content = String::new();
+    let mut tool_calls: Vec<Value> = Vec::new();
+    for data in body.lines().filter_map(|line| line.strip_prefix("data: ")) {
+        if data == "[DONE]" {
+            break;
+        }
+        let chunk: Value = serde_json::from_str(data).expect("chunk is JSON");
+        let delta = &chunk["choices"][0]["delta"];
+        assert!(chunk.get("error").is_none(), "stream failed: {chunk}");
+        if let Some(text) = delta["content"].as_str() {
+            content.push_str(text);
+        }
+        for call in delta["tool_calls"].as_array().into_iter().flatten() {
+            let index = call["index"].as_u64().expect("tool call index") as usize;
+            if tool_calls.len() <= index {
+                tool_calls.resize(index + 1, json!({"type": "function", "function": {}}));
+            }
+            merge_tool_call(&mut tool_calls[index], call);
+        }
+    }
+    let mut message = json!({"role": "assistant", "content": content});
+    if !tool_calls.is_empty() {
+        message["content"] = Value::Null;
+        message["tool_calls"] = Value::Array(tool_calls);
+    }
+    message
+}
+
+/// Streamed tool calls arrive as an id and name first, then argument fragments.
+fn merge_tool_call(call: &mut Value, delta: &Value) {
+    if let Some(id) = delta["id"].as_str() {
+        call["id"] = json!(id);
+    }
+    let function = call["function"].as_object_mut().expect("function object");
+    if let Some(name) = delta["function"]["name"].as_str() {
+        function.insert("name".to_string(), json!(name));
+    }
+    if let Some(arguments) = delta["function"]["arguments"].as_str() {
+        let existing = function
+            .entry("arguments")
+            .or_insert_with(|| json!(""))
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        function.insert("arguments".to_string(), json!(existing + arguments));
+    }
+}
+
+fn tool_result(assistant: &Value) -> Value {
+    let call = &assistant["tool_calls"][0];
+    assert_eq!(call["function"]["name"], "get_weather", "{assistant}");
+    assert_eq!(call["function"]["arguments"], r#"{"city":"Paris"}"#);
+    json!({
+        "role": "tool",
+        "tool_call_id": call["id"],
+        "content": "18°C and sunny"
+    })
+}
+
+async fn run_tool_loop(stream: bool) {
+    let server = TestServer::spawn_with_executor(Arc::new(weather_conversation()))
+        .await
+        .expect("Codex Serve test server should start");
+    let mut messages = vec![json!({"role": "user", "content": QUESTION})];
+
+    let assistant = send_turn(&server, &messages, stream).await;
+    let result = tool_result(&assistant);
+    messages.extend([assistant, result]);
+
+    let answer = send_turn(&server, &messages, stream).await;
+    assert_eq!(answer["content"], "It is 18°C and sunny in Paris.");
+    assert!(
+        answer
+            .as_object()
+            .and_then(|message| message.get("tool_calls"))
+            .is_none_or(|calls| calls.is_null() || calls == &Value::Array(Vec::new())),
+        "{answer}"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn tool_loop_round_trips_without_streaming() {
+    run_tool_loop(false).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn tool_loop_round_trips_while_streaming() {
+    run_tool_loop(true).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn replay_without_the_tool_call_is_reported_as_a_diff() {
+    let server = TestServer::spawn_with_executor(Arc::new(weather_conversation()))
+        .await
+        .expect("Codex Serve test server should start");
+    let question = json!({"role": "user", "content": QUESTION});
+    send_turn(&server, std::slice::from_ref(&question), false).await;
+
+    // A client that forgets to replay the assistant's tool call.
+    let orphan_result =
+        json!({"role": "tool", "tool_call_id": "call_1", "content": "18°C and sunny"});
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&request(&[question, orphan_result], false))
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let body: Map<String, Value> = response.json().await.expect("error body");
+    let message = body["error"]["message"].as_str().expect("error message");
+    assert!(
+        message.contains(
+            "turn 2 prompt does not match the script (- expected, + received):\n  user: What's the weather in Paris?\n- function_call call_1 get_weather({\"city\":\"Paris\"})\n+ function_call_output call_1: 18°C and sunny\n- function_call_output call_1: 18°C and sunny\n"
+        ),
+        "{message}"
+    );
+}
+
+/// A reply that calls a tool after some text, whatever the request declared.
+fn unexpected_tool_call() -> ScriptedChatExecutor {
+    ScriptedChatExecutor::from_events(|| {
+        vec![
+            ResponseEvent::OutputTextDelta("Let me check.".to_string()),
+            ResponseEvent::OutputItemDone(ResponseItem::FunctionCall {
+                id: None,
+                name: "web_search".to_string(),
+                arguments: r#"{"query":"weather in Paris"}"#.to_string(),
+                call_id: "ws_1".to_string(),
+            }),
+            completed("resp_search"),
+        ]
+    })
+}
+
+/// Sends [`QUESTION`] with or without the weather tool and returns the rebuilt assistant message
+/// with the finish reason.
+async fn describe_mode_reply(declare_tools: bool, stream: bool) -> (Value, String) {
+    let state = AppState::insecure_mock(true)
+        .with_config(
+            ServeConfig::builder()
+                .tool_call_fallback(ToolCallFallback::Describe)
+                .build(),
+        )
+        .with_executor(Arc::new(unexpected_tool_call()));
+    let server = TestServer::spawn_with_state(state)
+        .await
+        .expect("Codex Serve test server should start");
+    let mut body = request(&[json!({"role": "user", "content": QUESTION})], stream);
+    if !declare_tools {
+        body.as_object_mut()
+            .expect("request object")
+            .remove("tools");
+    }
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&body)
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    let text = response.text().await.expect("response body");
+    if !stream {
+        let body: Value = serde_json::from_str(&text).expect("response is JSON");
+        let choice = &body["choices"][0];
+        let finish = choice["finish_reason"].as_str().expect("finish reason");
+        return (choice["message"].clone(), finish.to_string());
+    }
+
+    let mut content = String::new();
+    let mut tool_calls = Vec::new();
+    let mut finish = String::new();
+    for data in text.lines().filter_map(|line| line.strip_prefix("data: ")) {
+        if data == "[DONE]" {
+            break;
+        }
+        let chunk: Value = serde_json::from_str(data).expect("chunk is JSON");
+        let choice = &chunk["choices"][0];
+        content.push_str(choice["delta"]["content"].as_str().unwrap_or_default());
+        tool_calls.extend(
+            choice["delta"]["tool_calls"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default(),
+        );
+        if let Some(reason) = choice["finish_reason"].as_str() {
+            finish = reason.to_string();
+        }
+    }
+    let message = json!({"content": content, "tool_calls": tool_calls});
+    (message, finish)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn tool_calls_without_declared_tools_are_described() {
+    for stream in [false, true] {
+        let (message, finish) = describe_mode_reply(false, stream).await;
+        assert_eq!(finish, "stop", "stream: {stream}");
+        assert_eq!(
+            message["content"],
+            "Let me check.\n\nThe model attempted to call `web_search` with query \"weather in Paris\".",
+            "stream: {stream}"
+        );
+        assert!(
+            message["tool_calls"]
+                .as_array()
+                .is_none_or(|calls| calls.is_empty()),
+            "stream: {stream}: {message}"
+        );
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn declared_tools_keep_their_tool_calls_in_describe_mode() {
+    for stream in [false, true] {
+        let (message, finish) = describe_mode_reply(true, stream).await;
+        assert_eq!(finish, "tool_calls", "stream: {stream}");
+        assert_eq!(message["content"], "Let me check.", "stream: {stream}");
+        assert_eq!(
+            message["tool_calls"][0]["function"]["name"], "web_search",
+            "stream: {stream}"
+        );
+    }
+}
+
+/// With `--sanitize-tool-names`, the model sees `weather_get` but the client only ever sees the
+/// `weather.get` it declared, in replies and in the history it replays.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn sanitized_tool_names_are_restored_on_tool_calls() {
+    for stream in [false, true] {
+        let executor = ScriptedChatExecutor::conversation(vec![
+            ScriptedTurn::new([format!("user: {QUESTION}")], || {
+                vec![
+                    ResponseEvent::OutputItemDone(ResponseItem::FunctionCall {
+                        id: None,
+                        name: "weather_get".to_string(),
+                        arguments: r#"{"city":"Paris"}"#.to_string(),
+                        call_id: "call_1".to_string(),
+                    }),
+                    completed("resp_turn_1"),
+                ]
+            }),
+            ScriptedTurn::new(
+                [
+                    format!("user: {QUESTION}"),
+                    r#"function_call call_1 weather_get({"city":"Paris"})"#.to_string(),
+                    "function_call_output call_1: 18°C and sunny".to_string(),
+                ],
+                || {
+                    vec![
+                        ResponseEvent::OutputTextDelta("It is 18°C and sunny.".to_string()),
+                        completed("resp_turn_2"),
+                    ]
+                },
+            ),
+        ]);
+        let state = AppState::insecure_mock(true)
+            .with_config(ServeConfig::builder().sanitize_tool_names(true).build())
+            .with_executor(Arc::new(executor));
+        let server = TestServer::spawn_with_state(state)
+            .await
+            .expect("Codex Serve test server should start");
+        let body = |messages: &[Value]| {
+            let mut body = request(messages, stream);
+            body["tools"][0]["function"]["name"] = json!("weather.get");
+            body
+        };
+        let mut messages = vec![json!({"role": "user", "content": QUESTION})];
+
+        let assistant = send_request(&server, &body(&messages), stream).await;
+        let call = &assistant["tool_calls"][0];
+        assert_eq!(call["function"]["name"], "weather.get", "stream: {stream}");
+        let result = json!({
+            "role": "tool",
+            "tool_call_id": call["id"],
+            "content": "18°C and sunny"
+        });
+        messages.extend([assistant, result]);
+
+        let answer = send_request(&server, &body(&messages), stream).await;
+        assert_eq!(
+            answer["content"], "It is 18°C and sunny.",
+            "stream: {stream}"
+        );
+    }
+}
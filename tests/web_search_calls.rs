@@ -0,0 +1,187 @@
+//! Web searches the upstream sends without an id: each search is announced by `OutputItemAdded`
+//! and finished by `OutputItemDone`, and must reach the client as one tool call whose id both
+//! events agree on, even when the announcement carries less of the action. Identical searches
+//! are still distinct calls.
+
+use std::sync::Arc;
+
+use codex_core::{ResponseEvent, ResponseItem};
+use codex_protocol::models::WebSearchAction;
+use codex_serve::{
+    AppState,
+    server::{ScriptedChatExecutor, TestServer},
+};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+
+fn search(id: Option<&str>, query: &str) -> ResponseItem {
+    ResponseItem::WebSearchCall {
+        id: id.map(str::to_string),
+        status: Some("completed".to_string()),
+        action: WebSearchAction::Search {
+            query: Some(query.to_string()),
+        },
+    }
+}
+
+/// A search as announced before its query is known.
+fn unfinished_search() -> ResponseItem {
+    ResponseItem::WebSearchCall {
+        id: None,
+        status: Some("in_progress".to_string()),
+        action: WebSearchAction::Search { query: None },
+    }
+}
+
+/// A response of `items`, each `(added, done)`.
+fn script(items: fn() -> Vec<(ResponseItem, ResponseItem)>) -> ScriptedChatExecutor {
+    ScriptedChatExecutor::from_events(move || {
+        let mut events: Vec<ResponseEvent> = items()
+            .into_iter()
+            .flat_map(|(added, done)| {
+                [
+                    ResponseEvent::OutputItemAdded(added),
+                    ResponseEvent::OutputItemDone(done),
+                ]
+            })
+            .collect();
+        events.push(ResponseEvent::Completed {
+            response_id: "resp_searches".to_string(),
+            token_usage: None,
+        });
+        events
+    })
+}
+
+/// Two searches, one with an empty id, each seen as added and then done.
+fn searches() -> ScriptedChatExecutor {
+    script(|| {
+        vec![
+            (
+                search(None, "rust release date"),
+                search(None, "rust release date"),
+            ),
+            (
+                search(Some(""), "tokio release date"),
+                search(Some(""), "tokio release date"),
+            ),
+        ]
+    })
+}
+
+async fn post(executor: ScriptedChatExecutor, stream: bool) -> String {
+    let state = AppState::insecure_mock(true).with_executor(Arc::new(executor));
+    let server = TestServer::spawn_with_state(state)
+        .await
+        .expect("Codex Serve test server should start");
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url()))
+        .json(&json!({
+            "model": "gpt-5",
+            "stream": stream,
+            "messages": [{"role": "user", "content": "When were they released?"}]
+        }))
+        .send()
+        .await
+        .expect("request should reach Codex Serve");
+    assert_eq!(response.status(), StatusCode::OK);
+    response.text().await.expect("response body")
+}
+
+/// The tool calls of an aggregated reply.
+async fn aggregated_calls(executor: ScriptedChatExecutor) -> Vec<Value> {
+    let body: Value = serde_json::from_str(&post(executor, false).await).expect("JSON response");
+    body["choices"][0]["message"]["tool_calls"]
+        .as_array()
+        .expect("tool calls")
+        .clone()
+}
+
+/// The ids a streamed reply introduces its tool calls by, in order.
+async fn streamed_ids(executor: ScriptedChatExecutor) -> Vec<Value> {
+    post(executor, true)
+        .await
+        .lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .take_while(|data| *data != "[DONE]")
+        .map(|data| serde_json::from_str::<Value>(data).expect("chunk is JSON"))
+        .flat_map(|chunk| {
+            chunk["choices"][0]["delta"]["tool_calls"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default()
+        })
+        .filter_map(|call| call.get("id").filter(|id| !id.is_null()).cloned())
+        .collect()
+}
+
+#[tokio::test]
+async fn each_search_is_one_aggregated_call() {
+    let calls = aggregated_calls(searches()).await;
+    assert_eq!(calls.len(), 2, "{calls:?}");
+    assert_ne!(calls[0]["id"], calls[1]["id"]);
+    assert!(
+        calls
+            .iter()
+            .all(|call| call["id"].as_str().unwrap().starts_with("ws_call_"))
+    );
+    let arguments: Value =
+        serde_json::from_str(calls[1]["function"]["arguments"].as_str().unwrap()).unwrap();
+    assert_eq!(
+        arguments,
+        json!({"type": "search", "query": "tokio release date"})
+    );
+}
+
+#[tokio::test]
+async fn streamed_searches_keep_their_ids() {
+    let expected: Vec<Value> = aggregated_calls(searches())
+        .await
+        .iter()
+        .map(|call| call["id"].clone())
+        .collect();
+    // The same ids as the aggregated reply: derived from the search, not generated per event.
+    assert_eq!(streamed_ids(searches()).await, expected);
+}
+
+#[tokio::test]
+async fn identical_searches_are_separate_calls() {
+    let twice = || {
+        script(|| {
+            vec![
+                (
+                    search(None, "rust release date"),
+                    search(None, "rust release date"),
+                ),
+                (
+                    search(None, "rust release date"),
+                    search(None, "rust release date"),
+                ),
+            ]
+        })
+    };
+    let calls = aggregated_calls(twice()).await;
+    assert_eq!(calls.len(), 2, "{calls:?}");
+    assert_ne!(calls[0]["id"], calls[1]["id"]);
+    assert_eq!(calls[0]["function"], calls[1]["function"]);
+
+    let ids = streamed_ids(twice()).await;
+    assert_eq!(ids.len(), 2, "{ids:?}");
+    assert_ne!(ids[0], ids[1]);
+}
+
+#[tokio::test]
+async fn a_search_announced_before_its_query_is_one_call() {
+    let announced = || script(|| vec![(unfinished_search(), search(None, "rust release date"))]);
+    let calls = aggregated_calls(announced()).await;
+    assert_eq!(calls.len(), 1, "{calls:?}");
+    let arguments: Value =
+        serde_json::from_str(calls[0]["function"]["arguments"].as_str().unwrap()).unwrap();
+    assert_eq!(
+        arguments,
+        json!({"type": "search", "query": "rust release date"})
+    );
+
+    let ids = streamed_ids(announced()).await;
+    assert_eq!(ids, [calls[0]["id"].clone()]);
+}